@@ -7,6 +7,7 @@
 
 use bencher::{benchmark_group, benchmark_main, Bencher};
 use hecs::*;
+use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
 
 struct Position(f32);
 struct Velocity(f32);
@@ -23,7 +24,7 @@ fn spawn_static(b: &mut Bencher) {
     struct Bundle {
         pos: Position,
         vel: Velocity,
-    };
+    }
 
     let mut world = World::new();
     b.iter(|| {
@@ -39,7 +40,7 @@ fn spawn_batch(b: &mut Bencher) {
     struct Bundle {
         pos: Position,
         vel: Velocity,
-    };
+    }
 
     let mut world = World::new();
     b.iter(|| {
@@ -77,6 +78,173 @@ fn iterate_mut_100k(b: &mut Bencher) {
     })
 }
 
+fn iterate_100k_for_each(b: &mut Bencher) {
+    let mut world = World::new();
+    for i in 0..100_000 {
+        world.spawn((Position(-(i as f32)), Velocity(i as f32)));
+    }
+    b.iter(|| {
+        world
+            .query::<(&mut Position, &Velocity)>()
+            .for_each(|(_, (pos, vel))| pos.0 += vel.0);
+    })
+}
+
+fn iterate_100k_values(b: &mut Bencher) {
+    let mut world = World::new();
+    for i in 0..100_000 {
+        world.spawn((Position(-(i as f32)), Velocity(i as f32)));
+    }
+    b.iter(|| {
+        world
+            .query::<(&mut Position, &Velocity)>()
+            .iter_values()
+            .for_each(|(pos, vel)| pos.0 += vel.0);
+    })
+}
+
+fn iterate_mut_100k_for_each(b: &mut Bencher) {
+    let mut world = World::new();
+    for i in 0..100_000 {
+        world.spawn((Position(-(i as f32)), Velocity(i as f32)));
+    }
+    b.iter(|| {
+        world
+            .query_mut::<(&mut Position, &Velocity)>()
+            .for_each(|(_, (pos, vel))| pos.0 += vel.0);
+    })
+}
+
+struct Marker;
+
+fn remove_marker_10k(b: &mut Bencher) {
+    let mut world = World::new();
+    let entities = world
+        .spawn_batch((0..10_000).map(|i| (Position(i as f32), Velocity(0.0))))
+        .into_vec();
+    b.iter(|| {
+        for &e in &entities {
+            world.insert_one(e, Marker).unwrap();
+        }
+        for &e in &entities {
+            world.remove_one::<Marker>(e).unwrap();
+        }
+    })
+}
+
+fn remove_all_marker_10k(b: &mut Bencher) {
+    let mut world = World::new();
+    let entities = world
+        .spawn_batch((0..10_000).map(|i| (Position(i as f32), Velocity(0.0))))
+        .into_vec();
+    b.iter(|| {
+        for &e in &entities {
+            world.insert_one(e, Marker).unwrap();
+        }
+        world.remove_all::<Marker>();
+    })
+}
+
+fn insert_batch_marker_10k(b: &mut Bencher) {
+    let mut world = World::new();
+    let entities = world
+        .spawn_batch((0..10_000).map(|i| (Position(i as f32), Velocity(0.0))))
+        .into_vec();
+    b.iter(|| {
+        world
+            .insert_batch(entities.iter().map(|&e| (e, (Marker,))))
+            .unwrap();
+        world.remove_all::<Marker>();
+    })
+}
+
+fn toggle_markers_10k(b: &mut Bencher) {
+    struct Other;
+
+    let mut world = World::new();
+    let entities = world
+        .spawn_batch((0..10_000).map(|i| (Position(i as f32), Velocity(0.0))))
+        .into_vec();
+    b.iter(|| {
+        world
+            .insert_batch(entities.iter().map(|&e| (e, (Marker,))))
+            .unwrap();
+        world
+            .insert_batch(entities.iter().step_by(2).map(|&e| (e, (Other,))))
+            .unwrap();
+        world.remove_all::<Marker>();
+        world.remove_all::<Other>();
+    })
+}
+
+/// Entities spread over several archetypes, shuffled so that lookups don't follow storage order
+fn random_access_world() -> (World, Vec<Entity>) {
+    let mut world = World::new();
+    let mut entities = Vec::with_capacity(10_000);
+    for i in 0..10_000 {
+        let pos = Position(i as f32);
+        entities.push(match i % 3 {
+            0 => world.spawn((pos,)),
+            1 => world.spawn((pos, Velocity(0.0))),
+            _ => world.spawn((pos, true)),
+        });
+    }
+    entities.shuffle(&mut StdRng::seed_from_u64(0));
+    (world, entities)
+}
+
+fn random_access_get(b: &mut Bencher) {
+    let (world, entities) = random_access_world();
+    b.iter(|| {
+        let mut sum = 0.0;
+        for &e in &entities {
+            sum += world.get::<Position>(e).unwrap().0;
+        }
+        sum
+    })
+}
+
+fn random_access_get_mut(b: &mut Bencher) {
+    let (world, entities) = random_access_world();
+    b.iter(|| {
+        for &e in &entities {
+            world.get_mut::<Position>(e).unwrap().0 += 1.0;
+        }
+    })
+}
+
+fn random_access_entity_ref(b: &mut Bencher) {
+    let (world, entities) = random_access_world();
+    let entities = entities
+        .iter()
+        .map(|&e| world.entity(e).unwrap())
+        .collect::<Vec<_>>();
+    b.iter(|| {
+        let mut sum = 0.0;
+        for e in &entities {
+            sum += e.get::<Position>().unwrap().0;
+        }
+        sum
+    })
+}
+
+fn insert_remove(b: &mut Bencher) {
+    struct Tag;
+
+    let mut world = World::new();
+    let entities = (0..1_000)
+        .map(|i| world.spawn((Position(-(i as f32)), Velocity(i as f32))))
+        .collect::<Vec<_>>();
+    b.iter(|| {
+        for &e in &entities {
+            world.insert_one(e, Tag).unwrap();
+        }
+        for &e in &entities {
+            world.remove_one::<Tag>(e).unwrap();
+        }
+    });
+}
+
 fn build(b: &mut Bencher) {
     let mut world = World::new();
     let mut builder = EntityBuilder::new();
@@ -93,6 +261,17 @@ benchmark_group!(
     spawn_batch,
     iterate_100k,
     iterate_mut_100k,
+    iterate_100k_for_each,
+    iterate_100k_values,
+    iterate_mut_100k_for_each,
+    random_access_get,
+    random_access_get_mut,
+    random_access_entity_ref,
+    insert_remove,
+    remove_marker_10k,
+    remove_all_marker_10k,
+    insert_batch_marker_10k,
+    toggle_markers_10k,
     build
 );
 benchmark_main!(benches);
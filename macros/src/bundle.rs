@@ -48,11 +48,11 @@ fn gen_dynamic_bundle_impl(
                 <Self as ::hecs::Bundle>::static_type_info()
             }
 
-            #[allow(clippy::forget_copy)]
-            unsafe fn put(mut self, mut f: impl ::std::ops::FnMut(*mut u8, ::hecs::TypeInfo)) {
+            #[allow(unused_mut, unused_variables)]
+            unsafe fn put(self, mut f: impl ::std::ops::FnMut(*mut u8, ::hecs::TypeInfo)) {
+                let mut this = ::std::mem::ManuallyDrop::new(self);
                 #(
-                    f((&mut self.#field_members as *mut #tys).cast::<u8>(), ::hecs::TypeInfo::of::<#tys>());
-                    ::std::mem::forget(self.#field_members);
+                    f((&mut this.#field_members as *mut #tys).cast::<u8>(), ::hecs::TypeInfo::of::<#tys>());
                 )*
             }
         }
@@ -110,6 +110,13 @@ fn gen_bundle_impl(
                 info
             }
 
+            #[allow(non_camel_case_types)]
+            fn with_static_type_info<__hecs__T>(f: impl ::std::ops::FnOnce(&[::hecs::TypeInfo]) -> __hecs__T) -> __hecs__T {
+                let mut info: [::hecs::TypeInfo; #num_tys] = [#(::hecs::TypeInfo::of::<#tys>()),*];
+                info.sort_unstable();
+                f(&info)
+            }
+
             unsafe fn get(
                 mut f: impl ::std::ops::FnMut(::hecs::TypeInfo) -> ::std::option::Option<::std::ptr::NonNull<u8>>,
             ) -> ::std::result::Result<Self, ::hecs::MissingComponent> {
@@ -8,7 +8,9 @@
 extern crate proc_macro;
 
 mod bundle;
+mod map_entities;
 mod query;
+mod stable_component;
 
 use proc_macro::TokenStream;
 use syn::{parse_macro_input, DeriveInput};
@@ -48,6 +50,9 @@ pub fn derive_bundle(input: TokenStream) -> TokenStream {
 /// one lifetime parameter, and all of their fields must be queries (e.g. references) using that
 /// lifetime.
 ///
+/// Entities may additionally be required to have or lack a component without borrowing it, as
+/// with `With` and `Without`, using `#[query(with = "T")]` and `#[query(without = "T")]`.
+///
 /// # Example
 /// ```ignore
 /// #[derive(Query, Debug, PartialEq)]
@@ -65,8 +70,16 @@ pub fn derive_bundle(input: TokenStream) -> TokenStream {
 ///         y: &mut false
 ///     }
 /// );
+///
+/// #[derive(Query)]
+/// #[query(with = "bool", without = "char")]
+/// struct Bar<'a> {
+///     x: &'a i32,
+/// }
+///
+/// assert_eq!(world.query::<Bar>().iter().count(), 1);
 /// ```
-#[proc_macro_derive(Query)]
+#[proc_macro_derive(Query, attributes(query))]
 pub fn derive_query(input: TokenStream) -> TokenStream {
     let input = parse_macro_input!(input as DeriveInput);
     match query::derive(input) {
@@ -75,3 +88,56 @@ pub fn derive_query(input: TokenStream) -> TokenStream {
     }
     .into()
 }
+
+/// Implement `MapEntities` for a struct or enum
+///
+/// Every field must implement `MapEntities`, except those marked `#[map_entities(skip)]`.
+///
+/// # Example
+/// ```ignore
+/// #[derive(MapEntities)]
+/// struct Follow {
+///     target: Entity,
+///     #[map_entities(skip)]
+///     distance: f32,
+/// }
+///
+/// let mut world = World::new();
+/// let mut chunk = World::new();
+/// let a = chunk.spawn(());
+/// chunk.spawn((Follow { target: a, distance: 1.0 },));
+/// let map = world.merge(chunk);
+/// world.map_entities::<Follow>(&map);
+/// ```
+#[proc_macro_derive(MapEntities, attributes(map_entities))]
+pub fn derive_map_entities(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match map_entities::derive(input) {
+        Ok(ts) => ts,
+        Err(e) => e.to_compile_error(),
+    }
+    .into()
+}
+
+/// Implement `StableComponent` for a struct or enum, named by a `#[stable_id("...")]` attribute
+///
+/// The name must be unique among component types, and should not change once data identifying
+/// the type by it has been recorded. A UUID works as well as a path-like name.
+///
+/// # Example
+/// ```ignore
+/// #[derive(StableComponent)]
+/// #[stable_id("game::Position")]
+/// struct Position([f32; 2]);
+///
+/// assert_eq!(Position::STABLE_TYPE_ID, StableTypeId::from_name("game::Position"));
+/// ```
+#[proc_macro_derive(StableComponent, attributes(stable_id))]
+pub fn derive_stable_component(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match stable_component::derive(input) {
+        Ok(ts) => ts,
+        Err(e) => e.to_compile_error(),
+    }
+    .into()
+}
@@ -0,0 +1,93 @@
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{format_ident, quote};
+use syn::{DeriveInput, Error, Result};
+
+pub fn derive(input: DeriveInput) -> Result<TokenStream2> {
+    let ident = input.ident;
+    let body = match input.data {
+        syn::Data::Struct(s) => {
+            let (pattern, mapped) = destructure(&s.fields)?;
+            quote! {
+                let Self #pattern = self;
+                #(::hecs::MapEntities::map_entities(#mapped, map);)*
+            }
+        }
+        syn::Data::Enum(e) => {
+            let mut arms = Vec::new();
+            for variant in &e.variants {
+                let variant_ident = &variant.ident;
+                let (pattern, mapped) = destructure(&variant.fields)?;
+                arms.push(quote! {
+                    Self::#variant_ident #pattern => {
+                        #(::hecs::MapEntities::map_entities(#mapped, map);)*
+                    }
+                });
+            }
+            quote! {
+                match self {
+                    #(#arms)*
+                }
+            }
+        }
+        syn::Data::Union(_) => {
+            return Err(Error::new_spanned(
+                ident,
+                "derive(MapEntities) does not support unions",
+            ))
+        }
+    };
+    let generics = add_additional_bounds_to_generic_params(input.generics);
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::hecs::MapEntities for #ident #ty_generics #where_clause {
+            #[allow(unused_variables)]
+            fn map_entities(&mut self, map: &::hecs::EntityMap) {
+                #body
+            }
+        }
+    })
+}
+
+/// Compute a pattern binding every field of `fields`, and the bindings of fields to be mapped
+fn destructure(fields: &syn::Fields) -> Result<(TokenStream2, Vec<syn::Ident>)> {
+    let mut bindings = Vec::new();
+    let mut mapped = Vec::new();
+    for (i, field) in fields.iter().enumerate() {
+        let binding = format_ident!("__hecs__field_{}", i);
+        if !is_skipped(field)? {
+            mapped.push(binding.clone());
+        }
+        bindings.push(binding);
+    }
+    let pattern = match fields {
+        syn::Fields::Named(fields) => {
+            let names = fields.named.iter().map(|f| f.ident.as_ref().unwrap());
+            quote! { { #(#names: #bindings),* } }
+        }
+        syn::Fields::Unnamed(_) => quote! { (#(#bindings),*) },
+        syn::Fields::Unit => quote! {},
+    };
+    Ok((pattern, mapped))
+}
+
+fn is_skipped(field: &syn::Field) -> Result<bool> {
+    let mut skipped = false;
+    for attr in &field.attrs {
+        if !attr.path.is_ident("map_entities") {
+            continue;
+        }
+        let arg = attr.parse_args::<syn::Ident>()?;
+        if arg != "skip" {
+            return Err(Error::new_spanned(arg, "expected `skip`"));
+        }
+        skipped = true;
+    }
+    Ok(skipped)
+}
+
+fn add_additional_bounds_to_generic_params(mut generics: syn::Generics) -> syn::Generics {
+    generics.type_params_mut().for_each(|tp| {
+        tp.bounds.push(syn::parse_quote!(::hecs::MapEntities));
+    });
+    generics
+}
@@ -6,6 +6,7 @@ use syn::{DeriveInput, Error, Ident, Lifetime, Result, Type};
 pub fn derive(input: DeriveInput) -> Result<TokenStream2> {
     let ident = input.ident;
     let vis = input.vis;
+    let (with, without) = filters(&input.attrs)?;
     let data = match input.data {
         syn::Data::Struct(s) => s,
         _ => {
@@ -81,6 +82,19 @@ pub fn derive(input: DeriveInput) -> Result<TokenStream2> {
         },
     };
 
+    let filter = quote! {
+        #(
+            if !archetype.has::<#with>() {
+                return ::std::option::Option::None;
+            }
+        )*
+        #(
+            if archetype.has::<#without>() {
+                return ::std::option::Option::None;
+            }
+        )*
+    };
+
     Ok(quote! {
         impl<'a> ::hecs::Query for #ident<'a> {
             type Fetch = #fetch_ident;
@@ -102,6 +116,7 @@ pub fn derive(input: DeriveInput) -> Result<TokenStream2> {
 
             #[allow(unused_variables, unused_mut)]
             fn access(archetype: &::hecs::Archetype) -> ::std::option::Option<::hecs::Access> {
+                #filter
                 let mut access = ::hecs::Access::Iterate;
                 #(
                     access = ::core::cmp::max(access, #fetches::access(archetype)?);
@@ -116,6 +131,7 @@ pub fn derive(input: DeriveInput) -> Result<TokenStream2> {
 
             #[allow(unused_variables)]
             fn new(archetype: &'a ::hecs::Archetype) -> ::std::option::Option<Self> {
+                #filter
                 ::std::option::Option::Some(Self {
                     #(
                         #fields: #fetches::new(archetype)?,
@@ -128,6 +144,11 @@ pub fn derive(input: DeriveInput) -> Result<TokenStream2> {
                 #(#fetches::release(archetype);)*
             }
 
+            #[allow(unused_variables, unused_mut)]
+            fn for_each_borrow(mut f: impl ::core::ops::FnMut(::core::any::TypeId, bool)) {
+                #(#fetches::for_each_borrow(&mut f);)*
+            }
+
             #[allow(unused_variables)]
             unsafe fn get(&self, n: usize) -> Self::Item {
                 #ident {
@@ -136,14 +157,68 @@ pub fn derive(input: DeriveInput) -> Result<TokenStream2> {
                     )*
                 }
             }
+
+            #[allow(unused_variables)]
+            unsafe fn should_skip(&self, n: usize) -> bool {
+                false #(|| <#fetches as ::hecs::Fetch<'a>>::should_skip(&self.#fields, n))*
+            }
+
+            fn filters_entities(&self) -> bool {
+                false #(|| <#fetches as ::hecs::Fetch<'a>>::filters_entities(&self.#fields))*
+            }
+
+            fn includes_disabled() -> bool {
+                false #(|| <#fetches as ::hecs::Fetch<'a>>::includes_disabled())*
+            }
         }
     })
 }
 
+/// Collect the component types named by `#[query(with = "T")]` and `#[query(without = "T")]`
+fn filters(attrs: &[syn::Attribute]) -> Result<(Vec<Type>, Vec<Type>)> {
+    let mut with = Vec::new();
+    let mut without = Vec::new();
+    for attr in attrs {
+        if !attr.path.is_ident("query") {
+            continue;
+        }
+        let list = match attr.parse_meta()? {
+            syn::Meta::List(list) => list,
+            meta => {
+                return Err(Error::new_spanned(
+                    meta,
+                    "expected `#[query(with = \"T\")]` or `#[query(without = \"T\")]`",
+                ))
+            }
+        };
+        for nested in list.nested {
+            let pair = match nested {
+                syn::NestedMeta::Meta(syn::Meta::NameValue(pair)) => pair,
+                nested => return Err(Error::new_spanned(nested, "expected `with` or `without`")),
+            };
+            let ty = match pair.lit {
+                syn::Lit::Str(ref lit) => lit.parse::<Type>()?,
+                ref lit => return Err(Error::new_spanned(lit, "expected a string naming a type")),
+            };
+            if pair.path.is_ident("with") {
+                with.push(ty);
+            } else if pair.path.is_ident("without") {
+                without.push(ty);
+            } else {
+                return Err(Error::new_spanned(
+                    pair.path,
+                    "expected `with` or `without`",
+                ));
+            }
+        }
+    }
+    Ok((with, without))
+}
+
 fn query_fetch_ty(lifetime: &Lifetime, ty: &Type) -> Result<TokenStream2> {
     struct Visitor<'a> {
         replace: &'a Lifetime,
-    };
+    }
     impl syn::visit_mut::VisitMut for Visitor<'_> {
         fn visit_lifetime_mut(&mut self, l: &mut Lifetime) {
             if l == self.replace {
@@ -0,0 +1,35 @@
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{DeriveInput, Error, Result};
+
+pub fn derive(input: DeriveInput) -> Result<TokenStream2> {
+    let ident = input.ident;
+    if !input.generics.params.is_empty() {
+        return Err(Error::new_spanned(
+            input.generics,
+            "derive(StableComponent) does not support generics, as every instantiation would share \
+             a name",
+        ));
+    }
+    let mut name = None;
+    for attr in &input.attrs {
+        if !attr.path.is_ident("stable_id") {
+            continue;
+        }
+        if name.is_some() {
+            return Err(Error::new_spanned(attr, "duplicate stable_id attribute"));
+        }
+        name = Some(attr.parse_args::<syn::LitStr>()?);
+    }
+    let name = name.ok_or_else(|| {
+        Error::new_spanned(
+            &ident,
+            "derive(StableComponent) requires a #[stable_id(\"...\")] attribute",
+        )
+    })?;
+    Ok(quote! {
+        impl ::hecs::StableComponent for #ident {
+            const STABLE_NAME: &'static str = #name;
+        }
+    })
+}
@@ -7,18 +7,29 @@
 
 use crate::alloc::alloc::{alloc, dealloc, Layout};
 use crate::alloc::boxed::Box;
+use crate::alloc::sync::Arc;
 use crate::alloc::{vec, vec::Vec};
+use core::alloc::GlobalAlloc;
 use core::any::{type_name, TypeId};
 use core::cell::UnsafeCell;
 use core::hash::{BuildHasher, BuildHasherDefault, Hasher};
 use core::mem;
 use core::ptr::{self, NonNull};
+use core::sync::atomic::AtomicU32;
 
 use hashbrown::{hash_map::DefaultHashBuilder, HashMap};
 
-use crate::borrow::AtomicBorrow;
+use crate::atomic_borrow::AtomicBorrow;
+use crate::chunk::{ChunkComponent, ChunkValue};
 use crate::query::Fetch;
-use crate::{align, Access, Component, Query};
+use crate::sparse::{SparseColumn, SparseSet};
+use crate::{align, Access, Component, EntityId, Query, StableComponent, StableTypeId};
+
+/// Alignment of the start of every column of sized components, sufficient for any SIMD load
+pub(crate) const COLUMN_ALIGN: usize = 64;
+
+/// Source of the memory backing a `World`'s component storage, other than the global allocator
+pub(crate) type StorageAllocator = Arc<dyn GlobalAlloc + Send + Sync>;
 
 /// A collection of entities having the same component types
 ///
@@ -28,11 +39,27 @@ pub struct Archetype {
     types: Vec<TypeInfo>,
     state: TypeIdMap<TypeState>,
     len: u32,
-    entities: Box<[u32]>,
+    entities: Box<[EntityId]>,
     // UnsafeCell allows unique references into `data` to be constructed while shared references
     // containing the `Archetype` exist
     data: UnsafeCell<NonNull<u8>>,
     data_size: usize,
+    /// Change tick stamped onto components as they're added or mutably accessed
+    tick: u32,
+    /// Chunk components shared by every entity, sorted by type
+    chunk: Vec<ChunkValue>,
+    /// Sparsely stored component types, which any of this archetype's entities may have
+    sparse: TypeIdMap<SparseColumn>,
+    /// Whether removing an entity shifts later entities down rather than swapping in the last
+    preserve_order: bool,
+    /// Archetype that an entity moves to when a component of a certain type is inserted
+    insert_edges: TypeIdMap<u32>,
+    /// Archetype that an entity moves to when a component of a certain type is removed
+    remove_edges: TypeIdMap<u32>,
+    /// Types stored sparsely for this archetype's entities even if not registered as sparse
+    pinned: Vec<TypeId>,
+    /// Allocator of `data`, if not the global allocator
+    allocator: Option<StorageAllocator>,
 }
 
 impl Archetype {
@@ -63,91 +90,363 @@ impl Archetype {
             len: 0,
             data: UnsafeCell::new(NonNull::dangling()),
             data_size: 0,
+            tick: 0,
+            chunk: Vec::new(),
+            sparse: TypeIdMap::default(),
+            preserve_order: false,
+            insert_edges: TypeIdMap::default(),
+            remove_edges: TypeIdMap::default(),
+            pinned: Vec::new(),
+            allocator: None,
+        }
+    }
+
+    /// Allocate component storage from `allocator` rather than the global allocator
+    ///
+    /// Must be called before any storage is allocated.
+    pub(crate) fn set_allocator(&mut self, allocator: Option<StorageAllocator>) {
+        debug_assert_eq!(self.data_size, 0);
+        self.allocator = allocator;
+    }
+
+    unsafe fn alloc_data(&self, layout: Layout) -> *mut u8 {
+        match self.allocator {
+            None => alloc(layout),
+            Some(ref x) => x.alloc(layout),
+        }
+    }
+
+    unsafe fn dealloc_data(&self, ptr: *mut u8, layout: Layout) {
+        match self.allocator {
+            None => dealloc(ptr, layout),
+            Some(ref x) => x.dealloc(ptr, layout),
         }
     }
 
     pub(crate) fn clear(&mut self) {
+        self.clear_with(|ty, ptr| unsafe { ty.drop(ptr) });
+    }
+
+    /// Like `clear`, but passes each component that needs dropping to `f` to be disposed of
+    pub(crate) fn clear_with(&mut self, mut f: impl FnMut(&TypeInfo, *mut u8)) {
         for ty in &self.types {
+            if !ty.needs_drop {
+                continue;
+            }
             for index in 0..self.len {
                 unsafe {
                     let removed = self
                         .get_dynamic(ty.id, ty.layout.size(), index)
                         .unwrap()
                         .as_ptr();
-                    (ty.drop)(removed);
+                    f(ty, removed);
                 }
             }
         }
         self.len = 0;
     }
 
-    pub(crate) fn has<T: Component>(&self) -> bool {
+    /// Whether entities are kept in the order they were added, even as others are removed
+    pub(crate) fn preserves_order(&self) -> bool {
+        self.preserve_order
+    }
+
+    pub(crate) fn set_preserve_order(&mut self, enabled: bool) {
+        self.preserve_order = enabled;
+    }
+
+    /// The chunk component of type `T` shared by this archetype's entities, if any
+    pub fn chunk<T: ChunkComponent>(&self) -> Option<&T> {
+        self.chunk
+            .iter()
+            .find(|x| x.ty() == TypeId::of::<T>())
+            .and_then(|x| x.downcast_ref())
+    }
+
+    pub(crate) fn chunk_values(&self) -> &[ChunkValue] {
+        &self.chunk
+    }
+
+    pub(crate) fn set_chunk_values(&mut self, chunk: Vec<ChunkValue>) {
+        self.chunk = chunk;
+    }
+
+    /// The archetype that an entity moves to when a `ty` component is inserted, if known
+    #[inline]
+    pub(crate) fn insert_edge(&self, ty: TypeId) -> Option<u32> {
+        self.insert_edges.get(&ty).copied()
+    }
+
+    pub(crate) fn set_insert_edge(&mut self, ty: TypeId, target: u32) {
+        self.insert_edges.insert(ty, target);
+    }
+
+    /// The archetype that an entity moves to when its `ty` component is removed, if known
+    #[inline]
+    pub(crate) fn remove_edge(&self, ty: TypeId) -> Option<u32> {
+        self.remove_edges.get(&ty).copied()
+    }
+
+    pub(crate) fn set_remove_edge(&mut self, ty: TypeId, target: u32) {
+        self.remove_edges.insert(ty, target);
+    }
+
+    /// Forget every edge, e.g. because archetypes have been renumbered
+    pub(crate) fn clear_edges(&mut self) {
+        self.insert_edges.clear();
+        self.remove_edges.clear();
+    }
+
+    /// Allow this archetype's entities to have components stored in `set`, unless they're already
+    /// stored in a column
+    pub(crate) fn add_sparse(&mut self, set: NonNull<SparseSet>) {
+        // Inserting a newly sparse type no longer changes an entity's archetype
+        self.clear_edges();
+        let ty = unsafe { set.as_ref().type_info().id() };
+        if !self.has_dynamic(ty) {
+            self.sparse.insert(ty, SparseColumn::new(set));
+        }
+    }
+
+    /// Store `ty` components sparsely for this archetype's entities, so that inserting one doesn't
+    /// move its entity
+    ///
+    /// The `World` must have a `SparseSet` for `ty`, and this archetype must lack a `ty` column.
+    pub(crate) fn pin(&mut self, ty: TypeId, this: u32) {
+        debug_assert!(self.sparse(ty).is_some());
+        if !self.pinned.contains(&ty) {
+            self.pinned.push(ty);
+        }
+        self.set_insert_edge(ty, this);
+    }
+
+    /// Whether `ty` components are stored sparsely for this archetype's entities, per `pin`
+    #[inline]
+    pub(crate) fn is_pinned(&self, ty: TypeId) -> bool {
+        self.pinned.contains(&ty)
+    }
+
+    /// The types passed to `pin`
+    pub(crate) fn pinned(&self) -> &[TypeId] {
+        &self.pinned
+    }
+
+    /// Forget every `SparseSet`, e.g. before this archetype leaves its `World`
+    pub(crate) fn clear_sparse(&mut self) {
+        self.sparse.clear();
+    }
+
+    /// The set storing components of type `ty` for this archetype's entities, if they're sparse
+    pub(crate) fn sparse(&self, ty: TypeId) -> Option<&SparseSet> {
+        self.sparse.get(&ty).map(|x| x.set())
+    }
+
+    /// Every set that this archetype's entities may have components in
+    pub(crate) fn sparse_sets(&self) -> impl Iterator<Item = &SparseSet> + '_ {
+        self.sparse.values().map(|x| x.set())
+    }
+
+    /// Find the `T` component of the entity at `index` along with its change tick and borrow state
+    ///
+    /// Costs a single type lookup for components stored in a column, so is used for random access.
+    #[inline]
+    pub(crate) unsafe fn locate<T: Component>(&self, index: u32) -> Option<ComponentSlot<'_, T>> {
+        match self.state.get(&TypeId::of::<T>()) {
+            Some(state) => {
+                let data = (*self.data.get()).as_ptr();
+                Some(ComponentSlot {
+                    target: NonNull::new_unchecked(
+                        data.add(state.offset).cast::<T>().add(index as usize),
+                    ),
+                    mutated: NonNull::new_unchecked(
+                        data.add(state.mutated)
+                            .cast::<AtomicU32>()
+                            .add(index as usize),
+                    ),
+                    borrow: &state.borrow,
+                })
+            }
+            None => {
+                if index >= self.len {
+                    return None;
+                }
+                let column = self.sparse.get(&TypeId::of::<T>())?;
+                let set = column.set();
+                let row = set.row(self.entity_id(index))?;
+                Some(ComponentSlot {
+                    target: set.component(row).cast(),
+                    mutated: set.mutated(row),
+                    borrow: column.borrow(),
+                })
+            }
+        }
+    }
+
+    /// Type, address, and change tick of the sparse `ty` component of the entity at `index`, if
+    /// it has one
+    pub(crate) unsafe fn get_sparse(
+        &self,
+        ty: TypeId,
+        index: u32,
+    ) -> Option<(&TypeInfo, NonNull<u8>, NonNull<AtomicU32>)> {
+        if index >= self.len {
+            return None;
+        }
+        let set = self.sparse(ty)?;
+        let row = set.row(self.entity_id(index))?;
+        Some((set.type_info(), set.component(row), set.mutated(row)))
+    }
+
+    /// Whether this archetype contains `T` components
+    pub fn has<T: Component>(&self) -> bool {
         self.has_dynamic(TypeId::of::<T>())
     }
 
-    pub(crate) fn has_dynamic(&self, id: TypeId) -> bool {
+    /// Whether this archetype contains components with the type identified by `id`
+    pub fn has_dynamic(&self, id: TypeId) -> bool {
         self.state.contains_key(&id)
     }
 
     pub(crate) fn get<T: Component>(&self) -> Option<NonNull<T>> {
         let state = self.state.get(&TypeId::of::<T>())?;
         Some(unsafe {
-            NonNull::new_unchecked(
-                (*self.data.get()).as_ptr().add(state.offset).cast::<T>() as *mut T
-            )
+            NonNull::new_unchecked((*self.data.get()).as_ptr().add(state.offset).cast::<T>())
         })
     }
 
+    /// Address of the first component of type `ty`, regardless of whether any exist
+    pub(crate) fn get_base(&self, ty: TypeId) -> Option<NonNull<u8>> {
+        let state = self.state.get(&ty)?;
+        Some(unsafe { NonNull::new_unchecked((*self.data.get()).as_ptr().add(state.offset)) })
+    }
+
+    /// Ticks at which each entity's `T` was added
+    pub(crate) fn added<T: Component>(&self) -> Option<NonNull<u32>> {
+        let state = self.state.get(&TypeId::of::<T>())?;
+        Some(unsafe { self.ticks(state.added) })
+    }
+
+    /// Ticks at which each entity's `T` was most recently added or mutably accessed
+    ///
+    /// Written through shared references to the archetype, hence atomic.
+    pub(crate) fn mutated<T: Component>(&self) -> Option<NonNull<AtomicU32>> {
+        self.mutated_dynamic(TypeId::of::<T>())
+    }
+
+    pub(crate) fn mutated_dynamic(&self, ty: TypeId) -> Option<NonNull<AtomicU32>> {
+        let state = self.state.get(&ty)?;
+        Some(unsafe { self.ticks(state.mutated).cast() })
+    }
+
+    unsafe fn ticks(&self, offset: usize) -> NonNull<u32> {
+        NonNull::new_unchecked((*self.data.get()).as_ptr().add(offset).cast::<u32>())
+    }
+
+    /// The tick at which `ty` was added to and last mutated on the entity at `index`
+    pub(crate) unsafe fn get_ticks(&self, ty: TypeId, index: u32) -> Option<ComponentTicks> {
+        let state = self.state.get(&ty)?;
+        Some(ComponentTicks {
+            added: *self.ticks(state.added).as_ptr().add(index as usize),
+            mutated: *self.ticks(state.mutated).as_ptr().add(index as usize),
+        })
+    }
+
+    /// Overwrite the ticks of `ty` on the entity at `index`, e.g. to preserve them across a move
+    pub(crate) unsafe fn set_ticks(&mut self, ty: TypeId, index: u32, ticks: ComponentTicks) {
+        let state = self.state.get(&ty).unwrap();
+        *self.ticks(state.added).as_ptr().add(index as usize) = ticks.added;
+        *self.ticks(state.mutated).as_ptr().add(index as usize) = ticks.mutated;
+    }
+
+    #[inline]
+    pub(crate) fn tick(&self) -> u32 {
+        self.tick
+    }
+
+    pub(crate) fn set_tick(&mut self, tick: u32) {
+        self.tick = tick;
+    }
+
     pub(crate) fn borrow<T: Component>(&self) {
-        if self
-            .state
-            .get(&TypeId::of::<T>())
-            .map_or(false, |x| !x.borrow.borrow())
-        {
+        if !self.borrow_dynamic(TypeId::of::<T>()) {
             panic!("{} already borrowed uniquely", type_name::<T>());
         }
     }
 
     pub(crate) fn borrow_mut<T: Component>(&self) {
-        if self
-            .state
-            .get(&TypeId::of::<T>())
-            .map_or(false, |x| !x.borrow.borrow_mut())
-        {
+        if !self.borrow_mut_dynamic(TypeId::of::<T>()) {
             panic!("{} already borrowed", type_name::<T>());
         }
     }
 
     pub(crate) fn release<T: Component>(&self) {
-        if let Some(x) = self.state.get(&TypeId::of::<T>()) {
-            x.borrow.release();
-        }
+        self.release_dynamic(TypeId::of::<T>());
     }
 
     pub(crate) fn release_mut<T: Component>(&self) {
-        if let Some(x) = self.state.get(&TypeId::of::<T>()) {
-            x.borrow.release_mut();
+        self.release_mut_dynamic(TypeId::of::<T>());
+    }
+
+    /// The borrow state of `ty`, whether stored in a column or sparsely
+    fn borrow_state(&self, ty: TypeId) -> Option<&AtomicBorrow> {
+        match self.state.get(&ty) {
+            Some(x) => Some(&x.borrow),
+            None => self.sparse.get(&ty).map(|x| x.borrow()),
         }
     }
 
+    /// Returns `false` if `ty` is already uniquely borrowed
+    pub(crate) fn borrow_dynamic(&self, ty: TypeId) -> bool {
+        self.borrow_state(ty).is_none_or(|x| x.borrow())
+    }
+
+    /// Returns `false` if `ty` is already borrowed
+    pub(crate) fn borrow_mut_dynamic(&self, ty: TypeId) -> bool {
+        self.borrow_state(ty).is_none_or(|x| x.borrow_mut())
+    }
+
+    pub(crate) fn release_dynamic(&self, ty: TypeId) {
+        if let Some(x) = self.borrow_state(ty) {
+            x.release();
+        }
+    }
+
+    pub(crate) fn release_mut_dynamic(&self, ty: TypeId) {
+        if let Some(x) = self.borrow_state(ty) {
+            x.release_mut();
+        }
+    }
+
+    /// Number of entities in this archetype
     #[inline]
-    pub(crate) fn len(&self) -> u32 {
+    pub fn len(&self) -> u32 {
         self.len
     }
 
+    /// Whether this archetype contains no entities
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
     #[inline]
-    pub(crate) fn entities(&self) -> NonNull<u32> {
+    pub(crate) fn entities(&self) -> NonNull<EntityId> {
         unsafe { NonNull::new_unchecked(self.entities.as_ptr() as *mut _) }
     }
 
-    pub(crate) fn entity_id(&self, index: u32) -> u32 {
+    pub(crate) fn entity_id(&self, index: u32) -> EntityId {
         self.entities[index as usize]
     }
 
-    pub(crate) fn types(&self) -> &[TypeInfo] {
+    /// Metadata of the component types of entities stored in this archetype
+    pub fn types(&self) -> &[TypeInfo] {
         &self.types
     }
 
+    pub(crate) fn type_info(&self, ty: TypeId) -> Option<&TypeInfo> {
+        self.types.iter().find(|x| x.id == ty)
+    }
+
     /// Enumerate the types of the components of entities stored in this archetype.
     ///
     /// Convenient for dispatching logic which needs to be performed on sets of type ids.  For
@@ -163,6 +462,21 @@ impl Archetype {
         self.types.iter().map(|typeinfo| typeinfo.id)
     }
 
+    /// Address of the `ty` component of the next entity to be allocated, which must fit in the
+    /// reserved capacity
+    ///
+    /// Writing components here before calling `allocate` ensures that a panic part-way through
+    /// never leaves uninitialized components in storage.
+    pub(crate) unsafe fn get_next_dynamic(&self, ty: TypeId, size: usize) -> Option<NonNull<u8>> {
+        debug_assert!(self.len < self.capacity());
+        Some(NonNull::new_unchecked(
+            (*self.data.get())
+                .as_ptr()
+                .add(self.state.get(&ty)?.offset + size * self.len as usize)
+                .cast::<u8>(),
+        ))
+    }
+
     /// `index` must be in-bounds
     pub(crate) unsafe fn get_dynamic(
         &self,
@@ -180,7 +494,7 @@ impl Archetype {
     }
 
     /// Every type must be written immediately after this call
-    pub(crate) unsafe fn allocate(&mut self, id: u32) -> u32 {
+    pub(crate) unsafe fn allocate(&mut self, id: EntityId) -> u32 {
         if self.len as usize == self.entities.len() {
             self.grow(self.len.max(64));
         }
@@ -192,14 +506,47 @@ impl Archetype {
 
     pub(crate) fn reserve(&mut self, additional: u32) {
         if additional > (self.capacity() - self.len()) {
-            self.grow(additional - (self.capacity() - self.len()));
+            // `grow` sizes storage relative to the current length
+            self.grow(additional);
         }
     }
 
-    pub(crate) fn capacity(&self) -> u32 {
+    /// Number of entities that may be stored without reallocating
+    pub fn capacity(&self) -> u32 {
         self.entities.len() as u32
     }
 
+    /// Reallocate storage to fit exactly the current entities, returning the number of bytes
+    /// freed
+    pub(crate) fn shrink_to_fit(&mut self) -> usize {
+        self.shrink_to(0)
+    }
+
+    /// Reduce capacity to `capacity` or the number of entities, whichever is greater, returning
+    /// the number of bytes freed
+    pub(crate) fn shrink_to(&mut self, capacity: u32) -> usize {
+        let capacity = capacity.max(self.len);
+        if self.capacity() <= capacity {
+            return 0;
+        }
+        let old = self.allocated_bytes();
+        self.grow(capacity - self.len);
+        old - self.allocated_bytes()
+    }
+
+    /// Bytes of heap memory used to store entities and their components
+    pub fn allocated_bytes(&self) -> usize {
+        self.data_size + self.entities.len() * mem::size_of::<EntityId>()
+    }
+
+    /// Alignment of `data`, sufficient for every component type as well as the tick arrays
+    fn data_align(&self) -> usize {
+        self.types
+            .first()
+            .map_or(1, |x| x.layout.align())
+            .max(COLUMN_ALIGN)
+    }
+
     fn grow(&mut self, increment: u32) {
         unsafe {
             let old_count = self.len as usize;
@@ -211,38 +558,47 @@ impl Archetype {
             let old_data_size = mem::replace(&mut self.data_size, 0);
             let mut state = HashMap::with_capacity_and_hasher(self.types.len(), Default::default());
             for ty in &self.types {
-                self.data_size = align(self.data_size, ty.layout.align());
-                state.insert(ty.id, TypeState::new(self.data_size));
+                // Zero-sized components occupy no space, so may share the suitably aligned start
+                let offset = if ty.layout.size() == 0 {
+                    0
+                } else {
+                    self.data_size = align(self.data_size, ty.layout.align().max(COLUMN_ALIGN));
+                    self.data_size
+                };
                 self.data_size += ty.layout.size() * count;
+                self.data_size = align(self.data_size, mem::align_of::<u32>());
+                let added = self.data_size;
+                let mutated = added + mem::size_of::<u32>() * count;
+                self.data_size = mutated + mem::size_of::<u32>() * count;
+                state.insert(ty.id, TypeState::new(offset, added, mutated));
             }
             let new_data = if self.data_size == 0 {
                 NonNull::dangling()
             } else {
-                NonNull::new(alloc(
-                    Layout::from_size_align(
-                        self.data_size,
-                        self.types.first().map_or(1, |x| x.layout.align()),
-                    )
-                    .unwrap(),
-                ))
-                .unwrap()
+                let layout = Layout::from_size_align(self.data_size, self.data_align()).unwrap();
+                NonNull::new(self.alloc_data(layout)).unwrap()
             };
             if old_data_size != 0 {
+                let old_data = (*self.data.get()).as_ptr();
                 for ty in &self.types {
-                    let old_off = self.state.get(&ty.id).unwrap().offset;
-                    let new_off = state.get(&ty.id).unwrap().offset;
+                    let old = self.state.get(&ty.id).unwrap();
+                    let new = state.get(&ty.id).unwrap();
                     ptr::copy_nonoverlapping(
-                        (*self.data.get()).as_ptr().add(old_off),
-                        new_data.as_ptr().add(new_off),
+                        old_data.add(old.offset),
+                        new_data.as_ptr().add(new.offset),
                         ty.layout.size() * old_count,
                     );
+                    for &(old, new) in &[(old.added, new.added), (old.mutated, new.mutated)] {
+                        ptr::copy_nonoverlapping(
+                            old_data.add(old),
+                            new_data.as_ptr().add(new),
+                            mem::size_of::<u32>() * old_count,
+                        );
+                    }
                 }
-                dealloc(
-                    (*self.data.get()).as_ptr().cast(),
-                    Layout::from_size_align_unchecked(
-                        old_data_size,
-                        self.types.first().map_or(1, |x| x.layout.align()),
-                    ),
+                self.dealloc_data(
+                    old_data,
+                    Layout::from_size_align_unchecked(old_data_size, self.data_align()),
                 );
             }
 
@@ -251,66 +607,280 @@ impl Archetype {
         }
     }
 
+    /// Copy the tick arrays' entries for `src` into `dst`
+    unsafe fn copy_ticks(&self, ty: TypeId, src: u32, dst: u32) {
+        let state = self.state.get(&ty).unwrap();
+        for &offset in &[state.added, state.mutated] {
+            let ticks = self.ticks(offset).as_ptr();
+            *ticks.add(dst as usize) = *ticks.add(src as usize);
+        }
+    }
+
+    /// Drop the entities at indices for which `keep` is `false`, preserving the order of the rest
+    pub(crate) unsafe fn retain(&mut self, keep: &[bool]) {
+        debug_assert_eq!(keep.len(), self.len as usize);
+        for ty in &self.types {
+            let size = ty.layout.size();
+            let base = self.get_base(ty.id).unwrap().as_ptr();
+            let mut fill = 0;
+            for (index, &keep) in keep.iter().enumerate() {
+                let component = base.add(index * size);
+                if !keep {
+                    if ty.needs_drop {
+                        (ty.drop)(component);
+                    }
+                    continue;
+                }
+                if fill != index {
+                    if size != 0 {
+                        ptr::copy_nonoverlapping(component, base.add(fill * size), size);
+                    }
+                    self.copy_ticks(ty.id, index as u32, fill as u32);
+                }
+                fill += 1;
+            }
+        }
+        let mut fill = 0;
+        for (index, &keep) in keep.iter().enumerate() {
+            if keep {
+                self.entities[fill] = self.entities[index];
+                fill += 1;
+            }
+        }
+        self.len = fill as u32;
+    }
+
     /// Returns the ID of the entity moved into `index`, if any
-    pub(crate) unsafe fn remove(&mut self, index: u32) -> Option<u32> {
-        let last = self.len - 1;
+    ///
+    /// If order is preserved, every later entity moves down by one.
+    pub(crate) unsafe fn remove(&mut self, index: u32) -> Option<EntityId> {
         for ty in &self.types {
-            let removed = self
-                .get_dynamic(ty.id, ty.layout.size(), index)
-                .unwrap()
-                .as_ptr();
-            (ty.drop)(removed);
-            if index != last {
-                ptr::copy_nonoverlapping(
-                    self.get_dynamic(ty.id, ty.layout.size(), last)
+            if ty.layout.size() != 0 {
+                (ty.drop)(
+                    self.get_dynamic(ty.id, ty.layout.size(), index)
                         .unwrap()
                         .as_ptr(),
-                    removed,
-                    ty.layout.size(),
                 );
+            } else if ty.needs_drop {
+                (ty.drop)(ty.dangling());
             }
+            self.fill_gap(ty, index);
         }
-        self.len = last;
-        if index != last {
-            self.entities[index as usize] = self.entities[last as usize];
-            Some(self.entities[last as usize])
-        } else {
-            None
-        }
+        self.finish_removal(index)
     }
 
-    /// Returns the ID of the entity moved into `index`, if any
+    /// Like `remove`, but passes each component to `f` to be moved out instead of dropping it
     pub(crate) unsafe fn move_to(
         &mut self,
         index: u32,
-        mut f: impl FnMut(*mut u8, TypeId, usize),
-    ) -> Option<u32> {
-        let last = self.len - 1;
+        mut f: impl FnMut(*mut u8, TypeId, usize, ComponentTicks),
+    ) -> Option<EntityId> {
         for ty in &self.types {
-            let moved = self
-                .get_dynamic(ty.id, ty.layout.size(), index)
-                .unwrap()
-                .as_ptr();
-            f(moved, ty.id(), ty.layout().size());
-            if index != last {
+            let ptr = if ty.layout.size() == 0 {
+                ty.dangling()
+            } else {
+                self.get_dynamic(ty.id, ty.layout.size(), index)
+                    .unwrap()
+                    .as_ptr()
+            };
+            f(
+                ptr,
+                ty.id,
+                ty.layout.size(),
+                self.get_ticks(ty.id, index).unwrap(),
+            );
+            self.fill_gap(ty, index);
+        }
+        self.finish_removal(index)
+    }
+
+    /// Overwrite the already moved-out `ty` component at `index` with a later one
+    unsafe fn fill_gap(&self, ty: &TypeInfo, index: u32) {
+        let last = self.len - 1;
+        if index == last {
+            return;
+        }
+        let size = ty.layout.size();
+        let state = self.state.get(&ty.id).unwrap();
+        let base = (*self.data.get()).as_ptr().add(state.offset);
+        if self.preserve_order {
+            let count = (last - index) as usize;
+            if size != 0 {
+                ptr::copy(
+                    base.add((index as usize + 1) * size),
+                    base.add(index as usize * size),
+                    count * size,
+                );
+            }
+            for &offset in &[state.added, state.mutated] {
+                let ticks = self.ticks(offset).as_ptr();
+                ptr::copy(
+                    ticks.add(index as usize + 1),
+                    ticks.add(index as usize),
+                    count,
+                );
+            }
+        } else {
+            if size != 0 {
                 ptr::copy_nonoverlapping(
-                    self.get_dynamic(ty.id, ty.layout.size(), last)
-                        .unwrap()
-                        .as_ptr(),
-                    moved,
-                    ty.layout.size(),
+                    base.add(last as usize * size),
+                    base.add(index as usize * size),
+                    size,
                 );
             }
+            self.copy_ticks(ty.id, last, index);
         }
-        self.len -= 1;
-        if index != last {
-            self.entities[index as usize] = self.entities[last as usize];
-            Some(self.entities[last as usize])
+    }
+
+    /// Close the gap left in `entities` by removing the entity at `index`, once every component
+    /// has been dealt with by `fill_gap`
+    fn finish_removal(&mut self, index: u32) -> Option<EntityId> {
+        let last = self.len - 1;
+        self.len = last;
+        if index == last {
+            return None;
+        }
+        if self.preserve_order {
+            self.entities
+                .copy_within(index as usize + 1..=last as usize, index as usize);
         } else {
-            None
+            self.entities[index as usize] = self.entities[last as usize];
+        }
+        Some(self.entities[index as usize])
+    }
+
+    /// Reorder entities so that the one previously at `order[i]` moves to `i`, keeping its ticks
+    ///
+    /// `order` must be a permutation of `0..len`.
+    pub(crate) unsafe fn permute(&mut self, order: &[u32]) {
+        debug_assert_eq!(order.len(), self.len as usize);
+        let len = self.len as usize;
+        let data = (*self.data.get()).as_ptr();
+        // Never read as bytes, since components may contain padding
+        let mut scratch = Vec::<u8>::new();
+        for ty in &self.types {
+            let state = self.state.get(&ty.id).unwrap();
+            let size = ty.layout.size();
+            if size != 0 {
+                let base = data.add(state.offset);
+                scratch.clear();
+                scratch.reserve(size * len);
+                ptr::copy_nonoverlapping(base, scratch.as_mut_ptr(), size * len);
+                for (i, &src) in order.iter().enumerate() {
+                    ptr::copy_nonoverlapping(
+                        scratch.as_ptr().add(src as usize * size),
+                        base.add(i * size),
+                        size,
+                    );
+                }
+            }
+            for &offset in &[state.added, state.mutated] {
+                let ticks = self.ticks(offset).as_ptr();
+                let old = core::slice::from_raw_parts(ticks, len).to_vec();
+                for (i, &src) in order.iter().enumerate() {
+                    *ticks.add(i) = old[src as usize];
+                }
+            }
+        }
+        let old = self.entities[..len].to_vec();
+        for (i, &src) in order.iter().enumerate() {
+            self.entities[i] = old[src as usize];
+        }
+    }
+
+    /// Assume that the first `len` entities are initialized, e.g. by a `ColumnBatch` writer
+    pub(crate) unsafe fn set_len(&mut self, len: u32) {
+        assert!(len <= self.capacity());
+        self.len = len;
+    }
+
+    pub(crate) fn set_entity_id(&mut self, index: u32, id: EntityId) {
+        self.entities[index as usize] = id;
+    }
+
+    /// Move every entity of `other`, which must have the same component types, onto the end of
+    /// `self`, stamping its components as newly added
+    ///
+    /// The IDs of the moved entities must then be assigned with `set_entity_id`. `other` is left
+    /// empty, keeping its storage.
+    pub(crate) unsafe fn merge(&mut self, other: &mut Archetype) {
+        debug_assert!(self.types == other.types);
+        let len = other.len;
+        if len == 0 {
+            return;
+        }
+        self.reserve(len);
+        let data = (*self.data.get()).as_ptr();
+        let other_data = (*other.data.get()).as_ptr();
+        for ty in &self.types {
+            let dst = self.state.get(&ty.id).unwrap();
+            let src = other.state.get(&ty.id).unwrap();
+            let size = ty.layout.size();
+            ptr::copy_nonoverlapping(
+                other_data.add(src.offset),
+                data.add(dst.offset + size * self.len as usize),
+                size * len as usize,
+            );
+            for &offset in &[dst.added, dst.mutated] {
+                let ticks = self.ticks(offset).as_ptr().add(self.len as usize);
+                for i in 0..len as usize {
+                    *ticks.add(i) = self.tick;
+                }
+            }
         }
+        self.len += len;
+        // The components now belong to `self`
+        other.len = 0;
     }
 
+    /// Move every entity to the end of `target`, dropping the components `target` doesn't store
+    ///
+    /// Returns the index in `target` of the first entity moved. Change ticks are preserved. Any
+    /// components of types this archetype lacks must be written to the moved entities.
+    pub(crate) unsafe fn move_all_to(&mut self, target: &mut Archetype) -> u32 {
+        let len = self.len;
+        let base = target.len;
+        if len == 0 {
+            return base;
+        }
+        target.reserve(len);
+        let data = (*self.data.get()).as_ptr();
+        let target_data = (*target.data.get()).as_ptr();
+        for ty in &self.types {
+            let src = self.state.get(&ty.id).unwrap();
+            let size = ty.layout.size();
+            let dst = match target.state.get(&ty.id) {
+                Some(x) => x,
+                None => {
+                    if ty.needs_drop {
+                        for i in 0..len as usize {
+                            (ty.drop)(data.add(src.offset + size * i));
+                        }
+                    }
+                    continue;
+                }
+            };
+            ptr::copy_nonoverlapping(
+                data.add(src.offset),
+                target_data.add(dst.offset + size * base as usize),
+                size * len as usize,
+            );
+            for &(src, dst) in &[(src.added, dst.added), (src.mutated, dst.mutated)] {
+                ptr::copy_nonoverlapping(
+                    self.ticks(src).as_ptr(),
+                    target.ticks(dst).as_ptr().add(base as usize),
+                    len as usize,
+                );
+            }
+        }
+        target.entities[base as usize..(base + len) as usize]
+            .copy_from_slice(&self.entities[..len as usize]);
+        target.len += len;
+        self.len = 0;
+        base
+    }
+
+    /// Write a newly added component, stamping it with the current tick
     pub(crate) unsafe fn put_dynamic(
         &mut self,
         component: *mut u8,
@@ -318,12 +888,23 @@ impl Archetype {
         size: usize,
         index: u32,
     ) {
-        let ptr = self
-            .get_dynamic(ty, size, index)
-            .unwrap()
-            .as_ptr()
-            .cast::<u8>();
-        ptr::copy_nonoverlapping(component, ptr, size);
+        if size != 0 {
+            let ptr = self
+                .get_dynamic(ty, size, index)
+                .unwrap()
+                .as_ptr()
+                .cast::<u8>();
+            ptr::copy_nonoverlapping(component, ptr, size);
+        }
+        let tick = self.tick;
+        self.set_ticks(
+            ty,
+            index,
+            ComponentTicks {
+                added: tick,
+                mutated: tick,
+            },
+        );
     }
 
     /// How, if at all, `Q` will access entities in this archetype
@@ -337,12 +918,9 @@ impl Drop for Archetype {
         self.clear();
         if self.data_size != 0 {
             unsafe {
-                dealloc(
+                self.dealloc_data(
                     (*self.data.get()).as_ptr().cast(),
-                    Layout::from_size_align_unchecked(
-                        self.data_size,
-                        self.types.first().map_or(1, |x| x.layout.align()),
-                    ),
+                    Layout::from_size_align_unchecked(self.data_size, self.data_align()),
                 );
             }
         }
@@ -393,26 +971,49 @@ impl Hasher for TypeIdHasher {
 /// faster no-op hash.
 pub(crate) type TypeIdMap<V> = HashMap<TypeId, V, BuildHasherDefault<TypeIdHasher>>;
 
+/// One entity's component, as found by `Archetype::locate`
+pub(crate) struct ComponentSlot<'a, T> {
+    pub target: NonNull<T>,
+    pub mutated: NonNull<AtomicU32>,
+    pub borrow: &'a AtomicBorrow,
+}
+
 struct TypeState {
     offset: usize,
+    /// Offset of the per-entity added ticks
+    added: usize,
+    /// Offset of the per-entity mutated ticks
+    mutated: usize,
     borrow: AtomicBorrow,
 }
 
 impl TypeState {
-    fn new(offset: usize) -> Self {
+    fn new(offset: usize, added: usize, mutated: usize) -> Self {
         Self {
             offset,
+            added,
+            mutated,
             borrow: AtomicBorrow::new(),
         }
     }
 }
 
+/// When a component was added to an entity and when it was last mutably accessed
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct ComponentTicks {
+    pub(crate) added: u32,
+    pub(crate) mutated: u32,
+}
+
 /// Metadata required to store a component
 #[derive(Debug, Copy, Clone)]
 pub struct TypeInfo {
     id: TypeId,
     layout: Layout,
     drop: unsafe fn(*mut u8),
+    /// Whether `drop` does anything, e.g. `false` for marker types
+    needs_drop: bool,
+    stable_id: Option<StableTypeId>,
     #[cfg(debug_assertions)]
     type_name: &'static str,
 }
@@ -428,20 +1029,87 @@ impl TypeInfo {
             id: TypeId::of::<T>(),
             layout: Layout::new::<T>(),
             drop: drop_ptr::<T>,
+            needs_drop: mem::needs_drop::<T>(),
+            stable_id: None,
             #[cfg(debug_assertions)]
             type_name: core::any::type_name::<T>(),
         }
     }
 
-    pub(crate) fn id(&self) -> TypeId {
+    /// Metadata for `T`, including its [`StableTypeId`]
+    pub fn of_stable<T: StableComponent>() -> Self {
+        Self::of::<T>().with_stable_id(T::STABLE_TYPE_ID)
+    }
+
+    /// Metadata for a type that may only be known at runtime, e.g. one defined by a script
+    ///
+    /// `id` identifies the type to the `World`: every component stored under a given `id` must
+    /// have the same `layout` and `drop`. A `TypeId` may be borrowed from some otherwise-unused
+    /// marker type to stand in for a type Rust doesn't know about, but must not be that of a
+    /// different type that is also used as a component.
+    ///
+    /// # Safety
+    ///
+    /// `drop` must be sound to call on a pointer to any value of the described type, and `layout`
+    /// must describe that type.
+    pub unsafe fn from_parts(id: TypeId, layout: Layout, drop: unsafe fn(*mut u8)) -> Self {
+        Self {
+            id,
+            layout,
+            drop,
+            needs_drop: true,
+            stable_id: None,
+            #[cfg(debug_assertions)]
+            type_name: "<dynamic>",
+        }
+    }
+
+    /// Record `id` as the [`StableTypeId`] of the described type
+    ///
+    /// Lets types known only at runtime, such as those of plugins and scripts, be recognized
+    /// across compilations.
+    pub fn with_stable_id(self, id: StableTypeId) -> Self {
+        Self {
+            stable_id: Some(id),
+            ..self
+        }
+    }
+
+    /// Identifier of the described type
+    pub fn id(&self) -> TypeId {
         self.id
     }
 
-    pub(crate) fn layout(&self) -> Layout {
+    /// Identifier of the described type that's consistent across compilations, if known
+    ///
+    /// Only set by `of_stable` and `with_stable_id`; component types added to a `World` by bundles
+    /// are described by `of`.
+    pub fn stable_id(&self) -> Option<StableTypeId> {
+        self.stable_id
+    }
+
+    /// Size and alignment of the described type
+    pub fn layout(&self) -> Layout {
         self.layout
     }
 
-    pub(crate) unsafe fn drop(&self, data: *mut u8) {
+    /// Name of the described type, for diagnostics
+    #[cfg(debug_assertions)]
+    pub(crate) fn type_name(&self) -> &'static str {
+        self.type_name
+    }
+
+    /// A well-aligned address for a value of the described type, valid if it's zero-sized
+    fn dangling(&self) -> *mut u8 {
+        self.layout.align() as *mut u8
+    }
+
+    /// Drop the value at `data` in place
+    ///
+    /// # Safety
+    ///
+    /// `data` must point to a valid value of the described type, which must not be used after.
+    pub unsafe fn drop(&self, data: *mut u8) {
         (self.drop)(data)
     }
 }
@@ -0,0 +1,173 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use core::future::Future;
+use core::mem;
+use core::ops::{Deref, DerefMut};
+use core::pin::Pin;
+use core::task::{Context, Poll, Waker};
+use std::boxed::Box;
+use std::sync::{Arc, Mutex};
+use std::vec::Vec;
+
+use crate::World;
+
+type Op = Box<dyn FnOnce(&mut World) + Send>;
+
+/// A `World` that async tasks may operate on through [`WorldHandle`]s
+///
+/// Tasks submit closures with [`WorldHandle::with`] and await their results. The closures run,
+/// with exclusive access to the world, whenever the owner calls [`run_pending`](Self::run_pending),
+/// e.g. once per frame, so tasks such as network handlers and asset loaders never contend with
+/// the game loop for a lock. Dereferences to the wrapped `World`. Requires the `async` feature.
+///
+/// # Example
+/// ```
+/// # use hecs::*;
+/// let mut world = AsyncWorld::new(World::new());
+/// let handle = world.handle();
+///
+/// // In a task
+/// let op = handle.with(|world| world.spawn((42,)));
+///
+/// // Between frames
+/// assert_eq!(world.run_pending(), 1);
+/// assert_eq!(world.len(), 1);
+/// # drop(op);
+/// ```
+pub struct AsyncWorld {
+    world: World,
+    queue: Arc<Mutex<Vec<Op>>>,
+}
+
+impl AsyncWorld {
+    /// Accept operations on `world`
+    pub fn new(world: World) -> Self {
+        Self {
+            world,
+            queue: Arc::default(),
+        }
+    }
+
+    /// Get a handle through which operations may be submitted from any thread
+    pub fn handle(&self) -> WorldHandle {
+        WorldHandle {
+            queue: self.queue.clone(),
+        }
+    }
+
+    /// Run the operations submitted so far, in order of submission, returning how many ran
+    ///
+    /// Operations submitted while this runs, including by the operations themselves, wait for the
+    /// next call. If an operation panics, those after it are discarded.
+    pub fn run_pending(&mut self) -> usize {
+        let ops = mem::take(&mut *self.queue.lock().unwrap());
+        let n = ops.len();
+        for op in ops {
+            op(&mut self.world);
+        }
+        n
+    }
+
+    /// Number of operations waiting for `run_pending`
+    pub fn pending(&self) -> usize {
+        self.queue.lock().unwrap().len()
+    }
+
+    /// Stop accepting operations, returning the world
+    ///
+    /// Operations that haven't run are discarded.
+    pub fn into_inner(self) -> World {
+        self.world
+    }
+}
+
+impl Deref for AsyncWorld {
+    type Target = World;
+
+    fn deref(&self) -> &World {
+        &self.world
+    }
+}
+
+impl DerefMut for AsyncWorld {
+    fn deref_mut(&mut self) -> &mut World {
+        &mut self.world
+    }
+}
+
+/// A cloneable handle for submitting operations to an [`AsyncWorld`]
+#[derive(Clone)]
+pub struct WorldHandle {
+    queue: Arc<Mutex<Vec<Op>>>,
+}
+
+impl WorldHandle {
+    /// Submit `f` to be run on the world by the next `AsyncWorld::run_pending`
+    ///
+    /// Await the returned future for `f`'s result, which is `None` if the `AsyncWorld` discarded
+    /// the operation instead of running it. The operation is submitted even if the future is never
+    /// polled.
+    pub fn with<R, F>(&self, f: F) -> WorldOp<R>
+    where
+        R: Send + 'static,
+        F: FnOnce(&mut World) -> R + Send + 'static,
+    {
+        let slot = Arc::new(Mutex::new(Slot {
+            result: None,
+            done: false,
+            waker: None,
+        }));
+        let completion = Completion(slot.clone());
+        self.queue.lock().unwrap().push(Box::new(move |world| {
+            let result = f(world);
+            completion.0.lock().unwrap().result = Some(result);
+        }));
+        WorldOp { slot }
+    }
+}
+
+/// The result of an operation submitted by [`WorldHandle::with`]
+pub struct WorldOp<R> {
+    slot: Arc<Mutex<Slot<R>>>,
+}
+
+impl<R> Future for WorldOp<R> {
+    type Output = Option<R>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<R>> {
+        let mut slot = self.slot.lock().unwrap();
+        if slot.done {
+            return Poll::Ready(slot.result.take());
+        }
+        slot.waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+struct Slot<R> {
+    result: Option<R>,
+    /// Whether the operation ran or was discarded
+    done: bool,
+    waker: Option<Waker>,
+}
+
+/// Wakes the operation's future once dropped, whether or not the operation ran
+struct Completion<R>(Arc<Mutex<Slot<R>>>);
+
+impl<R> Drop for Completion<R> {
+    fn drop(&mut self) {
+        let waker = {
+            let mut slot = self.0.lock().unwrap_or_else(|e| e.into_inner());
+            slot.done = true;
+            slot.waker.take()
+        };
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
@@ -0,0 +1,210 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+#[cfg(not(loom))]
+use core::sync::atomic::{AtomicUsize, Ordering};
+#[cfg(loom)]
+use loom::sync::atomic::{AtomicUsize, Ordering};
+
+/// Runtime borrow state of one column, sparse column, or resource
+///
+/// Counts shared borrows in the low bits, with the high bit set while uniquely borrowed. Acquiring
+/// never blocks: a failed attempt leaves the state as it was, so callers may panic, report the
+/// conflict, or try again later.
+///
+/// Built on loom's atomics under `--cfg loom`, for the model tests below.
+pub(crate) struct AtomicBorrow(AtomicUsize);
+
+impl AtomicBorrow {
+    pub fn new() -> Self {
+        Self(AtomicUsize::new(0))
+    }
+
+    /// Acquire a shared borrow, returning `false` if uniquely borrowed
+    pub fn borrow(&self) -> bool {
+        let value = self.0.fetch_add(1, Ordering::Acquire).wrapping_add(1);
+        if value == 0 {
+            // Wrapped, this borrow is invalid!
+            core::panic!()
+        }
+        if value & UNIQUE_BIT != 0 {
+            self.0.fetch_sub(1, Ordering::Release);
+            false
+        } else {
+            true
+        }
+    }
+
+    /// Acquire a unique borrow, returning `false` if borrowed at all
+    pub fn borrow_mut(&self) -> bool {
+        self.0
+            .compare_exchange(0, UNIQUE_BIT, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+    }
+
+    /// Release a shared borrow acquired by `borrow`
+    pub fn release(&self) {
+        let value = self.0.fetch_sub(1, Ordering::Release);
+        debug_assert!(value != 0, "unbalanced release");
+        debug_assert!(value & UNIQUE_BIT == 0, "shared release of unique borrow");
+    }
+
+    /// Release a unique borrow acquired by `borrow_mut`
+    pub fn release_mut(&self) {
+        let value = self.0.fetch_and(!UNIQUE_BIT, Ordering::Release);
+        debug_assert_ne!(value & UNIQUE_BIT, 0, "unique release of shared borrow");
+    }
+}
+
+const UNIQUE_BIT: usize = !(usize::MAX >> 1);
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exclusion() {
+        let x = AtomicBorrow::new();
+        assert!(x.borrow());
+        assert!(x.borrow());
+        assert!(!x.borrow_mut());
+        x.release();
+        assert!(!x.borrow_mut());
+        x.release();
+        assert!(x.borrow_mut());
+        assert!(!x.borrow());
+        assert!(!x.borrow_mut());
+        x.release_mut();
+        assert!(x.borrow());
+        x.release();
+        assert_eq!(x.0.load(Ordering::Relaxed), 0);
+    }
+
+    /// Threads contending for one flag never hold a unique borrow alongside any other
+    #[cfg(feature = "std")]
+    #[test]
+    fn contention() {
+        use core::sync::atomic::AtomicU32;
+        use std::thread;
+
+        const THREADS: u32 = 4;
+        const ITERS: u32 = if cfg!(miri) { 20 } else { 10_000 };
+
+        let flag = AtomicBorrow::new();
+        // Number of shared holders, or `u32::MAX` while uniquely held
+        let holders = AtomicU32::new(0);
+        thread::scope(|s| {
+            for i in 0..THREADS {
+                let (flag, holders) = (&flag, &holders);
+                s.spawn(move || {
+                    for j in 0..ITERS {
+                        if (i + j) % 3 == 0 {
+                            if flag.borrow_mut() {
+                                assert_eq!(holders.swap(u32::MAX, Ordering::Relaxed), 0);
+                                assert_eq!(holders.swap(0, Ordering::Relaxed), u32::MAX);
+                                flag.release_mut();
+                            }
+                        } else if flag.borrow() {
+                            assert_ne!(holders.fetch_add(1, Ordering::Relaxed), u32::MAX);
+                            holders.fetch_sub(1, Ordering::Relaxed);
+                            flag.release();
+                        }
+                    }
+                });
+            }
+        });
+        assert_eq!(flag.0.load(Ordering::Relaxed), 0);
+        assert!(flag.borrow_mut());
+    }
+}
+
+/// Model tests exploring every interleaving of a few threads, run with
+/// `RUSTFLAGS="--cfg loom" cargo test --release --lib atomic_borrow`
+#[cfg(all(test, loom))]
+mod loom_tests {
+    use super::*;
+    use loom::cell::UnsafeCell;
+    use loom::sync::Arc;
+    use loom::thread;
+
+    /// A value guarded by a flag, as a column is, so that loom reports accesses the flag fails to
+    /// order
+    struct Guarded {
+        flag: AtomicBorrow,
+        value: UnsafeCell<u32>,
+    }
+
+    impl Guarded {
+        fn new() -> Arc<Self> {
+            Arc::new(Self {
+                flag: AtomicBorrow::new(),
+                value: UnsafeCell::new(0),
+            })
+        }
+
+        fn read(&self) -> Option<u32> {
+            if !self.flag.borrow() {
+                return None;
+            }
+            let x = self.value.with(|x| unsafe { *x });
+            self.flag.release();
+            Some(x)
+        }
+
+        fn write(&self) -> bool {
+            if !self.flag.borrow_mut() {
+                return false;
+            }
+            self.value.with_mut(|x| unsafe { *x += 1 });
+            self.flag.release_mut();
+            true
+        }
+    }
+
+    #[test]
+    fn shared_and_unique() {
+        loom::model(|| {
+            let x = Guarded::new();
+            let y = x.clone();
+            let reader = thread::spawn(move || y.read());
+            let wrote = x.write();
+            let read = reader.join().unwrap();
+            // A reader yielding to a writer never sees an empty flag
+            assert!(wrote || read.is_some());
+            assert_eq!(x.read(), Some(wrote as u32));
+            assert!(x.flag.borrow_mut());
+        });
+    }
+
+    #[test]
+    fn unique_and_unique() {
+        loom::model(|| {
+            let x = Guarded::new();
+            let y = x.clone();
+            let writer = thread::spawn(move || y.write());
+            let wrote = x.write() as u32 + writer.join().unwrap() as u32;
+            assert!(wrote >= 1);
+            assert_eq!(x.read(), Some(wrote));
+        });
+    }
+
+    #[test]
+    fn shared_and_shared() {
+        loom::model(|| {
+            let x = Guarded::new();
+            let y = x.clone();
+            let reader = thread::spawn(move || {
+                assert!(y.flag.borrow());
+                assert!(!y.flag.borrow_mut());
+                y.flag.release();
+            });
+            assert_eq!(x.read(), Some(0));
+            reader.join().unwrap();
+            assert!(x.write());
+        });
+    }
+}
@@ -0,0 +1,665 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use crate::alloc::vec::Vec;
+use core::alloc::Layout;
+use core::any::TypeId;
+use core::convert::TryFrom;
+use core::fmt;
+use core::marker::PhantomData;
+use core::mem::MaybeUninit;
+use core::ops::Range;
+use core::ptr::NonNull;
+use core::{ptr, slice};
+
+#[cfg(feature = "std")]
+use std::error::Error;
+
+use crate::archetype::{Archetype, TypeIdMap, TypeInfo};
+use crate::Component;
+
+/// A collection of component types
+#[derive(Debug, Clone, Default)]
+pub struct ColumnBatchType {
+    types: Vec<TypeInfo>,
+}
+
+impl ColumnBatchType {
+    /// Create an empty type
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Update to include `T` components
+    ///
+    /// Has no effect if `T` is already included.
+    pub fn add<T: Component>(&mut self) -> &mut Self {
+        self.add_dynamic(TypeInfo::of::<T>())
+    }
+
+    /// Update to include components of type `ty`
+    ///
+    /// Has no effect if `ty` is already included.
+    pub fn add_dynamic(&mut self, ty: TypeInfo) -> &mut Self {
+        if !self.has_dynamic(ty.id()) {
+            self.types.push(ty);
+        }
+        self
+    }
+
+    /// Update to exclude `T` components
+    pub fn remove<T: Component>(&mut self) -> &mut Self {
+        self.remove_dynamic(TypeId::of::<T>())
+    }
+
+    /// Update to exclude components of type `id`
+    pub fn remove_dynamic(&mut self, id: TypeId) -> &mut Self {
+        self.types.retain(|x| x.id() != id);
+        self
+    }
+
+    /// Whether `T` components are included
+    pub fn has<T: Component>(&self) -> bool {
+        self.has_dynamic(TypeId::of::<T>())
+    }
+
+    /// Whether components of type `id` are included
+    pub fn has_dynamic(&self, id: TypeId) -> bool {
+        self.types.iter().any(|x| x.id() == id)
+    }
+
+    /// Construct a builder for a batch of up to `size` entities having these components
+    pub fn into_batch(self, size: u32) -> ColumnBatchBuilder {
+        ColumnBatchBuilder::new(self, size)
+    }
+}
+
+/// An incomplete collection of component data for entities with the same component types
+///
+/// Fill each column through `writer`, then call `build` to obtain a `ColumnBatch`.
+///
+/// # Example
+/// ```
+/// # use hecs::*;
+/// let mut ty = ColumnBatchType::new();
+/// ty.add::<i32>().add::<bool>();
+/// let mut builder = ty.into_batch(2);
+/// let mut numbers = builder.writer::<i32>().unwrap();
+/// for i in 0..2 {
+///     numbers.push(i).unwrap();
+/// }
+/// let mut flags = builder.writer::<bool>().unwrap();
+/// flags.push(true).unwrap();
+/// flags.push(false).unwrap();
+/// let batch = builder.build().unwrap();
+/// let mut world = World::new();
+/// let entities = world.spawn_column_batch(batch).collect::<Vec<_>>();
+/// assert_eq!(*world.get::<i32>(entities[1]).unwrap(), 1);
+/// ```
+pub struct ColumnBatchBuilder {
+    /// Number of components written to each column
+    fill: TypeIdMap<u32>,
+    target_fill: u32,
+    /// Always `Some` until consumed by `build`
+    archetype: Option<Archetype>,
+    /// Columns exposed by `storage_for_dynamic`, whose unwritten storage was filled with `poison`
+    #[cfg(debug_assertions)]
+    poisoned: Vec<TypeId>,
+}
+
+unsafe impl Send for ColumnBatchBuilder {}
+unsafe impl Sync for ColumnBatchBuilder {}
+
+impl ColumnBatchBuilder {
+    /// Create a batch with space for up to `size` entities having the components in `ty`
+    pub fn new(mut ty: ColumnBatchType, size: u32) -> Self {
+        ty.types.sort_unstable();
+        ty.types.dedup();
+        let fill = ty.types.iter().map(|x| (x.id(), 0)).collect();
+        let mut archetype = Archetype::new(ty.types);
+        archetype.reserve(size);
+        Self {
+            fill,
+            target_fill: size,
+            archetype: Some(archetype),
+            #[cfg(debug_assertions)]
+            poisoned: Vec::new(),
+        }
+    }
+
+    /// Get a handle for appending `T` components, if the batch has any
+    pub fn writer<T: Component>(&mut self) -> Option<BatchWriter<'_, T>> {
+        let archetype = self.archetype.as_mut().unwrap();
+        let base = archetype.get::<T>()?;
+        let fill = self.fill.get_mut(&TypeId::of::<T>()).unwrap();
+        let storage =
+            unsafe { slice::from_raw_parts_mut(base.as_ptr().cast(), self.target_fill as usize) };
+        Some(BatchWriter {
+            storage: storage[*fill as usize..].iter_mut(),
+            fill,
+        })
+    }
+
+    /// Address, layout, and capacity of the column of `ty` components, if the batch has any
+    ///
+    /// For writing runtime-typed components, e.g. from a loader or FFI layer; the `i`th component
+    /// belongs `i * layout.size()` bytes past the returned address. Once written, components must
+    /// be accounted for with `set_fill_dynamic`.
+    ///
+    /// In debug builds, the column's unwritten storage is first filled with a byte pattern, so
+    /// that `set_fill_dynamic` can catch components that were never written.
+    pub fn storage_for_dynamic(&mut self, ty: TypeId) -> Option<(*mut u8, Layout, u32)> {
+        let archetype = self.archetype.as_ref().unwrap();
+        let base = archetype.get_base(ty)?;
+        let layout = archetype.type_info(ty).unwrap().layout();
+        #[cfg(debug_assertions)]
+        if !self.poisoned.contains(&ty) {
+            self.poisoned.push(ty);
+            let fill = self.fill[&ty] as usize;
+            for i in fill * layout.size()..self.target_fill as usize * layout.size() {
+                unsafe {
+                    base.as_ptr().add(i).write(poison(i));
+                }
+            }
+        }
+        Some((base.as_ptr(), layout, self.target_fill))
+    }
+
+    /// Record that the first `fill` components of the column of `ty` have been written, returning
+    /// `false` if the batch has no such column
+    ///
+    /// # Safety
+    ///
+    /// `fill` must not exceed the batch's capacity, and exactly the first `fill` components of the
+    /// column must hold valid values of type `ty`, whose ownership passes to the batch. Debug
+    /// builds panic, naming the rows, if components of at least 4 bytes that were exposed by
+    /// `storage_for_dynamic` are accounted for without having been written.
+    pub unsafe fn set_fill_dynamic(&mut self, ty: TypeId, fill: u32) -> bool {
+        debug_assert!(fill <= self.target_fill);
+        #[cfg(debug_assertions)]
+        self.check_written(ty, fill);
+        match self.fill.get_mut(&ty) {
+            Some(x) => {
+                *x = fill;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Panic if any component of the column of `ty` between its current fill and `fill` still
+    /// holds the pattern written by `storage_for_dynamic`
+    ///
+    /// Smaller components are skipped, since legitimate values are too likely to match.
+    #[cfg(debug_assertions)]
+    fn check_written(&self, ty: TypeId, fill: u32) {
+        let archetype = self.archetype.as_ref().unwrap();
+        let (Some(info), Some(&old)) = (archetype.type_info(ty), self.fill.get(&ty)) else {
+            return;
+        };
+        let size = info.layout().size();
+        if size < 4 || !self.poisoned.contains(&ty) {
+            return;
+        }
+        let base = archetype.get_base(ty).unwrap().as_ptr();
+        let mut unwritten = Vec::<Range<u32>>::new();
+        for row in old..fill {
+            let start = row as usize * size;
+            if !(start..start + size).all(|i| unsafe { *base.add(i) } == poison(i)) {
+                continue;
+            }
+            match unwritten.last_mut() {
+                Some(x) if x.end == row => x.end += 1,
+                _ => unwritten.push(row..row + 1),
+            }
+        }
+        if !unwritten.is_empty() {
+            panic!(
+                "rows {:?} of the {} column were accounted for without being written",
+                unwritten,
+                info.type_name()
+            );
+        }
+    }
+
+    /// Append `T` components from `iter` until it's exhausted or the column is full, returning
+    /// the column's new fill, or `None` if the batch has no `T` components
+    ///
+    /// Components beyond the column's capacity are not taken from `iter`.
+    pub fn fill<T: Component>(&mut self, iter: impl IntoIterator<Item = T>) -> Option<u32> {
+        let mut writer = self.writer::<T>()?;
+        for x in iter.into_iter().take(writer.storage.len()) {
+            if writer.push(x).is_err() {
+                unreachable!();
+            }
+        }
+        Some(writer.fill())
+    }
+
+    /// Move every component out of `components` onto the end of the `T` column
+    ///
+    /// Panics if the column lacks room, e.g. due to `T` being added more than once.
+    pub(crate) fn append<T: Component>(&mut self, components: &mut Vec<T>) {
+        if components.is_empty() {
+            // Storage may not yet be allocated
+            return;
+        }
+        let archetype = self.archetype.as_mut().unwrap();
+        let base = archetype.get::<T>().unwrap();
+        let fill = self.fill.get_mut(&TypeId::of::<T>()).unwrap();
+        let n = components.len() as u32;
+        assert!(
+            n <= self.target_fill - *fill,
+            "duplicate component type {}",
+            core::any::type_name::<T>()
+        );
+        unsafe {
+            ptr::copy_nonoverlapping(
+                components.as_ptr(),
+                base.as_ptr().add(*fill as usize),
+                n as usize,
+            );
+            components.set_len(0);
+        }
+        *fill += n;
+    }
+
+    /// Divide the batch's rows into `n` runs of nearly equal length, each of which may be filled
+    /// on a different thread
+    ///
+    /// Every column of each [`BatchChunk`] covers the same rows, so the `i`th row of every column
+    /// belongs to the same entity. Once the chunks are filled, `SplitFill::finish` accounts for
+    /// their components as if they had been appended to the builder in order. Panics if any
+    /// components have already been written, or if `n` is zero.
+    ///
+    /// # Example
+    /// ```
+    /// # use hecs::*;
+    /// let mut ty = ColumnBatchType::new();
+    /// ty.add::<u32>().add::<bool>();
+    /// let mut builder = ty.into_batch(1000);
+    /// let mut split = builder.split_fill(4);
+    /// std::thread::scope(|scope| {
+    ///     for chunk in split.chunks() {
+    ///         scope.spawn(move || {
+    ///             let range = chunk.range();
+    ///             chunk.fill(range.clone());
+    ///             chunk.fill(range.map(|i| i % 2 == 0));
+    ///         });
+    ///     }
+    /// });
+    /// assert_eq!(split.finish(), 1000);
+    /// let mut world = World::new();
+    /// let entities = world.spawn_column_batch(builder.build().unwrap()).collect::<Vec<_>>();
+    /// assert_eq!(*world.get::<u32>(entities[999]).unwrap(), 999);
+    /// ```
+    pub fn split_fill(&mut self, n: u32) -> SplitFill<'_> {
+        assert!(n > 0, "batch must be split at least once");
+        assert!(
+            self.fill.values().all(|&x| x == 0),
+            "batch must be empty to be split"
+        );
+        let archetype = self.archetype.as_ref().unwrap();
+        let columns = archetype
+            .types()
+            .iter()
+            .map(|ty| (*ty, archetype.get_base(ty.id()).unwrap()))
+            .collect::<Vec<_>>();
+        let chunks = (0..n)
+            .map(|i| BatchChunk {
+                columns: columns.iter().map(|&(ty, base)| (ty, base, 0)).collect(),
+                rows: chunk_rows(self.target_fill, i, n),
+                _marker: PhantomData,
+            })
+            .collect();
+        SplitFill {
+            builder: self,
+            chunks,
+        }
+    }
+
+    /// Finish the batch, discarding any components beyond the length of the shortest column
+    ///
+    /// # Example
+    /// ```
+    /// # use hecs::*;
+    /// let mut ty = ColumnBatchType::new();
+    /// ty.add::<i32>().add::<bool>();
+    /// let mut builder = ty.into_batch(4);
+    /// builder.fill(0..);
+    /// builder.fill(vec![true, false, true]);
+    /// let batch = builder.build_truncated();
+    /// assert_eq!(batch.len(), 3);
+    /// ```
+    pub fn build_truncated(mut self) -> ColumnBatch {
+        let len = self
+            .fill
+            .values()
+            .copied()
+            .min()
+            .unwrap_or(self.target_fill);
+        let archetype = self.archetype.as_ref().unwrap();
+        for ty in archetype.types() {
+            let fill = self.fill.get_mut(&ty.id()).unwrap();
+            let base = archetype.get_base(ty.id()).unwrap().as_ptr();
+            for i in len..*fill {
+                unsafe {
+                    ty.drop(base.add(i as usize * ty.layout().size()));
+                }
+            }
+            *fill = len;
+        }
+        match self.build() {
+            Ok(x) => x,
+            Err(BatchIncomplete) => unreachable!(),
+        }
+    }
+
+    /// Finish the batch, failing if the columns have different lengths
+    ///
+    /// On failure, components that were written are dropped.
+    pub fn build(mut self) -> Result<ColumnBatch, BatchIncomplete> {
+        let mut fills = self.fill.values().copied();
+        let len = fills.next().unwrap_or(self.target_fill);
+        if fills.any(|x| x != len) {
+            return Err(BatchIncomplete);
+        }
+        let mut archetype = self.archetype.take().unwrap();
+        unsafe {
+            archetype.set_len(len);
+        }
+        Ok(ColumnBatch(archetype))
+    }
+}
+
+impl Drop for ColumnBatchBuilder {
+    fn drop(&mut self) {
+        if let Some(archetype) = self.archetype.take() {
+            // Drop the components written so far, which the archetype doesn't know of
+            for ty in archetype.types() {
+                let fill = self.fill[&ty.id()] as usize;
+                if fill == 0 {
+                    continue;
+                }
+                let base = archetype.get_base(ty.id()).unwrap().as_ptr();
+                for i in 0..fill {
+                    unsafe {
+                        ty.drop(base.add(i * ty.layout().size()));
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Handle for appending components to a `ColumnBatchBuilder`
+pub struct BatchWriter<'a, T> {
+    fill: &'a mut u32,
+    storage: core::slice::IterMut<'a, MaybeUninit<T>>,
+}
+
+impl<T> BatchWriter<'_, T> {
+    /// Add a component if there's space remaining, otherwise return it
+    pub fn push(&mut self, x: T) -> Result<(), T> {
+        match self.storage.next() {
+            Some(slot) => {
+                *slot = MaybeUninit::new(x);
+                *self.fill += 1;
+                Ok(())
+            }
+            None => Err(x),
+        }
+    }
+
+    /// How many components have been added to the column so far
+    pub fn fill(&self) -> u32 {
+        *self.fill
+    }
+}
+
+/// The rows covered by the `i`th of `n` chunks of a batch of `size` entities
+fn chunk_rows(size: u32, i: u32, n: u32) -> Range<u32> {
+    let start = (u64::from(size) * u64::from(i) / u64::from(n)) as u32;
+    let end = (u64::from(size) * u64::from(i + 1) / u64::from(n)) as u32;
+    start..end
+}
+
+/// The rows of a `ColumnBatchBuilder` divided into `BatchChunk`s, returned by
+/// [`ColumnBatchBuilder::split_fill`]
+///
+/// Dropping this without calling `finish` has the same effect as calling it.
+pub struct SplitFill<'a> {
+    builder: &'a mut ColumnBatchBuilder,
+    chunks: Vec<BatchChunk<'a>>,
+}
+
+impl<'a> SplitFill<'a> {
+    /// The chunks, in order of the rows they cover
+    ///
+    /// Chunks must stay in place: `finish` panics, leaking their components, if any were swapped
+    /// with each other or with the chunks of another `SplitFill`.
+    pub fn chunks(&mut self) -> &mut [BatchChunk<'a>] {
+        &mut self.chunks
+    }
+
+    /// Account for the components written to each chunk, returning the length of the shortest
+    /// column
+    ///
+    /// Each column keeps its components up to the first chunk in which it wasn't completely
+    /// filled, including those written to that chunk. Components written to later chunks that
+    /// can't be kept are dropped.
+    pub fn finish(mut self) -> u32 {
+        self.account();
+        let builder = &self.builder;
+        builder
+            .fill
+            .values()
+            .copied()
+            .min()
+            .unwrap_or(builder.target_fill)
+    }
+
+    /// Update the builder's fill of each column, dropping components that can't be kept
+    ///
+    /// Idempotent, since dropped components are forgotten by their chunks.
+    fn account(&mut self) {
+        if self.chunks.is_empty() {
+            // Forgotten after failing validation
+            return;
+        }
+        let archetype = self.builder.archetype.as_ref().unwrap();
+        let n = self.chunks.len() as u32;
+        let valid = (0..n).zip(&self.chunks).all(|(i, chunk)| {
+            chunk.rows == chunk_rows(self.builder.target_fill, i, n)
+                && chunk
+                    .columns
+                    .iter()
+                    .all(|&(ty, base, _)| archetype.get_base(ty.id()) == Some(base))
+        });
+        if !valid {
+            // Components may lie in another builder's storage, so can be neither kept nor dropped
+            self.chunks.clear();
+            panic!("chunks were moved between positions or SplitFills");
+        }
+        for i in 0..self.chunks[0].columns.len() {
+            let ty = self.chunks[0].columns[i].0;
+            let mut fill = 0;
+            let mut contiguous = true;
+            for chunk in &mut self.chunks {
+                let (_, base, ref mut written) = chunk.columns[i];
+                if contiguous {
+                    fill += *written;
+                    contiguous = *written == chunk.rows.len() as u32;
+                    continue;
+                }
+                for row in chunk.rows.start..chunk.rows.start + *written {
+                    unsafe {
+                        ty.drop(base.as_ptr().add(row as usize * ty.layout().size()));
+                    }
+                }
+                *written = 0;
+            }
+            *self.builder.fill.get_mut(&ty.id()).unwrap() = fill;
+        }
+    }
+}
+
+impl Drop for SplitFill<'_> {
+    fn drop(&mut self) {
+        self.account();
+    }
+}
+
+/// A run of rows of a `ColumnBatchBuilder`, in every column, that may be filled independently of
+/// the others
+///
+/// Obtained from [`SplitFill::chunks`].
+pub struct BatchChunk<'a> {
+    /// Type, base address, and number of components written to this chunk, of each column
+    columns: Vec<(TypeInfo, NonNull<u8>, u32)>,
+    rows: Range<u32>,
+    _marker: PhantomData<&'a mut ColumnBatchBuilder>,
+}
+
+unsafe impl Send for BatchChunk<'_> {}
+unsafe impl Sync for BatchChunk<'_> {}
+
+impl BatchChunk<'_> {
+    /// The rows of the batch covered by this chunk
+    pub fn range(&self) -> Range<u32> {
+        self.rows.clone()
+    }
+
+    /// Get a handle for appending `T` components to this chunk, if the batch has any
+    pub fn writer<T: Component>(&mut self) -> Option<BatchWriter<'_, T>> {
+        let (_, base, fill) = self
+            .columns
+            .iter_mut()
+            .find(|x| x.0.id() == TypeId::of::<T>())?;
+        let storage = unsafe {
+            slice::from_raw_parts_mut(
+                base.as_ptr()
+                    .cast::<MaybeUninit<T>>()
+                    .add(self.rows.start as usize),
+                self.rows.len(),
+            )
+        };
+        Some(BatchWriter {
+            storage: storage[*fill as usize..].iter_mut(),
+            fill,
+        })
+    }
+
+    /// Append `T` components from `iter` until it's exhausted or the chunk's part of the column
+    /// is full, returning the number written so far, or `None` if the batch has no `T` components
+    pub fn fill<T: Component>(&mut self, iter: impl IntoIterator<Item = T>) -> Option<u32> {
+        let mut writer = self.writer::<T>()?;
+        for x in iter.into_iter().take(writer.storage.len()) {
+            if writer.push(x).is_err() {
+                unreachable!();
+            }
+        }
+        Some(writer.fill())
+    }
+}
+
+/// A collection of entities having the same component types, stored by column
+///
+/// Constructed with `ColumnBatchBuilder` and spawned with `World::spawn_column_batch`, which moves
+/// whole columns into the world at once.
+pub struct ColumnBatch(pub(crate) Archetype);
+
+impl ColumnBatch {
+    /// Number of entities in the batch
+    pub fn len(&self) -> u32 {
+        self.0.len()
+    }
+
+    /// Whether the batch contains no entities
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Drop every entity's components, keeping the storage for reuse
+    pub fn clear(&mut self) {
+        self.0.clear();
+    }
+
+    /// Empty the batch, then reuse its storage to build another with the same component types and
+    /// space for up to `size` entities
+    ///
+    /// Doesn't reallocate unless `size` exceeds the batch's capacity. See
+    /// `World::spawn_column_batch_from`.
+    pub fn into_builder(mut self, size: u32) -> ColumnBatchBuilder {
+        self.clear();
+        self.0.set_chunk_values(Vec::new());
+        self.0.reserve(size);
+        ColumnBatchBuilder {
+            fill: self.0.types().iter().map(|x| (x.id(), 0)).collect(),
+            target_fill: size,
+            archetype: Some(self.0),
+            #[cfg(debug_assertions)]
+            poisoned: Vec::new(),
+        }
+    }
+}
+
+/// Byte `i` of the pattern with which `storage_for_dynamic` fills unwritten storage in debug
+/// builds
+///
+/// Varies with position so that a component is unlikely to match by chance.
+#[cfg(debug_assertions)]
+fn poison(i: usize) -> u8 {
+    (i as u8).wrapping_mul(0x9d) ^ 0xa5
+}
+
+/// A tuple of `Vec`s of components, one column per component type, for `World::spawn_columns`
+///
+/// The `i`th element of every `Vec` belongs to the `i`th entity.
+pub trait ComponentColumns {
+    #[doc(hidden)]
+    fn into_column_batch(self) -> ColumnBatch;
+}
+
+macro_rules! columns_impl {
+    ($($name: ident),*) => {
+        impl<$($name: Component),*> ComponentColumns for ($(Vec<$name>,)*) {
+            #[allow(unused_mut, unused_variables, non_snake_case)]
+            fn into_column_batch(self) -> ColumnBatch {
+                let ($(mut $name,)*) = self;
+                let mut len = None;
+                $(
+                    match len {
+                        None => len = Some($name.len()),
+                        Some(n) => assert_eq!(n, $name.len(), "columns must have equal lengths"),
+                    }
+                )*
+                let len = u32::try_from(len.unwrap_or(0)).expect("too many entities");
+                let mut ty = ColumnBatchType::new();
+                $(ty.add::<$name>();)*
+                let mut builder = ty.into_batch(len);
+                $(builder.append(&mut $name);)*
+                builder.build().unwrap()
+            }
+        }
+    };
+}
+
+smaller_tuples_too!(columns_impl, O, N, M, L, K, J, I, H, G, F, E, D, C, B, A);
+
+/// Error indicating that a `ColumnBatchBuilder`'s columns were not all filled to the same length
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct BatchIncomplete;
+
+impl fmt::Display for BatchIncomplete {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad("batch columns have mismatched lengths")
+    }
+}
+
+#[cfg(feature = "std")]
+impl Error for BatchIncomplete {}
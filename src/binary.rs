@@ -0,0 +1,557 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use core::any::TypeId;
+use core::convert::{TryFrom, TryInto};
+use std::io::{self, Read, Write};
+
+use crate::alloc::{boxed::Box, vec::Vec};
+use hashbrown::{HashMap, HashSet};
+
+use crate::archetype::{Archetype, TypeIdMap};
+use crate::{
+    ArchetypeId, Children, ColumnBatchBuilder, ColumnBatchType, Component, ComponentRegistry,
+    Entity, EntityBits, EntityBuilder, Parent, StableComponent, World,
+};
+
+/// Identifies the binary world format produced by `World::save_to`
+#[cfg(not(feature = "u64-index"))]
+const MAGIC: [u8; 8] = *b"HECSWRLD";
+/// Identifies the binary world format produced by `World::save_to`, with 64-bit entity IDs
+#[cfg(feature = "u64-index")]
+const MAGIC: [u8; 8] = *b"HECSWR64";
+
+/// Identifies worlds saved with the other entity ID width
+#[cfg(not(feature = "u64-index"))]
+const OTHER_MAGIC: [u8; 8] = *b"HECSWR64";
+#[cfg(feature = "u64-index")]
+const OTHER_MAGIC: [u8; 8] = *b"HECSWRLD";
+
+/// Size of an encoded entity handle
+const ENTITY_SIZE: usize = core::mem::size_of::<EntityBits>();
+
+/// Revision of the binary world format, incremented on incompatible changes
+const FORMAT_VERSION: u32 = 1;
+
+/// The component types that may be saved by [`World::save_to`] and loaded by
+/// [`World::load_from`], and how to encode them
+///
+/// Each type is identified in saved worlds by a name, such as `"game::Position"`, and the version
+/// of its encoding. [`Parent`] and [`Children`] are always registered.
+///
+/// # Example
+/// ```
+/// # use hecs::*;
+/// use std::convert::TryInto;
+///
+/// struct Health(u32);
+/// let mut registry = BinaryRegistry::new();
+/// registry.register::<Health>(
+///     "game::Health",
+///     1,
+///     |x, out| out.extend_from_slice(&x.0.to_le_bytes()),
+///     |bytes| Some(Health(u32::from_le_bytes(bytes.try_into().ok()?))),
+/// );
+///
+/// let mut world = World::new();
+/// let a = world.spawn((Health(10), true));
+/// let mut save = Vec::new();
+/// world.save_to(&registry, &mut save).unwrap();
+///
+/// // `bool` isn't registered, so isn't saved
+/// let loaded = World::load_from(&registry, &save[..]).unwrap();
+/// assert_eq!(loaded.get::<Health>(a).unwrap().0, 10);
+/// assert!(loaded.get::<bool>(a).is_err());
+/// ```
+pub struct BinaryRegistry {
+    types: TypeIdMap<BinaryType>,
+    names: HashMap<Box<str>, TypeId>,
+    /// Functions upgrading components saved with each outdated version of each name
+    migrations: HashMap<Box<str>, HashMap<u32, Migration>>,
+}
+
+/// Converts the bytes of a component saved with an outdated encoding into components of an entity
+type Migration = fn(&[u8], &mut EntityBuilder);
+
+struct BinaryType {
+    name: Box<str>,
+    version: u32,
+    add: fn(&mut ColumnBatchType),
+    /// Append every component in an archetype's column, each prefixed by its length
+    write: Box<WriteColumn>,
+    /// Decode a column written by `write` into a batch, returning `None` if it's malformed
+    read: Box<ReadColumn>,
+}
+
+type WriteColumn = dyn Fn(&Archetype, &mut Vec<u8>) + Send + Sync;
+type ReadColumn = dyn Fn(&[u8], &mut ColumnBatchBuilder) -> Option<()> + Send + Sync;
+
+impl BinaryRegistry {
+    /// Create a registry of only the built-in component types
+    pub fn new() -> Self {
+        let mut x = Self {
+            types: TypeIdMap::default(),
+            names: HashMap::default(),
+            migrations: HashMap::default(),
+        };
+        x.register::<Parent>(
+            "hecs::Parent",
+            1,
+            |x, out| out.extend_from_slice(&x.0.to_bits().to_le_bytes()),
+            |bytes| Some(Parent(decode_entity(bytes)?)),
+        );
+        x.register::<Children>(
+            "hecs::Children",
+            1,
+            |x, out| {
+                for child in &x.0 {
+                    out.extend_from_slice(&child.to_bits().to_le_bytes());
+                }
+            },
+            |bytes| {
+                if bytes.len() % ENTITY_SIZE != 0 {
+                    return None;
+                }
+                bytes
+                    .chunks(ENTITY_SIZE)
+                    .map(decode_entity)
+                    .collect::<Option<_>>()
+                    .map(Children)
+            },
+        );
+        x
+    }
+
+    /// Allow `T` components to be saved as `name`, encoded with `encode` and decoded with
+    /// `decode`
+    ///
+    /// `decode` receives exactly the bytes `encode` produced for one component, and returns `None`
+    /// if they're malformed. `version` identifies the encoding; worlds saved with other versions
+    /// of `name` fail to load.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `name` is already registered for another type.
+    pub fn register<T: Component>(
+        &mut self,
+        name: &str,
+        version: u32,
+        encode: fn(&T, &mut Vec<u8>),
+        decode: fn(&[u8]) -> Option<T>,
+    ) -> &mut Self {
+        let ty = TypeId::of::<T>();
+        match self.names.get(name) {
+            Some(&x) => assert!(
+                x == ty,
+                "component name {:?} already registered for another type",
+                name
+            ),
+            None => {
+                self.names.insert(name.into(), ty);
+            }
+        }
+        if let Some(old) = self.types.get(&ty) {
+            if *old.name != *name {
+                self.names.remove(&old.name);
+            }
+        }
+        self.types.insert(
+            ty,
+            BinaryType {
+                name: name.into(),
+                version,
+                add: |x| {
+                    x.add::<T>();
+                },
+                write: Box::new(move |archetype, out| {
+                    let mut value = Vec::new();
+                    archetype.borrow::<T>();
+                    let base = archetype.get::<T>().unwrap();
+                    for i in 0..archetype.len() as usize {
+                        value.clear();
+                        encode(unsafe { &*base.as_ptr().add(i) }, &mut value);
+                        write_varint(out, value.len() as u64);
+                        out.extend_from_slice(&value);
+                    }
+                    archetype.release::<T>();
+                }),
+                read: Box::new(move |mut data, builder| {
+                    let mut writer = builder.writer::<T>()?;
+                    while !data.is_empty() {
+                        writer.push(decode(next_value(&mut data)?)?).ok()?;
+                    }
+                    Some(())
+                }),
+            },
+        );
+        self
+    }
+
+    /// Allow `T` components to be saved as its `STABLE_NAME`
+    ///
+    /// See `register`.
+    pub fn register_stable<T: StableComponent>(
+        &mut self,
+        version: u32,
+        encode: fn(&T, &mut Vec<u8>),
+        decode: fn(&[u8]) -> Option<T>,
+    ) -> &mut Self {
+        self.register(T::STABLE_NAME, version, encode, decode)
+    }
+
+    /// Allow every type given an encoding in `registry` to be saved
+    pub fn register_from(&mut self, registry: &ComponentRegistry) -> &mut Self {
+        for ty in registry.iter() {
+            ty.register_binary(self);
+        }
+        self
+    }
+
+    /// Upgrade components saved as `name` with `version` by passing their bytes to `migrate`
+    ///
+    /// `migrate` adds whatever components should replace the old one to the builder, which may
+    /// be of any type, including none at all if the component is obsolete. The added components
+    /// are inserted into the loaded entity after its other components, overwriting any of the
+    /// same type. Migrations are only used for versions other than the one `name` is registered
+    /// with, if any, so they may also upgrade components of types that no longer exist.
+    ///
+    /// # Example
+    /// ```
+    /// # use hecs::*;
+    /// use std::convert::TryInto;
+    ///
+    /// // Version 1 of health was a single byte
+    /// struct Health(u32);
+    /// struct Dead;
+    ///
+    /// let mut old = BinaryRegistry::new();
+    /// old.register::<u8>("game::Health", 1, |x, out| out.push(*x), |bytes| bytes.first().copied());
+    /// let mut world = World::new();
+    /// let a = world.spawn((0u8,));
+    /// let mut save = Vec::new();
+    /// world.save_to(&old, &mut save).unwrap();
+    ///
+    /// let mut registry = BinaryRegistry::new();
+    /// registry
+    ///     .register::<Health>(
+    ///         "game::Health",
+    ///         2,
+    ///         |x, out| out.extend_from_slice(&x.0.to_le_bytes()),
+    ///         |bytes| Some(Health(u32::from_le_bytes(bytes.try_into().ok()?))),
+    ///     )
+    ///     .migrate("game::Health", 1, |bytes, entity| {
+    ///         let health = u32::from(bytes[0]);
+    ///         entity.add(Health(health));
+    ///         if health == 0 {
+    ///             entity.add(Dead);
+    ///         }
+    ///     });
+    /// let loaded = World::load_from(&registry, &save[..]).unwrap();
+    /// assert_eq!(loaded.get::<Health>(a).unwrap().0, 0);
+    /// assert!(loaded.satisfies::<&Dead>(a).unwrap());
+    /// ```
+    pub fn migrate(&mut self, name: &str, version: u32, migrate: Migration) -> &mut Self {
+        self.migrations
+            .entry(name.into())
+            .or_default()
+            .insert(version, migrate);
+        self
+    }
+
+    /// How to load components saved as `name` with `version`
+    fn column(&self, name: &str, version: u32) -> io::Result<LoadColumn<'_>> {
+        let ty = self.names.get(name).map(|x| &self.types[x]);
+        if let Some(ty) = ty.filter(|x| x.version == version) {
+            return Ok(LoadColumn::Decode(ty));
+        }
+        if let Some(&migrate) = self.migrations.get(name).and_then(|x| x.get(&version)) {
+            return Ok(LoadColumn::Migrate(migrate));
+        }
+        match ty {
+            Some(_) => Err(invalid_data("unsupported component version")),
+            None => Ok(LoadColumn::Skip),
+        }
+    }
+
+    /// Whether components of type `ty` may be saved
+    pub fn is_registered(&self, ty: TypeId) -> bool {
+        self.types.contains_key(&ty)
+    }
+}
+
+impl Default for BinaryRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// How to load one of the component types listed in a saved world
+#[derive(Copy, Clone)]
+enum LoadColumn<'a> {
+    Decode(&'a BinaryType),
+    Migrate(Migration),
+    Skip,
+}
+
+impl World {
+    /// Write the world's entities and their components of types registered with `registry` to
+    /// `writer`, in a compact binary format
+    ///
+    /// The format begins with a header identifying it and listing the name and version of each
+    /// component type, followed by a block of columns for each archetype. Components of
+    /// unregistered types, sparse components, chunk components, and resources are not saved.
+    /// Entities keep their handles when loaded with [`load_from`](Self::load_from).
+    ///
+    /// Output is produced an archetype block at a time, so `writer` may be a compression or
+    /// encryption stream and memory use is bounded by the largest block rather than the whole
+    /// world. `writer` is flushed, but not otherwise finalized: pass adapters that need finishing,
+    /// such as compressors, by reference and finish them afterwards.
+    pub fn save_to(&self, registry: &BinaryRegistry, mut writer: impl Write) -> io::Result<()> {
+        trace_span!("serialize_world", format = "binary", entities = self.len());
+        let table = registry.types.iter().collect::<Vec<_>>();
+        let mut out = Vec::new();
+        out.extend_from_slice(&MAGIC);
+        write_u32(&mut out, FORMAT_VERSION);
+        write_u32(&mut out, table.len() as u32);
+        for (_, ty) in &table {
+            write_u32(&mut out, ty.name.len() as u32);
+            out.extend_from_slice(ty.name.as_bytes());
+            write_u32(&mut out, ty.version);
+        }
+
+        let archetypes = self
+            .archetype_ids()
+            .zip(self.archetypes())
+            .filter(|(_, x)| !x.is_empty())
+            .collect::<Vec<_>>();
+        write_u32(&mut out, archetypes.len() as u32);
+        writer.write_all(&out)?;
+        for (id, archetype) in archetypes {
+            out.clear();
+            self.write_archetype(&table, id, archetype, &mut out);
+            trace_event!(
+                TRACE,
+                archetype = ?id,
+                entities = archetype.len(),
+                bytes = out.len(),
+                "saved archetype"
+            );
+            writer.write_all(&out)?;
+        }
+        writer.flush()
+    }
+
+    fn write_archetype(
+        &self,
+        table: &[(&TypeId, &BinaryType)],
+        id: ArchetypeId,
+        archetype: &Archetype,
+        out: &mut Vec<u8>,
+    ) {
+        write_u32(out, archetype.len());
+        for (entity, ()) in self.query_archetype::<()>(id).iter() {
+            out.extend_from_slice(&entity.to_bits().to_le_bytes());
+        }
+        let columns = table
+            .iter()
+            .enumerate()
+            .filter(|(_, (ty, _))| archetype.has_dynamic(**ty))
+            .collect::<Vec<_>>();
+        write_u32(out, columns.len() as u32);
+        let mut column = Vec::new();
+        for (index, (_, ty)) in columns {
+            column.clear();
+            (ty.write)(archetype, &mut column);
+            write_u32(out, index as u32);
+            out.extend_from_slice(&(column.len() as u64).to_le_bytes());
+            out.extend_from_slice(&column);
+        }
+    }
+
+    /// Construct a world from the output of [`save_to`](Self::save_to)
+    ///
+    /// Components whose names aren't registered with `registry` are skipped, so worlds saved by
+    /// newer builds that know of more component types may still be loaded. Components saved with
+    /// an outdated version are upgraded by the function passed to
+    /// [`BinaryRegistry::migrate`] for that version. Fails with `io::ErrorKind::InvalidData` if
+    /// the data is malformed, or if a registered component type was saved with a different
+    /// version for which there's no migration.
+    ///
+    /// Input is consumed an archetype block at a time, in small reads, and never past the end of
+    /// the saved world, so `reader` may be a decompression or decryption stream, and data
+    /// following the world is left for the caller. Wrap unbuffered readers in a `BufReader`.
+    pub fn load_from(registry: &BinaryRegistry, mut reader: impl Read) -> io::Result<World> {
+        trace_span!("deserialize_world", format = "binary");
+        let mut magic = [0; 8];
+        reader.read_exact(&mut magic)?;
+        if magic == OTHER_MAGIC {
+            return Err(invalid_data(
+                "world saved with a different entity ID width; see the `u64-index` feature",
+            ));
+        }
+        if magic != MAGIC {
+            return Err(invalid_data("not a saved world"));
+        }
+        if read_u32(&mut reader)? != FORMAT_VERSION {
+            return Err(invalid_data("unsupported world format version"));
+        }
+
+        // How to load each component in the saved world's table
+        let mut table = Vec::new();
+        for _ in 0..read_u32(&mut reader)? {
+            let len = read_u32(&mut reader)?;
+            let name = read_bytes(&mut reader, len.into())?;
+            let name = core::str::from_utf8(&name).map_err(|_| invalid_data("invalid name"))?;
+            let version = read_u32(&mut reader)?;
+            table.push(registry.column(name, version)?);
+        }
+
+        let mut world = World::new();
+        for _ in 0..read_u32(&mut reader)? {
+            let len = read_u32(&mut reader)?;
+            let mut handles = Vec::new();
+            for _ in 0..len {
+                let mut bytes = [0; ENTITY_SIZE];
+                reader.read_exact(&mut bytes)?;
+                handles.push(Entity::from_bits(EntityBits::from_le_bytes(bytes)));
+            }
+            let mut columns = Vec::new();
+            // Components added by migrations, for each entity in turn
+            let mut migrated = Vec::new();
+            for _ in 0..read_u32(&mut reader)? {
+                let index = read_u32(&mut reader)? as usize;
+                let size = read_u64(&mut reader)?;
+                let column = *table
+                    .get(index)
+                    .ok_or_else(|| invalid_data("invalid component index"))?;
+                match column {
+                    LoadColumn::Skip => {
+                        io::copy(&mut reader.by_ref().take(size), &mut io::sink())?;
+                    }
+                    LoadColumn::Decode(ty) => columns.push((ty, read_bytes(&mut reader, size)?)),
+                    LoadColumn::Migrate(migrate) => {
+                        let data = read_bytes(&mut reader, size)?;
+                        if migrated.is_empty() {
+                            migrated.resize_with(handles.len(), EntityBuilder::new);
+                        }
+                        let mut values = &data[..];
+                        for entity in &mut migrated {
+                            let value = next_value(&mut values)
+                                .ok_or_else(|| invalid_data("invalid component"))?;
+                            migrate(value, entity);
+                        }
+                        if !values.is_empty() {
+                            return Err(invalid_data("wrong number of components"));
+                        }
+                    }
+                }
+            }
+
+            let mut batch_type = ColumnBatchType::new();
+            for (ty, _) in &columns {
+                (ty.add)(&mut batch_type);
+            }
+            let mut builder = batch_type.into_batch(len);
+            for (ty, data) in &columns {
+                (ty.read)(data, &mut builder).ok_or_else(|| invalid_data("invalid component"))?;
+            }
+            let batch = builder
+                .build()
+                .map_err(|_| invalid_data("wrong number of components"))?;
+            if handles.iter().any(|x| world.occupant(x.id()).is_some())
+                || handles.iter().map(|x| x.id()).collect::<HashSet<_>>().len() != handles.len()
+            {
+                return Err(invalid_data("duplicate entity"));
+            }
+            trace_event!(
+                TRACE,
+                entities = len,
+                bytes = columns.iter().map(|(_, data)| data.len()).sum::<usize>(),
+                "loaded archetype"
+            );
+            world.spawn_column_batch_at(&handles, batch);
+            for (&handle, components) in handles.iter().zip(&mut migrated) {
+                world.insert(handle, components.build()).unwrap();
+            }
+        }
+        trace_event!(DEBUG, entities = world.len(), "deserialized world");
+        Ok(world)
+    }
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Split the length-prefixed value at the start of `data` from the rest
+fn next_value<'a>(data: &mut &'a [u8]) -> Option<&'a [u8]> {
+    let len = usize::try_from(read_varint(data)?).ok()?;
+    if data.len() < len {
+        return None;
+    }
+    let (value, rest) = data.split_at(len);
+    *data = rest;
+    Some(value)
+}
+
+fn decode_entity(bytes: &[u8]) -> Option<Entity> {
+    Some(Entity::from_bits(EntityBits::from_le_bytes(
+        bytes.try_into().ok()?,
+    )))
+}
+
+fn write_u32(out: &mut Vec<u8>, x: u32) {
+    out.extend_from_slice(&x.to_le_bytes());
+}
+
+/// Read exactly `len` bytes, without trusting `len` enough to allocate them all up front
+fn read_bytes(reader: &mut impl Read, len: u64) -> io::Result<Vec<u8>> {
+    let mut data = Vec::new();
+    reader.take(len).read_to_end(&mut data)?;
+    if data.len() as u64 != len {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(data)
+}
+
+fn read_u32(reader: &mut impl Read) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64(reader: &mut impl Read) -> io::Result<u64> {
+    let mut bytes = [0; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+/// Append `x` in LEB128 form, so small values occupy a single byte
+fn write_varint(out: &mut Vec<u8>, mut x: u64) {
+    loop {
+        let byte = (x & 0x7f) as u8;
+        x >>= 7;
+        if x == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+fn read_varint(data: &mut &[u8]) -> Option<u64> {
+    let mut x = 0;
+    for shift in (0..64).step_by(7) {
+        let (&byte, rest) = data.split_first()?;
+        *data = rest;
+        x |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Some(x);
+        }
+    }
+    None
+}
@@ -5,59 +5,21 @@
 // http://opensource.org/licenses/MIT>, at your option. This file may not be
 // copied, modified, or distributed except according to those terms.
 
-use core::any::TypeId;
+use core::any::{type_name, TypeId};
 use core::ops::{Deref, DerefMut};
 use core::ptr::NonNull;
-use core::sync::atomic::{AtomicUsize, Ordering};
+use core::sync::atomic::{AtomicU32, Ordering};
 
-use crate::archetype::Archetype;
-use crate::{Component, MissingComponent};
-
-pub struct AtomicBorrow(AtomicUsize);
-
-impl AtomicBorrow {
-    pub const fn new() -> Self {
-        Self(AtomicUsize::new(0))
-    }
-
-    pub fn borrow(&self) -> bool {
-        let value = self.0.fetch_add(1, Ordering::Acquire).wrapping_add(1);
-        if value == 0 {
-            // Wrapped, this borrow is invalid!
-            core::panic!()
-        }
-        if value & UNIQUE_BIT != 0 {
-            self.0.fetch_sub(1, Ordering::Release);
-            false
-        } else {
-            true
-        }
-    }
-
-    pub fn borrow_mut(&self) -> bool {
-        self.0
-            .compare_exchange(0, UNIQUE_BIT, Ordering::Acquire, Ordering::Relaxed)
-            .is_ok()
-    }
-
-    pub fn release(&self) {
-        let value = self.0.fetch_sub(1, Ordering::Release);
-        debug_assert!(value != 0, "unbalanced release");
-        debug_assert!(value & UNIQUE_BIT == 0, "shared release of unique borrow");
-    }
-
-    pub fn release_mut(&self) {
-        let value = self.0.fetch_and(!UNIQUE_BIT, Ordering::Release);
-        debug_assert_ne!(value & UNIQUE_BIT, 0, "unique release of shared borrow");
-    }
-}
-
-const UNIQUE_BIT: usize = !(usize::max_value() >> 1);
+use crate::alloc::vec::Vec;
+use crate::archetype::{Archetype, TypeInfo};
+use crate::atomic_borrow::AtomicBorrow;
+use crate::entities::EntityMeta;
+use crate::{Component, Entity, EntityId, MissingComponent, Query, QueryOne};
 
 /// Shared borrow of an entity's component
 #[derive(Clone)]
 pub struct Ref<'a, T: Component> {
-    archetype: &'a Archetype,
+    borrow: &'a AtomicBorrow,
     target: NonNull<T>,
 }
 
@@ -66,15 +28,26 @@ impl<'a, T: Component> Ref<'a, T> {
         archetype: &'a Archetype,
         index: u32,
     ) -> Result<Self, MissingComponent> {
-        let target = NonNull::new_unchecked(
-            archetype
-                .get::<T>()
-                .ok_or_else(MissingComponent::new::<T>)?
-                .as_ptr()
-                .add(index as usize),
-        );
-        archetype.borrow::<T>();
-        Ok(Self { archetype, target })
+        Self::try_new(archetype, index)?
+            .map(Ok)
+            .unwrap_or_else(|| panic!("{} already borrowed uniquely", type_name::<T>()))
+    }
+
+    /// Like `new`, but returning `None` if the component is already uniquely borrowed
+    pub(crate) unsafe fn try_new(
+        archetype: &'a Archetype,
+        index: u32,
+    ) -> Result<Option<Self>, MissingComponent> {
+        let slot = archetype
+            .locate::<T>(index)
+            .ok_or_else(MissingComponent::new::<T>)?;
+        if !slot.borrow.borrow() {
+            return Ok(None);
+        }
+        Ok(Some(Self {
+            borrow: slot.borrow,
+            target: slot.target,
+        }))
     }
 }
 
@@ -83,7 +56,7 @@ unsafe impl<T: Component> Sync for Ref<'_, T> {}
 
 impl<'a, T: Component> Drop for Ref<'a, T> {
     fn drop(&mut self) {
-        self.archetype.release::<T>();
+        self.borrow.release();
     }
 }
 
@@ -95,9 +68,13 @@ impl<'a, T: Component> Deref for Ref<'a, T> {
 }
 
 /// Unique borrow of an entity's component
+///
+/// The component is marked as changed the first time it's mutably dereferenced.
 pub struct RefMut<'a, T: Component> {
-    archetype: &'a Archetype,
+    borrow: &'a AtomicBorrow,
     target: NonNull<T>,
+    mutated: NonNull<AtomicU32>,
+    tick: u32,
 }
 
 impl<'a, T: Component> RefMut<'a, T> {
@@ -105,15 +82,28 @@ impl<'a, T: Component> RefMut<'a, T> {
         archetype: &'a Archetype,
         index: u32,
     ) -> Result<Self, MissingComponent> {
-        let target = NonNull::new_unchecked(
-            archetype
-                .get::<T>()
-                .ok_or_else(MissingComponent::new::<T>)?
-                .as_ptr()
-                .add(index as usize),
-        );
-        archetype.borrow_mut::<T>();
-        Ok(Self { archetype, target })
+        Self::try_new(archetype, index)?
+            .map(Ok)
+            .unwrap_or_else(|| panic!("{} already borrowed", type_name::<T>()))
+    }
+
+    /// Like `new`, but returning `None` if the component is already borrowed
+    pub(crate) unsafe fn try_new(
+        archetype: &'a Archetype,
+        index: u32,
+    ) -> Result<Option<Self>, MissingComponent> {
+        let slot = archetype
+            .locate::<T>(index)
+            .ok_or_else(MissingComponent::new::<T>)?;
+        if !slot.borrow.borrow_mut() {
+            return Ok(None);
+        }
+        Ok(Some(Self {
+            borrow: slot.borrow,
+            target: slot.target,
+            mutated: slot.mutated,
+            tick: archetype.tick(),
+        }))
     }
 }
 
@@ -122,7 +112,7 @@ unsafe impl<T: Component> Sync for RefMut<'_, T> {}
 
 impl<'a, T: Component> Drop for RefMut<'a, T> {
     fn drop(&mut self) {
-        self.archetype.release_mut::<T>();
+        self.borrow.release_mut();
     }
 }
 
@@ -135,7 +125,312 @@ impl<'a, T: Component> Deref for RefMut<'a, T> {
 
 impl<'a, T: Component> DerefMut for RefMut<'a, T> {
     fn deref_mut(&mut self) -> &mut T {
-        unsafe { self.target.as_mut() }
+        unsafe {
+            self.mutated.as_ref().store(self.tick, Ordering::Relaxed);
+            self.target.as_mut()
+        }
+    }
+}
+
+/// Shared borrow of every `T` component in an archetype, as a slice
+///
+/// Obtained from [`World::columns`](crate::World::columns). Useful for processing many components
+/// at once, e.g. with SIMD.
+pub struct Column<'a, T: Component> {
+    meta: &'a [EntityMeta],
+    archetype: &'a Archetype,
+    slice: &'a [T],
+}
+
+impl<'a, T: Component> Column<'a, T> {
+    pub(crate) fn new(meta: &'a [EntityMeta], archetype: &'a Archetype) -> Option<Self> {
+        let base = archetype.get::<T>()?;
+        archetype.borrow::<T>();
+        let slice = unsafe { core::slice::from_raw_parts(base.as_ptr(), archetype.len() as usize) };
+        Some(Self {
+            meta,
+            archetype,
+            slice,
+        })
+    }
+
+    /// The entity owning each component, in the same order
+    pub fn entities(&self) -> ColumnEntities<'a> {
+        ColumnEntities::new(self.meta, self.archetype)
+    }
+}
+
+#[cfg(feature = "bytemuck")]
+impl<T: Component + bytemuck::Pod> Column<'_, T> {
+    /// The components' memory, e.g. for uploading to a GPU without copying
+    ///
+    /// # Example
+    /// ```
+    /// # use hecs::*;
+    /// let mut world = World::new();
+    /// world.spawn(([1.0f32, 2.0],));
+    /// let column = world.columns::<[f32; 2]>().next().unwrap();
+    /// assert_eq!(column.as_bytes(), bytemuck::cast_slice::<f32, u8>(&[1.0, 2.0]));
+    /// ```
+    pub fn as_bytes(&self) -> &[u8] {
+        bytemuck::cast_slice(self.slice)
+    }
+}
+
+unsafe impl<T: Component> Send for Column<'_, T> {}
+unsafe impl<T: Component> Sync for Column<'_, T> {}
+
+impl<T: Component> Drop for Column<'_, T> {
+    fn drop(&mut self) {
+        self.archetype.release::<T>();
+    }
+}
+
+impl<T: Component> Deref for Column<'_, T> {
+    type Target = [T];
+    fn deref(&self) -> &[T] {
+        self.slice
+    }
+}
+
+/// Unique borrow of every `T` component in an archetype, as a slice
+///
+/// Obtained from [`World::columns_mut`](crate::World::columns_mut). Every component is marked as
+/// changed when this is constructed.
+pub struct ColumnMut<'a, T: Component> {
+    meta: &'a [EntityMeta],
+    archetype: &'a Archetype,
+    slice: &'a mut [T],
+}
+
+impl<'a, T: Component> ColumnMut<'a, T> {
+    pub(crate) fn new(meta: &'a [EntityMeta], archetype: &'a Archetype) -> Option<Self> {
+        let base = archetype.get::<T>()?;
+        archetype.borrow_mut::<T>();
+        let len = archetype.len() as usize;
+        let tick = archetype.tick();
+        unsafe {
+            let mutated = archetype.mutated::<T>().unwrap().as_ptr();
+            for i in 0..len {
+                (*mutated.add(i)).store(tick, Ordering::Relaxed);
+            }
+        }
+        let slice = unsafe { core::slice::from_raw_parts_mut(base.as_ptr(), len) };
+        Some(Self {
+            meta,
+            archetype,
+            slice,
+        })
+    }
+
+    /// The entity owning each component, in the same order
+    pub fn entities(&self) -> ColumnEntities<'a> {
+        ColumnEntities::new(self.meta, self.archetype)
+    }
+}
+
+#[cfg(feature = "bytemuck")]
+impl<T: Component + bytemuck::Pod> ColumnMut<'_, T> {
+    /// The components' memory, e.g. for uploading to a GPU without copying
+    pub fn as_bytes(&self) -> &[u8] {
+        bytemuck::cast_slice(self.slice)
+    }
+
+    /// The components' memory, for writing
+    pub fn as_bytes_mut(&mut self) -> &mut [u8] {
+        bytemuck::cast_slice_mut(self.slice)
+    }
+
+    /// Overwrite every component with data from `bytes`, e.g. read back from a GPU
+    ///
+    /// # Panics
+    ///
+    /// Panics if `bytes` differs in length from the column.
+    pub fn copy_from_bytes(&mut self, bytes: &[u8]) {
+        self.as_bytes_mut().copy_from_slice(bytes);
+    }
+}
+
+unsafe impl<T: Component> Send for ColumnMut<'_, T> {}
+unsafe impl<T: Component> Sync for ColumnMut<'_, T> {}
+
+impl<T: Component> Drop for ColumnMut<'_, T> {
+    fn drop(&mut self) {
+        self.archetype.release_mut::<T>();
+    }
+}
+
+impl<T: Component> Deref for ColumnMut<'_, T> {
+    type Target = [T];
+    fn deref(&self) -> &[T] {
+        self.slice
+    }
+}
+
+impl<T: Component> DerefMut for ColumnMut<'_, T> {
+    fn deref_mut(&mut self) -> &mut [T] {
+        self.slice
+    }
+}
+
+/// Iterator over the entities of a `Column` or `ColumnMut`
+#[derive(Clone)]
+pub struct ColumnEntities<'a> {
+    meta: &'a [EntityMeta],
+    ids: core::slice::Iter<'a, EntityId>,
+}
+
+impl<'a> ColumnEntities<'a> {
+    fn new(meta: &'a [EntityMeta], archetype: &'a Archetype) -> Self {
+        let ids = unsafe {
+            core::slice::from_raw_parts(archetype.entities().as_ptr(), archetype.len() as usize)
+        };
+        Self::from_ids(meta, ids)
+    }
+
+    pub(crate) fn from_ids(meta: &'a [EntityMeta], ids: &'a [EntityId]) -> Self {
+        Self {
+            meta,
+            ids: ids.iter(),
+        }
+    }
+}
+
+impl Iterator for ColumnEntities<'_> {
+    type Item = Entity;
+
+    fn next(&mut self) -> Option<Entity> {
+        let id = *self.ids.next()?;
+        Some(Entity {
+            id,
+            generation: self.meta[id as usize].generation,
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.ids.size_hint()
+    }
+}
+
+impl ExactSizeIterator for ColumnEntities<'_> {
+    fn len(&self) -> usize {
+        self.ids.len()
+    }
+}
+
+/// Shared borrow of an entity's component whose type need not be statically known
+pub struct RawRef<'a> {
+    archetype: &'a Archetype,
+    ty: &'a TypeInfo,
+    target: NonNull<u8>,
+}
+
+impl<'a> RawRef<'a> {
+    pub(crate) unsafe fn new(archetype: &'a Archetype, index: u32, ty: TypeId) -> Option<Self> {
+        let (info, target) = match archetype.type_info(ty) {
+            Some(info) => (
+                info,
+                archetype.get_dynamic(ty, info.layout().size(), index)?,
+            ),
+            None => {
+                let (info, target, _) = archetype.get_sparse(ty, index)?;
+                (info, target)
+            }
+        };
+        if !archetype.borrow_dynamic(ty) {
+            panic!("component already borrowed uniquely");
+        }
+        Some(Self {
+            archetype,
+            ty: info,
+            target,
+        })
+    }
+
+    /// Metadata describing the component's type
+    pub fn type_info(&self) -> &TypeInfo {
+        self.ty
+    }
+
+    /// Address of the component, valid for reads for as long as the `RawRef` lives
+    pub fn as_ptr(&self) -> *const u8 {
+        self.target.as_ptr()
+    }
+}
+
+unsafe impl Send for RawRef<'_> {}
+unsafe impl Sync for RawRef<'_> {}
+
+impl Drop for RawRef<'_> {
+    fn drop(&mut self) {
+        self.archetype.release_dynamic(self.ty.id());
+    }
+}
+
+/// Unique borrow of an entity's component whose type need not be statically known
+///
+/// The component is marked as changed the first time a mutable pointer is taken.
+pub struct RawRefMut<'a> {
+    archetype: &'a Archetype,
+    ty: &'a TypeInfo,
+    target: NonNull<u8>,
+    mutated: NonNull<AtomicU32>,
+}
+
+impl<'a> RawRefMut<'a> {
+    pub(crate) unsafe fn new(archetype: &'a Archetype, index: u32, ty: TypeId) -> Option<Self> {
+        let (info, target, mutated) = match archetype.type_info(ty) {
+            Some(info) => (
+                info,
+                archetype.get_dynamic(ty, info.layout().size(), index)?,
+                NonNull::new_unchecked(
+                    archetype
+                        .mutated_dynamic(ty)
+                        .unwrap()
+                        .as_ptr()
+                        .add(index as usize),
+                ),
+            ),
+            None => archetype.get_sparse(ty, index)?,
+        };
+        if !archetype.borrow_mut_dynamic(ty) {
+            panic!("component already borrowed");
+        }
+        Some(Self {
+            archetype,
+            ty: info,
+            target,
+            mutated,
+        })
+    }
+
+    /// Metadata describing the component's type
+    pub fn type_info(&self) -> &TypeInfo {
+        self.ty
+    }
+
+    /// Address of the component, valid for reads for as long as the `RawRefMut` lives
+    pub fn as_ptr(&self) -> *const u8 {
+        self.target.as_ptr()
+    }
+
+    /// Address of the component, valid for reads and writes for as long as the `RawRefMut` lives
+    pub fn as_mut_ptr(&mut self) -> *mut u8 {
+        unsafe {
+            self.mutated
+                .as_ref()
+                .store(self.archetype.tick(), Ordering::Relaxed);
+        }
+        self.target.as_ptr()
+    }
+}
+
+unsafe impl Send for RawRefMut<'_> {}
+unsafe impl Sync for RawRefMut<'_> {}
+
+impl Drop for RawRefMut<'_> {
+    fn drop(&mut self) {
+        self.archetype.release_mut_dynamic(self.ty.id());
     }
 }
 
@@ -177,6 +472,56 @@ impl<'a> EntityRef<'a> {
         Some(unsafe { RefMut::new(self.archetype?, self.index).ok()? })
     }
 
+    /// Whether this entity would be yielded by a query for `Q`
+    ///
+    /// See [`World::satisfies`](crate::World::satisfies).
+    pub fn satisfies<Q: Query>(&self) -> bool {
+        match self.archetype {
+            Some(archetype) => unsafe { crate::world::satisfies::<Q>(archetype, self.index) },
+            None => unsafe { crate::world::satisfies::<Q>(&Archetype::new(Vec::new()), 0) },
+        }
+    }
+
+    /// Prepare a query for `Q` against this entity alone
+    ///
+    /// Equivalent to [`World::query_one`](crate::World::query_one), except that an entity which
+    /// has been reserved but not yet flushed satisfies no query.
+    ///
+    /// # Example
+    /// ```
+    /// # use hecs::*;
+    /// let mut world = World::new();
+    /// let a = world.spawn((123, true));
+    /// let entity = world.entity(a).unwrap();
+    /// let mut query = entity.query::<(&mut i32, Option<&&str>)>().with::<bool>();
+    /// let (number, name) = query.get().unwrap();
+    /// *number += 1;
+    /// assert!(name.is_none());
+    /// ```
+    pub fn query<Q: Query>(&self) -> QueryOne<'a, Q> {
+        unsafe { QueryOne::new(self.archetype, self.index) }
+    }
+
+    /// Borrow the component identified by `ty`, if it exists
+    ///
+    /// Unlike [`get`](Self::get), supports components whose types are only known at runtime.
+    ///
+    /// Panics if the component is already uniquely borrowed from another entity with the same
+    /// components.
+    pub fn get_raw(&self, ty: TypeId) -> Option<RawRef<'a>> {
+        unsafe { RawRef::new(self.archetype?, self.index, ty) }
+    }
+
+    /// Uniquely borrow the component identified by `ty`, if it exists
+    ///
+    /// Unlike [`get_mut`](Self::get_mut), supports components whose types are only known at
+    /// runtime.
+    ///
+    /// Panics if the component is already borrowed from another entity with the same components.
+    pub fn get_raw_mut(&self, ty: TypeId) -> Option<RawRefMut<'a>> {
+        unsafe { RawRefMut::new(self.archetype?, self.index, ty) }
+    }
+
     /// Enumerate the types of the entity's components
     ///
     /// Convenient for dispatching component-specific logic for a single entity. For example, this
@@ -184,9 +529,39 @@ impl<'a> EntityRef<'a> {
     /// user-defined trait with methods for serialization, or to be called after spawning or before
     /// despawning to maintain secondary indices.
     pub fn component_types(&self) -> impl Iterator<Item = TypeId> + 'a {
-        self.archetype
-            .into_iter()
-            .flat_map(|arch| arch.types().iter().map(|ty| ty.id()))
+        let index = self.index;
+        self.archetype.into_iter().flat_map(move |arch| {
+            let id = arch.entity_id(index);
+            let sparse = arch
+                .sparse_sets()
+                .filter(move |x| x.contains(id))
+                .map(|x| x.type_info().id());
+            arch.types().iter().map(|ty| ty.id()).chain(sparse)
+        })
+    }
+
+    /// Whether any of the entity's components were added or mutably accessed at or after `tick`,
+    /// a past value of [`World::change_tick`](crate::World::change_tick)
+    pub fn changed_since(&self, tick: u32) -> bool {
+        let arch = match self.archetype {
+            Some(x) => x,
+            None => return false,
+        };
+        // Ticks wrap, so measure how long ago each change was made
+        let current = arch.tick();
+        let recent = |mutated: u32| current.wrapping_sub(mutated) <= current.wrapping_sub(tick);
+        let id = arch.entity_id(self.index);
+        arch.types().iter().any(|ty| {
+            recent(
+                unsafe { arch.get_ticks(ty.id(), self.index) }
+                    .unwrap()
+                    .mutated,
+            )
+        }) || arch.sparse_sets().any(|set| {
+            set.row(id).is_some_and(|row| {
+                recent(unsafe { set.mutated(row).as_ref() }.load(Ordering::Relaxed))
+            })
+        })
     }
 }
 
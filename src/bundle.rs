@@ -14,6 +14,11 @@ use crate::archetype::TypeInfo;
 use crate::Component;
 
 /// A dynamically typed collection of components
+///
+/// # Safety
+///
+/// `with_ids`, `type_info`, and `put` must agree on the exact set of component types, and `put`
+/// must pass each component out exactly once.
 pub unsafe trait DynamicBundle {
     /// Invoke a callback on the fields' type IDs, sorted by descending alignment then id
     #[doc(hidden)]
@@ -30,6 +35,14 @@ pub unsafe trait DynamicBundle {
 }
 
 /// A statically typed collection of components
+///
+/// Implemented for tuples of up to 24 components. Nested tuples are not flattened, since a tuple
+/// of components is itself a valid component; use `derive(Bundle)` for larger sets.
+///
+/// # Safety
+///
+/// `with_static_ids`, `static_type_info`, `with_static_type_info`, and `get` must agree with the `DynamicBundle`
+/// implementation on the exact set of component types.
 pub unsafe trait Bundle: DynamicBundle {
     #[doc(hidden)]
     fn with_static_ids<T>(f: impl FnOnce(&[TypeId]) -> T) -> T;
@@ -38,6 +51,12 @@ pub unsafe trait Bundle: DynamicBundle {
     #[doc(hidden)]
     fn static_type_info() -> Vec<TypeInfo>;
 
+    /// Invoke `f` with the output of `static_type_info`, ideally without allocating
+    #[doc(hidden)]
+    fn with_static_type_info<T>(f: impl FnOnce(&[TypeInfo]) -> T) -> T {
+        f(&Self::static_type_info())
+    }
+
     /// Construct `Self` by moving components out of pointers fetched by `f`
     ///
     /// # Safety
@@ -73,7 +92,7 @@ impl std::error::Error for MissingComponent {}
 macro_rules! tuple_impl {
     ($($name: ident),*) => {
         unsafe impl<$($name: Component),*> DynamicBundle for ($($name,)*) {
-            fn with_ids<T>(&self, f: impl FnOnce(&[TypeId]) -> T) -> T {
+            fn with_ids<Ret>(&self, f: impl FnOnce(&[TypeId]) -> Ret) -> Ret {
                 Self::with_static_ids(f)
             }
 
@@ -96,7 +115,7 @@ macro_rules! tuple_impl {
         }
 
         unsafe impl<$($name: Component),*> Bundle for ($($name,)*) {
-            fn with_static_ids<T>(f: impl FnOnce(&[TypeId]) -> T) -> T {
+            fn with_static_ids<Ret>(f: impl FnOnce(&[TypeId]) -> Ret) -> Ret {
                 const N: usize = count!($($name),*);
                 let mut xs: [(usize, TypeId); N] = [$((mem::align_of::<$name>(), TypeId::of::<$name>())),*];
                 xs.sort_unstable_by(|x, y| x.0.cmp(&y.0).reverse().then(x.1.cmp(&y.1)));
@@ -113,6 +132,13 @@ macro_rules! tuple_impl {
                 xs
             }
 
+            fn with_static_type_info<Ret>(f: impl FnOnce(&[TypeInfo]) -> Ret) -> Ret {
+                const N: usize = count!($($name),*);
+                let mut xs: [TypeInfo; N] = [$(TypeInfo::of::<$name>()),*];
+                xs.sort_unstable();
+                f(&xs)
+            }
+
             #[allow(unused_variables, unused_mut)]
             unsafe fn get(mut f: impl FnMut(TypeInfo) -> Option<NonNull<u8>>) -> Result<Self, MissingComponent> {
                 #[allow(non_snake_case)]
@@ -132,4 +158,6 @@ macro_rules! count {
     ($x: ident $(, $rest: ident)*) => { 1 + count!($($rest),*) };
 }
 
-smaller_tuples_too!(tuple_impl, O, N, M, L, K, J, I, H, G, F, E, D, C, B, A);
+smaller_tuples_too!(
+    tuple_impl, X, W, V, U, T, S, R, Q, P, O, N, M, L, K, J, I, H, G, F, E, D, C, B, A
+);
@@ -0,0 +1,157 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use crate::alloc::boxed::Box;
+use core::any::{Any, TypeId};
+use core::hash::{Hash, Hasher};
+use core::marker::PhantomData;
+use core::ptr::NonNull;
+
+use crate::archetype::Archetype;
+use crate::query::Fetch;
+use crate::{Access, Component, Query};
+
+/// A value stored once per archetype, shared by all of its entities
+///
+/// Entities are grouped into archetypes by the values of their chunk components as well as by
+/// the types of their ordinary components. Attach chunk components with
+/// [`World::spawn_chunked`](crate::World::spawn_chunked) or
+/// [`World::set_chunk`](crate::World::set_chunk), and read them in queries with [`Chunk`].
+/// Implemented for every suitable type.
+pub trait ChunkComponent: Component + Clone + Eq + Hash {}
+
+impl<T: Component + Clone + Eq + Hash> ChunkComponent for T {}
+
+/// Object-safe interface to a `ChunkComponent`
+trait ErasedChunk: Send + Sync {
+    fn as_any(&self) -> &dyn Any;
+    fn eq_dyn(&self, other: &dyn ErasedChunk) -> bool;
+    fn hash_dyn(&self, state: &mut dyn Hasher);
+    fn clone_dyn(&self) -> Box<dyn ErasedChunk>;
+}
+
+impl<T: ChunkComponent> ErasedChunk for T {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn eq_dyn(&self, other: &dyn ErasedChunk) -> bool {
+        other.as_any().downcast_ref::<T>() == Some(self)
+    }
+
+    fn hash_dyn(&self, mut state: &mut dyn Hasher) {
+        self.hash(&mut state);
+    }
+
+    fn clone_dyn(&self) -> Box<dyn ErasedChunk> {
+        Box::new(self.clone())
+    }
+}
+
+/// A type-erased chunk component, as stored by an archetype
+///
+/// An archetype's chunk components are sorted by type, so that equal sets compare equal.
+pub(crate) struct ChunkValue {
+    ty: TypeId,
+    value: Box<dyn ErasedChunk>,
+}
+
+impl ChunkValue {
+    pub fn new<T: ChunkComponent>(value: T) -> Self {
+        Self {
+            ty: TypeId::of::<T>(),
+            value: Box::new(value),
+        }
+    }
+
+    pub fn ty(&self) -> TypeId {
+        self.ty
+    }
+
+    pub fn downcast_ref<T: ChunkComponent>(&self) -> Option<&T> {
+        self.value.as_any().downcast_ref()
+    }
+}
+
+impl Clone for ChunkValue {
+    fn clone(&self) -> Self {
+        Self {
+            ty: self.ty,
+            value: self.value.clone_dyn(),
+        }
+    }
+}
+
+impl PartialEq for ChunkValue {
+    fn eq(&self, other: &Self) -> bool {
+        self.ty == other.ty && self.value.eq_dyn(&*other.value)
+    }
+}
+
+impl Eq for ChunkValue {}
+
+impl Hash for ChunkValue {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.ty.hash(state);
+        self.value.hash_dyn(state);
+    }
+}
+
+/// Query fetching the chunk component of type `T` shared by each entity
+///
+/// Yields `&T`. Chunk components can't be modified in place, so no borrow is acquired.
+///
+/// # Example
+/// ```
+/// # use hecs::*;
+/// #[derive(Clone, PartialEq, Eq, Hash)]
+/// struct Material(u32);
+/// let mut world = World::new();
+/// world.spawn_chunked(Material(1), (10,));
+/// world.spawn_chunked(Material(2), (20,));
+/// world.spawn_chunked(Material(1), (30,));
+/// world.spawn((40,));
+/// let mut drawn = world
+///     .query::<(Chunk<Material>, &i32)>()
+///     .iter()
+///     .map(|(_, (material, &x))| (material.0, x))
+///     .collect::<Vec<_>>();
+/// drawn.sort();
+/// assert_eq!(drawn, [(1, 10), (1, 30), (2, 20)]);
+/// ```
+pub struct Chunk<T>(PhantomData<fn(T)>);
+
+impl<T: ChunkComponent> Query for Chunk<T> {
+    type Fetch = FetchChunk<T>;
+}
+
+#[doc(hidden)]
+pub struct FetchChunk<T>(NonNull<T>);
+
+unsafe impl<'a, T: ChunkComponent> Fetch<'a> for FetchChunk<T> {
+    type Item = &'a T;
+
+    fn dangling() -> Self {
+        Self(NonNull::dangling())
+    }
+
+    fn access(archetype: &Archetype) -> Option<Access> {
+        archetype.chunk::<T>().map(|_| Access::Iterate)
+    }
+
+    fn borrow(_: &Archetype) {}
+    fn new(archetype: &'a Archetype) -> Option<Self> {
+        archetype.chunk::<T>().map(|x| Self(NonNull::from(x)))
+    }
+    fn release(_: &Archetype) {}
+
+    fn for_each_borrow(_: impl FnMut(TypeId, bool)) {}
+
+    unsafe fn get(&self, _: usize) -> Self::Item {
+        &*self.0.as_ptr()
+    }
+}
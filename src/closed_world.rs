@@ -0,0 +1,304 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use crate::{Component, DynamicBundle};
+
+/// A component type that may be stored in the world wrapper `W`, as declared with [`ecs_world!`]
+pub trait ClosedComponent<W>: Component {}
+
+/// A bundle whose every component may be stored in the world wrapper `W`, as declared with
+/// [`ecs_world!`]
+pub trait ClosedBundle<W>: DynamicBundle {}
+
+macro_rules! tuple_impl {
+    ($($name: ident),*) => {
+        impl<World, $($name: ClosedComponent<World>),*> ClosedBundle<World> for ($($name,)*) {}
+    };
+}
+
+smaller_tuples_too!(
+    tuple_impl, X, W, V, U, T, S, R, Q, P, O, N, M, L, K, J, I, H, G, F, E, D, C, B, A
+);
+
+/// Define a wrapper around [`World`](crate::World) that may only hold components of a fixed set
+/// of types
+///
+/// Spawning or inserting components of any other type is a compile-time error. Since every
+/// component is known, cloning and snapshots need no registration: the wrapper implements
+/// `Clone`, and offers `snapshot` and `diff` built on [`WorldSnapshot`](crate::WorldSnapshot) and
+/// [`WorldDiff`](crate::WorldDiff). Every type must therefore implement `Clone` and `PartialEq`.
+/// The wrapper dereferences to `World` for queries and other read-only access.
+///
+/// Preceding the definition with `#[serde]` also generates `serialize` and `deserialize`
+/// functions using the [row format](crate::serialize::row), keying each component by its type's
+/// name. This requires the `serde` feature, and every type to implement `Serialize` and
+/// `Deserialize`.
+///
+/// # Example
+/// ```
+/// # use hecs::*;
+/// #[derive(Clone, PartialEq, Debug)]
+/// struct Position(f32);
+/// #[derive(Clone, PartialEq, Debug)]
+/// struct Health(u32);
+///
+/// ecs_world! {
+///     /// Every entity in the game
+///     pub struct GameWorld { Position, Health }
+/// }
+///
+/// let mut world = GameWorld::new();
+/// let a = world.spawn((Position(1.0), Health(10)));
+/// let old = world.snapshot();
+/// world.get_mut::<Health>(a).unwrap().0 -= 1;
+/// assert_eq!(world.diff(&old).changed().len(), 1);
+///
+/// let copy = world.clone();
+/// assert_eq!(copy.get::<Health>(a).unwrap().0, 9);
+/// ```
+///
+/// Components of other types are rejected:
+/// ```compile_fail
+/// # use hecs::*;
+/// ecs_world! {
+///     struct Numbers { i32 }
+/// }
+/// Numbers::new().spawn((1, true));
+/// ```
+#[macro_export]
+macro_rules! ecs_world {
+    (#[serde] $(#[$meta:meta])* $vis:vis struct $name:ident { $($ty:ty),* $(,)? }) => {
+        $crate::ecs_world! { $(#[$meta])* $vis struct $name { $($ty),* } }
+        $crate::__ecs_world_serde! { $vis $name { $($ty),* } }
+    };
+    ($(#[$meta:meta])* $vis:vis struct $name:ident { $($ty:ty),* $(,)? }) => {
+        $(#[$meta])*
+        $vis struct $name($crate::World);
+
+        $(impl $crate::ClosedComponent<$name> for $ty {})*
+
+        impl $name {
+            /// Create an empty world
+            $vis fn new() -> Self {
+                Self($crate::World::new())
+            }
+
+            /// Create an entity with certain components
+            ///
+            /// See `World::spawn`.
+            $vis fn spawn(
+                &mut self,
+                components: impl $crate::ClosedBundle<Self>,
+            ) -> $crate::Entity {
+                self.0.spawn(components)
+            }
+
+            /// Create an entity with certain components and a specific `Entity` handle
+            ///
+            /// See `World::spawn_at`.
+            $vis fn spawn_at(
+                &mut self,
+                handle: $crate::Entity,
+                components: impl $crate::ClosedBundle<Self>,
+            ) {
+                self.0.spawn_at(handle, components)
+            }
+
+            /// Add `components` to `entity`
+            ///
+            /// See `World::insert`.
+            $vis fn insert(
+                &mut self,
+                entity: $crate::Entity,
+                components: impl $crate::ClosedBundle<Self>,
+            ) -> ::core::result::Result<(), $crate::NoSuchEntity> {
+                self.0.insert(entity, components)
+            }
+
+            /// Add `component` to `entity`
+            ///
+            /// See `World::insert_one`.
+            $vis fn insert_one(
+                &mut self,
+                entity: $crate::Entity,
+                component: impl $crate::ClosedComponent<Self>,
+            ) -> ::core::result::Result<(), $crate::NoSuchEntity> {
+                self.0.insert_one(entity, component)
+            }
+
+            /// Remove components from `entity`
+            ///
+            /// See `World::remove`.
+            $vis fn remove<T: $crate::Bundle + 'static>(
+                &mut self,
+                entity: $crate::Entity,
+            ) -> ::core::result::Result<T, $crate::ComponentError> {
+                self.0.remove::<T>(entity)
+            }
+
+            /// Remove the `T` component from `entity`
+            ///
+            /// See `World::remove_one`.
+            $vis fn remove_one<T: $crate::Component>(
+                &mut self,
+                entity: $crate::Entity,
+            ) -> ::core::result::Result<T, $crate::ComponentError> {
+                self.0.remove_one::<T>(entity)
+            }
+
+            /// Destroy an entity and all its components
+            ///
+            /// See `World::despawn`.
+            $vis fn despawn(
+                &mut self,
+                entity: $crate::Entity,
+            ) -> ::core::result::Result<(), $crate::NoSuchEntity> {
+                self.0.despawn(entity)
+            }
+
+            /// Despawn all entities
+            $vis fn clear(&mut self) {
+                self.0.clear()
+            }
+
+            /// Query a uniquely borrowed world
+            ///
+            /// See `World::query_mut`.
+            $vis fn query_mut<Q: $crate::Query>(&mut self) -> $crate::QueryMut<'_, Q> {
+                self.0.query_mut::<Q>()
+            }
+
+            /// A registry of every component type, for use with `World::snapshot`
+            $vis fn snapshot_registry() -> $crate::SnapshotRegistry {
+                let mut registry = $crate::SnapshotRegistry::new();
+                $(registry.register_eq::<$ty>();)*
+                registry
+            }
+
+            /// Capture every entity and component, as by `World::snapshot`
+            $vis fn snapshot(&mut self) -> $crate::WorldSnapshot {
+                self.0.snapshot(&Self::snapshot_registry())
+            }
+
+            /// The changes made since `old` was captured by `snapshot`
+            $vis fn diff(&mut self, old: &$crate::WorldSnapshot) -> $crate::WorldDiff {
+                $crate::WorldDiff::new(old, &self.snapshot())
+            }
+
+            /// Unwrap the underlying `World`
+            $vis fn into_inner(self) -> $crate::World {
+                self.0
+            }
+        }
+
+        impl ::core::default::Default for $name {
+            fn default() -> Self {
+                Self::new()
+            }
+        }
+
+        impl ::core::ops::Deref for $name {
+            type Target = $crate::World;
+
+            fn deref(&self) -> &$crate::World {
+                &self.0
+            }
+        }
+
+        /// Copies every entity, preserving its `Entity` handle
+        impl ::core::clone::Clone for $name {
+            fn clone(&self) -> Self {
+                let mut world = $crate::World::new();
+                let mut builder = $crate::EntityBuilder::new();
+                for (entity, components) in self.0.iter() {
+                    $(
+                        if let Some(x) = components.get::<$ty>() {
+                            builder.add(<$ty as ::core::clone::Clone>::clone(&*x));
+                        }
+                    )*
+                    world.spawn_at(entity, builder.build());
+                }
+                Self(world)
+            }
+        }
+    };
+}
+
+#[cfg(feature = "serde")]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __ecs_world_serde {
+    ($vis:vis $name:ident { $($ty:ty),* }) => {
+        impl $name {
+            /// Serialize every entity with the row format, keying components by type name
+            $vis fn serialize<S: $crate::__serde::Serializer>(
+                &self,
+                serializer: S,
+            ) -> ::core::result::Result<S::Ok, S::Error> {
+                struct Context;
+
+                impl $crate::serialize::row::SerializeContext for Context {
+                    fn serialize_entity<S: $crate::__serde::ser::SerializeMap>(
+                        &mut self,
+                        entity: $crate::EntityRef<'_>,
+                        map: &mut S,
+                    ) -> ::core::result::Result<(), S::Error> {
+                        $(
+                            $crate::serialize::row::try_serialize::<$ty, _, _>(
+                                &entity,
+                                ::core::stringify!($ty),
+                                map,
+                            )?;
+                        )*
+                        Ok(())
+                    }
+                }
+
+                $crate::serialize::row::serialize(&self.0, &mut Context, serializer)
+            }
+
+            /// Deserialize entities written by `serialize`
+            $vis fn deserialize<'de, D: $crate::__serde::Deserializer<'de>>(
+                deserializer: D,
+            ) -> ::core::result::Result<Self, D::Error> {
+                struct Context;
+
+                impl $crate::serialize::row::DeserializeContext for Context {
+                    #[allow(unused_assignments)]
+                    fn deserialize_entity<'de, M: $crate::__serde::de::MapAccess<'de>>(
+                        &mut self,
+                        mut map: M,
+                        entity: &mut $crate::EntityBuilder,
+                    ) -> ::core::result::Result<(), M::Error> {
+                        const NAMES: &[&str] = &[$(::core::stringify!($ty)),*];
+                        while let Some(key) = $crate::serialize::row::next_named_key(&mut map, NAMES)? {
+                            let mut i = 0;
+                            $(
+                                if key == i {
+                                    entity.add::<$ty>(map.next_value()?);
+                                }
+                                i += 1;
+                            )*
+                        }
+                        Ok(())
+                    }
+                }
+
+                $crate::serialize::row::deserialize(&mut Context, deserializer).map(Self)
+            }
+        }
+    };
+}
+
+#[cfg(not(feature = "serde"))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __ecs_world_serde {
+    ($($tt:tt)*) => {
+        ::core::compile_error!("`#[serde]` in `ecs_world!` requires hecs's `serde` feature");
+    };
+}
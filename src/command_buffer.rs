@@ -0,0 +1,284 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use crate::alloc::alloc::{alloc, dealloc, Layout};
+use crate::alloc::vec::Vec;
+use core::any::TypeId;
+use core::mem;
+use core::ops::Range;
+use core::ptr::{self, NonNull};
+
+use crate::archetype::TypeInfo;
+use crate::{align, Bundle, DynamicBundle, Entity, World};
+
+/// Records operations for future application to a `World`
+///
+/// Useful when operations cannot be applied directly due to ordering concerns or borrow checking,
+/// such as spawning or despawning entities while iterating a query. Components are moved into the
+/// buffer when an operation is recorded and moved into the `World` when the buffer is run.
+///
+/// Prefer reusing the same buffer over creating new ones repeatedly.
+///
+/// ```
+/// # use hecs::*;
+/// let mut world = World::new();
+/// let a = world.spawn((123, true));
+/// let b = world.spawn((456, false));
+/// let mut cmd = CommandBuffer::new();
+/// for (id, &flag) in &mut world.query::<&bool>() {
+///     if flag {
+///         cmd.despawn(id);
+///     } else {
+///         cmd.insert(id, ("abc",));
+///     }
+/// }
+/// cmd.run_on(&mut world); // cmd can now be reused
+/// assert!(!world.contains(a));
+/// assert_eq!(*world.get::<&str>(b).unwrap(), "abc");
+/// ```
+pub struct CommandBuffer {
+    cmds: Vec<Cmd>,
+    storage: NonNull<u8>,
+    layout: Layout,
+    cursor: usize,
+    components: Vec<ComponentInfo>,
+    ids: Vec<TypeId>,
+}
+
+impl CommandBuffer {
+    /// Create an empty command buffer
+    pub fn new() -> Self {
+        Self {
+            cmds: Vec::new(),
+            storage: NonNull::dangling(),
+            layout: Layout::from_size_align(0, 8).unwrap(),
+            cursor: 0,
+            components: Vec::new(),
+            ids: Vec::new(),
+        }
+    }
+
+    /// Add components from `bundle` to `entity`, if it exists
+    ///
+    /// Pairs well with `World::reserve_entity` to spawn entities with a known handle.
+    ///
+    /// When applied, behaves like `World::insert`. If `entity` was despawned by the time the buffer
+    /// is run, the components are dropped.
+    pub fn insert(&mut self, entity: Entity, bundle: impl DynamicBundle) {
+        let components = self.record(bundle);
+        self.cmds.push(Cmd::SpawnOrInsert {
+            entity: Some(entity),
+            components,
+        });
+    }
+
+    /// Spawn a new entity with components from `bundle`
+    ///
+    /// When applied, behaves like `World::spawn`. Use `World::reserve_entity` together with
+    /// `insert` instead if the new entity's handle is needed before the buffer is run.
+    pub fn spawn(&mut self, bundle: impl DynamicBundle) {
+        let components = self.record(bundle);
+        self.cmds.push(Cmd::SpawnOrInsert {
+            entity: None,
+            components,
+        });
+    }
+
+    /// Remove components from `entity` if they exist
+    ///
+    /// When applied, behaves like `World::remove`, discarding the removed components. Nothing
+    /// happens if `entity` does not exist or lacks any component in `T`.
+    pub fn remove<T: Bundle + 'static>(&mut self, entity: Entity) {
+        fn remove_and_drop<T: Bundle + 'static>(world: &mut World, entity: Entity) {
+            let _ = world.remove::<T>(entity);
+        }
+        self.cmds.push(Cmd::Remove {
+            entity,
+            remove: remove_and_drop::<T>,
+        });
+    }
+
+    /// Despawn `entity` if it exists
+    ///
+    /// When applied, behaves like `World::despawn`.
+    pub fn despawn(&mut self, entity: Entity) {
+        self.cmds.push(Cmd::Despawn(entity));
+    }
+
+    /// Apply all recorded operations to `world`, in the order they were recorded
+    ///
+    /// The buffer is left empty, ready for reuse.
+    pub fn run_on(&mut self, world: &mut World) {
+        // Detach the component metadata so that, should an operation panic, `Drop` cannot reach
+        // components that have already been moved into the world.
+        let mut components = mem::take(&mut self.components);
+        let mut ids = mem::take(&mut self.ids);
+        for cmd in self.cmds.drain(..) {
+            match cmd {
+                Cmd::SpawnOrInsert {
+                    entity,
+                    components: range,
+                } => {
+                    ids.clear();
+                    ids.extend(components[range.clone()].iter().map(|x| x.ty.id()));
+                    let bundle = RecordedEntity {
+                        storage: self.storage,
+                        components: &components[range],
+                        ids: &ids,
+                    };
+                    match entity {
+                        Some(entity) => {
+                            // If `entity` no longer exists, `bundle` quietly drops the components.
+                            let _ = world.insert(entity, bundle);
+                        }
+                        None => {
+                            world.spawn(bundle);
+                        }
+                    }
+                }
+                Cmd::Remove { entity, remove } => remove(world, entity),
+                Cmd::Despawn(entity) => {
+                    let _ = world.despawn(entity);
+                }
+            }
+        }
+        components.clear();
+        ids.clear();
+        self.components = components;
+        self.ids = ids;
+        self.cursor = 0;
+    }
+
+    /// Drop all recorded operations without applying them
+    pub fn clear(&mut self) {
+        self.cmds.clear();
+        self.ids.clear();
+        self.cursor = 0;
+        unsafe {
+            for info in self.components.drain(..) {
+                info.ty.drop(self.storage.as_ptr().add(info.offset));
+            }
+        }
+    }
+
+    /// Move the components of `bundle` into storage, returning their range in `self.components`
+    fn record(&mut self, bundle: impl DynamicBundle) -> Range<usize> {
+        let first = self.components.len();
+        unsafe {
+            bundle.put(|ptr, ty| self.add_inner(ptr, ty));
+        }
+        self.components[first..].sort_unstable_by_key(|x| x.ty);
+        first..self.components.len()
+    }
+
+    unsafe fn add_inner(&mut self, ptr: *mut u8, ty: TypeInfo) {
+        let offset = align(self.cursor, ty.layout().align());
+        let end = offset + ty.layout().size();
+        if end > self.layout.size() || ty.layout().align() > self.layout.align() {
+            let new_align = self.layout.align().max(ty.layout().align());
+            let (new_storage, new_layout) = Self::grow(end, self.cursor, new_align, self.storage);
+            if self.layout.size() != 0 {
+                dealloc(self.storage.as_ptr(), self.layout);
+            }
+            self.storage = new_storage;
+            self.layout = new_layout;
+        }
+
+        let addr = self.storage.as_ptr().add(offset);
+        ptr::copy_nonoverlapping(ptr, addr, ty.layout().size());
+        self.components.push(ComponentInfo { ty, offset });
+        self.cursor = end;
+    }
+
+    unsafe fn grow(
+        min_size: usize,
+        cursor: usize,
+        align: usize,
+        storage: NonNull<u8>,
+    ) -> (NonNull<u8>, Layout) {
+        let layout = Layout::from_size_align(min_size.next_power_of_two().max(64), align).unwrap();
+        let new_storage = NonNull::new_unchecked(alloc(layout));
+        ptr::copy_nonoverlapping(storage.as_ptr(), new_storage.as_ptr(), cursor);
+        (new_storage, layout)
+    }
+}
+
+unsafe impl Send for CommandBuffer {}
+unsafe impl Sync for CommandBuffer {}
+
+impl Drop for CommandBuffer {
+    fn drop(&mut self) {
+        // Ensure buffered components aren't leaked
+        self.clear();
+        if self.layout.size() != 0 {
+            unsafe {
+                dealloc(self.storage.as_ptr(), self.layout);
+            }
+        }
+    }
+}
+
+impl Default for CommandBuffer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The components of a single recorded spawn or insert, in storage owned by a `CommandBuffer`
+struct RecordedEntity<'a> {
+    storage: NonNull<u8>,
+    components: &'a [ComponentInfo],
+    ids: &'a [TypeId],
+}
+
+unsafe impl DynamicBundle for RecordedEntity<'_> {
+    fn with_ids<T>(&self, f: impl FnOnce(&[TypeId]) -> T) -> T {
+        f(self.ids)
+    }
+
+    fn type_info(&self) -> Vec<TypeInfo> {
+        self.components.iter().map(|x| x.ty).collect()
+    }
+
+    unsafe fn put(mut self, mut f: impl FnMut(*mut u8, TypeInfo)) {
+        // Ensure `Drop` won't free the components once they've been moved out
+        let components = mem::take(&mut self.components);
+        for info in components {
+            f(self.storage.as_ptr().add(info.offset), info.ty);
+        }
+    }
+}
+
+impl Drop for RecordedEntity<'_> {
+    fn drop(&mut self) {
+        // Ensures components aren't leaked if `put` was never called
+        for info in self.components {
+            unsafe {
+                info.ty.drop(self.storage.as_ptr().add(info.offset));
+            }
+        }
+    }
+}
+
+/// A type-erased component stored in a `CommandBuffer`
+struct ComponentInfo {
+    ty: TypeInfo,
+    // Position in `storage`
+    offset: usize,
+}
+
+enum Cmd {
+    SpawnOrInsert {
+        entity: Option<Entity>,
+        components: Range<usize>,
+    },
+    Remove {
+        entity: Entity,
+        remove: fn(&mut World, Entity),
+    },
+    Despawn(Entity),
+}
@@ -0,0 +1,134 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use crate::alloc::vec::Vec;
+use core::any::Any;
+use core::hash::Hash;
+
+use hashbrown::HashMap;
+
+use crate::{Changed, Component, Disabled, Entity, Including, World};
+
+/// The entities holding each value of their `T` component, maintained by a `World`
+///
+/// Obtained from [`World::component_index`] once registered with [`World::index_component`].
+///
+/// # Example
+/// ```
+/// # use hecs::*;
+/// #[derive(Clone, Hash, Eq, PartialEq)]
+/// struct Team(u32);
+/// let mut world = World::new();
+/// world.index_component::<Team>();
+/// let a = world.spawn((Team(1),));
+/// let b = world.spawn((Team(2),));
+/// world.get_mut::<Team>(b).unwrap().0 = 1;
+/// let index = world.component_index::<Team>().unwrap();
+/// assert_eq!(index.get(&Team(1)).len(), 2);
+/// assert!(index.get(&Team(2)).is_empty());
+/// ```
+pub struct ComponentIndex<T> {
+    entities: HashMap<T, Vec<Entity>>,
+    /// Value under which each entity is currently indexed
+    values: HashMap<Entity, T>,
+}
+
+impl<T: Hash + Eq + Clone> ComponentIndex<T> {
+    pub(crate) fn new() -> Self {
+        Self {
+            entities: HashMap::default(),
+            values: HashMap::default(),
+        }
+    }
+
+    /// The entities whose `T` component equals `value`, in no particular order
+    pub fn get(&self, value: &T) -> &[Entity] {
+        self.entities.get(value).map_or(&[], |x| x)
+    }
+
+    /// Every indexed value, with the entities holding it
+    pub fn iter(&self) -> impl Iterator<Item = (&T, &[Entity])> + '_ {
+        self.entities.iter().map(|(k, v)| (k, &v[..]))
+    }
+
+    /// Number of distinct values held
+    pub fn len(&self) -> usize {
+        self.entities.len()
+    }
+
+    /// Whether no entity holds a `T`
+    pub fn is_empty(&self) -> bool {
+        self.entities.is_empty()
+    }
+
+    /// Index every existing `T` in `world`
+    pub(crate) fn populate(&mut self, world: &World)
+    where
+        T: Component,
+    {
+        for (entity, value) in world.query::<Including<Disabled, &T>>().iter() {
+            self.insert(entity, value);
+        }
+    }
+
+    fn insert(&mut self, entity: Entity, value: &T) {
+        if let Some(old) = self.values.get(&entity) {
+            if old == value {
+                return;
+            }
+            self.remove(entity);
+        }
+        self.values.insert(entity, value.clone());
+        self.entities.entry(value.clone()).or_default().push(entity);
+    }
+
+    fn remove(&mut self, entity: Entity) {
+        let value = match self.values.remove(&entity) {
+            Some(x) => x,
+            None => return,
+        };
+        let entities = self.entities.get_mut(&value).unwrap();
+        let i = entities.iter().position(|&x| x == entity).unwrap();
+        entities.swap_remove(i);
+        if entities.is_empty() {
+            self.entities.remove(&value);
+        }
+    }
+}
+
+/// A `ComponentIndex` of any type
+pub(crate) trait AnyIndex: Send + Sync {
+    /// Account for components added, mutably accessed, or removed since the last call to
+    /// `World::clear_trackers`
+    fn refresh(&mut self, world: &World);
+
+    fn clear(&mut self);
+
+    fn as_any(&self) -> &dyn Any;
+}
+
+impl<T: Component + Hash + Eq + Clone> AnyIndex for ComponentIndex<T> {
+    fn refresh(&mut self, world: &World) {
+        for &entity in world.removed::<T>() {
+            if !world.satisfies::<&T>(entity).unwrap_or(false) {
+                self.remove(entity);
+            }
+        }
+        for (entity, value) in world.query::<Including<Disabled, Changed<T, &T>>>().iter() {
+            self.insert(entity, value);
+        }
+    }
+
+    fn clear(&mut self) {
+        self.entities.clear();
+        self.values.clear();
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
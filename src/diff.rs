@@ -0,0 +1,179 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use crate::alloc::vec::Vec;
+use core::any::TypeId;
+
+use hashbrown::HashMap;
+
+use crate::snapshot::ArchetypeSnapshot;
+use crate::{BuiltEntityClone, Entity, EntityBuilderClone, NoSuchEntity, World, WorldSnapshot};
+
+/// The changes that turn one [`WorldSnapshot`] into another
+///
+/// Typically computed by a server between the last state acknowledged by a client and the current
+/// state, then sent to that client and applied with [`World::apply_diff`]. Components whose types
+/// were registered with [`SnapshotRegistry::register_eq`](crate::SnapshotRegistry::register_eq)
+/// are included only if they changed; all others are included whenever present.
+///
+/// # Example
+/// ```
+/// # use hecs::*;
+/// let mut registry = SnapshotRegistry::new();
+/// registry.register_eq::<i32>().register_eq::<bool>();
+/// let mut server = World::new();
+/// let a = server.spawn((1, true));
+/// let b = server.spawn((2,));
+/// let mut client = World::new();
+/// let empty = client.snapshot(&registry);
+/// client.apply_diff(&WorldDiff::new(&empty, &server.snapshot(&registry))).unwrap();
+///
+/// let old = server.snapshot(&registry);
+/// *server.get_mut::<i32>(a).unwrap() = 10;
+/// server.despawn(b).unwrap();
+/// let diff = WorldDiff::new(&old, &server.snapshot(&registry));
+/// assert_eq!(diff.despawned(), [b]);
+/// client.apply_diff(&diff).unwrap();
+/// assert_eq!(*client.get::<i32>(a).unwrap(), 10);
+/// assert!(!client.contains(b));
+/// ```
+pub struct WorldDiff {
+    despawned: Vec<Entity>,
+    removed: Vec<(Entity, TypeId)>,
+    spawned: Vec<(Entity, BuiltEntityClone)>,
+    changed: Vec<(Entity, BuiltEntityClone)>,
+}
+
+impl WorldDiff {
+    /// Compute the changes from `old` to `new`
+    pub fn new(old: &WorldSnapshot, new: &WorldSnapshot) -> Self {
+        // Where each entity of `old` is stored, and whether it's been seen in `new`
+        let mut old_rows = HashMap::<Entity, (&ArchetypeSnapshot, usize, bool)>::new();
+        for archetype in &old.archetypes {
+            for (row, &id) in archetype.entities.iter().enumerate() {
+                let entity = old.entity(id);
+                old_rows.insert(entity, (archetype, row, false));
+            }
+        }
+
+        let mut diff = Self {
+            despawned: Vec::new(),
+            removed: Vec::new(),
+            spawned: Vec::new(),
+            changed: Vec::new(),
+        };
+        for archetype in &new.archetypes {
+            for (row, &id) in archetype.entities.iter().enumerate() {
+                let entity = new.entity(id);
+                let mut builder = EntityBuilderClone::new();
+                let (old_archetype, old_row) = match old_rows.get_mut(&entity) {
+                    Some((old_archetype, old_row, seen)) => {
+                        *seen = true;
+                        (*old_archetype, *old_row)
+                    }
+                    None => {
+                        for column in &archetype.columns {
+                            unsafe {
+                                builder.add_cloned_raw(column.get(row), column.ty, column.clone);
+                            }
+                        }
+                        diff.spawned.push((entity, builder.build()));
+                        continue;
+                    }
+                };
+                for column in &archetype.columns {
+                    let unchanged = old_archetype
+                        .columns
+                        .iter()
+                        .find(|x| x.ty.id() == column.ty.id())
+                        .and_then(|old_column| {
+                            let eq = column.eq?;
+                            Some(unsafe { eq(old_column.get(old_row), column.get(row)) })
+                        })
+                        .unwrap_or(false);
+                    if !unchanged {
+                        unsafe {
+                            builder.add_cloned_raw(column.get(row), column.ty, column.clone);
+                        }
+                    }
+                }
+                for &ty in &old_archetype.ids {
+                    if !archetype.ids.contains(&ty) {
+                        diff.removed.push((entity, ty));
+                    }
+                }
+                let built = builder.build();
+                if !built.is_empty() {
+                    diff.changed.push((entity, built));
+                }
+            }
+        }
+
+        // Preserve the order of `old` for determinism
+        for archetype in &old.archetypes {
+            for &id in &archetype.entities {
+                let entity = old.entity(id);
+                if !old_rows[&entity].2 {
+                    diff.despawned.push(entity);
+                }
+            }
+        }
+        diff
+    }
+
+    /// Entities that were despawned
+    pub fn despawned(&self) -> &[Entity] {
+        &self.despawned
+    }
+
+    /// Components removed from entities that still exist
+    pub fn removed(&self) -> &[(Entity, TypeId)] {
+        &self.removed
+    }
+
+    /// Entities that were spawned, with all their components
+    pub fn spawned(&self) -> impl ExactSizeIterator<Item = (Entity, &BuiltEntityClone)> + '_ {
+        self.spawned.iter().map(|(e, x)| (*e, x))
+    }
+
+    /// Components added to or changed on entities that already existed
+    pub fn changed(&self) -> impl ExactSizeIterator<Item = (Entity, &BuiltEntityClone)> + '_ {
+        self.changed.iter().map(|(e, x)| (*e, x))
+    }
+
+    /// Whether the two states were identical
+    pub fn is_empty(&self) -> bool {
+        self.despawned.is_empty()
+            && self.removed.is_empty()
+            && self.spawned.is_empty()
+            && self.changed.is_empty()
+    }
+}
+
+impl World {
+    /// Update the world with the changes recorded in `diff`
+    ///
+    /// Spawned entities receive the same handles they had in the world `diff` was computed from.
+    /// If the world was previously in the diff's old state, it is left in the new state. Returns
+    /// an error, having applied only some of the changes, if an entity that should exist does
+    /// not.
+    pub fn apply_diff(&mut self, diff: &WorldDiff) -> Result<(), NoSuchEntity> {
+        for &entity in &diff.despawned {
+            self.despawn_unlinked(entity)?;
+        }
+        for &(entity, ty) in &diff.removed {
+            self.remove_dynamic(entity, ty)?;
+        }
+        for (entity, components) in &diff.spawned {
+            self.spawn_at(*entity, components.clone());
+        }
+        for (entity, components) in &diff.changed {
+            self.insert(*entity, components.clone())?;
+        }
+        Ok(())
+    }
+}
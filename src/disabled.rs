@@ -0,0 +1,196 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use core::any::TypeId;
+use core::marker::PhantomData;
+
+use crate::archetype::Archetype;
+use crate::query::{Access, Fetch, SliceFetch};
+use crate::sparse::SparseFetch;
+use crate::{ComponentError, Entity, NoSuchEntity, Query, World};
+
+/// Marks an entity that queries pass over, as set by [`World::disable`]
+///
+/// Stored sparsely, so disabling or enabling an entity leaves it in its archetype. Queries visit
+/// disabled entities only when wrapped in [`Including<Disabled, _>`](Including).
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub struct Disabled;
+
+/// Query transformer that also visits entities marked with a `T` component, which queries
+/// otherwise skip
+///
+/// Only [`Disabled`] hides entities in this way.
+///
+/// # Example
+/// ```
+/// # use hecs::*;
+/// let mut world = World::new();
+/// world.spawn((123,));
+/// let b = world.spawn((456,));
+/// world.disable(b).unwrap();
+/// assert_eq!(world.query::<&i32>().iter().count(), 1);
+/// assert_eq!(world.query::<Including<Disabled, &i32>>().iter().count(), 2);
+/// let mut disabled = world.query::<Including<Disabled, With<Disabled, ()>>>();
+/// assert_eq!(disabled.iter().map(|(e, ())| e).collect::<Vec<_>>(), [b]);
+/// ```
+pub struct Including<T, Q = ()>(PhantomData<(Q, fn(T))>);
+
+impl<Q: Query> Query for Including<Disabled, Q> {
+    type Fetch = FetchIncluding<Q::Fetch>;
+}
+
+#[doc(hidden)]
+pub struct FetchIncluding<F>(F);
+
+unsafe impl<'a, F: Fetch<'a>> Fetch<'a> for FetchIncluding<F> {
+    type Item = F::Item;
+
+    fn dangling() -> Self {
+        Self(F::dangling())
+    }
+
+    fn access(archetype: &Archetype) -> Option<Access> {
+        F::access(archetype)
+    }
+
+    fn borrow(archetype: &Archetype) {
+        F::borrow(archetype)
+    }
+    fn new(archetype: &'a Archetype) -> Option<Self> {
+        F::new(archetype).map(Self)
+    }
+    fn release(archetype: &Archetype) {
+        F::release(archetype)
+    }
+
+    fn for_each_borrow(f: impl FnMut(TypeId, bool)) {
+        F::for_each_borrow(f);
+    }
+
+    unsafe fn get(&self, n: usize) -> F::Item {
+        self.0.get(n)
+    }
+
+    unsafe fn should_skip(&self, n: usize) -> bool {
+        self.0.should_skip(n)
+    }
+
+    fn filters_entities(&self) -> bool {
+        self.0.filters_entities()
+    }
+
+    fn includes_disabled() -> bool {
+        true
+    }
+}
+
+unsafe impl<'a, F: SliceFetch<'a>> SliceFetch<'a> for FetchIncluding<F> {
+    type Slice = F::Slice;
+
+    unsafe fn slice(&self, start: usize, len: usize) -> F::Slice {
+        self.0.slice(start, len)
+    }
+}
+
+/// A `Fetch` that also passes over disabled entities, unless `F` includes them
+pub(crate) struct EnabledFetch<F> {
+    fetch: F,
+    /// Set if some entities of the archetype may be disabled
+    disabled: Option<SparseFetch>,
+}
+
+impl<'a, F: Fetch<'a>> EnabledFetch<F> {
+    pub(crate) fn dangling() -> Self {
+        Self {
+            fetch: F::dangling(),
+            disabled: None,
+        }
+    }
+
+    /// Like `Fetch::new`, but also `None` if every entity of `archetype` is disabled
+    pub(crate) fn new(archetype: &'a Archetype) -> Option<Self> {
+        let disabled = if F::includes_disabled() {
+            None
+        } else if archetype.has::<Disabled>() {
+            return None;
+        } else {
+            SparseFetch::new(archetype, TypeId::of::<Disabled>()).filter(|x| !x.set().is_empty())
+        };
+        Some(Self {
+            fetch: F::new(archetype)?,
+            disabled,
+        })
+    }
+
+    /// See `Fetch::get`
+    #[inline]
+    pub(crate) unsafe fn get(&self, n: usize) -> F::Item {
+        self.fetch.get(n)
+    }
+
+    /// See `SliceFetch::slice`
+    #[inline]
+    pub(crate) unsafe fn slice(&self, start: usize, len: usize) -> F::Slice
+    where
+        F: SliceFetch<'a>,
+    {
+        self.fetch.slice(start, len)
+    }
+
+    /// See `Fetch::should_skip`
+    #[inline]
+    pub(crate) unsafe fn should_skip(&self, n: usize) -> bool {
+        self.disabled.as_ref().is_some_and(|x| x.row(n).is_some()) || self.fetch.should_skip(n)
+    }
+
+    /// See `Fetch::filters_entities`
+    #[inline]
+    pub(crate) fn filters_entities(&self) -> bool {
+        self.disabled.is_some() || self.fetch.filters_entities()
+    }
+}
+
+impl World {
+    /// Hide `entity` from queries until it's passed to [`enable`](Self::enable)
+    ///
+    /// Unlike removing its components, disabling an entity leaves it in its archetype, so that
+    /// many entities may be toggled every frame cheaply. While any entity is disabled, queries
+    /// check each entity they visit for a [`Disabled`] marker, as for any sparse component; wrap a
+    /// query in [`Including<Disabled, _>`](Including) to visit disabled entities too. Access by
+    /// handle, e.g. with `get` or `query_one`, and column views are unaffected.
+    ///
+    /// # Example
+    /// ```
+    /// # use hecs::*;
+    /// let mut world = World::new();
+    /// let a = world.spawn((123, true));
+    /// world.disable(a).unwrap();
+    /// assert!(world.query::<&i32>().iter().next().is_none());
+    /// assert_eq!(*world.get::<i32>(a).unwrap(), 123);
+    /// world.enable(a).unwrap();
+    /// assert_eq!(world.query::<&i32>().iter().count(), 1);
+    /// ```
+    pub fn disable(&mut self, entity: Entity) -> Result<(), NoSuchEntity> {
+        self.register_sparse::<Disabled>();
+        self.insert_one(entity, Disabled)
+    }
+
+    /// Reveal an entity hidden by [`disable`](Self::disable) to queries again
+    ///
+    /// Does nothing if `entity` isn't disabled.
+    pub fn enable(&mut self, entity: Entity) -> Result<(), NoSuchEntity> {
+        match self.remove_one::<Disabled>(entity) {
+            Ok(Disabled) | Err(ComponentError::MissingComponent(_)) => Ok(()),
+            Err(ComponentError::NoSuchEntity) => Err(NoSuchEntity),
+        }
+    }
+
+    /// Whether `entity` is hidden from queries by [`disable`](Self::disable)
+    pub fn is_disabled(&self, entity: Entity) -> Result<bool, NoSuchEntity> {
+        self.satisfies::<&Disabled>(entity)
+    }
+}
@@ -0,0 +1,41 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use crate::alloc::vec::Vec;
+
+use crate::{Component, Disabled, Including, With, Without, World};
+
+/// The value an entity's `T` component had at the most recent call to [`World::tick`]
+///
+/// Maintained for component types registered with [`World::register_double_buffered`], so that
+/// systems may read last frame's value while writing this frame's. Entities that gained their `T`
+/// since the last tick have no `Prev<T>` yet.
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct Prev<T>(pub T);
+
+/// Copy every `T` into its entity's `Prev<T>`, adding and removing `Prev<T>`s to match
+pub(crate) fn flip<T: Component + Clone>(world: &mut World) {
+    for (_, (current, prev)) in world.query_mut::<Including<Disabled, (&T, &mut Prev<T>)>>() {
+        prev.0.clone_from(current);
+    }
+    let added = world
+        .query_mut::<Including<Disabled, Without<Prev<T>, &T>>>()
+        .into_iter()
+        .map(|(entity, current)| (entity, Prev(current.clone())))
+        .collect::<Vec<_>>();
+    for (entity, prev) in added {
+        world.insert_one(entity, prev).unwrap();
+    }
+    let removed = world
+        .query_mut::<Including<Disabled, Without<T, With<Prev<T>, ()>>>>()
+        .into_iter()
+        .map(|(entity, ())| entity)
+        .collect::<Vec<_>>();
+    for entity in removed {
+        world.remove_one::<Prev<T>>(entity).unwrap();
+    }
+}
@@ -0,0 +1,292 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use crate::alloc::borrow::Cow;
+use crate::alloc::string::String;
+use crate::alloc::vec::Vec;
+use core::any::TypeId;
+use core::fmt;
+use core::ptr::NonNull;
+use core::sync::atomic::{AtomicU32, Ordering};
+
+#[cfg(feature = "std")]
+use std::error::Error;
+
+use crate::archetype::{Archetype, TypeInfo};
+use crate::entities::EntityMeta;
+use crate::Entity;
+
+/// A query whose component types are chosen at runtime
+///
+/// Useful for tools such as editors, where the set of components of interest isn't known at
+/// compile time. Matching entities are visited with
+/// [`World::query_dynamic`](crate::World::query_dynamic), which yields type-erased pointers to
+/// their components.
+///
+/// # Example
+/// ```
+/// # use hecs::*;
+/// # use core::any::TypeId;
+/// let mut world = World::new();
+/// let a = world.spawn((123, true));
+/// let query = DynamicQuery::new()
+///     .read(TypeId::of::<bool>())
+///     .write(TypeId::of::<i32>());
+/// for (_, mut row) in world.query_dynamic(&query).iter() {
+///     assert_eq!(row.type_info(1).id(), TypeId::of::<i32>());
+///     unsafe { *row.get_mut(1).unwrap().cast::<i32>() += 1; }
+/// }
+/// assert_eq!(*world.get::<i32>(a).unwrap(), 124);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct DynamicQuery {
+    /// Component types and whether they're written
+    components: Vec<(TypeId, bool)>,
+    /// Component types that matching entities must lack
+    excluded: Vec<TypeId>,
+}
+
+impl DynamicQuery {
+    /// A query matching every entity, fetching no components
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Additionally require and immutably borrow components of type `id`
+    pub fn read(mut self, id: TypeId) -> Self {
+        self.components.push((id, false));
+        self
+    }
+
+    /// Additionally require and uniquely borrow components of type `id`
+    pub fn write(mut self, id: TypeId) -> Self {
+        self.components.push((id, true));
+        self
+    }
+
+    /// Additionally require the absence of components of type `id`, without fetching anything
+    pub fn without(mut self, id: TypeId) -> Self {
+        self.excluded.push(id);
+        self
+    }
+
+    /// Parse a comma-separated list of `&Name`, `&mut Name`, and `!Name` terms, looking up each
+    /// name with `lookup`
+    pub(crate) fn parse(
+        query: &str,
+        mut lookup: impl FnMut(&str) -> Option<TypeId>,
+    ) -> Result<Self, QueryParseError> {
+        let mut result = Self::new();
+        if query.trim().is_empty() {
+            return Ok(result);
+        }
+        for term in query.split(',').map(str::trim) {
+            let (name, kind) = if let Some(rest) = term.strip_prefix('!') {
+                (rest.trim_start(), None)
+            } else if let Some(rest) = term.strip_prefix('&') {
+                let rest = rest.trim_start();
+                match rest.strip_prefix("mut") {
+                    Some(name) if name.is_empty() || name.starts_with(char::is_whitespace) => {
+                        (name.trim_start(), Some(true))
+                    }
+                    _ => (rest, Some(false)),
+                }
+            } else {
+                return Err(QueryParseError::Syntax(term.into()));
+            };
+            if name.is_empty() || name.contains(char::is_whitespace) {
+                return Err(QueryParseError::Syntax(term.into()));
+            }
+            let id = lookup(name).ok_or_else(|| QueryParseError::UnknownComponent(name.into()))?;
+            result = match kind {
+                None => result.without(id),
+                Some(false) => result.read(id),
+                Some(true) => result.write(id),
+            };
+        }
+        Ok(result)
+    }
+
+    fn matches(&self, archetype: &Archetype) -> bool {
+        self.components
+            .iter()
+            .all(|&(id, _)| archetype.has_dynamic(id))
+            && !self.excluded.iter().any(|&id| archetype.has_dynamic(id))
+    }
+}
+
+/// Error returned by [`World::query_str`](crate::World::query_str) and
+/// [`World::parse_query`](crate::World::parse_query)
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum QueryParseError {
+    /// A term wasn't of the form `&Name`, `&mut Name`, or `!Name`
+    Syntax(String),
+    /// No component type is registered under a name
+    UnknownComponent(String),
+}
+
+#[cfg(feature = "std")]
+impl Error for QueryParseError {}
+
+impl fmt::Display for QueryParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use QueryParseError::*;
+        match *self {
+            Syntax(ref x) => write!(f, "malformed query term {:?}", x),
+            UnknownComponent(ref x) => write!(f, "no component registered as {:?}", x),
+        }
+    }
+}
+
+/// A borrow of a `World` sufficient to execute a `DynamicQuery`
+///
+/// Dynamic borrows are acquired when this is constructed and released when it's dropped.
+/// Construct with [`World::query_dynamic`](crate::World::query_dynamic).
+pub struct DynamicQueryBorrow<'w> {
+    meta: &'w [EntityMeta],
+    /// Matching archetypes, each followed by its columns in the query's order
+    archetypes: Vec<(&'w Archetype, Vec<Column>)>,
+    query: Cow<'w, DynamicQuery>,
+}
+
+struct Column {
+    ty: TypeInfo,
+    base: NonNull<u8>,
+    /// Change ticks, if the column is written
+    mutated: Option<NonNull<AtomicU32>>,
+    tick: u32,
+}
+
+impl<'w> DynamicQueryBorrow<'w> {
+    pub(crate) fn new(
+        meta: &'w [EntityMeta],
+        archetypes: &'w [Archetype],
+        query: Cow<'w, DynamicQuery>,
+    ) -> Self {
+        let archetypes = archetypes
+            .iter()
+            .filter(|x| query.matches(x))
+            .map(|archetype| {
+                let columns = query
+                    .components
+                    .iter()
+                    .map(|&(id, write)| {
+                        let borrowed = if write {
+                            archetype.borrow_mut_dynamic(id)
+                        } else {
+                            archetype.borrow_dynamic(id)
+                        };
+                        assert!(borrowed, "component already borrowed");
+                        Column {
+                            ty: *archetype.type_info(id).unwrap(),
+                            base: archetype.get_base(id).unwrap(),
+                            mutated: if write {
+                                archetype.mutated_dynamic(id)
+                            } else {
+                                None
+                            },
+                            tick: archetype.tick(),
+                        }
+                    })
+                    .collect();
+                (archetype, columns)
+            })
+            .collect();
+        Self {
+            meta,
+            archetypes,
+            query,
+        }
+    }
+
+    /// Iterate over the matching entities
+    pub fn iter(&mut self) -> impl Iterator<Item = (Entity, DynamicRow<'_>)> + '_ {
+        let meta = self.meta;
+        self.archetypes
+            .iter()
+            .flat_map(move |(archetype, columns)| {
+                (0..archetype.len()).map(move |index| {
+                    let id = archetype.entity_id(index);
+                    let entity = Entity {
+                        id,
+                        generation: meta[id as usize].generation,
+                    };
+                    (entity, DynamicRow { columns, index })
+                })
+            })
+    }
+}
+
+unsafe impl Send for DynamicQueryBorrow<'_> {}
+unsafe impl Sync for DynamicQueryBorrow<'_> {}
+
+impl Drop for DynamicQueryBorrow<'_> {
+    fn drop(&mut self) {
+        for (archetype, _) in &self.archetypes {
+            for &(id, write) in &self.query.components {
+                if write {
+                    archetype.release_mut_dynamic(id);
+                } else {
+                    archetype.release_dynamic(id);
+                }
+            }
+        }
+    }
+}
+
+/// The components of a single entity matched by a `DynamicQuery`
+///
+/// Components are indexed in the order their types were added to the query.
+pub struct DynamicRow<'q> {
+    columns: &'q [Column],
+    index: u32,
+}
+
+impl DynamicRow<'_> {
+    /// Number of components
+    pub fn len(&self) -> usize {
+        self.columns.len()
+    }
+
+    /// Whether the query fetches no components
+    pub fn is_empty(&self) -> bool {
+        self.columns.is_empty()
+    }
+
+    /// Metadata describing the `i`th component's type
+    pub fn type_info(&self, i: usize) -> &TypeInfo {
+        &self.columns[i].ty
+    }
+
+    /// Address of the `i`th component, valid for reads while the `DynamicQueryBorrow` lives
+    pub fn get(&self, i: usize) -> *const u8 {
+        self.ptr(i)
+    }
+
+    /// Address of the `i`th component, valid for writes while the `DynamicQueryBorrow` lives, or
+    /// `None` if it was not requested with [`DynamicQuery::write`]
+    ///
+    /// Marks the component as changed.
+    pub fn get_mut(&mut self, i: usize) -> Option<*mut u8> {
+        let column = &self.columns[i];
+        unsafe {
+            (*column.mutated?.as_ptr().add(self.index as usize))
+                .store(column.tick, Ordering::Relaxed);
+        }
+        Some(self.ptr(i))
+    }
+
+    fn ptr(&self, i: usize) -> *mut u8 {
+        let column = &self.columns[i];
+        unsafe {
+            column
+                .base
+                .as_ptr()
+                .add(column.ty.layout().size() * self.index as usize)
+        }
+    }
+}
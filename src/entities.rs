@@ -1,43 +1,83 @@
+use alloc::vec;
 use alloc::vec::Vec;
 use core::cmp;
 use core::convert::TryFrom;
-use core::sync::atomic::{AtomicI64, Ordering};
+use core::sync::atomic::{AtomicI64, AtomicU32, Ordering};
 use core::{fmt, mem};
 #[cfg(feature = "std")]
 use std::error::Error;
 
+/// Integer type of entity IDs, as returned by [`Entity::id`]
+///
+/// `u32` by default, or `u64` with the `u64-index` feature, for worlds that need more than four
+/// billion IDs. An ID is only reused once its previous entity is despawned, so this bounds the
+/// number of simultaneously live entities plus those whose IDs were retired, rather than the
+/// number ever spawned.
+#[cfg(not(feature = "u64-index"))]
+pub type EntityId = u32;
+/// Integer type of entity IDs, as returned by [`Entity::id`]
+///
+/// `u64` with the `u64-index` feature, for worlds that need more than four billion IDs, or `u32`
+/// by default.
+#[cfg(feature = "u64-index")]
+pub type EntityId = u64;
+
+/// Integer type of the bits produced by [`Entity::to_bits`], `u64` by default or `u128` with the
+/// `u64-index` feature
+#[cfg(not(feature = "u64-index"))]
+pub type EntityBits = u64;
+/// Integer type of the bits produced by [`Entity::to_bits`], `u128` with the `u64-index` feature
+/// or `u64` by default
+#[cfg(feature = "u64-index")]
+pub type EntityBits = u128;
+
 /// Lightweight unique ID, or handle, of an entity
 ///
 /// Obtained from `World::spawn`. Can be stored to refer to an entity in the future.
 ///
+/// Handles are weak: they don't keep their entity alive, and once it's despawned, operations on
+/// them fail with `NoSuchEntity` even if its ID is reused. `World::validate` discards stale
+/// handles in bulk.
+///
 /// Enable the `serde` feature on the crate to make this `Serialize`able. Some applications may be
 /// able to save space by only serializing the output of `Entity::id`.
 #[derive(Clone, Copy, Hash, Eq, Ord, PartialEq, PartialOrd)]
 pub struct Entity {
     pub(crate) generation: u32,
-    pub(crate) id: u32,
+    pub(crate) id: EntityId,
 }
 
 impl Entity {
+    /// A handle that never refers to a live entity
+    ///
+    /// Useful as a placeholder in components that may or may not refer to an entity. World
+    /// operations on it fail with `NoSuchEntity`.
+    pub const DANGLING: Entity = Entity {
+        generation: u32::MAX,
+        id: EntityId::MAX,
+    };
+
     /// Convert to a form convenient for passing outside of rust
     ///
-    /// No particular structure is guaranteed for the returned bits.
+    /// The generation occupies the upper 32 bits and the `id` the remaining lower bits: 32 by
+    /// default, or 64 with the `u64-index` feature. This layout will not change without a breaking
+    /// release, so the bits may be stored in scripts, network packets, or GPU buffers.
     ///
     /// Useful for storing entity IDs externally, or in conjunction with `Entity::from_bits` and
     /// `World::spawn_at` for easy serialization. Alternatively, consider `id` for more compact
     /// representation.
-    pub fn to_bits(self) -> u64 {
-        u64::from(self.generation) << 32 | u64::from(self.id)
+    pub fn to_bits(self) -> EntityBits {
+        EntityBits::from(self.generation) << EntityId::BITS | EntityBits::from(self.id)
     }
 
     /// Reconstruct an `Entity` previously destructured with `to_bits`
     ///
     /// Useful for storing entity IDs externally, or in conjunction with `Entity::to_bits` and
     /// `World::spawn_at` for easy serialization.
-    pub fn from_bits(bits: u64) -> Self {
+    pub fn from_bits(bits: EntityBits) -> Self {
         Self {
-            generation: (bits >> 32) as u32,
-            id: bits as u32,
+            generation: (bits >> EntityId::BITS) as u32,
+            id: bits as EntityId,
         }
     }
 
@@ -48,7 +88,7 @@ impl Entity {
     /// specific snapshot of the world, such as when serializing.
     ///
     /// See also `World::find_entity_from_id`.
-    pub fn id(self) -> u32 {
+    pub fn id(self) -> EntityId {
         self.id
     }
 }
@@ -75,7 +115,7 @@ impl<'de> serde::Deserialize<'de> for Entity {
     where
         D: serde::Deserializer<'de>,
     {
-        let bits = u64::deserialize(deserializer)?;
+        let bits = EntityBits::deserialize(deserializer)?;
         Ok(Entity::from_bits(bits))
     }
 }
@@ -86,10 +126,10 @@ pub struct ReserveEntitiesIterator<'a> {
     meta: &'a [EntityMeta],
 
     // Reserved IDs formerly in the freelist to hand out.
-    id_iter: core::slice::Iter<'a, u32>,
+    id_iter: core::slice::Iter<'a, EntityId>,
 
     // New Entity IDs to hand out, outside the range of meta.len().
-    id_range: core::ops::Range<u32>,
+    id_range: core::ops::Range<EntityId>,
 }
 
 impl<'a> Iterator for ReserveEntitiesIterator<'a> {
@@ -106,7 +146,7 @@ impl<'a> Iterator for ReserveEntitiesIterator<'a> {
     }
 
     fn size_hint(&self) -> (usize, Option<usize>) {
-        let len = self.id_iter.len() + self.id_range.len();
+        let len = self.id_iter.len() + (self.id_range.end - self.id_range.start) as usize;
         (len, Some(len))
     }
 }
@@ -152,16 +192,74 @@ pub(crate) struct Entities {
     // and then from the new IDs, using only a single atomic subtract.
     //
     // Once `flush()` is done, `free_cursor` will equal `pending.len()`.
-    pending: Vec<u32>,
+    pending: Vec<EntityId>,
     free_cursor: AtomicI64,
     len: u32,
+    /// User-defined flags of each entity ID, kept the same length as `meta`
+    flags: Vec<AtomicU32>,
+    policy: GenerationPolicy,
+}
+
+impl Entities {
+    /// Bytes of heap memory used to track entities
+    pub(crate) fn allocated_bytes(&self) -> usize {
+        self.meta.capacity() * mem::size_of::<EntityMeta>()
+            + self.pending.capacity() * mem::size_of::<EntityId>()
+            + self.flags.capacity() * mem::size_of::<AtomicU32>()
+    }
+
+    /// Extend `flags` to cover every ID in `meta`
+    fn sync_flags(&mut self) {
+        let len = self.meta.len();
+        self.flags.resize_with(len, || AtomicU32::new(0));
+    }
+
+    /// The flags of `entity`, unless it doesn't exist or is yet to be flushed
+    pub fn flags(&self, entity: Entity) -> Option<&AtomicU32> {
+        let meta = self.meta.get(entity.id as usize)?;
+        if meta.generation != entity.generation
+            || meta.location.index == EntityMeta::EMPTY.location.index
+        {
+            return None;
+        }
+        Some(&self.flags[entity.id as usize])
+    }
+}
+
+impl Clone for Entities {
+    fn clone(&self) -> Self {
+        Self {
+            meta: self.meta.clone(),
+            pending: self.pending.clone(),
+            free_cursor: AtomicI64::new(self.free_cursor.load(Ordering::Relaxed)),
+            len: self.len,
+            flags: clone_flags(&self.flags),
+            policy: self.policy,
+        }
+    }
+
+    fn clone_from(&mut self, source: &Self) {
+        self.meta.clone_from(&source.meta);
+        self.pending.clone_from(&source.pending);
+        *self.free_cursor.get_mut() = source.free_cursor.load(Ordering::Relaxed);
+        self.len = source.len;
+        self.flags = clone_flags(&source.flags);
+        self.policy = source.policy;
+    }
+}
+
+fn clone_flags(flags: &[AtomicU32]) -> Vec<AtomicU32> {
+    flags
+        .iter()
+        .map(|x| AtomicU32::new(x.load(Ordering::Relaxed)))
+        .collect()
 }
 
 impl Entities {
     /// Reserve entity IDs concurrently
     ///
     /// Storage for entity generation and location is lazily allocated by calling `flush`.
-    pub fn reserve_entities(&self, count: u32) -> ReserveEntitiesIterator {
+    pub fn reserve_entities(&self, count: u32) -> ReserveEntitiesIterator<'_> {
         // Use one atomic subtract to grab a range of new IDs. The range might be
         // entirely nonnegative, meaning all IDs come from the freelist, or entirely
         // negative, meaning they are all new IDs to allocate, or a mix of both.
@@ -185,10 +283,10 @@ impl Entities {
             // to go, yielding `meta.len()+0 .. meta.len()+3`.
             let base = self.meta.len() as i64;
 
-            let new_id_end = u32::try_from(base - range_start).expect("too many entities");
+            let new_id_end = EntityId::try_from(base - range_start).expect("too many entities");
 
             // `new_id_end` is in range, so no need to check `start`.
-            let new_id_start = (base - range_end.min(0)) as u32;
+            let new_id_start = (base - range_end.min(0)) as EntityId;
 
             (new_id_start, new_id_end)
         };
@@ -220,7 +318,7 @@ impl Entities {
             // and farther beyond `meta.len()`.
             Entity {
                 generation: 0,
-                id: u32::try_from(self.meta.len() as i64 - n).expect("too many entities"),
+                id: EntityId::try_from(self.meta.len() as i64 - n).expect("too many entities"),
             }
         }
     }
@@ -248,8 +346,9 @@ impl Entities {
                 id,
             }
         } else {
-            let id = u32::try_from(self.meta.len()).expect("too many entities");
+            let id = EntityId::try_from(self.meta.len()).expect("too many entities");
             self.meta.push(EntityMeta::EMPTY);
+            self.flags.push(AtomicU32::new(0));
             Entity { generation: 0, id }
         }
     }
@@ -261,10 +360,12 @@ impl Entities {
         self.verify_flushed();
 
         let loc = if entity.id as usize >= self.meta.len() {
-            self.pending.extend((self.meta.len() as u32)..entity.id);
+            self.pending
+                .extend((self.meta.len() as EntityId)..entity.id);
             let new_free_cursor = self.pending.len() as i64;
             self.free_cursor.store(new_free_cursor, Ordering::Relaxed); // Not racey due to &mut self
             self.meta.resize(entity.id as usize + 1, EntityMeta::EMPTY);
+            self.sync_flags();
             self.len += 1;
             None
         } else if let Some(index) = self.pending.iter().position(|item| *item == entity.id) {
@@ -273,6 +374,10 @@ impl Entities {
             self.free_cursor.store(new_free_cursor, Ordering::Relaxed); // Not racey due to &mut self
             self.len += 1;
             None
+        } else if self.meta[entity.id as usize].is_retired() {
+            // Explicitly requested, so the caller has taken responsibility for stale handles
+            self.len += 1;
+            None
         } else {
             Some(mem::replace(
                 &mut self.meta[entity.id as usize].location,
@@ -281,28 +386,48 @@ impl Entities {
         };
 
         self.meta[entity.id as usize].generation = entity.generation;
+        *self.flags[entity.id as usize].get_mut() = 0;
 
         loc
     }
 
+    /// The live entity currently using `id`, if any
+    ///
+    /// Must not be called while reserved entities are awaiting `flush()`.
+    pub fn occupant(&self, id: EntityId) -> Option<Entity> {
+        let meta = self.meta.get(id as usize)?;
+        // Free IDs have no location
+        if meta.location.index == EntityMeta::EMPTY.location.index {
+            return None;
+        }
+        Some(Entity {
+            generation: meta.generation,
+            id,
+        })
+    }
+
     /// Destroy an entity, allowing it to be reused
     ///
     /// Must not be called while reserved entities are awaiting `flush()`.
     pub fn free(&mut self, entity: Entity) -> Result<Location, NoSuchEntity> {
         self.verify_flushed();
 
-        let meta = &mut self.meta[entity.id as usize];
-        if meta.generation != entity.generation {
+        let meta = self.meta.get_mut(entity.id as usize).ok_or(NoSuchEntity)?;
+        if meta.generation != entity.generation || meta.is_retired() {
             return Err(NoSuchEntity);
         }
-        meta.generation += 1;
+        self.check_reusable(entity.id);
+        let meta = &mut self.meta[entity.id as usize];
+        let reusable = meta.advance_generation();
 
         let loc = mem::replace(&mut meta.location, EntityMeta::EMPTY.location);
+        *self.flags[entity.id as usize].get_mut() = 0;
 
-        self.pending.push(entity.id);
-
-        let new_free_cursor = self.pending.len() as i64;
-        self.free_cursor.store(new_free_cursor, Ordering::Relaxed); // Not racey due to &mut self
+        if reusable {
+            self.pending.push(entity.id);
+            let new_free_cursor = self.pending.len() as i64;
+            self.free_cursor.store(new_free_cursor, Ordering::Relaxed); // Not racey due to &mut self
+        }
         self.len -= 1;
 
         Ok(loc)
@@ -316,29 +441,73 @@ impl Entities {
         let shortfall = additional as i64 - freelist_size;
         if shortfall > 0 {
             self.meta.reserve(shortfall as usize);
+            self.flags.reserve(shortfall as usize);
         }
     }
 
-    pub fn contains(&self, entity: Entity) -> bool {
-        // Note that out-of-range IDs are considered to be "contained" because
-        // they must be reserved IDs that we haven't flushed yet.
+    /// Allocate storage for a total of `count` entities, so that allocating and freeing them never
+    /// reallocates
+    pub fn reserve_total(&mut self, count: u32) {
+        self.verify_flushed();
         self.meta
-            .get(entity.id as usize)
-            .map_or(true, |meta| meta.generation == entity.generation)
+            .reserve_exact((count as usize).saturating_sub(self.meta.len()));
+        self.flags
+            .reserve_exact((count as usize).saturating_sub(self.flags.len()));
+        self.pending
+            .reserve_exact((count as usize).saturating_sub(self.pending.len()));
+    }
+
+    /// Whether `alloc` can succeed without reallocating
+    pub fn has_spare_capacity(&self) -> bool {
+        !self.pending.is_empty() || self.meta.len() < self.meta.capacity()
     }
 
+    pub fn contains(&self, entity: Entity) -> bool {
+        // Note that out-of-range IDs of the first generation are considered to be "contained"
+        // because they must be reserved IDs that we haven't flushed yet.
+        match self.meta.get(entity.id as usize) {
+            Some(meta) => meta.generation == entity.generation && !meta.is_retired(),
+            None => entity.generation == 0,
+        }
+    }
+
+    /// Free every entity, keeping IDs for reuse
+    ///
+    /// Generations advance as usual, so handles to the freed entities are never mistaken for
+    /// their successors.
     pub fn clear(&mut self) {
-        self.meta.clear();
+        self.verify_flushed();
+        self.check_all_reusable();
+        let mut free = vec![false; self.meta.len()];
+        for &id in &self.pending {
+            free[id as usize] = true;
+        }
         self.pending.clear();
-        self.free_cursor.store(0, Ordering::Relaxed); // Not racey due to &mut self
+        // Reversed so that the lowest IDs are reused first
+        for (id, meta) in self.meta.iter_mut().enumerate().rev() {
+            if meta.is_retired() {
+                continue;
+            }
+            if !free[id] {
+                meta.location = EntityMeta::EMPTY.location;
+                *self.flags[id].get_mut() = 0;
+                if !meta.advance_generation() {
+                    continue;
+                }
+            }
+            self.pending.push(id as EntityId);
+        }
+        self.free_cursor
+            .store(self.pending.len() as i64, Ordering::Relaxed); // Not racey due to &mut self
+        self.len = 0;
     }
 
     /// Access the location storage of an entity
     ///
     /// Must not be called on pending entities.
     pub fn get_mut(&mut self, entity: Entity) -> Result<&mut Location, NoSuchEntity> {
-        let meta = &mut self.meta[entity.id as usize];
-        if meta.generation == entity.generation {
+        let meta = self.meta.get_mut(entity.id as usize).ok_or(NoSuchEntity)?;
+        if meta.generation == entity.generation && !meta.is_retired() {
             Ok(&mut meta.location)
         } else {
             Err(NoSuchEntity)
@@ -346,23 +515,21 @@ impl Entities {
     }
 
     /// Returns `Ok(Location { archetype: 0, index: undefined })` for pending entities
+    #[inline]
     pub fn get(&self, entity: Entity) -> Result<Location, NoSuchEntity> {
         if self.meta.len() <= entity.id as usize {
+            if entity.generation != 0 {
+                return Err(NoSuchEntity);
+            }
             return Ok(Location {
                 archetype: 0,
-                index: u32::max_value(),
+                index: u32::MAX,
             });
         }
         let meta = &self.meta[entity.id as usize];
-        if meta.generation != entity.generation {
+        if meta.generation != entity.generation || meta.is_retired() {
             return Err(NoSuchEntity);
         }
-        if meta.location.archetype == 0 {
-            return Ok(Location {
-                archetype: 0,
-                index: u32::max_value(),
-            });
-        }
         Ok(meta.location)
     }
 
@@ -370,7 +537,7 @@ impl Entities {
     ///
     /// # Safety
     /// Must only be called for currently allocated `id`s.
-    pub unsafe fn resolve_unknown_gen(&self, id: u32) -> Entity {
+    pub unsafe fn resolve_unknown_gen(&self, id: EntityId) -> Entity {
         let meta_len = self.meta.len();
 
         if meta_len > id as usize {
@@ -400,7 +567,7 @@ impl Entities {
 
     /// Allocates space for entities previously reserved with `reserve_entity` or
     /// `reserve_entities`, then initializes each one using the supplied function.
-    pub fn flush(&mut self, mut init: impl FnMut(u32, &mut Location)) {
+    pub fn flush(&mut self, mut init: impl FnMut(EntityId, &mut Location)) {
         // Not racey due because of self is &mut.
         let free_cursor = self.free_cursor.load(Ordering::Relaxed);
 
@@ -410,9 +577,11 @@ impl Entities {
             let old_meta_len = self.meta.len();
             let new_meta_len = old_meta_len + -free_cursor as usize;
             self.meta.resize(new_meta_len, EntityMeta::EMPTY);
+            self.sync_flags();
+            self.len += -free_cursor as u32;
 
             for (id, meta) in self.meta.iter_mut().enumerate().skip(old_meta_len) {
-                init(id as u32, &mut meta.location);
+                init(id as EntityId, &mut meta.location);
             }
 
             self.free_cursor.store(0, Ordering::Relaxed);
@@ -429,6 +598,137 @@ impl Entities {
     pub fn len(&self) -> u32 {
         self.len
     }
+
+    pub fn set_policy(&mut self, policy: GenerationPolicy) {
+        self.policy = policy;
+    }
+
+    /// Enforce the policy for freeing the ID `id` of a live entity, before anything is modified
+    pub fn check_reusable(&self, id: EntityId) {
+        if self.policy == GenerationPolicy::Panic
+            && self.meta[id as usize].generation == u32::MAX - 1
+        {
+            panic!("entity ID {} has exhausted its generations", id);
+        }
+    }
+
+    /// Like `check_reusable`, for every live entity
+    pub fn check_all_reusable(&self) {
+        if self.policy != GenerationPolicy::Panic {
+            return;
+        }
+        for (id, meta) in self.meta.iter().enumerate() {
+            if meta.location.index != EntityMeta::EMPTY.location.index {
+                self.check_reusable(id as EntityId);
+            }
+        }
+    }
+
+    pub fn audit(&self) -> EntityAudit {
+        let mut audit = EntityAudit {
+            ids: self.meta.len() as EntityId,
+            live: self.len,
+            ..EntityAudit::default()
+        };
+        for meta in &self.meta {
+            if meta.is_retired() {
+                audit.retired += 1;
+                audit.recycles += u64::from(meta.generation);
+                continue;
+            }
+            audit.recycles += u64::from(meta.generation);
+            audit.max_generation = audit.max_generation.max(meta.generation);
+            if meta.generation >= u32::MAX - EntityAudit::NEAR_EXHAUSTION {
+                audit.near_exhausted += 1;
+            }
+        }
+        audit
+    }
+}
+
+/// What happens when an entity ID has been reused as many times as its generation can count
+///
+/// Set with [`World::set_generation_policy`](crate::World::set_generation_policy). A handle's
+/// generation distinguishes it from the handles of earlier entities that had the same ID, so
+/// reusing an ID after running out of generations would let stale handles refer to a new entity.
+/// Since a generation is 32 bits, that takes over four billion despawns of entities with the same
+/// ID, but can happen in long-running programs that spawn and despawn rapidly.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default)]
+pub enum GenerationPolicy {
+    /// Stop reusing the ID, permanently costing one entry of the entity table
+    #[default]
+    Retire,
+    /// Panic on any attempt to despawn the last entity that could use the ID, before the world is
+    /// modified
+    Panic,
+}
+
+/// Statistics on the use of entity IDs, as reported by [`World::entity_audit`]
+///
+/// # Example
+/// ```
+/// # use hecs::*;
+/// let mut world = World::new();
+/// let a = world.spawn(());
+/// world.despawn(a).unwrap();
+/// let b = world.spawn((1,));
+/// world.spawn(());
+/// let audit = world.entity_audit();
+/// assert_eq!((audit.ids(), audit.live()), (2, 2));
+/// assert_eq!(audit.recycles(), 1);
+/// assert_eq!(audit.empty(), 1);
+/// assert_eq!(audit.near_exhausted(), 0);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct EntityAudit {
+    ids: EntityId,
+    live: u32,
+    retired: u32,
+    near_exhausted: u32,
+    max_generation: u32,
+    recycles: u64,
+    pub(crate) empty: u32,
+}
+
+impl EntityAudit {
+    /// Number of generations from exhaustion within which an ID counts as near exhausted
+    pub const NEAR_EXHAUSTION: u32 = 1 << 16;
+
+    /// Number of IDs ever allocated, live or not
+    pub fn ids(&self) -> EntityId {
+        self.ids
+    }
+
+    /// Number of live entities, including reserved ones
+    pub fn live(&self) -> u32 {
+        self.live
+    }
+
+    /// Number of IDs that ran out of generations and will never be reused
+    pub fn retired(&self) -> u32 {
+        self.retired
+    }
+
+    /// Number of IDs, not yet retired, within `NEAR_EXHAUSTION` generations of retirement
+    pub fn near_exhausted(&self) -> u32 {
+        self.near_exhausted
+    }
+
+    /// Highest generation of any ID not yet retired
+    pub fn max_generation(&self) -> u32 {
+        self.max_generation
+    }
+
+    /// Total number of times entities' IDs have been freed
+    pub fn recycles(&self) -> u64 {
+        self.recycles
+    }
+
+    /// Number of live entities with no components, which in many programs suggests a handle that
+    /// was spawned or reserved and then forgotten
+    pub fn empty(&self) -> u32 {
+        self.empty
+    }
 }
 
 #[derive(Copy, Clone)]
@@ -442,9 +742,25 @@ impl EntityMeta {
         generation: 0,
         location: Location {
             archetype: 0,
-            index: u32::max_value(), // dummy value, to be filled in
+            index: u32::MAX, // dummy value, to be filled in
         },
     };
+
+    /// Advance the generation of a freed entity, returning whether its ID may be reused
+    ///
+    /// Once every generation of an ID has been used, the ID is retired rather than wrapping
+    /// around, so that a stale handle can never be mistaken for a live entity. This costs one
+    /// `EntityMeta` per four billion reuses of any one ID.
+    fn advance_generation(&mut self) -> bool {
+        self.generation = self.generation.wrapping_add(1);
+        self.generation != u32::MAX
+    }
+
+    /// Whether the ID has been freed for the last time, so that no handle refers to it, even one
+    /// naming its final generation
+    fn is_retired(&self) -> bool {
+        self.generation == u32::MAX && self.location.index == Self::EMPTY.location.index
+    }
 }
 
 #[derive(Copy, Clone)]
@@ -481,14 +797,25 @@ mod tests {
         assert_eq!(Entity::from_bits(e.to_bits()), e);
     }
 
+    #[test]
+    #[cfg(feature = "u64-index")]
+    fn wide_entity_bits() {
+        let e = Entity {
+            generation: 0xDEADBEEF,
+            id: 0xBAADF00D_FEEDFACE,
+        };
+        assert_eq!(e.to_bits(), 0xDEADBEEF << 64 | 0xBAADF00D_FEEDFACE);
+        assert_eq!(Entity::from_bits(e.to_bits()), e);
+    }
+
     #[test]
     fn alloc_and_free() {
         let mut rng = StdRng::seed_from_u64(0xFEEDFACEDEADF00D);
 
         let mut e = Entities::default();
-        let mut first_unused = 0u32;
-        let mut id_to_gen: HashMap<u32, u32> = Default::default();
-        let mut free_set: HashSet<u32> = Default::default();
+        let mut first_unused: EntityId = 0;
+        let mut id_to_gen: HashMap<EntityId, u32> = Default::default();
+        let mut free_set: HashSet<EntityId> = Default::default();
         let mut len = 0;
 
         for _ in 0..100 {
@@ -577,6 +904,49 @@ mod tests {
         assert_eq!(e.meta.len(), 4);
     }
 
+    #[test]
+    fn exhausted_generation() {
+        let mut e = Entities::default();
+        let old = Entity {
+            id: 0,
+            generation: u32::MAX - 1,
+        };
+        assert!(e.alloc_at(old).is_none());
+        e.free(old).unwrap();
+        assert!(!e.contains(old));
+        assert!(!e.contains(Entity {
+            id: 0,
+            generation: 0
+        }));
+        // Nor is the generation it was retired at
+        let last = Entity {
+            id: 0,
+            generation: u32::MAX,
+        };
+        assert!(!e.contains(last));
+        assert!(e.get(last).is_err());
+        assert!(e.get_mut(last).is_err());
+        assert!(e.free(last).is_err());
+
+        // The exhausted ID is never reused
+        let next = e.alloc();
+        assert_eq!(next.id, 1);
+        e.free(next).unwrap();
+        e.clear();
+        assert_eq!(e.alloc().id, 1);
+        assert_eq!(e.alloc().id, 2);
+        assert_eq!(e.len(), 2);
+
+        // ...unless asked for explicitly
+        let revived = Entity {
+            id: 0,
+            generation: 0,
+        };
+        assert!(e.alloc_at(revived).is_none());
+        assert!(e.contains(revived));
+        assert_eq!(e.len(), 3);
+    }
+
     #[test]
     fn contains() {
         let mut e = Entities::default();
@@ -627,7 +997,7 @@ mod tests {
         assert_eq!(v3.len(), 16);
         v3.sort_by_key(|entity| entity.id);
         for (i, entity) in v3.into_iter().enumerate() {
-            assert_eq!(entity.id, i as u32);
+            assert_eq!(entity.id, i as EntityId);
         }
 
         // 6 will come from pending.
@@ -66,41 +66,56 @@ impl EntityBuilder {
     pub fn add_bundle(&mut self, bundle: impl DynamicBundle) -> &mut Self {
         unsafe {
             bundle.put(|ptr, ty| {
-                match self.indices.entry(ty.id()) {
-                    Entry::Occupied(occupied) => {
-                        let index = *occupied.get();
-                        let (ty, offset) = self.info[index];
-                        let storage = self.storage.as_ptr().add(offset);
+                self.add_raw(ptr, ty);
+            });
+        }
+        self
+    }
 
-                        // Drop the existing value
-                        ty.drop(storage);
+    /// Add the component at `component`, described by `ty`, to the entity
+    ///
+    /// Allows components whose types are only known at runtime, such as those constructed with
+    /// [`TypeInfo::from_parts`], to be moved into the `World`. As with [`add`](Self::add), an
+    /// existing component with the same id is dropped and replaced.
+    ///
+    /// # Safety
+    ///
+    /// `component` must point to a valid value of the type described by `ty`. Ownership of the
+    /// value is transferred to the builder, so the caller must not use or drop it afterwards.
+    pub unsafe fn add_raw(&mut self, component: *mut u8, ty: TypeInfo) -> &mut Self {
+        match self.indices.entry(ty.id()) {
+            Entry::Occupied(occupied) => {
+                let index = *occupied.get();
+                let (ty, offset) = self.info[index];
+                let storage = self.storage.as_ptr().add(offset);
 
-                        // Overwrite the old value with our new one.
-                        ptr::copy_nonoverlapping(ptr, storage, ty.layout().size());
-                    }
-                    Entry::Vacant(vacant) => {
-                        let offset = align(self.cursor, ty.layout().align());
-                        let end = offset + ty.layout().size();
-                        if end > self.layout.size() || ty.layout().align() > self.layout.align() {
-                            let new_align = self.layout.align().max(ty.layout().align());
-                            let (new_storage, new_layout) =
-                                Self::grow(end, self.cursor, new_align, self.storage);
-                            if self.layout.size() != 0 {
-                                dealloc(self.storage.as_ptr(), self.layout);
-                            }
-                            self.storage = new_storage;
-                            self.layout = new_layout;
-                        }
-
-                        let addr = self.storage.as_ptr().add(offset);
-                        ptr::copy_nonoverlapping(ptr, addr, ty.layout().size());
-
-                        vacant.insert(self.info.len());
-                        self.info.push((ty, offset));
-                        self.cursor = end;
+                // Drop the existing value
+                ty.drop(storage);
+
+                // Overwrite the old value with our new one.
+                ptr::copy_nonoverlapping(component, storage, ty.layout().size());
+            }
+            Entry::Vacant(vacant) => {
+                let offset = align(self.cursor, ty.layout().align());
+                let end = offset + ty.layout().size();
+                if end > self.layout.size() || ty.layout().align() > self.layout.align() {
+                    let new_align = self.layout.align().max(ty.layout().align());
+                    let (new_storage, new_layout) =
+                        Self::grow(end, self.cursor, new_align, self.storage);
+                    if self.layout.size() != 0 {
+                        dealloc(self.storage.as_ptr(), self.layout);
                     }
+                    self.storage = new_storage;
+                    self.layout = new_layout;
                 }
-            });
+
+                let addr = self.storage.as_ptr().add(offset);
+                ptr::copy_nonoverlapping(component, addr, ty.layout().size());
+
+                vacant.insert(self.info.len());
+                self.info.push((ty, offset));
+                self.cursor = end;
+            }
         }
         self
     }
@@ -221,3 +236,243 @@ impl Drop for BuiltEntity<'_> {
         self.builder.clear();
     }
 }
+
+/// Variant of [`EntityBuilder`] whose output can be spawned any number of times
+///
+/// Requires every component to implement `Clone`. Useful for prefabs: build once, then spawn
+/// copies with `world.spawn(prefab.clone())`.
+///
+/// ```
+/// # use hecs::*;
+/// let mut world = World::new();
+/// let mut builder = EntityBuilderClone::new();
+/// builder.add(123).add("abc");
+/// let prefab = builder.build();
+/// let a = world.spawn(prefab.clone());
+/// let b = world.spawn(prefab);
+/// assert_eq!(*world.get::<i32>(a).unwrap(), 123);
+/// assert_eq!(*world.get::<&str>(b).unwrap(), "abc");
+/// ```
+pub struct EntityBuilderClone {
+    builder: EntityBuilder,
+    cloners: TypeIdMap<CloneFn>,
+}
+
+/// Clones the value at the first pointer into the uninitialized second pointer
+pub(crate) type CloneFn = unsafe fn(*const u8, *mut u8);
+
+impl EntityBuilderClone {
+    /// Create a builder representing an entity with no components
+    pub fn new() -> Self {
+        Self {
+            builder: EntityBuilder::new(),
+            cloners: Default::default(),
+        }
+    }
+
+    /// Add `component` to the entity.
+    ///
+    /// If the bundle already contains a component of type `T`, it will
+    /// be dropped and replaced with the most recently added one.
+    pub fn add<T: Component + Clone>(&mut self, component: T) -> &mut Self {
+        unsafe fn clone_ptr<T: Clone>(src: *const u8, dst: *mut u8) {
+            dst.cast::<T>().write((*src.cast::<T>()).clone());
+        }
+
+        self.builder.add(component);
+        self.cloners.insert(TypeId::of::<T>(), clone_ptr::<T>);
+        self
+    }
+
+    /// Add a clone of the component of type `ty` at `component`, using `clone`
+    pub(crate) unsafe fn add_cloned_raw(
+        &mut self,
+        component: *const u8,
+        ty: TypeInfo,
+        clone: CloneFn,
+    ) -> &mut Self {
+        let layout = ty.layout();
+        let scratch = if layout.size() == 0 {
+            NonNull::new_unchecked(layout.align() as *mut u8)
+        } else {
+            NonNull::new(alloc(layout)).unwrap()
+        };
+        clone(component, scratch.as_ptr());
+        self.builder.add_raw(scratch.as_ptr(), ty);
+        self.cloners.insert(ty.id(), clone);
+        if layout.size() != 0 {
+            dealloc(scratch.as_ptr(), layout);
+        }
+        self
+    }
+
+    /// Checks to see if the component of type `T` exists
+    pub fn has<T: Component>(&self) -> bool {
+        self.builder.has::<T>()
+    }
+
+    /// Borrow the component of type `T`, if it exists
+    pub fn get<T: Component>(&self) -> Option<&T> {
+        self.builder.get::<T>()
+    }
+
+    /// Uniquely borrow the component of type `T`, if it exists
+    pub fn get_mut<T: Component>(&mut self) -> Option<&mut T> {
+        self.builder.get_mut::<T>()
+    }
+
+    /// Construct a reusable `Bundle`
+    pub fn build(self) -> BuiltEntityClone {
+        let Self {
+            mut builder,
+            cloners,
+        } = self;
+        builder.info.sort_unstable_by_key(|x| x.0);
+        builder.ids.extend(builder.info.iter().map(|x| x.0.id()));
+        let cloners = builder
+            .info
+            .iter()
+            .map(|(ty, _)| cloners[&ty.id()])
+            .collect();
+        BuiltEntityClone { builder, cloners }
+    }
+}
+
+impl Default for EntityBuilderClone {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The output of an `EntityBuilderClone`, suitable for passing to `World::spawn` or
+/// `World::insert`
+///
+/// Pass a clone to spawn a copy, keeping the original for later. Every component is cloned before
+/// any reaches the world, so a panicking `Clone` impl leaves the world untouched.
+pub struct BuiltEntityClone {
+    builder: EntityBuilder,
+    /// Clone functions for each element of `builder.info`
+    cloners: Vec<CloneFn>,
+}
+
+impl BuiltEntityClone {
+    /// Whether there are no components
+    pub fn is_empty(&self) -> bool {
+        self.builder.info.is_empty()
+    }
+}
+
+impl Clone for BuiltEntityClone {
+    fn clone(&self) -> Self {
+        let source = &self.builder;
+        let mut builder = EntityBuilder::new();
+        unsafe {
+            if source.layout.size() != 0 {
+                builder.storage = NonNull::new(alloc(source.layout)).unwrap();
+                builder.layout = source.layout;
+            }
+            builder.cursor = source.cursor;
+            for (&(ty, offset), clone) in source.info.iter().zip(&self.cloners) {
+                clone(
+                    source.storage.as_ptr().add(offset),
+                    builder.storage.as_ptr().add(offset),
+                );
+                // Keep `builder` consistent in case a later clone panics
+                builder.info.push((ty, offset));
+            }
+        }
+        builder.ids.extend_from_slice(&source.ids);
+        builder.indices = source.indices.clone();
+        Self {
+            builder,
+            cloners: self.cloners.clone(),
+        }
+    }
+}
+
+unsafe impl DynamicBundle for BuiltEntityClone {
+    fn with_ids<T>(&self, f: impl FnOnce(&[TypeId]) -> T) -> T {
+        f(&self.builder.ids)
+    }
+
+    #[doc(hidden)]
+    fn type_info(&self) -> Vec<TypeInfo> {
+        self.builder.info.iter().map(|x| x.0).collect()
+    }
+
+    unsafe fn put(mut self, mut f: impl FnMut(*mut u8, TypeInfo)) {
+        for (ty, offset) in self.builder.info.drain(..) {
+            let ptr = self.builder.storage.as_ptr().add(offset);
+            f(ptr, ty);
+        }
+    }
+}
+
+/// The components of an entity despawned by
+/// [`World::despawn_and_take`](crate::World::despawn_and_take)
+///
+/// Pass to `World::spawn` or `World::insert` to move the components back into a `World`, or drop
+/// to drop them.
+pub struct TakenEntity {
+    builder: EntityBuilder,
+}
+
+impl TakenEntity {
+    pub(crate) fn new(mut builder: EntityBuilder) -> Self {
+        builder.info.sort_unstable_by_key(|x| x.0);
+        builder.ids.extend(builder.info.iter().map(|x| x.0.id()));
+        builder.indices.clear();
+        for (i, &(ty, _)) in builder.info.iter().enumerate() {
+            builder.indices.insert(ty.id(), i);
+        }
+        Self { builder }
+    }
+
+    /// Checks to see if the component of type `T` exists
+    pub fn has<T: Component>(&self) -> bool {
+        self.builder.has::<T>()
+    }
+
+    /// Borrow the component of type `T`, if it exists
+    pub fn get<T: Component>(&self) -> Option<&T> {
+        self.builder.get::<T>()
+    }
+
+    /// Uniquely borrow the component of type `T`, if it exists
+    pub fn get_mut<T: Component>(&mut self) -> Option<&mut T> {
+        self.builder.get_mut::<T>()
+    }
+
+    /// Enumerate the types of the components
+    pub fn component_types(&self) -> impl Iterator<Item = TypeId> + '_ {
+        self.builder.component_types()
+    }
+
+    /// Number of components
+    pub fn len(&self) -> usize {
+        self.builder.info.len()
+    }
+
+    /// Whether there are no components
+    pub fn is_empty(&self) -> bool {
+        self.builder.info.is_empty()
+    }
+}
+
+unsafe impl DynamicBundle for TakenEntity {
+    fn with_ids<T>(&self, f: impl FnOnce(&[TypeId]) -> T) -> T {
+        f(&self.builder.ids)
+    }
+
+    #[doc(hidden)]
+    fn type_info(&self) -> Vec<TypeInfo> {
+        self.builder.info.iter().map(|x| x.0).collect()
+    }
+
+    unsafe fn put(mut self, mut f: impl FnMut(*mut u8, TypeInfo)) {
+        for (ty, offset) in self.builder.info.drain(..) {
+            let ptr = self.builder.storage.as_ptr().add(offset);
+            f(ptr, ty);
+        }
+    }
+}
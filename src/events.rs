@@ -0,0 +1,116 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use crate::alloc::vec::Vec;
+use core::any::TypeId;
+use core::mem;
+
+use crate::archetype::{TypeIdMap, TypeInfo};
+use crate::Entity;
+
+/// A structural change to a `World`, recorded if enabled with
+/// [`World::record_events`](crate::World::record_events)
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum WorldEvent {
+    /// A component of type `ty` was added to `entity`, including by spawning it
+    ComponentAdded {
+        /// The entity that gained the component
+        entity: Entity,
+        /// The type of the component
+        ty: TypeId,
+    },
+    /// A component of type `ty` was removed from `entity`
+    ///
+    /// Not recorded for components that leave the world because their entity was despawned.
+    ComponentRemoved {
+        /// The entity that lost the component
+        entity: Entity,
+        /// The type of the component
+        ty: TypeId,
+    },
+    /// `entity` was despawned along with all its components
+    Despawned {
+        /// The former entity
+        entity: Entity,
+    },
+}
+
+/// Pending `WorldEvent`s, if recording is enabled
+#[derive(Default)]
+pub(crate) struct EventLog {
+    events: Option<Vec<WorldEvent>>,
+    /// Entities that lost a component of each type registered with `World::track_removed`
+    removals: TypeIdMap<Vec<Entity>>,
+}
+
+impl EventLog {
+    pub fn set_enabled(&mut self, enabled: bool) {
+        if enabled != self.is_enabled() {
+            self.events = if enabled { Some(Vec::new()) } else { None };
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.events.is_some()
+    }
+
+    /// Whether any component type's removals are tracked
+    pub fn tracks_removals(&self) -> bool {
+        !self.removals.is_empty()
+    }
+
+    pub fn track_removals(&mut self, ty: TypeId) {
+        self.removals.entry(ty).or_default();
+    }
+
+    pub fn removals(&self, ty: TypeId) -> &[Entity] {
+        self.removals.get(&ty).map_or(&[], |x| x)
+    }
+
+    pub fn clear_removals(&mut self) {
+        for x in self.removals.values_mut() {
+            x.clear();
+        }
+    }
+
+    pub fn take(&mut self) -> Vec<WorldEvent> {
+        self.events.as_mut().map_or_else(Vec::new, mem::take)
+    }
+
+    pub fn added(&mut self, entity: Entity, types: &[TypeInfo]) {
+        if let Some(ref mut events) = self.events {
+            events.extend(
+                types
+                    .iter()
+                    .map(|x| WorldEvent::ComponentAdded { entity, ty: x.id() }),
+            );
+        }
+    }
+
+    pub fn removed(&mut self, entity: Entity, ty: TypeId) {
+        if let Some(ref mut events) = self.events {
+            events.push(WorldEvent::ComponentRemoved { entity, ty });
+        }
+        if let Some(removals) = self.removals.get_mut(&ty) {
+            removals.push(entity);
+        }
+    }
+
+    /// Record that `entity` was despawned while holding components of `types`
+    pub fn despawned(&mut self, entity: Entity, types: impl Iterator<Item = TypeId>) {
+        if let Some(ref mut events) = self.events {
+            events.push(WorldEvent::Despawned { entity });
+        }
+        if !self.removals.is_empty() {
+            for ty in types {
+                if let Some(removals) = self.removals.get_mut(&ty) {
+                    removals.push(entity);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,309 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! C-compatible interface, for backing an engine written in another language
+//!
+//! Entities cross the boundary as the [`EntityBits`] produced by [`Entity::to_bits`], which are
+//! `u64`s unless the `u64-index` feature widens them to `u128`s. Component types are identified
+//! by the `u32`s returned from [`hecs_component_register`], which are shared by every `World` in
+//! the process, and their values are moved in and out with `memcpy`.
+//!
+//! No dynamic borrow checking is performed on behalf of foreign code. While a pointer into a
+//! `World` obtained from one of these functions is in use, no other function may be called on
+//! the same `World`, except that a component pointer obtained from [`hecs_get`] remains valid
+//! across further calls to `hecs_get` and [`hecs_contains`].
+
+use core::alloc::Layout;
+use core::any::TypeId;
+use core::ffi::c_void;
+use core::marker::PhantomData;
+use core::mem;
+use core::slice;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::RwLock;
+
+use crate::alloc::{boxed::Box, vec::Vec};
+use crate::{DynamicQuery, Entity, EntityBits, EntityBuilder, TypeInfo, World};
+
+/// Maximum number of component types that may be registered with [`hecs_component_register`]
+pub const HECS_MAX_COMPONENTS: u32 = 4096;
+
+/// Returned by [`hecs_component_register`] on failure
+pub const HECS_INVALID_COMPONENT: u32 = u32::MAX;
+
+/// Destructor of a component, given its address
+pub type HecsDropFn = unsafe extern "C" fn(component: *mut c_void);
+
+/// Invoked by [`hecs_query_each`] for each matching entity, with `components` pointing to the
+/// addresses of its components, in the order requested
+pub type HecsQueryFn =
+    unsafe extern "C" fn(user: *mut c_void, entity: EntityBits, components: *const *mut c_void);
+
+/// Create an empty world, to be freed with [`hecs_world_free`]
+#[no_mangle]
+pub extern "C" fn hecs_world_new() -> *mut World {
+    Box::into_raw(Box::new(World::new()))
+}
+
+/// Free a world created by [`hecs_world_new`], dropping every component
+///
+/// # Safety
+///
+/// `world` must be null or have come from `hecs_world_new`, and must not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn hecs_world_free(world: *mut World) {
+    if !world.is_null() {
+        drop(Box::from_raw(world));
+    }
+}
+
+/// Register a component type of `size` bytes aligned to `align`, with an optional destructor
+///
+/// Returns [`HECS_INVALID_COMPONENT`] if `size` and `align` don't describe a valid layout, or if
+/// [`HECS_MAX_COMPONENTS`] types have already been registered.
+///
+/// # Safety
+///
+/// `drop` must be sound to call on any value of the type for as long as it's stored in a `World`.
+#[no_mangle]
+pub unsafe extern "C" fn hecs_component_register(
+    size: usize,
+    align: usize,
+    drop: Option<HecsDropFn>,
+) -> u32 {
+    let layout = match Layout::from_size_align(size, align) {
+        Ok(x) => x,
+        Err(_) => return HECS_INVALID_COMPONENT,
+    };
+    let mut types = TYPES.write().unwrap();
+    let index = types.len();
+    if index >= HECS_MAX_COMPONENTS as usize {
+        return HECS_INVALID_COMPONENT;
+    }
+    if let Some(drop) = drop {
+        DROPS[index].store(drop as usize, Ordering::Relaxed);
+    }
+    let (id, drop) = slot(index);
+    types.push(TypeInfo::from_parts(id, layout, drop));
+    index as u32
+}
+
+/// Spawn an entity with no components
+///
+/// # Safety
+///
+/// `world` must be valid.
+#[no_mangle]
+pub unsafe extern "C" fn hecs_spawn(world: *mut World) -> EntityBits {
+    (*world).spawn(()).to_bits()
+}
+
+/// Despawn `entity`, dropping its components, returning `false` if it didn't exist
+///
+/// # Safety
+///
+/// `world` must be valid.
+#[no_mangle]
+pub unsafe extern "C" fn hecs_despawn(world: *mut World, entity: EntityBits) -> bool {
+    (*world).despawn(Entity::from_bits(entity)).is_ok()
+}
+
+/// Whether `entity` exists
+///
+/// # Safety
+///
+/// `world` must be valid.
+#[no_mangle]
+pub unsafe extern "C" fn hecs_contains(world: *const World, entity: EntityBits) -> bool {
+    (*world).contains(Entity::from_bits(entity))
+}
+
+/// Move the value at `value` into `entity` as its `component`, dropping any it already has
+///
+/// Returns `false`, leaving `value` untouched, if `entity` doesn't exist or `component` isn't
+/// registered. Otherwise, `value` must be treated as moved from.
+///
+/// # Safety
+///
+/// `world` must be valid and `value` must point to a value of the registered type.
+#[no_mangle]
+pub unsafe extern "C" fn hecs_insert(
+    world: *mut World,
+    entity: EntityBits,
+    component: u32,
+    value: *mut c_void,
+) -> bool {
+    let entity = Entity::from_bits(entity);
+    let ty = match type_info(component) {
+        Some(x) if (*world).contains(entity) => x,
+        _ => return false,
+    };
+    let mut builder = EntityBuilder::new();
+    builder.add_raw(value.cast(), ty);
+    (*world).insert(entity, builder.build()).is_ok()
+}
+
+/// Drop `entity`'s `component`, if it has one, returning `false` if `entity` doesn't exist
+///
+/// # Safety
+///
+/// `world` must be valid.
+#[no_mangle]
+pub unsafe extern "C" fn hecs_remove(
+    world: *mut World,
+    entity: EntityBits,
+    component: u32,
+) -> bool {
+    let ty = match type_info(component) {
+        Some(x) => x,
+        None => return (*world).contains(Entity::from_bits(entity)),
+    };
+    (*world)
+        .remove_dynamic(Entity::from_bits(entity), ty.id())
+        .is_ok()
+}
+
+/// Address of `entity`'s `component`, or null if it has none, which is marked as changed
+///
+/// The address is valid until components or entities are next added or removed.
+///
+/// # Safety
+///
+/// `world` must be valid.
+#[no_mangle]
+pub unsafe extern "C" fn hecs_get(
+    world: *const World,
+    entity: EntityBits,
+    component: u32,
+) -> *mut c_void {
+    let ty = match type_info(component) {
+        Some(x) => x,
+        None => return core::ptr::null_mut(),
+    };
+    let entity = match (*world).entity(Entity::from_bits(entity)) {
+        Ok(x) => x,
+        Err(_) => return core::ptr::null_mut(),
+    };
+    // The borrow is released immediately; keeping accesses disjoint is up to the caller
+    entity
+        .get_raw_mut(ty.id())
+        .map_or(core::ptr::null_mut(), |mut x| x.as_mut_ptr().cast())
+}
+
+/// Call `f` for every entity having all `count` of the `components`
+///
+/// Returns `false`, without calling `f`, if any of `components` is unregistered or repeated.
+///
+/// # Safety
+///
+/// `world` must be valid, `components` must point to `count` IDs unless `count` is zero, and `f`
+/// must not call any other function on `world`.
+#[no_mangle]
+pub unsafe extern "C" fn hecs_query_each(
+    world: *const World,
+    components: *const u32,
+    count: usize,
+    f: HecsQueryFn,
+    user: *mut c_void,
+) -> bool {
+    let components = if count == 0 {
+        &[]
+    } else {
+        slice::from_raw_parts(components, count)
+    };
+    let mut query = DynamicQuery::new();
+    for (i, &component) in components.iter().enumerate() {
+        if type_info(component).is_none() || components[..i].contains(&component) {
+            return false;
+        }
+        query = query.write(slot(component as usize).0);
+    }
+    let mut addresses = Vec::with_capacity(count);
+    for (entity, mut row) in (*world).query_dynamic(&query).iter() {
+        addresses.clear();
+        for i in 0..count {
+            addresses.push(row.get_mut(i).unwrap().cast::<c_void>());
+        }
+        f(user, entity.to_bits(), addresses.as_ptr());
+    }
+    true
+}
+
+fn type_info(component: u32) -> Option<TypeInfo> {
+    TYPES.read().unwrap().get(component as usize).copied()
+}
+
+/// Every registered component type, indexed by ID
+static TYPES: RwLock<Vec<TypeInfo>> = RwLock::new(Vec::new());
+
+/// Destructor of each registered component type, if any
+static DROPS: [AtomicUsize; HECS_MAX_COMPONENTS as usize] = [NO_DROP; HECS_MAX_COMPONENTS as usize];
+
+#[allow(clippy::declare_interior_mutable_const)]
+const NO_DROP: AtomicUsize = AtomicUsize::new(0);
+
+/// A marker type whose `TypeId` stands in for the registered component type with ID `INDEX`
+///
+/// Built from three nested hexadecimal digits, giving `HECS_MAX_COMPONENTS` distinct types.
+trait Slot: 'static {
+    const INDEX: usize;
+}
+
+struct Root;
+
+impl Slot for Root {
+    const INDEX: usize = 0;
+}
+
+macro_rules! digits {
+    ($($name:ident = $value:expr,)*) => {
+        $(
+            struct $name<T>(PhantomData<T>);
+
+            impl<T: Slot> Slot for $name<T> {
+                const INDEX: usize = T::INDEX * 16 + $value;
+            }
+        )*
+
+        /// Call `$f` with the slot extending `$parent` by the hexadecimal digit `$digit`
+        macro_rules! extend {
+            ($digit:expr, $f:ident, $parent:ty, $n:expr) => {
+                match $digit {
+                    $($value => $f::<$name<$parent>>($n),)*
+                    _ => unreachable!(),
+                }
+            };
+        }
+    };
+}
+
+digits! {
+    D0 = 0, D1 = 1, D2 = 2, D3 = 3, D4 = 4, D5 = 5, D6 = 6, D7 = 7,
+    D8 = 8, D9 = 9, D10 = 10, D11 = 11, D12 = 12, D13 = 13, D14 = 14, D15 = 15,
+}
+
+/// The `TypeId` and destructor standing in for the registered component type with ID `n`
+fn slot(n: usize) -> (TypeId, unsafe fn(*mut u8)) {
+    fn low<T: Slot>(n: usize) -> (TypeId, unsafe fn(*mut u8)) {
+        extend!(n & 15, leaf, T, n)
+    }
+    fn mid<T: Slot>(n: usize) -> (TypeId, unsafe fn(*mut u8)) {
+        extend!((n >> 4) & 15, low, T, n)
+    }
+    fn leaf<T: Slot>(n: usize) -> (TypeId, unsafe fn(*mut u8)) {
+        debug_assert_eq!(T::INDEX, n);
+        (TypeId::of::<T>(), drop_slot::<T>)
+    }
+    extend!(n >> 8, mid, Root, n)
+}
+
+unsafe fn drop_slot<T: Slot>(component: *mut u8) {
+    let drop = DROPS[T::INDEX].load(Ordering::Relaxed);
+    if drop != 0 {
+        mem::transmute::<usize, HecsDropFn>(drop)(component.cast());
+    }
+}
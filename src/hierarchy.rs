@@ -0,0 +1,182 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use crate::alloc::{vec, vec::Vec};
+
+use crate::{Entity, EntityMap, MapEntities, NoSuchEntity, World};
+
+/// The entity that an entity is attached to
+///
+/// Maintained by [`World::attach`] and [`World::detach`]. Inserting or removing this component
+/// directly will corrupt the hierarchy.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Parent(pub(crate) Entity);
+
+impl Parent {
+    /// The parent entity
+    pub fn get(&self) -> Entity {
+        self.0
+    }
+}
+
+/// The entities attached to an entity, in the order they were attached
+///
+/// Maintained by [`World::attach`] and [`World::detach`]. Inserting or removing this component
+/// directly will corrupt the hierarchy.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Children(pub(crate) Vec<Entity>);
+
+impl MapEntities for Parent {
+    fn map_entities(&mut self, map: &EntityMap) {
+        self.0.map_entities(map);
+    }
+}
+
+impl Children {
+    /// Iterate over the child entities
+    pub fn iter(&self) -> impl ExactSizeIterator<Item = Entity> + '_ {
+        self.0.iter().copied()
+    }
+
+    /// The child entities
+    pub fn as_slice(&self) -> &[Entity] {
+        &self.0
+    }
+
+    /// Number of child entities
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Whether there are no child entities
+    ///
+    /// Always `false` for a `Children` component stored in a `World`, which is removed along with
+    /// its last child.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl MapEntities for Children {
+    fn map_entities(&mut self, map: &EntityMap) {
+        self.0.map_entities(map);
+    }
+}
+
+impl World {
+    /// Attach `child` to `parent`, after any children it already has
+    ///
+    /// `child` is first detached from its current parent, if any. The relationship is recorded in
+    /// `child`'s [`Parent`] and `parent`'s [`Children`] components, and is maintained when either
+    /// entity is despawned.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `parent` is `child` or one of its descendants.
+    ///
+    /// # Example
+    /// ```
+    /// # use hecs::*;
+    /// let mut world = World::new();
+    /// let parent = world.spawn(());
+    /// let child = world.spawn((123,));
+    /// world.attach(child, parent).unwrap();
+    /// assert_eq!(world.parent(child), Some(parent));
+    /// world.despawn_recursive(parent).unwrap();
+    /// assert!(!world.contains(child));
+    /// ```
+    pub fn attach(&mut self, child: Entity, parent: Entity) -> Result<(), NoSuchEntity> {
+        if !self.contains(child) || !self.contains(parent) {
+            return Err(NoSuchEntity);
+        }
+        let mut ancestor = Some(parent);
+        while let Some(x) = ancestor {
+            assert!(
+                x != child,
+                "an entity cannot be attached to its own descendant"
+            );
+            ancestor = self.parent(x);
+        }
+        self.detach(child)?;
+        self.insert_one(child, Parent(parent))?;
+        if let Ok(mut children) = self.get_mut::<Children>(parent) {
+            children.0.push(child);
+            return Ok(());
+        }
+        self.insert_one(parent, Children(vec![child]))
+    }
+
+    /// Detach `child` from its parent, returning the former parent, if any
+    pub fn detach(&mut self, child: Entity) -> Result<Option<Entity>, NoSuchEntity> {
+        if !self.contains(child) {
+            return Err(NoSuchEntity);
+        }
+        let parent = match self.remove_one::<Parent>(child) {
+            Ok(Parent(x)) => x,
+            Err(_) => return Ok(None),
+        };
+        remove_child(self, parent, child);
+        Ok(Some(parent))
+    }
+
+    /// The entity that `entity` is attached to, if any
+    pub fn parent(&self, entity: Entity) -> Option<Entity> {
+        self.get::<Parent>(entity).ok().map(|x| x.0)
+    }
+
+    /// Despawn `entity` and, recursively, everything attached to it
+    pub fn despawn_recursive(&mut self, entity: Entity) -> Result<(), NoSuchEntity> {
+        self.detach(entity)?;
+        let mut pending = vec![entity];
+        while let Some(x) = pending.pop() {
+            if let Ok(children) = self.get::<Children>(x) {
+                pending.extend(children.iter());
+            }
+            // Every link of `x` is to another entity being despawned, so needn't be maintained
+            self.despawn_unlinked(x)?;
+        }
+        Ok(())
+    }
+}
+
+/// Remove the links between `entity` and its relatives prior to despawning it
+pub(crate) fn unlink(world: &mut World, entity: Entity) {
+    if let Some(parent) = world.parent(entity) {
+        remove_child(world, parent, entity);
+    }
+    if let Ok(children) = world.get::<Children>(entity).map(|x| x.0.clone()) {
+        for child in children {
+            let _ = world.remove_one::<Parent>(child);
+        }
+    }
+}
+
+/// Rewrite the links of entities moved by `World::merge` to refer to their new handles
+pub(crate) fn remap(world: &mut World, map: &EntityMap) {
+    for (_, entity) in map.iter() {
+        if let Ok(mut parent) = world.get_mut::<Parent>(entity) {
+            parent.0 = map.get(parent.0).unwrap();
+        }
+        if let Ok(mut children) = world.get_mut::<Children>(entity) {
+            for child in &mut children.0 {
+                *child = map.get(*child).unwrap();
+            }
+        }
+    }
+}
+
+fn remove_child(world: &mut World, parent: Entity, child: Entity) {
+    let now_empty = {
+        let mut children = world.get_mut::<Children>(parent).unwrap();
+        let index = children.0.iter().position(|&x| x == child).unwrap();
+        children.0.remove(index);
+        children.0.is_empty()
+    };
+    if now_empty {
+        world.remove_one::<Children>(parent).unwrap();
+    }
+}
@@ -0,0 +1,126 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use core::any;
+use core::fmt;
+
+use crate::archetype::TypeIdMap;
+use crate::{EntityRef, World};
+
+/// Formats a component of a type registered with `World::register_debug`, and compares it if
+/// registered with `World::register_eq`
+#[derive(Copy, Clone)]
+pub(crate) struct DebugFormatter {
+    pub(crate) name: &'static str,
+    fmt: unsafe fn(*const u8, &mut fmt::Formatter<'_>) -> fmt::Result,
+    pub(crate) eq: Option<unsafe fn(*const u8, *const u8) -> bool>,
+}
+
+impl DebugFormatter {
+    pub(crate) fn of<T: fmt::Debug + 'static>() -> Self {
+        unsafe fn fmt_ptr<T: fmt::Debug>(x: *const u8, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            fmt::Debug::fmt(&*x.cast::<T>(), f)
+        }
+
+        Self {
+            name: any::type_name::<T>(),
+            fmt: fmt_ptr::<T>,
+            eq: None,
+        }
+    }
+
+    pub(crate) fn of_eq<T: fmt::Debug + PartialEq + 'static>() -> Self {
+        unsafe fn eq_ptr<T: PartialEq>(x: *const u8, y: *const u8) -> bool {
+            *x.cast::<T>() == *y.cast::<T>()
+        }
+
+        Self {
+            eq: Some(eq_ptr::<T>),
+            ..Self::of::<T>()
+        }
+    }
+
+    /// Format the component at `ptr`
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must refer to a value of the type this formatter was constructed for.
+    pub(crate) unsafe fn display(self, ptr: *const u8) -> impl fmt::Debug {
+        Component {
+            formatter: self,
+            ptr,
+        }
+    }
+}
+
+/// Lists the components of an entity, as returned by
+/// [`World::debug_entity`](crate::World::debug_entity)
+///
+/// Formats as a map from each component's type to its value. Components of types that weren't
+/// registered with [`World::register_debug`](crate::World::register_debug) are keyed by `TypeId`,
+/// and their values elided. Borrows each component while formatting it, panicking if it's
+/// uniquely borrowed elsewhere.
+pub struct DebugEntity<'a> {
+    pub(crate) entity: EntityRef<'a>,
+    pub(crate) formatters: &'a TypeIdMap<DebugFormatter>,
+}
+
+impl fmt::Debug for DebugEntity<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut map = f.debug_map();
+        for ty in self.entity.component_types() {
+            match self.formatters.get(&ty) {
+                Some(&formatter) => {
+                    let component = self.entity.get_raw(ty).unwrap();
+                    map.entry(
+                        &format_args!("{}", formatter.name),
+                        &Component {
+                            formatter,
+                            ptr: component.as_ptr(),
+                        },
+                    );
+                }
+                None => {
+                    map.entry(&ty, &format_args!(".."));
+                }
+            }
+        }
+        map.finish()
+    }
+}
+
+/// Formats as a map from each entity to its components, as described by `World::debug_entity`
+///
+/// # Example
+/// ```
+/// # use hecs::*;
+/// let mut world = World::new();
+/// world.register_debug::<i32>();
+/// let a = world.spawn((42,));
+/// assert_eq!(format!("{:?}", world), format!("{{{:?}: {{i32: 42}}}}", a));
+/// ```
+impl fmt::Debug for World {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut map = f.debug_map();
+        for (entity, _) in self.iter() {
+            map.entry(&entity, &self.debug_entity(entity).unwrap());
+        }
+        map.finish()
+    }
+}
+
+struct Component {
+    formatter: DebugFormatter,
+    ptr: *const u8,
+}
+
+impl fmt::Debug for Component {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Safe because the formatter was registered for the type the pointer refers to
+        unsafe { (self.formatter.fmt)(self.ptr, f) }
+    }
+}
@@ -0,0 +1,166 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use core::borrow::Borrow;
+use core::fmt;
+use core::hash::{BuildHasher, Hash, Hasher};
+use core::ops::Deref;
+use std::borrow::ToOwned;
+use std::sync::{Arc, Mutex};
+
+use hashbrown::hash_map::{HashMap, RawEntryMut};
+
+/// A table of distinct values of `T`, which any number of worlds may share
+///
+/// Interning a value yields an [`Interned`] handle to the table's copy of it, so that components
+/// with equal values, such as names or asset paths repeated across many entities, are stored
+/// once rather than once per entity. Cloning an `Interner` gives another handle to the same table.
+///
+/// # Example
+/// ```
+/// # use hecs::*;
+/// let names = Interner::<String>::new();
+/// let mut a = World::new();
+/// let mut b = World::new();
+/// let x = a.spawn((names.intern_ref("goblin"),));
+/// let y = b.spawn((names.intern("goblin".to_string()),));
+/// assert_eq!(names.len(), 1);
+///
+/// let x = a.get::<Interned<String>>(x).unwrap();
+/// let y = b.get::<Interned<String>>(y).unwrap();
+/// assert!(Interned::ptr_eq(&x, &y));
+/// assert_eq!(x.len(), 6);
+/// ```
+pub struct Interner<T> {
+    table: Arc<Mutex<HashMap<Arc<T>, ()>>>,
+}
+
+impl<T: Eq + Hash> Interner<T> {
+    /// Create an empty table
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get a handle to the table's copy of `value`, adding it if necessary
+    pub fn intern(&self, value: T) -> Interned<T> {
+        let mut table = self.table.lock().unwrap();
+        match table.raw_entry_mut().from_key(&value) {
+            RawEntryMut::Occupied(x) => Interned(x.key().clone()),
+            RawEntryMut::Vacant(x) => Interned(x.insert(Arc::new(value), ()).0.clone()),
+        }
+    }
+
+    /// Like `intern`, but only constructing an owned value if `value` is new to the table
+    pub fn intern_ref<Q>(&self, value: &Q) -> Interned<T>
+    where
+        T: Borrow<Q>,
+        Q: ToOwned<Owned = T> + Eq + Hash + ?Sized,
+    {
+        let mut table = self.table.lock().unwrap();
+        let hash = table.hasher().hash_one(value);
+        let (x, ()) = table
+            .raw_entry_mut()
+            .from_hash(hash, |x| (**x).borrow() == value)
+            .or_insert_with(|| (Arc::new(value.to_owned()), ()));
+        Interned(x.clone())
+    }
+
+    /// Remove values no longer referred to by any `Interned`, returning how many were removed
+    pub fn purge(&self) -> usize {
+        let mut table = self.table.lock().unwrap();
+        let len = table.len();
+        table.retain(|x, ()| Arc::strong_count(x) > 1);
+        len - table.len()
+    }
+
+    /// Number of distinct values in the table, including those pending `purge`
+    pub fn len(&self) -> usize {
+        self.table.lock().unwrap().len()
+    }
+
+    /// Whether the table holds no values
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<T> Clone for Interner<T> {
+    fn clone(&self) -> Self {
+        Self {
+            table: self.table.clone(),
+        }
+    }
+}
+
+impl<T> Default for Interner<T> {
+    fn default() -> Self {
+        Self {
+            table: Arc::default(),
+        }
+    }
+}
+
+/// A component whose value is stored once in an [`Interner`] for every equal component
+///
+/// Dereferences to `T`, so querying for `&Interned<T>` gives access to the value much like
+/// querying for `&T` would. Each component stores only a pointer, and cloning one is cheap. The
+/// value can't be modified in place; replace the component with a newly interned value instead.
+pub struct Interned<T>(Arc<T>);
+
+impl<T> Interned<T> {
+    /// Whether `a` and `b` refer to the same table entry, which for handles from the same
+    /// `Interner` is equivalent to, and faster than, comparing their values
+    pub fn ptr_eq(a: &Self, b: &Self) -> bool {
+        Arc::ptr_eq(&a.0, &b.0)
+    }
+}
+
+impl<T> Clone for Interned<T> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<T> Deref for Interned<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T> AsRef<T> for Interned<T> {
+    fn as_ref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T: PartialEq> PartialEq for Interned<T> {
+    fn eq(&self, other: &Self) -> bool {
+        Self::ptr_eq(self, other) || *self.0 == *other.0
+    }
+}
+
+impl<T: Eq> Eq for Interned<T> {}
+
+impl<T: Hash> Hash for Interned<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.hash(state);
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Interned<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl<T: fmt::Display> fmt::Display for Interned<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
@@ -0,0 +1,194 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use crate::alloc::vec::Vec;
+
+use crate::{NoSuchEntity, SnapshotRegistry, World, WorldDiff, WorldSnapshot};
+
+/// A log of transactions applied to a `World`, for implementing undo and redo
+///
+/// Journaling is opt-in: changes are only recorded between [`begin`](Self::begin) and
+/// [`commit`](Self::commit), by comparing snapshots of the world's registered components taken at
+/// each end. Every kind of change is captured, including spawns, despawns, insertions, removals,
+/// and components modified in place through queries or `get_mut`.
+///
+/// # Example
+/// ```
+/// # use hecs::*;
+/// let mut registry = SnapshotRegistry::new();
+/// registry.register_eq::<i32>();
+/// let mut journal = Journal::new(registry);
+/// let mut world = World::new();
+/// let a = world.spawn((1,));
+///
+/// journal.begin(&mut world);
+/// *world.get_mut::<i32>(a).unwrap() = 2;
+/// let b = world.spawn((3,));
+/// journal.commit(&mut world);
+///
+/// journal.undo(&mut world).unwrap();
+/// assert_eq!(*world.get::<i32>(a).unwrap(), 1);
+/// assert!(!world.contains(b));
+/// journal.redo(&mut world).unwrap();
+/// assert_eq!(*world.get::<i32>(b).unwrap(), 3);
+/// ```
+pub struct Journal {
+    registry: SnapshotRegistry,
+    /// State of the world when the open transaction began, if any
+    open: Option<WorldSnapshot>,
+    transactions: Vec<Transaction>,
+    /// Number of `transactions` that are currently applied; the rest may be redone
+    applied: usize,
+}
+
+impl Journal {
+    /// Create an empty journal recording components of the types in `registry`
+    ///
+    /// Components of other types are neither recorded nor affected by undo and redo. As with
+    /// [`World::snapshot`], every component type present in the world must be registered.
+    pub fn new(registry: SnapshotRegistry) -> Self {
+        Self {
+            registry,
+            open: None,
+            transactions: Vec::new(),
+            applied: 0,
+        }
+    }
+
+    /// Begin recording a transaction
+    ///
+    /// # Panics
+    ///
+    /// Panics if a transaction is already open.
+    pub fn begin(&mut self, world: &mut World) {
+        assert!(self.open.is_none(), "a transaction is already open");
+        self.open = Some(world.snapshot(&self.registry));
+    }
+
+    /// Whether a transaction has begun but not yet been committed
+    pub fn is_open(&self) -> bool {
+        self.open.is_some()
+    }
+
+    /// Finish recording the open transaction, returning it
+    ///
+    /// Transactions that were undone and not redone are discarded. An empty transaction is still
+    /// recorded, so each `commit` may be undone independently.
+    ///
+    /// # Panics
+    ///
+    /// Panics if no transaction is open.
+    pub fn commit(&mut self, world: &mut World) -> &Transaction {
+        let before = self.open.take().expect("no transaction is open");
+        let after = world.snapshot(&self.registry);
+        self.transactions.truncate(self.applied);
+        self.transactions.push(Transaction {
+            undo: WorldDiff::new(&after, &before),
+            redo: WorldDiff::new(&before, &after),
+        });
+        self.applied += 1;
+        &self.transactions[self.applied - 1]
+    }
+
+    /// Discard the open transaction, if any, leaving the world as it is
+    pub fn abandon(&mut self) {
+        self.open = None;
+    }
+
+    /// Revert the most recently applied transaction, returning `false` if there is none
+    ///
+    /// # Panics
+    ///
+    /// Panics if a transaction is open.
+    pub fn undo(&mut self, world: &mut World) -> Result<bool, NoSuchEntity> {
+        assert!(self.open.is_none(), "a transaction is open");
+        if self.applied == 0 {
+            return Ok(false);
+        }
+        world.revert(&self.transactions[self.applied - 1])?;
+        self.applied -= 1;
+        Ok(true)
+    }
+
+    /// Reapply the most recently undone transaction, returning `false` if there is none
+    ///
+    /// # Panics
+    ///
+    /// Panics if a transaction is open.
+    pub fn redo(&mut self, world: &mut World) -> Result<bool, NoSuchEntity> {
+        assert!(self.open.is_none(), "a transaction is open");
+        let transaction = match self.transactions.get(self.applied) {
+            Some(x) => x,
+            None => return Ok(false),
+        };
+        world.replay(transaction)?;
+        self.applied += 1;
+        Ok(true)
+    }
+
+    /// The transactions currently applied, oldest first
+    pub fn applied(&self) -> &[Transaction] {
+        &self.transactions[..self.applied]
+    }
+
+    /// The transactions that have been undone and may be redone, most recently undone last
+    pub fn undone(&self) -> impl ExactSizeIterator<Item = &Transaction> + '_ {
+        self.transactions[self.applied..].iter().rev()
+    }
+
+    /// Forget every transaction
+    pub fn clear(&mut self) {
+        self.transactions.clear();
+        self.applied = 0;
+    }
+}
+
+/// The changes made to a `World` between [`Journal::begin`] and [`Journal::commit`]
+pub struct Transaction {
+    undo: WorldDiff,
+    redo: WorldDiff,
+}
+
+impl Transaction {
+    /// The changes that restore the world to its state before the transaction
+    ///
+    /// The [`changed`](WorldDiff::changed) components are the old values of those the transaction
+    /// modified.
+    pub fn undo(&self) -> &WorldDiff {
+        &self.undo
+    }
+
+    /// The changes the transaction made
+    ///
+    /// The [`changed`](WorldDiff::changed) components are the new values of those the transaction
+    /// modified.
+    pub fn redo(&self) -> &WorldDiff {
+        &self.redo
+    }
+
+    /// Whether the transaction changed nothing
+    pub fn is_empty(&self) -> bool {
+        self.redo.is_empty()
+    }
+}
+
+impl World {
+    /// Undo the changes made by `transaction`
+    ///
+    /// The world should be in the state immediately after the transaction, e.g. because any
+    /// transactions committed since have been reverted. Entities the transaction despawned are
+    /// respawned with their original handles. Returns an error, having reverted only some of the
+    /// changes, if an entity the transaction left alive no longer exists.
+    pub fn revert(&mut self, transaction: &Transaction) -> Result<(), NoSuchEntity> {
+        self.apply_diff(&transaction.undo)
+    }
+
+    /// Redo the changes made by `transaction`, after it has been reverted
+    pub fn replay(&mut self, transaction: &Transaction) -> Result<(), NoSuchEntity> {
+        self.apply_diff(&transaction.redo)
+    }
+}
@@ -0,0 +1,38 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use core::fmt;
+
+use crate::alloc::boxed::Box;
+
+/// A human-readable name uniquely identifying an entity within its `World`
+///
+/// Maintained by [`World::insert_label`](crate::World::insert_label) and
+/// [`World::remove_label`](crate::World::remove_label). Inserting or removing this component
+/// directly will prevent the entity from being found by
+/// [`World::find_label`](crate::World::find_label).
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct Label(pub(crate) Box<str>);
+
+impl Label {
+    /// The label's text
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl PartialEq<str> for Label {
+    fn eq(&self, other: &str) -> bool {
+        *self.0 == *other
+    }
+}
+
+impl fmt::Display for Label {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
@@ -55,39 +55,154 @@ macro_rules! smaller_tuples_too {
     };
 }
 
+/// Emit a `tracing` event at `$level` if the `tracing` feature is enabled
+macro_rules! trace_event {
+    ($level:ident, $($tt:tt)*) => {
+        #[cfg(feature = "tracing")]
+        ::tracing::event!(::tracing::Level::$level, $($tt)*);
+    };
+}
+
+/// Enter a `tracing` span until the end of the enclosing block if the `tracing` feature is enabled
+#[allow(unused_macros)] // Only used by serialization
+macro_rules! trace_span {
+    ($($tt:tt)*) => {
+        #[cfg(feature = "tracing")]
+        let _span = ::tracing::debug_span!($($tt)*).entered();
+    };
+}
+
 mod archetype;
+#[cfg(feature = "async")]
+mod async_world;
+mod atomic_borrow;
+mod batch;
+#[cfg(feature = "std")]
+mod binary;
 mod borrow;
 mod bundle;
+mod chunk;
+mod closed_world;
+mod command_buffer;
+mod component_index;
+mod diff;
+mod disabled;
+mod double_buffer;
+mod dynamic_query;
 mod entities;
 mod entity_builder;
+mod events;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+mod hierarchy;
+mod inspect;
+#[cfg(feature = "std")]
+mod intern;
+mod journal;
+mod label;
+mod map_entities;
+#[cfg(feature = "std")]
+mod non_send;
+#[cfg(feature = "parallel")]
+mod parallel;
+mod pool;
+#[cfg(feature = "profile")]
+mod profile;
 mod query;
 mod query_one;
+mod registry;
+mod relation;
+mod resources;
+#[cfg(feature = "parallel")]
+pub mod schedule;
 #[cfg(feature = "serde")]
 pub mod serialize;
+mod snapshot;
+mod sparse;
+mod stable_id;
+mod state;
+pub mod testing;
+mod trait_query;
+mod transaction;
+pub mod transform;
+mod transient;
 mod world;
+mod world_cell;
 
-pub use archetype::Archetype;
-pub use borrow::{EntityRef, Ref, RefMut};
+pub use archetype::{Archetype, TypeInfo};
+#[cfg(feature = "async")]
+pub use async_world::{AsyncWorld, WorldHandle, WorldOp};
+pub use batch::{
+    BatchChunk, BatchIncomplete, BatchWriter, ColumnBatch, ColumnBatchBuilder, ColumnBatchType,
+    ComponentColumns, SplitFill,
+};
+#[cfg(feature = "std")]
+pub use binary::BinaryRegistry;
+pub use borrow::{Column, ColumnEntities, ColumnMut, EntityRef, RawRef, RawRefMut, Ref, RefMut};
 pub use bundle::{Bundle, DynamicBundle, MissingComponent};
-pub use entities::{Entity, NoSuchEntity};
-pub use entity_builder::{BuiltEntity, EntityBuilder};
+pub use chunk::{Chunk, ChunkComponent};
+pub use closed_world::{ClosedBundle, ClosedComponent};
+pub use command_buffer::CommandBuffer;
+pub use component_index::ComponentIndex;
+pub use diff::WorldDiff;
+pub use disabled::{Disabled, Including};
+pub use double_buffer::Prev;
+pub use dynamic_query::{DynamicQuery, DynamicQueryBorrow, DynamicRow, QueryParseError};
+pub use entities::{Entity, EntityAudit, EntityBits, EntityId, GenerationPolicy, NoSuchEntity};
+pub use entity_builder::{
+    BuiltEntity, BuiltEntityClone, EntityBuilder, EntityBuilderClone, TakenEntity,
+};
+pub use events::WorldEvent;
+pub use hierarchy::{Children, Parent};
+pub use inspect::DebugEntity;
+#[cfg(feature = "std")]
+pub use intern::{Interned, Interner};
+pub use journal::{Journal, Transaction};
+pub use label::Label;
+pub use map_entities::MapEntities;
+#[cfg(feature = "std")]
+pub use non_send::NonSend;
+#[cfg(feature = "parallel")]
+pub use parallel::QueryParIter;
+pub use pool::PoolStats;
+#[cfg(feature = "profile")]
+pub use profile::{ComponentProfile, ProfileFrame, WorldProfiler};
 pub use query::{
-    Access, BatchedIter, Query, QueryBorrow, QueryItem, QueryIter, QueryMut, With, Without,
+    Access, Added, BatchedIter, Changed, FilterArchetypes, Map, Mut, Or, OrDefault, PreparedQuery,
+    PreparedQueryBorrow, PreparedQueryIter, Query, QueryAccess, QueryBorrow, QueryCursor,
+    QueryCursorIter, QueryItem, QueryIter, QueryMut, QuerySingleError, QuerySlice, QueryValues,
+    QueryView, QueryViewItem, SliceChunkIter, SliceIter, With, Without, WithoutAny,
 };
 pub use query_one::QueryOne;
-pub use world::{ArchetypesGeneration, Component, ComponentError, Iter, SpawnBatchIter, World};
+pub use registry::{ComponentRegistry, RegisteredComponent};
+pub use relation::{QueryRelates, Relation};
+pub use resources::{ResourceRef, ResourceRefMut};
+pub use snapshot::{SnapshotRegistry, WorldSnapshot};
+pub use stable_id::{StableComponent, StableTypeId};
+pub use state::StateComponent;
+pub use trait_query::TraitQueryBorrow;
+pub use transaction::WorldTransaction;
+pub use world::{
+    ArchetypeId, ArchetypeStats, ArchetypesGeneration, BorrowError, Component, ComponentError,
+    EntityMap, EntityMut, Iter, QueryLookup, QueryOneError, SpawnColumnBatchIter, SpawnedEntities,
+    World, WorldStats,
+};
+pub use world_cell::{UnsafeWorldCell, WorldView};
 
 // Unstable implementation details needed by the macros
-#[doc(hidden)]
-pub use archetype::TypeInfo;
 #[cfg(feature = "macros")]
 #[doc(hidden)]
 pub use lazy_static;
 #[doc(hidden)]
-pub use query::Fetch;
+pub use query::{Fetch, SliceFetch};
+#[cfg(feature = "serde")]
+#[doc(hidden)]
+pub use serde as __serde;
+#[doc(hidden)]
+pub use trait_query::{FetchTraitRead, FetchTraitWrite, TraitCast, TraitObject};
 
 #[cfg(feature = "macros")]
-pub use hecs_macros::{Bundle, Query};
+pub use hecs_macros::{Bundle, MapEntities, Query, StableComponent};
 
 fn align(x: usize, alignment: usize) -> usize {
     debug_assert!(alignment.is_power_of_two());
@@ -0,0 +1,92 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use crate::alloc::{boxed::Box, vec::Vec};
+
+use crate::{Component, Entity, EntityMap, World};
+
+/// Types containing entity handles that may be rewritten by an [`EntityMap`]
+///
+/// Implement this for components that refer to other entities, then use
+/// [`World::map_entities`] to fix them up after a [`World::merge`]. With the `macros` feature
+/// enabled, this can be derived for structs and enums whose fields all implement it; fields
+/// that don't contain handles can be excluded with `#[map_entities(skip)]`.
+///
+/// # Example
+/// ```
+/// # use hecs::*;
+/// struct Target(Entity);
+///
+/// impl MapEntities for Target {
+///     fn map_entities(&mut self, map: &EntityMap) {
+///         self.0.map_entities(map);
+///     }
+/// }
+///
+/// let mut world = World::new();
+/// let mut chunk = World::new();
+/// let a = chunk.spawn(());
+/// let b = chunk.spawn((Target(a),));
+/// let map = world.merge(chunk);
+/// world.map_entities::<Target>(&map);
+/// let b = map.get(b).unwrap();
+/// assert_eq!(world.get::<Target>(b).unwrap().0, map.get(a).unwrap());
+/// ```
+pub trait MapEntities {
+    /// Replace every handle in `self` that appears in `map` with its new value
+    ///
+    /// Handles that `map` doesn't contain are left unchanged.
+    fn map_entities(&mut self, map: &EntityMap);
+}
+
+impl MapEntities for Entity {
+    fn map_entities(&mut self, map: &EntityMap) {
+        if let Some(new) = map.get(*self) {
+            *self = new;
+        }
+    }
+}
+
+impl<T: MapEntities> MapEntities for Option<T> {
+    fn map_entities(&mut self, map: &EntityMap) {
+        if let Some(x) = self {
+            x.map_entities(map);
+        }
+    }
+}
+
+impl<T: MapEntities> MapEntities for [T] {
+    fn map_entities(&mut self, map: &EntityMap) {
+        for x in self {
+            x.map_entities(map);
+        }
+    }
+}
+
+impl<T: MapEntities> MapEntities for Vec<T> {
+    fn map_entities(&mut self, map: &EntityMap) {
+        self.as_mut_slice().map_entities(map);
+    }
+}
+
+impl<T: MapEntities + ?Sized> MapEntities for Box<T> {
+    fn map_entities(&mut self, map: &EntityMap) {
+        (**self).map_entities(map);
+    }
+}
+
+impl World {
+    /// Rewrite the handles in every `T` component through `map`
+    ///
+    /// Typically called for each such component type after [`merge`](Self::merge), which only
+    /// updates the hierarchy's links itself.
+    pub fn map_entities<T: Component + MapEntities>(&mut self, map: &EntityMap) {
+        for (_, x) in self.query_mut::<&mut T>() {
+            x.map_entities(map);
+        }
+    }
+}
@@ -0,0 +1,222 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use core::any::TypeId;
+use core::marker::PhantomData;
+use core::mem::ManuallyDrop;
+use std::thread::{self, ThreadId};
+
+use crate::query::{FetchRead, FetchWrite};
+use crate::{Access, Archetype, ComponentError, Entity, Fetch, NoSuchEntity, Query, World};
+
+/// Query transformer fetching components that need not be `Send` or `Sync`
+///
+/// `NonSend<&T>` and `NonSend<&mut T>` fetch components inserted with
+/// [`World::insert_non_send`], which are bound to the thread that inserted them. The resulting
+/// items are only `Send` if a reference to `T` is, so such queries can't be used with `par_iter`,
+/// and a query borrow including one can't be moved to another thread.
+///
+/// Accessing a component from any thread other than the one that inserted it, e.g. after moving
+/// the whole `World`, panics. A component dropped on another thread is leaked instead.
+///
+/// # Example
+/// ```
+/// # use hecs::*;
+/// # use std::rc::Rc;
+/// let mut world = World::new();
+/// let a = world.spawn((1,));
+/// world.insert_non_send(a, Rc::new("shared")).unwrap();
+/// for (_, (&x, name)) in world.query::<(&i32, NonSend<&Rc<&str>>)>().iter() {
+///     assert_eq!((x, **name), (1, "shared"));
+/// }
+/// ```
+///
+/// Queries touching such components can't leave the thread:
+/// ```compile_fail
+/// # use hecs::*;
+/// # use std::rc::Rc;
+/// let world = World::new();
+/// let mut query = world.query::<NonSend<&Rc<i32>>>();
+/// std::thread::scope(|s| {
+///     s.spawn(move || for _ in query.iter() {});
+/// });
+/// ```
+pub struct NonSend<Q>(PhantomData<*const Q>);
+
+/// Storage for a component bound to the thread that created it
+#[doc(hidden)]
+pub struct NonSendCell<T> {
+    value: ManuallyDrop<T>,
+    thread: ThreadId,
+}
+
+impl<T> NonSendCell<T> {
+    fn new(value: T) -> Self {
+        Self {
+            value: ManuallyDrop::new(value),
+            thread: thread::current().id(),
+        }
+    }
+
+    fn check(&self, thread: ThreadId) {
+        assert!(
+            self.thread == thread,
+            "non-Send component accessed from a thread other than the one it was inserted on"
+        );
+    }
+
+    fn into_inner(self) -> T {
+        self.check(thread::current().id());
+        let mut this = ManuallyDrop::new(self);
+        unsafe { ManuallyDrop::take(&mut this.value) }
+    }
+}
+
+// Safe because the value is only accessed, or dropped, on the thread that created it
+unsafe impl<T> Send for NonSendCell<T> {}
+unsafe impl<T> Sync for NonSendCell<T> {}
+
+impl<T> Drop for NonSendCell<T> {
+    fn drop(&mut self) {
+        if self.thread == thread::current().id() {
+            unsafe {
+                ManuallyDrop::drop(&mut self.value);
+            }
+        }
+    }
+}
+
+impl<T: 'static> Query for NonSend<&'_ T> {
+    type Fetch = FetchNonSend<FetchRead<NonSendCell<T>>>;
+}
+
+impl<T: 'static> Query for NonSend<&'_ mut T> {
+    type Fetch = FetchNonSend<FetchWrite<NonSendCell<T>>>;
+}
+
+#[doc(hidden)]
+pub struct FetchNonSend<F> {
+    fetch: F,
+    /// The thread constructing the fetch, on which every fetched component must have been inserted
+    thread: Option<ThreadId>,
+}
+
+unsafe impl<'a, T: 'static> Fetch<'a> for FetchNonSend<FetchRead<NonSendCell<T>>> {
+    type Item = &'a T;
+
+    fn dangling() -> Self {
+        Self {
+            fetch: FetchRead::dangling(),
+            thread: None,
+        }
+    }
+
+    fn access(archetype: &Archetype) -> Option<Access> {
+        FetchRead::<NonSendCell<T>>::access(archetype)
+    }
+
+    fn borrow(archetype: &Archetype) {
+        FetchRead::<NonSendCell<T>>::borrow(archetype)
+    }
+    fn new(archetype: &'a Archetype) -> Option<Self> {
+        Some(Self {
+            fetch: FetchRead::new(archetype)?,
+            thread: Some(thread::current().id()),
+        })
+    }
+    fn release(archetype: &Archetype) {
+        FetchRead::<NonSendCell<T>>::release(archetype)
+    }
+
+    fn for_each_borrow(f: impl FnMut(TypeId, bool)) {
+        FetchRead::<NonSendCell<T>>::for_each_borrow(f);
+    }
+
+    unsafe fn get(&self, n: usize) -> &'a T {
+        let cell = <FetchRead<NonSendCell<T>> as Fetch<'a>>::get(&self.fetch, n);
+        cell.check(self.thread.unwrap());
+        &cell.value
+    }
+
+    unsafe fn should_skip(&self, n: usize) -> bool {
+        <FetchRead<NonSendCell<T>> as Fetch<'a>>::should_skip(&self.fetch, n)
+    }
+
+    fn filters_entities(&self) -> bool {
+        <FetchRead<NonSendCell<T>> as Fetch<'a>>::filters_entities(&self.fetch)
+    }
+}
+
+unsafe impl<'a, T: 'static> Fetch<'a> for FetchNonSend<FetchWrite<NonSendCell<T>>> {
+    type Item = &'a mut T;
+
+    fn dangling() -> Self {
+        Self {
+            fetch: FetchWrite::dangling(),
+            thread: None,
+        }
+    }
+
+    fn access(archetype: &Archetype) -> Option<Access> {
+        FetchWrite::<NonSendCell<T>>::access(archetype)
+    }
+
+    fn borrow(archetype: &Archetype) {
+        FetchWrite::<NonSendCell<T>>::borrow(archetype)
+    }
+    fn new(archetype: &'a Archetype) -> Option<Self> {
+        Some(Self {
+            fetch: FetchWrite::new(archetype)?,
+            thread: Some(thread::current().id()),
+        })
+    }
+    fn release(archetype: &Archetype) {
+        FetchWrite::<NonSendCell<T>>::release(archetype)
+    }
+
+    fn for_each_borrow(f: impl FnMut(TypeId, bool)) {
+        FetchWrite::<NonSendCell<T>>::for_each_borrow(f);
+    }
+
+    unsafe fn get(&self, n: usize) -> &'a mut T {
+        let cell = <FetchWrite<NonSendCell<T>> as Fetch<'a>>::get(&self.fetch, n);
+        cell.check(self.thread.unwrap());
+        &mut cell.value
+    }
+
+    unsafe fn should_skip(&self, n: usize) -> bool {
+        <FetchWrite<NonSendCell<T>> as Fetch<'a>>::should_skip(&self.fetch, n)
+    }
+
+    fn filters_entities(&self) -> bool {
+        <FetchWrite<NonSendCell<T>> as Fetch<'a>>::filters_entities(&self.fetch)
+    }
+}
+
+impl World {
+    /// Add `component` to `entity`, bound to the current thread, replacing any existing `T`
+    ///
+    /// Unlike ordinary components, `T` need not be `Send` or `Sync`. Such components are accessed
+    /// through [`NonSend`] queries, and are not visible to ordinary queries or `get`.
+    pub fn insert_non_send<T: 'static>(
+        &mut self,
+        entity: Entity,
+        component: T,
+    ) -> Result<(), NoSuchEntity> {
+        self.insert_one(entity, NonSendCell::new(component))
+    }
+
+    /// Remove the `T` component inserted into `entity` by `insert_non_send`
+    ///
+    /// # Panics
+    ///
+    /// Panics if the component was inserted on another thread, in which case it's leaked.
+    pub fn remove_non_send<T: 'static>(&mut self, entity: Entity) -> Result<T, ComponentError> {
+        self.remove_one::<NonSendCell<T>>(entity)
+            .map(NonSendCell::into_inner)
+    }
+}
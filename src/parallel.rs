@@ -0,0 +1,155 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use core::marker::PhantomData;
+
+use rayon::iter::plumbing::{bridge_unindexed, Folder, UnindexedConsumer, UnindexedProducer};
+use rayon::iter::ParallelIterator;
+
+use crate::archetype::Archetype;
+use crate::disabled::EnabledFetch;
+use crate::entities::EntityMeta;
+use crate::query::Fetch;
+use crate::{Entity, Query, QueryItem};
+
+/// Parallel iterator over the set of entities with the components in `Q`
+///
+/// Work is divided between archetypes first, then within large archetypes, so that every thread
+/// visits contiguous runs of components.
+pub struct QueryParIter<'q, Q: Query> {
+    producer: Producer<'q, Q>,
+}
+
+impl<'q, Q: Query> QueryParIter<'q, Q> {
+    /// # Safety
+    ///
+    /// `'q` must be sufficient to guarantee that `Q` cannot violate borrow safety, either with
+    /// dynamic borrow checks or by representing exclusive access to the `World`.
+    pub(crate) unsafe fn new(meta: &'q [EntityMeta], archetypes: &'q [Archetype]) -> Self {
+        Self {
+            producer: Producer {
+                meta,
+                archetypes,
+                start: 0,
+                end: archetypes.last().map_or(0, |x| x.len()),
+                _marker: PhantomData,
+            },
+        }
+    }
+}
+
+impl<'q, Q: Query> ParallelIterator for QueryParIter<'q, Q>
+where
+    QueryItem<'q, Q>: Send,
+{
+    type Item = (Entity, QueryItem<'q, Q>);
+
+    fn drive_unindexed<C>(self, consumer: C) -> C::Result
+    where
+        C: UnindexedConsumer<Self::Item>,
+    {
+        bridge_unindexed(self.producer, consumer)
+    }
+}
+
+/// A contiguous range of entities, starting at index `start` of the first archetype and ending
+/// just before index `end` of the last
+struct Producer<'q, Q: Query> {
+    meta: &'q [EntityMeta],
+    archetypes: &'q [Archetype],
+    start: u32,
+    end: u32,
+    _marker: PhantomData<Q>,
+}
+
+// Safe because every split of the range is disjoint, so the borrow rules established when the
+// iterator was constructed hold across threads just as they do for a single `QueryIter`.
+unsafe impl<Q: Query> Send for Producer<'_, Q> {}
+
+impl<'q, Q: Query> UnindexedProducer for Producer<'q, Q>
+where
+    QueryItem<'q, Q>: Send,
+{
+    type Item = (Entity, QueryItem<'q, Q>);
+
+    fn split(self) -> (Self, Option<Self>) {
+        match self.archetypes.len() {
+            0 => (self, None),
+            1 => {
+                let len = self.end - self.start;
+                if len < 2 || Q::Fetch::access(&self.archetypes[0]).is_none() {
+                    return (self, None);
+                }
+                let mid = self.start + len / 2;
+                (
+                    Self {
+                        end: mid,
+                        ..self.clone()
+                    },
+                    Some(Self { start: mid, ..self }),
+                )
+            }
+            n => {
+                let (left, right) = self.archetypes.split_at(n / 2);
+                (
+                    Self {
+                        archetypes: left,
+                        end: left[left.len() - 1].len(),
+                        ..self.clone()
+                    },
+                    Some(Self {
+                        archetypes: right,
+                        start: 0,
+                        ..self
+                    }),
+                )
+            }
+        }
+    }
+
+    fn fold_with<F>(self, mut folder: F) -> F
+    where
+        F: Folder<Self::Item>,
+    {
+        let last = self.archetypes.len().wrapping_sub(1);
+        for (i, archetype) in self.archetypes.iter().enumerate() {
+            let fetch = match EnabledFetch::<Q::Fetch>::new(archetype) {
+                Some(x) => x,
+                None => continue,
+            };
+            let start = if i == 0 { self.start } else { 0 };
+            let end = if i == last { self.end } else { archetype.len() };
+            for index in start..end {
+                if unsafe { fetch.should_skip(index as usize) } {
+                    continue;
+                }
+                let id = archetype.entity_id(index);
+                let entity = Entity {
+                    id,
+                    generation: self.meta[id as usize].generation,
+                };
+                folder = folder.consume((entity, unsafe { fetch.get(index as usize) }));
+                if folder.full() {
+                    return folder;
+                }
+            }
+        }
+        folder
+    }
+}
+
+impl<Q: Query> Clone for Producer<'_, Q> {
+    fn clone(&self) -> Self {
+        Self {
+            meta: self.meta,
+            archetypes: self.archetypes,
+            start: self.start,
+            end: self.end,
+            _marker: PhantomData,
+        }
+    }
+}
@@ -0,0 +1,149 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use crate::alloc::boxed::Box;
+use crate::alloc::vec::Vec;
+use core::any::{Any, TypeId};
+
+use crate::archetype::{TypeIdMap, TypeInfo};
+use crate::Component;
+
+/// Occupancy of a component pool, as reported by [`World::pool_stats`](crate::World::pool_stats)
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct PoolStats {
+    pub(crate) pooled: usize,
+    pub(crate) capacity: usize,
+    pub(crate) recycled: u64,
+    pub(crate) reused: u64,
+    pub(crate) dropped: u64,
+}
+
+impl PoolStats {
+    /// Number of components currently held for reuse
+    pub fn pooled(&self) -> usize {
+        self.pooled
+    }
+
+    /// Largest number of components the pool will hold
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Number of components the world moved into the pool rather than dropping
+    pub fn recycled(&self) -> u64 {
+        self.recycled
+    }
+
+    /// Number of components taken back out of the pool with `World::take_pooled`
+    pub fn reused(&self) -> u64 {
+        self.reused
+    }
+
+    /// Number of components dropped because the pool was full
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+}
+
+/// Components of type `T` that left a `World`, kept so their heap allocations may be reused
+struct ComponentPool<T> {
+    values: Vec<T>,
+    stats: PoolStats,
+}
+
+/// A `ComponentPool` of any type
+trait AnyPool: Send + Sync {
+    /// Move the component at `ptr` into the pool, or drop it if the pool is full
+    unsafe fn recycle(&mut self, ptr: *mut u8);
+
+    fn stats(&self) -> PoolStats;
+
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<T: Component> AnyPool for ComponentPool<T> {
+    unsafe fn recycle(&mut self, ptr: *mut u8) {
+        let value = ptr.cast::<T>().read();
+        if self.values.len() < self.stats.capacity {
+            self.values.push(value);
+            self.stats.recycled += 1;
+        } else {
+            self.stats.dropped += 1;
+        }
+    }
+
+    fn stats(&self) -> PoolStats {
+        PoolStats {
+            pooled: self.values.len(),
+            ..self.stats
+        }
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+/// The pools of every component type registered with `World::register_pool`
+#[derive(Default)]
+pub(crate) struct Pools(TypeIdMap<Box<dyn AnyPool>>);
+
+impl Pools {
+    pub(crate) fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub(crate) fn contains(&self, ty: TypeId) -> bool {
+        self.0.contains_key(&ty)
+    }
+
+    /// Begin pooling up to `capacity` components of type `T`, keeping any already pooled
+    pub(crate) fn register<T: Component>(&mut self, capacity: usize) {
+        let pool = self.0.entry(TypeId::of::<T>()).or_insert_with(|| {
+            Box::new(ComponentPool::<T> {
+                values: Vec::new(),
+                stats: PoolStats::default(),
+            })
+        });
+        let pool = pool
+            .as_any_mut()
+            .downcast_mut::<ComponentPool<T>>()
+            .unwrap();
+        pool.stats.capacity = capacity;
+        pool.values.truncate(capacity);
+        let len = pool.values.len();
+        pool.values.reserve_exact(capacity - len);
+    }
+
+    pub(crate) fn take<T: Component>(&mut self) -> Option<T> {
+        let pool = self
+            .0
+            .get_mut(&TypeId::of::<T>())?
+            .as_any_mut()
+            .downcast_mut::<ComponentPool<T>>()
+            .unwrap();
+        let value = pool.values.pop()?;
+        pool.stats.reused += 1;
+        Some(value)
+    }
+
+    pub(crate) fn stats(&self, ty: TypeId) -> Option<PoolStats> {
+        self.0.get(&ty).map(|x| x.stats())
+    }
+
+    /// Dispose of the component of type `ty` at `ptr`, moving it into its pool if it has one
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must point to a valid value of type `ty`, which must not be used after.
+    pub(crate) unsafe fn drop(&mut self, ty: &TypeInfo, ptr: *mut u8) {
+        match self.0.get_mut(&ty.id()) {
+            Some(pool) => pool.recycle(ptr),
+            None => ty.drop(ptr),
+        }
+    }
+}
@@ -0,0 +1,159 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use core::any::TypeId;
+use std::sync::Mutex;
+use std::vec::Vec;
+
+use crate::archetype::{Archetype, TypeIdMap};
+use crate::{Fetch, Query, QueryAccess};
+
+/// Statistics on the queries run against a `World`, accumulated until taken with `dump`
+///
+/// Obtained from [`World::profiler`](crate::World::profiler). Every query constructed by
+/// `World::query`, `World::query_mut`, and their relatives, including `PreparedQuery`, is
+/// recorded once, whether or not it's iterated. Requires the `profile` feature.
+///
+/// # Example
+/// ```
+/// # use hecs::*;
+/// # use core::any::TypeId;
+/// let mut world = World::new();
+/// world.spawn((1, true));
+/// world.spawn((2,));
+/// world.query::<&i32>().iter().count();
+/// world.query_mut::<(&mut i32, &bool)>();
+/// let frame = world.profiler().dump();
+/// assert_eq!(frame.queries(), 2);
+/// assert_eq!(frame.entities_matched(), 3);
+/// let ints = frame.component(TypeId::of::<i32>()).unwrap();
+/// assert_eq!((ints.reads(), ints.writes()), (1, 1));
+/// assert_eq!(world.profiler().dump().queries(), 0);
+/// ```
+#[derive(Default)]
+pub struct WorldProfiler {
+    frame: Mutex<FrameData>,
+}
+
+#[derive(Default)]
+struct FrameData {
+    queries: u64,
+    archetypes_scanned: u64,
+    archetypes_matched: u64,
+    entities_matched: u64,
+    /// Number of queries reading and writing each component type
+    components: TypeIdMap<(u64, u64)>,
+}
+
+impl WorldProfiler {
+    /// Account for the construction of a `Q` query over `archetypes`
+    pub(crate) fn record<Q: Query>(&self, archetypes: &[Archetype]) {
+        let access = QueryAccess::of::<Q>();
+        let (matched, entities) = archetypes
+            .iter()
+            .filter(|x| Q::Fetch::access(x).is_some())
+            .fold((0, 0), |(n, len), x| (n + 1, len + u64::from(x.len())));
+        let mut frame = self.frame.lock().unwrap();
+        frame.queries += 1;
+        frame.archetypes_scanned += archetypes.len() as u64;
+        frame.archetypes_matched += matched;
+        frame.entities_matched += entities;
+        for &ty in access.reads() {
+            frame.components.entry(ty).or_default().0 += 1;
+        }
+        for &ty in access.writes() {
+            frame.components.entry(ty).or_default().1 += 1;
+        }
+    }
+
+    /// Take the statistics accumulated since the last call, e.g. once per frame
+    pub fn dump(&self) -> ProfileFrame {
+        let frame = core::mem::take(&mut *self.frame.lock().unwrap());
+        let mut components = frame
+            .components
+            .into_iter()
+            .map(|(ty, (reads, writes))| ComponentProfile { ty, reads, writes })
+            .collect::<Vec<_>>();
+        components.sort_unstable_by_key(|x| core::cmp::Reverse(x.reads + x.writes));
+        ProfileFrame {
+            queries: frame.queries,
+            archetypes_scanned: frame.archetypes_scanned,
+            archetypes_matched: frame.archetypes_matched,
+            entities_matched: frame.entities_matched,
+            components,
+        }
+    }
+}
+
+/// Query statistics taken by [`WorldProfiler::dump`]
+#[derive(Debug, Clone)]
+pub struct ProfileFrame {
+    queries: u64,
+    archetypes_scanned: u64,
+    archetypes_matched: u64,
+    entities_matched: u64,
+    components: Vec<ComponentProfile>,
+}
+
+impl ProfileFrame {
+    /// Number of queries constructed
+    pub fn queries(&self) -> u64 {
+        self.queries
+    }
+
+    /// Total number of archetypes that queries had to consider
+    pub fn archetypes_scanned(&self) -> u64 {
+        self.archetypes_scanned
+    }
+
+    /// Total number of archetypes that queries matched
+    pub fn archetypes_matched(&self) -> u64 {
+        self.archetypes_matched
+    }
+
+    /// Total number of entities in the archetypes that queries matched
+    ///
+    /// Includes entities skipped individually, e.g. by `Changed`.
+    pub fn entities_matched(&self) -> u64 {
+        self.entities_matched
+    }
+
+    /// Accesses of each component type, most accessed first
+    pub fn components(&self) -> &[ComponentProfile] {
+        &self.components
+    }
+
+    /// Accesses of the component type `ty`, if any query accessed it
+    pub fn component(&self, ty: TypeId) -> Option<&ComponentProfile> {
+        self.components.iter().find(|x| x.ty == ty)
+    }
+}
+
+/// How many queries accessed a component type, as reported by [`ProfileFrame::components`]
+#[derive(Debug, Clone)]
+pub struct ComponentProfile {
+    ty: TypeId,
+    reads: u64,
+    writes: u64,
+}
+
+impl ComponentProfile {
+    /// The component type
+    pub fn type_id(&self) -> TypeId {
+        self.ty
+    }
+
+    /// Number of queries that only read the component
+    pub fn reads(&self) -> u64 {
+        self.reads
+    }
+
+    /// Number of queries that wrote the component
+    pub fn writes(&self) -> u64 {
+        self.writes
+    }
+}
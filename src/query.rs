@@ -5,14 +5,31 @@
 // http://opensource.org/licenses/MIT>, at your option. This file may not be
 // copied, modified, or distributed except according to those terms.
 
+use core::any::TypeId;
+use core::fmt;
 use core::marker::PhantomData;
 use core::ptr::NonNull;
+use core::slice;
+use core::sync::atomic::{AtomicU32, Ordering};
 
+use crate::alloc::borrow::Cow;
+use crate::alloc::vec::{self, Vec};
 use crate::archetype::Archetype;
+use crate::borrow::ColumnEntities;
+use crate::disabled::EnabledFetch;
 use crate::entities::EntityMeta;
-use crate::{Component, Entity};
+#[cfg(feature = "parallel")]
+use crate::parallel::QueryParIter;
+use crate::sparse::SparseFetch;
+use crate::{Bundle, Component, Entity, EntityId, World};
+
+#[cfg(feature = "std")]
+use std::error::Error;
 
 /// A collection of component types to fetch from a `World`
+///
+/// Implemented for tuples of up to 24 queries. Since a tuple of queries is itself a query, larger
+/// sets may be fetched by nesting tuples, e.g. `((&A, &B), (&C, &mut D))`.
 pub trait Query {
     #[doc(hidden)]
     type Fetch: for<'a> Fetch<'a>;
@@ -24,6 +41,11 @@ pub trait Query {
 pub type QueryItem<'a, Q> = <<Q as Query>::Fetch as Fetch<'a>>::Item;
 
 /// Streaming iterators over contiguous homogeneous ranges of components
+///
+/// # Safety
+///
+/// `access` must report every component type that `borrow`, `new`, and `get` touch, and `get` must
+/// not produce references that alias in ways the reported `Access` does not permit.
 pub unsafe trait Fetch<'a>: Sized {
     /// Type of value to be fetched
     type Item;
@@ -41,6 +63,9 @@ pub unsafe trait Fetch<'a>: Sized {
     /// Release dynamic borrows acquired by `borrow`
     fn release(archetype: &Archetype);
 
+    /// Invoke `f` for every component type that may be borrowed and whether the borrow is unique
+    fn for_each_borrow(f: impl FnMut(TypeId, bool));
+
     /// Access the `n`th item in this archetype without bounds checking
     ///
     /// # Safety
@@ -49,8 +74,51 @@ pub unsafe trait Fetch<'a>: Sized {
     /// - Bounds-checking must be performed externally
     /// - Any resulting borrows must be legal (e.g. no &mut to something another iterator might access)
     unsafe fn get(&self, n: usize) -> Self::Item;
+
+    /// Whether the `n`th item in this archetype should be passed over rather than fetched
+    ///
+    /// # Safety
+    /// - Bounds-checking must be performed externally
+    #[inline]
+    unsafe fn should_skip(&self, n: usize) -> bool {
+        let _ = n;
+        false
+    }
+
+    /// Whether `should_skip` may pass over any entity of this archetype
+    ///
+    /// Must be `true` if `should_skip` can ever return `true`. Lets entities be counted a whole
+    /// archetype at a time when no per-entity filtering is needed.
+    #[inline]
+    fn filters_entities(&self) -> bool {
+        false
+    }
+
+    /// Whether entities hidden by `World::disable` are visited, as by `Including<Disabled, _>`
+    fn includes_disabled() -> bool {
+        false
+    }
+}
+
+/// A `Fetch` whose items for a range of entities can be borrowed as contiguous slices
+///
+/// # Safety
+///
+/// `slice` must not produce references that alias in ways the reported `Access` does not permit.
+pub unsafe trait SliceFetch<'a>: Fetch<'a> {
+    /// Type of the slices yielded for each range of entities
+    type Slice;
+
+    /// Access items `start..start + len` of this archetype without bounds checking
+    ///
+    /// # Safety
+    /// - As for `Fetch::get`, for every item in the range
+    unsafe fn slice(&self, start: usize, len: usize) -> Self::Slice;
 }
 
+/// Type of slices yielded by `QueryBorrow::iter_slices`
+pub type QuerySlice<'a, Q> = <<Q as Query>::Fetch as SliceFetch<'a>>::Slice;
+
 /// Type of access a `Query` may have to an `Archetype`
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd)]
 pub enum Access {
@@ -62,22 +130,116 @@ pub enum Access {
     Write,
 }
 
-impl<'a, T: Component> Query for &'a T {
+/// The component types a `Query` may borrow, independent of any particular archetype
+///
+/// Useful for scheduling systems: two queries whose accesses are compatible can run concurrently
+/// without risking a borrow conflict.
+///
+/// # Example
+/// ```
+/// # use hecs::*;
+/// let a = QueryAccess::of::<(&i32, &mut bool)>();
+/// let b = QueryAccess::of::<&i32>();
+/// let c = QueryAccess::of::<With<i32, &bool>>();
+/// assert!(a.is_compatible(&b));
+/// assert!(!a.is_compatible(&c));
+/// ```
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct QueryAccess {
+    reads: Vec<TypeId>,
+    writes: Vec<TypeId>,
+}
+
+impl QueryAccess {
+    /// Compute the access of `Q`
+    pub fn of<Q: Query>() -> Self {
+        let mut reads = Vec::new();
+        let mut writes = Vec::new();
+        Q::Fetch::for_each_borrow(|id, unique| {
+            if unique {
+                writes.push(id);
+            } else {
+                reads.push(id);
+            }
+        });
+        writes.sort_unstable();
+        writes.dedup();
+        reads.sort_unstable();
+        reads.dedup();
+        reads.retain(|x| writes.binary_search(x).is_err());
+        Self { reads, writes }
+    }
+
+    /// Component types that are only read, in sorted order
+    pub fn reads(&self) -> &[TypeId] {
+        &self.reads
+    }
+
+    /// Component types that are written, in sorted order
+    pub fn writes(&self) -> &[TypeId] {
+        &self.writes
+    }
+
+    /// Record a borrow of `id`, unique if `unique` is set
+    #[cfg(feature = "parallel")]
+    pub(crate) fn insert(&mut self, id: TypeId, unique: bool) {
+        if unique {
+            if let Ok(i) = self.reads.binary_search(&id) {
+                self.reads.remove(i);
+            }
+            if let Err(i) = self.writes.binary_search(&id) {
+                self.writes.insert(i, id);
+            }
+        } else if self.writes.binary_search(&id).is_err() {
+            if let Err(i) = self.reads.binary_search(&id) {
+                self.reads.insert(i, id);
+            }
+        }
+    }
+
+    /// Record every borrow made with `other`
+    #[cfg(feature = "parallel")]
+    pub(crate) fn extend(&mut self, other: &QueryAccess) {
+        for &id in &other.writes {
+            self.insert(id, true);
+        }
+        for &id in &other.reads {
+            self.insert(id, false);
+        }
+    }
+
+    /// Whether a query with this access can run concurrently with one having `other`
+    pub fn is_compatible(&self, other: &QueryAccess) -> bool {
+        let disjoint = |x: &[TypeId], y: &[TypeId]| x.iter().all(|t| y.binary_search(t).is_err());
+        disjoint(&self.writes, &other.writes)
+            && disjoint(&self.writes, &other.reads)
+            && disjoint(&self.reads, &other.writes)
+    }
+}
+
+impl<T: Component> Query for &'_ T {
     type Fetch = FetchRead<T>;
 }
 
 #[doc(hidden)]
-pub struct FetchRead<T>(NonNull<T>);
+pub struct FetchRead<T> {
+    components: NonNull<T>,
+    /// Set if `T` is stored sparsely rather than in a column
+    sparse: Option<SparseFetch>,
+}
 
 unsafe impl<'a, T: Component> Fetch<'a> for FetchRead<T> {
     type Item = &'a T;
 
     fn dangling() -> Self {
-        Self(NonNull::dangling())
+        Self {
+            components: NonNull::dangling(),
+            sparse: None,
+        }
     }
 
     fn access(archetype: &Archetype) -> Option<Access> {
-        if archetype.has::<T>() {
+        if archetype.has::<T>() || archetype.sparse(TypeId::of::<T>()).is_some() {
             Some(Access::Read)
         } else {
             None
@@ -88,33 +250,71 @@ unsafe impl<'a, T: Component> Fetch<'a> for FetchRead<T> {
         archetype.borrow::<T>();
     }
     fn new(archetype: &'a Archetype) -> Option<Self> {
-        archetype.get::<T>().map(Self)
+        Some(match archetype.get::<T>() {
+            Some(components) => Self {
+                components,
+                sparse: None,
+            },
+            None => Self {
+                components: NonNull::dangling(),
+                sparse: Some(SparseFetch::new(archetype, TypeId::of::<T>())?),
+            },
+        })
     }
     fn release(archetype: &Archetype) {
         archetype.release::<T>();
     }
 
+    fn for_each_borrow(mut f: impl FnMut(TypeId, bool)) {
+        f(TypeId::of::<T>(), false);
+    }
+
     unsafe fn get(&self, n: usize) -> Self::Item {
-        &*self.0.as_ptr().add(n)
+        match self.sparse {
+            None => &*self.components.as_ptr().add(n),
+            Some(ref sparse) => {
+                let row = sparse.row(n).unwrap();
+                &*sparse.set().component(row).as_ptr().cast::<T>()
+            }
+        }
+    }
+
+    unsafe fn should_skip(&self, n: usize) -> bool {
+        self.sparse.as_ref().is_some_and(|x| x.row(n).is_none())
+    }
+
+    fn filters_entities(&self) -> bool {
+        self.sparse.is_some()
     }
 }
 
-impl<'a, T: Component> Query for &'a mut T {
+impl<T: Component> Query for &'_ mut T {
     type Fetch = FetchWrite<T>;
 }
 
 #[doc(hidden)]
-pub struct FetchWrite<T>(NonNull<T>);
+pub struct FetchWrite<T> {
+    components: NonNull<T>,
+    mutated: NonNull<AtomicU32>,
+    tick: u32,
+    /// Set if `T` is stored sparsely rather than in a column
+    sparse: Option<SparseFetch>,
+}
 
 unsafe impl<'a, T: Component> Fetch<'a> for FetchWrite<T> {
     type Item = &'a mut T;
 
     fn dangling() -> Self {
-        Self(NonNull::dangling())
+        Self {
+            components: NonNull::dangling(),
+            mutated: NonNull::dangling(),
+            tick: 0,
+            sparse: None,
+        }
     }
 
     fn access(archetype: &Archetype) -> Option<Access> {
-        if archetype.has::<T>() {
+        if archetype.has::<T>() || archetype.sparse(TypeId::of::<T>()).is_some() {
             Some(Access::Write)
         } else {
             None
@@ -125,14 +325,173 @@ unsafe impl<'a, T: Component> Fetch<'a> for FetchWrite<T> {
         archetype.borrow_mut::<T>();
     }
     fn new(archetype: &'a Archetype) -> Option<Self> {
-        archetype.get::<T>().map(Self)
+        if !archetype.has::<T>() {
+            let sparse = SparseFetch::new(archetype, TypeId::of::<T>())?;
+            return Some(Self {
+                tick: sparse.set().tick(),
+                sparse: Some(sparse),
+                ..Self::dangling()
+            });
+        }
+        Some(Self {
+            components: archetype.get::<T>()?,
+            mutated: archetype.mutated::<T>()?,
+            tick: archetype.tick(),
+            sparse: None,
+        })
     }
     fn release(archetype: &Archetype) {
         archetype.release_mut::<T>();
     }
 
+    fn for_each_borrow(mut f: impl FnMut(TypeId, bool)) {
+        f(TypeId::of::<T>(), true);
+    }
+
+    unsafe fn get(&self, n: usize) -> Self::Item {
+        let (component, mutated) = self.get_untracked(n);
+        mutated.store(self.tick, Ordering::Relaxed);
+        component
+    }
+
+    unsafe fn should_skip(&self, n: usize) -> bool {
+        self.sparse.as_ref().is_some_and(|x| x.row(n).is_none())
+    }
+
+    fn filters_entities(&self) -> bool {
+        self.sparse.is_some()
+    }
+}
+
+impl<T: Component> FetchWrite<T> {
+    /// Access the `n`th component and its mutation tick, without marking it as changed
+    unsafe fn get_untracked<'a>(&self, n: usize) -> (&'a mut T, &'a AtomicU32) {
+        if let Some(ref sparse) = self.sparse {
+            let row = sparse.row(n).unwrap();
+            let set = sparse.set();
+            return (
+                &mut *set.component(row).as_ptr().cast::<T>(),
+                &*set.mutated(row).as_ptr(),
+            );
+        }
+        (
+            &mut *self.components.as_ptr().add(n),
+            &*self.mutated.as_ptr().add(n),
+        )
+    }
+}
+
+/// Query for uniquely borrowing `T` components, only marking those actually modified as changed
+///
+/// Items dereference to `T`, and count as changed for `Changed` only once mutably dereferenced,
+/// whereas every item of a `&mut T` query counts as changed. Useful where a system writes to
+/// only some of the components it visits, especially with `Option<Mut<T>>`.
+///
+/// # Example
+/// ```
+/// # use hecs::*;
+/// let mut world = World::new();
+/// let a = world.spawn((1, 1.0f32));
+/// let b = world.spawn((2,));
+/// world.clear_trackers();
+/// for (_, (&x, y)) in world.query_mut::<(&i32, Option<Mut<f32>>)>() {
+///     if let Some(mut y) = y {
+///         if x > 1 {
+///             *y += 1.0;
+///         }
+///     }
+/// }
+/// assert_eq!(world.query::<Changed<f32, ()>>().iter().count(), 0);
+/// for (_, mut x) in world.query_mut::<Mut<i32>>() {
+///     if *x == 2 {
+///         *x = 3;
+///     }
+/// }
+/// let changed = world.query::<Changed<i32, ()>>().iter().map(|(e, _)| e).collect::<Vec<_>>();
+/// assert_eq!(changed, [b]);
+/// ```
+pub struct Mut<'a, T> {
+    component: &'a mut T,
+    mutated: &'a AtomicU32,
+    tick: u32,
+}
+
+impl<'a, T> Mut<'a, T> {
+    /// Mark the component as changed and borrow it for the rest of its lifetime
+    pub fn into_inner(self) -> &'a mut T {
+        self.mutated.store(self.tick, Ordering::Relaxed);
+        self.component
+    }
+}
+
+impl<T> core::ops::Deref for Mut<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.component
+    }
+}
+
+impl<T> core::ops::DerefMut for Mut<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.mutated.store(self.tick, Ordering::Relaxed);
+        self.component
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Mut<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.component.fmt(f)
+    }
+}
+
+impl<T: Component> Query for Mut<'_, T> {
+    type Fetch = FetchMut<T>;
+}
+
+#[doc(hidden)]
+pub struct FetchMut<T>(FetchWrite<T>);
+
+unsafe impl<'a, T: Component> Fetch<'a> for FetchMut<T> {
+    type Item = Mut<'a, T>;
+
+    fn dangling() -> Self {
+        Self(FetchWrite::dangling())
+    }
+
+    fn access(archetype: &Archetype) -> Option<Access> {
+        FetchWrite::<T>::access(archetype)
+    }
+
+    fn borrow(archetype: &Archetype) {
+        FetchWrite::<T>::borrow(archetype)
+    }
+    fn new(archetype: &'a Archetype) -> Option<Self> {
+        FetchWrite::new(archetype).map(Self)
+    }
+    fn release(archetype: &Archetype) {
+        FetchWrite::<T>::release(archetype)
+    }
+
+    fn for_each_borrow(f: impl FnMut(TypeId, bool)) {
+        FetchWrite::<T>::for_each_borrow(f)
+    }
+
     unsafe fn get(&self, n: usize) -> Self::Item {
-        &mut *self.0.as_ptr().add(n)
+        let (component, mutated) = self.0.get_untracked(n);
+        Mut {
+            component,
+            mutated,
+            tick: self.0.tick,
+        }
+    }
+
+    unsafe fn should_skip(&self, n: usize) -> bool {
+        self.0.should_skip(n)
+    }
+
+    fn filters_entities(&self) -> bool {
+        self.0.filters_entities()
     }
 }
 
@@ -164,297 +523,2468 @@ unsafe impl<'a, T: Fetch<'a>> Fetch<'a> for TryFetch<T> {
         T::release(archetype)
     }
 
+    fn for_each_borrow(f: impl FnMut(TypeId, bool)) {
+        T::for_each_borrow(f);
+    }
+
     unsafe fn get(&self, n: usize) -> Option<T::Item> {
-        Some(self.0.as_ref()?.get(n))
+        let fetch = self.0.as_ref()?;
+        if fetch.should_skip(n) {
+            return None;
+        }
+        Some(fetch.get(n))
+    }
+
+    fn includes_disabled() -> bool {
+        T::includes_disabled()
     }
 }
 
-/// Query transformer skipping entities that have a `T` component
+/// Query that yields each entity's `T` component, or `T::default()` for entities lacking one
 ///
-/// See also `QueryBorrow::without`.
+/// Matches every entity, like `Option<&T>`. A default value is constructed for each entity
+/// lacking a `T` each time it's visited, so best suited to components that are cheap to construct.
 ///
 /// # Example
 /// ```
 /// # use hecs::*;
+/// #[derive(Clone, Default)]
+/// struct Speed(f32);
 /// let mut world = World::new();
-/// let a = world.spawn((123, true, "abc"));
-/// let b = world.spawn((456, false));
-/// let c = world.spawn((42, "def"));
-/// let entities = world.query::<Without<bool, &i32>>()
+/// world.spawn((Speed(2.0), 1u8));
+/// world.spawn((1u8,));
+/// let total = world
+///     .query::<OrDefault<&Speed>>()
 ///     .iter()
-///     .map(|(e, &i)| (e, i))
-///     .collect::<Vec<_>>();
-/// assert_eq!(entities, &[(c, 42)]);
+///     .map(|(_, speed)| speed.0)
+///     .sum::<f32>();
+/// assert_eq!(total, 2.0);
 /// ```
-pub struct Without<T, Q>(PhantomData<(Q, fn(T))>);
+pub struct OrDefault<Q>(PhantomData<fn(Q)>);
 
-impl<T: Component, Q: Query> Query for Without<T, Q> {
-    type Fetch = FetchWithout<T, Q::Fetch>;
+impl<T: Component + Clone + Default> Query for OrDefault<&'_ T> {
+    type Fetch = FetchOrDefault<T>;
 }
 
 #[doc(hidden)]
-pub struct FetchWithout<T, F>(F, PhantomData<fn(T)>);
+pub struct FetchOrDefault<T>(TryFetch<FetchRead<T>>);
 
-unsafe impl<'a, T: Component, F: Fetch<'a>> Fetch<'a> for FetchWithout<T, F> {
-    type Item = F::Item;
+unsafe impl<'a, T: Component + Clone + Default> Fetch<'a> for FetchOrDefault<T> {
+    type Item = Cow<'a, T>;
 
     fn dangling() -> Self {
-        Self(F::dangling(), PhantomData)
+        Self(TryFetch::dangling())
     }
 
     fn access(archetype: &Archetype) -> Option<Access> {
-        if archetype.has::<T>() {
-            None
-        } else {
-            F::access(archetype)
-        }
+        TryFetch::<FetchRead<T>>::access(archetype)
     }
 
     fn borrow(archetype: &Archetype) {
-        F::borrow(archetype)
+        TryFetch::<FetchRead<T>>::borrow(archetype)
     }
     fn new(archetype: &'a Archetype) -> Option<Self> {
-        if archetype.has::<T>() {
-            return None;
-        }
-        Some(Self(F::new(archetype)?, PhantomData))
+        TryFetch::new(archetype).map(Self)
     }
     fn release(archetype: &Archetype) {
-        F::release(archetype)
+        TryFetch::<FetchRead<T>>::release(archetype)
     }
 
-    unsafe fn get(&self, n: usize) -> F::Item {
-        self.0.get(n)
+    fn for_each_borrow(f: impl FnMut(TypeId, bool)) {
+        TryFetch::<FetchRead<T>>::for_each_borrow(f);
+    }
+
+    unsafe fn get(&self, n: usize) -> Cow<'a, T> {
+        match self.0.get(n) {
+            Some(x) => Cow::Borrowed(x),
+            None => Cow::Owned(T::default()),
+        }
     }
 }
 
-/// Query transformer skipping entities that do not have a `T` component
+/// Query that matches entities satisfying either or both of two queries
 ///
-/// See also `QueryBorrow::with`.
+/// Yields which of the queries matched. Nest `Or`s to combine more than two queries.
 ///
 /// # Example
 /// ```
 /// # use hecs::*;
 /// let mut world = World::new();
-/// let a = world.spawn((123, true, "abc"));
-/// let b = world.spawn((456, false));
-/// let c = world.spawn((42, "def"));
-/// let entities = world.query::<With<bool, &i32>>()
+/// let a = world.spawn((123, true));
+/// let b = world.spawn((456,));
+/// let c = world.spawn((false, 'x'));
+/// let d = world.spawn(('y',));
+/// let mut entities = world
+///     .query::<Or<&i32, &bool>>()
 ///     .iter()
-///     .map(|(e, &i)| (e, i))
+///     .map(|(e, x)| (e, x.map(|&i| i, |&b| b)))
 ///     .collect::<Vec<_>>();
-/// assert_eq!(entities.len(), 2);
-/// assert!(entities.contains(&(a, 123)));
-/// assert!(entities.contains(&(b, 456)));
+/// entities.sort_by_key(|(e, _)| *e);
+/// assert_eq!(entities, [
+///     (a, Or::Both(123, true)),
+///     (b, Or::Left(456)),
+///     (c, Or::Right(false)),
+/// ]);
 /// ```
-pub struct With<T, Q>(PhantomData<(Q, fn(T))>);
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum Or<L, R> {
+    /// Only the left query matched
+    Left(L),
+    /// Only the right query matched
+    Right(R),
+    /// Both queries matched
+    Both(L, R),
+}
 
-impl<T: Component, Q: Query> Query for With<T, Q> {
-    type Fetch = FetchWith<T, Q::Fetch>;
+impl<L, R> Or<L, R> {
+    /// Construct an `Or` from the results of two queries, if either matched
+    pub fn new(l: Option<L>, r: Option<R>) -> Option<Self> {
+        match (l, r) {
+            (None, None) => None,
+            (Some(l), None) => Some(Or::Left(l)),
+            (None, Some(r)) => Some(Or::Right(r)),
+            (Some(l), Some(r)) => Some(Or::Both(l, r)),
+        }
+    }
+
+    /// Destructure into the results of the two queries
+    pub fn split(self) -> (Option<L>, Option<R>) {
+        match self {
+            Or::Left(l) => (Some(l), None),
+            Or::Right(r) => (None, Some(r)),
+            Or::Both(l, r) => (Some(l), Some(r)),
+        }
+    }
+
+    /// The result of the left query, if it matched
+    pub fn left(self) -> Option<L> {
+        self.split().0
+    }
+
+    /// The result of the right query, if it matched
+    pub fn right(self) -> Option<R> {
+        self.split().1
+    }
+
+    /// Transform the results of each query
+    pub fn map<L1, R1>(self, f: impl FnOnce(L) -> L1, g: impl FnOnce(R) -> R1) -> Or<L1, R1> {
+        match self {
+            Or::Left(l) => Or::Left(f(l)),
+            Or::Right(r) => Or::Right(g(r)),
+            Or::Both(l, r) => Or::Both(f(l), g(r)),
+        }
+    }
+}
+
+impl<L: Query, R: Query> Query for Or<L, R> {
+    type Fetch = FetchOr<L::Fetch, R::Fetch>;
 }
 
 #[doc(hidden)]
-pub struct FetchWith<T, F>(F, PhantomData<fn(T)>);
+pub struct FetchOr<L, R> {
+    left: Option<L>,
+    right: Option<R>,
+}
 
-unsafe impl<'a, T: Component, F: Fetch<'a>> Fetch<'a> for FetchWith<T, F> {
-    type Item = F::Item;
+impl<L, R> FetchOr<L, R> {
+    /// Fetches for the queries matching the `n`th entity
+    unsafe fn matching<'a>(&self, n: usize) -> (Option<&L>, Option<&R>)
+    where
+        L: Fetch<'a>,
+        R: Fetch<'a>,
+    {
+        (
+            self.left.as_ref().filter(|x| !x.should_skip(n)),
+            self.right.as_ref().filter(|x| !x.should_skip(n)),
+        )
+    }
+}
+
+unsafe impl<'a, L: Fetch<'a>, R: Fetch<'a>> Fetch<'a> for FetchOr<L, R> {
+    type Item = Or<L::Item, R::Item>;
 
     fn dangling() -> Self {
-        Self(F::dangling(), PhantomData)
+        Self {
+            left: None,
+            right: None,
+        }
     }
 
     fn access(archetype: &Archetype) -> Option<Access> {
-        if archetype.has::<T>() {
-            F::access(archetype)
-        } else {
-            None
-        }
+        L::access(archetype).max(R::access(archetype))
     }
 
     fn borrow(archetype: &Archetype) {
-        F::borrow(archetype)
+        L::borrow(archetype);
+        R::borrow(archetype);
     }
     fn new(archetype: &'a Archetype) -> Option<Self> {
-        if !archetype.has::<T>() {
+        let (left, right) = (L::new(archetype), R::new(archetype));
+        if left.is_none() && right.is_none() {
             return None;
         }
-        Some(Self(F::new(archetype)?, PhantomData))
+        Some(Self { left, right })
     }
     fn release(archetype: &Archetype) {
-        F::release(archetype)
+        L::release(archetype);
+        R::release(archetype);
     }
 
-    unsafe fn get(&self, n: usize) -> F::Item {
-        self.0.get(n)
+    fn for_each_borrow(mut f: impl FnMut(TypeId, bool)) {
+        L::for_each_borrow(&mut f);
+        R::for_each_borrow(&mut f);
     }
-}
 
-/// A borrow of a `World` sufficient to execute the query `Q`
-///
-/// Note that borrows are not released until this object is dropped.
-pub struct QueryBorrow<'w, Q: Query> {
-    meta: &'w [EntityMeta],
-    archetypes: &'w [Archetype],
-    borrowed: bool,
-    _marker: PhantomData<Q>,
-}
+    unsafe fn get(&self, n: usize) -> Self::Item {
+        let (left, right) = self.matching(n);
+        Or::new(left.map(|x| x.get(n)), right.map(|x| x.get(n))).unwrap()
+    }
 
-impl<'w, Q: Query> QueryBorrow<'w, Q> {
-    pub(crate) fn new(meta: &'w [EntityMeta], archetypes: &'w [Archetype]) -> Self {
-        Self {
-            meta,
-            archetypes,
-            borrowed: false,
-            _marker: PhantomData,
-        }
+    unsafe fn should_skip(&self, n: usize) -> bool {
+        let (left, right) = self.matching(n);
+        left.is_none() && right.is_none()
     }
 
-    /// Execute the query
-    ///
-    /// Must be called only once per query.
-    // The lifetime narrowing here is required for soundness.
-    pub fn iter(&mut self) -> QueryIter<'_, Q> {
-        self.borrow();
-        unsafe { QueryIter::new(self.meta, self.archetypes) }
+    fn filters_entities(&self) -> bool {
+        // Entities are only passed over if neither side matches every one
+        let left_matches_all = self.left.as_ref().is_some_and(|x| !x.filters_entities());
+        let right_matches_all = self.right.as_ref().is_some_and(|x| !x.filters_entities());
+        !(left_matches_all || right_matches_all)
     }
 
-    /// Like `iter`, but returns child iterators of at most `batch_size` elements
-    ///
-    /// Useful for distributing work over a threadpool.
-    // The lifetime narrowing here is required for soundness.
-    pub fn iter_batched(&mut self, batch_size: u32) -> BatchedIter<'_, Q> {
-        self.borrow();
-        unsafe { BatchedIter::new(self.meta, self.archetypes, batch_size) }
+    fn includes_disabled() -> bool {
+        L::includes_disabled() || R::includes_disabled()
     }
+}
 
-    fn borrow(&mut self) {
+/// Query transformer skipping entities that have a `T` component
+///
+/// See also `QueryBorrow::without`.
+///
+/// # Example
+/// ```
+/// # use hecs::*;
+/// let mut world = World::new();
+/// let a = world.spawn((123, true, "abc"));
+/// let b = world.spawn((456, false));
+/// let c = world.spawn((42, "def"));
+/// let entities = world.query::<Without<bool, &i32>>()
+///     .iter()
+///     .map(|(e, &i)| (e, i))
+///     .collect::<Vec<_>>();
+/// assert_eq!(entities, &[(c, 42)]);
+/// ```
+pub struct Without<T, Q>(PhantomData<(Q, fn(T))>);
+
+impl<T: Component, Q: Query> Query for Without<T, Q> {
+    type Fetch = FetchWithout<T, Q::Fetch>;
+}
+
+#[doc(hidden)]
+pub struct FetchWithout<T, F>(F, Option<SparseFetch>, PhantomData<fn(T)>);
+
+unsafe impl<'a, T: Component, F: Fetch<'a>> Fetch<'a> for FetchWithout<T, F> {
+    type Item = F::Item;
+
+    fn dangling() -> Self {
+        Self(F::dangling(), None, PhantomData)
+    }
+
+    fn access(archetype: &Archetype) -> Option<Access> {
+        if archetype.has::<T>() {
+            None
+        } else {
+            F::access(archetype)
+        }
+    }
+
+    fn borrow(archetype: &Archetype) {
+        F::borrow(archetype)
+    }
+    fn new(archetype: &'a Archetype) -> Option<Self> {
+        if archetype.has::<T>() {
+            return None;
+        }
+        Some(Self(
+            F::new(archetype)?,
+            SparseFetch::new(archetype, TypeId::of::<T>()),
+            PhantomData,
+        ))
+    }
+    fn release(archetype: &Archetype) {
+        F::release(archetype)
+    }
+
+    fn for_each_borrow(f: impl FnMut(TypeId, bool)) {
+        F::for_each_borrow(f);
+    }
+
+    unsafe fn get(&self, n: usize) -> F::Item {
+        self.0.get(n)
+    }
+
+    unsafe fn should_skip(&self, n: usize) -> bool {
+        self.1.as_ref().is_some_and(|x| x.row(n).is_some()) || self.0.should_skip(n)
+    }
+
+    fn filters_entities(&self) -> bool {
+        self.1.is_some() || self.0.filters_entities()
+    }
+
+    fn includes_disabled() -> bool {
+        F::includes_disabled()
+    }
+}
+
+/// Query transformer skipping entities that have any of the components in the bundle `B`
+///
+/// Equivalent to nesting `Without` once for each component of `B`, e.g. `WithoutAny<(A, B), Q>`
+/// matches the same entities as `Without<A, Without<B, Q>>`. Note that `Without<(A, B), Q>`
+/// instead skips entities having a single component of tuple type `(A, B)`. See also
+/// `QueryBorrow::without_any`.
+///
+/// # Example
+/// ```
+/// # use hecs::*;
+/// struct Hidden;
+/// struct Culled;
+/// let mut world = World::new();
+/// let a = world.spawn((123, Hidden));
+/// let b = world.spawn((456, Culled));
+/// let c = world.spawn((42, "def"));
+/// let entities = world.query::<WithoutAny<(Hidden, Culled), &i32>>()
+///     .iter()
+///     .map(|(e, &i)| (e, i))
+///     .collect::<Vec<_>>();
+/// assert_eq!(entities, &[(c, 42)]);
+/// ```
+pub struct WithoutAny<B, Q>(PhantomData<(Q, fn(B))>);
+
+impl<B: Bundle, Q: Query> Query for WithoutAny<B, Q> {
+    type Fetch = FetchWithoutAny<B, Q::Fetch>;
+}
+
+#[doc(hidden)]
+pub struct FetchWithoutAny<B, F>(F, Vec<SparseFetch>, PhantomData<fn(B)>);
+
+impl<B: Bundle, F> FetchWithoutAny<B, F> {
+    fn excludes(archetype: &Archetype) -> bool {
+        B::with_static_ids(|ids| ids.iter().any(|&id| archetype.has_dynamic(id)))
+    }
+}
+
+unsafe impl<'a, B: Bundle, F: Fetch<'a>> Fetch<'a> for FetchWithoutAny<B, F> {
+    type Item = F::Item;
+
+    fn dangling() -> Self {
+        Self(F::dangling(), Vec::new(), PhantomData)
+    }
+
+    fn access(archetype: &Archetype) -> Option<Access> {
+        if Self::excludes(archetype) {
+            None
+        } else {
+            F::access(archetype)
+        }
+    }
+
+    fn borrow(archetype: &Archetype) {
+        F::borrow(archetype)
+    }
+    fn new(archetype: &'a Archetype) -> Option<Self> {
+        if Self::excludes(archetype) {
+            return None;
+        }
+        let sparse = B::with_static_ids(|ids| {
+            ids.iter()
+                .filter_map(|&id| SparseFetch::new(archetype, id))
+                .collect()
+        });
+        Some(Self(F::new(archetype)?, sparse, PhantomData))
+    }
+    fn release(archetype: &Archetype) {
+        F::release(archetype)
+    }
+
+    fn for_each_borrow(f: impl FnMut(TypeId, bool)) {
+        F::for_each_borrow(f);
+    }
+
+    unsafe fn get(&self, n: usize) -> F::Item {
+        self.0.get(n)
+    }
+
+    unsafe fn should_skip(&self, n: usize) -> bool {
+        self.1.iter().any(|x| x.row(n).is_some()) || self.0.should_skip(n)
+    }
+
+    fn filters_entities(&self) -> bool {
+        !self.1.is_empty() || self.0.filters_entities()
+    }
+
+    fn includes_disabled() -> bool {
+        F::includes_disabled()
+    }
+}
+
+/// Query transformer skipping entities that do not have a `T` component
+///
+/// See also `QueryBorrow::with`.
+///
+/// # Example
+/// ```
+/// # use hecs::*;
+/// let mut world = World::new();
+/// let a = world.spawn((123, true, "abc"));
+/// let b = world.spawn((456, false));
+/// let c = world.spawn((42, "def"));
+/// let entities = world.query::<With<bool, &i32>>()
+///     .iter()
+///     .map(|(e, &i)| (e, i))
+///     .collect::<Vec<_>>();
+/// assert_eq!(entities.len(), 2);
+/// assert!(entities.contains(&(a, 123)));
+/// assert!(entities.contains(&(b, 456)));
+/// ```
+pub struct With<T, Q>(PhantomData<(Q, fn(T))>);
+
+impl<T: Component, Q: Query> Query for With<T, Q> {
+    type Fetch = FetchWith<T, Q::Fetch>;
+}
+
+#[doc(hidden)]
+pub struct FetchWith<T, F>(F, Option<SparseFetch>, PhantomData<fn(T)>);
+
+unsafe impl<'a, T: Component, F: Fetch<'a>> Fetch<'a> for FetchWith<T, F> {
+    type Item = F::Item;
+
+    fn dangling() -> Self {
+        Self(F::dangling(), None, PhantomData)
+    }
+
+    fn access(archetype: &Archetype) -> Option<Access> {
+        if archetype.has::<T>() || archetype.sparse(TypeId::of::<T>()).is_some() {
+            F::access(archetype)
+        } else {
+            None
+        }
+    }
+
+    fn borrow(archetype: &Archetype) {
+        F::borrow(archetype)
+    }
+    fn new(archetype: &'a Archetype) -> Option<Self> {
+        let sparse = if archetype.has::<T>() {
+            None
+        } else {
+            Some(SparseFetch::new(archetype, TypeId::of::<T>())?)
+        };
+        Some(Self(F::new(archetype)?, sparse, PhantomData))
+    }
+    fn release(archetype: &Archetype) {
+        F::release(archetype)
+    }
+
+    fn for_each_borrow(f: impl FnMut(TypeId, bool)) {
+        F::for_each_borrow(f);
+    }
+
+    unsafe fn get(&self, n: usize) -> F::Item {
+        self.0.get(n)
+    }
+
+    unsafe fn should_skip(&self, n: usize) -> bool {
+        self.1.as_ref().is_some_and(|x| x.row(n).is_none()) || self.0.should_skip(n)
+    }
+
+    fn filters_entities(&self) -> bool {
+        self.1.is_some() || self.0.filters_entities()
+    }
+
+    fn includes_disabled() -> bool {
+        F::includes_disabled()
+    }
+}
+
+/// Query transformer skipping entities whose `T` component was not added since the last call to
+/// `World::clear_trackers`
+///
+/// Replacing a component with `World::insert` counts as adding it. See also `QueryBorrow::added`.
+///
+/// # Example
+/// ```
+/// # use hecs::*;
+/// let mut world = World::new();
+/// let a = world.spawn((123,));
+/// world.clear_trackers();
+/// let b = world.spawn((456,));
+/// let entities = world.query::<Added<i32, &i32>>()
+///     .iter()
+///     .map(|(e, &i)| (e, i))
+///     .collect::<Vec<_>>();
+/// assert_eq!(entities, &[(b, 456)]);
+/// ```
+pub struct Added<T, Q>(PhantomData<(Q, fn(T))>);
+
+impl<T: Component, Q: Query> Query for Added<T, Q> {
+    type Fetch = FetchAdded<T, Q::Fetch>;
+}
+
+#[doc(hidden)]
+pub struct FetchAdded<T, F> {
+    fetch: F,
+    added: NonNull<u32>,
+    tick: u32,
+    _marker: PhantomData<fn(T)>,
+}
+
+unsafe impl<'a, T: Component, F: Fetch<'a>> Fetch<'a> for FetchAdded<T, F> {
+    type Item = F::Item;
+
+    fn dangling() -> Self {
+        Self {
+            fetch: F::dangling(),
+            added: NonNull::dangling(),
+            tick: 0,
+            _marker: PhantomData,
+        }
+    }
+
+    fn access(archetype: &Archetype) -> Option<Access> {
+        if archetype.has::<T>() {
+            F::access(archetype)
+        } else {
+            None
+        }
+    }
+
+    fn borrow(archetype: &Archetype) {
+        F::borrow(archetype)
+    }
+    fn new(archetype: &'a Archetype) -> Option<Self> {
+        Some(Self {
+            added: archetype.added::<T>()?,
+            fetch: F::new(archetype)?,
+            tick: archetype.tick(),
+            _marker: PhantomData,
+        })
+    }
+    fn release(archetype: &Archetype) {
+        F::release(archetype)
+    }
+
+    fn for_each_borrow(f: impl FnMut(TypeId, bool)) {
+        F::for_each_borrow(f);
+    }
+
+    unsafe fn get(&self, n: usize) -> F::Item {
+        self.fetch.get(n)
+    }
+
+    unsafe fn should_skip(&self, n: usize) -> bool {
+        *self.added.as_ptr().add(n) != self.tick || self.fetch.should_skip(n)
+    }
+
+    fn filters_entities(&self) -> bool {
+        true
+    }
+
+    fn includes_disabled() -> bool {
+        F::includes_disabled()
+    }
+}
+
+/// Query transformer skipping entities whose `T` component was neither added nor mutably accessed
+/// since the last call to `World::clear_trackers`
+///
+/// Any unique borrow of a component, whether through a query, `World::get_mut`, or
+/// `EntityRef::get_mut`, counts as a change, regardless of whether the component is actually
+/// modified. See also `QueryBorrow::changed`.
+///
+/// # Example
+/// ```
+/// # use hecs::*;
+/// let mut world = World::new();
+/// let a = world.spawn((123,));
+/// let b = world.spawn((456,));
+/// world.clear_trackers();
+/// *world.get_mut::<i32>(a).unwrap() += 1;
+/// let entities = world.query::<Changed<i32, &i32>>()
+///     .iter()
+///     .map(|(e, &i)| (e, i))
+///     .collect::<Vec<_>>();
+/// assert_eq!(entities, &[(a, 124)]);
+/// ```
+pub struct Changed<T, Q>(PhantomData<(Q, fn(T))>);
+
+impl<T: Component, Q: Query> Query for Changed<T, Q> {
+    type Fetch = FetchChanged<T, Q::Fetch>;
+}
+
+#[doc(hidden)]
+pub struct FetchChanged<T, F> {
+    fetch: F,
+    mutated: NonNull<AtomicU32>,
+    tick: u32,
+    _marker: PhantomData<fn(T)>,
+}
+
+unsafe impl<'a, T: Component, F: Fetch<'a>> Fetch<'a> for FetchChanged<T, F> {
+    type Item = F::Item;
+
+    fn dangling() -> Self {
+        Self {
+            fetch: F::dangling(),
+            mutated: NonNull::dangling(),
+            tick: 0,
+            _marker: PhantomData,
+        }
+    }
+
+    fn access(archetype: &Archetype) -> Option<Access> {
+        if archetype.has::<T>() {
+            F::access(archetype)
+        } else {
+            None
+        }
+    }
+
+    fn borrow(archetype: &Archetype) {
+        F::borrow(archetype)
+    }
+    fn new(archetype: &'a Archetype) -> Option<Self> {
+        Some(Self {
+            mutated: archetype.mutated::<T>()?,
+            fetch: F::new(archetype)?,
+            tick: archetype.tick(),
+            _marker: PhantomData,
+        })
+    }
+    fn release(archetype: &Archetype) {
+        F::release(archetype)
+    }
+
+    fn for_each_borrow(f: impl FnMut(TypeId, bool)) {
+        F::for_each_borrow(f);
+    }
+
+    unsafe fn get(&self, n: usize) -> F::Item {
+        self.fetch.get(n)
+    }
+
+    unsafe fn should_skip(&self, n: usize) -> bool {
+        (*self.mutated.as_ptr().add(n)).load(Ordering::Relaxed) != self.tick
+            || self.fetch.should_skip(n)
+    }
+
+    fn filters_entities(&self) -> bool {
+        true
+    }
+
+    fn includes_disabled() -> bool {
+        F::includes_disabled()
+    }
+}
+
+/// A domain-specific view of the items of another query, fetched with [`Map`]
+///
+/// Lets code that consumes a query be written against its own types rather than raw component
+/// layouts. Implemented by a marker type, often the view type itself with a `'static` lifetime,
+/// alongside [`QueryViewItem`].
+pub trait QueryView {
+    /// Query whose items are transformed
+    type Query: Query;
+}
+
+/// The item yielded by a [`QueryView`] for borrows of lifetime `'a`
+///
+/// Once rust offers generic associated types, this will be moved into `QueryView`.
+pub trait QueryViewItem<'a>: QueryView {
+    /// Type of value yielded for each entity
+    type Item;
+
+    /// Transform an item of `Self::Query`
+    fn view(item: QueryItem<'a, Self::Query>) -> Self::Item;
+}
+
+/// Query transformer yielding the items of `V::Query` transformed by `V`
+///
+/// Borrows exactly the components `V::Query` does. See also `QueryBorrow::map`.
+///
+/// # Example
+/// ```
+/// # use hecs::*;
+/// struct Health(u32);
+/// struct Poisoned(u32);
+///
+/// struct Character<'a> {
+///     health: &'a mut Health,
+///     poison: Option<&'a Poisoned>,
+/// }
+///
+/// impl Character<'_> {
+///     fn tick(&mut self) {
+///         self.health.0 -= self.poison.map_or(0, |x| x.0);
+///     }
+/// }
+///
+/// impl QueryView for Character<'static> {
+///     type Query = (&'static mut Health, Option<&'static Poisoned>);
+/// }
+///
+/// impl<'a> QueryViewItem<'a> for Character<'static> {
+///     type Item = Character<'a>;
+///     fn view((health, poison): (&'a mut Health, Option<&'a Poisoned>)) -> Character<'a> {
+///         Character { health, poison }
+///     }
+/// }
+///
+/// let mut world = World::new();
+/// let a = world.spawn((Health(10), Poisoned(3)));
+/// let b = world.spawn((Health(10),));
+/// for (_, mut character) in world.query_mut::<Map<Character>>() {
+///     character.tick();
+/// }
+/// assert_eq!(world.get::<Health>(a).unwrap().0, 7);
+/// assert_eq!(world.get::<Health>(b).unwrap().0, 10);
+/// ```
+pub struct Map<V>(PhantomData<fn(V)>);
+
+impl<V: QueryView + for<'a> QueryViewItem<'a>> Query for Map<V> {
+    type Fetch = FetchMap<V, <V::Query as Query>::Fetch>;
+}
+
+#[doc(hidden)]
+pub struct FetchMap<V, F>(F, PhantomData<fn(V)>);
+
+unsafe impl<'a, V, F> Fetch<'a> for FetchMap<V, F>
+where
+    V: QueryViewItem<'a>,
+    F: Fetch<'a, Item = QueryItem<'a, V::Query>>,
+{
+    type Item = V::Item;
+
+    fn dangling() -> Self {
+        Self(F::dangling(), PhantomData)
+    }
+
+    fn access(archetype: &Archetype) -> Option<Access> {
+        F::access(archetype)
+    }
+
+    fn borrow(archetype: &Archetype) {
+        F::borrow(archetype)
+    }
+    fn new(archetype: &'a Archetype) -> Option<Self> {
+        Some(Self(F::new(archetype)?, PhantomData))
+    }
+    fn release(archetype: &Archetype) {
+        F::release(archetype)
+    }
+
+    fn for_each_borrow(f: impl FnMut(TypeId, bool)) {
+        F::for_each_borrow(f);
+    }
+
+    unsafe fn get(&self, n: usize) -> V::Item {
+        V::view(self.0.get(n))
+    }
+
+    unsafe fn should_skip(&self, n: usize) -> bool {
+        self.0.should_skip(n)
+    }
+
+    fn filters_entities(&self) -> bool {
+        self.0.filters_entities()
+    }
+
+    fn includes_disabled() -> bool {
+        F::includes_disabled()
+    }
+}
+
+unsafe impl<'a, T: Component> SliceFetch<'a> for FetchRead<T> {
+    type Slice = &'a [T];
+
+    unsafe fn slice(&self, start: usize, len: usize) -> &'a [T] {
+        assert!(
+            self.sparse.is_none(),
+            "sparse component {} can't be borrowed as a slice",
+            core::any::type_name::<T>()
+        );
+        core::slice::from_raw_parts(self.components.as_ptr().add(start), len)
+    }
+}
+
+unsafe impl<'a, T: Component> SliceFetch<'a> for FetchWrite<T> {
+    type Slice = &'a mut [T];
+
+    unsafe fn slice(&self, start: usize, len: usize) -> &'a mut [T] {
+        assert!(
+            self.sparse.is_none(),
+            "sparse component {} can't be borrowed as a slice",
+            core::any::type_name::<T>()
+        );
+        for n in start..start + len {
+            (*self.mutated.as_ptr().add(n)).store(self.tick, Ordering::Relaxed);
+        }
+        core::slice::from_raw_parts_mut(self.components.as_ptr().add(start), len)
+    }
+}
+
+unsafe impl<'a, T: SliceFetch<'a>> SliceFetch<'a> for TryFetch<T> {
+    type Slice = Option<T::Slice>;
+
+    unsafe fn slice(&self, start: usize, len: usize) -> Option<T::Slice> {
+        Some(self.0.as_ref()?.slice(start, len))
+    }
+}
+
+unsafe impl<'a, T: Component, F: SliceFetch<'a>> SliceFetch<'a> for FetchWithout<T, F> {
+    type Slice = F::Slice;
+
+    unsafe fn slice(&self, start: usize, len: usize) -> F::Slice {
+        self.0.slice(start, len)
+    }
+}
+
+unsafe impl<'a, B: Bundle, F: SliceFetch<'a>> SliceFetch<'a> for FetchWithoutAny<B, F> {
+    type Slice = F::Slice;
+
+    unsafe fn slice(&self, start: usize, len: usize) -> F::Slice {
+        self.0.slice(start, len)
+    }
+}
+
+unsafe impl<'a, T: Component, F: SliceFetch<'a>> SliceFetch<'a> for FetchWith<T, F> {
+    type Slice = F::Slice;
+
+    unsafe fn slice(&self, start: usize, len: usize) -> F::Slice {
+        self.0.slice(start, len)
+    }
+}
+
+unsafe impl<'a, T: Component, F: SliceFetch<'a>> SliceFetch<'a> for FetchAdded<T, F> {
+    type Slice = F::Slice;
+
+    unsafe fn slice(&self, start: usize, len: usize) -> F::Slice {
+        self.fetch.slice(start, len)
+    }
+}
+
+unsafe impl<'a, T: Component, F: SliceFetch<'a>> SliceFetch<'a> for FetchChanged<T, F> {
+    type Slice = F::Slice;
+
+    unsafe fn slice(&self, start: usize, len: usize) -> F::Slice {
+        self.fetch.slice(start, len)
+    }
+}
+
+/// A borrow of a `World` sufficient to execute the query `Q`
+///
+/// Note that borrows are not released until this object is dropped.
+pub struct QueryBorrow<'w, Q: Query> {
+    meta: &'w [EntityMeta],
+    archetypes: &'w [Archetype],
+    borrowed: bool,
+    _marker: PhantomData<Q>,
+}
+
+impl<'w, Q: Query> QueryBorrow<'w, Q> {
+    pub(crate) fn new(meta: &'w [EntityMeta], archetypes: &'w [Archetype]) -> Self {
+        Self {
+            meta,
+            archetypes,
+            borrowed: false,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Execute the query
+    ///
+    /// Must be called only once per query.
+    // The lifetime narrowing here is required for soundness.
+    pub fn iter(&mut self) -> QueryIter<'_, Q> {
+        self.borrow();
+        unsafe { QueryIter::new(self.meta, self.archetypes) }
+    }
+
+    /// Execute the query, calling `f` on each matching entity
+    ///
+    /// Equivalent to `self.iter().for_each(f)`, and likewise must be called only once per query.
+    /// Entities are visited an archetype at a time in a tight loop, which can be faster than
+    /// driving the iterator with `next`, as a `for` loop does.
+    ///
+    /// # Example
+    /// ```
+    /// # use hecs::*;
+    /// let mut world = World::new();
+    /// world.spawn((1, 2.0));
+    /// world.spawn((3, 4.0));
+    /// let mut total = 0.0;
+    /// world
+    ///     .query::<(&i32, &mut f64)>()
+    ///     .for_each(|(_, (&n, x))| {
+    ///         *x *= 2.0;
+    ///         total += n as f64 + *x;
+    ///     });
+    /// assert_eq!(total, 16.0);
+    /// ```
+    pub fn for_each<'q>(&'q mut self, f: impl FnMut((Entity, QueryItem<'q, Q>))) {
+        self.iter().for_each(f);
+    }
+
+    /// Execute the query, yielding only the components of each matching entity
+    ///
+    /// Like `iter`, must be called only once per query. Skipping the lookup of each entity's
+    /// handle leaves loops over plain data free for the compiler to vectorize.
+    ///
+    /// # Example
+    /// ```
+    /// # use hecs::*;
+    /// let mut world = World::new();
+    /// world.spawn((1, 2.0));
+    /// world.spawn((3, 4.0));
+    /// let total = world
+    ///     .query::<(&i32, &f64)>()
+    ///     .iter_values()
+    ///     .map(|(&n, &x)| n as f64 * x)
+    ///     .sum::<f64>();
+    /// assert_eq!(total, 14.0);
+    /// ```
+    pub fn iter_values(&mut self) -> QueryValues<'_, Q> {
+        self.borrow();
+        unsafe { QueryValues::new(self.archetypes) }
+    }
+
+    /// Execute the query, returning the only matching entity
+    ///
+    /// Like `iter`, must be called only once per query. Useful for entities of which there should
+    /// be exactly one, like the player or the camera: unlike taking the first item of `iter`,
+    /// this reports an error if there are several.
+    ///
+    /// # Example
+    /// ```
+    /// # use hecs::*;
+    /// struct Camera;
+    /// let mut world = World::new();
+    /// assert_eq!(world.query::<&Camera>().single().err(), Some(QuerySingleError::NoMatches));
+    /// let a = world.spawn((Camera, 1.0f32));
+    /// assert_eq!(world.query::<(&Camera, &f32)>().single().unwrap().0, a);
+    /// world.spawn((Camera,));
+    /// assert_eq!(
+    ///     world.query::<&Camera>().single().err(),
+    ///     Some(QuerySingleError::MultipleMatches(2))
+    /// );
+    /// ```
+    // The lifetime narrowing here is required for soundness.
+    pub fn single(&mut self) -> Result<(Entity, QueryItem<'_, Q>), QuerySingleError> {
+        single(self.iter())
+    }
+
+    /// Execute the query, appending each matching entity and its components to `out`
+    ///
+    /// Like `iter`, must be called only once per query. Reserves space in `out` for every entity
+    /// of the matching archetypes up front, so a buffer cleared and reused from one run of a
+    /// system to the next reallocates only when the number of matches grows.
+    ///
+    /// # Example
+    /// ```
+    /// # use hecs::*;
+    /// let mut world = World::new();
+    /// let a = world.spawn((2,));
+    /// let b = world.spawn((1, true));
+    /// let mut items = Vec::new();
+    /// let mut query = world.query::<&i32>();
+    /// query.collect_into(&mut items);
+    /// items.sort_by_key(|&(_, &x)| x);
+    /// assert_eq!(items, [(b, &1), (a, &2)]);
+    /// ```
+    // The lifetime narrowing here is required for soundness.
+    pub fn collect_into<'q>(&'q mut self, out: &mut Vec<(Entity, QueryItem<'q, Q>)>) {
+        let iter = self.iter();
+        out.reserve(iter.size_hint().1.unwrap());
+        iter.for_each(|x| out.push(x));
+    }
+
+    /// Append the handle of each matching entity to `out`
+    ///
+    /// Reserves space in `out` for every entity of the matching archetypes up front. Like
+    /// `count`, acquires no borrows, so may be called before or after `iter`.
+    ///
+    /// # Example
+    /// ```
+    /// # use hecs::*;
+    /// let mut world = World::new();
+    /// let a = world.spawn((1,));
+    /// world.spawn((true,));
+    /// let mut entities = Vec::new();
+    /// world.query::<&i32>().entities_into(&mut entities);
+    /// assert_eq!(entities, [a]);
+    /// ```
+    pub fn entities_into(&self, out: &mut Vec<Entity>) {
+        out.reserve(
+            self.archetypes
+                .iter()
+                .filter(|&x| Q::Fetch::access(x).is_some())
+                .map(|x| x.len() as usize)
+                .sum(),
+        );
+        for archetype in self.archetypes {
+            let fetch = match EnabledFetch::<Q::Fetch>::new(archetype) {
+                Some(x) => x,
+                None => continue,
+            };
+            for n in 0..archetype.len() {
+                if unsafe { fetch.should_skip(n as usize) } {
+                    continue;
+                }
+                let id = archetype.entity_id(n);
+                out.push(Entity {
+                    id,
+                    generation: self.meta[id as usize].generation,
+                });
+            }
+        }
+    }
+
+    /// Execute the query, returning a matching entity chosen uniformly at random, if any
+    ///
+    /// Like `iter`, must be called only once per query. Takes time proportional to the number of
+    /// archetypes, rather than the number of matching entities, except that queries which skip
+    /// individual entities, such as those using `Changed` or `Without` on a sparse component, may
+    /// need several attempts, and fall back to visiting every entity if most are skipped.
+    ///
+    /// # Example
+    /// ```
+    /// # use hecs::*;
+    /// let mut world = World::new();
+    /// let a = world.spawn((1,));
+    /// let b = world.spawn((2, true));
+    /// world.spawn(("c",));
+    /// let mut rng = rand::thread_rng();
+    /// let (e, &x) = world.query::<&i32>().get_random(&mut rng).unwrap();
+    /// assert!((e == a && x == 1) || (e == b && x == 2));
+    /// ```
+    #[cfg(feature = "rand")]
+    pub fn get_random<R: rand::Rng + ?Sized>(
+        &mut self,
+        rng: &mut R,
+    ) -> Option<(Entity, QueryItem<'_, Q>)> {
+        self.sample(1, rng).pop()
+    }
+
+    /// Execute the query, returning `n` distinct matching entities chosen uniformly at random, or
+    /// every matching entity in random order if there are fewer than `n`
+    ///
+    /// See `get_random`.
+    #[cfg(feature = "rand")]
+    pub fn sample<R: rand::Rng + ?Sized>(
+        &mut self,
+        n: usize,
+        rng: &mut R,
+    ) -> Vec<(Entity, QueryItem<'_, Q>)> {
+        self.borrow();
+        let rows = sample_rows::<Q, R>(self.archetypes, n, rng);
+        let meta = self.meta;
+        rows.into_iter()
+            .map(|(archetype, row)| {
+                let archetype = &self.archetypes[archetype];
+                let fetch = Q::Fetch::new(archetype).unwrap();
+                let id = archetype.entity_id(row as u32);
+                let entity = Entity {
+                    id,
+                    generation: meta[id as usize].generation,
+                };
+                // Each row is distinct, so the items can't alias
+                (entity, unsafe { fetch.get(row) })
+            })
+            .collect()
+    }
+
+    /// Number of entities matched by the query, computed without fetching any components
+    ///
+    /// Takes time proportional to the number of archetypes, plus the number of entities in matching
+    /// archetypes where the query filters individual entities, as `Added`, `Changed`, sparsely
+    /// stored components, and disabled entities do. Acquires no borrows, so may be called before or
+    /// after `iter`.
+    ///
+    /// # Example
+    /// ```
+    /// # use hecs::*;
+    /// let mut world = World::new();
+    /// world.spawn((123, true));
+    /// world.spawn((456,));
+    /// world.spawn(("abc",));
+    /// assert_eq!(world.query::<&i32>().count(), 2);
+    /// assert!(world.query::<(&i32, &&str)>().is_empty());
+    /// ```
+    pub fn count(&self) -> u32 {
+        self.archetypes
+            .iter()
+            .map(|archetype| match EnabledFetch::<Q::Fetch>::new(archetype) {
+                None => 0,
+                Some(fetch) if !fetch.filters_entities() => archetype.len(),
+                Some(fetch) => (0..archetype.len() as usize)
+                    .filter(|&n| unsafe { !fetch.should_skip(n) })
+                    .count() as u32,
+            })
+            .sum()
+    }
+
+    /// Whether the query matches no entities
+    ///
+    /// Like `count`, but may return early.
+    pub fn is_empty(&self) -> bool {
+        !self
+            .archetypes
+            .iter()
+            .any(|archetype| match EnabledFetch::<Q::Fetch>::new(archetype) {
+                None => false,
+                Some(fetch) if !fetch.filters_entities() => !archetype.is_empty(),
+                Some(fetch) => {
+                    (0..archetype.len() as usize).any(|n| unsafe { !fetch.should_skip(n) })
+                }
+            })
+    }
+
+    /// Like `iter`, but returns child iterators of at most `batch_size` elements
+    ///
+    /// Useful for distributing work over a threadpool. Each `Batch` covers a contiguous run of
+    /// a single archetype, and may be sent to another thread independently of the others.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `batch_size` is zero.
+    ///
+    /// # Example
+    /// ```
+    /// # use hecs::*;
+    /// let mut world = World::new();
+    /// world.spawn_batch((0..1_000).map(|i| (i,)));
+    /// let mut query = world.query::<&mut i32>();
+    /// std::thread::scope(|s| {
+    ///     for batch in query.iter_batched(256) {
+    ///         s.spawn(move || batch.for_each(|(_, x)| *x *= 2));
+    ///     }
+    /// });
+    /// drop(query);
+    /// assert_eq!(world.query::<&i32>().iter().map(|(_, &x)| x).sum::<i32>(), 999 * 1_000);
+    /// ```
+    // The lifetime narrowing here is required for soundness.
+    pub fn iter_batched(&mut self, batch_size: u32) -> BatchedIter<'_, Q> {
+        self.borrow();
+        unsafe { BatchedIter::new(self.meta, self.archetypes, batch_size) }
+    }
+
+    /// Execute the query, yielding each maximal run of contiguously stored matching entities as
+    /// slices of their components
+    ///
+    /// Useful for handing whole ranges of components to SIMD kernels or bulk copies without
+    /// per-entity overhead. Typically yields one run per matching archetype, but entities passed
+    /// over by filters such as `Changed` or by `World::disable` split an archetype's entities into
+    /// several runs. Supported for `&T`, `&mut T`, `Option`s of such queries, the `With`,
+    /// `Without`, `WithoutAny`, `Added`, `Changed`, and `Including` transformers, and tuples of
+    /// these, yielding e.g. `(&[T], &mut [U])` for `(&T, &mut U)`. Every `&mut T` in a run counts
+    /// as changed.
+    ///
+    /// Like `iter`, must be called only once per query.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a run would include a component stored sparsely, as set by
+    /// `World::register_sparse`.
+    ///
+    /// # Example
+    /// ```
+    /// # use hecs::*;
+    /// let mut world = World::new();
+    /// world.spawn_batch((0..4).map(|i| (i as f32, 1.0f64)));
+    /// world.spawn((10.0f32, 2.0f64, true));
+    /// let mut query = world.query::<(&mut f32, &f64)>();
+    /// let mut runs = 0;
+    /// for (entities, (xs, ys)) in query.iter_slices() {
+    ///     assert_eq!(entities.len(), xs.len());
+    ///     for (x, y) in xs.iter_mut().zip(ys) {
+    ///         *x *= *y as f32;
+    ///     }
+    ///     runs += 1;
+    /// }
+    /// assert_eq!(runs, 2);
+    /// ```
+    // The lifetime narrowing here is required for soundness.
+    pub fn iter_slices<'q>(&'q mut self) -> SliceIter<'q, Q>
+    where
+        Q::Fetch: SliceFetch<'q>,
+    {
+        self.borrow();
+        unsafe { SliceIter::new(self.meta, self.archetypes) }
+    }
+
+    /// Like `iter_slices`, but splitting runs into chunks of `N` entities aligned for SIMD
+    ///
+    /// Each column is stored from an address aligned to 64 bytes, and chunks begin at entities
+    /// whose index in their archetype is a multiple of `N`, so the slices of a chunk of `N`
+    /// entities begin at multiples of `N * size_of::<T>()` when that's a power of two no greater
+    /// than 64, e.g. 32 bytes for eight `f32`s. Chunks are shorter than `N` only at the start or
+    /// end of a run, so a kernel can take the aligned path when a chunk is full and fall back to
+    /// scalar code otherwise. Shorter chunks aren't padded, since that would require making up
+    /// values for the missing components.
+    ///
+    /// Like `iter`, must be called only once per query.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `N` is zero, or as `iter_slices`.
+    ///
+    /// # Example
+    /// ```
+    /// # use hecs::*;
+    /// let mut world = World::new();
+    /// world.spawn_batch((0..20).map(|i| (i as f32, 2u8)));
+    /// let mut query = world.query::<(&mut f32, &u8)>();
+    /// let mut lens = Vec::new();
+    /// for (entities, (xs, ys)) in query.iter_chunks::<8>() {
+    ///     lens.push(entities.len());
+    ///     if xs.len() == 8 {
+    ///         // A full chunk, suitable for aligned loads into 256-bit registers
+    ///         assert_eq!(xs.as_ptr() as usize % 32, 0);
+    ///     }
+    ///     for (x, &y) in xs.iter_mut().zip(ys) {
+    ///         *x *= f32::from(y);
+    ///     }
+    /// }
+    /// assert_eq!(lens, [8, 8, 4]);
+    /// ```
+    // The lifetime narrowing here is required for soundness.
+    pub fn iter_chunks<'q, const N: usize>(&'q mut self) -> SliceChunkIter<'q, Q, N>
+    where
+        Q::Fetch: SliceFetch<'q>,
+    {
+        assert!(N != 0, "chunks must not be empty");
+        self.borrow();
+        unsafe { SliceChunkIter::new(self.meta, self.archetypes) }
+    }
+
+    /// Like `iter`, but yields entities in order of the key computed by `f` from each item
+    ///
+    /// The sort is stable, so entities with equal keys are yielded in the order `iter` would
+    /// visit them. `f` may be called several times per entity; see `iter_sorted_by_cached_key` if
+    /// it's expensive.
+    ///
+    /// # Example
+    /// ```
+    /// # use hecs::*;
+    /// let mut world = World::new();
+    /// let a = world.spawn((3, "a"));
+    /// let b = world.spawn((1, "b"));
+    /// let c = world.spawn((2,));
+    /// let order = world
+    ///     .query::<&i32>()
+    ///     .iter_sorted_by_key(|&&depth| depth)
+    ///     .map(|(e, _)| e)
+    ///     .collect::<Vec<_>>();
+    /// assert_eq!(order, [b, c, a]);
+    /// ```
+    // The lifetime narrowing here is required for soundness.
+    pub fn iter_sorted_by_key<K: Ord>(
+        &mut self,
+        mut f: impl FnMut(&QueryItem<'_, Q>) -> K,
+    ) -> vec::IntoIter<(Entity, QueryItem<'_, Q>)> {
+        let mut items = self.iter().collect::<Vec<_>>();
+        items.sort_by_key(|(_, x)| f(x));
+        items.into_iter()
+    }
+
+    /// Like `iter_sorted_by_key`, but calls `f` only once per entity
+    // The lifetime narrowing here is required for soundness.
+    pub fn iter_sorted_by_cached_key<K: Ord>(
+        &mut self,
+        mut f: impl FnMut(&QueryItem<'_, Q>) -> K,
+    ) -> impl ExactSizeIterator<Item = (Entity, QueryItem<'_, Q>)> {
+        let mut items = self.iter().map(|x| (f(&x.1), x)).collect::<Vec<_>>();
+        items.sort_by(|x, y| x.0.cmp(&y.0));
+        items.into_iter().map(|(_, x)| x)
+    }
+
+    /// Like `iter`, but visits only the matching archetypes for which `f` returns `true`
+    ///
+    /// `f` is called once per matching archetype, as iteration reaches it, rather than once per
+    /// entity, so culling whole archetypes, e.g. those lacking any of a group of components, costs
+    /// nothing per entity. Borrows are acquired as by `iter`, including on archetypes `f` rejects.
+    /// Like `iter`, must be called only once per query.
+    ///
+    /// # Example
+    /// ```
+    /// # use hecs::*;
+    /// # use std::any::TypeId;
+    /// struct Mesh;
+    /// struct Sprite;
+    /// let mut world = World::new();
+    /// let a = world.spawn((1, Mesh));
+    /// let b = world.spawn((2, Sprite));
+    /// world.spawn((3,));
+    /// let drawable = [TypeId::of::<Mesh>(), TypeId::of::<Sprite>()];
+    /// let mut entities = world
+    ///     .query::<&i32>()
+    ///     .filter_archetypes(|x| x.component_types().any(|ty| drawable.contains(&ty)))
+    ///     .map(|(e, _)| e)
+    ///     .collect::<Vec<_>>();
+    /// entities.sort();
+    /// assert_eq!(entities, [a, b]);
+    /// ```
+    // The lifetime narrowing here is required for soundness.
+    pub fn filter_archetypes<F: FnMut(&Archetype) -> bool>(
+        &mut self,
+        f: F,
+    ) -> FilterArchetypes<'_, Q, F> {
+        self.borrow();
+        FilterArchetypes {
+            iter: unsafe { QueryIter::new(self.meta, &[]) },
+            archetypes: self.archetypes.iter(),
+            filter: f,
+        }
+    }
+
+    /// Like `iter`, but distributes work across rayon's global thread pool
+    ///
+    /// Borrows are acquired for the lifetime of the returned iterator exactly as for `iter`, so
+    /// parallel iteration is subject to the same dynamic borrow checks. Requires the `parallel`
+    /// feature.
+    ///
+    /// # Example
+    /// ```
+    /// # use hecs::*;
+    /// use rayon::prelude::*;
+    /// let mut world = World::new();
+    /// world.spawn_batch((0..1_000).map(|i| (i, true)));
+    /// world.query::<&mut i32>().par_iter().for_each(|(_, x)| *x *= 2);
+    /// let sum = world.query::<&i32>().par_iter().map(|(_, &x)| x).sum::<i32>();
+    /// assert_eq!(sum, 999 * 1_000);
+    /// ```
+    // The lifetime narrowing here is required for soundness.
+    #[cfg(feature = "parallel")]
+    pub fn par_iter(&mut self) -> QueryParIter<'_, Q> {
+        self.borrow();
+        unsafe { QueryParIter::new(self.meta, self.archetypes) }
+    }
+
+    fn borrow(&mut self) {
+        if self.borrowed {
+            panic!(
+                "called QueryBorrow::iter twice on the same borrow; construct a new query instead"
+            );
+        }
+        for x in self.archetypes {
+            // TODO: Release prior borrows on failure?
+            if Q::Fetch::access(x) >= Some(Access::Read) {
+                Q::Fetch::borrow(x);
+            }
+        }
+        self.borrowed = true;
+    }
+
+    /// Transform the query into one that requires a certain component without borrowing it
+    ///
+    /// This can be useful when the component needs to be borrowed elsewhere and it isn't necessary
+    /// for the iterator to expose its data directly.
+    ///
+    /// Equivalent to using a query type wrapped in `With`.
+    ///
+    /// # Example
+    /// ```
+    /// # use hecs::*;
+    /// let mut world = World::new();
+    /// let a = world.spawn((123, true, "abc"));
+    /// let b = world.spawn((456, false));
+    /// let c = world.spawn((42, "def"));
+    /// let entities = world.query::<&i32>()
+    ///     .with::<bool>()
+    ///     .iter()
+    ///     .map(|(e, &i)| (e, i)) // Copy out of the world
+    ///     .collect::<Vec<_>>();
+    /// assert!(entities.contains(&(a, 123)));
+    /// assert!(entities.contains(&(b, 456)));
+    /// ```
+    pub fn with<T: Component>(self) -> QueryBorrow<'w, With<T, Q>> {
+        self.transform()
+    }
+
+    /// Transform the query into one that skips entities having a certain component
+    ///
+    /// Equivalent to using a query type wrapped in `Without`.
+    ///
+    /// # Example
+    /// ```
+    /// # use hecs::*;
+    /// let mut world = World::new();
+    /// let a = world.spawn((123, true, "abc"));
+    /// let b = world.spawn((456, false));
+    /// let c = world.spawn((42, "def"));
+    /// let entities = world.query::<&i32>()
+    ///     .without::<bool>()
+    ///     .iter()
+    ///     .map(|(e, &i)| (e, i)) // Copy out of the world
+    ///     .collect::<Vec<_>>();
+    /// assert_eq!(entities, &[(c, 42)]);
+    /// ```
+    pub fn without<T: Component>(self) -> QueryBorrow<'w, Without<T, Q>> {
+        self.transform()
+    }
+
+    /// Transform the query into one that skips entities having any of the components in `B`
+    ///
+    /// Equivalent to using a query type wrapped in `WithoutAny`.
+    pub fn without_any<B: Bundle>(self) -> QueryBorrow<'w, WithoutAny<B, Q>> {
+        self.transform()
+    }
+
+    /// Transform the query into one that skips entities whose `T` component was not added since
+    /// the last call to `World::clear_trackers`
+    ///
+    /// Equivalent to using a query type wrapped in `Added`.
+    pub fn added<T: Component>(self) -> QueryBorrow<'w, Added<T, Q>> {
+        self.transform()
+    }
+
+    /// Transform the query into one that skips entities whose `T` component was not added or
+    /// mutably accessed since the last call to `World::clear_trackers`
+    ///
+    /// Equivalent to using a query type wrapped in `Changed`.
+    pub fn changed<T: Component>(self) -> QueryBorrow<'w, Changed<T, Q>> {
+        self.transform()
+    }
+
+    /// Transform the query into one yielding its items as transformed by `V`
+    ///
+    /// Useful for handing a query over to code that should see only a domain-specific view of
+    /// the components. Equivalent to using `Map<V>` as the query type.
+    pub fn map<V>(self) -> QueryBorrow<'w, Map<V>>
+    where
+        V: QueryView<Query = Q> + for<'a> QueryViewItem<'a>,
+    {
+        self.transform()
+    }
+
+    /// Helper to change the type of the query
+    fn transform<R: Query>(mut self) -> QueryBorrow<'w, R> {
+        let x = QueryBorrow {
+            meta: self.meta,
+            archetypes: self.archetypes,
+            borrowed: self.borrowed,
+            _marker: PhantomData,
+        };
+        // Ensure `Drop` won't fire redundantly
+        self.borrowed = false;
+        x
+    }
+}
+
+unsafe impl<'w, Q: Query + Send> Send for QueryBorrow<'w, Q> {}
+unsafe impl<'w, Q: Query + Sync> Sync for QueryBorrow<'w, Q> {}
+
+impl<'w, Q: Query> Drop for QueryBorrow<'w, Q> {
+    fn drop(&mut self) {
         if self.borrowed {
-            panic!(
-                "called QueryBorrow::iter twice on the same borrow; construct a new query instead"
-            );
+            for x in self.archetypes {
+                if Q::Fetch::access(x) >= Some(Access::Read) {
+                    Q::Fetch::release(x);
+                }
+            }
+        }
+    }
+}
+
+impl<'q, 'w, Q: Query> IntoIterator for &'q mut QueryBorrow<'w, Q> {
+    type Item = (Entity, QueryItem<'q, Q>);
+    type IntoIter = QueryIter<'q, Q>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+#[cfg(feature = "parallel")]
+impl<'q, 'w, Q: Query> rayon::iter::IntoParallelIterator for &'q mut QueryBorrow<'w, Q>
+where
+    QueryItem<'q, Q>: Send,
+{
+    type Item = (Entity, QueryItem<'q, Q>);
+    type Iter = QueryParIter<'q, Q>;
+
+    fn into_par_iter(self) -> Self::Iter {
+        self.par_iter()
+    }
+}
+
+/// Choose up to `n` distinct (archetype index, row) pairs uniformly from those matched by `Q`
+#[cfg(feature = "rand")]
+fn sample_rows<Q: Query, R: rand::Rng + ?Sized>(
+    archetypes: &[Archetype],
+    n: usize,
+    rng: &mut R,
+) -> Vec<(usize, usize)> {
+    use hashbrown::HashSet;
+    use rand::seq::SliceRandom;
+
+    // Every row of every matching archetype, including those `Q` may go on to skip
+    let total = archetypes
+        .iter()
+        .filter(|x| Q::Fetch::access(x).is_some())
+        .map(|x| x.len() as usize)
+        .sum::<usize>();
+    let mut result = Vec::with_capacity(n.min(total));
+    if n == 0 || total == 0 {
+        return result;
+    }
+    // Draw rows at random, rejecting repeats and skipped rows. Every unskipped row is equally
+    // likely to be accepted, so the accepted rows are a uniform sample.
+    if n <= total / 2 {
+        let mut chosen = HashSet::<usize>::with_capacity(n);
+        for _ in 0..4 * n + 16 {
+            let mut index = rng.gen_range(0, total);
+            if !chosen.insert(index) {
+                continue;
+            }
+            for (i, archetype) in archetypes.iter().enumerate() {
+                if Q::Fetch::access(archetype).is_none() {
+                    continue;
+                }
+                let len = archetype.len() as usize;
+                if index >= len {
+                    index -= len;
+                    continue;
+                }
+                if EnabledFetch::<Q::Fetch>::new(archetype)
+                    .is_some_and(|x| unsafe { !x.should_skip(index) })
+                {
+                    result.push((i, index));
+                }
+                break;
+            }
+            if result.len() == n {
+                return result;
+            }
+        }
+        result.clear();
+    }
+    // Too many rows are skipped or wanted for rejection to pay off, so reservoir sample instead
+    let mut seen = 0;
+    for (i, archetype) in archetypes.iter().enumerate() {
+        let fetch = match EnabledFetch::<Q::Fetch>::new(archetype) {
+            Some(x) => x,
+            None => continue,
+        };
+        for row in 0..archetype.len() as usize {
+            if unsafe { fetch.should_skip(row) } {
+                continue;
+            }
+            seen += 1;
+            if result.len() < n {
+                result.push((i, row));
+            } else {
+                let j = rng.gen_range(0, seen);
+                if j < n {
+                    result[j] = (i, row);
+                }
+            }
+        }
+    }
+    result.shuffle(rng);
+    result
+}
+
+/// The only item of `iter`, if it has exactly one
+fn single<I: Iterator>(mut iter: I) -> Result<I::Item, QuerySingleError> {
+    let item = iter.next().ok_or(QuerySingleError::NoMatches)?;
+    if iter.next().is_some() {
+        return Err(QuerySingleError::MultipleMatches(2 + iter.count() as u32));
+    }
+    Ok(item)
+}
+
+/// Error returned by [`QueryBorrow::single`] and [`QueryMut::single`] unless exactly one entity
+/// matches the query
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum QuerySingleError {
+    /// No entity matches the query
+    NoMatches,
+    /// The given number of entities match the query
+    MultipleMatches(u32),
+}
+
+#[cfg(feature = "std")]
+impl Error for QuerySingleError {}
+
+impl fmt::Display for QuerySingleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use QuerySingleError::*;
+        match *self {
+            NoMatches => f.write_str("no entity matches the query"),
+            MultipleMatches(n) => write!(f, "{} entities match the query, not one", n),
+        }
+    }
+}
+
+/// Iterator over the set of entities with the components in `Q`
+pub struct QueryIter<'q, Q: Query> {
+    meta: &'q [EntityMeta],
+    archetypes: &'q [Archetype],
+    archetype_index: usize,
+    iter: ChunkIter<Q>,
+}
+
+impl<'q, Q: Query> QueryIter<'q, Q> {
+    /// # Safety
+    ///
+    /// `'q` must be sufficient to guarantee that `Q` cannot violate borrow safety, either with
+    /// dynamic borrow checks or by representing exclusive access to the `World`.
+    pub(crate) unsafe fn new(meta: &'q [EntityMeta], archetypes: &'q [Archetype]) -> Self {
+        Self {
+            meta,
+            archetypes,
+            archetype_index: 0,
+            iter: ChunkIter::empty(),
+        }
+    }
+}
+
+unsafe impl<'q, Q: Query + Send> Send for QueryIter<'q, Q> {}
+unsafe impl<'q, Q: Query + Sync> Sync for QueryIter<'q, Q> {}
+
+impl<'q, Q: Query> Iterator for QueryIter<'q, Q> {
+    type Item = (Entity, QueryItem<'q, Q>);
+
+    #[inline(always)]
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match unsafe { self.iter.next() } {
+                None => {
+                    let archetype = self.archetypes.get(self.archetype_index)?;
+                    self.archetype_index += 1;
+                    self.iter = ChunkIter::new(archetype);
+                    continue;
+                }
+                Some((id, components)) => {
+                    return Some((
+                        Entity {
+                            id,
+                            generation: unsafe { self.meta.get_unchecked(id as usize).generation },
+                        },
+                        components,
+                    ));
+                }
+            }
+        }
+    }
+
+    fn fold<B, F>(mut self, init: B, mut f: F) -> B
+    where
+        F: FnMut(B, Self::Item) -> B,
+    {
+        // Finish the current archetype, then visit each remaining one in a single tight loop
+        let mut acc = unsafe { self.iter.fold(self.meta, init, &mut f) };
+        for archetype in &self.archetypes[self.archetype_index..] {
+            acc = unsafe { ChunkIter::<Q>::new(archetype).fold(self.meta, acc, &mut f) };
+        }
+        acc
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        // Entities may be skipped individually, so only an upper bound is known
+        let n = self.archetypes[self.archetype_index..]
+            .iter()
+            .filter(|&x| Q::Fetch::access(x).is_some())
+            .map(|x| x.len() as usize)
+            .sum::<usize>()
+            + self.iter.remaining();
+        (0, Some(n))
+    }
+}
+
+/// Iterator over the entities matching `Q` in archetypes accepted by a predicate
+///
+/// Returned by [`QueryBorrow::filter_archetypes`].
+pub struct FilterArchetypes<'q, Q: Query, F> {
+    /// Entities of the current archetype
+    iter: QueryIter<'q, Q>,
+    archetypes: slice::Iter<'q, Archetype>,
+    filter: F,
+}
+
+impl<'q, Q: Query, F: FnMut(&Archetype) -> bool> Iterator for FilterArchetypes<'q, Q, F> {
+    type Item = (Entity, QueryItem<'q, Q>);
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(x) = self.iter.next() {
+                return Some(x);
+            }
+            let archetype = self.archetypes.next()?;
+            if Q::Fetch::access(archetype).is_some() && (self.filter)(archetype) {
+                // Safe because the borrows were acquired by `QueryBorrow::filter_archetypes`
+                self.iter = unsafe { QueryIter::new(self.iter.meta, slice::from_ref(archetype)) };
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        // Archetypes may yet be rejected, so only an upper bound is known
+        let n = self
+            .archetypes
+            .clone()
+            .filter(|&x| Q::Fetch::access(x).is_some())
+            .map(|x| x.len() as usize)
+            .sum::<usize>();
+        (0, Some(self.iter.size_hint().1.unwrap() + n))
+    }
+}
+
+/// Iterator over the components of the entities matching `Q`, without their handles
+///
+/// Returned by [`QueryBorrow::iter_values`] and [`QueryMut::into_values`].
+pub struct QueryValues<'q, Q: Query> {
+    archetypes: &'q [Archetype],
+    archetype_index: usize,
+    iter: ChunkIter<Q>,
+}
+
+impl<'q, Q: Query> QueryValues<'q, Q> {
+    /// # Safety
+    ///
+    /// `'q` must be sufficient to guarantee that `Q` cannot violate borrow safety, either with
+    /// dynamic borrow checks or by representing exclusive access to the `World`.
+    unsafe fn new(archetypes: &'q [Archetype]) -> Self {
+        Self {
+            archetypes,
+            archetype_index: 0,
+            iter: ChunkIter::empty(),
+        }
+    }
+}
+
+unsafe impl<'q, Q: Query + Send> Send for QueryValues<'q, Q> {}
+unsafe impl<'q, Q: Query + Sync> Sync for QueryValues<'q, Q> {}
+
+impl<'q, Q: Query> Iterator for QueryValues<'q, Q> {
+    type Item = QueryItem<'q, Q>;
+
+    #[inline(always)]
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match unsafe { self.iter.next_value() } {
+                None => {
+                    let archetype = self.archetypes.get(self.archetype_index)?;
+                    self.archetype_index += 1;
+                    self.iter = ChunkIter::new(archetype);
+                }
+                Some(components) => return Some(components),
+            }
+        }
+    }
+
+    fn fold<B, F>(mut self, init: B, mut f: F) -> B
+    where
+        F: FnMut(B, Self::Item) -> B,
+    {
+        let mut acc = unsafe { self.iter.fold_values(init, &mut f) };
+        for archetype in &self.archetypes[self.archetype_index..] {
+            acc = unsafe { ChunkIter::<Q>::new(archetype).fold_values(acc, &mut f) };
+        }
+        acc
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let n = self.archetypes[self.archetype_index..]
+            .iter()
+            .filter(|&x| Q::Fetch::access(x).is_some())
+            .map(|x| x.len() as usize)
+            .sum::<usize>()
+            + self.iter.remaining();
+        (0, Some(n))
+    }
+}
+
+/// A query builder that's convertible directly into an iterator
+pub struct QueryMut<'q, Q: Query> {
+    iter: QueryIter<'q, Q>,
+}
+
+impl<'q, Q: Query> QueryMut<'q, Q> {
+    pub(crate) fn new(meta: &'q [EntityMeta], archetypes: &'q mut [Archetype]) -> Self {
+        unsafe { Self::new_unchecked(meta, archetypes) }
+    }
+
+    /// # Safety
+    ///
+    /// No other access to `archetypes` during `'q` may conflict with `Q`'s.
+    pub(crate) unsafe fn new_unchecked(
+        meta: &'q [EntityMeta],
+        archetypes: &'q [Archetype],
+    ) -> Self {
+        Self {
+            iter: QueryIter::new(meta, archetypes),
+        }
+    }
+
+    /// Transform the query into one that requires a certain component without borrowing it
+    ///
+    /// See `QueryBorrow::with`
+    pub fn with<T: Component>(self) -> QueryMut<'q, With<T, Q>> {
+        self.transform()
+    }
+
+    /// Transform the query into one that skips entities having a certain component
+    ///
+    /// See `QueryBorrow::without`
+    pub fn without<T: Component>(self) -> QueryMut<'q, Without<T, Q>> {
+        self.transform()
+    }
+
+    /// Transform the query into one that skips entities having any of the components in `B`
+    ///
+    /// See `QueryBorrow::without_any`
+    pub fn without_any<B: Bundle>(self) -> QueryMut<'q, WithoutAny<B, Q>> {
+        self.transform()
+    }
+
+    /// Transform the query into one that skips entities whose `T` component was not recently added
+    ///
+    /// See `QueryBorrow::added`
+    pub fn added<T: Component>(self) -> QueryMut<'q, Added<T, Q>> {
+        self.transform()
+    }
+
+    /// Transform the query into one that skips entities whose `T` component was not recently
+    /// changed
+    ///
+    /// See `QueryBorrow::changed`
+    pub fn changed<T: Component>(self) -> QueryMut<'q, Changed<T, Q>> {
+        self.transform()
+    }
+
+    /// Transform the query into one yielding its items as transformed by `V`
+    ///
+    /// See `QueryBorrow::map`
+    pub fn map<V>(self) -> QueryMut<'q, Map<V>>
+    where
+        V: QueryView<Query = Q> + for<'a> QueryViewItem<'a>,
+    {
+        self.transform()
+    }
+
+    /// Call `f` on each matching entity
+    ///
+    /// See `QueryBorrow::for_each`.
+    pub fn for_each(self, f: impl FnMut((Entity, QueryItem<'q, Q>))) {
+        self.iter.for_each(f);
+    }
+
+    /// Return the only matching entity
+    ///
+    /// See `QueryBorrow::single`.
+    pub fn single(self) -> Result<(Entity, QueryItem<'q, Q>), QuerySingleError> {
+        single(self.iter)
+    }
+
+    /// Like `into_iter`, but yields only the components of each matching entity
+    ///
+    /// See `QueryBorrow::iter_values`.
+    pub fn into_values(self) -> QueryValues<'q, Q> {
+        unsafe { QueryValues::new(self.iter.archetypes) }
+    }
+
+    /// Like `into_iter`, but returns child iterators of at most `batch_size` elements
+    ///
+    /// See `QueryBorrow::iter_batched`.
+    pub fn into_iter_batched(self, batch_size: u32) -> BatchedIter<'q, Q> {
+        unsafe { BatchedIter::new(self.iter.meta, self.iter.archetypes, batch_size) }
+    }
+
+    /// Like `into_iter`, but distributes work across rayon's global thread pool
+    ///
+    /// Since the world is uniquely borrowed, no dynamic borrow checks are needed. Equivalent to
+    /// `into_par_iter`, without importing rayon's traits. Requires the `parallel` feature.
+    ///
+    /// # Example
+    /// ```
+    /// # use hecs::*;
+    /// use rayon::prelude::*;
+    /// let mut world = World::new();
+    /// world.spawn_batch((0..1_000).map(|i| (i,)));
+    /// world.query_mut::<&mut i32>().par_iter_mut().for_each(|(_, x)| *x *= 2);
+    /// assert_eq!(world.query_mut::<&i32>().into_iter().map(|(_, &x)| x).sum::<i32>(), 999 * 1_000);
+    /// ```
+    #[cfg(feature = "parallel")]
+    pub fn par_iter_mut(self) -> QueryParIter<'q, Q> {
+        unsafe { QueryParIter::new(self.iter.meta, self.iter.archetypes) }
+    }
+
+    /// Helper to change the type of the query
+    fn transform<R: Query>(self) -> QueryMut<'q, R> {
+        QueryMut {
+            iter: unsafe { QueryIter::new(self.iter.meta, self.iter.archetypes) },
         }
-        for x in self.archetypes {
-            // TODO: Release prior borrows on failure?
-            if Q::Fetch::access(x) >= Some(Access::Read) {
-                Q::Fetch::borrow(x);
+    }
+}
+
+impl<'q, Q: Query> IntoIterator for QueryMut<'q, Q> {
+    type Item = <QueryIter<'q, Q> as Iterator>::Item;
+    type IntoIter = QueryIter<'q, Q>;
+
+    #[inline]
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter
+    }
+}
+
+#[cfg(feature = "parallel")]
+impl<'q, Q: Query> rayon::iter::IntoParallelIterator for QueryMut<'q, Q>
+where
+    QueryItem<'q, Q>: Send,
+{
+    type Item = (Entity, QueryItem<'q, Q>);
+    type Iter = QueryParIter<'q, Q>;
+
+    #[inline]
+    fn into_par_iter(self) -> Self::Iter {
+        self.par_iter_mut()
+    }
+}
+
+struct ChunkIter<Q: Query> {
+    entities: NonNull<EntityId>,
+    fetch: EnabledFetch<Q::Fetch>,
+    position: usize,
+    len: usize,
+}
+
+impl<Q: Query> ChunkIter<Q> {
+    /// Construct an iterator over the entities in `archetype`, or an empty iterator if `Q` doesn't
+    /// match it
+    fn new(archetype: &Archetype) -> Self {
+        EnabledFetch::<Q::Fetch>::new(archetype).map_or(Self::empty(), |fetch| Self {
+            entities: archetype.entities(),
+            fetch,
+            position: 0,
+            len: archetype.len() as usize,
+        })
+    }
+
+    fn empty() -> Self {
+        Self {
+            entities: NonNull::dangling(),
+            fetch: EnabledFetch::dangling(),
+            position: 0,
+            len: 0,
+        }
+    }
+
+    #[inline]
+    unsafe fn next<'a>(&mut self) -> Option<(EntityId, <Q::Fetch as Fetch<'a>>::Item)> {
+        loop {
+            if self.position == self.len {
+                return None;
+            }
+            let position = self.position;
+            self.position += 1;
+            if self.fetch.should_skip(position) {
+                continue;
             }
+            let entity = self.entities.as_ptr().add(position);
+            return Some((*entity, self.fetch.get(position)));
         }
-        self.borrowed = true;
     }
 
-    /// Transform the query into one that requires a certain component without borrowing it
+    /// Like `next`, without reading the entity's ID
+    #[inline]
+    unsafe fn next_value<'a>(&mut self) -> Option<<Q::Fetch as Fetch<'a>>::Item> {
+        while self.position < self.len {
+            let position = self.position;
+            self.position += 1;
+            if !self.fetch.should_skip(position) {
+                return Some(self.fetch.get(position));
+            }
+        }
+        None
+    }
+
+    /// Like `fold`, without reading the entity's ID
+    #[inline]
+    unsafe fn fold_values<'a, B>(
+        &mut self,
+        mut acc: B,
+        mut f: impl FnMut(B, <Q::Fetch as Fetch<'a>>::Item) -> B,
+    ) -> B {
+        for position in self.position..self.len {
+            if !self.fetch.should_skip(position) {
+                acc = f(acc, self.fetch.get(position));
+            }
+        }
+        self.position = self.len;
+        acc
+    }
+
+    /// Pass every remaining item to `f`, in a loop free of the bookkeeping `next` needs to resume
+    #[inline]
+    unsafe fn fold<'a, B>(
+        &mut self,
+        meta: &[EntityMeta],
+        mut acc: B,
+        mut f: impl FnMut(B, (Entity, <Q::Fetch as Fetch<'a>>::Item)) -> B,
+    ) -> B {
+        for position in self.position..self.len {
+            if self.fetch.should_skip(position) {
+                continue;
+            }
+            let id = *self.entities.as_ptr().add(position);
+            let entity = Entity {
+                id,
+                generation: meta.get_unchecked(id as usize).generation,
+            };
+            acc = f(acc, (entity, self.fetch.get(position)));
+        }
+        self.position = self.len;
+        acc
+    }
+
+    /// Upper bound on the number of items yet to be yielded
+    fn remaining(&self) -> usize {
+        self.len - self.position
+    }
+}
+
+/// Batched version of `QueryIter`
+pub struct BatchedIter<'q, Q: Query> {
+    _marker: PhantomData<&'q Q>,
+    meta: &'q [EntityMeta],
+    archetypes: &'q [Archetype],
+    archetype_index: usize,
+    batch_size: u32,
+    batch: u32,
+}
+
+impl<'q, Q: Query> BatchedIter<'q, Q> {
+    /// # Safety
     ///
-    /// This can be useful when the component needs to be borrowed elsewhere and it isn't necessary
-    /// for the iterator to expose its data directly.
+    /// `'q` must be sufficient to guarantee that `Q` cannot violate borrow safety, either with
+    /// dynamic borrow checks or by representing exclusive access to the `World`.
+    pub(crate) unsafe fn new(
+        meta: &'q [EntityMeta],
+        archetypes: &'q [Archetype],
+        batch_size: u32,
+    ) -> Self {
+        assert!(batch_size > 0, "batch size must be nonzero");
+        Self {
+            _marker: PhantomData,
+            meta,
+            archetypes,
+            archetype_index: 0,
+            batch_size,
+            batch: 0,
+        }
+    }
+}
+
+unsafe impl<'q, Q: Query + Send> Send for BatchedIter<'q, Q> {}
+unsafe impl<'q, Q: Query + Sync> Sync for BatchedIter<'q, Q> {}
+
+impl<'q, Q: Query> Iterator for BatchedIter<'q, Q> {
+    type Item = Batch<'q, Q>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let archetype = self.archetypes.get(self.archetype_index)?;
+            let offset = self.batch_size * self.batch;
+            if offset >= archetype.len() {
+                self.archetype_index += 1;
+                self.batch = 0;
+                continue;
+            }
+            if let Some(fetch) = EnabledFetch::<Q::Fetch>::new(archetype) {
+                self.batch += 1;
+                return Some(Batch {
+                    meta: self.meta,
+                    state: ChunkIter {
+                        entities: archetype.entities(),
+                        fetch,
+                        len: (offset + self.batch_size.min(archetype.len() - offset)) as usize,
+                        position: offset as usize,
+                    },
+                });
+            } else {
+                self.archetype_index += 1;
+                debug_assert_eq!(
+                    self.batch, 0,
+                    "query fetch should always reject at the first batch or not at all"
+                );
+                continue;
+            }
+        }
+    }
+}
+
+/// Iterator over runs of contiguously stored entities matching a query, as slices of their
+/// components
+///
+/// Yielded by `QueryBorrow::iter_slices`.
+pub struct SliceIter<'q, Q: Query> {
+    meta: &'q [EntityMeta],
+    archetypes: core::slice::Iter<'q, Archetype>,
+    /// Archetype currently being traversed, and the position in it of the next entity
+    archetype: Option<(&'q Archetype, EnabledFetch<Q::Fetch>)>,
+    position: usize,
+}
+
+impl<'q, Q: Query> SliceIter<'q, Q> {
+    /// # Safety
+    ///
+    /// `'q` must be sufficient to guarantee that `Q` cannot violate borrow safety, either with
+    /// dynamic borrow checks or by representing exclusive access to the `World`.
+    unsafe fn new(meta: &'q [EntityMeta], archetypes: &'q [Archetype]) -> Self {
+        Self {
+            meta,
+            archetypes: archetypes.iter(),
+            archetype: None,
+            position: 0,
+        }
+    }
+}
+
+unsafe impl<'q, Q: Query + Send> Send for SliceIter<'q, Q> {}
+unsafe impl<'q, Q: Query + Sync> Sync for SliceIter<'q, Q> {}
+
+impl<'q, Q: Query> Iterator for SliceIter<'q, Q>
+where
+    Q::Fetch: SliceFetch<'q>,
+{
+    type Item = (ColumnEntities<'q>, QuerySlice<'q, Q>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (archetype, fetch) = match self.archetype {
+                Some((archetype, ref fetch)) => (archetype, fetch),
+                None => {
+                    let archetype = self.archetypes.next()?;
+                    self.position = 0;
+                    self.archetype =
+                        EnabledFetch::<Q::Fetch>::new(archetype).map(|x| (archetype, x));
+                    continue;
+                }
+            };
+            let len = archetype.len() as usize;
+            unsafe {
+                while self.position < len && fetch.should_skip(self.position) {
+                    self.position += 1;
+                }
+                let start = self.position;
+                while self.position < len && !fetch.should_skip(self.position) {
+                    self.position += 1;
+                }
+                if start == self.position {
+                    self.archetype = None;
+                    continue;
+                }
+                let ids = core::slice::from_raw_parts(
+                    archetype.entities().as_ptr().add(start),
+                    self.position - start,
+                );
+                return Some((
+                    ColumnEntities::from_ids(self.meta, ids),
+                    fetch.slice(start, self.position - start),
+                ));
+            }
+        }
+    }
+}
+
+/// Iterator over chunks of contiguously stored entities matching a query, as slices of their
+/// components
+///
+/// Yielded by `QueryBorrow::iter_chunks`.
+pub struct SliceChunkIter<'q, Q: Query, const N: usize> {
+    meta: &'q [EntityMeta],
+    archetypes: core::slice::Iter<'q, Archetype>,
+    /// Archetype currently being traversed
+    archetype: Option<(&'q Archetype, EnabledFetch<Q::Fetch>)>,
+    /// Position in the archetype of the next entity, and the end of the run containing it
+    position: usize,
+    end: usize,
+}
+
+impl<'q, Q: Query, const N: usize> SliceChunkIter<'q, Q, N> {
+    /// # Safety
+    ///
+    /// `'q` must be sufficient to guarantee that `Q` cannot violate borrow safety, either with
+    /// dynamic borrow checks or by representing exclusive access to the `World`.
+    unsafe fn new(meta: &'q [EntityMeta], archetypes: &'q [Archetype]) -> Self {
+        Self {
+            meta,
+            archetypes: archetypes.iter(),
+            archetype: None,
+            position: 0,
+            end: 0,
+        }
+    }
+}
+
+unsafe impl<'q, Q: Query + Send, const N: usize> Send for SliceChunkIter<'q, Q, N> {}
+unsafe impl<'q, Q: Query + Sync, const N: usize> Sync for SliceChunkIter<'q, Q, N> {}
+
+impl<'q, Q: Query, const N: usize> Iterator for SliceChunkIter<'q, Q, N>
+where
+    Q::Fetch: SliceFetch<'q>,
+{
+    type Item = (ColumnEntities<'q>, QuerySlice<'q, Q>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (archetype, fetch) = match self.archetype {
+                Some((archetype, ref fetch)) => (archetype, fetch),
+                None => {
+                    let archetype = self.archetypes.next()?;
+                    self.position = 0;
+                    self.end = 0;
+                    self.archetype =
+                        EnabledFetch::<Q::Fetch>::new(archetype).map(|x| (archetype, x));
+                    continue;
+                }
+            };
+            unsafe {
+                if self.position == self.end {
+                    // Find the next run
+                    let len = archetype.len() as usize;
+                    while self.position < len && fetch.should_skip(self.position) {
+                        self.position += 1;
+                    }
+                    self.end = self.position;
+                    while self.end < len && !fetch.should_skip(self.end) {
+                        self.end += 1;
+                    }
+                    if self.position == self.end {
+                        self.archetype = None;
+                        continue;
+                    }
+                }
+                let start = self.position;
+                self.position = ((start / N + 1) * N).min(self.end);
+                let ids = core::slice::from_raw_parts(
+                    archetype.entities().as_ptr().add(start),
+                    self.position - start,
+                );
+                return Some((
+                    ColumnEntities::from_ids(self.meta, ids),
+                    fetch.slice(start, self.position - start),
+                ));
+            }
+        }
+    }
+}
+
+/// A sequence of entities yielded by `BatchedIter`
+pub struct Batch<'q, Q: Query> {
+    meta: &'q [EntityMeta],
+    state: ChunkIter<Q>,
+}
+
+impl<'q, Q: Query> Iterator for Batch<'q, Q> {
+    type Item = (Entity, QueryItem<'q, Q>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (id, components) = unsafe { self.state.next()? };
+        Some((
+            Entity {
+                id,
+                generation: self.meta[id as usize].generation,
+            },
+            components,
+        ))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        // Entities may be skipped individually, so only an upper bound is known
+        (0, Some(self.state.remaining()))
+    }
+}
+
+unsafe impl<'q, Q: Query + Send> Send for Batch<'q, Q> {}
+unsafe impl<'q, Q: Query + Sync> Sync for Batch<'q, Q> {}
+
+/// A query that caches the set of archetypes it matches
+///
+/// `World::query` inspects every archetype in the world each time it's executed. A
+/// `PreparedQuery` remembers which archetypes matched and only inspects archetypes created since
+/// its last use, which can be much cheaper for worlds containing many archetypes. A prepared query
+/// may be executed against different worlds, but doing so discards the cache.
+///
+/// # Example
+/// ```
+/// # use hecs::*;
+/// let mut world = World::new();
+/// let a = world.spawn((123, true));
+/// let b = world.spawn((456, "abc"));
+/// let mut query = PreparedQuery::<&i32>::new();
+/// let entities = query
+///     .query(&world)
+///     .iter()
+///     .map(|(e, &i)| (e, i))
+///     .collect::<Vec<_>>();
+/// assert_eq!(entities.len(), 2);
+/// assert!(entities.contains(&(a, 123)));
+/// assert!(entities.contains(&(b, 456)));
+/// ```
+pub struct PreparedQuery<Q: Query> {
+    // ID of the world the cache describes, and the number of its archetypes already inspected
+    memo: (u64, usize),
+    archetypes: Vec<usize>,
+    _marker: PhantomData<fn() -> Q>,
+}
+
+impl<Q: Query> PreparedQuery<Q> {
+    /// Create a prepared query which is not yet attached to any world
+    pub fn new() -> Self {
+        Self {
+            memo: (0, 0),
+            archetypes: Vec::new(),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Bring the cache up to date with `world`
+    fn update(&mut self, world: &World) {
+        if self.memo.0 != world.id() {
+            self.memo = (world.id(), 0);
+            self.archetypes.clear();
+        }
+        let archetypes = world.archetypes_inner();
+        self.archetypes.extend(
+            (self.memo.1..archetypes.len()).filter(|&i| Q::Fetch::access(&archetypes[i]).is_some()),
+        );
+        self.memo.1 = archetypes.len();
+    }
+
+    /// Query `world`, using dynamic borrow checking
     ///
-    /// Equivalent to using a query type wrapped in `With`.
+    /// Borrows are acquired immediately and released when the returned value is dropped.
     ///
-    /// # Example
-    /// ```
-    /// # use hecs::*;
-    /// let mut world = World::new();
-    /// let a = world.spawn((123, true, "abc"));
-    /// let b = world.spawn((456, false));
-    /// let c = world.spawn((42, "def"));
-    /// let entities = world.query::<&i32>()
-    ///     .with::<bool>()
-    ///     .iter()
-    ///     .map(|(e, &i)| (e, i)) // Copy out of the world
-    ///     .collect::<Vec<_>>();
-    /// assert!(entities.contains(&(a, 123)));
-    /// assert!(entities.contains(&(b, 456)));
-    /// ```
-    pub fn with<T: Component>(self) -> QueryBorrow<'w, With<T, Q>> {
-        self.transform()
+    /// Panics if the query would conflict with an existing borrow.
+    pub fn query<'q>(&'q mut self, world: &'q World) -> PreparedQueryBorrow<'q, Q> {
+        self.update(world);
+        world.profile::<Q>(world.archetypes_inner());
+        PreparedQueryBorrow::new(
+            world.entities_meta(),
+            world.archetypes_inner(),
+            &self.archetypes,
+        )
     }
 
-    /// Transform the query into one that skips entities having a certain component
+    /// Query a uniquely borrowed world
     ///
-    /// Equivalent to using a query type wrapped in `Without`.
-    ///
-    /// # Example
-    /// ```
-    /// # use hecs::*;
-    /// let mut world = World::new();
-    /// let a = world.spawn((123, true, "abc"));
-    /// let b = world.spawn((456, false));
-    /// let c = world.spawn((42, "def"));
-    /// let entities = world.query::<&i32>()
-    ///     .without::<bool>()
-    ///     .iter()
-    ///     .map(|(e, &i)| (e, i)) // Copy out of the world
-    ///     .collect::<Vec<_>>();
-    /// assert_eq!(entities, &[(c, 42)]);
-    /// ```
-    pub fn without<T: Component>(self) -> QueryBorrow<'w, Without<T, Q>> {
-        self.transform()
+    /// Like `query`, but faster because dynamic borrow checks can be skipped.
+    pub fn query_mut<'q>(&'q mut self, world: &'q mut World) -> PreparedQueryIter<'q, Q> {
+        self.update(world);
+        world.profile::<Q>(world.archetypes_inner());
+        unsafe {
+            PreparedQueryIter::new(
+                world.entities_meta(),
+                world.archetypes_inner(),
+                &self.archetypes,
+            )
+        }
     }
+}
 
-    /// Helper to change the type of the query
-    fn transform<R: Query>(mut self) -> QueryBorrow<'w, R> {
-        let x = QueryBorrow {
-            meta: self.meta,
-            archetypes: self.archetypes,
-            borrowed: self.borrowed,
+impl<Q: Query> Default for PreparedQuery<Q> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A borrow of a `World` sufficient to execute the prepared query `Q`
+///
+/// Unlike `QueryBorrow`, borrows are acquired on construction, so `iter` may be called any number
+/// of times. Borrows are released when this object is dropped.
+pub struct PreparedQueryBorrow<'q, Q: Query> {
+    meta: &'q [EntityMeta],
+    archetypes: &'q [Archetype],
+    state: &'q [usize],
+    _marker: PhantomData<Q>,
+}
+
+impl<'q, Q: Query> PreparedQueryBorrow<'q, Q> {
+    fn new(meta: &'q [EntityMeta], archetypes: &'q [Archetype], state: &'q [usize]) -> Self {
+        for &i in state {
+            let x = &archetypes[i];
+            if Q::Fetch::access(x) >= Some(Access::Read) {
+                Q::Fetch::borrow(x);
+            }
+        }
+        Self {
+            meta,
+            archetypes,
+            state,
             _marker: PhantomData,
-        };
-        // Ensure `Drop` won't fire redundantly
-        self.borrowed = false;
-        x
+        }
+    }
+
+    /// Execute the prepared query
+    // The lifetime narrowing here is required for soundness.
+    pub fn iter(&mut self) -> PreparedQueryIter<'_, Q> {
+        unsafe { PreparedQueryIter::new(self.meta, self.archetypes, self.state) }
     }
 }
 
-unsafe impl<'w, Q: Query> Send for QueryBorrow<'w, Q> {}
-unsafe impl<'w, Q: Query> Sync for QueryBorrow<'w, Q> {}
+unsafe impl<'q, Q: Query + Send> Send for PreparedQueryBorrow<'q, Q> {}
+unsafe impl<'q, Q: Query + Sync> Sync for PreparedQueryBorrow<'q, Q> {}
 
-impl<'w, Q: Query> Drop for QueryBorrow<'w, Q> {
+impl<Q: Query> Drop for PreparedQueryBorrow<'_, Q> {
     fn drop(&mut self) {
-        if self.borrowed {
-            for x in self.archetypes {
-                if Q::Fetch::access(x) >= Some(Access::Read) {
-                    Q::Fetch::release(x);
-                }
+        for &i in self.state {
+            let x = &self.archetypes[i];
+            if Q::Fetch::access(x) >= Some(Access::Read) {
+                Q::Fetch::release(x);
             }
         }
     }
 }
 
-impl<'q, 'w, Q: Query> IntoIterator for &'q mut QueryBorrow<'w, Q> {
-    type Item = (Entity, QueryItem<'q, Q>);
-    type IntoIter = QueryIter<'q, Q>;
+impl<'a, 'q, Q: Query> IntoIterator for &'a mut PreparedQueryBorrow<'q, Q> {
+    type Item = (Entity, QueryItem<'a, Q>);
+    type IntoIter = PreparedQueryIter<'a, Q>;
 
     fn into_iter(self) -> Self::IntoIter {
         self.iter()
     }
 }
 
-/// Iterator over the set of entities with the components in `Q`
-pub struct QueryIter<'q, Q: Query> {
+/// Iterator over the set of entities matched by a `PreparedQuery`
+pub struct PreparedQueryIter<'q, Q: Query> {
     meta: &'q [EntityMeta],
     archetypes: &'q [Archetype],
-    archetype_index: usize,
+    state: core::slice::Iter<'q, usize>,
     iter: ChunkIter<Q>,
 }
 
-impl<'q, Q: Query> QueryIter<'q, Q> {
+impl<'q, Q: Query> PreparedQueryIter<'q, Q> {
     /// # Safety
     ///
     /// `'q` must be sufficient to guarantee that `Q` cannot violate borrow safety, either with
     /// dynamic borrow checks or by representing exclusive access to the `World`.
-    pub(crate) unsafe fn new(meta: &'q [EntityMeta], archetypes: &'q [Archetype]) -> Self {
+    unsafe fn new(meta: &'q [EntityMeta], archetypes: &'q [Archetype], state: &'q [usize]) -> Self {
         Self {
             meta,
             archetypes,
-            archetype_index: 0,
+            state: state.iter(),
             iter: ChunkIter::empty(),
         }
     }
 }
 
-unsafe impl<'q, Q: Query> Send for QueryIter<'q, Q> {}
-unsafe impl<'q, Q: Query> Sync for QueryIter<'q, Q> {}
+unsafe impl<'q, Q: Query + Send> Send for PreparedQueryIter<'q, Q> {}
+unsafe impl<'q, Q: Query + Sync> Sync for PreparedQueryIter<'q, Q> {}
 
-impl<'q, Q: Query> Iterator for QueryIter<'q, Q> {
+impl<'q, Q: Query> Iterator for PreparedQueryIter<'q, Q> {
     type Item = (Entity, QueryItem<'q, Q>);
 
     #[inline(always)]
@@ -462,15 +2992,8 @@ impl<'q, Q: Query> Iterator for QueryIter<'q, Q> {
         loop {
             match unsafe { self.iter.next() } {
                 None => {
-                    let archetype = self.archetypes.get(self.archetype_index)?;
-                    self.archetype_index += 1;
-                    self.iter =
-                        Q::Fetch::new(archetype).map_or(ChunkIter::empty(), |fetch| ChunkIter {
-                            entities: archetype.entities(),
-                            fetch,
-                            position: 0,
-                            len: archetype.len() as usize,
-                        });
+                    let archetype = &self.archetypes[*self.state.next()?];
+                    self.iter = ChunkIter::new(archetype);
                     continue;
                 }
                 Some((id, components)) => {
@@ -487,192 +3010,178 @@ impl<'q, Q: Query> Iterator for QueryIter<'q, Q> {
     }
 
     fn size_hint(&self) -> (usize, Option<usize>) {
-        let n = self.len();
-        (n, Some(n))
-    }
-}
-
-impl<'q, Q: Query> ExactSizeIterator for QueryIter<'q, Q> {
-    fn len(&self) -> usize {
-        self.archetypes
-            .iter()
-            .filter(|&x| Q::Fetch::access(x).is_some())
-            .map(|x| x.len() as usize)
-            .sum()
+        // Entities may be skipped individually, so only an upper bound is known
+        let n = self
+            .state
+            .clone()
+            .map(|&i| self.archetypes[i].len() as usize)
+            .sum::<usize>()
+            + self.iter.remaining();
+        (0, Some(n))
     }
 }
 
-/// A query builder that's convertible directly into an iterator
-pub struct QueryMut<'q, Q: Query> {
-    iter: QueryIter<'q, Q>,
+/// A position in the traversal of a query, for visiting a bounded number of entities at a time
+///
+/// Time-sliced systems, which spread expensive work on many entities over several frames, can
+/// keep a cursor between frames and pick up where they left off with each call to `query_mut`.
+/// Once every matching entity has been visited, the cursor starts again from the beginning.
+///
+/// The world may change between calls. Archetypes created since the pass started are visited
+/// when the cursor reaches them; entities that moved to another archetype, or were moved within
+/// one by the despawning of others, may be skipped or visited twice by one pass. Passing a
+/// different world, or a world that was compacted, restarts the pass.
+///
+/// # Example
+/// ```
+/// # use hecs::*;
+/// let mut world = World::new();
+/// world.spawn_batch((0..5).map(|i| (i,)));
+/// let mut cursor = QueryCursor::<&mut i32>::new();
+/// let mut frames = Vec::new();
+/// for _ in 0..3 {
+///     let frame = cursor
+///         .query_mut(&mut world, 2)
+///         .map(|(_, &mut x)| x)
+///         .collect::<Vec<_>>();
+///     frames.push(frame);
+/// }
+/// assert_eq!(frames, [vec![0, 1], vec![2, 3], vec![4]]);
+/// assert_eq!(cursor.query_mut(&mut world, 2).count(), 2);
+/// ```
+pub struct QueryCursor<Q: Query> {
+    /// ID of the world being traversed, and the position in it of the next entity
+    world: u64,
+    archetype: usize,
+    index: u32,
+    _marker: PhantomData<fn() -> Q>,
 }
 
-impl<'q, Q: Query> QueryMut<'q, Q> {
-    pub(crate) fn new(meta: &'q [EntityMeta], archetypes: &'q mut [Archetype]) -> Self {
+impl<Q: Query> QueryCursor<Q> {
+    /// Create a cursor at the start of a pass
+    pub fn new() -> Self {
         Self {
-            iter: unsafe { QueryIter::new(meta, archetypes) },
+            world: 0,
+            archetype: 0,
+            index: 0,
+            _marker: PhantomData,
         }
     }
 
-    /// Transform the query into one that requires a certain component without borrowing it
-    ///
-    /// See `QueryBorrow::with`
-    pub fn with<T: Component>(self) -> QueryMut<'q, With<T, Q>> {
-        self.transform()
+    /// Return to the start of the pass
+    pub fn reset(&mut self) {
+        self.archetype = 0;
+        self.index = 0;
     }
 
-    /// Transform the query into one that skips entities having a certain component
-    ///
-    /// See `QueryBorrow::without`
-    pub fn without<T: Component>(self) -> QueryMut<'q, Without<T, Q>> {
-        self.transform()
+    /// Whether the next call to `query_mut` will begin a new pass
+    pub fn is_at_start(&self) -> bool {
+        self.archetype == 0 && self.index == 0
     }
 
-    /// Helper to change the type of the query
-    fn transform<R: Query>(self) -> QueryMut<'q, R> {
-        QueryMut {
-            iter: unsafe { QueryIter::new(self.iter.meta, self.iter.archetypes) },
+    /// Visit up to `budget` entities of a uniquely borrowed world, continuing from the previous
+    /// call
+    ///
+    /// Yields fewer than `budget` entities only if the pass ends, in which case the next call
+    /// starts another. The cursor advances past only those entities actually yielded.
+    pub fn query_mut<'q>(
+        &'q mut self,
+        world: &'q mut World,
+        budget: usize,
+    ) -> QueryCursorIter<'q, Q> {
+        if self.world != world.id() {
+            self.world = world.id();
+            self.reset();
         }
-    }
-}
-
-impl<'q, Q: Query> IntoIterator for QueryMut<'q, Q> {
-    type Item = <QueryIter<'q, Q> as Iterator>::Item;
-    type IntoIter = QueryIter<'q, Q>;
-
-    #[inline]
-    fn into_iter(self) -> Self::IntoIter {
-        self.iter
-    }
-}
-
-struct ChunkIter<Q: Query> {
-    entities: NonNull<u32>,
-    fetch: Q::Fetch,
-    position: usize,
-    len: usize,
-}
-
-impl<Q: Query> ChunkIter<Q> {
-    fn empty() -> Self {
-        Self {
-            entities: NonNull::dangling(),
-            fetch: Q::Fetch::dangling(),
-            position: 0,
-            len: 0,
+        world.profile::<Q>(world.archetypes_inner());
+        QueryCursorIter {
+            meta: world.entities_meta(),
+            archetypes: world.archetypes_inner(),
+            cursor: self,
+            fetch: None,
+            budget,
         }
     }
+}
 
-    #[inline]
-    unsafe fn next<'a>(&mut self) -> Option<(u32, <Q::Fetch as Fetch<'a>>::Item)> {
-        if self.position == self.len {
-            return None;
-        }
-        let entity = self.entities.as_ptr().add(self.position);
-        let item = self.fetch.get(self.position);
-        self.position += 1;
-        Some((*entity, item))
+impl<Q: Query> Default for QueryCursor<Q> {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
-/// Batched version of `QueryIter`
-pub struct BatchedIter<'q, Q: Query> {
-    _marker: PhantomData<&'q Q>,
+/// Iterator over the entities visited by [`QueryCursor::query_mut`]
+pub struct QueryCursorIter<'q, Q: Query> {
     meta: &'q [EntityMeta],
     archetypes: &'q [Archetype],
-    archetype_index: usize,
-    batch_size: u32,
-    batch: u32,
+    cursor: &'q mut QueryCursor<Q>,
+    /// Fetch for the archetype the cursor is in, if already constructed
+    fetch: Option<EnabledFetch<Q::Fetch>>,
+    /// Number of entities still to be yielded
+    budget: usize,
 }
 
-impl<'q, Q: Query> BatchedIter<'q, Q> {
-    /// # Safety
-    ///
-    /// `'q` must be sufficient to guarantee that `Q` cannot violate borrow safety, either with
-    /// dynamic borrow checks or by representing exclusive access to the `World`.
-    pub(crate) unsafe fn new(
-        meta: &'q [EntityMeta],
-        archetypes: &'q [Archetype],
-        batch_size: u32,
-    ) -> Self {
-        Self {
-            _marker: PhantomData,
-            meta,
-            archetypes,
-            archetype_index: 0,
-            batch_size,
-            batch: 0,
-        }
-    }
-}
+unsafe impl<'q, Q: Query + Send> Send for QueryCursorIter<'q, Q> {}
+unsafe impl<'q, Q: Query + Sync> Sync for QueryCursorIter<'q, Q> {}
 
-unsafe impl<'q, Q: Query> Send for BatchedIter<'q, Q> {}
-unsafe impl<'q, Q: Query> Sync for BatchedIter<'q, Q> {}
-
-impl<'q, Q: Query> Iterator for BatchedIter<'q, Q> {
-    type Item = Batch<'q, Q>;
+impl<'q, Q: Query> Iterator for QueryCursorIter<'q, Q> {
+    type Item = (Entity, QueryItem<'q, Q>);
 
     fn next(&mut self) -> Option<Self::Item> {
-        loop {
-            let archetype = self.archetypes.get(self.archetype_index)?;
-            let offset = self.batch_size * self.batch;
-            if offset >= archetype.len() {
-                self.archetype_index += 1;
-                self.batch = 0;
-                continue;
+        while self.budget != 0 {
+            let cursor = &mut *self.cursor;
+            let archetype = match self.archetypes.get(cursor.archetype) {
+                Some(x) => x,
+                None => {
+                    // End of the pass
+                    cursor.reset();
+                    self.budget = 0;
+                    return None;
+                }
+            };
+            if self.fetch.is_none() {
+                self.fetch = EnabledFetch::<Q::Fetch>::new(archetype);
             }
-            if let Some(fetch) = Q::Fetch::new(archetype) {
-                self.batch += 1;
-                return Some(Batch {
-                    meta: self.meta,
-                    state: ChunkIter {
-                        entities: archetype.entities(),
-                        fetch,
-                        len: (offset + self.batch_size.min(archetype.len() - offset)) as usize,
-                        position: offset as usize,
+            let fetch = match self.fetch {
+                Some(ref x) if cursor.index < archetype.len() => x,
+                _ => {
+                    cursor.archetype += 1;
+                    cursor.index = 0;
+                    self.fetch = None;
+                    continue;
+                }
+            };
+            let n = cursor.index as usize;
+            cursor.index += 1;
+            unsafe {
+                if fetch.should_skip(n) {
+                    continue;
+                }
+                self.budget -= 1;
+                let id = archetype.entity_id(n as u32);
+                return Some((
+                    Entity {
+                        id,
+                        generation: self.meta[id as usize].generation,
                     },
-                });
-            } else {
-                self.archetype_index += 1;
-                debug_assert_eq!(
-                    self.batch, 0,
-                    "query fetch should always reject at the first batch or not at all"
-                );
-                continue;
+                    fetch.get(n),
+                ));
             }
         }
+        None
     }
-}
-
-/// A sequence of entities yielded by `BatchedIter`
-pub struct Batch<'q, Q: Query> {
-    meta: &'q [EntityMeta],
-    state: ChunkIter<Q>,
-}
 
-impl<'q, Q: Query> Iterator for Batch<'q, Q> {
-    type Item = (Entity, QueryItem<'q, Q>);
-
-    fn next(&mut self) -> Option<Self::Item> {
-        let (id, components) = unsafe { self.state.next()? };
-        Some((
-            Entity {
-                id,
-                generation: self.meta[id as usize].generation,
-            },
-            components,
-        ))
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(self.budget))
     }
 }
 
-unsafe impl<'q, Q: Query> Send for Batch<'q, Q> {}
-unsafe impl<'q, Q: Query> Sync for Batch<'q, Q> {}
-
 macro_rules! tuple_impl {
     ($($name: ident),*) => {
         unsafe impl<'a, $($name: Fetch<'a>),*> Fetch<'a> for ($($name,)*) {
             type Item = ($($name::Item,)*);
 
+            #[allow(clippy::unused_unit)]
             fn dangling() -> Self {
                 ($($name::dangling(),)*)
             }
@@ -699,12 +3208,46 @@ macro_rules! tuple_impl {
                 $($name::release(archetype);)*
             }
 
-            #[allow(unused_variables)]
+            #[allow(unused_variables, unused_mut)]
+            fn for_each_borrow(mut f: impl FnMut(TypeId, bool)) {
+                $($name::for_each_borrow(&mut f);)*
+            }
+
+            #[allow(unused_variables, clippy::unused_unit)]
             unsafe fn get(&self, n: usize) -> Self::Item {
                 #[allow(non_snake_case)]
                 let ($($name,)*) = self;
                 ($($name.get(n),)*)
             }
+
+            #[allow(unused_variables)]
+            unsafe fn should_skip(&self, n: usize) -> bool {
+                #[allow(non_snake_case)]
+                let ($($name,)*) = self;
+                false $(|| $name.should_skip(n))*
+            }
+
+            #[allow(unused_variables)]
+            fn filters_entities(&self) -> bool {
+                #[allow(non_snake_case)]
+                let ($($name,)*) = self;
+                false $(|| $name.filters_entities())*
+            }
+
+            fn includes_disabled() -> bool {
+                false $(|| $name::includes_disabled())*
+            }
+        }
+
+        unsafe impl<'a, $($name: SliceFetch<'a>),*> SliceFetch<'a> for ($($name,)*) {
+            type Slice = ($($name::Slice,)*);
+
+            #[allow(unused_variables, clippy::unused_unit)]
+            unsafe fn slice(&self, start: usize, len: usize) -> Self::Slice {
+                #[allow(non_snake_case)]
+                let ($($name,)*) = self;
+                ($($name.slice(start, len),)*)
+            }
         }
 
         impl<$($name: Query),*> Query for ($($name,)*) {
@@ -714,7 +3257,9 @@ macro_rules! tuple_impl {
 }
 
 //smaller_tuples_too!(tuple_impl, B, A);
-smaller_tuples_too!(tuple_impl, O, N, M, L, K, J, I, H, G, F, E, D, C, B, A);
+smaller_tuples_too!(
+    tuple_impl, X, W, V, U, T, S, R, Q, P, O, N, M, L, K, J, I, H, G, F, E, D, C, B, A
+);
 
 #[cfg(test)]
 mod tests {
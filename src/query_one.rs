@@ -1,11 +1,18 @@
 use core::marker::PhantomData;
 
-use crate::query::{Fetch, With, Without};
-use crate::{Archetype, Component, Query, QueryItem};
+use crate::query::{
+    Added, Changed, Fetch, Map, QueryView, QueryViewItem, With, Without, WithoutAny,
+};
+use crate::{Archetype, Bundle, Component, Query, QueryItem};
 
 /// A borrow of a `World` sufficient to execute the query `Q` on a single entity
+///
+/// Constructed by [`World::query_one`](crate::World::query_one) or
+/// [`EntityRef::query`](crate::EntityRef::query). Every query type and transformation that
+/// `World::query` supports behaves the same way here, as if iterating over just the one entity.
 pub struct QueryOne<'a, Q: Query> {
-    archetype: &'a Archetype,
+    /// `None` for an entity that has been reserved but not yet given an archetype
+    archetype: Option<&'a Archetype>,
     index: u32,
     borrowed: bool,
     _marker: PhantomData<Q>,
@@ -17,7 +24,7 @@ impl<'a, Q: Query> QueryOne<'a, Q> {
     /// # Safety
     ///
     /// `index` must be in-bounds for `archetype`
-    pub(crate) unsafe fn new(archetype: &'a Archetype, index: u32) -> Self {
+    pub(crate) unsafe fn new(archetype: Option<&'a Archetype>, index: u32) -> Self {
         Self {
             archetype,
             index,
@@ -37,10 +44,14 @@ impl<'a, Q: Query> QueryOne<'a, Q> {
         if self.borrowed {
             panic!("called QueryOnce::get twice; construct a new query instead");
         }
+        let archetype = self.archetype?;
         unsafe {
-            let fetch = Q::Fetch::new(self.archetype)?;
+            let fetch = Q::Fetch::new(archetype)?;
+            if fetch.should_skip(self.index as usize) {
+                return None;
+            }
             self.borrowed = true;
-            Q::Fetch::borrow(self.archetype);
+            Q::Fetch::borrow(archetype);
             Some(fetch.get(self.index as usize))
         }
     }
@@ -59,6 +70,39 @@ impl<'a, Q: Query> QueryOne<'a, Q> {
         self.transform()
     }
 
+    /// Transform the query into one that skips entities having any of the components in `B`
+    ///
+    /// See `QueryBorrow::without_any` for details.
+    pub fn without_any<B: Bundle>(self) -> QueryOne<'a, WithoutAny<B, Q>> {
+        self.transform()
+    }
+
+    /// Transform the query into one that fails if the entity's `T` component was not recently
+    /// added
+    ///
+    /// See `QueryBorrow::added` for details.
+    pub fn added<T: Component>(self) -> QueryOne<'a, Added<T, Q>> {
+        self.transform()
+    }
+
+    /// Transform the query into one that fails if the entity's `T` component was not recently
+    /// changed
+    ///
+    /// See `QueryBorrow::changed` for details.
+    pub fn changed<T: Component>(self) -> QueryOne<'a, Changed<T, Q>> {
+        self.transform()
+    }
+
+    /// Transform the query into one producing its result as transformed by `V`
+    ///
+    /// See `QueryBorrow::map` for details.
+    pub fn map<V>(self) -> QueryOne<'a, Map<V>>
+    where
+        V: QueryView<Query = Q> + for<'b> QueryViewItem<'b>,
+    {
+        self.transform()
+    }
+
     /// Helper to change the type of the query
     fn transform<R: Query>(mut self) -> QueryOne<'a, R> {
         let x = QueryOne {
@@ -75,11 +119,11 @@ impl<'a, Q: Query> QueryOne<'a, Q> {
 
 impl<Q: Query> Drop for QueryOne<'_, Q> {
     fn drop(&mut self) {
-        if self.borrowed {
-            Q::Fetch::release(self.archetype);
+        if let (true, Some(archetype)) = (self.borrowed, self.archetype) {
+            Q::Fetch::release(archetype);
         }
     }
 }
 
-unsafe impl<Q: Query> Send for QueryOne<'_, Q> {}
-unsafe impl<Q: Query> Sync for QueryOne<'_, Q> {}
+unsafe impl<Q: Query + Send> Send for QueryOne<'_, Q> {}
+unsafe impl<Q: Query + Sync> Sync for QueryOne<'_, Q> {}
@@ -0,0 +1,220 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use crate::alloc::boxed::Box;
+#[cfg(feature = "std")]
+use crate::alloc::sync::Arc;
+#[cfg(feature = "std")]
+use crate::alloc::vec::Vec;
+
+use hashbrown::HashMap;
+
+use crate::entity_builder::CloneFn;
+use crate::snapshot::EqFn;
+#[cfg(feature = "std")]
+use crate::BinaryRegistry;
+use crate::{StableComponent, StableTypeId, TypeInfo, World};
+
+/// Component types contributed at runtime, such as by dynamically loaded plugins, and how to
+/// clone and save them
+///
+/// A plugin populates a registry handed to it by the host while loading, instantiating the
+/// generic methods in its own build. The host then passes the registry to
+/// [`World::register_components`] to address the types by name and [`StableTypeId`], to
+/// [`SnapshotRegistry::register_from`](crate::SnapshotRegistry::register_from) to snapshot and
+/// clone them, and to [`BinaryRegistry::register_from`] to save and load them, without its own
+/// code ever naming them.
+///
+/// # Example
+/// ```
+/// # use hecs::*;
+/// #[derive(Clone, PartialEq)]
+/// struct Door(bool);
+/// impl StableComponent for Door {
+///     const STABLE_NAME: &'static str = "plugin::Door";
+/// }
+///
+/// // In the plugin
+/// fn load(registry: &mut ComponentRegistry) {
+///     registry.register_eq::<Door>();
+/// }
+///
+/// // In the host
+/// let mut registry = ComponentRegistry::new();
+/// load(&mut registry);
+/// let mut world = World::new();
+/// world.register_components(&registry);
+/// let mut snapshots = SnapshotRegistry::new();
+/// snapshots.register_from(&registry);
+///
+/// let a = world.spawn((Door(true),));
+/// let b = world.clone_entity(&snapshots, a).unwrap();
+/// assert!(world.get_named(b, "plugin::Door").is_some());
+/// ```
+#[derive(Clone, Default)]
+pub struct ComponentRegistry {
+    types: HashMap<StableTypeId, RegisteredComponent>,
+}
+
+/// A component type in a [`ComponentRegistry`]
+#[derive(Clone)]
+pub struct RegisteredComponent {
+    name: Box<str>,
+    info: TypeInfo,
+    clone: Option<(CloneFn, Option<EqFn>)>,
+    /// Registers the type's encoding with a `BinaryRegistry`
+    #[cfg(feature = "std")]
+    binary: Option<Arc<RegisterBinary>>,
+}
+
+#[cfg(feature = "std")]
+type RegisterBinary = dyn Fn(&mut BinaryRegistry) + Send + Sync;
+
+impl ComponentRegistry {
+    /// Create an empty registry
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add `T`, keeping anything already known about it
+    ///
+    /// # Panics
+    ///
+    /// Panics if `T::STABLE_TYPE_ID` is already registered for another type.
+    pub fn register<T: StableComponent>(&mut self) -> &mut Self {
+        self.entry::<T>();
+        self
+    }
+
+    /// Add `T`, allowing it to be cloned and captured by snapshots
+    ///
+    /// See `register`.
+    pub fn register_clone<T: StableComponent + Clone>(&mut self) -> &mut Self {
+        self.entry::<T>().clone = Some((clone_ptr::<T>, None));
+        self
+    }
+
+    /// Add `T`, allowing it to be cloned, captured by snapshots, and compared by diffs
+    ///
+    /// See `register`.
+    pub fn register_eq<T: StableComponent + Clone + PartialEq>(&mut self) -> &mut Self {
+        unsafe fn eq_ptr<T: PartialEq>(x: *const u8, y: *const u8) -> bool {
+            *x.cast::<T>() == *y.cast::<T>()
+        }
+
+        self.entry::<T>().clone = Some((clone_ptr::<T>, Some(eq_ptr::<T>)));
+        self
+    }
+
+    /// Add `T`, allowing it to be saved by `World::save_to`, as by
+    /// [`BinaryRegistry::register_stable`]
+    ///
+    /// See `register`.
+    #[cfg(feature = "std")]
+    pub fn register_binary<T: StableComponent>(
+        &mut self,
+        version: u32,
+        encode: fn(&T, &mut Vec<u8>),
+        decode: fn(&[u8]) -> Option<T>,
+    ) -> &mut Self {
+        self.entry::<T>().binary = Some(Arc::new(move |registry: &mut BinaryRegistry| {
+            registry.register_stable::<T>(version, encode, decode);
+        }));
+        self
+    }
+
+    fn entry<T: StableComponent>(&mut self) -> &mut RegisteredComponent {
+        let info = TypeInfo::of_stable::<T>();
+        let entry = self
+            .types
+            .entry(T::STABLE_TYPE_ID)
+            .or_insert_with(|| RegisteredComponent {
+                name: T::STABLE_NAME.into(),
+                info,
+                clone: None,
+                #[cfg(feature = "std")]
+                binary: None,
+            });
+        assert!(
+            entry.info.id() == info.id(),
+            "stable ID of component name {:?} already registered for another type",
+            T::STABLE_NAME
+        );
+        entry
+    }
+
+    /// The type identified by `id`, if registered
+    pub fn get(&self, id: StableTypeId) -> Option<&RegisteredComponent> {
+        self.types.get(&id)
+    }
+
+    /// Every registered type, in arbitrary order
+    pub fn iter(&self) -> impl Iterator<Item = &RegisteredComponent> + '_ {
+        self.types.values()
+    }
+
+    /// Number of registered types
+    pub fn len(&self) -> usize {
+        self.types.len()
+    }
+
+    /// Whether no types are registered
+    pub fn is_empty(&self) -> bool {
+        self.types.is_empty()
+    }
+}
+
+impl RegisteredComponent {
+    /// The type's `STABLE_NAME`
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Metadata of the type, including its `StableTypeId`
+    pub fn type_info(&self) -> TypeInfo {
+        self.info
+    }
+
+    /// Whether components of the type may be cloned
+    pub fn is_cloneable(&self) -> bool {
+        self.clone.is_some()
+    }
+
+    /// How to clone and compare components of the type, if registered
+    pub(crate) fn cloner(&self) -> Option<(CloneFn, Option<EqFn>)> {
+        self.clone
+    }
+
+    /// Register the type's encoding with `registry`, if it has one
+    #[cfg(feature = "std")]
+    pub(crate) fn register_binary(&self, registry: &mut BinaryRegistry) {
+        if let Some(f) = &self.binary {
+            f(registry);
+        }
+    }
+}
+
+unsafe fn clone_ptr<T: Clone>(src: *const u8, dst: *mut u8) {
+    dst.cast::<T>().write((*src.cast::<T>()).clone());
+}
+
+impl World {
+    /// Make every type in `registry` addressable by name and `StableTypeId`, as if by
+    /// `register_stable`
+    ///
+    /// Lets a host drive components contributed by plugins through `get_named`, `insert_named`,
+    /// `named_query`, `stable_type`, and the like.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a type's name is already registered for a different type.
+    pub fn register_components(&mut self, registry: &ComponentRegistry) {
+        for ty in registry.iter() {
+            self.register_named_inner(&ty.name, ty.info);
+        }
+    }
+}
@@ -0,0 +1,111 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use core::marker::PhantomData;
+
+use crate::{Component, Entity, Parent, Query, QueryAccess, QueryItem, QueryIter, World};
+
+/// A component referring to another entity, which [`World::query_relates_mut`] can follow
+pub trait Relation: Component {
+    /// The entity referred to
+    fn target(&self) -> Entity;
+}
+
+impl Relation for Parent {
+    fn target(&self) -> Entity {
+        self.0
+    }
+}
+
+impl World {
+    /// Query a uniquely borrowed world for `Q` on every entity with an `R` relation, alongside `T`
+    /// on the entity it refers to
+    ///
+    /// Each item pairs `Q`'s item with `T`'s item for the target, or `None` if the target was
+    /// despawned or doesn't satisfy `T`. Like `query_nested_mut` followed by a lookup for each
+    /// item, but with no borrow checks per item, and the target's item may be held alongside the
+    /// rest.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `T` borrows any component uniquely, since many entities may refer to the same
+    /// target, if `Q` borrows `R` uniquely, or if one query borrows uniquely a component type that
+    /// the other borrows at all.
+    ///
+    /// # Example
+    /// ```
+    /// # use hecs::*;
+    /// struct Target(Entity);
+    /// impl Relation for Target {
+    ///     fn target(&self) -> Entity {
+    ///         self.0
+    ///     }
+    /// }
+    /// struct Aim(f32);
+    ///
+    /// let mut world = World::new();
+    /// let a = world.spawn((1.0_f32,));
+    /// let b = world.spawn((Aim(0.0), Target(a)));
+    /// let dead = world.spawn((2.0_f32,));
+    /// let c = world.spawn((Aim(0.0), Target(dead)));
+    /// world.despawn(dead).unwrap();
+    /// for (_, (aim, target)) in world.query_relates_mut::<&mut Aim, Target, &f32>() {
+    ///     aim.0 = target.copied().unwrap_or(-1.0);
+    /// }
+    /// assert_eq!(world.get::<Aim>(b).unwrap().0, 1.0);
+    /// assert_eq!(world.get::<Aim>(c).unwrap().0, -1.0);
+    /// ```
+    pub fn query_relates_mut<Q: Query, R: Relation, T: Query>(
+        &mut self,
+    ) -> QueryRelates<'_, Q, R, T> {
+        let target = QueryAccess::of::<T>();
+        assert!(
+            target.writes().is_empty(),
+            "related query must not borrow uniquely"
+        );
+        assert!(
+            QueryAccess::of::<&R>().is_compatible(&QueryAccess::of::<Q>()),
+            "query must not borrow the relation uniquely"
+        );
+        assert!(
+            QueryAccess::of::<Q>().is_compatible(&target),
+            "queries have conflicting access"
+        );
+        self.flush();
+        self.profile::<(&R, Q)>(self.archetypes_inner());
+        self.profile::<T>(self.archetypes_inner());
+        let this = &*self;
+        // Safe because the queries' accesses are compatible, `T` is read-only, and `self` is
+        // uniquely borrowed
+        QueryRelates {
+            world: this,
+            iter: unsafe { QueryIter::new(this.entities_meta(), this.archetypes_inner()) },
+            _marker: PhantomData,
+        }
+    }
+}
+
+/// Iterator over the entities with a [`Relation`], returned by [`World::query_relates_mut`]
+pub struct QueryRelates<'w, Q: Query, R: Relation, T: Query> {
+    world: &'w World,
+    iter: QueryIter<'w, (&'w R, Q)>,
+    _marker: PhantomData<fn(T)>,
+}
+
+impl<'w, Q: Query, R: Relation, T: Query> Iterator for QueryRelates<'w, Q, R, T> {
+    type Item = (Entity, (QueryItem<'w, Q>, Option<QueryItem<'w, T>>));
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (entity, (relation, item)) = self.iter.next()?;
+        let target = unsafe { self.world.query_one_unchecked::<T>(relation.target()).ok() };
+        Some((entity, (item, target)))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
@@ -0,0 +1,120 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use crate::alloc::boxed::Box;
+use core::any::{type_name, Any, TypeId};
+use core::cell::UnsafeCell;
+use core::ops::{Deref, DerefMut};
+
+use crate::archetype::TypeIdMap;
+use crate::atomic_borrow::AtomicBorrow;
+use crate::Component;
+
+/// Singleton values stored alongside a `World`'s entities, at most one of each type
+#[derive(Default)]
+pub(crate) struct Resources {
+    map: TypeIdMap<Resource>,
+}
+
+struct Resource {
+    borrow: AtomicBorrow,
+    value: Box<UnsafeCell<dyn Any + Send + Sync>>,
+}
+
+impl Resources {
+    pub fn insert<T: Component>(&mut self, value: T) -> Option<T> {
+        let old = self.map.insert(
+            TypeId::of::<T>(),
+            Resource {
+                borrow: AtomicBorrow::new(),
+                value: Box::new(UnsafeCell::new(value)),
+            },
+        )?;
+        Some(into_inner(old))
+    }
+
+    pub fn remove<T: Component>(&mut self) -> Option<T> {
+        self.map.remove(&TypeId::of::<T>()).map(into_inner)
+    }
+
+    pub fn contains<T: Component>(&self) -> bool {
+        self.map.contains_key(&TypeId::of::<T>())
+    }
+
+    pub fn get<T: Component>(&self) -> Option<ResourceRef<'_, T>> {
+        let resource = self.map.get(&TypeId::of::<T>())?;
+        if !resource.borrow.borrow() {
+            panic!("{} resource already borrowed uniquely", type_name::<T>());
+        }
+        let value = unsafe { &*resource.value.get() };
+        Some(ResourceRef {
+            borrow: &resource.borrow,
+            value: value.downcast_ref().unwrap(),
+        })
+    }
+
+    pub fn get_mut<T: Component>(&self) -> Option<ResourceRefMut<'_, T>> {
+        let resource = self.map.get(&TypeId::of::<T>())?;
+        if !resource.borrow.borrow_mut() {
+            panic!("{} resource already borrowed", type_name::<T>());
+        }
+        let value = unsafe { &mut *resource.value.get() };
+        Some(ResourceRefMut {
+            borrow: &resource.borrow,
+            value: value.downcast_mut().unwrap(),
+        })
+    }
+}
+
+fn into_inner<T: Component>(resource: Resource) -> T {
+    // Safe because resources are keyed by the `TypeId` of their value
+    unsafe { Box::from_raw(Box::into_raw(resource.value).cast::<UnsafeCell<T>>()).into_inner() }
+}
+
+/// Shared borrow of a `World`'s resource
+pub struct ResourceRef<'a, T: Component> {
+    borrow: &'a AtomicBorrow,
+    value: &'a T,
+}
+
+impl<T: Component> Drop for ResourceRef<'_, T> {
+    fn drop(&mut self) {
+        self.borrow.release();
+    }
+}
+
+impl<T: Component> Deref for ResourceRef<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        self.value
+    }
+}
+
+/// Unique borrow of a `World`'s resource
+pub struct ResourceRefMut<'a, T: Component> {
+    borrow: &'a AtomicBorrow,
+    value: &'a mut T,
+}
+
+impl<T: Component> Drop for ResourceRefMut<'_, T> {
+    fn drop(&mut self) {
+        self.borrow.release_mut();
+    }
+}
+
+impl<T: Component> Deref for ResourceRefMut<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        self.value
+    }
+}
+
+impl<T: Component> DerefMut for ResourceRefMut<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.value
+    }
+}
@@ -0,0 +1,384 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! A minimal executor running functions over a `World`, in parallel where their accesses allow
+//!
+//! Each system is a function whose arguments are [`SystemParam`]s: queries, resources, and a
+//! [`CommandBuffer`] for changes that must wait for exclusive access. A [`Schedule`] runs its
+//! systems in the order they were added, except that consecutive systems whose accesses are
+//! compatible run concurrently on rayon's thread pool. Command buffers are applied at sync points
+//! and at the end of each run.
+//!
+//! # Example
+//! ```
+//! # use hecs::*;
+//! use hecs::schedule::Schedule;
+//!
+//! struct Position(f32);
+//! struct Velocity(f32);
+//! struct Time(f32);
+//! struct Despawned(u32);
+//!
+//! let mut world = World::new();
+//! world.insert_resource(Time(0.5));
+//! world.insert_resource(Despawned(0));
+//! let a = world.spawn((Position(0.0), Velocity(4.0)));
+//! let b = world.spawn((Position(100.0), Velocity(4.0)));
+//!
+//! let mut schedule = Schedule::new();
+//! schedule
+//!     .add_system(
+//!         |query: QueryMut<(&mut Position, &Velocity)>, time: ResourceRef<Time>| {
+//!             for (_, (pos, vel)) in query {
+//!                 pos.0 += vel.0 * time.0;
+//!             }
+//!         },
+//!     )
+//!     .add_system(|query: QueryMut<&Position>, cmd: &mut CommandBuffer| {
+//!         for (entity, pos) in query {
+//!             if pos.0 > 50.0 {
+//!                 cmd.despawn(entity);
+//!             }
+//!         }
+//!     })
+//!     .add_sync()
+//!     .add_system(|query: QueryMut<()>, mut despawned: ResourceRefMut<Despawned>| {
+//!         despawned.0 = 2 - query.into_iter().count() as u32;
+//!     });
+//! schedule.run(&mut world);
+//!
+//! assert_eq!(world.get::<Position>(a).unwrap().0, 2.0);
+//! assert!(!world.contains(b));
+//! assert_eq!(world.resource::<Despawned>().unwrap().0, 1);
+//! ```
+
+use crate::alloc::{boxed::Box, vec::Vec};
+use core::any::{type_name, TypeId};
+use core::marker::PhantomData;
+use core::mem;
+
+use crate::{
+    CommandBuffer, Component, Query, QueryAccess, QueryMut, ResourceRef, ResourceRefMut,
+    UnsafeWorldCell, World,
+};
+
+/// The component and resource types a system may borrow
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct SystemAccess {
+    components: QueryAccess,
+    resources: QueryAccess,
+}
+
+impl SystemAccess {
+    /// Access to the components read or written by `Q`
+    pub fn query<Q: Query>() -> Self {
+        Self {
+            components: QueryAccess::of::<Q>(),
+            resources: QueryAccess::default(),
+        }
+    }
+
+    /// Access to a resource of type `T`, unique if `unique` is set
+    pub fn resource<T: Component>(unique: bool) -> Self {
+        let mut resources = QueryAccess::default();
+        resources.insert(TypeId::of::<T>(), unique);
+        Self {
+            components: QueryAccess::default(),
+            resources,
+        }
+    }
+
+    /// The component types borrowed
+    pub fn components(&self) -> &QueryAccess {
+        &self.components
+    }
+
+    /// The resource types borrowed
+    pub fn resources(&self) -> &QueryAccess {
+        &self.resources
+    }
+
+    /// Whether a system with this access can run concurrently with one having `other`
+    pub fn is_compatible(&self, other: &SystemAccess) -> bool {
+        self.components.is_compatible(&other.components)
+            && self.resources.is_compatible(&other.resources)
+    }
+
+    /// Record every borrow made with `other`, which must be compatible
+    fn merge<T>(&mut self, other: &SystemAccess) {
+        assert!(
+            self.is_compatible(other),
+            "system parameter {} conflicts with an earlier parameter",
+            type_name::<T>()
+        );
+        self.components.extend(&other.components);
+        self.resources.extend(&other.resources);
+    }
+}
+
+/// A value that a system may take as an argument, fetched from the `World` each time it runs
+///
+/// Implemented for [`QueryMut`], [`ResourceRef`], [`ResourceRefMut`], `&mut CommandBuffer`, and
+/// tuples of parameters.
+///
+/// # Safety
+///
+/// `access` must report every borrow that `get` makes without a dynamic borrow check.
+pub unsafe trait SystemParam {
+    /// Storage carried by each system from one run to the next
+    type State: Default + Send + 'static;
+
+    /// The argument itself, borrowing from a world for `'w`
+    type Item<'w>;
+
+    /// The component and resource types that `get` may borrow
+    fn access() -> SystemAccess;
+
+    /// Fetch the argument from `world`
+    ///
+    /// # Safety
+    ///
+    /// Nothing borrowed elsewhere during `'w` may conflict with `access`.
+    unsafe fn get<'w>(state: &'w mut Self::State, world: UnsafeWorldCell<'w>) -> Self::Item<'w>;
+
+    /// Apply changes recorded in `state` at a sync point
+    fn apply(state: &mut Self::State, world: &mut World) {
+        let _ = (state, world);
+    }
+}
+
+unsafe impl<Q: Query> SystemParam for QueryMut<'_, Q> {
+    type State = ();
+    type Item<'w> = QueryMut<'w, Q>;
+
+    fn access() -> SystemAccess {
+        SystemAccess::query::<Q>()
+    }
+
+    unsafe fn get<'w>((): &'w mut (), world: UnsafeWorldCell<'w>) -> QueryMut<'w, Q> {
+        world.query::<Q>()
+    }
+}
+
+/// Panics if the resource doesn't exist when the system runs
+unsafe impl<T: Component> SystemParam for ResourceRef<'_, T> {
+    type State = ();
+    type Item<'w> = ResourceRef<'w, T>;
+
+    fn access() -> SystemAccess {
+        SystemAccess::resource::<T>(false)
+    }
+
+    unsafe fn get<'w>((): &'w mut (), world: UnsafeWorldCell<'w>) -> ResourceRef<'w, T> {
+        world
+            .world()
+            .resource::<T>()
+            .unwrap_or_else(|| panic!("no {} resource", type_name::<T>()))
+    }
+}
+
+/// Panics if the resource doesn't exist when the system runs
+unsafe impl<T: Component> SystemParam for ResourceRefMut<'_, T> {
+    type State = ();
+    type Item<'w> = ResourceRefMut<'w, T>;
+
+    fn access() -> SystemAccess {
+        SystemAccess::resource::<T>(true)
+    }
+
+    unsafe fn get<'w>((): &'w mut (), world: UnsafeWorldCell<'w>) -> ResourceRefMut<'w, T> {
+        world
+            .world()
+            .resource_mut::<T>()
+            .unwrap_or_else(|| panic!("no {} resource", type_name::<T>()))
+    }
+}
+
+/// A buffer owned by the system, applied at the next sync point
+unsafe impl SystemParam for &'_ mut CommandBuffer {
+    type State = CommandBuffer;
+    type Item<'w> = &'w mut CommandBuffer;
+
+    fn access() -> SystemAccess {
+        SystemAccess::default()
+    }
+
+    unsafe fn get<'w>(state: &'w mut CommandBuffer, _: UnsafeWorldCell<'w>) -> Self::Item<'w> {
+        state
+    }
+
+    fn apply(state: &mut CommandBuffer, world: &mut World) {
+        state.run_on(world);
+    }
+}
+
+macro_rules! tuple_impl {
+    ($($name: ident),*) => {
+        unsafe impl<$($name: SystemParam),*> SystemParam for ($($name,)*) {
+            type State = ($($name::State,)*);
+            type Item<'w> = ($($name::Item<'w>,)*);
+
+            #[allow(unused_mut)]
+            fn access() -> SystemAccess {
+                let mut access = SystemAccess::default();
+                $(access.merge::<$name>(&$name::access());)*
+                access
+            }
+
+            #[allow(unused_variables, non_snake_case, clippy::unused_unit)]
+            unsafe fn get<'w>(state: &'w mut Self::State, world: UnsafeWorldCell<'w>) -> Self::Item<'w> {
+                let ($($name,)*) = state;
+                ($($name::get($name, world),)*)
+            }
+
+            #[allow(unused_variables, non_snake_case)]
+            fn apply(state: &mut Self::State, world: &mut World) {
+                let ($($name,)*) = state;
+                $($name::apply($name, world);)*
+            }
+        }
+    };
+}
+
+smaller_tuples_too!(tuple_impl, L, K, J, I, H, G, F, E, D, C, B, A);
+
+/// A function that can be run by a [`Schedule`], taking the parameters `P`
+pub trait SystemFn<P: SystemParam>: Send + 'static {
+    /// Call the function with its fetched parameters
+    fn run(&mut self, params: P::Item<'_>);
+}
+
+macro_rules! system_fn_impl {
+    ($($name: ident),*) => {
+        impl<Func, $($name: SystemParam),*> SystemFn<($($name,)*)> for Func
+        where
+            Func: Send + 'static,
+            for<'a> &'a mut Func: FnMut($($name),*) + FnMut($($name::Item<'_>),*),
+        {
+            #[allow(non_snake_case, clippy::too_many_arguments)]
+            fn run(&mut self, params: <($($name,)*) as SystemParam>::Item<'_>) {
+                // Pins down which of the function's signatures is called
+                fn call<$($name),*>(mut f: impl FnMut($($name),*), $($name: $name),*) {
+                    f($($name),*)
+                }
+                let ($($name,)*) = params;
+                call(self, $($name),*)
+            }
+        }
+    };
+}
+
+smaller_tuples_too!(system_fn_impl, L, K, J, I, H, G, F, E, D, C, B, A);
+
+/// Systems to be run over a `World`, divided by sync points
+///
+/// See the [module documentation](self).
+#[derive(Default)]
+pub struct Schedule {
+    /// Systems between consecutive sync points
+    stages: Vec<Vec<Box<dyn System>>>,
+}
+
+impl Schedule {
+    /// Create a schedule with no systems
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Run `system` after every system added so far, or concurrently with those it's compatible
+    /// with since the last one it conflicts with
+    ///
+    /// Panics if two of the system's parameters conflict, e.g. by both writing the same
+    /// component type.
+    pub fn add_system<P: SystemParam + 'static>(&mut self, system: impl SystemFn<P>) -> &mut Self {
+        if self.stages.is_empty() {
+            self.stages.push(Vec::new());
+        }
+        self.stages
+            .last_mut()
+            .unwrap()
+            .push(Box::new(FunctionSystem::<_, P> {
+                f: system,
+                state: P::State::default(),
+                access: P::access(),
+                _marker: PhantomData,
+            }));
+        self
+    }
+
+    /// Apply the command buffers of every system added so far before running any added later
+    pub fn add_sync(&mut self) -> &mut Self {
+        self.stages.push(Vec::new());
+        self
+    }
+
+    /// Run every system once, then apply their command buffers
+    pub fn run(&mut self, world: &mut World) {
+        world.flush();
+        for stage in &mut self.stages {
+            let mut systems = &mut stage[..];
+            while !systems.is_empty() {
+                // Run the longest prefix of mutually compatible systems together
+                let n = (1..systems.len())
+                    .find(|&i| {
+                        let access = systems[i].access();
+                        systems[..i]
+                            .iter()
+                            .any(|x| !x.access().is_compatible(access))
+                    })
+                    .unwrap_or(systems.len());
+                let (batch, rest) = mem::take(&mut systems).split_at_mut(n);
+                systems = rest;
+                let world = world.as_unsafe_cell();
+                match batch {
+                    // Safe because systems in the same batch have compatible access
+                    [system] => unsafe { system.run(world) },
+                    _ => rayon::scope(|s| {
+                        for system in batch {
+                            s.spawn(move |_| unsafe { system.run(world) });
+                        }
+                    }),
+                }
+            }
+            for system in stage.iter_mut() {
+                system.apply(world);
+            }
+        }
+    }
+}
+
+trait System: Send {
+    fn access(&self) -> &SystemAccess;
+
+    /// # Safety
+    ///
+    /// Nothing borrowed elsewhere may conflict with `access`.
+    unsafe fn run(&mut self, world: UnsafeWorldCell<'_>);
+
+    fn apply(&mut self, world: &mut World);
+}
+
+struct FunctionSystem<F, P: SystemParam> {
+    f: F,
+    state: P::State,
+    access: SystemAccess,
+    _marker: PhantomData<fn() -> P>,
+}
+
+impl<F: SystemFn<P>, P: SystemParam> System for FunctionSystem<F, P> {
+    fn access(&self) -> &SystemAccess {
+        &self.access
+    }
+
+    unsafe fn run(&mut self, world: UnsafeWorldCell<'_>) {
+        self.f.run(P::get(&mut self.state, world));
+    }
+
+    fn apply(&mut self, world: &mut World) {
+        P::apply(&mut self.state, world);
+    }
+}
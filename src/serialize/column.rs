@@ -0,0 +1,800 @@
+//! Fast column-major serialization
+//!
+//! Stores each archetype's components one column at a time, and loads them directly into
+//! [`ColumnBatch`]es spawned with [`World::spawn_column_batch_at`]. Much faster to load than
+//! [`row`](super::row) serialization for large worlds, but less convenient to inspect or edit by
+//! hand.
+//!
+//! In terms of the serde data model, we treat a [`World`] as a sequence of archetypes, and each
+//! archetype as a 4-tuple of the number of serialized component types, a sequence of entity
+//! handles, a tuple of user-controlled component IDs, and a tuple of columns, themselves tuples of
+//! component values in the same order as the handles. Backwards-incompatible changes to this model
+//! are subject to the same semantic versioning stability guarantees as the hecs API.
+
+use crate::alloc::vec::Vec;
+use core::{cell::RefCell, fmt, slice};
+
+use serde::{
+    de::{self, DeserializeSeed, SeqAccess, Visitor},
+    ser::{SerializeSeq, SerializeTuple},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+
+use crate::{
+    Archetype, BatchWriter, ColumnBatch, ColumnBatchBuilder, ColumnBatchType, Component, Entity,
+    World,
+};
+
+/// Implements serialization of archetypes
+///
+/// Data external to the [`World`] can be exposed during serialization by storing references inside
+/// the struct implementing this trait.
+///
+/// # Example
+///
+/// ```
+/// # use serde::{Serialize, Deserialize};
+/// # #[derive(Serialize)]
+/// # struct Position([f32; 3]);
+/// # #[derive(Serialize)]
+/// # struct Velocity([f32; 3]);
+/// use std::any::TypeId;
+/// use hecs::{*, serialize::column::*};
+///
+/// // Could include references to external state for use by serialization methods
+/// struct Context;
+/// #[derive(Serialize, Deserialize)]
+/// enum ComponentId { Position, Velocity }
+///
+/// impl SerializeContext for Context {
+///     fn component_count(&self, archetype: &Archetype) -> usize {
+///         archetype.component_types()
+///             .filter(|&t| t == TypeId::of::<Position>() || t == TypeId::of::<Velocity>())
+///             .count()
+///     }
+///
+///     fn serialize_component_ids<S: serde::ser::SerializeTuple>(
+///         &mut self,
+///         archetype: &Archetype,
+///         out: &mut S,
+///     ) -> Result<(), S::Error> {
+///         try_serialize_id::<Position, _, _>(archetype, &ComponentId::Position, out)?;
+///         try_serialize_id::<Velocity, _, _>(archetype, &ComponentId::Velocity, out)?;
+///         Ok(())
+///     }
+///
+///     fn serialize_components<S: serde::ser::SerializeTuple>(
+///         &mut self,
+///         archetype: &Archetype,
+///         out: &mut S,
+///     ) -> Result<(), S::Error> {
+///         // Columns must be serialized in the same order as their IDs
+///         try_serialize::<Position, _>(archetype, out)?;
+///         try_serialize::<Velocity, _>(archetype, out)?;
+///         Ok(())
+///     }
+/// }
+/// ```
+pub trait SerializeContext {
+    /// Number of component types from `archetype` that will be serialized
+    ///
+    /// Must equal the number of elements written by both `serialize_component_ids` and
+    /// `serialize_components`.
+    fn component_count(&self, archetype: &Archetype) -> usize;
+
+    /// Serialize the IDs of the component types from `archetype` that will be serialized
+    fn serialize_component_ids<S: SerializeTuple>(
+        &mut self,
+        archetype: &Archetype,
+        out: &mut S,
+    ) -> Result<(), S::Error>;
+
+    /// Serialize one column for each component type from `archetype`, in the same order as
+    /// `serialize_component_ids`
+    fn serialize_components<S: SerializeTuple>(
+        &mut self,
+        archetype: &Archetype,
+        out: &mut S,
+    ) -> Result<(), S::Error>;
+}
+
+/// If `archetype` has `T` components, serialize `id` into `out`
+///
+/// Convenience method for [`SerializeContext::serialize_component_ids`] implementations.
+pub fn try_serialize_id<T: Component, I: Serialize + ?Sized, S: SerializeTuple>(
+    archetype: &Archetype,
+    id: &I,
+    out: &mut S,
+) -> Result<(), S::Error> {
+    if archetype.has::<T>() {
+        out.serialize_element(id)?;
+    }
+    Ok(())
+}
+
+/// If `archetype` has `T` components, serialize them as a single column into `out`
+///
+/// Convenience method for [`SerializeContext::serialize_components`] implementations.
+///
+/// Panics if the components are already uniquely borrowed.
+pub fn try_serialize<T: Component + Serialize, S: SerializeTuple>(
+    archetype: &Archetype,
+    out: &mut S,
+) -> Result<(), S::Error> {
+    if let Some(base) = archetype.get::<T>() {
+        archetype.borrow::<T>();
+        let column = unsafe { slice::from_raw_parts(base.as_ptr(), archetype.len() as usize) };
+        let result = out.serialize_element(&SerializeColumn(column));
+        archetype.release::<T>();
+        result?;
+    }
+    Ok(())
+}
+
+/// Serialize a [`World`] through a [`SerializeContext`] to a [`Serializer`]
+pub fn serialize<C, S>(world: &World, context: &mut C, serializer: S) -> Result<S::Ok, S::Error>
+where
+    C: SerializeContext,
+    S: Serializer,
+{
+    trace_span!("serialize_world", format = "column", entities = world.len());
+    let archetypes = world.archetypes_inner().iter().filter(|x| !x.is_empty());
+    let mut seq = serializer.serialize_seq(Some(archetypes.clone().count()))?;
+    for archetype in archetypes {
+        seq.serialize_element(&SerializeArchetype {
+            world,
+            archetype,
+            context: RefCell::new(&mut *context),
+        })?;
+    }
+    seq.end()
+}
+
+struct SerializeArchetype<'a, C> {
+    world: &'a World,
+    archetype: &'a Archetype,
+    context: RefCell<&'a mut C>,
+}
+
+impl<C: SerializeContext> Serialize for SerializeArchetype<'_, C> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut context = self.context.borrow_mut();
+        let count = context.component_count(self.archetype);
+
+        let mut tuple = serializer.serialize_tuple(4)?;
+        tuple.serialize_element(&(count as u32))?;
+        tuple.serialize_element(&SerializeEntities {
+            world: self.world,
+            archetype: self.archetype,
+        })?;
+        tuple.serialize_element(&SerializeComponentIds {
+            archetype: self.archetype,
+            count,
+            context: RefCell::new(&mut **context),
+        })?;
+        tuple.serialize_element(&SerializeComponents {
+            archetype: self.archetype,
+            count,
+            context: RefCell::new(&mut **context),
+        })?;
+        tuple.end()
+    }
+}
+
+struct SerializeEntities<'a> {
+    world: &'a World,
+    archetype: &'a Archetype,
+}
+
+impl Serialize for SerializeEntities<'_> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let meta = self.world.entities_meta();
+        let mut seq = serializer.serialize_seq(Some(self.archetype.len() as usize))?;
+        for index in 0..self.archetype.len() {
+            let id = self.archetype.entity_id(index);
+            seq.serialize_element(&Entity {
+                id,
+                generation: meta[id as usize].generation,
+            })?;
+        }
+        seq.end()
+    }
+}
+
+struct SerializeComponentIds<'a, C> {
+    archetype: &'a Archetype,
+    count: usize,
+    context: RefCell<&'a mut C>,
+}
+
+impl<C: SerializeContext> Serialize for SerializeComponentIds<'_, C> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut tuple = serializer.serialize_tuple(self.count)?;
+        self.context
+            .borrow_mut()
+            .serialize_component_ids(self.archetype, &mut tuple)?;
+        tuple.end()
+    }
+}
+
+struct SerializeComponents<'a, C> {
+    archetype: &'a Archetype,
+    count: usize,
+    context: RefCell<&'a mut C>,
+}
+
+impl<C: SerializeContext> Serialize for SerializeComponents<'_, C> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut tuple = serializer.serialize_tuple(self.count)?;
+        self.context
+            .borrow_mut()
+            .serialize_components(self.archetype, &mut tuple)?;
+        tuple.end()
+    }
+}
+
+struct SerializeColumn<'a, T>(&'a [T]);
+
+impl<T: Serialize> Serialize for SerializeColumn<'_, T> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut tuple = serializer.serialize_tuple(self.0.len())?;
+        for x in self.0 {
+            tuple.serialize_element(x)?;
+        }
+        tuple.end()
+    }
+}
+
+/// Implements deserialization of archetypes
+///
+/// Data external to the [`World`] can be populated during deserialization by storing mutable
+/// references inside the struct implementing this trait.
+///
+/// # Example
+/// ```
+/// # use serde::{Serialize, Deserialize};
+/// # #[derive(Deserialize)]
+/// # struct Position([f32; 3]);
+/// # #[derive(Deserialize)]
+/// # struct Velocity([f32; 3]);
+/// use hecs::{*, serialize::column::*};
+///
+/// #[derive(Default)]
+/// struct Context {
+///     components: Vec<ComponentId>,
+/// }
+/// #[derive(Serialize, Deserialize)]
+/// enum ComponentId { Position, Velocity }
+///
+/// impl DeserializeContext for Context {
+///     fn deserialize_component_ids<'de, A>(
+///         &mut self,
+///         mut seq: A,
+///     ) -> Result<ColumnBatchType, A::Error>
+///     where
+///         A: serde::de::SeqAccess<'de>,
+///     {
+///         // Remember the order in which the columns will be encountered
+///         self.components.clear();
+///         let mut batch = ColumnBatchType::new();
+///         while let Some(id) = seq.next_element()? {
+///             match id {
+///                 ComponentId::Position => {
+///                     batch.add::<Position>();
+///                 }
+///                 ComponentId::Velocity => {
+///                     batch.add::<Velocity>();
+///                 }
+///             }
+///             self.components.push(id);
+///         }
+///         Ok(batch)
+///     }
+///
+///     fn deserialize_components<'de, A>(
+///         &mut self,
+///         entity_count: u32,
+///         mut seq: A,
+///         batch: &mut ColumnBatchBuilder,
+///     ) -> Result<(), A::Error>
+///     where
+///         A: serde::de::SeqAccess<'de>,
+///     {
+///         for component in &self.components {
+///             match *component {
+///                 ComponentId::Position => {
+///                     deserialize_column::<Position, _>(entity_count, &mut seq, batch)?;
+///                 }
+///                 ComponentId::Velocity => {
+///                     deserialize_column::<Velocity, _>(entity_count, &mut seq, batch)?;
+///                 }
+///             }
+///         }
+///         Ok(())
+///     }
+/// }
+/// ```
+pub trait DeserializeContext {
+    /// Deserialize a tuple of component IDs, returning the types of the components they identify
+    fn deserialize_component_ids<'de, A>(&mut self, seq: A) -> Result<ColumnBatchType, A::Error>
+    where
+        A: SeqAccess<'de>;
+
+    /// Deserialize one column of `entity_count` components into `batch` for each ID yielded by
+    /// the preceding `deserialize_component_ids` call, in the same order
+    ///
+    /// Deserialization fails if any column of `batch` is left incomplete.
+    fn deserialize_components<'de, A>(
+        &mut self,
+        entity_count: u32,
+        seq: A,
+        batch: &mut ColumnBatchBuilder,
+    ) -> Result<(), A::Error>
+    where
+        A: SeqAccess<'de>;
+}
+
+/// Deserialize a column of `entity_count` `T`s from `seq` into `out`
+///
+/// Convenience method for [`DeserializeContext::deserialize_components`] implementations. Fails if
+/// `out` has no `T` column.
+pub fn deserialize_column<'de, T, A>(
+    entity_count: u32,
+    seq: &mut A,
+    out: &mut ColumnBatchBuilder,
+) -> Result<(), A::Error>
+where
+    T: Component + Deserialize<'de>,
+    A: SeqAccess<'de>,
+{
+    let writer = out
+        .writer::<T>()
+        .ok_or_else(|| de::Error::custom("component type missing from batch"))?;
+    seq.next_element_seed(DeserializeColumn {
+        entity_count,
+        writer,
+    })?
+    .ok_or_else(|| de::Error::invalid_length(0, &"a column of components"))
+}
+
+struct DeserializeColumn<'a, T> {
+    entity_count: u32,
+    writer: BatchWriter<'a, T>,
+}
+
+impl<'de, T: Deserialize<'de>> DeserializeSeed<'de> for DeserializeColumn<'_, T> {
+    type Value = ();
+
+    fn deserialize<D>(self, deserializer: D) -> Result<(), D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_tuple(self.entity_count as usize, ColumnVisitor(self))
+    }
+}
+
+struct ColumnVisitor<'a, T>(DeserializeColumn<'a, T>);
+
+impl<'de, T: Deserialize<'de>> Visitor<'de> for ColumnVisitor<'_, T> {
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "a column of {} components", self.0.entity_count)
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<(), A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        let DeserializeColumn {
+            entity_count,
+            mut writer,
+        } = self.0;
+        for i in 0..entity_count {
+            let x = seq
+                .next_element()?
+                .ok_or_else(|| de::Error::invalid_length(i as usize, &"a complete column"))?;
+            if writer.push(x).is_err() {
+                return Err(de::Error::custom("column overflows batch"));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Deserialize a [`World`] with a [`DeserializeContext`] and a [`Deserializer`]
+pub fn deserialize<'de, C, D>(context: &mut C, deserializer: D) -> Result<World, D::Error>
+where
+    C: DeserializeContext,
+    D: Deserializer<'de>,
+{
+    trace_span!("deserialize_world", format = "column");
+    let world = deserializer.deserialize_seq(WorldVisitor(context))?;
+    trace_event!(DEBUG, entities = world.len(), "deserialized world");
+    Ok(world)
+}
+
+struct WorldVisitor<'a, C>(&'a mut C);
+
+impl<'de, C: DeserializeContext> Visitor<'de> for WorldVisitor<'_, C> {
+    type Value = World;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a sequence of archetypes")
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<World, A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        let mut world = World::new();
+        while let Some((handles, batch)) = seq.next_element_seed(DeserializeArchetype(self.0))? {
+            world.spawn_column_batch_at(&handles, batch);
+        }
+        Ok(world)
+    }
+}
+
+struct DeserializeArchetype<'a, C>(&'a mut C);
+
+impl<'de, C: DeserializeContext> DeserializeSeed<'de> for DeserializeArchetype<'_, C> {
+    type Value = (Vec<Entity>, ColumnBatch);
+
+    fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_tuple(4, ArchetypeVisitor(self.0))
+    }
+}
+
+struct ArchetypeVisitor<'a, C>(&'a mut C);
+
+impl<'de, C: DeserializeContext> Visitor<'de> for ArchetypeVisitor<'_, C> {
+    type Value = (Vec<Entity>, ColumnBatch);
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a 4-tuple of component count, entities, component IDs, and columns")
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        let count = seq
+            .next_element::<u32>()?
+            .ok_or_else(|| de::Error::invalid_length(0, &self))?;
+        let handles = seq
+            .next_element::<Vec<Entity>>()?
+            .ok_or_else(|| de::Error::invalid_length(1, &self))?;
+        let entity_count = handles.len() as u32;
+        let ty = seq
+            .next_element_seed(DeserializeComponentIds(&mut *self.0, count))?
+            .ok_or_else(|| de::Error::invalid_length(2, &self))?;
+        let mut batch = ty.into_batch(entity_count);
+        seq.next_element_seed(DeserializeComponents {
+            context: &mut *self.0,
+            count,
+            entity_count,
+            batch: &mut batch,
+        })?
+        .ok_or_else(|| de::Error::invalid_length(3, &self))?;
+        let batch = batch.build().map_err(de::Error::custom)?;
+        if batch.len() != entity_count {
+            return Err(de::Error::custom(
+                "component columns are shorter than entity list",
+            ));
+        }
+        Ok((handles, batch))
+    }
+}
+
+struct DeserializeComponentIds<'a, C>(&'a mut C, u32);
+
+impl<'de, C: DeserializeContext> DeserializeSeed<'de> for DeserializeComponentIds<'_, C> {
+    type Value = ColumnBatchType;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_tuple(self.1 as usize, ComponentIdsVisitor(self.0))
+    }
+}
+
+struct ComponentIdsVisitor<'a, C>(&'a mut C);
+
+impl<'de, C: DeserializeContext> Visitor<'de> for ComponentIdsVisitor<'_, C> {
+    type Value = ColumnBatchType;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a tuple of component IDs")
+    }
+
+    fn visit_seq<A>(self, seq: A) -> Result<Self::Value, A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        self.0.deserialize_component_ids(seq)
+    }
+}
+
+struct DeserializeComponents<'a, C> {
+    context: &'a mut C,
+    count: u32,
+    entity_count: u32,
+    batch: &'a mut ColumnBatchBuilder,
+}
+
+impl<'de, C: DeserializeContext> DeserializeSeed<'de> for DeserializeComponents<'_, C> {
+    type Value = ();
+
+    fn deserialize<D>(self, deserializer: D) -> Result<(), D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_tuple(self.count as usize, ComponentsVisitor(self))
+    }
+}
+
+struct ComponentsVisitor<'a, C>(DeserializeComponents<'a, C>);
+
+impl<'de, C: DeserializeContext> Visitor<'de> for ComponentsVisitor<'_, C> {
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a tuple of component columns")
+    }
+
+    fn visit_seq<A>(self, seq: A) -> Result<(), A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        let DeserializeComponents {
+            context,
+            entity_count,
+            batch,
+            ..
+        } = self.0;
+        context.deserialize_components(entity_count, seq, batch)
+    }
+}
+
+// `serde_test` has no token for the `u128` handles of `u64-index`
+#[cfg(all(test, not(feature = "u64-index")))]
+mod tests {
+    use core::any::TypeId;
+    use std::fmt;
+
+    use serde::{Deserialize, Serialize};
+
+    use super::*;
+    use crate::*;
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug, Copy, Clone)]
+    struct Position([f32; 3]);
+    #[derive(Serialize, Deserialize, PartialEq, Debug, Copy, Clone)]
+    struct Velocity([f32; 3]);
+
+    #[derive(Default)]
+    struct Context {
+        components: Vec<ComponentId>,
+    }
+    #[derive(Serialize, Deserialize)]
+    enum ComponentId {
+        Position,
+        Velocity,
+    }
+
+    #[derive(Serialize, Deserialize)]
+    /// Bodge into serde_test's very strict interface
+    struct SerWorld(#[serde(with = "helpers")] World);
+
+    impl PartialEq for SerWorld {
+        fn eq(&self, other: &Self) -> bool {
+            fn same_components<T: Component + PartialEq>(x: &EntityRef, y: &EntityRef) -> bool {
+                x.get::<T>().as_deref() == y.get::<T>().as_deref()
+            }
+
+            for ((x_id, x), (y_id, y)) in self.0.iter().zip(other.0.iter()) {
+                if x_id != y_id
+                    || !same_components::<Position>(&x, &y)
+                    || !same_components::<Velocity>(&x, &y)
+                {
+                    return false;
+                }
+            }
+            true
+        }
+    }
+
+    impl fmt::Debug for SerWorld {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.debug_map()
+                .entries(self.0.iter().map(|(id, ref e)| {
+                    (
+                        id,
+                        (
+                            e.get::<Position>().map(|x| *x),
+                            e.get::<Velocity>().map(|x| *x),
+                        ),
+                    )
+                }))
+                .finish()
+        }
+    }
+
+    mod helpers {
+        use super::*;
+        pub fn serialize<S: Serializer>(x: &World, s: S) -> Result<S::Ok, S::Error> {
+            crate::serialize::column::serialize(x, &mut Context::default(), s)
+        }
+        pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<World, D::Error> {
+            crate::serialize::column::deserialize(&mut Context::default(), d)
+        }
+    }
+
+    impl SerializeContext for Context {
+        fn component_count(&self, archetype: &Archetype) -> usize {
+            archetype
+                .component_types()
+                .filter(|&t| t == TypeId::of::<Position>() || t == TypeId::of::<Velocity>())
+                .count()
+        }
+
+        fn serialize_component_ids<S: SerializeTuple>(
+            &mut self,
+            archetype: &Archetype,
+            out: &mut S,
+        ) -> Result<(), S::Error> {
+            try_serialize_id::<Position, _, _>(archetype, &ComponentId::Position, out)?;
+            try_serialize_id::<Velocity, _, _>(archetype, &ComponentId::Velocity, out)?;
+            Ok(())
+        }
+
+        fn serialize_components<S: SerializeTuple>(
+            &mut self,
+            archetype: &Archetype,
+            out: &mut S,
+        ) -> Result<(), S::Error> {
+            try_serialize::<Position, _>(archetype, out)?;
+            try_serialize::<Velocity, _>(archetype, out)?;
+            Ok(())
+        }
+    }
+
+    impl DeserializeContext for Context {
+        fn deserialize_component_ids<'de, A>(
+            &mut self,
+            mut seq: A,
+        ) -> Result<ColumnBatchType, A::Error>
+        where
+            A: SeqAccess<'de>,
+        {
+            self.components.clear();
+            let mut batch = ColumnBatchType::new();
+            while let Some(id) = seq.next_element()? {
+                match id {
+                    ComponentId::Position => {
+                        batch.add::<Position>();
+                    }
+                    ComponentId::Velocity => {
+                        batch.add::<Velocity>();
+                    }
+                }
+                self.components.push(id);
+            }
+            Ok(batch)
+        }
+
+        fn deserialize_components<'de, A>(
+            &mut self,
+            entity_count: u32,
+            mut seq: A,
+            batch: &mut ColumnBatchBuilder,
+        ) -> Result<(), A::Error>
+        where
+            A: SeqAccess<'de>,
+        {
+            for component in &self.components {
+                match *component {
+                    ComponentId::Position => {
+                        deserialize_column::<Position, _>(entity_count, &mut seq, batch)?;
+                    }
+                    ComponentId::Velocity => {
+                        deserialize_column::<Velocity, _>(entity_count, &mut seq, batch)?;
+                    }
+                }
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn roundtrip() {
+        use serde_test::{Token, assert_tokens};
+
+        let mut world = World::new();
+        let p0 = Position([0.0, 0.0, 0.0]);
+        let v0 = Velocity([1.0, 1.0, 1.0]);
+        let p1 = Position([2.0, 2.0, 2.0]);
+        let e0 = world.spawn((p0, v0));
+        let e1 = world.spawn((p1,));
+
+        assert_tokens(&SerWorld(world), &[
+            Token::NewtypeStruct { name: "SerWorld" },
+            Token::Seq { len: Some(2) },
+
+            Token::Tuple { len: 4 },
+            Token::U32(2),
+            Token::Seq { len: Some(1) },
+            Token::U64(e0.to_bits()),
+            Token::SeqEnd,
+
+            Token::Tuple { len: 2 },
+            Token::UnitVariant { name: "ComponentId", variant: "Position" },
+            Token::UnitVariant { name: "ComponentId", variant: "Velocity" },
+            Token::TupleEnd,
+
+            Token::Tuple { len: 2 },
+            Token::Tuple { len: 1 },
+            Token::NewtypeStruct { name: "Position" },
+            Token::Tuple { len: 3 },
+            Token::F32(0.0),
+            Token::F32(0.0),
+            Token::F32(0.0),
+            Token::TupleEnd,
+            Token::TupleEnd,
+            Token::Tuple { len: 1 },
+            Token::NewtypeStruct { name: "Velocity" },
+            Token::Tuple { len: 3 },
+            Token::F32(1.0),
+            Token::F32(1.0),
+            Token::F32(1.0),
+            Token::TupleEnd,
+            Token::TupleEnd,
+            Token::TupleEnd,
+            Token::TupleEnd,
+
+            Token::Tuple { len: 4 },
+            Token::U32(1),
+            Token::Seq { len: Some(1) },
+            Token::U64(e1.to_bits()),
+            Token::SeqEnd,
+
+            Token::Tuple { len: 1 },
+            Token::UnitVariant { name: "ComponentId", variant: "Position" },
+            Token::TupleEnd,
+
+            Token::Tuple { len: 1 },
+            Token::Tuple { len: 1 },
+            Token::NewtypeStruct { name: "Position" },
+            Token::Tuple { len: 3 },
+            Token::F32(2.0),
+            Token::F32(2.0),
+            Token::F32(2.0),
+            Token::TupleEnd,
+            Token::TupleEnd,
+            Token::TupleEnd,
+            Token::TupleEnd,
+
+            Token::SeqEnd,
+        ])
+    }
+}
@@ -0,0 +1,13 @@
+//! Serialization support; requires the `serde` feature
+//!
+//! [`Component`](crate::Component)s are not necessarily serializable, so [`World`](crate::World)
+//! cannot implement `Serialize` directly. Instead, the submodules of this one provide helpers that
+//! rely on user-implemented traits to control the procedure explicitly.
+//!
+//! - [`row`] serializes each entity's components together, and is convenient for hand-edited or
+//!   diffed data such as level definitions and save games.
+//! - [`column`] serializes each archetype one column at a time, and is much faster to load for
+//!   large worlds, making it suitable for snapshots.
+
+pub mod column;
+pub mod row;
@@ -1,4 +1,7 @@
-//! Convenience tools for serializing [`World`]s; requires the `serde` feature
+//! Human-friendly row-major serialization
+//!
+//! Stores each entity's components together. Makes for easily hand-edited and diffed data, at
+//! the cost of visiting every component individually.
 //!
 //! [`Component`]s are not necessarily serializable, so we cannot directly implement [`Serialize`]
 //! for [`World`]. The helper functions defined in this module allow serialization and
@@ -14,7 +17,7 @@
 use core::{cell::RefCell, fmt};
 
 use serde::{
-    de::{DeserializeSeed, MapAccess, Visitor},
+    de::{self, DeserializeSeed, MapAccess, Visitor},
     ser::SerializeMap,
     Deserializer, Serialize, Serializer,
 };
@@ -34,7 +37,7 @@ use crate::{Component, EntityBuilder, EntityRef, World};
 /// # struct Position([f32; 3]);
 /// # #[derive(Serialize)]
 /// # struct Velocity([f32; 3]);
-/// use hecs::{*, serialize::*};
+/// use hecs::{*, serialize::row::*};
 ///
 /// // Could include references to external state for use by `serialize_entity`
 /// struct Context;
@@ -86,6 +89,7 @@ where
     C: SerializeContext,
     S: Serializer,
 {
+    trace_span!("serialize_world", format = "row", entities = world.len());
     let mut seq = serializer.serialize_map(Some(world.len() as usize))?;
     for (id, components) in world {
         seq.serialize_key(&id)?;
@@ -97,6 +101,42 @@ where
     seq.end()
 }
 
+/// Like [`serialize`], but only entities whose components were added to or mutably accessed at
+/// or after `since`, a past value of [`World::change_tick`]
+///
+/// Produces an incremental save, to be layered with [`deserialize_into`] over an earlier one
+/// taken when [`World::change_tick`] was `since`. Each entity included is serialized in full.
+/// Despawning an entity or removing a component doesn't count as a change, so the application
+/// must track those separately, e.g. with [`World::record_events`].
+pub fn serialize_changed<C, S>(
+    world: &World,
+    since: u32,
+    context: &mut C,
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    C: SerializeContext,
+    S: Serializer,
+{
+    let len = world
+        .iter()
+        .filter(|(_, components)| components.changed_since(since))
+        .count();
+    trace_span!("serialize_world", format = "row", entities = len, since);
+    let mut seq = serializer.serialize_map(Some(len))?;
+    for (id, components) in world {
+        if !components.changed_since(since) {
+            continue;
+        }
+        seq.serialize_key(&id)?;
+        seq.serialize_value(&SerializeComponents(RefCell::new((
+            context,
+            Some(components),
+        ))))?;
+    }
+    seq.end()
+}
+
 struct SerializeComponents<'a, C>(RefCell<(&'a mut C, Option<EntityRef<'a>>)>);
 
 impl<'a, C: SerializeContext> Serialize for SerializeComponents<'a, C> {
@@ -118,7 +158,28 @@ where
     C: DeserializeContext,
     D: Deserializer<'de>,
 {
-    deserializer.deserialize_map(WorldVisitor(context))
+    let mut world = World::new();
+    deserialize_into(&mut world, context, deserializer)?;
+    Ok(world)
+}
+
+/// Deserialize entities into an existing [`World`], e.g. to apply an incremental save produced by
+/// [`serialize_changed`]
+///
+/// Each entity replaces any existing entity with the same ID.
+pub fn deserialize_into<'de, C, D>(
+    world: &mut World,
+    context: &mut C,
+    deserializer: D,
+) -> Result<(), D::Error>
+where
+    C: DeserializeContext,
+    D: Deserializer<'de>,
+{
+    trace_span!("deserialize_world", format = "row");
+    deserializer.deserialize_map(WorldVisitor(context, world))?;
+    trace_event!(DEBUG, entities = world.len(), "deserialized world");
+    Ok(())
 }
 
 /// Implements deserialization of entities from a serde [`MapAccess`] into an [`EntityBuilder`]
@@ -133,7 +194,7 @@ where
 /// # struct Position([f32; 3]);
 /// # #[derive(Deserialize)]
 /// # struct Velocity([f32; 3]);
-/// use hecs::{*, serialize::*};
+/// use hecs::{*, serialize::row::*};
 ///
 /// // Could include references to external state for use by `deserialize_entity`
 /// struct Context;
@@ -174,29 +235,65 @@ pub trait DeserializeContext {
         M: MapAccess<'de>;
 }
 
-struct WorldVisitor<'a, C>(&'a mut C);
+/// Deserialize the next key of `map`, which must be one of `names`, as its index in `names`
+#[doc(hidden)]
+pub fn next_named_key<'de, M: MapAccess<'de>>(
+    map: &mut M,
+    names: &'static [&'static str],
+) -> Result<Option<usize>, M::Error> {
+    map.next_key_seed(NamedKey(names))
+}
+
+struct NamedKey(&'static [&'static str]);
+
+impl<'de> DeserializeSeed<'de> for NamedKey {
+    type Value = usize;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<usize, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_identifier(self)
+    }
+}
+
+impl<'de> Visitor<'de> for NamedKey {
+    type Value = usize;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a component type name")
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<usize, E> {
+        self.0
+            .iter()
+            .position(|&x| x == v)
+            .ok_or_else(|| E::unknown_field(v, self.0))
+    }
+}
+
+struct WorldVisitor<'a, C>(&'a mut C, &'a mut World);
 
 impl<'de, 'a, C> Visitor<'de> for WorldVisitor<'a, C>
 where
     C: DeserializeContext,
 {
-    type Value = World;
+    type Value = ();
 
     fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
         formatter.write_str("a world")
     }
 
-    fn visit_map<A>(self, mut map: A) -> Result<World, A::Error>
+    fn visit_map<A>(self, mut map: A) -> Result<(), A::Error>
     where
         A: MapAccess<'de>,
     {
-        let mut world = World::new();
         let mut builder = EntityBuilder::new();
         while let Some(id) = map.next_key()? {
             map.next_value_seed(DeserializeComponents(self.0, &mut builder))?;
-            world.spawn_at(id, builder.build());
+            self.1.spawn_at(id, builder.build());
         }
-        Ok(world)
+        Ok(())
     }
 }
 
@@ -236,7 +333,8 @@ where
     }
 }
 
-#[cfg(test)]
+// `serde_test` has no token for the `u128` handles of `u64-index`
+#[cfg(all(test, not(feature = "u64-index")))]
 mod tests {
     use std::fmt;
 
@@ -264,7 +362,7 @@ mod tests {
     impl PartialEq for SerWorld {
         fn eq(&self, other: &Self) -> bool {
             fn same_components<T: Component + PartialEq>(x: &EntityRef, y: &EntityRef) -> bool {
-                x.get::<T>().as_ref().map(|x| &**x) == y.get::<T>().as_ref().map(|x| &**x)
+                x.get::<T>().as_deref() == y.get::<T>().as_deref()
             }
 
             for ((x_id, x), (y_id, y)) in self.0.iter().zip(other.0.iter()) {
@@ -298,10 +396,10 @@ mod tests {
     mod helpers {
         use super::*;
         pub fn serialize<S: Serializer>(x: &World, s: S) -> Result<S::Ok, S::Error> {
-            crate::serialize::serialize(x, &mut Context, s)
+            crate::serialize::row::serialize(x, &mut Context, s)
         }
         pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<World, D::Error> {
-            crate::serialize::deserialize(&mut Context, d)
+            crate::serialize::row::deserialize(&mut Context, d)
         }
     }
 
@@ -396,4 +494,45 @@ mod tests {
             Token::MapEnd,
         ])
     }
+
+    #[test]
+    #[rustfmt::skip]
+    fn changed_only() {
+        use serde_test::{Token, assert_ser_tokens};
+
+        struct SerChanged(World, u32);
+
+        impl Serialize for SerChanged {
+            fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+                serialize_changed(&self.0, self.1, &mut Context, s)
+            }
+        }
+
+        let mut world = World::new();
+        world.spawn((Position([0.0; 3]), Velocity([1.0; 3])));
+        let e1 = world.spawn((Position([2.0; 3]),));
+        world.clear_trackers();
+        let since = world.change_tick();
+        world.clear_trackers();
+        world.get_mut::<Position>(e1).unwrap().0[0] = 3.0;
+
+        assert_ser_tokens(&SerChanged(world, since), &[
+            Token::Map { len: Some(1) },
+
+            Token::U64(e1.to_bits()),
+            Token::Map { len: None },
+
+            Token::UnitVariant { name: "ComponentId", variant: "Position" },
+            Token::NewtypeStruct { name: "Position" },
+            Token::Tuple { len: 3 },
+            Token::F32(3.0),
+            Token::F32(2.0),
+            Token::F32(2.0),
+            Token::TupleEnd,
+
+            Token::MapEnd,
+
+            Token::MapEnd,
+        ])
+    }
 }
@@ -0,0 +1,235 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use crate::alloc::alloc::{alloc, dealloc, Layout};
+use crate::alloc::vec::Vec;
+use core::any::TypeId;
+use core::ptr::NonNull;
+
+use crate::archetype::{Archetype, TypeIdMap, TypeInfo};
+use crate::chunk::ChunkValue;
+use crate::entities::Entities;
+use crate::entity_builder::CloneFn;
+use crate::{Children, Component, ComponentRegistry, Entity, EntityId, Parent};
+
+/// The component types that may be captured by a [`WorldSnapshot`], and how to clone them
+///
+/// [`Parent`] and [`Children`] are always registered.
+#[derive(Clone)]
+pub struct SnapshotRegistry {
+    cloners: TypeIdMap<(CloneFn, Option<EqFn>)>,
+}
+
+/// Whether the values at two pointers are equal
+pub(crate) type EqFn = unsafe fn(*const u8, *const u8) -> bool;
+
+impl SnapshotRegistry {
+    /// Create a registry of only the built-in component types
+    pub fn new() -> Self {
+        let mut x = Self {
+            cloners: TypeIdMap::default(),
+        };
+        x.register_eq::<Parent>().register_eq::<Children>();
+        x
+    }
+
+    /// Allow `T` components to be captured
+    ///
+    /// A [`WorldDiff`](crate::WorldDiff) will treat every `T` as changed.
+    pub fn register<T: Component + Clone>(&mut self) -> &mut Self {
+        self.cloners
+            .insert(TypeId::of::<T>(), (clone_ptr::<T>, None));
+        self
+    }
+
+    /// Allow `T` components to be captured, and compared so that a
+    /// [`WorldDiff`](crate::WorldDiff) can omit unchanged ones
+    pub fn register_eq<T: Component + Clone + PartialEq>(&mut self) -> &mut Self {
+        unsafe fn eq_ptr<T: PartialEq>(x: *const u8, y: *const u8) -> bool {
+            *x.cast::<T>() == *y.cast::<T>()
+        }
+
+        self.cloners
+            .insert(TypeId::of::<T>(), (clone_ptr::<T>, Some(eq_ptr::<T>)));
+        self
+    }
+
+    /// Allow every cloneable type in `registry` to be captured
+    pub fn register_from(&mut self, registry: &ComponentRegistry) -> &mut Self {
+        for ty in registry.iter() {
+            if let Some(cloner) = ty.cloner() {
+                self.cloners.insert(ty.type_info().id(), cloner);
+            }
+        }
+        self
+    }
+
+    /// Whether components of type `ty` may be captured
+    pub fn is_registered(&self, ty: TypeId) -> bool {
+        self.cloners.contains_key(&ty)
+    }
+
+    /// How to clone components of type `ty`, if registered
+    pub(crate) fn cloner(&self, ty: TypeId) -> Option<CloneFn> {
+        self.cloners.get(&ty).map(|x| x.0)
+    }
+}
+
+unsafe fn clone_ptr<T: Clone>(src: *const u8, dst: *mut u8) {
+    dst.cast::<T>().write((*src.cast::<T>()).clone());
+}
+
+impl Default for SnapshotRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A copy of the entities and components of a `World` at some moment
+///
+/// Captured with [`World::snapshot`](crate::World::snapshot) and reinstated, any number of times,
+/// with [`World::restore`](crate::World::restore).
+pub struct WorldSnapshot {
+    pub(crate) entities: Entities,
+    pub(crate) archetypes: Vec<ArchetypeSnapshot>,
+    /// Sparsely stored components, one set per type
+    pub(crate) sparse: Vec<ArchetypeSnapshot>,
+}
+
+impl WorldSnapshot {
+    /// The handle of the entity with ID `id` at the time of capture
+    pub(crate) fn entity(&self, id: EntityId) -> Entity {
+        Entity {
+            id,
+            generation: self.entities.meta[id as usize].generation,
+        }
+    }
+}
+
+pub(crate) struct ArchetypeSnapshot {
+    pub(crate) ids: Vec<TypeId>,
+    pub(crate) types: Vec<TypeInfo>,
+    pub(crate) entities: Vec<EntityId>,
+    pub(crate) columns: Vec<Column>,
+    pub(crate) chunk: Vec<ChunkValue>,
+}
+
+impl ArchetypeSnapshot {
+    /// Clone every entity of `archetype` using the cloners in `registry`
+    ///
+    /// Panics if any of the archetype's component types aren't registered.
+    pub(crate) fn new(registry: &SnapshotRegistry, archetype: &Archetype) -> Self {
+        let len = archetype.len();
+        let columns = archetype
+            .types()
+            .iter()
+            .map(|ty| {
+                let (clone, eq) = *registry
+                    .cloners
+                    .get(&ty.id())
+                    .expect("component type not registered for snapshots");
+                let mut column = Column::new(*ty, clone, eq, len as usize);
+                let base = archetype.get_base(ty.id()).unwrap().as_ptr();
+                let size = ty.layout().size();
+                for i in 0..len as usize {
+                    unsafe {
+                        clone(base.add(i * size), column.get(i));
+                    }
+                    // Counted only once initialized, so a panicking clone drops just these
+                    column.len += 1;
+                }
+                column
+            })
+            .collect();
+        Self {
+            ids: archetype.types().iter().map(|x| x.id()).collect(),
+            types: archetype.types().to_vec(),
+            entities: (0..len).map(|i| archetype.entity_id(i)).collect(),
+            columns,
+            chunk: archetype.chunk_values().to_vec(),
+        }
+    }
+
+    /// Clone every captured entity into fresh storage
+    ///
+    /// Should a clone panic, only the components already cloned for that entity are leaked.
+    pub(crate) fn instantiate(&self) -> Archetype {
+        let mut archetype = Archetype::new(self.types.clone());
+        archetype.reserve(self.entities.len() as u32);
+        for (i, &id) in self.entities.iter().enumerate() {
+            unsafe {
+                for column in &self.columns {
+                    let dst = archetype
+                        .get_next_dynamic(column.ty.id(), column.ty.layout().size())
+                        .unwrap();
+                    (column.clone)(column.get(i), dst.as_ptr());
+                }
+                archetype.allocate(id);
+            }
+        }
+        archetype
+    }
+}
+
+/// Cloned components of a single type, stored contiguously
+pub(crate) struct Column {
+    pub(crate) ty: TypeInfo,
+    pub(crate) clone: CloneFn,
+    pub(crate) eq: Option<EqFn>,
+    data: NonNull<u8>,
+    /// Number of initialized components
+    len: usize,
+    capacity: usize,
+}
+
+impl Column {
+    /// Allocate uninitialized storage for `capacity` components of type `ty`
+    fn new(ty: TypeInfo, clone: CloneFn, eq: Option<EqFn>, capacity: usize) -> Self {
+        let layout = Self::layout(&ty, capacity);
+        let data = if layout.size() == 0 {
+            unsafe { NonNull::new_unchecked(layout.align() as *mut u8) }
+        } else {
+            NonNull::new(unsafe { alloc(layout) }).unwrap()
+        };
+        Self {
+            ty,
+            clone,
+            eq,
+            data,
+            len: 0,
+            capacity,
+        }
+    }
+
+    fn layout(ty: &TypeInfo, len: usize) -> Layout {
+        Layout::from_size_align(ty.layout().size() * len, ty.layout().align()).unwrap()
+    }
+
+    /// Address of the `i`th component
+    pub(crate) fn get(&self, i: usize) -> *mut u8 {
+        unsafe { self.data.as_ptr().add(i * self.ty.layout().size()) }
+    }
+}
+
+impl Drop for Column {
+    fn drop(&mut self) {
+        for i in 0..self.len {
+            unsafe {
+                self.ty.drop(self.get(i));
+            }
+        }
+        let layout = Self::layout(&self.ty, self.capacity);
+        if layout.size() != 0 {
+            unsafe {
+                dealloc(self.data.as_ptr(), layout);
+            }
+        }
+    }
+}
+
+unsafe impl Send for Column {}
+unsafe impl Sync for Column {}
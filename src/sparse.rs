@@ -0,0 +1,322 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use crate::alloc::boxed::Box;
+use crate::alloc::vec;
+use core::any::TypeId;
+use core::ptr::{self, NonNull};
+use core::sync::atomic::AtomicU32;
+
+use hashbrown::HashMap;
+
+use crate::archetype::{Archetype, ComponentTicks, StorageAllocator, TypeIdMap, TypeInfo};
+use crate::atomic_borrow::AtomicBorrow;
+use crate::EntityId;
+
+/// Components of one type stored apart from any archetype, keyed by the ID of their entity
+///
+/// Adding or removing a sparse component leaves its entity in place, so costs the same no matter
+/// how many other components the entity has.
+pub(crate) struct SparseSet {
+    /// Single-column archetype holding each component in a row tagged with its entity's ID
+    storage: Archetype,
+    /// Row of `storage` holding each entity's component
+    rows: HashMap<EntityId, u32>,
+    /// Whether components of this type are stored here regardless of archetype, as opposed to only
+    /// for archetypes that pin the type
+    by_default: bool,
+}
+
+impl SparseSet {
+    fn new(ty: TypeInfo, tick: u32, allocator: Option<&StorageAllocator>) -> Self {
+        let mut storage = Archetype::new(vec![ty]);
+        storage.set_allocator(allocator.cloned());
+        storage.set_tick(tick);
+        Self {
+            storage,
+            rows: HashMap::default(),
+            by_default: false,
+        }
+    }
+
+    pub(crate) fn type_info(&self) -> &TypeInfo {
+        &self.storage.types()[0]
+    }
+
+    /// The components and the IDs of the entities they belong to
+    pub(crate) fn storage(&self) -> &Archetype {
+        &self.storage
+    }
+
+    pub(crate) fn contains(&self, id: EntityId) -> bool {
+        self.rows.contains_key(&id)
+    }
+
+    pub(crate) fn is_default(&self) -> bool {
+        self.by_default
+    }
+
+    /// Store components of this type here regardless of archetype from now on
+    pub(crate) fn set_default(&mut self) {
+        self.by_default = true;
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+
+    /// The row holding the component of the entity `id`, if it has one
+    #[inline]
+    pub(crate) fn row(&self, id: EntityId) -> Option<u32> {
+        self.rows.get(&id).copied()
+    }
+
+    /// Address of the component at `row`, which must be in bounds
+    #[inline]
+    pub(crate) unsafe fn component(&self, row: u32) -> NonNull<u8> {
+        let ty = self.type_info();
+        self.storage
+            .get_dynamic(ty.id(), ty.layout().size(), row)
+            .unwrap()
+    }
+
+    /// Change tick of the component at `row`, which must be in bounds
+    #[inline]
+    pub(crate) unsafe fn mutated(&self, row: u32) -> NonNull<AtomicU32> {
+        let base = self.storage.mutated_dynamic(self.type_info().id()).unwrap();
+        NonNull::new_unchecked(base.as_ptr().add(row as usize))
+    }
+
+    #[inline]
+    pub(crate) fn tick(&self) -> u32 {
+        self.storage.tick()
+    }
+
+    pub(crate) fn set_tick(&mut self, tick: u32) {
+        self.storage.set_tick(tick);
+    }
+
+    /// Ensure `additional` more components may be stored without moving existing ones
+    pub(crate) fn reserve(&mut self, additional: u32) {
+        self.storage.reserve(additional);
+    }
+
+    /// Move `component` into the entity `id`, overwriting without dropping any it already has
+    pub(crate) unsafe fn put(&mut self, id: EntityId, component: *mut u8) {
+        let size = self.type_info().layout().size();
+        self.put_with(id, |dst| ptr::copy_nonoverlapping(component, dst, size));
+    }
+
+    /// Like `put`, but with `f` writing the component directly into storage
+    pub(crate) unsafe fn put_with(&mut self, id: EntityId, f: impl FnOnce(*mut u8)) {
+        let row = match self.rows.get(&id) {
+            Some(&row) => {
+                f(self.component(row).as_ptr());
+                row
+            }
+            None => {
+                // Written before the row is allocated, so a panicking `f` leaves storage untouched
+                self.storage.reserve(1);
+                let ty = *self.type_info();
+                let dst = self
+                    .storage
+                    .get_next_dynamic(ty.id(), ty.layout().size())
+                    .unwrap();
+                f(dst.as_ptr());
+                let row = self.storage.allocate(id);
+                self.rows.insert(id, row);
+                row
+            }
+        };
+        let tick = self.tick();
+        self.storage.set_ticks(
+            self.type_info().id(),
+            row,
+            ComponentTicks {
+                added: tick,
+                mutated: tick,
+            },
+        );
+    }
+
+    /// Forget the component of the entity `id`, if any, after passing it to `f` to be moved out
+    pub(crate) unsafe fn take(
+        &mut self,
+        id: EntityId,
+        f: impl FnOnce(*mut u8, ComponentTicks),
+    ) -> bool {
+        let row = match self.rows.remove(&id) {
+            Some(x) => x,
+            None => return false,
+        };
+        let mut f = Some(f);
+        if let Some(moved) = self
+            .storage
+            .move_to(row, |ptr, _, _, ticks| (f.take().unwrap())(ptr, ticks))
+        {
+            self.rows.insert(moved, row);
+        }
+        true
+    }
+
+    /// Drop the component of the entity `id`, if any
+    pub(crate) fn remove(&mut self, id: EntityId) -> bool {
+        let row = match self.rows.remove(&id) {
+            Some(x) => x,
+            None => return false,
+        };
+        if let Some(moved) = unsafe { self.storage.remove(row) } {
+            self.rows.insert(moved, row);
+        }
+        true
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.storage.clear();
+        self.rows.clear();
+    }
+
+    /// Like `clear`, but passes each component that needs dropping to `f` to be disposed of
+    pub(crate) fn clear_with(&mut self, f: impl FnMut(&TypeInfo, *mut u8)) {
+        self.storage.clear_with(f);
+        self.rows.clear();
+    }
+
+    pub(crate) fn shrink_to(&mut self, capacity: u32) -> usize {
+        self.rows.shrink_to(capacity as usize);
+        self.storage.shrink_to(capacity)
+    }
+}
+
+/// The `SparseSet`s of a `World`, which its archetypes refer to by address
+#[derive(Default)]
+pub(crate) struct SparseSets {
+    sets: TypeIdMap<NonNull<SparseSet>>,
+}
+
+impl SparseSets {
+    pub(crate) fn is_empty(&self) -> bool {
+        self.sets.is_empty()
+    }
+
+    /// Whether components of type `ty` are stored sparsely unless already in a column, as opposed
+    /// to only for archetypes that pin `ty`
+    pub(crate) fn contains(&self, ty: TypeId) -> bool {
+        self.get(ty).is_some_and(|x| x.is_default())
+    }
+
+    pub(crate) fn get(&self, ty: TypeId) -> Option<&SparseSet> {
+        self.sets.get(&ty).map(|x| unsafe { x.as_ref() })
+    }
+
+    /// Address of the sparse `ty` component of the entity `id`, if it has one
+    pub(crate) fn component(&self, ty: TypeId, id: EntityId) -> Option<NonNull<u8>> {
+        let set = self.get(ty)?;
+        Some(unsafe { set.component(set.row(id)?) })
+    }
+
+    pub(crate) fn get_mut(&mut self, ty: TypeId) -> Option<&mut SparseSet> {
+        self.sets.get_mut(&ty).map(|x| unsafe { x.as_mut() })
+    }
+
+    /// Find or create the set storing `ty`; the `bool` is `true` if it was created
+    pub(crate) fn insert(
+        &mut self,
+        ty: TypeInfo,
+        tick: u32,
+        allocator: Option<&StorageAllocator>,
+    ) -> (NonNull<SparseSet>, bool) {
+        if let Some(&x) = self.sets.get(&ty.id()) {
+            return (x, false);
+        }
+        let set = NonNull::from(Box::leak(Box::new(SparseSet::new(ty, tick, allocator))));
+        self.sets.insert(ty.id(), set);
+        (set, true)
+    }
+
+    pub(crate) fn iter(&self) -> impl Iterator<Item = &SparseSet> + '_ {
+        self.sets.values().map(|x| unsafe { x.as_ref() })
+    }
+
+    pub(crate) fn iter_mut(&mut self) -> impl Iterator<Item = &mut SparseSet> + '_ {
+        self.sets.values_mut().map(|x| unsafe { x.as_mut() })
+    }
+
+    /// Addresses of every set, for an archetype to refer to
+    pub(crate) fn raw(&self) -> impl Iterator<Item = NonNull<SparseSet>> + '_ {
+        self.sets.values().copied()
+    }
+}
+
+impl Drop for SparseSets {
+    fn drop(&mut self) {
+        for &set in self.sets.values() {
+            unsafe {
+                drop(Box::from_raw(set.as_ptr()));
+            }
+        }
+    }
+}
+
+/// A `SparseSet` as seen by one archetype
+///
+/// Each archetype borrows the components of its own entities independently, just as it does for
+/// its columns.
+pub(crate) struct SparseColumn {
+    set: NonNull<SparseSet>,
+    borrow: AtomicBorrow,
+}
+
+impl SparseColumn {
+    pub(crate) fn new(set: NonNull<SparseSet>) -> Self {
+        Self {
+            set,
+            borrow: AtomicBorrow::new(),
+        }
+    }
+
+    pub(crate) fn set(&self) -> &SparseSet {
+        // Safe because a `World` outlives the sets its archetypes refer to
+        unsafe { self.set.as_ref() }
+    }
+
+    pub(crate) fn borrow(&self) -> &AtomicBorrow {
+        &self.borrow
+    }
+}
+
+/// Locates the components of one sparsely stored type for the entities of an archetype
+pub(crate) struct SparseFetch {
+    set: NonNull<SparseSet>,
+    entities: NonNull<EntityId>,
+    len: usize,
+}
+
+impl SparseFetch {
+    pub(crate) fn new(archetype: &Archetype, ty: TypeId) -> Option<Self> {
+        Some(Self {
+            set: NonNull::from(archetype.sparse(ty)?),
+            entities: archetype.entities(),
+            len: archetype.len() as usize,
+        })
+    }
+
+    #[inline]
+    pub(crate) fn set(&self) -> &SparseSet {
+        unsafe { self.set.as_ref() }
+    }
+
+    /// The row of `set` holding the component of the archetype's `n`th entity, if it has one
+    #[inline]
+    pub(crate) unsafe fn row(&self, n: usize) -> Option<u32> {
+        if n >= self.len {
+            // e.g. a reserved entity's placeholder location
+            return None;
+        }
+        self.set().row(*self.entities.as_ptr().add(n))
+    }
+}
@@ -0,0 +1,99 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use core::fmt;
+
+use crate::Component;
+
+/// Identifies a component type consistently across compilations, unlike `TypeId`
+///
+/// Derived by hashing a name chosen by the application, such as `"game::Position"` or a UUID, so
+/// data recorded by one build, or by a plugin compiled separately, can be matched up with the
+/// types of another. Usually obtained from [`StableComponent::STABLE_TYPE_ID`].
+///
+/// Serializes as a `u64`, so it may be used as the component key passed to
+/// [`serialize::row::try_serialize`](crate::serialize::row::try_serialize) and friends.
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct StableTypeId(u64);
+
+impl StableTypeId {
+    /// The ID of the type named `name`
+    ///
+    /// Computed with the 64-bit FNV-1a hash, which will not change.
+    pub const fn from_name(name: &str) -> Self {
+        let bytes = name.as_bytes();
+        let mut hash = 0xcbf2_9ce4_8422_2325u64;
+        let mut i = 0;
+        while i < bytes.len() {
+            hash ^= bytes[i] as u64;
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+            i += 1;
+        }
+        Self(hash)
+    }
+
+    /// Reconstruct an ID from the output of `to_bits`
+    pub const fn from_bits(bits: u64) -> Self {
+        Self(bits)
+    }
+
+    /// Convert to a form convenient for passing outside of Rust
+    pub const fn to_bits(self) -> u64 {
+        self.0
+    }
+}
+
+impl fmt::Debug for StableTypeId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "StableTypeId({:016x})", self.0)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for StableTypeId {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(self.0)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for StableTypeId {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        <u64 as serde::Deserialize>::deserialize(deserializer).map(Self)
+    }
+}
+
+/// A component type with a name that identifies it across compilations
+///
+/// Implemented by `#[derive(StableComponent)]` when the `macros` feature is enabled, taking the
+/// name from a `#[stable_id("...")]` attribute. Components of such types may be described by
+/// [`TypeInfo::of_stable`](crate::TypeInfo::of_stable), saved by
+/// [`BinaryRegistry::register_stable`](crate::BinaryRegistry::register_stable), and serialized
+/// under their `STABLE_TYPE_ID`.
+///
+/// # Example
+/// ```
+/// # use hecs::*;
+/// struct Position(f32);
+///
+/// impl StableComponent for Position {
+///     const STABLE_NAME: &'static str = "game::Position";
+/// }
+///
+/// assert_eq!(Position::STABLE_TYPE_ID, StableTypeId::from_name("game::Position"));
+/// assert_eq!(
+///     TypeInfo::of_stable::<Position>().stable_id(),
+///     Some(Position::STABLE_TYPE_ID)
+/// );
+/// ```
+pub trait StableComponent: Component {
+    /// The name identifying this type, which no other component type may share
+    const STABLE_NAME: &'static str;
+
+    /// The ID computed from `STABLE_NAME`
+    const STABLE_TYPE_ID: StableTypeId = StableTypeId::from_name(Self::STABLE_NAME);
+}
@@ -0,0 +1,165 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use core::any::TypeId;
+
+use crate::{Component, ComponentError, Entity, NoSuchEntity, World};
+
+/// An enum component accompanied by a marker component identifying its variant, declared with
+/// [`state_component!`]
+///
+/// Assign states with [`World::set_state`] to keep the markers in sync.
+pub trait StateComponent: Component + Sized {
+    /// The marker type of the current variant
+    #[doc(hidden)]
+    fn marker(&self) -> TypeId;
+
+    /// Insert `self` and the marker of its variant into `entity`
+    #[doc(hidden)]
+    fn insert_with_marker(self, world: &mut World, entity: Entity) -> Result<(), NoSuchEntity>;
+}
+
+impl World {
+    /// Give `entity` the state `state`, replacing any existing `S` along with its variant's marker
+    ///
+    /// Assigning a state of the same variant as the existing one simply overwrites it, without
+    /// moving the entity to another archetype.
+    ///
+    /// # Example
+    /// ```
+    /// # use hecs::*;
+    /// state_component! {
+    ///     #[derive(Debug, PartialEq)]
+    ///     enum Mood { Calm, Angry(u32) }
+    ///     mod mood;
+    /// }
+    /// let mut world = World::new();
+    /// let a = world.spawn(());
+    /// world.set_state(a, Mood::Calm).unwrap();
+    /// world.set_state(a, Mood::Angry(3)).unwrap();
+    /// assert!(world.get::<mood::Calm>(a).is_err());
+    /// let angry = world.query::<&Mood>().with::<mood::Angry>().iter().count();
+    /// assert_eq!(angry, 1);
+    /// ```
+    pub fn set_state<S: StateComponent>(
+        &mut self,
+        entity: Entity,
+        state: S,
+    ) -> Result<(), NoSuchEntity> {
+        let old = match self.get::<S>(entity) {
+            Ok(x) => Some(x.marker()),
+            Err(ComponentError::NoSuchEntity) => return Err(NoSuchEntity),
+            Err(ComponentError::MissingComponent(_)) => None,
+        };
+        match old {
+            Some(old) if old == state.marker() => {
+                *self.get_mut::<S>(entity).unwrap() = state;
+                return Ok(());
+            }
+            Some(old) => self.remove_dynamic(entity, old)?,
+            None => {}
+        }
+        state.insert_with_marker(self, entity)
+    }
+
+    /// Remove and return the `S` state of `entity`, along with its variant's marker
+    pub fn remove_state<S: StateComponent>(&mut self, entity: Entity) -> Result<S, ComponentError> {
+        let state = self.remove_one::<S>(entity)?;
+        self.remove_dynamic(entity, state.marker())?;
+        Ok(state)
+    }
+}
+
+/// Declare an enum component and a module of marker types, one per variant, kept in sync with it
+///
+/// Each variant of the enum gets a unit struct of the same name in the module, which is present
+/// on exactly the entities whose state has that variant, so long as states are assigned with
+/// [`World::set_state`] and removed with [`World::remove_state`]. Queries can then be restricted
+/// to a variant with filters like `With<state::Stunned, Q>`, and because every variant is stored
+/// in its own archetypes, such queries skip entities in other states without inspecting them.
+/// Changing a state's variant through `get_mut` or a query leaves its marker stale.
+///
+/// # Example
+/// ```
+/// # use hecs::*;
+/// state_component! {
+///     /// What a character is up to
+///     #[derive(Debug, Clone, PartialEq)]
+///     pub enum State {
+///         Idle,
+///         Stunned { turns: u32 },
+///         Walking(f32),
+///     }
+///     pub mod state;
+/// }
+///
+/// let mut world = World::new();
+/// let a = world.spawn((1,));
+/// let b = world.spawn((2,));
+/// world.set_state(a, State::Idle).unwrap();
+/// world.set_state(b, State::Stunned { turns: 2 }).unwrap();
+/// for (_, (x, state)) in world.query_mut::<With<state::Stunned, (&i32, &mut State)>>() {
+///     assert_eq!(*x, 2);
+///     if let State::Stunned { turns } = state {
+///         *turns -= 1;
+///     }
+/// }
+/// world.set_state(b, State::Walking(1.5)).unwrap();
+/// assert_eq!(world.query::<()>().with::<state::Walking>().iter().count(), 1);
+/// assert_eq!(world.remove_state::<State>(a).unwrap(), State::Idle);
+/// assert!(world.get::<state::Idle>(a).is_err());
+/// ```
+#[macro_export]
+macro_rules! state_component {
+    (
+        $(#[$meta:meta])*
+        $vis:vis enum $name:ident {
+            $(
+                $(#[$vmeta:meta])*
+                $variant:ident $(($($tuple:tt)*))? $({$($fields:tt)*})?
+            ),* $(,)?
+        }
+        $mvis:vis mod $markers:ident;
+    ) => {
+        $(#[$meta])*
+        $vis enum $name {
+            $($(#[$vmeta])* $variant $(($($tuple)*))? $({$($fields)*})?),*
+        }
+
+        #[doc = ::core::concat!("Markers of the variants of [`", ::core::stringify!($name), "`]")]
+        $mvis mod $markers {
+            $(
+                #[doc = ::core::concat!(
+                    "Present on entities whose state is [`", ::core::stringify!($variant), "`](super::",
+                    ::core::stringify!($name), "::", ::core::stringify!($variant), ")"
+                )]
+                #[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Hash)]
+                pub struct $variant;
+            )*
+        }
+
+        impl $crate::StateComponent for $name {
+            fn marker(&self) -> ::core::any::TypeId {
+                match *self {
+                    $($name::$variant { .. } => ::core::any::TypeId::of::<$markers::$variant>(),)*
+                }
+            }
+
+            fn insert_with_marker(
+                self,
+                world: &mut $crate::World,
+                entity: $crate::Entity,
+            ) -> ::core::result::Result<(), $crate::NoSuchEntity> {
+                match self {
+                    $(state @ $name::$variant { .. } => {
+                        world.insert(entity, (state, $markers::$variant))
+                    })*
+                }
+            }
+        }
+    };
+}
@@ -0,0 +1,233 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Helpers for comparing worlds, e.g. to debug desyncs between simulations that should agree
+//!
+//! Component values are compared only for types registered with [`World::register_eq`] in either
+//! world. Components of other types are compared by presence alone.
+
+use crate::alloc::format;
+use crate::alloc::string::String;
+use crate::alloc::vec::Vec;
+use core::any::TypeId;
+use core::fmt;
+
+use hashbrown::HashMap;
+
+use crate::{Entity, EntityRef, World};
+
+/// Panic with a report of their differences unless `a` and `b` hold the same entities and
+/// components
+///
+/// # Example
+/// ```
+/// # use hecs::*;
+/// let mut a = World::new();
+/// a.register_eq::<i32>();
+/// a.spawn((1, true));
+/// let mut b = World::new();
+/// b.spawn((1, true));
+/// testing::assert_world_eq(&a, &b);
+/// ```
+#[track_caller]
+pub fn assert_world_eq(a: &World, b: &World) {
+    let delta = WorldDelta::between(a, b);
+    if !delta.is_empty() {
+        panic!("worlds differ:\n{}", delta);
+    }
+}
+
+/// The differences between two worlds' entities and components
+///
+/// Displays as a report listing one difference per line.
+///
+/// # Example
+/// ```
+/// # use hecs::*;
+/// # use hecs::testing::WorldDelta;
+/// let mut a = World::new();
+/// a.register_eq::<i32>();
+/// let e = a.spawn((1, true));
+/// let mut b = World::new();
+/// b.spawn_at(e, (2, true));
+/// let delta = WorldDelta::between(&a, &b);
+/// assert_eq!(delta.differences().len(), 1);
+/// assert_eq!(delta.to_string(), format!("{:?} i32: a = 1, b = 2\n", e));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct WorldDelta {
+    only_in_a: Vec<Entity>,
+    only_in_b: Vec<Entity>,
+    differences: Vec<ComponentDifference>,
+}
+
+impl WorldDelta {
+    /// Compare every entity of `a` with the entity of `b` having the same handle
+    pub fn between(a: &World, b: &World) -> Self {
+        // `World::contains` also reports IDs that could be reserved, so look for spawned entities
+        let mut in_b = b.iter().collect::<HashMap<_, _>>();
+        let mut delta = Self::default();
+        for (entity, x) in a.iter() {
+            match in_b.remove(&entity) {
+                Some(y) => delta.compare(a, b, entity, x, y),
+                None => delta.only_in_a.push(entity),
+            }
+        }
+        delta
+            .only_in_b
+            .extend(in_b.into_iter().map(|(entity, _)| entity));
+        delta.only_in_a.sort_unstable();
+        delta.only_in_b.sort_unstable();
+        delta.differences.sort_by_key(|x| x.entity);
+        delta
+    }
+
+    fn compare(
+        &mut self,
+        a: &World,
+        b: &World,
+        entity: Entity,
+        x: EntityRef<'_>,
+        y: EntityRef<'_>,
+    ) {
+        for ty in x.component_types() {
+            let formatter = a.debug_formatter(ty).or_else(|| b.debug_formatter(ty));
+            let x_ptr = x.get_raw(ty).unwrap().as_ptr();
+            let y_ptr = y.get_raw(ty).map(|y| y.as_ptr());
+            let equal = match (y_ptr, formatter.and_then(|f| f.eq)) {
+                (None, _) => false,
+                (Some(y_ptr), Some(eq)) => unsafe { eq(x_ptr, y_ptr) },
+                (Some(_), None) => true,
+            };
+            if equal {
+                continue;
+            }
+            let describe = |ptr: *const u8| match formatter {
+                Some(f) => format!("{:?}", unsafe { f.display(ptr) }),
+                None => String::from(".."),
+            };
+            self.differences.push(ComponentDifference {
+                entity,
+                ty,
+                name: formatter.map(|f| f.name),
+                a: Some(describe(x_ptr)),
+                b: y_ptr.map(describe),
+            });
+        }
+        for ty in y.component_types() {
+            if x.get_raw(ty).is_some() {
+                continue;
+            }
+            let formatter = a.debug_formatter(ty).or_else(|| b.debug_formatter(ty));
+            let y_ptr = y.get_raw(ty).unwrap().as_ptr();
+            self.differences.push(ComponentDifference {
+                entity,
+                ty,
+                name: formatter.map(|f| f.name),
+                a: None,
+                b: Some(match formatter {
+                    Some(f) => format!("{:?}", unsafe { f.display(y_ptr) }),
+                    None => String::from(".."),
+                }),
+            });
+        }
+    }
+
+    /// Entities of `a` that don't exist in `b`, in ascending order
+    pub fn only_in_a(&self) -> &[Entity] {
+        &self.only_in_a
+    }
+
+    /// Entities of `b` that don't exist in `a`, in ascending order
+    pub fn only_in_b(&self) -> &[Entity] {
+        &self.only_in_b
+    }
+
+    /// Components that differ between entities present in both worlds, ordered by entity
+    pub fn differences(&self) -> &[ComponentDifference] {
+        &self.differences
+    }
+
+    /// Whether the two worlds were found to be identical
+    pub fn is_empty(&self) -> bool {
+        self.only_in_a.is_empty() && self.only_in_b.is_empty() && self.differences.is_empty()
+    }
+}
+
+impl fmt::Display for WorldDelta {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for entity in &self.only_in_a {
+            writeln!(f, "{:?} only in a", entity)?;
+        }
+        for entity in &self.only_in_b {
+            writeln!(f, "{:?} only in b", entity)?;
+        }
+        for x in &self.differences {
+            writeln!(f, "{}", x)?;
+        }
+        Ok(())
+    }
+}
+
+/// A component of one entity that's missing from, or has a different value in, one of two
+/// worlds
+#[derive(Debug, Clone)]
+pub struct ComponentDifference {
+    entity: Entity,
+    ty: TypeId,
+    name: Option<&'static str>,
+    a: Option<String>,
+    b: Option<String>,
+}
+
+impl ComponentDifference {
+    /// The entity whose component differs
+    pub fn entity(&self) -> Entity {
+        self.entity
+    }
+
+    /// The type of the component
+    pub fn type_id(&self) -> TypeId {
+        self.ty
+    }
+
+    /// The name of the component's type, if registered with `World::register_debug` or
+    /// `World::register_eq`
+    pub fn type_name(&self) -> Option<&'static str> {
+        self.name
+    }
+
+    /// The `Debug` formatting of the component in the first world, or `None` if absent there
+    ///
+    /// Values of unregistered types are formatted as `..`.
+    pub fn a(&self) -> Option<&str> {
+        self.a.as_deref()
+    }
+
+    /// The `Debug` formatting of the component in the second world, or `None` if absent there
+    ///
+    /// Values of unregistered types are formatted as `..`.
+    pub fn b(&self) -> Option<&str> {
+        self.b.as_deref()
+    }
+}
+
+impl fmt::Display for ComponentDifference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?} ", self.entity)?;
+        match self.name {
+            Some(name) => write!(f, "{}", name)?,
+            None => write!(f, "{:?}", self.ty)?,
+        }
+        write!(
+            f,
+            ": a = {}, b = {}",
+            self.a.as_deref().unwrap_or("(absent)"),
+            self.b.as_deref().unwrap_or("(absent)")
+        )
+    }
+}
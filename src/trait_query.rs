@@ -0,0 +1,385 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use crate::alloc::{boxed::Box, vec::Vec};
+use core::any::{type_name, TypeId};
+use core::ptr::NonNull;
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use crate::archetype::{Archetype, TypeInfo};
+use crate::entities::EntityMeta;
+use crate::query::{Access, Fetch};
+use crate::{Component, Entity};
+
+/// Converts a pointer to a component into a pointer to the trait object `Tr`, through a shared
+/// reference
+type Cast<Tr> = Box<dyn Fn(*const u8) -> *const Tr + Send + Sync>;
+
+/// Like `Cast`, but through a unique reference
+type CastMut<Tr> = Box<dyn Fn(*mut u8) -> *mut Tr + Send + Sync>;
+
+/// The component types registered as implementing the trait object type `Tr`
+pub(crate) struct TraitImpls<Tr: ?Sized> {
+    impls: Vec<(TypeInfo, Cast<Tr>, CastMut<Tr>)>,
+}
+
+impl<Tr: ?Sized + 'static> TraitImpls<Tr> {
+    pub fn new() -> Self {
+        Self { impls: Vec::new() }
+    }
+
+    pub fn insert<T: Component>(&mut self, cast: fn(&T) -> &Tr, cast_mut: fn(&mut T) -> &mut Tr) {
+        let cast: Cast<Tr> = Box::new(move |x| unsafe { cast(&*x.cast::<T>()) as *const Tr });
+        let cast_mut: CastMut<Tr> =
+            Box::new(move |x| unsafe { cast_mut(&mut *x.cast::<T>()) as *mut Tr });
+        match self
+            .impls
+            .iter_mut()
+            .find(|(ty, _, _)| ty.id() == TypeId::of::<T>())
+        {
+            Some(x) => {
+                x.1 = cast;
+                x.2 = cast_mut;
+            }
+            None => self.impls.push((TypeInfo::of::<T>(), cast, cast_mut)),
+        }
+    }
+
+    /// Every archetype column storing a `Tr` implementation
+    pub fn columns<'a>(&'a self, archetypes: &'a [Archetype]) -> Vec<TraitColumn<'a, Tr>> {
+        let mut columns = Vec::new();
+        for archetype in archetypes.iter().filter(|x| !x.is_empty()) {
+            for (ty, cast, cast_mut) in &self.impls {
+                if archetype.has_dynamic(ty.id()) {
+                    columns.push(TraitColumn {
+                        archetype,
+                        ty,
+                        cast,
+                        cast_mut,
+                    });
+                }
+            }
+        }
+        columns
+    }
+}
+
+pub(crate) struct TraitColumn<'a, Tr: ?Sized> {
+    archetype: &'a Archetype,
+    ty: &'a TypeInfo,
+    cast: &'a Cast<Tr>,
+    cast_mut: &'a CastMut<Tr>,
+}
+
+impl<Tr: ?Sized> Clone for TraitColumn<'_, Tr> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<Tr: ?Sized> Copy for TraitColumn<'_, Tr> {}
+
+impl<'a, Tr: ?Sized> TraitColumn<'a, Tr> {
+    /// Each entity's handle and a pointer to its component as a shared `Tr`
+    pub fn iter(self, meta: &'a [EntityMeta]) -> impl Iterator<Item = (Entity, *const Tr)> + 'a {
+        self.components(meta)
+            .map(move |(entity, _, ptr)| (entity, (self.cast)(ptr)))
+    }
+
+    /// Each entity's handle and a pointer to its component as a unique `Tr`, marking every
+    /// component as changed
+    pub fn iter_mut(self, meta: &'a [EntityMeta]) -> impl Iterator<Item = (Entity, *mut Tr)> + 'a {
+        let archetype = self.archetype;
+        let mutated = archetype.mutated_dynamic(self.ty.id()).unwrap();
+        self.components(meta)
+            .map(move |(entity, index, ptr)| unsafe {
+                (*mutated.as_ptr().add(index as usize)).store(archetype.tick(), Ordering::Relaxed);
+                (entity, (self.cast_mut)(ptr))
+            })
+    }
+
+    /// Each entity's handle, index, and component
+    fn components(
+        self,
+        meta: &'a [EntityMeta],
+    ) -> impl Iterator<Item = (Entity, u32, *mut u8)> + 'a {
+        let archetype = self.archetype;
+        let base = archetype.get_base(self.ty.id()).unwrap();
+        let size = self.ty.layout().size();
+        (0..archetype.len()).map(move |index| unsafe {
+            let id = archetype.entity_id(index);
+            let entity = Entity {
+                id,
+                generation: meta[id as usize].generation,
+            };
+            (entity, index, base.as_ptr().add(size * index as usize))
+        })
+    }
+}
+
+/// A borrow of every component of a `World` registered as implementing `Tr`
+///
+/// Dynamic borrows are acquired when this is constructed and released when it's dropped.
+/// Construct with [`World::query_trait`](crate::World::query_trait).
+pub struct TraitQueryBorrow<'w, Tr: ?Sized> {
+    meta: &'w [EntityMeta],
+    columns: Vec<TraitColumn<'w, Tr>>,
+}
+
+impl<'w, Tr: ?Sized + 'static> TraitQueryBorrow<'w, Tr> {
+    pub(crate) fn new(
+        meta: &'w [EntityMeta],
+        archetypes: &'w [Archetype],
+        impls: Option<&'w TraitImpls<Tr>>,
+    ) -> Self {
+        let columns = impls.map_or_else(Vec::new, |x| x.columns(archetypes));
+        for column in &columns {
+            assert!(
+                column.archetype.borrow_dynamic(column.ty.id()),
+                "component already borrowed uniquely"
+            );
+        }
+        Self { meta, columns }
+    }
+
+    /// Iterate over the components implementing `Tr`, along with their entities
+    ///
+    /// An entity having several such components is visited once for each.
+    pub fn iter(&self) -> impl Iterator<Item = (Entity, &'_ Tr)> + '_ {
+        let meta = self.meta;
+        self.columns.iter().flat_map(move |&column| {
+            column
+                .iter(meta)
+                .map(|(entity, x)| (entity, unsafe { &*x }))
+        })
+    }
+}
+
+unsafe impl<Tr: ?Sized + Sync> Send for TraitQueryBorrow<'_, Tr> {}
+unsafe impl<Tr: ?Sized + Sync> Sync for TraitQueryBorrow<'_, Tr> {}
+
+impl<Tr: ?Sized> Drop for TraitQueryBorrow<'_, Tr> {
+    fn drop(&mut self) {
+        for column in &self.columns {
+            column.archetype.release_dynamic(column.ty.id());
+        }
+    }
+}
+
+/// Make `&dyn Trait` and `&mut dyn Trait` usable as query types, matching entities that have a
+/// component of any of the listed types, each of which must implement `Trait`
+///
+/// Unlike [`World::query_trait`](crate::World::query_trait), these compose with every other query
+/// type, and need no registration with the `World`. An entity having several of the listed
+/// components yields the first of them to be listed. Components stored sparsely are not matched.
+/// Must be invoked in the crate defining `Trait`.
+///
+/// # Example
+/// ```
+/// # use hecs::*;
+/// trait Damageable {
+///     fn damage(&mut self, amount: u32);
+///     fn health(&self) -> u32;
+/// }
+/// struct Monster(u32);
+/// impl Damageable for Monster {
+///     fn damage(&mut self, amount: u32) { self.0 -= amount; }
+///     fn health(&self) -> u32 { self.0 }
+/// }
+/// struct Npc { health: u32 }
+/// impl Damageable for Npc {
+///     fn damage(&mut self, amount: u32) { self.health -= amount; }
+///     fn health(&self) -> u32 { self.health }
+/// }
+/// trait_query!(dyn Damageable: Monster, Npc);
+///
+/// let mut world = World::new();
+/// world.spawn((Monster(10), 2u32));
+/// world.spawn((Npc { health: 20 },));
+/// for (_, (x, &armor)) in world.query_mut::<(&mut dyn Damageable, &u32)>() {
+///     x.damage(5 - armor);
+/// }
+/// let total = world
+///     .query::<&dyn Damageable>()
+///     .iter()
+///     .map(|(_, x)| x.health())
+///     .sum::<u32>();
+/// assert_eq!(total, 27);
+/// ```
+#[macro_export]
+macro_rules! trait_query {
+    (dyn $tr:path: $($ty:ty),+ $(,)?) => {
+        unsafe impl $crate::TraitObject for dyn $tr {
+            fn column(
+                archetype: &$crate::Archetype,
+            ) -> ::core::option::Option<$crate::TraitCast<Self>> {
+                $(
+                    if archetype.has::<$ty>() {
+                        return ::core::option::Option::Some($crate::TraitCast {
+                            ty: ::core::any::TypeId::of::<$ty>(),
+                            size: ::core::mem::size_of::<$ty>(),
+                            cast: |x| x.cast::<$ty>() as *mut Self,
+                        });
+                    }
+                )+
+                ::core::option::Option::None
+            }
+
+            fn for_each_type(mut f: impl ::core::ops::FnMut(::core::any::TypeId)) {
+                $(f(::core::any::TypeId::of::<$ty>());)+
+            }
+        }
+
+        impl<'a> $crate::Query for &'a dyn $tr {
+            type Fetch = $crate::FetchTraitRead<dyn $tr>;
+        }
+
+        impl<'a> $crate::Query for &'a mut dyn $tr {
+            type Fetch = $crate::FetchTraitWrite<dyn $tr>;
+        }
+    };
+}
+
+/// A trait object type made usable as a query type by [`trait_query!`]
+///
+/// # Safety
+///
+/// `column` must only return types stored in a column of `archetype`, with casts valid for their
+/// components, and `for_each_type` must visit every type `column` may return.
+#[doc(hidden)]
+pub unsafe trait TraitObject: 'static {
+    /// The implementation of this trait by a column of `archetype`, if any
+    fn column(archetype: &Archetype) -> Option<TraitCast<Self>>;
+
+    /// Invoke `f` for every component type that may implement this trait
+    fn for_each_type(f: impl FnMut(TypeId));
+}
+
+/// How to view the components of one type as the trait object `Tr`
+#[doc(hidden)]
+pub struct TraitCast<Tr: ?Sized> {
+    pub ty: TypeId,
+    pub size: usize,
+    /// Converts a pointer to a component into a pointer to it as a `Tr`, without dereferencing it
+    pub cast: fn(*mut u8) -> *mut Tr,
+}
+
+/// Stands in for the cast of a dangling fetch, which is never called
+fn no_cast<Tr: ?Sized>(_: *mut u8) -> *mut Tr {
+    unreachable!()
+}
+
+#[doc(hidden)]
+pub struct FetchTraitRead<Tr: ?Sized> {
+    base: NonNull<u8>,
+    size: usize,
+    cast: fn(*mut u8) -> *mut Tr,
+}
+
+unsafe impl<'a, Tr: ?Sized + TraitObject> Fetch<'a> for FetchTraitRead<Tr> {
+    type Item = &'a Tr;
+
+    fn dangling() -> Self {
+        Self {
+            base: NonNull::dangling(),
+            size: 0,
+            cast: no_cast::<Tr>,
+        }
+    }
+
+    fn access(archetype: &Archetype) -> Option<Access> {
+        Tr::column(archetype).map(|_| Access::Read)
+    }
+
+    fn borrow(archetype: &Archetype) {
+        if let Some(x) = Tr::column(archetype) {
+            if !archetype.borrow_dynamic(x.ty) {
+                panic!("{} already borrowed uniquely", type_name::<Tr>());
+            }
+        }
+    }
+    fn new(archetype: &'a Archetype) -> Option<Self> {
+        let x = Tr::column(archetype)?;
+        Some(Self {
+            base: archetype.get_base(x.ty)?,
+            size: x.size,
+            cast: x.cast,
+        })
+    }
+    fn release(archetype: &Archetype) {
+        if let Some(x) = Tr::column(archetype) {
+            archetype.release_dynamic(x.ty);
+        }
+    }
+
+    fn for_each_borrow(mut f: impl FnMut(TypeId, bool)) {
+        Tr::for_each_type(|ty| f(ty, false));
+    }
+
+    unsafe fn get(&self, n: usize) -> Self::Item {
+        &*(self.cast)(self.base.as_ptr().add(self.size * n))
+    }
+}
+
+#[doc(hidden)]
+pub struct FetchTraitWrite<Tr: ?Sized> {
+    base: NonNull<u8>,
+    size: usize,
+    cast: fn(*mut u8) -> *mut Tr,
+    mutated: NonNull<AtomicU32>,
+    tick: u32,
+}
+
+unsafe impl<'a, Tr: ?Sized + TraitObject> Fetch<'a> for FetchTraitWrite<Tr> {
+    type Item = &'a mut Tr;
+
+    fn dangling() -> Self {
+        Self {
+            base: NonNull::dangling(),
+            size: 0,
+            cast: no_cast::<Tr>,
+            mutated: NonNull::dangling(),
+            tick: 0,
+        }
+    }
+
+    fn access(archetype: &Archetype) -> Option<Access> {
+        Tr::column(archetype).map(|_| Access::Write)
+    }
+
+    fn borrow(archetype: &Archetype) {
+        if let Some(x) = Tr::column(archetype) {
+            if !archetype.borrow_mut_dynamic(x.ty) {
+                panic!("{} already borrowed", type_name::<Tr>());
+            }
+        }
+    }
+    fn new(archetype: &'a Archetype) -> Option<Self> {
+        let x = Tr::column(archetype)?;
+        Some(Self {
+            base: archetype.get_base(x.ty)?,
+            size: x.size,
+            cast: x.cast,
+            mutated: archetype.mutated_dynamic(x.ty)?,
+            tick: archetype.tick(),
+        })
+    }
+    fn release(archetype: &Archetype) {
+        if let Some(x) = Tr::column(archetype) {
+            archetype.release_mut_dynamic(x.ty);
+        }
+    }
+
+    fn for_each_borrow(mut f: impl FnMut(TypeId, bool)) {
+        Tr::for_each_type(|ty| f(ty, true));
+    }
+
+    unsafe fn get(&self, n: usize) -> Self::Item {
+        (*self.mutated.as_ptr().add(n)).store(self.tick, Ordering::Relaxed);
+        &mut *(self.cast)(self.base.as_ptr().add(self.size * n))
+    }
+}
@@ -0,0 +1,113 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use crate::alloc::vec::Vec;
+
+use crate::{Bundle, CommandBuffer, DynamicBundle, Entity, World};
+
+impl World {
+    /// Run `f`, applying the structural changes it stages only if it returns `Ok`
+    ///
+    /// Spawns, despawns, insertions, and removals made through the [`WorldTransaction`] are
+    /// recorded rather than applied, so they aren't visible through
+    /// [`WorldTransaction::world`] until committed. If `f` returns `Err` or panics, they're
+    /// discarded, along with the handles reserved by `WorldTransaction::spawn`, leaving the
+    /// world as it was. Components modified in place, e.g. through a query, are not rolled back.
+    ///
+    /// # Example
+    /// ```
+    /// # use hecs::*;
+    /// let mut world = World::new();
+    /// let a = world.spawn((1,));
+    /// let result = world.transaction(|tx| {
+    ///     tx.spawn(("goblin",));
+    ///     tx.despawn(a);
+    ///     Err("out of goblins")
+    /// });
+    /// assert_eq!(result, Err::<(), _>("out of goblins"));
+    /// assert!(world.contains(a));
+    /// assert_eq!(world.len(), 1);
+    ///
+    /// let b = world.transaction(|tx| Ok::<_, ()>(tx.spawn(("goblin",)))).unwrap();
+    /// assert_eq!(*world.get::<&str>(b).unwrap(), "goblin");
+    /// ```
+    pub fn transaction<T, E>(
+        &mut self,
+        f: impl FnOnce(&mut WorldTransaction<'_>) -> Result<T, E>,
+    ) -> Result<T, E> {
+        let mut rollback = Rollback {
+            world: self,
+            reserved: Vec::new(),
+        };
+        let mut tx = WorldTransaction {
+            world: rollback.world,
+            cmd: CommandBuffer::new(),
+            reserved: &mut rollback.reserved,
+        };
+        let result = f(&mut tx)?;
+        let mut cmd = tx.cmd;
+        // The reserved entities are about to receive their components
+        rollback.reserved.clear();
+        cmd.run_on(rollback.world);
+        Ok(result)
+    }
+}
+
+/// Structural changes staged by [`World::transaction`]
+pub struct WorldTransaction<'w> {
+    world: &'w World,
+    cmd: CommandBuffer,
+    reserved: &'w mut Vec<Entity>,
+}
+
+impl<'w> WorldTransaction<'w> {
+    /// The world as it was when the transaction began
+    pub fn world(&self) -> &'w World {
+        self.world
+    }
+
+    /// Stage spawning an entity with components from `bundle`, returning its handle
+    ///
+    /// Until the transaction commits, the entity is only reserved, as by `World::reserve_entity`.
+    pub fn spawn(&mut self, bundle: impl DynamicBundle) -> Entity {
+        let entity = self.world.reserve_entity();
+        self.reserved.push(entity);
+        self.cmd.insert(entity, bundle);
+        entity
+    }
+
+    /// Stage adding components from `bundle` to `entity`, as by `World::insert`
+    ///
+    /// The components are dropped if `entity` doesn't exist when the transaction commits.
+    pub fn insert(&mut self, entity: Entity, bundle: impl DynamicBundle) {
+        self.cmd.insert(entity, bundle);
+    }
+
+    /// Stage removing and dropping `T` components from `entity`, as by `World::remove`
+    pub fn remove<T: Bundle + 'static>(&mut self, entity: Entity) {
+        self.cmd.remove::<T>(entity);
+    }
+
+    /// Stage despawning `entity`, as by `World::despawn`
+    pub fn despawn(&mut self, entity: Entity) {
+        self.cmd.despawn(entity);
+    }
+}
+
+/// Releases the entities reserved by a transaction unless it commits, including while unwinding
+struct Rollback<'w> {
+    world: &'w mut World,
+    reserved: Vec<Entity>,
+}
+
+impl Drop for Rollback<'_> {
+    fn drop(&mut self) {
+        for &entity in &self.reserved {
+            let _ = self.world.despawn(entity);
+        }
+    }
+}
@@ -0,0 +1,163 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Propagation of transforms through the hierarchy maintained by [`World::attach`]
+//!
+//! Generic over the transform type, which is typically a matrix or affine transform from a math
+//! library. Composition is by multiplication, with parents on the left, as is usual for
+//! column-vector conventions.
+//!
+//! # Example
+//! ```
+//! # use hecs::*;
+//! use hecs::transform::{propagate_transforms, GlobalTransform, LocalTransform};
+//!
+//! let mut world = World::new();
+//! let parent = world.spawn((LocalTransform(2),));
+//! let child = world.spawn((LocalTransform(3),));
+//! world.attach(child, parent).unwrap();
+//! propagate_transforms::<i32>(&mut world);
+//! assert_eq!(world.get::<GlobalTransform<i32>>(child).unwrap().0, 6);
+//!
+//! world.clear_trackers();
+//! world.get_mut::<LocalTransform<i32>>(parent).unwrap().0 = 5;
+//! propagate_transforms::<i32>(&mut world);
+//! assert_eq!(world.get::<GlobalTransform<i32>>(child).unwrap().0, 15);
+//! ```
+
+use crate::alloc::{vec, vec::Vec};
+use core::ops::Mul;
+
+use hashbrown::HashSet;
+
+use crate::{Changed, Children, Component, Entity, Parent, With, Without, World};
+
+/// An entity's transform relative to its parent, or to the world if it has none
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct LocalTransform<T>(pub T);
+
+/// An entity's transform relative to the world, computed by [`propagate_transforms`]
+///
+/// Modifying this component directly has no lasting effect.
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct GlobalTransform<T>(pub T);
+
+/// Update the [`GlobalTransform`] of every entity whose [`LocalTransform`] or that of an ancestor
+/// changed since the last call to `World::clear_trackers`
+///
+/// Each entity with a `LocalTransform<T>` receives a `GlobalTransform<T>` equal to its parent's
+/// `GlobalTransform<T>` multiplied by its `LocalTransform<T>`, or just its `LocalTransform<T>` if
+/// it has no parent or its parent has no `LocalTransform<T>`. Subtrees in which no transform
+/// changed and no entity was attached or detached are skipped. Entities that lost their
+/// `LocalTransform<T>` also lose their `GlobalTransform<T>`. Typically called once per frame,
+/// before `clear_trackers`; removals made before the first call aren't observed.
+pub fn propagate_transforms<T>(world: &mut World)
+where
+    T: Component + Copy + Mul<Output = T>,
+{
+    world.track_removed::<Parent>();
+    world.track_removed::<LocalTransform<T>>();
+
+    // Entities whose global transforms may be stale, regardless of their ancestors
+    let mut dirty = HashSet::<Entity>::new();
+    dirty.extend(
+        world
+            .query::<Changed<LocalTransform<T>, ()>>()
+            .iter()
+            .map(|(e, ())| e),
+    );
+    dirty.extend(
+        world
+            .query::<With<LocalTransform<T>, Changed<Parent, ()>>>()
+            .iter()
+            .map(|(e, ())| e),
+    );
+    dirty.extend(
+        world
+            .query::<Without<GlobalTransform<T>, With<LocalTransform<T>, ()>>>()
+            .iter()
+            .map(|(e, ())| e),
+    );
+    for &entity in world.removed::<Parent>() {
+        if world
+            .satisfies::<&LocalTransform<T>>(entity)
+            .unwrap_or(false)
+        {
+            dirty.insert(entity);
+        }
+    }
+    let mut orphaned = Vec::new();
+    for &entity in world.removed::<LocalTransform<T>>() {
+        if let Ok(children) = world.get::<Children>(entity) {
+            dirty.extend(children.iter());
+        }
+        if world
+            .satisfies::<&GlobalTransform<T>>(entity)
+            .unwrap_or(false)
+        {
+            orphaned.push(entity);
+        }
+    }
+    for entity in orphaned {
+        if !world.satisfies::<&LocalTransform<T>>(entity).unwrap() {
+            world.remove_one::<GlobalTransform<T>>(entity).unwrap();
+        }
+    }
+
+    // Recompute only the subtrees beneath dirty entities with no dirty ancestor
+    let roots = dirty
+        .iter()
+        .copied()
+        .filter(|&entity| {
+            let mut ancestor = transform_parent::<T>(world, entity);
+            while let Some(x) = ancestor {
+                if dirty.contains(&x) {
+                    return false;
+                }
+                ancestor = transform_parent::<T>(world, x);
+            }
+            true
+        })
+        .collect::<Vec<_>>();
+    for root in roots {
+        let parent = transform_parent::<T>(world, root)
+            .map(|x| world.get::<GlobalTransform<T>>(x).unwrap().0);
+        let mut pending = vec![(root, parent)];
+        while let Some((entity, parent)) = pending.pop() {
+            let local = world.get::<LocalTransform<T>>(entity).unwrap().0;
+            let global = match parent {
+                Some(parent) => parent * local,
+                None => local,
+            };
+            let stored = match world.get_mut::<GlobalTransform<T>>(entity) {
+                Ok(mut x) => {
+                    x.0 = global;
+                    true
+                }
+                Err(_) => false,
+            };
+            if !stored {
+                world.insert_one(entity, GlobalTransform(global)).unwrap();
+            }
+            if let Ok(children) = world.get::<Children>(entity) {
+                pending.extend(
+                    children
+                        .iter()
+                        .filter(|&x| world.satisfies::<&LocalTransform<T>>(x).unwrap())
+                        .map(|x| (x, Some(global))),
+                );
+            }
+        }
+    }
+}
+
+/// The parent of `entity`, if it has a `LocalTransform<T>`
+fn transform_parent<T: Component>(world: &World, entity: Entity) -> Option<Entity> {
+    world
+        .parent(entity)
+        .filter(|&x| world.satisfies::<&LocalTransform<T>>(x).unwrap())
+}
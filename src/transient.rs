@@ -0,0 +1,60 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use crate::alloc::collections::BinaryHeap;
+use crate::alloc::vec::Vec;
+use core::any::TypeId;
+use core::cmp::Reverse;
+
+use hashbrown::HashMap;
+
+use crate::Entity;
+
+/// What expires: one component of an entity, or the whole entity if `None`
+pub(crate) type Target = (Entity, Option<TypeId>);
+
+/// Components and entities due to be removed by `World::tick`
+#[derive(Default)]
+pub(crate) struct Transients {
+    /// Number of times `World::tick` has been called
+    now: u64,
+    /// The tick at which each target currently expires
+    expiries: HashMap<Target, u64>,
+    /// Every expiry scheduled, soonest first, including those since superseded in `expiries`
+    queue: BinaryHeap<Reverse<(u64, Target)>>,
+}
+
+impl Transients {
+    /// Schedule `target` to expire at the `ttl`th following tick, replacing any earlier schedule
+    pub(crate) fn schedule(&mut self, target: Target, ttl: u32) {
+        let expiry = self.now + u64::from(ttl.max(1));
+        self.expiries.insert(target, expiry);
+        self.queue.push(Reverse((expiry, target)));
+    }
+
+    /// Advance to the next tick, returning the targets that expire on it
+    pub(crate) fn advance(&mut self) -> Vec<Target> {
+        self.now += 1;
+        let mut expired = Vec::new();
+        while let Some(&Reverse((expiry, target))) = self.queue.peek() {
+            if expiry > self.now {
+                break;
+            }
+            self.queue.pop();
+            if self.expiries.get(&target) == Some(&expiry) {
+                self.expiries.remove(&target);
+                expired.push(target);
+            }
+        }
+        expired
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.expiries.clear();
+        self.queue.clear();
+    }
+}
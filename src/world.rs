@@ -5,9 +5,19 @@
 // http://opensource.org/licenses/MIT>, at your option. This file may not be
 // copied, modified, or distributed except according to those terms.
 
-use crate::alloc::vec::Vec;
-use core::any::TypeId;
+use crate::alloc::{vec, vec::Vec};
+use core::alloc::GlobalAlloc;
+use core::any::{type_name, Any, TypeId};
 use core::convert::TryFrom;
+use core::hash::Hash;
+use core::iter::FusedIterator;
+use core::marker::PhantomData;
+use core::ops::Range;
+#[cfg(target_has_atomic = "64")]
+use core::sync::atomic::AtomicU64;
+#[cfg(not(target_has_atomic = "64"))]
+use core::sync::atomic::AtomicUsize;
+use core::sync::atomic::{AtomicU32, Ordering};
 use core::{fmt, mem, ptr};
 
 #[cfg(feature = "std")]
@@ -15,12 +25,34 @@ use std::error::Error;
 
 use hashbrown::{HashMap, HashSet};
 
+use crate::alloc::borrow::Cow;
 use crate::alloc::boxed::Box;
-use crate::archetype::Archetype;
-use crate::entities::{Entities, Location, ReserveEntitiesIterator};
+use crate::alloc::sync::Arc;
+use crate::archetype::{Archetype, ComponentTicks, StorageAllocator, TypeIdMap, TypeInfo};
+use crate::chunk::{ChunkComponent, ChunkValue};
+use crate::component_index::{AnyIndex, ComponentIndex};
+use crate::disabled::EnabledFetch;
+use crate::double_buffer;
+use crate::dynamic_query::{DynamicQuery, DynamicQueryBorrow, QueryParseError};
+use crate::entities::{
+    Entities, EntityAudit, EntityMeta, GenerationPolicy, Location, ReserveEntitiesIterator,
+};
+use crate::events::{EventLog, WorldEvent};
+use crate::hierarchy::{self, Children, Parent};
+use crate::inspect::{DebugEntity, DebugFormatter};
+use crate::pool::{PoolStats, Pools};
+#[cfg(feature = "profile")]
+use crate::profile::WorldProfiler;
+use crate::resources::{ResourceRef, ResourceRefMut, Resources};
+use crate::snapshot::{ArchetypeSnapshot, SnapshotRegistry, WorldSnapshot};
+use crate::sparse::{SparseSet, SparseSets};
+use crate::trait_query::{TraitImpls, TraitQueryBorrow};
+use crate::transient::Transients;
 use crate::{
-    Bundle, DynamicBundle, Entity, EntityRef, Fetch, MissingComponent, NoSuchEntity, Query,
-    QueryBorrow, QueryItem, QueryMut, QueryOne, Ref, RefMut,
+    Bundle, Column, ColumnBatch, ColumnMut, ComponentColumns, Disabled, DynamicBundle, Entity,
+    EntityBuilder, EntityId, EntityRef, Fetch, Including, Label, MissingComponent, NoSuchEntity,
+    Query, QueryAccess, QueryBorrow, QueryItem, QueryIter, QueryMut, QueryOne, RawRef, RawRefMut,
+    Ref, RefMut, StableComponent, StableTypeId, TakenEntity, UnsafeWorldCell, With, WorldView,
 };
 
 /// An unordered collection of entities, each having any number of distinctly typed components
@@ -29,7 +61,11 @@ use crate::{
 /// type, but far more efficient to traverse.
 ///
 /// The components of entities who have the same set of component types are stored in contiguous
-/// runs, allowing for extremely fast, cache-friendly iteration.
+/// runs, allowing for extremely fast, cache-friendly iteration. Zero-sized components, such as unit
+/// structs used as markers, occupy no storage beyond their change ticks, and are never copied.
+/// Each entity's components sit at the same index of every column of its archetype, so a query
+/// visits the columns it needs as parallel slices, without any "group" declaration like those of
+/// sparse-set ECSs. Components registered with `register_sparse` are the exception.
 ///
 /// There is a maximum number of unique entity IDs, which means that there is a maximum number of live
 /// entities. When old entities are despawned, their IDs will be reused on a future entity, and
@@ -43,27 +79,156 @@ use crate::{
 /// over which they may retain handles of despawned entities.
 pub struct World {
     entities: Entities,
+    /// Distinguishes this world, and its archetypes since any `compact`, from all others, for the
+    /// benefit of `PreparedQuery`
+    id: u64,
     index: HashMap<Box<[TypeId]>, u32>,
+    /// Like `index`, for archetypes having chunk components
+    chunked_index: HashMap<ChunkedKey, u32>,
     archetypes: Vec<Archetype>,
-    archetype_generation: u64,
+    /// Stamped onto components when they're added or mutably accessed; see `clear_trackers`
+    change_tick: u32,
+    resources: Resources,
+    remove_hooks: TypeIdMap<RemoveHook>,
+    events: EventLog,
+    /// `TraitImpls` for each trait object type registered with `register_trait`
+    trait_impls: TypeIdMap<Box<dyn Any + Send + Sync>>,
+    /// Storage for component types registered with `register_sparse`
+    sparse: SparseSets,
+    /// See `set_preserve_order`
+    preserve_order: bool,
+    /// Component types registered with `register_named`
+    names: HashMap<Box<str>, TypeInfo>,
+    /// The types in `names`, by their `StableTypeId`s
+    stable_types: HashMap<StableTypeId, TypeInfo>,
+    /// Entity holding each `Label`
+    labels: HashMap<Box<str>, Entity>,
+    /// Allocator of component storage, if not the global allocator
+    allocator: Option<StorageAllocator>,
+    /// Components and entities scheduled to expire by `insert_transient` and `spawn_transient`
+    transients: Transients,
+    /// Formatters of component types registered with `register_debug`
+    debug_formatters: TypeIdMap<DebugFormatter>,
+    /// `ComponentIndex` of each component type registered with `index_component`
+    component_indexes: TypeIdMap<Box<dyn AnyIndex>>,
+    /// Component types registered with `register_double_buffered`, with the `flip` of each
+    double_buffered: Vec<(TypeId, Flip)>,
+    /// Pools of component types registered with `register_pool`
+    pools: Pools,
+    #[cfg(feature = "profile")]
+    profiler: WorldProfiler,
 }
 
+/// Component types and chunk components identifying an archetype
+type ChunkedKey = (Box<[TypeId]>, Box<[ChunkValue]>);
+
+/// Invoked on a component, given its entity, just before it leaves the `World`
+type RemoveHook = Box<dyn Fn(Entity, *mut u8) + Send + Sync>;
+
+/// Updates the `Prev` components of a type registered with `World::register_double_buffered`
+type Flip = fn(&mut World);
+
 impl World {
     /// Create an empty world
     pub fn new() -> Self {
         // `flush` assumes archetype 0 always exists, representing entities with no components.
-        let mut archetypes = Vec::new();
-        archetypes.push(Archetype::new(Vec::new()));
+        let archetypes = vec![Archetype::new(Vec::new())];
         let mut index = HashMap::default();
         index.insert(Box::default(), 0);
         Self {
             entities: Entities::default(),
+            id: next_world_id(),
             index,
+            chunked_index: HashMap::default(),
             archetypes,
-            archetype_generation: 0,
+            change_tick: 1,
+            resources: Resources::default(),
+            remove_hooks: TypeIdMap::default(),
+            events: EventLog::default(),
+            trait_impls: TypeIdMap::default(),
+            sparse: SparseSets::default(),
+            preserve_order: false,
+            names: HashMap::default(),
+            stable_types: HashMap::default(),
+            labels: HashMap::default(),
+            allocator: None,
+            transients: Transients::default(),
+            debug_formatters: TypeIdMap::default(),
+            component_indexes: TypeIdMap::default(),
+            double_buffered: Vec::new(),
+            pools: Pools::default(),
+            #[cfg(feature = "profile")]
+            profiler: WorldProfiler::default(),
         }
     }
 
+    /// Create an empty world whose component storage is allocated from `allocator`
+    ///
+    /// Useful for keeping to a memory budget, or for measuring how much memory is used. Component
+    /// columns, including their change ticks, come from `allocator`; bookkeeping such as entity
+    /// metadata and archetype indices still comes from the global allocator. Column batches
+    /// extracted from the world keep their storage until they're spawned elsewhere.
+    ///
+    /// # Example
+    /// ```
+    /// # use hecs::*;
+    /// use std::alloc::{GlobalAlloc, Layout, System};
+    /// use std::sync::{atomic::{AtomicUsize, Ordering}, Arc};
+    ///
+    /// #[derive(Default)]
+    /// struct Counting(AtomicUsize);
+    ///
+    /// unsafe impl GlobalAlloc for Counting {
+    ///     unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+    ///         self.0.fetch_add(layout.size(), Ordering::Relaxed);
+    ///         System.alloc(layout)
+    ///     }
+    ///     unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+    ///         self.0.fetch_sub(layout.size(), Ordering::Relaxed);
+    ///         System.dealloc(ptr, layout)
+    ///     }
+    /// }
+    ///
+    /// let allocator = Arc::new(Counting::default());
+    /// let mut world = World::with_allocator(allocator.clone());
+    /// world.spawn_batch((0..100).map(|i| (i as u64,)));
+    /// assert!(allocator.0.load(Ordering::Relaxed) >= 100 * 8);
+    /// drop(world);
+    /// assert_eq!(allocator.0.load(Ordering::Relaxed), 0);
+    /// ```
+    pub fn with_allocator<A: GlobalAlloc + Send + Sync + 'static>(allocator: Arc<A>) -> Self {
+        let mut world = Self::new();
+        world.allocator = Some(allocator);
+        world
+    }
+
+    /// Create an empty world with room for `entities` entity handles
+    ///
+    /// Together with `register_archetype`, `try_spawn`, and `try_insert`, lets a world run within
+    /// a fixed memory budget. Reserve every handle and archetype that will be needed up front;
+    /// afterwards `try_spawn` and `try_insert` report when storage is exhausted instead of
+    /// allocating more. Despawning an entity frees its handle and its slot in the archetype for
+    /// reuse. No allocation happens in these calls so long as no component type is stored
+    /// sparsely and event recording is off.
+    ///
+    /// # Example
+    /// ```
+    /// # use hecs::*;
+    /// let mut world = World::with_capacity(2);
+    /// world.register_archetype::<(i32,)>(2);
+    /// world.register_archetype::<(i32, bool)>(1);
+    /// let a = world.try_spawn((1,)).unwrap();
+    /// let b = world.try_spawn((2,)).unwrap();
+    /// assert_eq!(world.try_spawn((3,)), Err((3,)));
+    /// world.try_insert(a, (true,)).unwrap();
+    /// assert_eq!(world.try_insert(b, (false,)), Err((false,)));
+    /// ```
+    pub fn with_capacity(entities: u32) -> Self {
+        let mut world = Self::new();
+        world.entities.reserve_total(entities);
+        world
+    }
+
     /// Create an entity with certain components
     ///
     /// Returns the ID of the newly created entity.
@@ -89,8 +254,35 @@ impl World {
 
         let entity = self.entities.alloc();
 
-        self.spawn_inner(entity, components);
+        self.spawn_inner(entity, components, &[]);
+
+        entity
+    }
 
+    /// Create an entity with certain components, sharing the chunk component `chunk`
+    ///
+    /// Entities with equal chunk components of a given type are stored together, and the value
+    /// is stored once for all of them. See `Chunk` to access the value in queries, and
+    /// `set_chunk` to change it.
+    ///
+    /// # Example
+    /// ```
+    /// # use hecs::*;
+    /// #[derive(Clone, PartialEq, Eq, Hash, Debug)]
+    /// struct Material(u32);
+    /// let mut world = World::new();
+    /// let a = world.spawn_chunked(Material(7), (123,));
+    /// assert_eq!(world.chunk::<Material>(a).unwrap(), &Material(7));
+    /// assert_eq!(*world.get::<i32>(a).unwrap(), 123);
+    /// ```
+    pub fn spawn_chunked<C: ChunkComponent>(
+        &mut self,
+        chunk: C,
+        components: impl DynamicBundle,
+    ) -> Entity {
+        self.flush();
+        let entity = self.entities.alloc();
+        self.spawn_inner(entity, components, &[ChunkValue::new(chunk)]);
         entity
     }
 
@@ -119,29 +311,51 @@ impl World {
         // Ensure all entity allocations are accounted for so `self.entities` can realloc if
         // necessary
         self.flush();
-
-        let loc = self.entities.alloc_at(handle);
-        if let Some(loc) = loc {
-            if let Some(moved) =
-                unsafe { self.archetypes[loc.archetype as usize].remove(loc.index) }
-            {
-                self.entities.meta[moved as usize].location.index = loc.index;
-            }
+        if let Some(existing) = self.entities.occupant(handle.id) {
+            self.despawn(existing).unwrap();
         }
 
-        self.spawn_inner(handle, components);
+        let displaced = self.entities.alloc_at(handle);
+        debug_assert!(displaced.is_none());
+
+        self.spawn_inner(handle, components, &[]);
     }
 
-    fn spawn_inner(&mut self, entity: Entity, components: impl DynamicBundle) {
-        let archetype_id = components.with_ids(|ids| {
-            self.index.get(ids).copied().unwrap_or_else(|| {
-                let x = self.archetypes.len() as u32;
-                self.archetypes.push(Archetype::new(components.type_info()));
-                self.index.insert(ids.into(), x);
-                self.archetype_generation += 1;
-                x
-            })
-        });
+    /// Create an entity with certain components, only if storage already exists for it
+    ///
+    /// Unlike `spawn`, never allocates: fails, returning `components`, if the archetype for
+    /// exactly `B`'s components hasn't been created, for example by `register_archetype`, or is
+    /// full, or if no entity handle is free. See `with_capacity`.
+    ///
+    /// Entities with sparsely stored components may still cause allocation.
+    pub fn try_spawn<B: Bundle>(&mut self, components: B) -> Result<Entity, B> {
+        self.flush();
+        let archetypes = &self.archetypes;
+        let index = &self.index;
+        let room = B::with_static_ids(|ids| index.get(ids).copied())
+            .map(|x| &archetypes[x as usize])
+            .filter(|x| x.len() < x.capacity())
+            .is_some();
+        if !room || !self.entities.has_spare_capacity() {
+            return Err(components);
+        }
+        let entity = self.entities.alloc();
+        self.spawn_inner(entity, components, &[]);
+        Ok(entity)
+    }
+
+    fn spawn_inner(
+        &mut self,
+        entity: Entity,
+        components: impl DynamicBundle,
+        chunk: &[ChunkValue],
+    ) {
+        if !self.sparse.is_empty() {
+            self.spawn_sparse(entity, components, chunk);
+            return;
+        }
+        let archetype_id = components
+            .with_ids(|ids| self.archetype_in_chunk(ids, chunk, || components.type_info()));
 
         let archetype = &mut self.archetypes[archetype_id as usize];
         unsafe {
@@ -154,22 +368,65 @@ impl World {
                 index,
             };
         }
+        self.events.added(entity, archetype.types());
+    }
+
+    /// Like `spawn_inner`, but moving components of sparsely stored types into their sets
+    fn spawn_sparse(
+        &mut self,
+        entity: Entity,
+        components: impl DynamicBundle,
+        chunk: &[ChunkValue],
+    ) {
+        let info = components.type_info();
+        let dense = info
+            .iter()
+            .copied()
+            .filter(|x| !self.sparse.contains(x.id()))
+            .collect::<Vec<_>>();
+        let ids = dense.iter().map(|x| x.id()).collect::<Vec<_>>();
+        let archetype_id = self.archetype_in_chunk(&ids, chunk, || dense);
+
+        let archetype = &mut self.archetypes[archetype_id as usize];
+        let sparse = &mut self.sparse;
+        unsafe {
+            let index = archetype.allocate(entity.id);
+            components.put(|ptr, ty| {
+                if archetype.has_dynamic(ty.id()) {
+                    archetype.put_dynamic(ptr, ty.id(), ty.layout().size(), index);
+                } else {
+                    sparse.get_mut(ty.id()).unwrap().put(entity.id, ptr);
+                }
+            });
+            self.entities.meta[entity.id as usize].location = Location {
+                archetype: archetype_id,
+                index,
+            };
+        }
+        self.events.added(entity, &info);
     }
 
     /// Efficiently spawn a large number of entities with the same components
     ///
-    /// Faster than calling `spawn` repeatedly with the same components.
+    /// Faster than calling `spawn` repeatedly with the same components. Every entity is spawned
+    /// before this returns, with storage for as many as `iter`'s size hint allows reserved up
+    /// front, so iterators with exact size hints such as ranges and `Vec`s never cause storage to
+    /// be reallocated midway.
+    ///
+    /// The returned handles, in the order their components were yielded, don't borrow the world.
     ///
     /// # Example
     /// ```
     /// # use hecs::*;
     /// let mut world = World::new();
-    /// let entities = world.spawn_batch((0..1_000).map(|i| (i, "abc"))).collect::<Vec<_>>();
-    /// for i in 0..1_000 {
-    ///     assert_eq!(*world.get::<i32>(entities[i]).unwrap(), i as i32);
+    /// let entities = world.spawn_batch((0..1_000).map(|i| (i, "abc")));
+    /// for (i, &e) in entities.as_slice().iter().enumerate() {
+    ///     assert_eq!(*world.get::<i32>(e).unwrap(), i as i32);
     /// }
+    /// world.spawn((true,));
+    /// assert_eq!(entities.into_vec().len(), 1_000);
     /// ```
-    pub fn spawn_batch<I>(&mut self, iter: I) -> SpawnBatchIter<'_, I::IntoIter>
+    pub fn spawn_batch<I>(&mut self, iter: I) -> SpawnedEntities
     where
         I: IntoIterator,
         I::Item: Bundle,
@@ -178,495 +435,4146 @@ impl World {
         // necessary
         self.flush();
 
-        let iter = iter.into_iter();
+        let mut iter = iter.into_iter();
         let (lower, upper) = iter.size_hint();
-        let archetype_id = self.reserve_inner::<I::Item>(
-            u32::try_from(upper.unwrap_or(lower)).expect("iterator too large"),
+        let expected = upper.unwrap_or(lower);
+        let archetype_id =
+            self.reserve_inner::<I::Item>(u32::try_from(expected).expect("iterator too large"));
+        let archetype = &mut self.archetypes[archetype_id as usize];
+        let mut handles = Vec::with_capacity(expected);
+        while let Some(components) = iter.next() {
+            if archetype.len() == archetype.capacity() {
+                // The size hint was an underestimate; reserve for as many more as it now promises
+                let additional = u32::try_from(iter.size_hint().0.saturating_add(1))
+                    .expect("iterator too large");
+                archetype.reserve(additional);
+                self.entities.reserve(additional);
+            }
+            let entity = self.entities.alloc();
+            unsafe {
+                let index = archetype.allocate(entity.id);
+                components.put(|ptr, ty| {
+                    archetype.put_dynamic(ptr, ty.id(), ty.layout().size(), index);
+                });
+                self.entities.meta[entity.id as usize].location = Location {
+                    archetype: archetype_id,
+                    index,
+                };
+            }
+            self.events.added(entity, archetype.types());
+            handles.push(entity);
+        }
+        trace_event!(
+            DEBUG,
+            archetype = archetype_id,
+            entities = handles.len(),
+            bytes = handles.len()
+                * archetype
+                    .types()
+                    .iter()
+                    .map(|x| x.layout().size())
+                    .sum::<usize>(),
+            "spawned batch"
         );
-
-        SpawnBatchIter {
-            inner: iter,
-            entities: &mut self.entities,
-            archetype_id,
-            archetype: &mut self.archetypes[archetype_id as usize],
+        SpawnedEntities {
+            inner: handles.into_iter(),
         }
     }
 
-    /// Allocate many entities ID concurrently
+    /// Spawn entities whose components are stored in `batch`
     ///
-    /// Unlike `spawn`, this can be called simultaneously to other operations on the `World` such as
-    /// queries, but does not immediately create the entities. Reserved entities are not visible to
-    /// queries or world iteration, but can be otherwise operated on freely. Operations that
-    /// uniquely borrow the world, such as `insert` or `despawn`, will cause all outstanding
-    /// reserved entities to become real entities before proceeding. This can also be done
-    /// explicitly by calling `flush`.
+    /// Faster than `spawn_batch` because components are moved a whole column at a time. If an
+    /// archetype with exactly the batch's component types already exists, each column is appended
+    /// onto that archetype's, without creating another or spawning entities one at a time. The
+    /// entities of a batch returned by `extract` share the chunk components they had before.
     ///
-    /// Useful for reserving an ID that will later have components attached to it with `insert`.
-    pub fn reserve_entities(&self, count: u32) -> ReserveEntitiesIterator {
-        self.entities.reserve_entities(count)
-    }
-
-    /// Allocate an entity ID concurrently
+    /// Returns the handles of the new entities, which are spawned immediately, in the same order
+    /// as their components were written to `batch`. Entities spawned together can therefore be
+    /// linked to one another straight away.
     ///
-    /// See `reserve_entities`.
-    pub fn reserve_entity(&self) -> Entity {
-        self.entities.reserve_entity()
-    }
-
-    /// Destroy an entity and all its components
-    pub fn despawn(&mut self, entity: Entity) -> Result<(), NoSuchEntity> {
-        self.flush();
-        let loc = self.entities.free(entity)?;
-        if let Some(moved) = unsafe { self.archetypes[loc.archetype as usize].remove(loc.index) } {
-            self.entities.meta[moved as usize].location.index = loc.index;
-        }
-        Ok(())
-    }
-
-    /// Ensure `additional` entities with exact components `T` can be spawned without reallocating
-    pub fn reserve<T: Bundle>(&mut self, additional: u32) {
-        self.reserve_inner::<T>(additional);
+    /// # Example
+    /// ```
+    /// # use hecs::*;
+    /// struct Emitter(Entity);
+    ///
+    /// let mut world = World::new();
+    /// let mut ty = ColumnBatchType::new();
+    /// ty.add::<bool>();
+    /// let mut builder = ty.into_batch(2);
+    /// let mut writer = builder.writer::<bool>().unwrap();
+    /// writer.push(false).unwrap();
+    /// writer.push(true).unwrap();
+    /// let entities = world
+    ///     .spawn_column_batch(builder.build().unwrap())
+    ///     .collect::<Vec<_>>();
+    /// // Link the projectile to its emitter, spawned in the same batch
+    /// world.insert_one(entities[1], Emitter(entities[0])).unwrap();
+    /// assert!(*world.get::<bool>(entities[1]).unwrap());
+    /// ```
+    pub fn spawn_column_batch(&mut self, mut batch: ColumnBatch) -> SpawnColumnBatchIter<'_> {
+        self.spawn_column_batch_from(&mut batch)
     }
 
-    fn reserve_inner<T: Bundle>(&mut self, additional: u32) -> u32 {
+    /// Like `spawn_column_batch`, but leaves `batch` empty rather than consuming it
+    ///
+    /// The batch keeps its storage, so it may be refilled with `ColumnBatch::into_builder` without
+    /// reallocating, e.g. for spawning a wave of entities every few seconds.
+    ///
+    /// # Example
+    /// ```
+    /// # use hecs::*;
+    /// let mut world = World::new();
+    /// let mut ty = ColumnBatchType::new();
+    /// ty.add::<i32>();
+    /// let mut builder = ty.into_batch(3);
+    /// for _ in 0..2 {
+    ///     builder.fill(0..3);
+    ///     let mut batch = builder.build().unwrap();
+    ///     assert_eq!(world.spawn_column_batch_from(&mut batch).len(), 3);
+    ///     assert!(batch.is_empty());
+    ///     builder = batch.into_builder(3);
+    /// }
+    /// assert_eq!(world.len(), 6);
+    /// ```
+    pub fn spawn_column_batch_from(&mut self, batch: &mut ColumnBatch) -> SpawnColumnBatchIter<'_> {
         self.flush();
-        self.entities.reserve(additional);
-
-        let archetype_id = T::with_static_ids(|ids| {
-            self.index.get(ids).copied().unwrap_or_else(|| {
-                let x = self.archetypes.len() as u32;
-                self.archetypes.push(Archetype::new(T::static_type_info()));
-                self.index.insert(ids.into(), x);
-                self.archetype_generation += 1;
-                x
-            })
-        });
-
-        self.archetypes[archetype_id as usize].reserve(additional);
-        archetype_id
-    }
 
-    /// Despawn all entities
-    ///
-    /// Preserves allocated storage for reuse.
-    pub fn clear(&mut self) {
-        for x in &mut self.archetypes {
-            x.clear();
+        let count = batch.len();
+        self.entities.reserve(count);
+        let (archetype_id, base) = self.merge_batch(batch);
+        let archetype = &mut self.archetypes[archetype_id as usize];
+        for index in base..base + count {
+            let entity = self.entities.alloc();
+            archetype.set_entity_id(index, entity.id);
+            self.entities.meta[entity.id as usize].location = Location {
+                archetype: archetype_id,
+                index,
+            };
+            self.events.added(entity, archetype.types());
         }
-        self.entities.clear();
-    }
 
-    /// Whether `entity` still exists
-    pub fn contains(&self, entity: Entity) -> bool {
-        self.entities.contains(entity)
+        SpawnColumnBatchIter {
+            meta: &self.entities.meta,
+            archetype: &self.archetypes[archetype_id as usize],
+            range: base..base + count,
+        }
     }
 
-    /// Efficiently iterate over all entities that have certain components, using dynamic borrow
-    /// checking
-    ///
-    /// Prefer `query_mut` when concurrent access to the `World` is not required.
-    ///
-    /// Calling `iter` on the returned value yields `(Entity, Q)` tuples, where `Q` is some query
-    /// type. A query type is `&T`, `&mut T`, a tuple of query types, or an `Option` wrapping a
-    /// query type, where `T` is any component type. Components queried with `&mut` must only appear
-    /// once. Entities which do not have a component type referenced outside of an `Option` will be
-    /// skipped.
-    ///
-    /// Entities are yielded in arbitrary order.
-    ///
-    /// The returned `QueryBorrow` can be further transformed with combinator methods; see its
-    /// documentation for details.
+    /// Spawn one entity for each index of the equal-length `Vec`s in the tuple `columns`
     ///
-    /// Iterating a query will panic if it would violate an existing unique reference or construct
-    /// an invalid unique reference. This occurs when two simultaneously-active queries could expose
-    /// the same entity. Simultaneous queries can access the same component type if and only if the
-    /// world contains no entities that have all components required by both queries, assuming no
-    /// other component borrows are outstanding.
+    /// Like `spawn_column_batch`, but taking components already stored by column, such as the
+    /// output of a level loader. Each `Vec` is copied into the world in one go, keeping its
+    /// allocation for reuse.
     ///
-    /// Iterating a query yields references with lifetimes bound to the `QueryBorrow` returned
-    /// here. To ensure those are invalidated, the return value of this method must be dropped for
-    /// its dynamic borrows from the world to be released. Similarly, lifetime rules ensure that
-    /// references obtained from a query cannot outlive the `QueryBorrow`.
+    /// Panics if the `Vec`s differ in length, or if two hold the same component type.
     ///
     /// # Example
     /// ```
     /// # use hecs::*;
     /// let mut world = World::new();
-    /// let a = world.spawn((123, true, "abc"));
-    /// let b = world.spawn((456, false));
-    /// let c = world.spawn((42, "def"));
-    /// let entities = world.query::<(&i32, &bool)>()
-    ///     .iter()
-    ///     .map(|(e, (&i, &b))| (e, i, b)) // Copy out of the world
+    /// let positions = vec![1.0f32, 2.0, 3.0];
+    /// let tiles = vec!["grass", "water", "sand"];
+    /// let entities = world
+    ///     .spawn_columns((positions, tiles))
     ///     .collect::<Vec<_>>();
-    /// assert_eq!(entities.len(), 2);
-    /// assert!(entities.contains(&(a, 123, true)));
-    /// assert!(entities.contains(&(b, 456, false)));
+    /// assert_eq!(*world.get::<&str>(entities[1]).unwrap(), "water");
+    /// assert_eq!(*world.get::<f32>(entities[2]).unwrap(), 3.0);
     /// ```
-    pub fn query<Q: Query>(&self) -> QueryBorrow<'_, Q> {
-        QueryBorrow::new(&self.entities.meta, &self.archetypes)
+    pub fn spawn_columns(&mut self, columns: impl ComponentColumns) -> SpawnColumnBatchIter<'_> {
+        self.spawn_column_batch(columns.into_column_batch())
     }
 
-    /// Query a uniquely borrowed world
+    /// Spawn entities whose components are stored in `batch`, with specific `Entity` handles
     ///
-    /// Like `query`, but faster because dynamic borrow checks can be skipped. Note that, unlike
-    /// `query`, this returns an `IntoIterator` which can be passed directly to a `for` loop.
-    pub fn query_mut<Q: Query>(&mut self) -> QueryMut<'_, Q> {
-        QueryMut::new(&self.entities.meta, &mut self.archetypes)
+    /// See `spawn_column_batch` and `spawn_at`. The `i`th entity of the batch receives the `i`th
+    /// handle. Despawns any existing entities with the same `Entity::id`s.
+    ///
+    /// Panics if the number of handles differs from the number of entities in `batch`, or if any
+    /// two handles share an ID.
+    pub fn spawn_column_batch_at(&mut self, handles: &[Entity], mut batch: ColumnBatch) {
+        assert_eq!(
+            handles.len(),
+            batch.len() as usize,
+            "number of entity handles must match the number of entities in the batch"
+        );
+        assert_eq!(
+            handles.iter().map(|x| x.id).collect::<HashSet<_>>().len(),
+            handles.len(),
+            "entity handles must be distinct"
+        );
+        self.flush();
+
+        for &handle in handles {
+            if let Some(existing) = self.entities.occupant(handle.id) {
+                self.despawn(existing).unwrap();
+            }
+        }
+        for &handle in handles {
+            let displaced = self.entities.alloc_at(handle);
+            debug_assert!(displaced.is_none());
+        }
+
+        let (archetype_id, base) = self.merge_batch(&mut batch);
+        let archetype = &mut self.archetypes[archetype_id as usize];
+        for (index, handle) in (base..).zip(handles) {
+            archetype.set_entity_id(index, handle.id);
+            self.entities.meta[handle.id as usize].location = Location {
+                archetype: archetype_id,
+                index,
+            };
+            self.events.added(*handle, archetype.types());
+        }
     }
 
-    /// Prepare a query against a single entity, using dynamic borrow checking
+    /// Spawn an entity for each element of `iter`, batching together those with the same component
+    /// types
     ///
-    /// Prefer `query_one_mut` when concurrent access to the `World` is not required.
-    ///
-    /// Call `get` on the resulting `QueryOne` to actually execute the query. The `QueryOne` value
-    /// is responsible for releasing the dynamically-checked borrow made by `get`, so it can't be
-    /// dropped while references returned by `get` are live.
+    /// Like calling `spawn` for each element, but the components are first gathered into a
+    /// `ColumnBatch` per distinct set of component types, each of which is then moved into the
+    /// world at once as by `spawn_column_batch`. Suits data-driven loaders that assemble entities
+    /// with an `EntityBuilder`, and so can't use `spawn_batch`. Returns the new entities in the
+    /// order of `iter`.
     ///
-    /// Handy for accessing multiple components simultaneously.
+    /// Falls back to spawning each entity individually if any component type is stored sparsely.
     ///
     /// # Example
     /// ```
     /// # use hecs::*;
     /// let mut world = World::new();
-    /// let a = world.spawn((123, true, "abc"));
-    /// // The returned query must outlive the borrow made by `get`
-    /// let mut query = world.query_one::<(&mut i32, &bool)>(a).unwrap();
-    /// let (number, flag) = query.get().unwrap();
-    /// if *flag { *number *= 2; }
-    /// assert_eq!(*number, 246);
+    /// let mut builders = (0..4)
+    ///     .map(|i| {
+    ///         let mut builder = EntityBuilder::new();
+    ///         builder.add(i);
+    ///         if i % 2 == 0 {
+    ///             builder.add("even");
+    ///         }
+    ///         builder
+    ///     })
+    ///     .collect::<Vec<_>>();
+    /// let entities = world.extend_built(builders.iter_mut().map(|x| x.build()));
+    /// assert_eq!(*world.get::<i32>(entities[3]).unwrap(), 3);
+    /// assert_eq!(*world.get::<&str>(entities[2]).unwrap(), "even");
+    /// assert!(world.get::<&str>(entities[1]).is_err());
     /// ```
-    pub fn query_one<Q: Query>(&self, entity: Entity) -> Result<QueryOne<'_, Q>, NoSuchEntity> {
-        let loc = self.entities.get(entity)?;
-        Ok(unsafe { QueryOne::new(&self.archetypes[loc.archetype as usize], loc.index) })
-    }
+    pub fn extend_built<I>(&mut self, iter: I) -> Vec<Entity>
+    where
+        I: IntoIterator,
+        I::Item: DynamicBundle,
+    {
+        let iter = iter.into_iter();
+        if !self.sparse.is_empty() {
+            return iter.map(|x| self.spawn(x)).collect();
+        }
 
-    /// Query a single entity in a uniquely borrow world
-    ///
-    /// Like `query_one`, but faster because dynamic borrow checks can be skipped. Note that, unlike
-    /// `query_one`, on success this returns the query's results directly.
-    pub fn query_one_mut<Q: Query>(
-        &mut self,
-        entity: Entity,
-    ) -> Result<QueryItem<'_, Q>, QueryOneError> {
-        let loc = self.entities.get(entity)?;
-        unsafe {
-            let fetch = Q::Fetch::new(&self.archetypes[loc.archetype as usize])
-                .ok_or(QueryOneError::Unsatisfied)?;
-            Ok(fetch.get(loc.index as usize))
+        // Staged components of each distinct set of component types
+        let mut index = HashMap::<Box<[TypeId]>, usize>::new();
+        let mut batches = Vec::<Archetype>::new();
+        // The batch and row of each entity, in order
+        let mut rows = Vec::with_capacity(iter.size_hint().0);
+        for components in iter {
+            let batch = components.with_ids(|ids| match index.get(ids) {
+                Some(&x) => x,
+                None => {
+                    index.insert(ids.into(), batches.len());
+                    batches.push(Archetype::new(components.type_info()));
+                    batches.len() - 1
+                }
+            });
+            let archetype = &mut batches[batch];
+            unsafe {
+                let row = archetype.allocate(0);
+                components.put(|ptr, ty| {
+                    archetype.put_dynamic(ptr, ty.id(), ty.layout().size(), row);
+                });
+                rows.push((batch, row));
+            }
         }
+
+        let handles = batches
+            .into_iter()
+            .map(|x| self.spawn_column_batch(ColumnBatch(x)).collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        rows.into_iter()
+            .map(|(batch, row)| handles[batch][row as usize])
+            .collect()
     }
 
-    /// Borrow the `T` component of `entity`
-    ///
-    /// Panics if the component is already uniquely borrowed from another entity with the same
-    /// components.
-    pub fn get<T: Component>(&self, entity: Entity) -> Result<Ref<'_, T>, ComponentError> {
-        let loc = self.entities.get(entity)?;
-        if loc.archetype == 0 {
-            return Err(MissingComponent::new::<T>().into());
+    /// Move the contents of `batch` into the matching archetype, returning its ID and the index of
+    /// the first new entity
+    fn merge_batch(&mut self, batch: &mut ColumnBatch) -> (u32, u32) {
+        let ids = batch.0.types().iter().map(|x| x.id()).collect::<Vec<_>>();
+        let archetype_id =
+            self.archetype_in_chunk(&ids, batch.0.chunk_values(), || batch.0.types().to_vec());
+        let archetype = &mut self.archetypes[archetype_id as usize];
+        let base = archetype.len();
+        trace_event!(
+            DEBUG,
+            archetype = archetype_id,
+            entities = batch.0.len(),
+            bytes = batch.0.len() as usize
+                * batch
+                    .0
+                    .types()
+                    .iter()
+                    .map(|x| x.layout().size())
+                    .sum::<usize>(),
+            "spawned column batch"
+        );
+        unsafe {
+            archetype.merge(&mut batch.0);
         }
-        Ok(unsafe { Ref::new(&self.archetypes[loc.archetype as usize], loc.index)? })
+        (archetype_id, base)
     }
 
-    /// Uniquely borrow the `T` component of `entity`
-    ///
-    /// Panics if the component is already borrowed from another entity with the same components.
-    pub fn get_mut<T: Component>(&self, entity: Entity) -> Result<RefMut<'_, T>, ComponentError> {
-        let loc = self.entities.get(entity)?;
-        if loc.archetype == 0 {
-            return Err(MissingComponent::new::<T>().into());
-        }
-        Ok(unsafe { RefMut::new(&self.archetypes[loc.archetype as usize], loc.index)? })
-    }
-
-    /// Access an entity regardless of its component types
+    /// Move every entity of `other` into this world, returning a mapping from their old handles
+    /// to their new ones
     ///
-    /// Does not immediately borrow any component.
-    pub fn entity(&self, entity: Entity) -> Result<EntityRef<'_>, NoSuchEntity> {
-        Ok(match self.entities.get(entity)? {
-            Location { archetype: 0, .. } => EntityRef::empty(),
-            loc => unsafe { EntityRef::new(&self.archetypes[loc.archetype as usize], loc.index) },
-        })
-    }
-
-    /// Given an id obtained from `Entity::id`, reconstruct the still-live `Entity`.
+    /// Components are moved an entire archetype at a time, which is much faster than despawning
+    /// and respawning each entity. Links made with [`attach`](Self::attach) are updated, but
+    /// handles stored in other components are not; use the returned [`EntityMap`] to fix them up.
+    /// Resources of `other` are dropped.
     ///
-    /// # Safety
-    /// `id` must correspond to a currently live `Entity`. A despawned or never-allocated `id` will produce undefined behavior.
-    pub unsafe fn find_entity_from_id(&self, id: u32) -> Entity {
-        self.entities.resolve_unknown_gen(id)
+    /// # Example
+    /// ```
+    /// # use hecs::*;
+    /// let mut world = World::new();
+    /// let mut chunk = World::new();
+    /// let a = chunk.spawn((123, "abc"));
+    /// let map = world.merge(chunk);
+    /// let b = map.get(a).unwrap();
+    /// assert_eq!(*world.get::<i32>(b).unwrap(), 123);
+    /// ```
+    pub fn merge(&mut self, mut other: World) -> EntityMap {
+        self.flush();
+        other.flush();
+        let labeled = !other.labels.is_empty();
+        self.entities.reserve(other.entities.len());
+        let mut map = EntityMap {
+            map: HashMap::with_capacity(other.entities.len() as usize),
+        };
+        for mut archetype in mem::take(&mut other.archetypes) {
+            if archetype.is_empty() {
+                continue;
+            }
+            let old = (0..archetype.len())
+                .map(|i| {
+                    let id = archetype.entity_id(i);
+                    Entity {
+                        id,
+                        generation: other.entities.meta[id as usize].generation,
+                    }
+                })
+                .collect::<Vec<_>>();
+            let ids = archetype.types().iter().map(|x| x.id()).collect::<Vec<_>>();
+            let archetype_id = self.archetype_in_chunk(&ids, archetype.chunk_values(), || {
+                archetype.types().to_vec()
+            });
+            let base = self.archetypes[archetype_id as usize].len();
+            unsafe {
+                self.archetypes[archetype_id as usize].merge(&mut archetype);
+            }
+            for (index, old) in (base..).zip(old) {
+                let new = self.entities.alloc();
+                let archetype = &mut self.archetypes[archetype_id as usize];
+                archetype.set_entity_id(index, new.id);
+                self.entities.meta[new.id as usize].location = Location {
+                    archetype: archetype_id,
+                    index,
+                };
+                self.events.added(new, archetype.types());
+                map.map.insert(old, new);
+            }
+        }
+        for set in other.sparse.iter_mut() {
+            let ty = *set.type_info();
+            self.sparse_set_like(set);
+            let dst = self.sparse.get_mut(ty.id()).unwrap();
+            while !set.storage().is_empty() {
+                let id = set.storage().entity_id(0);
+                let new = map
+                    .get(Entity {
+                        id,
+                        generation: other.entities.meta[id as usize].generation,
+                    })
+                    .unwrap();
+                unsafe {
+                    set.take(id, |ptr, _| dst.put(new.id, ptr));
+                }
+                self.events.added(new, core::slice::from_ref(&ty));
+            }
+        }
+        hierarchy::remap(self, &map);
+        if labeled {
+            for (_, entity) in map.iter() {
+                self.adopt_label(entity);
+            }
+        }
+        map
     }
 
-    /// Iterate over all entities in the world
+    /// Move `entity` and all its components into `dst`, returning its handle there
     ///
-    /// Entities are yielded in arbitrary order. Prefer `World::query` for better performance when
-    /// components will be accessed in predictable patterns.
+    /// Components are moved by copying their bytes, so their types need not have been seen by
+    /// `dst` before. `entity` is first detached from its parent and children, if any, since those
+    /// handles aren't meaningful in `dst`. Removal hooks are not invoked.
     ///
     /// # Example
     /// ```
     /// # use hecs::*;
     /// let mut world = World::new();
-    /// let a = world.spawn(());
-    /// let b = world.spawn(());
-    /// let ids = world.iter().map(|(id, _)| id).collect::<Vec<_>>();
-    /// assert_eq!(ids.len(), 2);
-    /// assert!(ids.contains(&a));
-    /// assert!(ids.contains(&b));
+    /// let mut render_world = World::new();
+    /// let a = world.spawn((123, "abc"));
+    /// let b = world.take(&mut render_world, a).unwrap();
+    /// assert!(!world.contains(a));
+    /// assert_eq!(*render_world.get::<&str>(b).unwrap(), "abc");
     /// ```
-    pub fn iter(&self) -> Iter<'_> {
-        Iter::new(&self.archetypes, &self.entities)
+    pub fn take(&mut self, dst: &mut World, entity: Entity) -> Result<Entity, NoSuchEntity> {
+        self.flush();
+        dst.flush();
+        if !self.contains(entity) {
+            return Err(NoSuchEntity);
+        }
+        self.detach(entity)?;
+        if let Ok(children) = self.get::<Children>(entity).map(|x| x.as_slice().to_vec()) {
+            for child in children {
+                self.detach(child)?;
+            }
+        }
+
+        self.forget_label(entity);
+        let loc = self.entities.free(entity)?;
+        let source = &mut self.archetypes[loc.archetype as usize];
+        self.events
+            .despawned(entity, entity_types(source, &self.sparse, entity));
+        let ids = source.types().iter().map(|x| x.id()).collect::<Vec<_>>();
+        let archetype_id =
+            dst.archetype_in_chunk(&ids, source.chunk_values(), || source.types().to_vec());
+        let new = dst.entities.alloc();
+        let target = &mut dst.archetypes[archetype_id as usize];
+        unsafe {
+            let index = target.allocate(new.id);
+            if let Some(moved) = source.move_to(loc.index, |ptr, ty, size, _| {
+                target.put_dynamic(ptr, ty, size, index);
+            }) {
+                relocate(&mut self.entities.meta, source, moved, loc.index);
+            }
+            dst.entities.meta[new.id as usize].location = Location {
+                archetype: archetype_id,
+                index,
+            };
+        }
+        dst.events.added(new, target.types());
+        for set in self.sparse.iter_mut() {
+            let ty = *set.type_info();
+            if !set.contains(entity.id) {
+                continue;
+            }
+            dst.sparse_set_like(set);
+            let target = dst.sparse.get_mut(ty.id()).unwrap();
+            unsafe {
+                set.take(entity.id, |ptr, _| target.put(new.id, ptr));
+            }
+            dst.events.added(new, core::slice::from_ref(&ty));
+        }
+        dst.adopt_label(new);
+        Ok(new)
     }
 
-    /// Add `components` to `entity`
+    /// Spawn a copy of `entity`, cloning each of its components with `registry`
     ///
-    /// Computational cost is proportional to the number of components `entity` has. If an entity
-    /// already has a component of a certain type, it is dropped and replaced.
+    /// The copy is attached to the same parent as `entity`, but has no children or label. Its
+    /// components are marked as added. Unless `entity` has children or a label, the copy is stored
+    /// in the same archetype, so its components are cloned directly from row to row.
     ///
-    /// When inserting a single component, see `insert_one` for convenience.
+    /// Panics if any component type of `entity` other than `Children` or `Label` isn't registered
+    /// with `registry`.
     ///
     /// # Example
     /// ```
     /// # use hecs::*;
     /// let mut world = World::new();
-    /// let e = world.spawn((123, "abc"));
-    /// world.insert(e, (456, true));
-    /// assert_eq!(*world.get::<i32>(e).unwrap(), 456);
-    /// assert_eq!(*world.get::<bool>(e).unwrap(), true);
+    /// let mut registry = SnapshotRegistry::new();
+    /// registry.register::<i32>().register::<String>();
+    /// let a = world.spawn((123, String::from("abc")));
+    /// let b = world.clone_entity(&registry, a).unwrap();
+    /// assert_ne!(a, b);
+    /// assert_eq!(*world.get::<String>(b).unwrap(), "abc");
+    /// assert_eq!(world.archetype_id(a).unwrap(), world.archetype_id(b).unwrap());
     /// ```
-    pub fn insert(
+    pub fn clone_entity(
         &mut self,
+        registry: &SnapshotRegistry,
         entity: Entity,
-        components: impl DynamicBundle,
-    ) -> Result<(), NoSuchEntity> {
-        use hashbrown::hash_map::Entry;
+    ) -> Result<Entity, NoSuchEntity> {
+        let mut clones = self.clone_entities(registry, core::iter::once(entity))?;
+        Ok(clones.pop().unwrap())
+    }
 
+    /// Spawn a copy of each of `entities`, as if by `clone_entity`, returning the copies' handles
+    /// in the same order
+    ///
+    /// Storage for each archetype's copies is reserved up front. Fails without cloning anything if
+    /// any of `entities` doesn't exist. Should a `Clone` impl panic, the copies already made
+    /// remain, and the one being made is spawned only if it panicked while cloning a sparsely
+    /// stored component, which that copy then lacks.
+    pub fn clone_entities(
+        &mut self,
+        registry: &SnapshotRegistry,
+        entities: impl IntoIterator<Item = Entity>,
+    ) -> Result<Vec<Entity>, NoSuchEntity> {
         self.flush();
-        let loc = self.entities.get_mut(entity)?;
-        unsafe {
-            // Assemble Vec<TypeInfo> for the final entity
-            let arch = &mut self.archetypes[loc.archetype as usize];
-            let mut info = arch.types().to_vec();
-            for ty in components.type_info() {
-                if let Some(ptr) = arch.get_dynamic(ty.id(), ty.layout().size(), loc.index) {
-                    ty.drop(ptr.as_ptr());
-                } else {
-                    info.push(ty);
-                }
+        let entities = entities.into_iter().collect::<Vec<_>>();
+        // Archetype of the copies of each source archetype's entities, and how many there are
+        let mut targets = HashMap::<u32, (u32, u32)>::new();
+        for &entity in &entities {
+            if !self.contains(entity) {
+                return Err(NoSuchEntity);
             }
-            info.sort();
-
-            // Find the archetype it'll live in
-            let elements = info.iter().map(|x| x.id()).collect();
-            let target = match self.index.entry(elements) {
-                Entry::Occupied(x) => *x.get(),
-                Entry::Vacant(x) => {
-                    let index = self.archetypes.len() as u32;
-                    self.archetypes.push(Archetype::new(info));
-                    x.insert(index);
-                    self.archetype_generation += 1;
-                    index
-                }
+            let source = self.entities.get(entity)?.archetype;
+            if let Some((_, count)) = targets.get_mut(&source) {
+                *count += 1;
+                continue;
+            }
+            let arch = &self.archetypes[source as usize];
+            let skipped = [TypeId::of::<Children>(), TypeId::of::<Label>()]
+                .iter()
+                .copied()
+                .filter(|&ty| arch.has_dynamic(ty))
+                .collect::<Vec<_>>();
+            let target = if skipped.is_empty() {
+                source
+            } else {
+                self.remove_target(source, &skipped)
             };
-
-            if target == loc.archetype {
-                // Update components in the current archetype
-                let arch = &mut self.archetypes[loc.archetype as usize];
-                components.put(|ptr, ty| {
-                    arch.put_dynamic(ptr, ty.id(), ty.layout().size(), loc.index);
-                });
-                return Ok(());
+            for ty in self.archetypes[target as usize].types() {
+                assert!(
+                    registry.is_registered(ty.id()),
+                    "component type not registered for cloning"
+                );
             }
+            targets.insert(source, (target, 1));
+        }
+        for &(target, count) in targets.values() {
+            self.archetypes[target as usize].reserve(count);
+        }
 
-            // Move into a new archetype
-            let (source_arch, target_arch) = index2(
-                &mut self.archetypes,
-                loc.archetype as usize,
-                target as usize,
-            );
-            let target_index = target_arch.allocate(entity.id);
-            loc.archetype = target;
-            let old_index = mem::replace(&mut loc.index, target_index);
-            if let Some(moved) = source_arch.move_to(old_index, |ptr, ty, size| {
-                target_arch.put_dynamic(ptr, ty, size, target_index);
-            }) {
-                self.entities.meta[moved as usize].location.index = old_index;
-            }
-            components.put(|ptr, ty| {
-                target_arch.put_dynamic(ptr, ty.id(), ty.layout().size(), target_index);
-            });
+        let mut clones = Vec::with_capacity(entities.len());
+        for entity in entities {
+            let loc = self.entities.get(entity)?;
+            let target = targets[&loc.archetype].0;
+            clones.push(unsafe { self.clone_inner(registry, entity, loc, target) });
         }
-        Ok(())
+        Ok(clones)
     }
 
-    /// Add `component` to `entity`
+    /// Spawn a copy of `entity`, stored at `loc`, in archetype `target`
     ///
-    /// See `insert`.
-    pub fn insert_one(
+    /// `target` must hold a subset of the types of `entity`'s archetype, all registered with
+    /// `registry`, and have room for another entity.
+    unsafe fn clone_inner(
         &mut self,
+        registry: &SnapshotRegistry,
         entity: Entity,
-        component: impl Component,
-    ) -> Result<(), NoSuchEntity> {
-        self.insert(entity, (component,))
+        loc: Location,
+        target: u32,
+    ) -> Entity {
+        // Cloned before the row is allocated, so a panic leaves storage untouched
+        for &ty in self.archetypes[target as usize].types() {
+            let size = ty.layout().size();
+            let (src, dst) = if size == 0 {
+                let ptr = ty.layout().align() as *mut u8;
+                (ptr, ptr)
+            } else {
+                let src = self.archetypes[loc.archetype as usize]
+                    .get_dynamic(ty.id(), size, loc.index)
+                    .unwrap();
+                let dst = self.archetypes[target as usize]
+                    .get_next_dynamic(ty.id(), size)
+                    .unwrap();
+                (src.as_ptr(), dst.as_ptr())
+            };
+            (registry.cloner(ty.id()).unwrap())(src, dst);
+        }
+        let clone = self.entities.alloc();
+        let index = self.archetypes[target as usize].allocate(clone.id);
+        let tick = self.archetypes[target as usize].tick();
+        let arch = &mut self.archetypes[target as usize];
+        for ty in arch.types().to_vec() {
+            arch.set_ticks(
+                ty.id(),
+                index,
+                ComponentTicks {
+                    added: tick,
+                    mutated: tick,
+                },
+            );
+        }
+        self.entities.meta[clone.id as usize].location = Location {
+            archetype: target,
+            index,
+        };
+        self.events.added(clone, arch.types());
+
+        for set in self.sparse.iter_mut() {
+            let ty = *set.type_info();
+            let row = match set.row(entity.id) {
+                Some(x)
+                    if ty.id() != TypeId::of::<Children>() && ty.id() != TypeId::of::<Label>() =>
+                {
+                    x
+                }
+                _ => continue,
+            };
+            let clone_fn = registry
+                .cloner(ty.id())
+                .expect("component type not registered for cloning");
+            // Ensure the source isn't moved by storing the copy
+            set.reserve(1);
+            let src = set.component(row).as_ptr();
+            set.put_with(clone.id, |dst| clone_fn(src, dst));
+            self.events.added(clone, core::slice::from_ref(&ty));
+        }
+
+        if let Some(parent) = self.parent(entity) {
+            self.get_mut::<Children>(parent).unwrap().0.push(clone);
+        }
+        clone
     }
 
-    /// Remove components from `entity`
-    ///
-    /// Computational cost is proportional to the number of components `entity` has. The entity
-    /// itself is not removed, even if no components remain; use `despawn` for that. If any
-    /// component in `T` is not present in `entity`, no components are removed and an error is
-    /// returned.
+    /// Despawn every entity matching `Q`, moving their components into `ColumnBatch`es
     ///
-    /// When removing a single component, see `remove_one` for convenience.
+    /// Returns one batch for each archetype containing matching entities, along with the former
+    /// handles of the entities it holds, in order. Archetypes whose entities all match are moved
+    /// out wholesale without copying any components. Entities are detached from their relatives
+    /// as if by `despawn`, before matching. Each batch keeps the chunk components its entities
+    /// shared, which they share again once spawned with `spawn_column_batch`.
     ///
     /// # Example
     /// ```
     /// # use hecs::*;
     /// let mut world = World::new();
-    /// let e = world.spawn((123, "abc", true));
-    /// assert_eq!(world.remove::<(i32, &str)>(e), Ok((123, "abc")));
-    /// assert!(world.get::<i32>(e).is_err());
-    /// assert!(world.get::<&str>(e).is_err());
-    /// assert_eq!(*world.get::<bool>(e).unwrap(), true);
+    /// let a = world.spawn((123, true));
+    /// let b = world.spawn((456,));
+    /// let batches = world.extract::<&bool>();
+    /// assert!(!world.contains(a));
+    /// assert!(world.contains(b));
+    /// let mut other = World::new();
+    /// for (old, batch) in batches {
+    ///     assert_eq!(old, [a]);
+    ///     other.spawn_column_batch(batch);
+    /// }
+    /// assert_eq!(other.query::<(&i32, &bool)>().iter().count(), 1);
     /// ```
-    pub fn remove<T: Bundle>(&mut self, entity: Entity) -> Result<T, ComponentError> {
-        use hashbrown::hash_map::Entry;
-
+    pub fn extract<Q: Query>(&mut self) -> Vec<(Vec<Entity>, ColumnBatch)> {
         self.flush();
-        let loc = self.entities.get_mut(entity)?;
-        unsafe {
-            let removed = T::with_static_ids(|ids| ids.iter().copied().collect::<HashSet<_>>());
-            let info = self.archetypes[loc.archetype as usize]
-                .types()
-                .iter()
-                .cloned()
-                .filter(|x| !removed.contains(&x.id()))
+        let linked = self
+            .archetypes
+            .iter()
+            .any(|x| x.has::<Parent>() || x.has::<Children>());
+        if linked || !self.labels.is_empty() {
+            let matching = self
+                .query_mut::<Q>()
+                .into_iter()
+                .map(|(e, _)| e)
                 .collect::<Vec<_>>();
-            let elements = info.iter().map(|x| x.id()).collect();
-            let target = match self.index.entry(elements) {
-                Entry::Occupied(x) => *x.get(),
-                Entry::Vacant(x) => {
-                    self.archetypes.push(Archetype::new(info));
-                    let index = (self.archetypes.len() - 1) as u32;
-                    x.insert(index);
-                    self.archetype_generation += 1;
-                    index
+            for entity in matching {
+                if linked {
+                    hierarchy::unlink(self, entity);
                 }
+                self.forget_label(entity);
+            }
+        }
+
+        let mut batches = Vec::new();
+        for archetype in &mut self.archetypes {
+            let rows = match EnabledFetch::<Q::Fetch>::new(archetype) {
+                Some(fetch) => (0..archetype.len())
+                    .filter(|&n| unsafe { !fetch.should_skip(n as usize) })
+                    .collect::<Vec<_>>(),
+                None => continue,
             };
-            let old_index = loc.index;
-            let source_arch = &self.archetypes[loc.archetype as usize];
-            let bundle =
-                T::get(|ty| source_arch.get_dynamic(ty.id(), ty.layout().size(), old_index))?;
-            // If we actually removed any components, the entity needs to be moved into a new archetype
-            if loc.archetype != target {
-                let (source_arch, target_arch) = index2(
-                    &mut self.archetypes,
-                    loc.archetype as usize,
-                    target as usize,
-                );
-                let target_index = target_arch.allocate(entity.id);
-                loc.archetype = target;
-                loc.index = target_index;
-                if let Some(moved) = source_arch.move_to(old_index, |src, ty, size| {
-                    // Only move the components present in the target archetype, i.e. the non-removed ones.
-                    if let Some(dst) = target_arch.get_dynamic(ty, size, target_index) {
-                        ptr::copy_nonoverlapping(src, dst.as_ptr(), size);
+            if rows.is_empty() {
+                continue;
+            }
+            for &row in &rows {
+                self.entities.check_reusable(archetype.entity_id(row));
+            }
+            let extracted = if rows.len() == archetype.len() as usize {
+                let mut empty = Archetype::new(archetype.types().to_vec());
+                empty.set_allocator(self.allocator.clone());
+                empty.set_tick(archetype.tick());
+                empty.set_preserve_order(self.preserve_order);
+                empty.set_chunk_values(archetype.chunk_values().to_vec());
+                for set in self.sparse.raw() {
+                    empty.add_sparse(set);
+                }
+                let mut extracted = mem::replace(archetype, empty);
+                extracted.clear_sparse();
+                extracted
+            } else {
+                let mut extracted = Archetype::new(archetype.types().to_vec());
+                extracted.set_allocator(self.allocator.clone());
+                extracted.set_chunk_values(archetype.chunk_values().to_vec());
+                extracted.reserve(rows.len() as u32);
+                // Visit rows in descending order so each swap-remove fills the vacancy with an
+                // entity that's staying behind
+                for &row in rows.iter().rev() {
+                    unsafe {
+                        let index = extracted.allocate(archetype.entity_id(row));
+                        if let Some(moved) = archetype.move_to(row, |ptr, ty, size, _| {
+                            extracted.put_dynamic(ptr, ty, size, index);
+                        }) {
+                            relocate(&mut self.entities.meta, archetype, moved, row);
+                        }
                     }
-                }) {
-                    self.entities.meta[moved as usize].location.index = old_index;
+                }
+                extracted
+            };
+            let entities = &mut self.entities;
+            let events = &mut self.events;
+            let sparse = &self.sparse;
+            let handles = (0..extracted.len())
+                .map(|i| {
+                    let id = extracted.entity_id(i);
+                    let entity = Entity {
+                        id,
+                        generation: entities.meta[id as usize].generation,
+                    };
+                    entities.free(entity).unwrap();
+                    events.despawned(entity, entity_types(&extracted, sparse, entity));
+                    entity
+                })
+                .collect();
+            batches.push((handles, ColumnBatch(extracted)));
+        }
+        if !self.sparse.is_empty() {
+            for (handles, _) in &batches {
+                for &entity in handles {
+                    self.despawn_sparse(entity);
                 }
             }
-            Ok(bundle)
         }
+        batches
     }
 
-    /// Remove the `T` component from `entity`
+    /// Capture the current entities and components of the world
     ///
-    /// See `remove`.
-    pub fn remove_one<T: Component>(&mut self, entity: Entity) -> Result<T, ComponentError> {
-        self.remove::<(T,)>(entity).map(|(x,)| x)
+    /// Every component type present must be registered with `registry`. Resources are not
+    /// captured.
+    ///
+    /// # Panics
+    ///
+    /// Panics if any component type present isn't registered.
+    ///
+    /// # Example
+    /// ```
+    /// # use hecs::*;
+    /// let mut world = World::new();
+    /// let a = world.spawn((1,));
+    /// let mut registry = SnapshotRegistry::new();
+    /// registry.register::<i32>();
+    /// let snapshot = world.snapshot(&registry);
+    /// *world.get_mut::<i32>(a).unwrap() = 2;
+    /// world.spawn((3,));
+    /// world.restore(&snapshot);
+    /// assert_eq!(*world.get::<i32>(a).unwrap(), 1);
+    /// assert_eq!(world.len(), 1);
+    /// ```
+    pub fn snapshot(&mut self, registry: &SnapshotRegistry) -> WorldSnapshot {
+        self.flush();
+        WorldSnapshot {
+            entities: self.entities.clone(),
+            archetypes: self
+                .archetypes
+                .iter()
+                .filter(|x| !x.is_empty())
+                .map(|x| ArchetypeSnapshot::new(registry, x))
+                .collect(),
+            sparse: self
+                .sparse
+                .iter()
+                .filter(|x| !x.storage().is_empty())
+                .map(|x| ArchetypeSnapshot::new(registry, x.storage()))
+                .collect(),
+        }
     }
 
-    /// Borrow the `T` component of `entity` without safety checks
+    /// Replace all entities and components with those captured in `snapshot`
     ///
-    /// Should only be used as a building block for safe abstractions.
-    ///
-    /// # Safety
+    /// Entity handles that were valid when `snapshot` was taken become valid again, and entity
+    /// allocation resumes exactly where it stood, so replaying the same operations reproduces the
+    /// same handles. As after `clear`, handles to entities spawned since the snapshot may be
+    /// mistaken for reserved entities until their IDs are reused. Existing storage is reused,
+    /// making this far cheaper than despawning everything and respawning from serialized data.
+    /// Restored components are marked as added. Resources are unaffected, and neither removal
+    /// hooks nor events are triggered.
     ///
-    /// `entity` must have been previously obtained from this `World`, and no unique borrow of the
-    /// same component of `entity` may be live simultaneous to the returned reference.
-    pub unsafe fn get_unchecked<T: Component>(&self, entity: Entity) -> Result<&T, ComponentError> {
-        let loc = self.entities.get(entity)?;
-        if loc.archetype == 0 {
-            return Err(MissingComponent::new::<T>().into());
+    /// Every component is cloned before the world is touched, so if a `Clone` impl panics the
+    /// world is left as it was.
+    pub fn restore(&mut self, snapshot: &WorldSnapshot) {
+        let mut archetypes = snapshot
+            .archetypes
+            .iter()
+            .map(|x| x.instantiate())
+            .collect::<Vec<_>>();
+        let mut sparse = snapshot
+            .sparse
+            .iter()
+            .map(|x| x.instantiate())
+            .collect::<Vec<_>>();
+        for archetype in &mut self.archetypes {
+            archetype.clear();
+        }
+        self.entities.clone_from(&snapshot.entities);
+        for (snap, cloned) in snapshot.archetypes.iter().zip(&mut archetypes) {
+            let archetype_id =
+                self.archetype_in_chunk(&snap.ids, &snap.chunk, || snap.types.clone());
+            let archetype = &mut self.archetypes[archetype_id as usize];
+            unsafe {
+                archetype.merge(cloned);
+            }
+            for (index, &id) in snap.entities.iter().enumerate() {
+                let index = index as u32;
+                archetype.set_entity_id(index, id);
+                self.entities.meta[id as usize].location = Location {
+                    archetype: archetype_id,
+                    index,
+                };
+            }
+        }
+        for set in self.sparse.iter_mut() {
+            set.clear();
+        }
+        for (snap, cloned) in snapshot.sparse.iter().zip(&mut sparse) {
+            let ty = snap.types[0];
+            if self.sparse.get(ty.id()).is_none() {
+                self.register_sparse_dynamic(ty);
+            }
+            let set = self.sparse.get_mut(ty.id()).unwrap();
+            set.reserve(snap.entities.len() as u32);
+            for (index, &id) in snap.entities.iter().enumerate() {
+                unsafe {
+                    let src = cloned
+                        .get_dynamic(ty.id(), ty.layout().size(), index as u32)
+                        .unwrap();
+                    set.put(id, src.as_ptr());
+                }
+            }
+            // The components now belong to `set`
+            unsafe {
+                cloned.set_len(0);
+            }
         }
-        Ok(&*self.archetypes[loc.archetype as usize]
-            .get::<T>()
-            .ok_or_else(MissingComponent::new::<T>)?
-            .as_ptr()
-            .add(loc.index as usize))
+        self.labels.clear();
+        let labels = self
+            .query_mut::<&Label>()
+            .into_iter()
+            .map(|(entity, label)| (label.0.clone(), entity))
+            .collect::<Vec<_>>();
+        self.labels.extend(labels);
     }
 
-    /// Uniquely borrow the `T` component of `entity` without safety checks
+    /// Allocate many entities ID concurrently
     ///
-    /// Should only be used as a building block for safe abstractions.
+    /// Unlike `spawn`, this can be called simultaneously to other operations on the `World` such as
+    /// queries, but does not immediately create the entities. Reserved entities are not visible to
+    /// queries or world iteration, but can be otherwise operated on freely. Operations that
+    /// uniquely borrow the world, such as `insert` or `despawn`, will cause all outstanding
+    /// reserved entities to become real entities before proceeding. This can also be done
+    /// explicitly by calling `flush`.
     ///
-    /// # Safety
+    /// Useful for reserving an ID that will later have components attached to it with `insert`.
+    /// Freed IDs are reused and new IDs allocated with a single atomic operation however many are
+    /// reserved, so large numbers of entities may be reserved cheaply and then populated in bulk
+    /// with `spawn_column_batch_at`.
     ///
-    /// `entity` must have been previously obtained from this `World`, and no borrow of the same
-    /// component of `entity` may be live simultaneous to the returned reference.
-    pub unsafe fn get_unchecked_mut<T: Component>(
-        &self,
-        entity: Entity,
-    ) -> Result<&mut T, ComponentError> {
-        let loc = self.entities.get(entity)?;
-        if loc.archetype == 0 {
-            return Err(MissingComponent::new::<T>().into());
-        }
-        Ok(&mut *self.archetypes[loc.archetype as usize]
-            .get::<T>()
-            .ok_or_else(MissingComponent::new::<T>)?
-            .as_ptr()
-            .add(loc.index as usize))
+    /// # Example
+    /// ```
+    /// # use hecs::*;
+    /// let mut world = World::new();
+    /// let entities = world.reserve_entities(1_000).collect::<Vec<_>>();
+    /// let mut batch = ColumnBatchType::new();
+    /// batch.add::<f32>();
+    /// let mut batch = batch.into_batch(1_000);
+    /// let mut xs = batch.writer::<f32>().unwrap();
+    /// for i in 0..1_000 {
+    ///     let _ = xs.push(i as f32);
+    /// }
+    /// world.spawn_column_batch_at(&entities, batch.build().unwrap());
+    /// assert_eq!(*world.get::<f32>(entities[10]).unwrap(), 10.0);
+    /// ```
+    pub fn reserve_entities(&self, count: u32) -> ReserveEntitiesIterator<'_> {
+        self.entities.reserve_entities(count)
     }
 
-    /// Convert all reserved entities into empty entities that can be iterated and accessed
+    /// Allocate an entity ID concurrently
     ///
-    /// Invoked implicitly by `spawn`, `despawn`, `insert`, and `remove`.
-    pub fn flush(&mut self) {
-        let arch = &mut self.archetypes[0];
-        self.entities
-            .flush(|id, location| location.index = unsafe { arch.allocate(id) });
+    /// See `reserve_entities`.
+    pub fn reserve_entity(&self) -> Entity {
+        self.entities.reserve_entity()
     }
 
-    /// Inspect the archetypes that entities are organized into
+    /// Destroy an entity and all its components
     ///
-    /// Useful for dynamically scheduling concurrent queries by checking borrows in advance. Does
-    /// not provide access to entities.
-    pub fn archetypes(&self) -> impl ExactSizeIterator<Item = &'_ Archetype> + '_ {
-        self.archetypes.iter()
+    /// Entities attached to `entity` with [`attach`](Self::attach) are detached, not despawned;
+    /// see [`despawn_recursive`](Self::despawn_recursive).
+    pub fn despawn(&mut self, entity: Entity) -> Result<(), NoSuchEntity> {
+        self.flush();
+        hierarchy::unlink(self, entity);
+        self.despawn_unlinked(entity)
+    }
+
+    /// Destroy `entity`, returning its components rather than dropping them
+    ///
+    /// Useful for moving entities into inventories or pools to be spawned again later. Like with
+    /// `take`, the entity is first detached from its parent and children, and chunk components,
+    /// being shared with other entities, are left behind.
+    ///
+    /// # Example
+    /// ```
+    /// # use hecs::*;
+    /// let mut world = World::new();
+    /// let a = world.spawn((123, "abc"));
+    /// let taken = world.despawn_and_take(a).unwrap();
+    /// assert!(!world.contains(a));
+    /// assert_eq!(taken.get::<i32>(), Some(&123));
+    /// let b = world.spawn(taken);
+    /// assert_eq!(*world.get::<&str>(b).unwrap(), "abc");
+    /// ```
+    pub fn despawn_and_take(&mut self, entity: Entity) -> Result<TakenEntity, NoSuchEntity> {
+        self.flush();
+        if !self.contains(entity) {
+            return Err(NoSuchEntity);
+        }
+        self.detach(entity)?;
+        if let Ok(children) = self.get::<Children>(entity).map(|x| x.as_slice().to_vec()) {
+            for child in children {
+                self.detach(child)?;
+            }
+        }
+        self.forget_label(entity);
+
+        let loc = self.entities.free(entity)?;
+        let archetype = &mut self.archetypes[loc.archetype as usize];
+        call_remove_hooks(&self.remove_hooks, archetype, entity, loc.index, |_| true);
+        call_sparse_remove_hooks(&self.remove_hooks, &self.sparse, entity, |_| true);
+        self.events
+            .despawned(entity, entity_types(archetype, &self.sparse, entity));
+        let mut builder = EntityBuilder::new();
+        let types = archetype.types().to_vec();
+        unsafe {
+            if let Some(moved) = archetype.move_to(loc.index, |ptr, ty, _, _| {
+                let ty = *types.iter().find(|x| x.id() == ty).unwrap();
+                builder.add_raw(ptr, ty);
+            }) {
+                relocate(&mut self.entities.meta, archetype, moved, loc.index);
+            }
+            for set in self.sparse.iter_mut() {
+                let ty = *set.type_info();
+                set.take(entity.id, |ptr, _| {
+                    builder.add_raw(ptr, ty);
+                });
+            }
+        }
+        Ok(TakenEntity::new(builder))
+    }
+
+    /// Destroy `entity`, returning its `T` components and dropping any others
+    ///
+    /// If any component in `T` is not present, the entity is left untouched and an error is
+    /// returned.
+    ///
+    /// # Example
+    /// ```
+    /// # use hecs::*;
+    /// let mut world = World::new();
+    /// let a = world.spawn((123, "abc", true));
+    /// assert!(world.take_bundle::<(i32, char)>(a).is_err());
+    /// assert_eq!(world.take_bundle::<(i32, &str)>(a), Ok((123, "abc")));
+    /// assert!(!world.contains(a));
+    /// ```
+    pub fn take_bundle<T: Bundle>(&mut self, entity: Entity) -> Result<T, ComponentError> {
+        let bundle = self.remove::<T>(entity)?;
+        self.despawn(entity)?;
+        Ok(bundle)
+    }
+
+    /// Destroy an entity without maintaining the links of related entities
+    pub(crate) fn despawn_unlinked(&mut self, entity: Entity) -> Result<(), NoSuchEntity> {
+        self.flush();
+        self.forget_label(entity);
+        let loc = self.entities.free(entity)?;
+        let archetype = &self.archetypes[loc.archetype as usize];
+        call_remove_hooks(&self.remove_hooks, archetype, entity, loc.index, |_| true);
+        self.events
+            .despawned(entity, entity_types(archetype, &self.sparse, entity));
+        let moved = if self.pools.is_empty() {
+            unsafe { self.archetypes[loc.archetype as usize].remove(loc.index) }
+        } else {
+            let archetype = &mut self.archetypes[loc.archetype as usize];
+            let types = archetype.types().to_vec();
+            let pools = &mut self.pools;
+            unsafe {
+                archetype.move_to(loc.index, |ptr, ty, _, _| {
+                    let ty = types.iter().find(|x| x.id() == ty).unwrap();
+                    pools.drop(ty, ptr);
+                })
+            }
+        };
+        if let Some(moved) = moved {
+            relocate(
+                &mut self.entities.meta,
+                &self.archetypes[loc.archetype as usize],
+                moved,
+                loc.index,
+            );
+        }
+        self.despawn_sparse(entity);
+        Ok(())
+    }
+
+    /// Drop the sparsely stored components of `entity`, which is being despawned
+    fn despawn_sparse(&mut self, entity: Entity) {
+        call_sparse_remove_hooks(&self.remove_hooks, &self.sparse, entity, |_| true);
+        for set in self.sparse.iter_mut() {
+            let ty = *set.type_info();
+            if self.pools.contains(ty.id()) {
+                let pools = &mut self.pools;
+                unsafe {
+                    set.take(entity.id, |ptr, _| pools.drop(&ty, ptr));
+                }
+            } else {
+                set.remove(entity.id);
+            }
+        }
+    }
+
+    /// Despawn every entity matching `Q`, returning how many were despawned
+    ///
+    /// Much faster than calling `despawn` for each entity, since archetypes are compacted in bulk.
+    ///
+    /// # Example
+    /// ```
+    /// # use hecs::*;
+    /// struct Dead;
+    /// let mut world = World::new();
+    /// let a = world.spawn((123, Dead));
+    /// let b = world.spawn((456,));
+    /// assert_eq!(world.despawn_all::<&Dead>(), 1);
+    /// assert!(!world.contains(a));
+    /// assert!(world.contains(b));
+    /// ```
+    pub fn despawn_all<Q: Query>(&mut self) -> u32 {
+        self.flush();
+        let doomed = self
+            .query_mut::<Q>()
+            .into_iter()
+            .map(|(e, _)| e)
+            .collect::<Vec<_>>();
+        self.despawn_many(&doomed)
+    }
+
+    /// Despawn every entity for which `f` returns `false`
+    ///
+    /// See `despawn_all`.
+    pub fn retain(&mut self, mut f: impl FnMut(Entity, EntityRef<'_>) -> bool) {
+        self.flush();
+        let doomed = self
+            .iter()
+            .filter(|&(entity, components)| !f(entity, components))
+            .map(|(e, _)| e)
+            .collect::<Vec<_>>();
+        self.despawn_many(&doomed);
+    }
+
+    /// Despawn the distinct, live entities `doomed`, returning how many there were
+    fn despawn_many(&mut self, doomed: &[Entity]) -> u32 {
+        if doomed.is_empty() {
+            return 0;
+        }
+        for &entity in doomed {
+            self.entities.check_reusable(entity.id);
+        }
+        if self
+            .archetypes
+            .iter()
+            .any(|x| x.has::<Parent>() || x.has::<Children>())
+        {
+            for &entity in doomed {
+                hierarchy::unlink(self, entity);
+            }
+        }
+
+        if !self.pools.is_empty() {
+            for &entity in doomed {
+                self.despawn_unlinked(entity).unwrap();
+            }
+            return doomed.len() as u32;
+        }
+
+        // Locate the doomed entities only after unlinking, which may have moved them
+        let mut keep = HashMap::<u32, Vec<bool>>::new();
+        for &entity in doomed {
+            let loc = *self.entities.get_mut(entity).unwrap();
+            let archetype = &self.archetypes[loc.archetype as usize];
+            keep.entry(loc.archetype)
+                .or_insert_with(|| vec![true; archetype.len() as usize])[loc.index as usize] =
+                false;
+            call_remove_hooks(&self.remove_hooks, archetype, entity, loc.index, |_| true);
+            self.events
+                .despawned(entity, entity_types(archetype, &self.sparse, entity));
+            self.forget_label(entity);
+            self.despawn_sparse(entity);
+            self.entities.free(entity).unwrap();
+        }
+        for (archetype_id, keep) in keep {
+            let archetype = &mut self.archetypes[archetype_id as usize];
+            if keep.iter().all(|&x| !x) {
+                archetype.clear();
+                continue;
+            }
+            unsafe {
+                archetype.retain(&keep);
+            }
+            for index in 0..archetype.len() {
+                let id = archetype.entity_id(index);
+                self.entities.meta[id as usize].location.index = index;
+            }
+        }
+        doomed.len() as u32
+    }
+
+    /// Ensure `additional` entities with exact components `T` can be spawned without reallocating
+    pub fn reserve<T: Bundle>(&mut self, additional: u32) {
+        self.reserve_inner::<T>(additional);
+    }
+
+    /// Create the archetype that `spawn` stores entities with exact components `T` in, with room
+    /// for at least `capacity` of them
+    ///
+    /// Lets the cost of creating and allocating archetypes be paid ahead of time, e.g. during a
+    /// loading screen rather than when the first wave of enemies spawns mid-combat. Because
+    /// `PreparedQuery` and `archetypes_generation` track archetype creation, registering every
+    /// archetype upfront also means prepared queries needn't be refreshed later. Unlike `reserve`,
+    /// no entity IDs are allocated, and sparsely stored components are accounted for.
+    ///
+    /// # Example
+    /// ```
+    /// # use hecs::*;
+    /// let mut world = World::new();
+    /// world.register_archetype::<(i32, bool)>(100);
+    /// let generation = world.archetypes_generation();
+    /// for i in 0..100 {
+    ///     world.spawn((i, true));
+    /// }
+    /// assert_eq!(world.archetypes_generation(), generation);
+    /// ```
+    pub fn register_archetype<T: Bundle>(&mut self, capacity: u32) {
+        let dense = T::static_type_info()
+            .into_iter()
+            .filter(|x| !self.sparse.contains(x.id()))
+            .collect::<Vec<_>>();
+        let ids = dense.iter().map(|x| x.id()).collect::<Vec<_>>();
+        let archetype_id = self.archetype_for(&ids, || dense);
+        let archetype = &mut self.archetypes[archetype_id as usize];
+        archetype.reserve(capacity.saturating_sub(archetype.len()));
+    }
+
+    fn reserve_inner<T: Bundle>(&mut self, additional: u32) -> u32 {
+        self.flush();
+        self.entities.reserve(additional);
+
+        let archetype_id = T::with_static_ids(|ids| self.archetype_for(ids, T::static_type_info));
+
+        self.archetypes[archetype_id as usize].reserve(additional);
+        archetype_id
+    }
+
+    /// Find the archetype storing exactly the components `ids`, creating it from `info` if needed
+    fn archetype_for(&mut self, ids: &[TypeId], info: impl FnOnce() -> Vec<TypeInfo>) -> u32 {
+        if let Some(&x) = self.index.get(ids) {
+            return x;
+        }
+        let x = self.archetypes.len() as u32;
+        let mut archetype = Archetype::new(info());
+        archetype.set_allocator(self.allocator.clone());
+        archetype.set_tick(self.change_tick);
+        archetype.set_preserve_order(self.preserve_order);
+        for set in self.sparse.raw() {
+            archetype.add_sparse(set);
+        }
+        self.archetypes.push(archetype);
+        self.index.insert(ids.into(), x);
+        trace_event!(
+            DEBUG,
+            archetype = x,
+            components = ids.len(),
+            "created archetype"
+        );
+        x
+    }
+
+    /// Like `archetype_for`, but for entities sharing the chunk components `chunk`, sorted by type
+    fn archetype_in_chunk(
+        &mut self,
+        ids: &[TypeId],
+        chunk: &[ChunkValue],
+        info: impl FnOnce() -> Vec<TypeInfo>,
+    ) -> u32 {
+        if chunk.is_empty() {
+            return self.archetype_for(ids, info);
+        }
+        let key = (Box::<[TypeId]>::from(ids), Box::<[ChunkValue]>::from(chunk));
+        if let Some(&x) = self.chunked_index.get(&key) {
+            return x;
+        }
+        let x = self.archetypes.len() as u32;
+        let mut archetype = Archetype::new(info());
+        archetype.set_allocator(self.allocator.clone());
+        archetype.set_tick(self.change_tick);
+        archetype.set_preserve_order(self.preserve_order);
+        archetype.set_chunk_values(chunk.to_vec());
+        for set in self.sparse.raw() {
+            archetype.add_sparse(set);
+        }
+        self.archetypes.push(archetype);
+        self.chunked_index.insert(key, x);
+        trace_event!(
+            DEBUG,
+            archetype = x,
+            components = ids.len(),
+            chunk_values = chunk.len(),
+            "created archetype"
+        );
+        x
+    }
+
+    /// Keep each archetype's entities in the order they were added to it, for deterministic
+    /// iteration
+    ///
+    /// Queries visit archetypes in the order they were created, then the entities of each in
+    /// storage order, so a `World` given the same sequence of operations always yields entities
+    /// in the same order, e.g. on every machine taking part in a lockstep simulation. By default,
+    /// though, removing an entity from an archetype, by despawning it or changing its components,
+    /// moves the archetype's last entity into the vacancy, so storage order soon departs from
+    /// spawn order. When enabled, later entities are shifted down instead, at a cost proportional
+    /// to their number, and each archetype holds its entities in the order they entered it. An
+    /// entity whose components change enters its new archetype last.
+    ///
+    /// Takes effect for subsequent removals. Regardless of this setting:
+    /// - parallel iteration visits entities in no particular order
+    /// - component types within an archetype are sorted by `TypeId`, which can differ between
+    ///   builds, so `Archetype::types` and serialized component order are only consistent between
+    ///   identical binaries
+    ///
+    /// # Example
+    /// ```
+    /// # use hecs::*;
+    /// let mut world = World::new();
+    /// world.set_preserve_order(true);
+    /// let entities = (0..4).map(|i| world.spawn((i,))).collect::<Vec<_>>();
+    /// world.despawn(entities[1]).unwrap();
+    /// let order = world.query_mut::<&i32>().into_iter().map(|(_, &x)| x).collect::<Vec<_>>();
+    /// assert_eq!(order, [0, 2, 3]);
+    /// ```
+    pub fn set_preserve_order(&mut self, enabled: bool) {
+        self.preserve_order = enabled;
+        for archetype in &mut self.archetypes {
+            archetype.set_preserve_order(enabled);
+        }
+    }
+
+    /// Reorder the entities of each archetype storing `T` by the key `key` computes from their `T`
+    ///
+    /// Iteration then visits each archetype's entities in ascending order of key, until entities
+    /// are added to or removed from it; see also `set_preserve_order`. The sort is stable, and takes
+    /// time linear in an archetype's length when its entities are already in order, so sorting
+    /// again every frame is cheap when few keys change. Entity handles and change ticks are
+    /// unaffected. Sparse `T` components are ignored.
+    ///
+    /// # Example
+    /// ```
+    /// # use hecs::*;
+    /// struct Depth(i32);
+    /// let mut world = World::new();
+    /// for (name, depth) in [("b", 2), ("c", 3), ("a", 1)] {
+    ///     world.spawn((name, Depth(depth)));
+    /// }
+    /// world.sort_archetype_by::<Depth, _>(|x| x.0);
+    /// let order = world.query_mut::<&&str>().into_iter().map(|(_, &x)| x).collect::<Vec<_>>();
+    /// assert_eq!(order, ["a", "b", "c"]);
+    /// ```
+    pub fn sort_archetype_by<T: Component, K: Ord>(&mut self, mut key: impl FnMut(&T) -> K) {
+        self.flush();
+        let mut keys = Vec::new();
+        let mut order = Vec::new();
+        for archetype in &mut self.archetypes {
+            let base = match archetype.get::<T>() {
+                Some(x) if !archetype.is_empty() => x,
+                _ => continue,
+            };
+            keys.clear();
+            keys.extend(
+                (0..archetype.len() as usize).map(|i| key(unsafe { &*base.as_ptr().add(i) })),
+            );
+            order.clear();
+            order.extend(0..archetype.len());
+            order.sort_by(|&a, &b| keys[a as usize].cmp(&keys[b as usize]));
+            if order.iter().enumerate().all(|(i, &x)| i as u32 == x) {
+                continue;
+            }
+            unsafe {
+                archetype.permute(&order);
+            }
+            for index in 0..archetype.len() {
+                self.entities.meta[archetype.entity_id(index) as usize]
+                    .location
+                    .index = index;
+            }
+        }
+    }
+
+    /// Release storage not needed by the current entities, returning the number of bytes freed
+    ///
+    /// Useful after the population of an archetype shrinks drastically. Subsequent spawns may need
+    /// to reallocate.
+    pub fn shrink_to_fit(&mut self) -> usize {
+        self.flush();
+        let sparse = self
+            .sparse
+            .iter_mut()
+            .map(|x| x.shrink_to(0))
+            .sum::<usize>();
+        sparse
+            + self
+                .archetypes
+                .iter_mut()
+                .map(|x| x.shrink_to_fit())
+                .sum::<usize>()
+    }
+
+    /// Like `shrink_to_fit`, but also discard archetypes containing no entities
+    ///
+    /// Archetypes are renumbered, so state derived from `archetypes` must be recomputed; see
+    /// `archetypes_generation`. `PreparedQuery` caches are invalidated.
+    ///
+    /// # Example
+    /// ```
+    /// # use hecs::*;
+    /// let mut world = World::new();
+    /// let a = world.spawn((123, true));
+    /// let b = world.spawn((456,));
+    /// world.despawn(a).unwrap();
+    /// assert!(world.compact() > 0);
+    /// assert_eq!(world.archetypes().len(), 2);
+    /// assert_eq!(*world.get::<i32>(b).unwrap(), 456);
+    /// ```
+    pub fn compact(&mut self) -> usize {
+        let mut freed = self.shrink_to_fit();
+        // Archetype 0 is retained, since `flush` assumes it exists
+        if self.archetypes[1..].iter().all(|x| !x.is_empty()) {
+            return freed;
+        }
+        let mut remap = Vec::with_capacity(self.archetypes.len());
+        let mut next = 0;
+        let mut old_index = 0;
+        self.archetypes.retain(|x| {
+            let keep = old_index == 0 || !x.is_empty();
+            old_index += 1;
+            if keep {
+                remap.push(Some(next));
+                next += 1;
+            } else {
+                freed += x.allocated_bytes();
+                remap.push(None);
+            }
+            keep
+        });
+        let remap_index = |x: &mut u32| match remap[*x as usize] {
+            Some(new) => {
+                *x = new;
+                true
+            }
+            None => false,
+        };
+        self.index.retain(|_, x| remap_index(x));
+        self.chunked_index.retain(|_, x| remap_index(x));
+        for archetype in &mut self.archetypes {
+            archetype.clear_edges();
+        }
+        for meta in &mut self.entities.meta {
+            if let Some(new) = remap[meta.location.archetype as usize] {
+                meta.location.archetype = new;
+            }
+        }
+        self.id = next_world_id();
+        freed
+    }
+
+    /// Despawn all entities
+    ///
+    /// Preserves allocated storage for reuse, including archetypes and entity IDs, so repopulating
+    /// a cleared world is cheap. Handles to despawned entities remain invalid.
+    pub fn clear(&mut self) {
+        self.flush();
+        self.entities.check_all_reusable();
+        if !self.remove_hooks.is_empty()
+            || self.events.is_enabled()
+            || self.events.tracks_removals()
+        {
+            for archetype in &self.archetypes {
+                for index in 0..archetype.len() {
+                    let id = archetype.entity_id(index);
+                    let entity = Entity {
+                        id,
+                        generation: self.entities.meta[id as usize].generation,
+                    };
+                    call_remove_hooks(&self.remove_hooks, archetype, entity, index, |_| true);
+                    call_sparse_remove_hooks(&self.remove_hooks, &self.sparse, entity, |_| true);
+                    self.events
+                        .despawned(entity, entity_types(archetype, &self.sparse, entity));
+                }
+            }
+        }
+        let pools = &mut self.pools;
+        for x in &mut self.archetypes {
+            x.clear_with(|ty, ptr| unsafe { pools.drop(ty, ptr) });
+        }
+        for x in self.sparse.iter_mut() {
+            x.clear_with(|ty, ptr| unsafe { pools.drop(ty, ptr) });
+        }
+        self.entities.clear();
+        self.labels.clear();
+        self.transients.clear();
+        for x in self.component_indexes.values_mut() {
+            x.clear();
+        }
+    }
+
+    /// Like `clear`, but release any storage beyond room for `max_capacity` entities in each
+    /// archetype, returning the number of bytes freed
+    ///
+    /// Useful when reusing a world for scratch work whose population occasionally spikes.
+    ///
+    /// # Example
+    /// ```
+    /// # use hecs::*;
+    /// let mut world = World::new();
+    /// world.spawn_batch((0..1000).map(|i| (i,)));
+    /// world.spawn((true,));
+    /// assert!(world.clear_with_capacity_policy(100) > 0);
+    /// assert!(world.archetypes().all(|x| x.capacity() <= 100));
+    /// ```
+    pub fn clear_with_capacity_policy(&mut self, max_capacity: u32) -> usize {
+        self.clear();
+        let sparse = self
+            .sparse
+            .iter_mut()
+            .map(|x| x.shrink_to(max_capacity))
+            .sum::<usize>();
+        sparse
+            + self
+                .archetypes
+                .iter_mut()
+                .map(|x| x.shrink_to(max_capacity))
+                .sum::<usize>()
+    }
+
+    /// Whether `entity` still exists
+    ///
+    /// Handles are checked generation and all: once `entity` is despawned, this is `false` even
+    /// after its ID is reused. See `occupant` to look up the current user of an ID.
+    pub fn contains(&self, entity: Entity) -> bool {
+        self.entities.contains(entity)
+    }
+
+    /// User-defined flags of `entity`, stored alongside its handle rather than its components
+    ///
+    /// Every entity has a `u32` of flags, zero when it's spawned, for hot state like "visible"
+    /// that many systems touch. Being atomic and outside any archetype, the flags may be read and
+    /// written through a shared reference to the world at any time, including while queries
+    /// borrow the entity's components, without borrow checks or conflicts. Flags are not saved by
+    /// serialization or snapshots, nor carried along by `take` or `clone_entity`.
+    ///
+    /// Fails for entities reserved since the world was last flushed.
+    ///
+    /// # Example
+    /// ```
+    /// # use hecs::*;
+    /// use std::sync::atomic::Ordering;
+    /// const VISIBLE: u32 = 1;
+    /// let mut world = World::new();
+    /// let a = world.spawn((1,));
+    /// let b = world.spawn((2,));
+    /// for (e, x) in world.query::<&i32>().iter() {
+    ///     if *x > 1 {
+    ///         world.flags(e).unwrap().fetch_or(VISIBLE, Ordering::Relaxed);
+    ///     }
+    /// }
+    /// assert_eq!(world.flags(a).unwrap().load(Ordering::Relaxed), 0);
+    /// assert_eq!(world.flags(b).unwrap().load(Ordering::Relaxed), VISIBLE);
+    /// ```
+    pub fn flags(&self, entity: Entity) -> Result<&AtomicU32, NoSuchEntity> {
+        self.entities.flags(entity).ok_or(NoSuchEntity)
+    }
+
+    /// The live entity currently using the ID `id`, if any, whatever its generation
+    ///
+    /// Entities reserved but not yet flushed are not reported.
+    ///
+    /// # Example
+    /// ```
+    /// # use hecs::*;
+    /// let mut world = World::new();
+    /// let a = world.spawn(());
+    /// world.despawn(a).unwrap();
+    /// let b = world.spawn(());
+    /// assert_eq!(a.id(), b.id());
+    /// assert!(!world.contains(a));
+    /// assert_eq!(world.occupant(a.id()), Some(b));
+    /// ```
+    pub fn occupant(&self, id: EntityId) -> Option<Entity> {
+        self.entities.occupant(id)
+    }
+
+    /// Replace each handle in `entities` that no longer exists with `Entity::DANGLING`, returning
+    /// the number replaced
+    ///
+    /// Convenient for cleaning up collections of handles, such as those held by components, in one
+    /// pass after despawning.
+    ///
+    /// # Example
+    /// ```
+    /// # use hecs::*;
+    /// let mut world = World::new();
+    /// let a = world.spawn(());
+    /// let b = world.spawn(());
+    /// world.despawn(a).unwrap();
+    /// let mut targets = [a, b];
+    /// assert_eq!(world.validate(&mut targets), 1);
+    /// assert_eq!(targets, [Entity::DANGLING, b]);
+    /// ```
+    pub fn validate(&self, entities: &mut [Entity]) -> usize {
+        let mut stale = 0;
+        for entity in entities {
+            if *entity != Entity::DANGLING && !self.contains(*entity) {
+                *entity = Entity::DANGLING;
+                stale += 1;
+            }
+        }
+        stale
+    }
+
+    /// Efficiently iterate over all entities that have certain components, using dynamic borrow
+    /// checking
+    ///
+    /// Prefer `query_mut` when concurrent access to the `World` is not required.
+    ///
+    /// Calling `iter` on the returned value yields `(Entity, Q)` tuples, where `Q` is some query
+    /// type. A query type is `&T`, `&mut T`, a tuple of query types, or an `Option` wrapping a
+    /// query type, where `T` is any component type. Components queried with `&mut` must only appear
+    /// once. Entities which do not have a component type referenced outside of an `Option` will be
+    /// skipped.
+    ///
+    /// Entities are yielded in arbitrary order.
+    ///
+    /// The returned `QueryBorrow` can be further transformed with combinator methods; see its
+    /// documentation for details.
+    ///
+    /// Iterating a query will panic if it would violate an existing unique reference or construct
+    /// an invalid unique reference. This occurs when two simultaneously-active queries could expose
+    /// the same entity. Simultaneous queries can access the same component type if and only if the
+    /// world contains no entities that have all components required by both queries, assuming no
+    /// other component borrows are outstanding.
+    ///
+    /// Iterating a query yields references with lifetimes bound to the `QueryBorrow` returned
+    /// here. To ensure those are invalidated, the return value of this method must be dropped for
+    /// its dynamic borrows from the world to be released. Similarly, lifetime rules ensure that
+    /// references obtained from a query cannot outlive the `QueryBorrow`.
+    ///
+    /// # Example
+    /// ```
+    /// # use hecs::*;
+    /// let mut world = World::new();
+    /// let a = world.spawn((123, true, "abc"));
+    /// let b = world.spawn((456, false));
+    /// let c = world.spawn((42, "def"));
+    /// let entities = world.query::<(&i32, &bool)>()
+    ///     .iter()
+    ///     .map(|(e, (&i, &b))| (e, i, b)) // Copy out of the world
+    ///     .collect::<Vec<_>>();
+    /// assert_eq!(entities.len(), 2);
+    /// assert!(entities.contains(&(a, 123, true)));
+    /// assert!(entities.contains(&(b, 456, false)));
+    /// ```
+    pub fn query<Q: Query>(&self) -> QueryBorrow<'_, Q> {
+        self.profile::<Q>(&self.archetypes);
+        QueryBorrow::new(&self.entities.meta, &self.archetypes)
+    }
+
+    /// Efficiently iterate over all entities that have components chosen at runtime, using dynamic
+    /// borrow checking
+    ///
+    /// Panics if any required component is already borrowed incompatibly. See `DynamicQuery`.
+    pub fn query_dynamic<'a>(&'a self, query: &'a DynamicQuery) -> DynamicQueryBorrow<'a> {
+        DynamicQueryBorrow::new(&self.entities.meta, &self.archetypes, Cow::Borrowed(query))
+    }
+
+    /// Register `T` as implementing the trait object type `Tr`, for use with `query_trait`
+    ///
+    /// `cast` converts shared references, for `query_trait`, and `cast_mut` unique ones, for
+    /// `query_trait_mut`. Both are typically just `|x| x`, relying on unsized coercion. Replaces
+    /// any casts previously registered for `T` and `Tr`.
+    ///
+    /// # Example
+    /// ```
+    /// # use hecs::*;
+    /// trait Damageable {
+    ///     fn damage(&mut self, amount: u32);
+    ///     fn health(&self) -> u32;
+    /// }
+    /// struct Monster(u32);
+    /// impl Damageable for Monster {
+    ///     fn damage(&mut self, amount: u32) { self.0 -= amount; }
+    ///     fn health(&self) -> u32 { self.0 }
+    /// }
+    /// struct Npc { health: u32 }
+    /// impl Damageable for Npc {
+    ///     fn damage(&mut self, amount: u32) { self.health -= amount; }
+    ///     fn health(&self) -> u32 { self.health }
+    /// }
+    ///
+    /// let mut world = World::new();
+    /// world.register_trait::<Monster, dyn Damageable>(|x| x, |x| x);
+    /// world.register_trait::<Npc, dyn Damageable>(|x| x, |x| x);
+    /// world.spawn((Monster(10),));
+    /// world.spawn((Npc { health: 20 }, true));
+    /// for (_, x) in world.query_trait_mut::<dyn Damageable>() {
+    ///     x.damage(5);
+    /// }
+    /// let total = world
+    ///     .query_trait::<dyn Damageable>()
+    ///     .iter()
+    ///     .map(|(_, x)| x.health())
+    ///     .sum::<u32>();
+    /// assert_eq!(total, 20);
+    /// ```
+    pub fn register_trait<T: Component, Tr: ?Sized + 'static>(
+        &mut self,
+        cast: fn(&T) -> &Tr,
+        cast_mut: fn(&mut T) -> &mut Tr,
+    ) {
+        self.trait_impls
+            .entry(TypeId::of::<Tr>())
+            .or_insert_with(|| Box::new(TraitImpls::<Tr>::new()))
+            .downcast_mut::<TraitImpls<Tr>>()
+            .unwrap()
+            .insert(cast, cast_mut);
+    }
+
+    /// Iterate over every component registered as implementing `Tr`, using dynamic borrow
+    /// checking
+    ///
+    /// Panics if any such component is already borrowed uniquely. See `register_trait`.
+    pub fn query_trait<Tr: ?Sized + 'static>(&self) -> TraitQueryBorrow<'_, Tr> {
+        TraitQueryBorrow::new(&self.entities.meta, &self.archetypes, self.trait_impls())
+    }
+
+    /// Uniquely borrow every component registered as implementing `Tr`
+    ///
+    /// Like `query_trait`, but yields unique references, and dynamic borrow checks are skipped.
+    /// An entity having several such components is visited once for each.
+    pub fn query_trait_mut<Tr: ?Sized + 'static>(
+        &mut self,
+    ) -> impl Iterator<Item = (Entity, &'_ mut Tr)> + '_ {
+        let meta = &self.entities.meta;
+        let archetypes = &self.archetypes;
+        let columns = self
+            .trait_impls::<Tr>()
+            .map_or_else(Vec::new, |x| x.columns(archetypes));
+        columns.into_iter().flat_map(move |column| {
+            column
+                .iter_mut(meta)
+                .map(|(entity, x)| (entity, unsafe { &mut *x }))
+        })
+    }
+
+    fn trait_impls<Tr: ?Sized + 'static>(&self) -> Option<&TraitImpls<Tr>> {
+        self.trait_impls
+            .get(&TypeId::of::<Tr>())
+            .map(|x| x.downcast_ref().unwrap())
+    }
+
+    /// Make components of type `T` addressable by `name`, e.g. from scripts or separately
+    /// compiled WASM modules that can't share Rust's `TypeId`s
+    ///
+    /// Names are independent of the compiler and build, so remain stable across runs. The type also
+    /// becomes addressable by `StableTypeId::from_name(name)` through `stable_type`. Registering the
+    /// same name for the same type again has no effect.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `name` is already registered for a different type.
+    ///
+    /// # Example
+    /// ```
+    /// # use hecs::*;
+    /// struct Position([f32; 2]);
+    /// let mut world = World::new();
+    /// world.register_named::<Position>("game::Position");
+    /// let e = world.spawn((Position([1.0, 2.0]),));
+    /// let pos = world.get_named(e, "game::Position").unwrap();
+    /// assert_eq!(unsafe { (*pos.as_ptr().cast::<Position>()).0 }, [1.0, 2.0]);
+    /// ```
+    pub fn register_named<T: Component>(&mut self, name: &str) {
+        let ty = TypeInfo::of::<T>().with_stable_id(StableTypeId::from_name(name));
+        self.register_named_inner(name, ty);
+    }
+
+    /// Make components of type `T` addressable by its `STABLE_NAME` and `STABLE_TYPE_ID`
+    ///
+    /// See `register_named`.
+    ///
+    /// # Example
+    /// ```
+    /// # use hecs::*;
+    /// struct Position([f32; 2]);
+    /// impl StableComponent for Position {
+    ///     const STABLE_NAME: &'static str = "game::Position";
+    /// }
+    /// let mut world = World::new();
+    /// world.register_stable::<Position>();
+    /// let ty = world.stable_type(Position::STABLE_TYPE_ID).unwrap();
+    /// assert_eq!(ty.id(), core::any::TypeId::of::<Position>());
+    /// assert_eq!(world.named_type("game::Position"), Some(ty));
+    /// ```
+    pub fn register_stable<T: StableComponent>(&mut self) {
+        self.register_named_inner(T::STABLE_NAME, TypeInfo::of_stable::<T>());
+    }
+
+    pub(crate) fn register_named_inner(&mut self, name: &str, ty: TypeInfo) {
+        let stable_id = ty.stable_id().unwrap();
+        match self.names.get(name) {
+            Some(x) => assert!(
+                x.id() == ty.id(),
+                "component name {:?} already registered for another type",
+                name
+            ),
+            None => {
+                if let Some(x) = self.stable_types.get(&stable_id) {
+                    assert!(
+                        x.id() == ty.id(),
+                        "stable ID of component name {:?} already registered for another type",
+                        name
+                    );
+                }
+                self.names.insert(name.into(), ty);
+                self.stable_types.insert(stable_id, ty);
+            }
+        }
+    }
+
+    /// Allow `T` components to be displayed by `debug_entity`
+    pub fn register_debug<T: Component + fmt::Debug>(&mut self) {
+        self.debug_formatters
+            .insert(TypeId::of::<T>(), DebugFormatter::of::<T>());
+    }
+
+    /// Allow `T` components to be displayed by `debug_entity` and compared by
+    /// [`testing::WorldDelta`](crate::testing::WorldDelta)
+    pub fn register_eq<T: Component + fmt::Debug + PartialEq>(&mut self) {
+        self.debug_formatters
+            .insert(TypeId::of::<T>(), DebugFormatter::of_eq::<T>());
+    }
+
+    pub(crate) fn debug_formatter(&self, ty: TypeId) -> Option<DebugFormatter> {
+        self.debug_formatters.get(&ty).copied()
+    }
+
+    /// Describe every component of `entity`, for the benefit of debug inspectors
+    ///
+    /// Components are listed with `Debug` formatting if their types were registered with
+    /// `register_debug`; others are only identified by `TypeId`. See `EntityRef::component_types`
+    /// and `EntityRef::get_raw` to handle runtime-typed components by other means.
+    ///
+    /// # Example
+    /// ```
+    /// # use hecs::*;
+    /// let mut world = World::new();
+    /// world.register_debug::<i32>();
+    /// world.register_debug::<&str>();
+    /// let a = world.spawn((42, "abc"));
+    /// let description = format!("{:?}", world.debug_entity(a).unwrap());
+    /// assert!(description.contains("i32: 42"));
+    /// assert!(description.contains(r#"&str: "abc""#));
+    /// ```
+    pub fn debug_entity(&self, entity: Entity) -> Result<DebugEntity<'_>, NoSuchEntity> {
+        Ok(DebugEntity {
+            entity: self.entity(entity)?,
+            formatters: &self.debug_formatters,
+        })
+    }
+
+    /// The type of component registered as `name` with `register_named`, if any
+    pub fn named_type(&self, name: &str) -> Option<TypeInfo> {
+        self.names.get(name).copied()
+    }
+
+    /// The type of component identified by `id`, if registered with `register_named` or
+    /// `register_stable`
+    ///
+    /// Suits data recorded by another build, or by a separately compiled plugin, that identifies
+    /// component types by `StableTypeId`.
+    pub fn stable_type(&self, id: StableTypeId) -> Option<TypeInfo> {
+        self.stable_types.get(&id).copied()
+    }
+
+    /// Borrow `entity`'s component registered as `name`, if it has one
+    ///
+    /// Panics if the component is already borrowed uniquely.
+    pub fn get_named(&self, entity: Entity, name: &str) -> Option<RawRef<'_>> {
+        let ty = self.named_type(name)?;
+        self.entity(entity).ok()?.get_raw(ty.id())
+    }
+
+    /// Uniquely borrow `entity`'s component registered as `name`, if it has one
+    ///
+    /// Panics if the component is already borrowed.
+    pub fn get_named_mut(&self, entity: Entity, name: &str) -> Option<RawRefMut<'_>> {
+        let ty = self.named_type(name)?;
+        self.entity(entity).ok()?.get_raw_mut(ty.id())
+    }
+
+    /// Move the value at `component` into `entity` as its component registered as `name`, as if
+    /// by `insert`
+    ///
+    /// # Safety
+    ///
+    /// `component` must point to a valid value of the type registered as `name`, which is moved
+    /// into the `World` unless `entity` doesn't exist.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `name` isn't registered.
+    pub unsafe fn insert_named(
+        &mut self,
+        entity: Entity,
+        name: &str,
+        component: *mut u8,
+    ) -> Result<(), NoSuchEntity> {
+        let ty = self.expect_named(name);
+        if !self.contains(entity) {
+            return Err(NoSuchEntity);
+        }
+        let mut builder = EntityBuilder::new();
+        builder.add_raw(component, ty);
+        self.insert(entity, builder.build())
+    }
+
+    /// Remove and drop `entity`'s component registered as `name`, if it has one
+    ///
+    /// # Panics
+    ///
+    /// Panics if `name` isn't registered.
+    pub fn remove_named(&mut self, entity: Entity, name: &str) -> Result<(), NoSuchEntity> {
+        let ty = self.expect_named(name);
+        self.remove_dynamic(entity, ty.id())
+    }
+
+    /// A `DynamicQuery` reading the components registered as `reads` and writing those registered
+    /// as `writes`, in that order, or `None` if any name isn't registered
+    ///
+    /// # Example
+    /// ```
+    /// # use hecs::*;
+    /// let mut world = World::new();
+    /// world.register_named::<i32>("health");
+    /// world.register_named::<bool>("alive");
+    /// world.spawn((10, true));
+    /// let query = world.named_query(&["alive"], &["health"]).unwrap();
+    /// for (_, mut row) in world.query_dynamic(&query).iter() {
+    ///     unsafe { *row.get_mut(1).unwrap().cast::<i32>() -= 1; }
+    /// }
+    /// assert!(world.named_query(&["mana"], &[]).is_none());
+    /// ```
+    pub fn named_query(&self, reads: &[&str], writes: &[&str]) -> Option<DynamicQuery> {
+        let mut query = DynamicQuery::new();
+        for name in reads {
+            query = query.read(self.named_type(name)?.id());
+        }
+        for name in writes {
+            query = query.write(self.named_type(name)?.id());
+        }
+        Some(query)
+    }
+
+    /// A `DynamicQuery` described by `query`, a comma-separated list of terms naming components
+    /// registered with `register_named`
+    ///
+    /// `&Name` reads, `&mut Name` writes, and `!Name` excludes entities having the component. Rows
+    /// hold the fetched components in the order their terms appear, skipping exclusions. An empty
+    /// string matches every entity.
+    ///
+    /// # Example
+    /// ```
+    /// # use hecs::*;
+    /// let mut world = World::new();
+    /// world.register_named::<i32>("health");
+    /// world.register_named::<bool>("dead");
+    /// world.spawn((10,));
+    /// world.spawn((0, true));
+    /// let query = world.parse_query("&mut health, !dead").unwrap();
+    /// assert_eq!(world.query_dynamic(&query).iter().count(), 1);
+    /// assert_eq!(
+    ///     world.parse_query("&health, &mana").unwrap_err(),
+    ///     QueryParseError::UnknownComponent("mana".into())
+    /// );
+    /// ```
+    pub fn parse_query(&self, query: &str) -> Result<DynamicQuery, QueryParseError> {
+        DynamicQuery::parse(query, |name| Some(self.named_type(name)?.id()))
+    }
+
+    /// Parse `query` as by `parse_query` and execute it, e.g. for a debug console
+    ///
+    /// Panics if a component is already borrowed incompatibly, including by an earlier term.
+    ///
+    /// # Example
+    /// ```
+    /// # use hecs::*;
+    /// struct Health(i32);
+    /// struct Dead;
+    /// let mut world = World::new();
+    /// world.register_named::<Health>("Health");
+    /// world.register_named::<Dead>("Dead");
+    /// let a = world.spawn((Health(10),));
+    /// world.spawn((Health(0), Dead));
+    /// for (_, mut row) in world.query_str("&mut Health, !Dead").unwrap().iter() {
+    ///     unsafe { (*row.get_mut(0).unwrap().cast::<Health>()).0 += 1 };
+    /// }
+    /// assert_eq!(world.get::<Health>(a).unwrap().0, 11);
+    /// ```
+    pub fn query_str(&self, query: &str) -> Result<DynamicQueryBorrow<'_>, QueryParseError> {
+        let query = self.parse_query(query)?;
+        Ok(DynamicQueryBorrow::new(
+            &self.entities.meta,
+            &self.archetypes,
+            Cow::Owned(query),
+        ))
+    }
+
+    fn expect_named(&self, name: &str) -> TypeInfo {
+        self.named_type(name)
+            .unwrap_or_else(|| panic!("no component registered as {:?}", name))
+    }
+
+    /// Label `entity` as `label`, replacing any label it already has
+    ///
+    /// Labels are unique within a `World`, giving entities stable, human-readable handles for
+    /// editors, debug commands, and scripts. If another entity was labeled `label`, it loses its
+    /// label and is returned. The label is stored as `entity`'s [`Label`] component, and forgotten
+    /// when `entity` is despawned.
+    ///
+    /// Labels follow entities moved by `merge` or `take`, unless the destination already has an
+    /// entity with the same label, in which case they're dropped.
+    ///
+    /// # Example
+    /// ```
+    /// # use hecs::*;
+    /// let mut world = World::new();
+    /// let a = world.spawn((123,));
+    /// world.insert_label(a, "player").unwrap();
+    /// assert_eq!(world.find_label("player"), Some(a));
+    /// assert_eq!(world.label(a).unwrap().as_str(), "player");
+    /// world.despawn(a).unwrap();
+    /// assert_eq!(world.find_label("player"), None);
+    /// ```
+    pub fn insert_label(
+        &mut self,
+        entity: Entity,
+        label: &str,
+    ) -> Result<Option<Entity>, NoSuchEntity> {
+        if !self.contains(entity) {
+            return Err(NoSuchEntity);
+        }
+        let previous = self.find_label(label);
+        if previous == Some(entity) {
+            return Ok(None);
+        }
+        if let Some(x) = previous {
+            self.remove_one::<Label>(x).unwrap();
+        }
+        self.remove_label(entity)?;
+        self.labels.insert(label.into(), entity);
+        self.insert_one(entity, Label(label.into()))?;
+        Ok(previous)
+    }
+
+    /// Remove and return `entity`'s label, if any
+    pub fn remove_label(&mut self, entity: Entity) -> Result<Option<Label>, NoSuchEntity> {
+        if !self.contains(entity) {
+            return Err(NoSuchEntity);
+        }
+        let label = match self.remove_one::<Label>(entity) {
+            Ok(x) => x,
+            Err(_) => return Ok(None),
+        };
+        if self.labels.get(&label.0) == Some(&entity) {
+            self.labels.remove(&label.0);
+        }
+        Ok(Some(label))
+    }
+
+    /// The entity labeled `label` with `insert_label`, if any
+    pub fn find_label(&self, label: &str) -> Option<Entity> {
+        let entity = *self.labels.get(label)?;
+        // Guard against `Label` components having been inserted or removed directly
+        let current = self.get::<Label>(entity).ok()?;
+        if *current == *label {
+            Some(entity)
+        } else {
+            None
+        }
+    }
+
+    /// The label of `entity`, if any
+    ///
+    /// Panics if the label is already borrowed uniquely.
+    pub fn label(&self, entity: Entity) -> Option<Ref<'_, Label>> {
+        self.get::<Label>(entity).ok()
+    }
+
+    /// Forget the label of `entity`, which is about to leave the `World`
+    fn forget_label(&mut self, entity: Entity) {
+        if self.labels.is_empty() {
+            return;
+        }
+        let label = match self.get::<Label>(entity) {
+            Ok(x) => x.0.clone(),
+            Err(_) => return,
+        };
+        if self.labels.get(&label) == Some(&entity) {
+            self.labels.remove(&label);
+        }
+    }
+
+    /// Record the label of `entity`, newly moved in from another `World`, dropping it if another
+    /// entity already has the same label
+    fn adopt_label(&mut self, entity: Entity) {
+        let label = match self.get::<Label>(entity) {
+            Ok(x) => x.0.clone(),
+            Err(_) => return,
+        };
+        if self.find_label(&label).is_some() {
+            self.remove_one::<Label>(entity).unwrap();
+        } else {
+            self.labels.insert(label, entity);
+        }
+    }
+
+    /// Query a uniquely borrowed world
+    ///
+    /// Like `query`, but faster because dynamic borrow checks can be skipped. Note that, unlike
+    /// `query`, this returns an `IntoIterator` which can be passed directly to a `for` loop.
+    ///
+    /// No borrow flags are touched at all: the unique borrow of the world already guarantees that
+    /// nothing else can access its components while the query lives.
+    ///
+    /// # Example
+    /// ```
+    /// # use hecs::*;
+    /// let mut world = World::new();
+    /// let a = world.spawn((1, 2.0f32));
+    /// for (_, (x, y)) in world.query_mut::<(&mut i32, &f32)>() {
+    ///     *x += *y as i32;
+    /// }
+    /// assert_eq!(*world.get::<i32>(a).unwrap(), 3);
+    /// ```
+    pub fn query_mut<Q: Query>(&mut self) -> QueryMut<'_, Q> {
+        self.profile::<Q>(&self.archetypes);
+        QueryMut::new(&self.entities.meta, &mut self.archetypes)
+    }
+
+    /// Query a uniquely borrowed world for `A` and `B` at once, e.g. to run two systems side by side
+    ///
+    /// Like `query_mut`, skips dynamic borrow checks. Instead, the accesses of `A` and `B` are
+    /// checked for compatibility once, up front; queries that only share read-only components
+    /// are always compatible.
+    ///
+    /// # Panics
+    ///
+    /// Panics if one query borrows uniquely a component type that the other borrows at all; see
+    /// `QueryAccess::is_compatible`.
+    ///
+    /// # Example
+    /// ```
+    /// # use hecs::*;
+    /// let mut world = World::new();
+    /// let a = world.spawn((1, 2.0_f32, true));
+    /// let (ints, floats) = world.query_pair_mut::<(&mut i32, &bool), (&mut f32, &bool)>();
+    /// for ((_, (i, _)), (_, (f, _))) in ints.into_iter().zip(floats) {
+    ///     *i += 1;
+    ///     *f *= 2.0;
+    /// }
+    /// assert_eq!(*world.get::<i32>(a).unwrap(), 2);
+    /// assert_eq!(*world.get::<f32>(a).unwrap(), 4.0);
+    /// ```
+    pub fn query_pair_mut<A: Query, B: Query>(&mut self) -> (QueryMut<'_, A>, QueryMut<'_, B>) {
+        assert!(
+            QueryAccess::of::<A>().is_compatible(&QueryAccess::of::<B>()),
+            "queries have conflicting access"
+        );
+        self.profile::<A>(&self.archetypes);
+        self.profile::<B>(&self.archetypes);
+        // Safe because the queries' accesses are compatible and `self` is uniquely borrowed
+        unsafe {
+            (
+                QueryMut::new_unchecked(&self.entities.meta, &self.archetypes),
+                QueryMut::new_unchecked(&self.entities.meta, &self.archetypes),
+            )
+        }
+    }
+
+    /// Query a uniquely borrowed world for `A`, alongside `B` to be visited repeatedly
+    ///
+    /// Like `query_pair_mut`, but the `QueryLookup` may be iterated or queried by entity once per
+    /// item of `A`, allowing for cross products such as collision pairs, or for following the
+    /// entities referred to by `A`'s components, without any per-item borrow checks.
+    ///
+    /// # Panics
+    ///
+    /// Panics if one query borrows uniquely a component type that the other borrows at all; see
+    /// `QueryAccess::is_compatible`.
+    ///
+    /// # Example
+    /// ```
+    /// # use hecs::*;
+    /// struct Target(Entity);
+    /// struct Aim(f32);
+    /// let mut world = World::new();
+    /// let a = world.spawn((1.0_f32,));
+    /// let b = world.spawn((Aim(0.0), Target(a)));
+    /// let (followers, mut targets) = world.query_nested_mut::<(&mut Aim, &Target), &f32>();
+    /// for (_, (aim, target)) in followers {
+    ///     if let Ok(&x) = targets.get(target.0) {
+    ///         aim.0 = x;
+    ///     }
+    /// }
+    /// assert_eq!(world.get::<Aim>(b).unwrap().0, 1.0);
+    /// ```
+    pub fn query_nested_mut<A: Query, B: Query>(
+        &mut self,
+    ) -> (QueryMut<'_, A>, QueryLookup<'_, B>) {
+        assert!(
+            QueryAccess::of::<A>().is_compatible(&QueryAccess::of::<B>()),
+            "queries have conflicting access"
+        );
+        self.flush();
+        self.profile::<A>(&self.archetypes);
+        self.profile::<B>(&self.archetypes);
+        let this = &*self;
+        // Safe because the queries' accesses are compatible, `self` is uniquely borrowed, and
+        // `QueryLookup` only yields one item of `B` at a time
+        unsafe {
+            (
+                QueryMut::new_unchecked(&this.entities.meta, &this.archetypes),
+                QueryLookup {
+                    world: this,
+                    _marker: PhantomData,
+                },
+            )
+        }
+    }
+
+    /// Share a uniquely borrowed world between threads whose accesses are coordinated externally
+    ///
+    /// Intended for schedulers that compare the `QueryAccess` of each system ahead of time, and
+    /// so have no use for dynamic borrow checks. See [`UnsafeWorldCell`].
+    pub fn as_unsafe_cell(&mut self) -> UnsafeWorldCell<'_> {
+        UnsafeWorldCell::new(self)
+    }
+
+    /// Share a uniquely borrowed world between threads that only read from it
+    ///
+    /// Unlike sharing `&World`, no reader can modify the world, e.g. with `get_mut`, so queries
+    /// through the view skip dynamic borrow checks. See [`WorldView`].
+    pub fn read_view(&mut self) -> WorldView<'_> {
+        WorldView::new(self)
+    }
+
+    /// Prepare a query against a single entity, using dynamic borrow checking
+    ///
+    /// Prefer `query_one_mut` when concurrent access to the `World` is not required.
+    ///
+    /// Call `get` on the resulting `QueryOne` to actually execute the query. The `QueryOne` value
+    /// is responsible for releasing the dynamically-checked borrow made by `get`, so it can't be
+    /// dropped while references returned by `get` are live.
+    ///
+    /// Handy for accessing multiple components simultaneously.
+    ///
+    /// # Example
+    /// ```
+    /// # use hecs::*;
+    /// let mut world = World::new();
+    /// let a = world.spawn((123, true, "abc"));
+    /// // The returned query must outlive the borrow made by `get`
+    /// let mut query = world.query_one::<(&mut i32, &bool)>(a).unwrap();
+    /// let (number, flag) = query.get().unwrap();
+    /// if *flag { *number *= 2; }
+    /// assert_eq!(*number, 246);
+    /// ```
+    pub fn query_one<Q: Query>(&self, entity: Entity) -> Result<QueryOne<'_, Q>, NoSuchEntity> {
+        let loc = self.entities.get(entity)?;
+        Ok(unsafe { QueryOne::new(Some(&self.archetypes[loc.archetype as usize]), loc.index) })
+    }
+
+    /// Query a single entity in a uniquely borrow world
+    ///
+    /// Like `query_one`, but faster because dynamic borrow checks can be skipped. Note that, unlike
+    /// `query_one`, on success this returns the query's results directly.
+    pub fn query_one_mut<Q: Query>(
+        &mut self,
+        entity: Entity,
+    ) -> Result<QueryItem<'_, Q>, QueryOneError> {
+        // Safe because `self` is uniquely borrowed
+        unsafe { self.query_one_unchecked::<Q>(entity) }
+    }
+
+    /// Query a single entity without dynamic borrow checks
+    ///
+    /// Like `query_one_mut`, but needing only a shared borrow of the world, so that entities
+    /// found through other queries or lookups can be revisited in inner loops without the cost of
+    /// acquiring and releasing borrows each time. See also `get_unchecked` and
+    /// `get_unchecked_mut`. Whether `entity` is live is still checked, since the check costs one
+    /// comparison against the metadata read to locate the entity anyway.
+    ///
+    /// # Safety
+    ///
+    /// No borrow of a component `Q` accesses uniquely, nor unique borrow of a component `Q`
+    /// reads, may be live for the same entity simultaneous to the returned references.
+    ///
+    /// # Example
+    /// ```
+    /// # use hecs::*;
+    /// let mut world = World::new();
+    /// let a = world.spawn((1, 2.0f32));
+    /// let b = world.spawn((3, 4.0f32));
+    /// for (_, x) in world.query::<&mut f32>().iter() {
+    ///     // Safe because the query only borrows `f32`s.
+    ///     let &i = unsafe { world.query_one_unchecked::<&i32>(a).unwrap() };
+    ///     *x += i as f32;
+    /// }
+    /// assert_eq!(*world.get::<f32>(b).unwrap(), 5.0);
+    /// ```
+    pub unsafe fn query_one_unchecked<Q: Query>(
+        &self,
+        entity: Entity,
+    ) -> Result<QueryItem<'_, Q>, QueryOneError> {
+        let loc = self.entities.get(entity)?;
+        let fetch = Q::Fetch::new(&self.archetypes[loc.archetype as usize])
+            .ok_or(QueryOneError::Unsatisfied)?;
+        if fetch.should_skip(loc.index as usize) {
+            return Err(QueryOneError::Unsatisfied);
+        }
+        Ok(fetch.get(loc.index as usize))
+    }
+
+    /// Whether `entity` would be yielded by a query for `Q`
+    ///
+    /// Much cheaper than `query_one` or a full query, since no borrows are acquired and no
+    /// components are read.
+    ///
+    /// # Example
+    /// ```
+    /// # use hecs::*;
+    /// let mut world = World::new();
+    /// let a = world.spawn((123, true));
+    /// assert!(world.satisfies::<(&i32, Without<&str, &bool>)>(a).unwrap());
+    /// assert!(!world.satisfies::<&&str>(a).unwrap());
+    /// ```
+    pub fn satisfies<Q: Query>(&self, entity: Entity) -> Result<bool, NoSuchEntity> {
+        let loc = self.entities.get(entity)?;
+        Ok(unsafe { satisfies::<Q>(&self.archetypes[loc.archetype as usize], loc.index) })
+    }
+
+    /// Query several distinct entities in a uniquely borrowed world
+    ///
+    /// Like `query_one_mut`, but returns results for every entity in `entities` at once, or an
+    /// error if any of them is missing or doesn't satisfy the query.
+    ///
+    /// Panics if any entity appears in `entities` more than once.
+    ///
+    /// # Example
+    /// ```
+    /// # use hecs::*;
+    /// let mut world = World::new();
+    /// let a = world.spawn((10, true));
+    /// let b = world.spawn((20, false));
+    /// let [(x, _), (y, _)] = world.query_many_mut::<(&mut i32, &bool), 2>([a, b]).unwrap();
+    /// core::mem::swap(x, y);
+    /// assert_eq!(*world.get::<i32>(a).unwrap(), 20);
+    /// ```
+    pub fn query_many_mut<Q: Query, const N: usize>(
+        &mut self,
+        entities: [Entity; N],
+    ) -> Result<[QueryItem<'_, Q>; N], QueryOneError> {
+        assert_distinct(&entities);
+        let mut locs = [Location {
+            archetype: 0,
+            index: 0,
+        }; N];
+        for (loc, &entity) in locs.iter_mut().zip(&entities) {
+            *loc = self.entities.get(entity)?;
+            unsafe {
+                let fetch = Q::Fetch::new(&self.archetypes[loc.archetype as usize])
+                    .ok_or(QueryOneError::Unsatisfied)?;
+                if fetch.should_skip(loc.index as usize) {
+                    return Err(QueryOneError::Unsatisfied);
+                }
+            }
+        }
+        // Safe because the entities are distinct, so no two items alias
+        let archetypes = &self.archetypes;
+        Ok(locs.map(move |loc| unsafe {
+            Q::Fetch::new(&archetypes[loc.archetype as usize])
+                .unwrap()
+                .get(loc.index as usize)
+        }))
+    }
+
+    /// Uniquely borrow the `T` components of several distinct entities at once
+    ///
+    /// Faster than a query when only a handful of entities are involved, and, unlike `get_mut`,
+    /// permits simultaneous access to entities with the same components.
+    ///
+    /// Panics if any entity appears in `entities` more than once.
+    ///
+    /// # Example
+    /// ```
+    /// # use hecs::*;
+    /// let mut world = World::new();
+    /// let attacker = world.spawn((10,));
+    /// let target = world.spawn((100,));
+    /// let [a, t] = world.get_many_mut::<i32, 2>([attacker, target]).unwrap();
+    /// *t -= *a;
+    /// assert_eq!(*world.get::<i32>(target).unwrap(), 90);
+    /// ```
+    pub fn get_many_mut<T: Component, const N: usize>(
+        &mut self,
+        entities: [Entity; N],
+    ) -> Result<[&mut T; N], ComponentError> {
+        assert_distinct(&entities);
+        for &entity in &entities {
+            let loc = self.entities.get(entity)?;
+            if !unsafe { satisfies::<&T>(&self.archetypes[loc.archetype as usize], loc.index) } {
+                return Err(MissingComponent::new::<T>().into());
+            }
+        }
+        // Safe because the entities are distinct and `self` is uniquely borrowed
+        let this = &*self;
+        Ok(entities.map(move |entity| unsafe { this.get_unchecked_mut::<T>(entity).unwrap() }))
+    }
+
+    /// Borrow the `T` component of `entity`
+    ///
+    /// Panics if the component is already uniquely borrowed from another entity with the same
+    /// components.
+    #[inline]
+    pub fn get<T: Component>(&self, entity: Entity) -> Result<Ref<'_, T>, ComponentError> {
+        let loc = self.entities.get(entity)?;
+        Ok(unsafe { Ref::new(&self.archetypes[loc.archetype as usize], loc.index)? })
+    }
+
+    /// Uniquely borrow the `T` component of `entity`
+    ///
+    /// Panics if the component is already borrowed from another entity with the same components.
+    #[inline]
+    pub fn get_mut<T: Component>(&self, entity: Entity) -> Result<RefMut<'_, T>, ComponentError> {
+        let loc = self.entities.get(entity)?;
+        Ok(unsafe { RefMut::new(&self.archetypes[loc.archetype as usize], loc.index)? })
+    }
+
+    /// Like `get`, but returning `BorrowError::Conflict` rather than panicking if the component is
+    /// already uniquely borrowed
+    ///
+    /// Lets a caller sharing the world with other threads poll for access, such as while a
+    /// parallel query holds the component uniquely, instead of panicking.
+    ///
+    /// # Example
+    /// ```
+    /// # use hecs::*;
+    /// let mut world = World::new();
+    /// let a = world.spawn((123,));
+    /// let x = world.get_mut::<i32>(a).unwrap();
+    /// assert!(matches!(world.try_borrow::<i32>(a), Err(BorrowError::Conflict(_))));
+    /// drop(x);
+    /// assert_eq!(*world.try_borrow::<i32>(a).unwrap(), 123);
+    /// ```
+    pub fn try_borrow<T: Component>(&self, entity: Entity) -> Result<Ref<'_, T>, BorrowError> {
+        let loc = self.entities.get(entity)?;
+        unsafe { Ref::try_new(&self.archetypes[loc.archetype as usize], loc.index)? }
+            .ok_or_else(BorrowError::conflict::<T>)
+    }
+
+    /// Like `get_mut`, but returning `BorrowError::Conflict` rather than panicking if the
+    /// component is already borrowed
+    ///
+    /// See `try_borrow`.
+    pub fn try_borrow_mut<T: Component>(
+        &self,
+        entity: Entity,
+    ) -> Result<RefMut<'_, T>, BorrowError> {
+        let loc = self.entities.get(entity)?;
+        unsafe { RefMut::try_new(&self.archetypes[loc.archetype as usize], loc.index)? }
+            .ok_or_else(BorrowError::conflict::<T>)
+    }
+
+    /// Spawn an entity with no components, returning a handle for building it up
+    ///
+    /// # Example
+    /// ```
+    /// # use hecs::*;
+    /// let mut world = World::new();
+    /// let a = world.spawn_empty().insert((123, "abc")).insert_one(true).id();
+    /// assert_eq!(*world.get::<i32>(a).unwrap(), 123);
+    /// assert!(*world.get::<bool>(a).unwrap());
+    /// ```
+    pub fn spawn_empty(&mut self) -> EntityMut<'_> {
+        let entity = self.spawn(());
+        self.entity_mut(entity).unwrap()
+    }
+
+    /// Uniquely access an entity, to operate on it repeatedly without naming it each time
+    ///
+    /// Since the world is borrowed uniquely, components are accessed without dynamic borrow checks,
+    /// and the entity's location is looked up only once rather than for every operation.
+    pub fn entity_mut(&mut self, entity: Entity) -> Result<EntityMut<'_>, NoSuchEntity> {
+        self.flush();
+        let location = self.entities.get(entity)?;
+        Ok(EntityMut {
+            world: self,
+            entity,
+            location,
+        })
+    }
+
+    /// Access an entity regardless of its component types
+    ///
+    /// Does not immediately borrow any component. The entity's location is resolved once, up
+    /// front, so the result is a cheap way to repeatedly access the same entity's components.
+    pub fn entity(&self, entity: Entity) -> Result<EntityRef<'_>, NoSuchEntity> {
+        let loc = self.entities.get(entity)?;
+        let archetype = &self.archetypes[loc.archetype as usize];
+        Ok(if loc.index < archetype.len() {
+            unsafe { EntityRef::new(archetype, loc.index) }
+        } else {
+            // A reserved entity
+            EntityRef::empty()
+        })
+    }
+
+    /// Given an id obtained from `Entity::id`, reconstruct the still-live `Entity`.
+    ///
+    /// # Safety
+    /// `id` must correspond to a currently live `Entity`. A despawned or never-allocated `id` will produce undefined behavior.
+    pub unsafe fn find_entity_from_id(&self, id: EntityId) -> Entity {
+        self.entities.resolve_unknown_gen(id)
+    }
+
+    /// Iterate over all entities in the world
+    ///
+    /// Entities are yielded in arbitrary order. Prefer `World::query` for better performance when
+    /// components will be accessed in predictable patterns.
+    ///
+    /// # Example
+    /// ```
+    /// # use hecs::*;
+    /// let mut world = World::new();
+    /// let a = world.spawn(());
+    /// let b = world.spawn(());
+    /// let ids = world.iter().map(|(id, _)| id).collect::<Vec<_>>();
+    /// assert_eq!(ids.len(), 2);
+    /// assert!(ids.contains(&a));
+    /// assert!(ids.contains(&b));
+    /// ```
+    pub fn iter(&self) -> Iter<'_> {
+        Iter::new(&self.archetypes, &self.entities)
+    }
+
+    /// Add `components` to `entity`
+    ///
+    /// Computational cost is proportional to the number of components `entity` has. If an entity
+    /// already has a component of a certain type, it is dropped and replaced.
+    ///
+    /// When inserting a single component, see `insert_one` for convenience.
+    ///
+    /// # Example
+    /// ```
+    /// # use hecs::*;
+    /// let mut world = World::new();
+    /// let e = world.spawn((123, "abc"));
+    /// world.insert(e, (456, true));
+    /// assert_eq!(*world.get::<i32>(e).unwrap(), 456);
+    /// assert_eq!(*world.get::<bool>(e).unwrap(), true);
+    /// ```
+    pub fn insert(
+        &mut self,
+        entity: Entity,
+        components: impl DynamicBundle,
+    ) -> Result<(), NoSuchEntity> {
+        self.flush();
+        let loc = *self.entities.get_mut(entity)?;
+        let info = components.type_info();
+        let target = self.insert_target(loc.archetype, &info);
+        unsafe {
+            self.insert_inner(entity, components, &info, target);
+        }
+        Ok(())
+    }
+
+    /// Add `components` to `entity`, only if storage already exists for the result
+    ///
+    /// Unlike `insert`, never allocates: fails, returning `components`, if `entity` doesn't
+    /// exist, or would move to an archetype that hasn't been created, for example by
+    /// `register_archetype`, or is full. Entities sharing a chunk component are always refused.
+    /// See `with_capacity`.
+    pub fn try_insert<B: Bundle>(&mut self, entity: Entity, components: B) -> Result<(), B> {
+        self.flush();
+        let loc = match self.entities.get_mut(entity) {
+            Ok(x) => *x,
+            Err(NoSuchEntity) => return Err(components),
+        };
+        B::with_static_type_info(|info| {
+            let target = match self.try_insert_target(loc.archetype, info) {
+                Some(x) => x,
+                None => return Err(components),
+            };
+            let arch = &self.archetypes[target as usize];
+            if target != loc.archetype && arch.len() == arch.capacity() {
+                return Err(components);
+            }
+            unsafe {
+                self.insert_inner(entity, components, info, target);
+            }
+            Ok(())
+        })
+    }
+
+    /// Like `insert_target`, but returning `None` rather than allocating
+    fn try_insert_target(&self, source: u32, info: &[TypeInfo]) -> Option<u32> {
+        /// Most component types an archetype may have to be found without allocating
+        const MAX_TYPES: usize = 64;
+
+        let arch = &self.archetypes[source as usize];
+        if let [ty] = *info {
+            if let Some(x) = arch.insert_edge(ty.id()) {
+                return Some(x);
+            }
+        }
+        if !arch.chunk_values().is_empty() {
+            return None;
+        }
+        let mut ids = [TypeId::of::<()>(); MAX_TYPES];
+        let mut len = 0;
+        let mut existing = arch.types().iter().copied().peekable();
+        let mut added = info
+            .iter()
+            .copied()
+            .filter(|x| {
+                !arch.has_dynamic(x.id())
+                    && !self.sparse.contains(x.id())
+                    && !arch.is_pinned(x.id())
+            })
+            .peekable();
+        // Merge the sorted lists of types, as sorting the result would
+        loop {
+            let next = match (existing.peek(), added.peek()) {
+                (Some(x), Some(y)) if y < x => added.next(),
+                (Some(_), _) => existing.next(),
+                (None, _) => added.next(),
+            };
+            let next = match next {
+                Some(x) => x,
+                None => break,
+            };
+            *ids.get_mut(len)? = next.id();
+            len += 1;
+        }
+        let target = *self.index.get(&ids[..len])?;
+        // Carrying pins along is left to `insert_target`
+        let target_arch = &self.archetypes[target as usize];
+        if arch.pinned().iter().all(|&ty| target_arch.is_pinned(ty)) {
+            Some(target)
+        } else {
+            None
+        }
+    }
+
+    /// Add `components` to each entity, as if by `insert`
+    ///
+    /// Much faster than calling `insert` in a loop when many entities share an archetype, since
+    /// the destination archetype of each group of them is found and allocated for only once. If
+    /// any entity does not exist, no components are added and an error is returned.
+    ///
+    /// # Example
+    /// ```
+    /// # use hecs::*;
+    /// let mut world = World::new();
+    /// let entities = world.spawn_batch((0..100).map(|i| (i,))).collect::<Vec<_>>();
+    /// world.insert_batch(entities.iter().map(|&e| (e, (true,)))).unwrap();
+    /// assert_eq!(world.query::<(&i32, &bool)>().iter().count(), 100);
+    /// ```
+    pub fn insert_batch<B: Bundle>(
+        &mut self,
+        iter: impl IntoIterator<Item = (Entity, B)>,
+    ) -> Result<(), NoSuchEntity> {
+        self.flush();
+        let entities = &mut self.entities;
+        let mut items = iter
+            .into_iter()
+            .map(|(entity, components)| Ok((*entities.get_mut(entity)?, entity, components)))
+            .collect::<Result<Vec<_>, NoSuchEntity>>()?;
+        items.sort_by_key(|x| (x.0.archetype, x.0.index));
+        // Source archetype, number of entities, and whether they're listed once each in storage
+        // order, in which case every entity of the archetype is listed if the count matches
+        let mut groups = Vec::<(u32, u32, bool)>::new();
+        for &(loc, _, _) in &items {
+            match groups.last_mut() {
+                Some((last, count, ordered)) if *last == loc.archetype => {
+                    *ordered &= loc.index == *count;
+                    *count += 1;
+                }
+                _ => groups.push((loc.archetype, 1, loc.index == 0)),
+            }
+        }
+
+        let info = B::static_type_info();
+        let mut items = items.into_iter();
+        for (source, count, ordered) in groups {
+            let target = self.insert_target(source, &info);
+            if target != source {
+                self.archetypes[target as usize].reserve(count);
+            }
+            if target != source && ordered && self.archetypes[source as usize].len() == count {
+                // Move the whole archetype at once, each shared column copied in one piece
+                for &(loc, entity, _) in &items.as_slice()[..count as usize] {
+                    unsafe {
+                        self.drop_replaced(entity, loc, &info, target);
+                    }
+                }
+                let (source_arch, target_arch) =
+                    index2(&mut self.archetypes, source as usize, target as usize);
+                trace_event!(
+                    TRACE,
+                    entities = count,
+                    from = source,
+                    to = target,
+                    "moved archetype"
+                );
+                let base = unsafe { source_arch.move_all_to(target_arch) };
+                let sparse = &mut self.sparse;
+                for (n, (_, entity, components)) in items.by_ref().take(count as usize).enumerate()
+                {
+                    let index = base + n as u32;
+                    self.entities.meta[entity.id as usize].location = Location {
+                        archetype: target,
+                        index,
+                    };
+                    unsafe {
+                        components.put(|ptr, ty| {
+                            if target_arch.has_dynamic(ty.id()) {
+                                target_arch.put_dynamic(ptr, ty.id(), ty.layout().size(), index);
+                            } else {
+                                sparse.get_mut(ty.id()).unwrap().put(entity.id, ptr);
+                            }
+                        });
+                    }
+                }
+                continue;
+            }
+            for (_, entity, components) in items.by_ref().take(count as usize) {
+                // An entity listed more than once will have moved since being grouped
+                let current = self.entities.meta[entity.id as usize].location.archetype;
+                let target = if current == source {
+                    target
+                } else {
+                    self.insert_target(current, &info)
+                };
+                unsafe {
+                    self.insert_inner(entity, components, &info, target);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Find the archetype that entities in `source` move to when components `info` are added
+    fn insert_target(&mut self, source: u32, info: &[TypeInfo]) -> u32 {
+        // Inserting a single component is common enough to be worth caching the destination of
+        if let [ty] = *info {
+            if let Some(x) = self.archetypes[source as usize].insert_edge(ty.id()) {
+                return x;
+            }
+        }
+        let arch = &self.archetypes[source as usize];
+        let chunk = arch.chunk_values().to_vec();
+        let mut target_info = arch.types().to_vec();
+        for ty in info {
+            if !arch.has_dynamic(ty.id())
+                && !self.sparse.contains(ty.id())
+                && !arch.is_pinned(ty.id())
+            {
+                target_info.push(*ty);
+            }
+        }
+        target_info.sort();
+        let elements = target_info.iter().map(|x| x.id()).collect::<Vec<_>>();
+        let target = self.archetype_in_chunk(&elements, &chunk, || target_info);
+        self.carry_pins(source, target);
+        if let [ty] = *info {
+            self.archetypes[source as usize].set_insert_edge(ty.id(), target);
+        }
+        target
+    }
+
+    /// Find or create the archetype for entities of archetype `source` that lose their
+    /// `removed` components
+    fn remove_target(&mut self, source: u32, removed: &[TypeId]) -> u32 {
+        if let [ty] = *removed {
+            if let Some(x) = self.archetypes[source as usize].remove_edge(ty) {
+                return x;
+            }
+        }
+        let arch = &self.archetypes[source as usize];
+        let info = arch
+            .types()
+            .iter()
+            .cloned()
+            .filter(|x| !removed.contains(&x.id()))
+            .collect::<Vec<_>>();
+        let chunk = arch.chunk_values().to_vec();
+        let elements = info.iter().map(|x| x.id()).collect::<Vec<_>>();
+        let target = self.archetype_in_chunk(&elements, &chunk, || info);
+        self.carry_pins(source, target);
+        if let [ty] = *removed {
+            self.archetypes[source as usize].set_remove_edge(ty, target);
+        }
+        target
+    }
+
+    /// Pin the types pinned by archetype `source` for `target` too, so that sparse components of
+    /// entities moving from one to the other remain sparse
+    ///
+    /// `target` must lack columns for those types, as the targets of insertion and removal do.
+    fn carry_pins(&mut self, source: u32, target: u32) {
+        if source == target || self.archetypes[source as usize].pinned().is_empty() {
+            return;
+        }
+        let (source_arch, target_arch) =
+            index2(&mut self.archetypes, source as usize, target as usize);
+        for &ty in source_arch.pinned() {
+            target_arch.pin(ty, target);
+        }
+    }
+
+    /// Add `components`, described by `info`, to `entity`, which must exist
+    ///
+    /// `target` must be the `insert_target` of the entity's current archetype.
+    unsafe fn insert_inner(
+        &mut self,
+        entity: Entity,
+        components: impl DynamicBundle,
+        info: &[TypeInfo],
+        target: u32,
+    ) {
+        let loc = *self.entities.get_mut(entity).unwrap();
+        self.drop_replaced(entity, loc, info, target);
+
+        let loc = &mut self.entities.meta[entity.id as usize].location;
+        let arch = &mut self.archetypes[loc.archetype as usize];
+        let sparse = &mut self.sparse;
+        if target == loc.archetype {
+            // Update components in the current archetype
+            components.put(|ptr, ty| {
+                if arch.has_dynamic(ty.id()) {
+                    arch.put_dynamic(ptr, ty.id(), ty.layout().size(), loc.index);
+                } else {
+                    sparse.get_mut(ty.id()).unwrap().put(entity.id, ptr);
+                }
+            });
+            return;
+        }
+
+        // Move into a new archetype
+        trace_event!(
+            TRACE,
+            ?entity,
+            from = loc.archetype,
+            to = target,
+            "moved entity"
+        );
+        let (source_arch, target_arch) = index2(
+            &mut self.archetypes,
+            loc.archetype as usize,
+            target as usize,
+        );
+        let target_index = target_arch.allocate(entity.id);
+        loc.archetype = target;
+        let old_index = mem::replace(&mut loc.index, target_index);
+        if let Some(moved) = source_arch.move_to(old_index, |ptr, ty, size, ticks| {
+            target_arch.put_dynamic(ptr, ty, size, target_index);
+            target_arch.set_ticks(ty, target_index, ticks);
+        }) {
+            relocate(&mut self.entities.meta, source_arch, moved, old_index);
+        }
+        components.put(|ptr, ty| {
+            if target_arch.has_dynamic(ty.id()) {
+                target_arch.put_dynamic(ptr, ty.id(), ty.layout().size(), target_index);
+            } else {
+                sparse.get_mut(ty.id()).unwrap().put(entity.id, ptr);
+            }
+        });
+    }
+
+    /// Drop the components of `entity`, at `loc`, that are about to be replaced by components of
+    /// types `info` as it moves to archetype `target`, recording the other types as added
+    unsafe fn drop_replaced(
+        &mut self,
+        entity: Entity,
+        loc: Location,
+        info: &[TypeInfo],
+        target: u32,
+    ) {
+        let arch = &self.archetypes[loc.archetype as usize];
+        for ty in info {
+            let existing = arch
+                .get_dynamic(ty.id(), ty.layout().size(), loc.index)
+                .or_else(|| self.sparse.component(ty.id(), entity.id));
+            if let Some(ptr) = existing {
+                if let Some(hook) = self.remove_hooks.get(&ty.id()) {
+                    hook(entity, ptr.as_ptr());
+                }
+                self.pools.drop(ty, ptr.as_ptr());
+            } else if self.events.is_enabled() {
+                self.events.added(entity, core::slice::from_ref(ty));
+            }
+        }
+        // A sparse component replaced by one stored in a column must leave its set, lest the
+        // entity have both. The target may not have allocated its columns yet.
+        let target = &self.archetypes[target as usize];
+        for ty in info {
+            if !target.types().iter().any(|x| x.id() == ty.id()) {
+                continue;
+            }
+            if let Some(set) = self.sparse.get_mut(ty.id()) {
+                // Already dropped
+                set.take(entity.id, |_, _| {});
+            }
+        }
+    }
+
+    /// Add `component` to `entity`
+    ///
+    /// See `insert`.
+    pub fn insert_one(
+        &mut self,
+        entity: Entity,
+        component: impl Component,
+    ) -> Result<(), NoSuchEntity> {
+        self.insert(entity, (component,))
+    }
+
+    /// Uniquely access the `T` component of `entity`, first adding the result of `f` if it has
+    /// none
+    ///
+    /// `entity` is moved to a new archetype only if the component was missing.
+    ///
+    /// # Example
+    /// ```
+    /// # use hecs::*;
+    /// let mut world = World::new();
+    /// let a = world.spawn((true,));
+    /// *world.get_or_insert_with(a, || 0).unwrap() += 1;
+    /// *world.get_or_insert_with(a, || 0).unwrap() += 1;
+    /// assert_eq!(*world.get::<i32>(a).unwrap(), 2);
+    /// ```
+    pub fn get_or_insert_with<T: Component>(
+        &mut self,
+        entity: Entity,
+        f: impl FnOnce() -> T,
+    ) -> Result<&mut T, NoSuchEntity> {
+        if !self.satisfies::<&T>(entity)? {
+            self.insert_one(entity, f())?;
+        }
+        Ok(self.query_one_mut::<&mut T>(entity).unwrap())
+    }
+
+    /// Remove components from `entity`
+    ///
+    /// Computational cost is proportional to the number of components `entity` has. The entity
+    /// itself is not removed, even if no components remain; use `despawn` for that. If any
+    /// component in `T` is not present in `entity`, no components are removed and an error is
+    /// returned.
+    ///
+    /// When removing a single component, see `remove_one` for convenience.
+    ///
+    /// # Example
+    /// ```
+    /// # use hecs::*;
+    /// let mut world = World::new();
+    /// let e = world.spawn((123, "abc", true));
+    /// assert_eq!(world.remove::<(i32, &str)>(e), Ok((123, "abc")));
+    /// assert!(world.get::<i32>(e).is_err());
+    /// assert!(world.get::<&str>(e).is_err());
+    /// assert_eq!(*world.get::<bool>(e).unwrap(), true);
+    /// ```
+    pub fn remove<T: Bundle>(&mut self, entity: Entity) -> Result<T, ComponentError> {
+        self.flush();
+        let loc = *self.entities.get_mut(entity)?;
+        unsafe {
+            let removed = T::with_static_ids(|ids| ids.to_vec());
+            let target = self.remove_target(loc.archetype, &removed);
+            let loc = self.entities.get_mut(entity)?;
+            let old_index = loc.index;
+            let source_arch = &self.archetypes[loc.archetype as usize];
+            let sparse = &mut self.sparse;
+            let present = |id: TypeId| {
+                source_arch.has_dynamic(id) || sparse.get(id).is_some_and(|x| x.contains(entity.id))
+            };
+            if removed.iter().all(|&id| present(id)) {
+                call_remove_hooks(&self.remove_hooks, source_arch, entity, old_index, |id| {
+                    removed.contains(&id)
+                });
+                call_sparse_remove_hooks(&self.remove_hooks, sparse, entity, |id| {
+                    removed.contains(&id) && !source_arch.has_dynamic(id)
+                });
+            }
+            let bundle = T::get(|ty| {
+                source_arch
+                    .get_dynamic(ty.id(), ty.layout().size(), old_index)
+                    .or_else(|| sparse.component(ty.id(), entity.id))
+            })?;
+            for &id in &removed {
+                if !source_arch.has_dynamic(id) {
+                    // Already moved into `bundle`
+                    sparse.get_mut(id).unwrap().take(entity.id, |_, _| {});
+                }
+            }
+            let events = &mut self.events;
+            T::with_static_ids(|ids| {
+                for &id in ids {
+                    events.removed(entity, id);
+                }
+            });
+            // If we actually removed any components, the entity needs to be moved into a new archetype
+            if loc.archetype != target {
+                trace_event!(
+                    TRACE,
+                    ?entity,
+                    from = loc.archetype,
+                    to = target,
+                    "moved entity"
+                );
+                let (source_arch, target_arch) = index2(
+                    &mut self.archetypes,
+                    loc.archetype as usize,
+                    target as usize,
+                );
+                let target_index = target_arch.allocate(entity.id);
+                loc.archetype = target;
+                loc.index = target_index;
+                if let Some(moved) = source_arch.move_to(old_index, |src, ty, size, ticks| {
+                    // Only move the components present in the target archetype, i.e. the non-removed ones.
+                    if let Some(dst) = target_arch.get_dynamic(ty, size, target_index) {
+                        ptr::copy_nonoverlapping(src, dst.as_ptr(), size);
+                        target_arch.set_ticks(ty, target_index, ticks);
+                    }
+                }) {
+                    relocate(&mut self.entities.meta, source_arch, moved, old_index);
+                }
+            }
+            Ok(bundle)
+        }
+    }
+
+    /// Remove and drop the `T` component of every entity that has one, returning how many did
+    ///
+    /// Much faster than removing components one entity at a time, since entities sharing an
+    /// archetype are moved to their new archetype all at once, each column copied in one piece.
+    /// Useful for clearing marker components at the end of a frame.
+    ///
+    /// # Example
+    /// ```
+    /// # use hecs::*;
+    /// struct Hit;
+    /// let mut world = World::new();
+    /// let a = world.spawn((1, Hit));
+    /// let b = world.spawn((2, Hit, true));
+    /// let c = world.spawn((3,));
+    /// assert_eq!(world.remove_all::<Hit>(), 2);
+    /// assert!(world.query::<&Hit>().is_empty());
+    /// assert_eq!(*world.get::<i32>(b).unwrap(), 2);
+    /// ```
+    pub fn remove_all<T: Component>(&mut self) -> u32 {
+        self.remove_all_matching::<T, Including<Disabled>>()
+    }
+
+    /// Like `remove_all`, but only for entities that satisfy `Q`
+    ///
+    /// Entities are still moved an archetype at a time, except where `Q` excludes some but not all
+    /// of an archetype's entities, e.g. due to `Changed` or a sparse component.
+    pub fn remove_all_matching<T: Component, Q: Query>(&mut self) -> u32 {
+        self.flush();
+        let ty = TypeId::of::<T>();
+        let mut count = 0;
+        // Entities to handle individually
+        let mut stragglers = Vec::new();
+        for source in 0..self.archetypes.len() as u32 {
+            let archetype = &self.archetypes[source as usize];
+            if archetype.is_empty() {
+                continue;
+            }
+            let fetch = match EnabledFetch::<<With<T, Q> as Query>::Fetch>::new(archetype) {
+                Some(x) => x,
+                None => continue,
+            };
+            let skipped = (0..archetype.len() as usize)
+                .filter(|&n| unsafe { fetch.should_skip(n) })
+                .count();
+            if skipped != 0 || !archetype.has::<T>() || self.pools.contains(ty) {
+                let entities = &self.entities;
+                stragglers.extend((0..archetype.len()).filter_map(|n| {
+                    if unsafe { fetch.should_skip(n as usize) } {
+                        return None;
+                    }
+                    let id = archetype.entity_id(n);
+                    Some(Entity {
+                        id,
+                        generation: entities.meta[id as usize].generation,
+                    })
+                }));
+                continue;
+            }
+
+            let target = self.remove_target(source, &[ty]);
+            let source_arch = &self.archetypes[source as usize];
+            for index in 0..source_arch.len() {
+                let id = source_arch.entity_id(index);
+                let entity = Entity {
+                    id,
+                    generation: self.entities.meta[id as usize].generation,
+                };
+                call_remove_hooks(&self.remove_hooks, source_arch, entity, index, |x| x == ty);
+                self.events.removed(entity, ty);
+            }
+            let (source_arch, target_arch) =
+                index2(&mut self.archetypes, source as usize, target as usize);
+            let moved = source_arch.len();
+            trace_event!(
+                TRACE,
+                entities = moved,
+                from = source,
+                to = target,
+                "moved archetype"
+            );
+            let base = unsafe { source_arch.move_all_to(target_arch) };
+            for index in base..base + moved {
+                self.entities.meta[target_arch.entity_id(index) as usize].location = Location {
+                    archetype: target,
+                    index,
+                };
+            }
+            count += moved;
+        }
+        for entity in stragglers {
+            self.remove_dynamic(entity, ty).unwrap();
+            count += 1;
+        }
+        count
+    }
+
+    /// Remove and drop the component of type `ty` from `entity`, if it has one
+    pub(crate) fn remove_dynamic(
+        &mut self,
+        entity: Entity,
+        ty: TypeId,
+    ) -> Result<(), NoSuchEntity> {
+        self.flush();
+        let loc = *self.entities.get_mut(entity)?;
+        let removed = match self.archetypes[loc.archetype as usize].type_info(ty) {
+            Some(&x) => x,
+            None => {
+                if self.sparse.component(ty, entity.id).is_some() {
+                    call_sparse_remove_hooks(&self.remove_hooks, &self.sparse, entity, |x| x == ty);
+                    self.events.removed(entity, ty);
+                    let pools = &mut self.pools;
+                    let set = self.sparse.get_mut(ty).unwrap();
+                    let info = *set.type_info();
+                    unsafe {
+                        set.take(entity.id, |ptr, _| pools.drop(&info, ptr));
+                    }
+                }
+                return Ok(());
+            }
+        };
+        let target = self.remove_target(loc.archetype, &[ty]);
+        let loc = self.entities.get_mut(entity)?;
+        let old_index = loc.index;
+        let source_arch = &self.archetypes[loc.archetype as usize];
+        call_remove_hooks(&self.remove_hooks, source_arch, entity, old_index, |x| {
+            x == ty
+        });
+        self.events.removed(entity, ty);
+        trace_event!(
+            TRACE,
+            ?entity,
+            from = loc.archetype,
+            to = target,
+            "moved entity"
+        );
+        let (source_arch, target_arch) = index2(
+            &mut self.archetypes,
+            loc.archetype as usize,
+            target as usize,
+        );
+        let pools = &mut self.pools;
+        unsafe {
+            let target_index = target_arch.allocate(entity.id);
+            loc.archetype = target;
+            loc.index = target_index;
+            if let Some(moved) = source_arch.move_to(old_index, |src, id, size, ticks| {
+                match target_arch.get_dynamic(id, size, target_index) {
+                    Some(dst) => {
+                        ptr::copy_nonoverlapping(src, dst.as_ptr(), size);
+                        target_arch.set_ticks(id, target_index, ticks);
+                    }
+                    None => pools.drop(&removed, src),
+                }
+            }) {
+                relocate(&mut self.entities.meta, source_arch, moved, old_index);
+            }
+        }
+        Ok(())
+    }
+
+    /// The chunk component of type `C` shared by `entity`
+    pub fn chunk<C: ChunkComponent>(&self, entity: Entity) -> Result<&C, ComponentError> {
+        let loc = self.entities.get(entity)?;
+        self.archetypes[loc.archetype as usize]
+            .chunk::<C>()
+            .ok_or_else(MissingComponent::new::<C>)
+            .map_err(ComponentError::MissingComponent)
+    }
+
+    /// Move `entity` into the chunk whose `C` component is `value`, replacing any `C` it
+    /// previously shared
+    ///
+    /// Computational cost is proportional to the number of components `entity` has. Other chunk
+    /// components are retained.
+    ///
+    /// # Example
+    /// ```
+    /// # use hecs::*;
+    /// #[derive(Clone, PartialEq, Eq, Hash, Debug)]
+    /// struct Material(u32);
+    /// let mut world = World::new();
+    /// let a = world.spawn((123,));
+    /// world.set_chunk(a, Material(1)).unwrap();
+    /// assert_eq!(world.chunk::<Material>(a).unwrap(), &Material(1));
+    /// assert_eq!(world.remove_chunk::<Material>(a), Ok(Material(1)));
+    /// assert!(world.chunk::<Material>(a).is_err());
+    /// ```
+    pub fn set_chunk<C: ChunkComponent>(
+        &mut self,
+        entity: Entity,
+        value: C,
+    ) -> Result<(), NoSuchEntity> {
+        self.flush();
+        let loc = *self.entities.get_mut(entity)?;
+        let source = &self.archetypes[loc.archetype as usize];
+        let mut chunk = source
+            .chunk_values()
+            .iter()
+            .filter(|x| x.ty() != TypeId::of::<C>())
+            .cloned()
+            .collect::<Vec<_>>();
+        chunk.push(ChunkValue::new(value));
+        chunk.sort_unstable_by_key(|x| x.ty());
+        self.move_to_chunk(entity, &chunk);
+        Ok(())
+    }
+
+    /// Move `entity` out of the chunk it shares the `C` component of, returning that value
+    ///
+    /// See `set_chunk`.
+    pub fn remove_chunk<C: ChunkComponent>(&mut self, entity: Entity) -> Result<C, ComponentError> {
+        self.flush();
+        let value = self.chunk::<C>(entity)?.clone();
+        let loc = *self.entities.get_mut(entity)?;
+        let chunk = self.archetypes[loc.archetype as usize]
+            .chunk_values()
+            .iter()
+            .filter(|x| x.ty() != TypeId::of::<C>())
+            .cloned()
+            .collect::<Vec<_>>();
+        self.move_to_chunk(entity, &chunk);
+        Ok(value)
+    }
+
+    /// Move `entity`, which must exist, into the archetype for its components in `chunk`
+    fn move_to_chunk(&mut self, entity: Entity, chunk: &[ChunkValue]) {
+        let loc = *self.entities.get_mut(entity).unwrap();
+        let source = &self.archetypes[loc.archetype as usize];
+        let info = source.types().to_vec();
+        let ids = info.iter().map(|x| x.id()).collect::<Vec<_>>();
+        let target = self.archetype_in_chunk(&ids, chunk, || info);
+        if target == loc.archetype {
+            return;
+        }
+        self.carry_pins(loc.archetype, target);
+        trace_event!(
+            TRACE,
+            ?entity,
+            from = loc.archetype,
+            to = target,
+            "moved entity"
+        );
+        let (source_arch, target_arch) = index2(
+            &mut self.archetypes,
+            loc.archetype as usize,
+            target as usize,
+        );
+        unsafe {
+            let target_index = target_arch.allocate(entity.id);
+            if let Some(moved) = source_arch.move_to(loc.index, |ptr, ty, size, ticks| {
+                target_arch.put_dynamic(ptr, ty, size, target_index);
+                target_arch.set_ticks(ty, target_index, ticks);
+            }) {
+                relocate(&mut self.entities.meta, source_arch, moved, loc.index);
+            }
+            self.entities.meta[entity.id as usize].location = Location {
+                archetype: target,
+                index: target_index,
+            };
+        }
+    }
+
+    /// Remove the `T` component from `entity`
+    ///
+    /// See `remove`.
+    pub fn remove_one<T: Component>(&mut self, entity: Entity) -> Result<T, ComponentError> {
+        self.remove::<(T,)>(entity).map(|(x,)| x)
+    }
+
+    /// Remove the components `T` from `entity` and add `components`, moving it directly to its
+    /// final archetype
+    ///
+    /// Equivalent to `remove` followed by `insert`, but copies the entity's remaining components
+    /// only once, and never exposes it in an intermediate archetype. If any component in `T` is
+    /// not present in `entity`, no components are removed or added and an error is returned.
+    ///
+    /// # Example
+    /// ```
+    /// # use hecs::*;
+    /// struct Walking;
+    /// struct Swimming;
+    /// let mut world = World::new();
+    /// let e = world.spawn((Walking, 123));
+    /// world.exchange::<(Walking,)>(e, (Swimming,)).unwrap();
+    /// assert!(world.get::<Walking>(e).is_err());
+    /// assert!(world.get::<Swimming>(e).is_ok());
+    /// assert_eq!(*world.get::<i32>(e).unwrap(), 123);
+    /// ```
+    pub fn exchange<T: Bundle>(
+        &mut self,
+        entity: Entity,
+        components: impl DynamicBundle,
+    ) -> Result<T, ComponentError> {
+        self.flush();
+        let loc = *self.entities.get_mut(entity)?;
+        let removed = T::with_static_ids(|ids| ids.iter().copied().collect::<HashSet<_>>());
+        let inserted = components.type_info();
+        if !self.sparse.is_empty()
+            && (removed.iter().any(|&id| self.sparse.get(id).is_some())
+                || inserted.iter().any(|x| self.sparse.get(x.id()).is_some()))
+        {
+            // Sparse components don't take part in archetype transitions anyway
+            let bundle = self.remove::<T>(entity)?;
+            self.insert(entity, components)?;
+            return Ok(bundle);
+        }
+        let source_arch = &self.archetypes[loc.archetype as usize];
+        let is_replaced =
+            |id: TypeId| !removed.contains(&id) && inserted.iter().any(|x| x.id() == id);
+        let mut info = source_arch
+            .types()
+            .iter()
+            .cloned()
+            .filter(|x| !removed.contains(&x.id()) && !is_replaced(x.id()))
+            .chain(inserted.iter().cloned())
+            .collect::<Vec<_>>();
+        info.sort();
+        unsafe {
+            if removed.iter().all(|&id| source_arch.has_dynamic(id)) {
+                call_remove_hooks(&self.remove_hooks, source_arch, entity, loc.index, |id| {
+                    removed.contains(&id) || is_replaced(id)
+                });
+            }
+            let bundle =
+                T::get(|ty| source_arch.get_dynamic(ty.id(), ty.layout().size(), loc.index))?;
+            let events = &mut self.events;
+            T::with_static_ids(|ids| {
+                for &id in ids {
+                    events.removed(entity, id);
+                }
+            });
+            if events.is_enabled() {
+                for ty in &inserted {
+                    if removed.contains(&ty.id()) || !source_arch.has_dynamic(ty.id()) {
+                        events.added(entity, core::slice::from_ref(ty));
+                    }
+                }
+            }
+
+            let chunk = source_arch.chunk_values().to_vec();
+            let elements = info.iter().map(|x| x.id()).collect::<Vec<_>>();
+            let target = self.archetype_in_chunk(&elements, &chunk, || info);
+            if target == loc.archetype {
+                // Every removed type was also inserted, so the emptied slots are simply refilled
+                let arch = &mut self.archetypes[target as usize];
+                for ty in &inserted {
+                    if is_replaced(ty.id()) {
+                        let ptr = arch.get_dynamic(ty.id(), ty.layout().size(), loc.index);
+                        self.pools.drop(ty, ptr.unwrap().as_ptr());
+                    }
+                }
+                components.put(|ptr, ty| {
+                    arch.put_dynamic(ptr, ty.id(), ty.layout().size(), loc.index);
+                });
+                return Ok(bundle);
+            }
+
+            trace_event!(
+                TRACE,
+                ?entity,
+                from = loc.archetype,
+                to = target,
+                "moved entity"
+            );
+            let (source_arch, target_arch) = index2(
+                &mut self.archetypes,
+                loc.archetype as usize,
+                target as usize,
+            );
+            let target_index = target_arch.allocate(entity.id);
+            let meta_loc = self.entities.get_mut(entity).unwrap();
+            meta_loc.archetype = target;
+            meta_loc.index = target_index;
+            let pools = &mut self.pools;
+            if let Some(moved) = source_arch.move_to(loc.index, |src, ty, size, ticks| {
+                if removed.contains(&ty) {
+                    // Already moved into `bundle`
+                } else if let Some(x) = inserted.iter().find(|x| x.id() == ty) {
+                    pools.drop(x, src);
+                } else {
+                    target_arch.put_dynamic(src, ty, size, target_index);
+                    target_arch.set_ticks(ty, target_index, ticks);
+                }
+            }) {
+                relocate(&mut self.entities.meta, source_arch, moved, loc.index);
+            }
+            components.put(|ptr, ty| {
+                target_arch.put_dynamic(ptr, ty.id(), ty.layout().size(), target_index);
+            });
+            Ok(bundle)
+        }
+    }
+
+    /// Borrow the `T` component of `entity` without safety checks
+    ///
+    /// Should only be used as a building block for safe abstractions.
+    ///
+    /// # Safety
+    ///
+    /// `entity` must have been previously obtained from this `World`, and no unique borrow of the
+    /// same component of `entity` may be live simultaneous to the returned reference.
+    pub unsafe fn get_unchecked<T: Component>(&self, entity: Entity) -> Result<&T, ComponentError> {
+        let loc = self.entities.get(entity)?;
+        let archetype = &self.archetypes[loc.archetype as usize];
+        let slot = archetype
+            .locate::<T>(loc.index)
+            .ok_or_else(MissingComponent::new::<T>)?;
+        Ok(&*slot.target.as_ptr())
+    }
+
+    /// Uniquely borrow the `T` component of `entity` without safety checks
+    ///
+    /// Should only be used as a building block for safe abstractions.
+    ///
+    /// # Safety
+    ///
+    /// `entity` must have been previously obtained from this `World`, and no borrow of the same
+    /// component of `entity` may be live simultaneous to the returned reference.
+    #[allow(clippy::mut_from_ref)]
+    pub unsafe fn get_unchecked_mut<T: Component>(
+        &self,
+        entity: Entity,
+    ) -> Result<&mut T, ComponentError> {
+        let loc = self.entities.get(entity)?;
+        let archetype = &self.archetypes[loc.archetype as usize];
+        let slot = archetype
+            .locate::<T>(loc.index)
+            .ok_or_else(MissingComponent::new::<T>)?;
+        slot.mutated
+            .as_ref()
+            .store(archetype.tick(), Ordering::Relaxed);
+        Ok(&mut *slot.target.as_ptr())
+    }
+
+    /// Store `T` components apart from archetypes, so adding or removing one needn't move its
+    /// entity
+    ///
+    /// Entities with the same ordinary components share an archetype, so adding or removing a
+    /// component usually moves all of an entity's components into a different one. Adding or
+    /// removing a sparse component is instead a single hash map operation, at the cost of slower
+    /// access during queries. Best suited to rarely held, frequently toggled components such as
+    /// status effects. Sparse components are queried, borrowed, and removed just like any other,
+    /// except that they aren't visible to `Added`, `Changed`, `DynamicQuery`, `columns`, trait
+    /// queries, nor `WorldDiff`, and are dropped rather than moved into `ColumnBatch`es by
+    /// `extract`. `T` components already present, or later added by `spawn_batch` or
+    /// `spawn_column_batch`, are stored in archetypes as usual.
+    ///
+    /// # Example
+    /// ```
+    /// # use hecs::*;
+    /// struct Stunned;
+    /// let mut world = World::new();
+    /// world.register_sparse::<Stunned>();
+    /// let a = world.spawn((123, true));
+    /// world.insert_one(a, Stunned).unwrap();
+    /// assert_eq!(world.query::<(&i32, &Stunned)>().iter().count(), 1);
+    /// world.remove_one::<Stunned>(a).unwrap();
+    /// assert_eq!(world.query::<With<Stunned, &i32>>().iter().count(), 0);
+    /// ```
+    pub fn register_sparse<T: Component>(&mut self) {
+        self.register_sparse_dynamic(TypeInfo::of::<T>());
+    }
+
+    fn register_sparse_dynamic(&mut self, ty: TypeInfo) {
+        self.sparse_set_dynamic(ty);
+        self.sparse.get_mut(ty.id()).unwrap().set_default();
+    }
+
+    /// Find or create the set for `ty` components, without storing them sparsely by default
+    fn sparse_set_dynamic(&mut self, ty: TypeInfo) {
+        let (set, created) = self
+            .sparse
+            .insert(ty, self.change_tick, self.allocator.as_ref());
+        if created {
+            for archetype in &mut self.archetypes {
+                archetype.add_sparse(set);
+            }
+            // Existing archetypes may now match different queries, so caches must be rebuilt
+            self.id = next_world_id();
+        }
+    }
+
+    /// Find or create a set like `other`, from another world
+    fn sparse_set_like(&mut self, other: &SparseSet) {
+        if other.is_default() {
+            self.register_sparse_dynamic(*other.type_info());
+        } else {
+            self.sparse_set_dynamic(*other.type_info());
+        }
+    }
+
+    /// Store `entity`'s `B` components apart from its archetype, as if registered with
+    /// `register_sparse`, so that toggling them doesn't move it between archetypes
+    ///
+    /// Suits entities that frequently gain and lose the same few components, such as buffs and
+    /// debuffs. `B` components the entity already has are moved out of its archetype, which moves
+    /// the entity once. From then on, every entity in its new archetype stores `B` components
+    /// sparsely, as do entities of each archetype the entity later moves to by gaining or losing
+    /// other components, while entities elsewhere are unaffected. See `register_sparse` for how
+    /// sparse components differ; pinning has no effect on types already registered sparse.
+    ///
+    /// # Example
+    /// ```
+    /// # use hecs::*;
+    /// struct Haste;
+    /// let mut world = World::new();
+    /// let a = world.spawn((1, Haste));
+    /// world.pin_components::<(Haste,)>(a).unwrap();
+    /// assert!(world.get::<Haste>(a).is_ok());
+    /// let generation = world.archetypes_generation();
+    /// for _ in 0..10 {
+    ///     world.remove_one::<Haste>(a).unwrap();
+    ///     world.insert_one(a, Haste).unwrap();
+    /// }
+    /// assert_eq!(world.archetypes_generation(), generation);
+    /// assert_eq!(world.query::<(&i32, &Haste)>().iter().count(), 1);
+    /// ```
+    pub fn pin_components<B: Bundle>(&mut self, entity: Entity) -> Result<(), NoSuchEntity> {
+        self.flush();
+        let loc = *self.entities.get_mut(entity)?;
+        let info = B::static_type_info();
+        for &ty in &info {
+            if !self.sparse.contains(ty.id()) {
+                self.sparse_set_dynamic(ty);
+            }
+        }
+        let held = info
+            .iter()
+            .map(|x| x.id())
+            .filter(|&id| self.archetypes[loc.archetype as usize].has_dynamic(id))
+            .collect::<Vec<_>>();
+        let target = if held.is_empty() {
+            loc.archetype
+        } else {
+            self.remove_target(loc.archetype, &held)
+        };
+        if target != loc.archetype {
+            trace_event!(
+                TRACE,
+                ?entity,
+                from = loc.archetype,
+                to = target,
+                "moved entity"
+            );
+            let (source_arch, target_arch) = index2(
+                &mut self.archetypes,
+                loc.archetype as usize,
+                target as usize,
+            );
+            let sparse = &mut self.sparse;
+            unsafe {
+                let target_index = target_arch.allocate(entity.id);
+                if let Some(moved) = source_arch.move_to(loc.index, |src, ty, size, ticks| {
+                    match target_arch.get_dynamic(ty, size, target_index) {
+                        Some(dst) => {
+                            ptr::copy_nonoverlapping(src, dst.as_ptr(), size);
+                            target_arch.set_ticks(ty, target_index, ticks);
+                        }
+                        None => sparse.get_mut(ty).unwrap().put(entity.id, src),
+                    }
+                }) {
+                    relocate(&mut self.entities.meta, source_arch, moved, loc.index);
+                }
+                self.entities.meta[entity.id as usize].location = Location {
+                    archetype: target,
+                    index: target_index,
+                };
+            }
+        }
+        let arch = &mut self.archetypes[target as usize];
+        for ty in &info {
+            if !self.sparse.contains(ty.id()) {
+                arch.pin(ty.id(), target);
+            }
+        }
+        Ok(())
+    }
+
+    /// Register `hook` to be called on every `T` component just before it leaves the world
+    ///
+    /// The hook is invoked when a `T` is removed with `remove`, replaced with `insert`, or
+    /// despawned along with its entity, including by `clear`. It is not invoked for components
+    /// still present when the `World` itself is dropped. Replaces any hook previously registered
+    /// for `T`.
+    ///
+    /// # Example
+    /// ```
+    /// # use hecs::*;
+    /// # use std::sync::{Arc, Mutex};
+    /// let freed = Arc::new(Mutex::new(Vec::new()));
+    /// let mut world = World::new();
+    /// let sink = freed.clone();
+    /// world.on_remove::<u32>(move |_, buffer| sink.lock().unwrap().push(*buffer));
+    /// let a = world.spawn((7u32,));
+    /// world.despawn(a).unwrap();
+    /// assert_eq!(*freed.lock().unwrap(), [7]);
+    /// ```
+    pub fn on_remove<T: Component>(
+        &mut self,
+        hook: impl Fn(Entity, &mut T) + Send + Sync + 'static,
+    ) {
+        self.remove_hooks.insert(
+            TypeId::of::<T>(),
+            Box::new(move |entity, ptr| hook(entity, unsafe { &mut *ptr.cast::<T>() })),
+        );
+    }
+
+    /// Keep up to `capacity` `T` components that the world would otherwise drop, so that their
+    /// heap allocations may be reused
+    ///
+    /// Components enter the pool when despawned along with their entity, including by `clear`,
+    /// or replaced with `insert`, and may be taken back out with `take_pooled` to construct new
+    /// components without touching the allocator. Those removed with `remove` are returned to the
+    /// caller as usual, and those still present when the `World` is dropped are dropped. Calling
+    /// this again for `T` changes the capacity, dropping any pooled components beyond it.
+    ///
+    /// # Example
+    /// ```
+    /// # use hecs::*;
+    /// struct Mesh(Vec<f32>);
+    /// let mut world = World::new();
+    /// world.register_pool::<Mesh>(16);
+    /// let a = world.spawn((Mesh(Vec::with_capacity(1024)),));
+    /// world.despawn(a).unwrap();
+    /// let mut mesh = world.take_pooled::<Mesh>().unwrap();
+    /// assert!(mesh.0.capacity() >= 1024);
+    /// mesh.0.clear();
+    /// world.spawn((mesh,));
+    /// let stats = world.pool_stats::<Mesh>().unwrap();
+    /// assert_eq!((stats.recycled(), stats.reused(), stats.pooled()), (1, 1, 0));
+    /// ```
+    pub fn register_pool<T: Component>(&mut self, capacity: usize) {
+        self.pools.register::<T>(capacity);
+    }
+
+    /// Take a `T` component previously discarded by the world out of its pool, if any
+    ///
+    /// The component is exactly as it was when it left the world. Always `None` unless `T` was
+    /// registered with `register_pool`.
+    pub fn take_pooled<T: Component>(&mut self) -> Option<T> {
+        self.pools.take::<T>()
+    }
+
+    /// Occupancy of the pool of `T` components, if registered with `register_pool`
+    pub fn pool_stats<T: Component>(&self) -> Option<PoolStats> {
+        self.pools.stats(TypeId::of::<T>())
+    }
+
+    /// Maintain a [`ComponentIndex`] of the entities holding each value of `T`, for
+    /// `component_index`
+    ///
+    /// The index notices values as they're added or mutably accessed, and entities as they lose
+    /// their `T`. It's brought up to date by `component_index` and by `clear_trackers`, taking
+    /// time proportional to the number of entities whose `T` changed since the most recent call
+    /// to `clear_trackers`, plus the time to skip those that didn't. Sparse `T` components aren't
+    /// indexed. Does nothing if `T` is already indexed.
+    pub fn index_component<T: Component + Hash + Eq + Clone>(&mut self) {
+        if self.component_indexes.contains_key(&TypeId::of::<T>()) {
+            return;
+        }
+        self.track_removed::<T>();
+        let mut index = ComponentIndex::<T>::new();
+        index.populate(self);
+        self.component_indexes
+            .insert(TypeId::of::<T>(), Box::new(index));
+    }
+
+    /// The index of `T` components registered with `index_component`, brought up to date
+    pub fn component_index<T: Component + Hash + Eq + Clone>(
+        &mut self,
+    ) -> Option<&ComponentIndex<T>> {
+        self.component_indexes.get(&TypeId::of::<T>())?;
+        self.refresh_component_indexes();
+        self.component_indexes
+            .get(&TypeId::of::<T>())?
+            .as_any()
+            .downcast_ref()
+    }
+
+    fn refresh_component_indexes(&mut self) {
+        if self.component_indexes.is_empty() {
+            return;
+        }
+        let mut indexes = mem::take(&mut self.component_indexes);
+        for index in indexes.values_mut() {
+            index.refresh(self);
+        }
+        self.component_indexes = indexes;
+    }
+
+    /// Begin recording the entities that lose their `T` component, for `removed`
+    ///
+    /// Entities are recorded when `T` is removed from them, or when they're despawned or otherwise
+    /// leave the world while holding a `T`, but not when their `T` is replaced by `insert`.
+    pub fn track_removed<T: Component>(&mut self) {
+        self.events.track_removals(TypeId::of::<T>());
+    }
+
+    /// The entities that lost their `T` component since the last call to `clear_trackers`, in
+    /// the order they lost it
+    ///
+    /// Useful for removing stale entries from external structures like spatial indices. Always
+    /// empty unless enabled with [`track_removed`](Self::track_removed). An entity may appear more
+    /// than once if it regained and lost another `T` in the meantime.
+    ///
+    /// # Example
+    /// ```
+    /// # use hecs::*;
+    /// let mut world = World::new();
+    /// world.track_removed::<i32>();
+    /// let a = world.spawn((1, true));
+    /// let b = world.spawn((2,));
+    /// world.remove_one::<i32>(a).unwrap();
+    /// world.despawn(b).unwrap();
+    /// assert_eq!(world.removed::<i32>(), [a, b]);
+    /// world.clear_trackers();
+    /// assert!(world.removed::<i32>().is_empty());
+    /// ```
+    pub fn removed<T: Component>(&self) -> &[Entity] {
+        self.events.removals(TypeId::of::<T>())
+    }
+
+    /// Begin or stop recording [`WorldEvent`]s describing structural changes to the world
+    ///
+    /// Recording is disabled by default. Events accumulate until drained with
+    /// [`take_events`](Self::take_events), allowing caches of world state to be maintained
+    /// incrementally. Replacing an existing component with `insert` records no event; use a
+    /// [`Changed`](crate::Changed) query to observe that. Disabling recording discards any
+    /// pending events.
+    ///
+    /// # Example
+    /// ```
+    /// # use hecs::*;
+    /// # use core::any::TypeId;
+    /// let mut world = World::new();
+    /// world.record_events(true);
+    /// let a = world.spawn((123,));
+    /// world.despawn(a).unwrap();
+    /// assert_eq!(world.take_events(), [
+    ///     WorldEvent::ComponentAdded { entity: a, ty: TypeId::of::<i32>() },
+    ///     WorldEvent::Despawned { entity: a },
+    /// ]);
+    /// ```
+    pub fn record_events(&mut self, enabled: bool) {
+        self.events.set_enabled(enabled);
+    }
+
+    /// Remove and return the events recorded since the last call, in the order they occurred
+    ///
+    /// Always empty unless enabled with [`record_events`](Self::record_events).
+    pub fn take_events(&mut self) -> Vec<WorldEvent> {
+        self.events.take()
+    }
+
+    /// Store `value` as the world's unique resource of type `T`, returning the previous one
+    ///
+    /// Resources are singletons, such as the current time or loaded assets, that aren't associated
+    /// with any entity. They're borrow-checked independently of components, so they can be
+    /// accessed while queries are live.
+    ///
+    /// # Example
+    /// ```
+    /// # use hecs::*;
+    /// struct Time(f32);
+    /// let mut world = World::new();
+    /// world.insert_resource(Time(0.0));
+    /// world.spawn((1.0f32,));
+    /// for (_, x) in world.query::<&mut f32>().iter() {
+    ///     world.resource_mut::<Time>().unwrap().0 += *x;
+    /// }
+    /// assert_eq!(world.resource::<Time>().unwrap().0, 1.0);
+    /// ```
+    pub fn insert_resource<T: Component>(&mut self, value: T) -> Option<T> {
+        self.resources.insert(value)
+    }
+
+    /// Remove and return the resource of type `T`, if it exists
+    pub fn remove_resource<T: Component>(&mut self) -> Option<T> {
+        self.resources.remove()
+    }
+
+    /// Whether a resource of type `T` exists
+    pub fn contains_resource<T: Component>(&self) -> bool {
+        self.resources.contains::<T>()
+    }
+
+    /// Borrow the resource of type `T`, if it exists
+    ///
+    /// Panics if the resource is already uniquely borrowed.
+    pub fn resource<T: Component>(&self) -> Option<ResourceRef<'_, T>> {
+        self.resources.get()
+    }
+
+    /// Uniquely borrow the resource of type `T`, if it exists
+    ///
+    /// Panics if the resource is already borrowed.
+    pub fn resource_mut<T: Component>(&self) -> Option<ResourceRefMut<'_, T>> {
+        self.resources.get_mut()
+    }
+
+    /// Convert all reserved entities into empty entities that can be iterated and accessed
+    ///
+    /// Invoked implicitly by `spawn`, `despawn`, `insert`, and `remove`.
+    pub fn flush(&mut self) {
+        let arch = &mut self.archetypes[0];
+        self.entities
+            .flush(|id, location| location.index = unsafe { arch.allocate(id) });
+    }
+
+    /// Choose what happens when an entity ID runs out of generations
+    ///
+    /// See [`GenerationPolicy`]; the default is `Retire`.
+    pub fn set_generation_policy(&mut self, policy: GenerationPolicy) {
+        self.entities.set_policy(policy);
+    }
+
+    /// Summarize how entity IDs have been used, to spot exhaustion of their generations or leaked
+    /// entities before they become a problem
+    pub fn entity_audit(&self) -> EntityAudit {
+        let mut audit = self.entities.audit();
+        let empty = &self.archetypes[0];
+        audit.empty = (0..empty.len())
+            .filter(|&i| !self.sparse.iter().any(|x| x.contains(empty.entity_id(i))))
+            .count() as u32;
+        audit
+    }
+
+    /// Summarize the memory used by the world and its archetypes
+    ///
+    /// Useful for surfacing memory usage in debugging tools, and for detecting fragmentation into
+    /// many sparsely-populated archetypes. Resources are not accounted for.
+    ///
+    /// # Example
+    /// ```
+    /// # use hecs::*;
+    /// let mut world = World::new();
+    /// world.spawn_batch((0..100).map(|i| (i, true)));
+    /// let e = world.spawn((0u8,));
+    /// world.despawn(e).unwrap();
+    /// let stats = world.stats();
+    /// assert_eq!(stats.entities(), 100);
+    /// assert_eq!(stats.empty_archetypes(), 2);
+    /// assert!(stats.allocated_bytes() >= 100 * 5);
+    /// ```
+    pub fn stats(&self) -> WorldStats {
+        WorldStats {
+            archetypes: self
+                .archetypes
+                .iter()
+                .map(|x| ArchetypeStats {
+                    types: x.types().to_vec(),
+                    len: x.len(),
+                    capacity: x.capacity(),
+                    allocated_bytes: x.allocated_bytes(),
+                })
+                .collect(),
+            entity_bytes: self.entities.allocated_bytes(),
+        }
+    }
+
+    /// Inspect the archetypes that entities are organized into
+    ///
+    /// Useful for dynamically scheduling concurrent queries by checking borrows in advance. Does
+    /// not provide access to entities.
+    pub fn archetypes(&self) -> impl ExactSizeIterator<Item = &'_ Archetype> + '_ {
+        self.archetypes.iter()
+    }
+
+    /// The ID of every archetype, in the order of `archetypes`
+    pub fn archetype_ids(&self) -> impl ExactSizeIterator<Item = ArchetypeId> {
+        (0..self.archetypes.len() as u32).map(ArchetypeId)
+    }
+
+    /// The archetype identified by `id`, if it exists
+    pub fn archetype(&self, id: ArchetypeId) -> Option<&Archetype> {
+        self.archetypes.get(id.0 as usize)
+    }
+
+    /// The ID of the archetype currently holding `entity`
+    ///
+    /// # Example
+    /// ```
+    /// # use hecs::*;
+    /// let mut world = World::new();
+    /// let a = world.spawn((1, true));
+    /// let b = world.spawn((2, false));
+    /// let c = world.spawn((3,));
+    /// let id = world.archetype_id(a).unwrap();
+    /// assert_eq!(world.archetype_id(b).unwrap(), id);
+    /// assert_ne!(world.archetype_id(c).unwrap(), id);
+    /// assert!(world.archetype(id).unwrap().has::<bool>());
+    /// ```
+    pub fn archetype_id(&self, entity: Entity) -> Result<ArchetypeId, NoSuchEntity> {
+        Ok(ArchetypeId(self.entities.get(entity)?.archetype))
+    }
+
+    /// Like `query`, but only visits the entities in the archetype identified by `id`
+    ///
+    /// Useful for revisiting just the archetypes known to have changed, e.g. from
+    /// [`take_events`](Self::take_events). Yields nothing if the archetype doesn't exist.
+    ///
+    /// # Example
+    /// ```
+    /// # use hecs::*;
+    /// let mut world = World::new();
+    /// let a = world.spawn((1, true));
+    /// world.spawn((2,));
+    /// let id = world.archetype_id(a).unwrap();
+    /// let entities = world
+    ///     .query_archetype::<&i32>(id)
+    ///     .iter()
+    ///     .map(|(e, _)| e)
+    ///     .collect::<Vec<_>>();
+    /// assert_eq!(entities, [a]);
+    /// ```
+    pub fn query_archetype<Q: Query>(&self, id: ArchetypeId) -> QueryBorrow<'_, Q> {
+        let range = self.archetype_range(id);
+        self.profile::<Q>(&self.archetypes[range.clone()]);
+        QueryBorrow::new(&self.entities.meta, &self.archetypes[range])
+    }
+
+    /// Like `query_mut`, but only visits the entities in the archetype identified by `id`
+    pub fn query_archetype_mut<Q: Query>(&mut self, id: ArchetypeId) -> QueryMut<'_, Q> {
+        let range = self.archetype_range(id);
+        self.profile::<Q>(&self.archetypes[range.clone()]);
+        QueryMut::new(&self.entities.meta, &mut self.archetypes[range])
+    }
+
+    /// Index range of `archetypes` holding at most the archetype identified by `id`
+    fn archetype_range(&self, id: ArchetypeId) -> Range<usize> {
+        let start = (id.0 as usize).min(self.archetypes.len());
+        start..(start + 1).min(self.archetypes.len())
+    }
+
+    /// Borrow the `T` components of each archetype that has them, as slices
+    ///
+    /// Each archetype is borrowed as its `Column` is yielded, and released when it's dropped.
+    ///
+    /// # Example
+    /// ```
+    /// # use hecs::*;
+    /// let mut world = World::new();
+    /// let a = world.spawn((1.0f32,));
+    /// let b = world.spawn((2.0f32, true));
+    /// let mut sum = 0.0;
+    /// for column in world.columns::<f32>() {
+    ///     sum += column.iter().sum::<f32>();
+    ///     assert_eq!(column.entities().len(), column.len());
+    /// }
+    /// assert_eq!(sum, 3.0);
+    /// ```
+    pub fn columns<T: Component>(&self) -> impl Iterator<Item = Column<'_, T>> + '_ {
+        let meta = &self.entities.meta;
+        self.archetypes
+            .iter()
+            .filter(|x| !x.is_empty())
+            .filter_map(move |x| Column::new(meta, x))
+    }
+
+    /// Uniquely borrow the `T` components of each archetype that has them, as slices
+    ///
+    /// See `columns`. Every `T` is marked as changed.
+    pub fn columns_mut<T: Component>(&self) -> impl Iterator<Item = ColumnMut<'_, T>> + '_ {
+        let meta = &self.entities.meta;
+        self.archetypes
+            .iter()
+            .filter(|x| !x.is_empty())
+            .filter_map(move |x| ColumnMut::new(meta, x))
+    }
+
+    /// Returns a distinct value after `archetypes` is changed
+    ///
+    /// Store the current value after deriving information from `archetypes`, then check whether the
+    /// value returned by this function differs before attempting an operation that relies on its
+    /// correctness. Useful for determining whether e.g. a concurrent query execution plan is still
+    /// correct.
+    ///
+    /// The generation may be, but is not necessarily, changed as a result of adding or removing any
+    /// entity or component.
+    ///
+    /// # Example
+    /// ```
+    /// # use hecs::*;
+    /// let mut world = World::new();
+    /// let initial_gen = world.archetypes_generation();
+    /// world.spawn((123, "abc"));
+    /// assert_ne!(initial_gen, world.archetypes_generation());
+    /// ```
+    pub fn archetypes_generation(&self) -> ArchetypesGeneration {
+        ArchetypesGeneration {
+            world: self.id,
+            archetypes: self.archetypes.len() as u32,
+        }
+    }
+
+    /// The archetypes created since `generation` was obtained from `archetypes_generation`
+    ///
+    /// Lets caches derived from archetypes, such as the archetypes a render pipeline's queries
+    /// match, be refreshed incrementally. Returns `None` if every cache must be rebuilt, because
+    /// archetypes were renumbered by `compact`, existing archetypes became able to hold a type
+    /// newly stored sparsely by `register_sparse` or `pin_components`, or `generation` came from a
+    /// different world.
+    /// Archetypes are otherwise never removed, even once emptied of entities.
+    ///
+    /// # Example
+    /// ```
+    /// # use hecs::*;
+    /// let mut world = World::new();
+    /// world.spawn((123,));
+    /// let generation = world.archetypes_generation();
+    /// world.spawn((456,));
+    /// assert_eq!(world.archetypes_created_since(generation).unwrap().count(), 0);
+    /// let a = world.spawn((789, true));
+    /// let created = world.archetypes_created_since(generation).unwrap();
+    /// assert_eq!(created.collect::<Vec<_>>(), [world.archetype_id(a).unwrap()]);
+    /// world.despawn(a).unwrap();
+    /// world.compact();
+    /// assert!(world.archetypes_created_since(generation).is_none());
+    /// ```
+    pub fn archetypes_created_since(
+        &self,
+        generation: ArchetypesGeneration,
+    ) -> Option<impl ExactSizeIterator<Item = ArchetypeId>> {
+        if generation.world != self.id {
+            return None;
+        }
+        Some((generation.archetypes..self.archetypes.len() as u32).map(ArchetypeId))
+    }
+
+    /// Begin a new period of change detection
+    ///
+    /// `Added` and `Changed` queries match components that were added or mutably accessed since
+    /// the most recent call to this method, or since the world was created if it has never been
+    /// called. Typically called once per frame, after all systems interested in changes have run.
+    /// Also forgets the entities reported by `removed`.
+    ///
+    /// # Example
+    /// ```
+    /// # use hecs::*;
+    /// let mut world = World::new();
+    /// let a = world.spawn((123,));
+    /// assert_eq!(world.query::<Added<i32, ()>>().iter().count(), 1);
+    /// world.clear_trackers();
+    /// assert_eq!(world.query::<Added<i32, ()>>().iter().count(), 0);
+    /// ```
+    pub fn clear_trackers(&mut self) {
+        self.refresh_component_indexes();
+        self.change_tick = self.change_tick.wrapping_add(1);
+        for archetype in &mut self.archetypes {
+            archetype.set_tick(self.change_tick);
+        }
+        for set in self.sparse.iter_mut() {
+            set.set_tick(self.change_tick);
+        }
+        self.events.clear_removals();
+    }
+
+    /// Add `component` to `entity`, to be removed by the `ttl`th following call to `tick`
+    ///
+    /// Suits short-lived markers such as "damaged this frame" or "just spawned". Replaces any
+    /// existing `T`, and any expiry scheduled for it; inserting `T` by other means keeps the
+    /// expiry. A `ttl` of zero is treated as one.
+    ///
+    /// # Example
+    /// ```
+    /// # use hecs::*;
+    /// struct Hit;
+    /// let mut world = World::new();
+    /// let a = world.spawn((123,));
+    /// world.insert_transient(a, Hit, 2).unwrap();
+    /// world.tick();
+    /// assert!(world.satisfies::<&Hit>(a).unwrap());
+    /// world.tick();
+    /// assert!(!world.satisfies::<&Hit>(a).unwrap());
+    /// ```
+    pub fn insert_transient<T: Component>(
+        &mut self,
+        entity: Entity,
+        component: T,
+        ttl: u32,
+    ) -> Result<(), NoSuchEntity> {
+        self.insert_one(entity, component)?;
+        self.transients
+            .schedule((entity, Some(TypeId::of::<T>())), ttl);
+        Ok(())
     }
 
-    /// Returns a distinct value after `archetypes` is changed
+    /// Spawn an entity with `components`, to be despawned by the `ttl`th following call to `tick`
     ///
-    /// Store the current value after deriving information from `archetypes`, then check whether the
-    /// value returned by this function differs before attempting an operation that relies on its
-    /// correctness. Useful for determining whether e.g. a concurrent query execution plan is still
-    /// correct.
+    /// Suits event entities, which are observed by queries for a while and then discarded. A `ttl`
+    /// of zero is treated as one.
+    pub fn spawn_transient(&mut self, components: impl DynamicBundle, ttl: u32) -> Entity {
+        let entity = self.spawn(components);
+        self.transients.schedule((entity, None), ttl);
+        entity
+    }
+
+    /// Maintain a [`Prev<T>`](crate::Prev) alongside every `T` component, updated by each call to `tick`
     ///
-    /// The generation may be, but is not necessarily, changed as a result of adding or removing any
-    /// entity or component.
+    /// Lets systems read the value a component had at the end of the previous frame, e.g. to
+    /// interpolate or estimate velocities, without cloning it by hand. Does nothing if `T` is
+    /// already registered.
     ///
     /// # Example
     /// ```
     /// # use hecs::*;
     /// let mut world = World::new();
-    /// let initial_gen = world.archetypes_generation();
-    /// world.spawn((123, "abc"));
-    /// assert_ne!(initial_gen, world.archetypes_generation());
+    /// world.register_double_buffered::<i32>();
+    /// let a = world.spawn((1,));
+    /// world.tick();
+    /// *world.get_mut::<i32>(a).unwrap() = 2;
+    /// assert_eq!(world.get::<Prev<i32>>(a).unwrap().0, 1);
+    /// world.tick();
+    /// assert_eq!(world.get::<Prev<i32>>(a).unwrap().0, 2);
     /// ```
-    pub fn archetypes_generation(&self) -> ArchetypesGeneration {
-        ArchetypesGeneration(self.archetype_generation)
+    pub fn register_double_buffered<T: Component + Clone>(&mut self) {
+        let ty = TypeId::of::<T>();
+        if self.double_buffered.iter().all(|&(x, _)| x != ty) {
+            self.double_buffered.push((ty, double_buffer::flip::<T>));
+        }
+    }
+
+    /// Advance the clock of `insert_transient` and `spawn_transient`, removing whatever expires
+    ///
+    /// Typically called once per frame. First updates the `Prev` components of types registered
+    /// with `register_double_buffered`. Components and entities that were already removed by
+    /// other means are skipped. Independent of `clear_trackers`.
+    pub fn tick(&mut self) {
+        for i in 0..self.double_buffered.len() {
+            (self.double_buffered[i].1)(self);
+        }
+        for (entity, ty) in self.transients.advance() {
+            // Already removed by other means if these fail
+            let _ = match ty {
+                Some(ty) => self.remove_dynamic(entity, ty),
+                None => self.despawn(entity),
+            };
+        }
+    }
+
+    /// The tick currently stamped onto components as they're added or mutably accessed
+    ///
+    /// Advanced by `clear_trackers`.
+    pub fn change_tick(&self) -> u32 {
+        self.change_tick
+    }
+
+    #[inline]
+    /// Statistics on the queries run against this world
+    ///
+    /// Requires the `profile` feature. See [`WorldProfiler`].
+    #[cfg(feature = "profile")]
+    pub fn profiler(&self) -> &WorldProfiler {
+        &self.profiler
+    }
+
+    /// Record the construction of a `Q` query over `archetypes` if profiling is enabled
+    #[cfg(feature = "profile")]
+    pub(crate) fn profile<Q: Query>(&self, archetypes: &[Archetype]) {
+        self.profiler.record::<Q>(archetypes);
+    }
+
+    #[cfg(not(feature = "profile"))]
+    #[inline(always)]
+    #[allow(clippy::extra_unused_type_parameters)]
+    pub(crate) fn profile<Q: Query>(&self, _: &[Archetype]) {}
+
+    pub(crate) fn id(&self) -> u64 {
+        self.id
+    }
+
+    #[inline]
+    pub(crate) fn entities_meta(&self) -> &[EntityMeta] {
+        &self.entities.meta
+    }
+
+    #[inline]
+    pub(crate) fn archetypes_inner(&self) -> &[Archetype] {
+        &self.archetypes
     }
 
     /// Number of currently live entities
@@ -741,6 +4649,166 @@ impl From<MissingComponent> for ComponentError {
     }
 }
 
+/// Errors that arise when borrowing components without panicking on conflict
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum BorrowError {
+    /// The entity was already despawned
+    NoSuchEntity,
+    /// The entity did not have the requested component
+    MissingComponent(MissingComponent),
+    /// The named component type was already borrowed in a conflicting way
+    Conflict(&'static str),
+}
+
+impl BorrowError {
+    fn conflict<T: Component>() -> Self {
+        BorrowError::Conflict(type_name::<T>())
+    }
+}
+
+#[cfg(feature = "std")]
+impl Error for BorrowError {}
+
+impl fmt::Display for BorrowError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use BorrowError::*;
+        match *self {
+            NoSuchEntity => f.write_str("no such entity"),
+            MissingComponent(ref x) => x.fmt(f),
+            Conflict(ty) => write!(f, "{} already borrowed", ty),
+        }
+    }
+}
+
+impl From<NoSuchEntity> for BorrowError {
+    fn from(NoSuchEntity: NoSuchEntity) -> Self {
+        BorrowError::NoSuchEntity
+    }
+}
+
+impl From<MissingComponent> for BorrowError {
+    fn from(x: MissingComponent) -> Self {
+        BorrowError::MissingComponent(x)
+    }
+}
+
+impl From<ComponentError> for BorrowError {
+    fn from(x: ComponentError) -> Self {
+        match x {
+            ComponentError::NoSuchEntity => BorrowError::NoSuchEntity,
+            ComponentError::MissingComponent(x) => BorrowError::MissingComponent(x),
+        }
+    }
+}
+
+/// A value distinct from the `World::id` of every world that exists
+fn next_world_id() -> u64 {
+    #[cfg(target_has_atomic = "64")]
+    {
+        static ID: AtomicU64 = AtomicU64::new(1);
+        ID.fetch_add(1, Ordering::Relaxed)
+    }
+    // Targets without 64-bit atomics only repeat an ID after billions of worlds
+    #[cfg(not(target_has_atomic = "64"))]
+    {
+        static ID: AtomicUsize = AtomicUsize::new(1);
+        ID.fetch_add(1, Ordering::Relaxed) as u64
+    }
+}
+
+/// Update the locations of the entities moved within `archetype` by removing one from `index`,
+/// given the ID of the entity that took its place
+fn relocate(meta: &mut [EntityMeta], archetype: &Archetype, moved: EntityId, index: u32) {
+    if !archetype.preserves_order() {
+        meta[moved as usize].location.index = index;
+        return;
+    }
+    for row in index..archetype.len() {
+        meta[archetype.entity_id(row) as usize].location.index = row;
+    }
+}
+
+/// Invoke the hooks in `hooks` for the components of the entity at `index` of `archetype` whose
+/// types satisfy `filter`
+fn call_remove_hooks(
+    hooks: &TypeIdMap<RemoveHook>,
+    archetype: &Archetype,
+    entity: Entity,
+    index: u32,
+    filter: impl Fn(TypeId) -> bool,
+) {
+    if hooks.is_empty() {
+        return;
+    }
+    for ty in archetype.types() {
+        if let Some(hook) = hooks.get(&ty.id()).filter(|_| filter(ty.id())) {
+            unsafe {
+                let ptr = archetype
+                    .get_dynamic(ty.id(), ty.layout().size(), index)
+                    .unwrap();
+                hook(entity, ptr.as_ptr());
+            }
+        }
+    }
+}
+
+/// Types of the components of `entity`, which is in `archetype`, including sparse ones
+fn entity_types<'a>(
+    archetype: &'a Archetype,
+    sparse: &'a SparseSets,
+    entity: Entity,
+) -> impl Iterator<Item = TypeId> + 'a {
+    archetype.types().iter().map(|x| x.id()).chain(
+        sparse
+            .iter()
+            .filter(move |x| x.contains(entity.id))
+            .map(|x| x.type_info().id()),
+    )
+}
+
+/// Like `call_remove_hooks`, for the sparsely stored components of `entity`
+fn call_sparse_remove_hooks(
+    hooks: &TypeIdMap<RemoveHook>,
+    sparse: &SparseSets,
+    entity: Entity,
+    filter: impl Fn(TypeId) -> bool,
+) {
+    if hooks.is_empty() {
+        return;
+    }
+    for set in sparse.iter() {
+        let ty = set.type_info().id();
+        if let Some(hook) = hooks.get(&ty).filter(|_| filter(ty)) {
+            if let Some(row) = set.row(entity.id) {
+                unsafe {
+                    hook(entity, set.component(row).as_ptr());
+                }
+            }
+        }
+    }
+}
+
+/// Whether the entity at `index` of `archetype` would be yielded by a query for `Q`
+///
+/// # Safety
+///
+/// `index` must be in bounds, unless `archetype` stores no components.
+pub(crate) unsafe fn satisfies<Q: Query>(archetype: &Archetype, index: u32) -> bool {
+    match Q::Fetch::new(archetype) {
+        Some(fetch) => !fetch.should_skip(index as usize),
+        None => false,
+    }
+}
+
+fn assert_distinct(entities: &[Entity]) {
+    for (i, a) in entities.iter().enumerate() {
+        assert!(
+            entities[..i].iter().all(|b| a != b),
+            "entities must be distinct"
+        );
+    }
+}
+
 /// Errors that arise when querying a single entity
 #[derive(Debug, Clone, Eq, PartialEq, Hash)]
 pub enum QueryOneError {
@@ -776,6 +4844,141 @@ impl From<NoSuchEntity> for QueryOneError {
 pub trait Component: Send + Sync + 'static {}
 impl<T: Send + Sync + 'static> Component for T {}
 
+/// A query that may be iterated, or queried by entity, any number of times
+///
+/// Obtained from [`World::query_nested_mut`]. Each of its items borrows the `QueryLookup`, so
+/// only one may be held at a time.
+pub struct QueryLookup<'w, Q: Query> {
+    world: &'w World,
+    _marker: PhantomData<Q>,
+}
+
+impl<'w, Q: Query> QueryLookup<'w, Q> {
+    /// Iterate over every matching entity
+    pub fn iter(&mut self) -> QueryIter<'_, Q> {
+        unsafe { QueryIter::new(&self.world.entities.meta, &self.world.archetypes) }
+    }
+
+    /// Query a single entity
+    ///
+    /// See `World::query_one_mut`.
+    pub fn get(&mut self, entity: Entity) -> Result<QueryItem<'_, Q>, QueryOneError> {
+        unsafe { self.world.query_one_unchecked::<Q>(entity) }
+    }
+}
+
+unsafe impl<'w, Q: Query + Send> Send for QueryLookup<'w, Q> {}
+unsafe impl<'w, Q: Query + Sync> Sync for QueryLookup<'w, Q> {}
+
+/// Unique access to a single entity of a `World`
+///
+/// Constructed by [`World::entity_mut`] or [`World::spawn_empty`]. Methods that add or remove
+/// components return `&mut Self` or the removed components, so that a complex entity can be set
+/// up in one chain of calls.
+pub struct EntityMut<'w> {
+    world: &'w mut World,
+    entity: Entity,
+    /// Kept up to date as the entity moves between archetypes
+    location: Location,
+}
+
+impl<'w> EntityMut<'w> {
+    /// The entity's handle
+    pub fn id(&self) -> Entity {
+        self.entity
+    }
+
+    /// Access the world containing the entity
+    ///
+    /// Borrows `self` uniquely, so that components borrowed with `get` can't alias those
+    /// reached through the world:
+    /// ```compile_fail
+    /// # use hecs::*;
+    /// let mut world = World::new();
+    /// let mut entity = world.spawn_empty();
+    /// entity.insert_one(1);
+    /// let e = entity.id();
+    /// let x = entity.get::<i32>().unwrap();
+    /// *entity.world().get_mut::<i32>(e).unwrap() = 5;
+    /// assert_eq!(*x, 1);
+    /// ```
+    pub fn world(&mut self) -> &World {
+        self.world
+    }
+
+    /// Whether the entity has a `T` component
+    pub fn has<T: Component>(&self) -> bool {
+        self.get::<T>().is_some()
+    }
+
+    /// Access the entity's `T` component, if it has one
+    pub fn get<T: Component>(&self) -> Option<&T> {
+        unsafe { self.fetch::<&T>() }
+    }
+
+    /// Uniquely access the entity's `T` component, if it has one
+    pub fn get_mut<T: Component>(&mut self) -> Option<&mut T> {
+        // Safe because the world, and hence the component, is uniquely borrowed
+        unsafe { self.fetch::<&mut T>() }
+    }
+
+    /// Query the entity for `Q`, returning `None` if it doesn't satisfy it
+    pub fn query<Q: Query>(&mut self) -> Option<QueryItem<'_, Q>> {
+        unsafe { self.fetch::<Q>() }
+    }
+
+    /// Fetch `Q` without checking for borrow conflicts, which must be upheld by the caller
+    unsafe fn fetch<Q: Query>(&self) -> Option<QueryItem<'_, Q>> {
+        let archetype = &self.world.archetypes[self.location.archetype as usize];
+        let fetch = Q::Fetch::new(archetype)?;
+        if fetch.should_skip(self.location.index as usize) {
+            return None;
+        }
+        Some(fetch.get(self.location.index as usize))
+    }
+
+    /// Add `components` to the entity, replacing any existing components of the same types
+    ///
+    /// See [`World::insert`].
+    pub fn insert(&mut self, components: impl DynamicBundle) -> &mut Self {
+        self.world.insert(self.entity, components).unwrap();
+        self.relocate();
+        self
+    }
+
+    /// Add `component` to the entity, replacing any existing component of the same type
+    pub fn insert_one(&mut self, component: impl Component) -> &mut Self {
+        self.insert((component,))
+    }
+
+    /// Remove components from the entity
+    ///
+    /// See [`World::remove`].
+    pub fn remove<T: Bundle>(&mut self) -> Result<T, MissingComponent> {
+        let result = self.world.remove::<T>(self.entity);
+        self.relocate();
+        result.map_err(|e| match e {
+            ComponentError::MissingComponent(e) => e,
+            ComponentError::NoSuchEntity => unreachable!(),
+        })
+    }
+
+    /// Remove the `T` component from the entity
+    pub fn remove_one<T: Component>(&mut self) -> Result<T, MissingComponent> {
+        self.remove::<(T,)>().map(|(x,)| x)
+    }
+
+    /// Despawn the entity
+    pub fn despawn(self) {
+        self.world.despawn(self.entity).unwrap();
+    }
+
+    /// Refresh the cached location after the entity may have moved
+    fn relocate(&mut self) {
+        self.location = self.world.entities.meta[self.entity.id as usize].location;
+    }
+}
+
 /// Iterator over all of a world's entities
 pub struct Iter<'a> {
     archetypes: core::slice::Iter<'a, Archetype>,
@@ -808,7 +5011,7 @@ impl<'a> Iterator for Iter<'a> {
                     self.index = 0;
                 }
                 Some(current) => {
-                    if self.index == current.len() as u32 {
+                    if self.index == current.len() {
                         self.current = None;
                         continue;
                     }
@@ -858,71 +5061,240 @@ impl<A: DynamicBundle> core::iter::FromIterator<A> for World {
     }
 }
 
+/// Identifies an archetype of a `World`
+///
+/// Obtained from [`World::archetype_id`] or [`World::archetype_ids`]. Valid until the world's
+/// archetypes are renumbered by [`World::compact`].
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct ArchetypeId(u32);
+
 /// Determines freshness of information derived from `World::archetypes`
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
-pub struct ArchetypesGeneration(u64);
-
-/// Entity IDs created by `World::spawn_batch`
-pub struct SpawnBatchIter<'a, I>
-where
-    I: Iterator,
-    I::Item: Bundle,
-{
-    inner: I,
-    entities: &'a mut Entities,
-    archetype_id: u32,
-    archetype: &'a mut Archetype,
-}
-
-impl<I> Drop for SpawnBatchIter<'_, I>
-where
-    I: Iterator,
-    I::Item: Bundle,
-{
-    fn drop(&mut self) {
-        for _ in self {}
-    }
-}
-
-impl<I> Iterator for SpawnBatchIter<'_, I>
-where
-    I: Iterator,
-    I::Item: Bundle,
-{
+pub struct ArchetypesGeneration {
+    world: u64,
+    /// Number of archetypes, which only grows until the world is compacted
+    archetypes: u32,
+}
+
+/// Memory usage of a `World`, as reported by [`World::stats`]
+#[derive(Debug, Clone)]
+pub struct WorldStats {
+    archetypes: Vec<ArchetypeStats>,
+    entity_bytes: usize,
+}
+
+impl WorldStats {
+    /// Statistics for each archetype, in the order of `World::archetypes`
+    pub fn archetypes(&self) -> &[ArchetypeStats] {
+        &self.archetypes
+    }
+
+    /// Number of entities stored in archetypes
+    ///
+    /// Excludes reserved entities that haven't yet been flushed.
+    pub fn entities(&self) -> u32 {
+        self.archetypes.iter().map(|x| x.len).sum()
+    }
+
+    /// Total number of entities that archetypes may store without reallocating
+    pub fn capacity(&self) -> u32 {
+        self.archetypes.iter().map(|x| x.capacity).sum()
+    }
+
+    /// Number of archetypes containing no entities
+    pub fn empty_archetypes(&self) -> usize {
+        self.archetypes.iter().filter(|x| x.len == 0).count()
+    }
+
+    /// Bytes of heap memory used to track entities, excluding their components
+    pub fn entity_bytes(&self) -> usize {
+        self.entity_bytes
+    }
+
+    /// Total bytes of heap memory used by archetypes and entity tracking
+    pub fn allocated_bytes(&self) -> usize {
+        self.entity_bytes
+            + self
+                .archetypes
+                .iter()
+                .map(|x| x.allocated_bytes)
+                .sum::<usize>()
+    }
+}
+
+/// Memory usage of a single archetype, as reported by [`World::stats`]
+#[derive(Debug, Clone)]
+pub struct ArchetypeStats {
+    types: Vec<TypeInfo>,
+    len: u32,
+    capacity: u32,
+    allocated_bytes: usize,
+}
+
+impl ArchetypeStats {
+    /// Types of the components stored in the archetype
+    pub fn types(&self) -> &[TypeInfo] {
+        &self.types
+    }
+
+    /// Number of entities stored
+    pub fn len(&self) -> u32 {
+        self.len
+    }
+
+    /// Whether no entities are stored
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Number of entities that may be stored without reallocating
+    pub fn capacity(&self) -> u32 {
+        self.capacity
+    }
+
+    /// Bytes of heap memory used to store entities and their components
+    pub fn allocated_bytes(&self) -> usize {
+        self.allocated_bytes
+    }
+}
+
+/// Mapping from the handles of entities in a merged `World` to their new handles
+///
+/// Returned by [`World::merge`], and may also be constructed by hand for custom loading schemes.
+/// Components containing handles can be updated through a map with [`MapEntities`](crate::MapEntities).
+#[derive(Debug, Clone, Default)]
+pub struct EntityMap {
+    map: HashMap<Entity, Entity>,
+}
+
+impl EntityMap {
+    /// Create an empty map
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record that `old` is now identified by `new`, returning its previous mapping, if any
+    pub fn insert(&mut self, old: Entity, new: Entity) -> Option<Entity> {
+        self.map.insert(old, new)
+    }
+
+    /// The new handle of the entity formerly identified by `old`, if any
+    pub fn get(&self, old: Entity) -> Option<Entity> {
+        self.map.get(&old).copied()
+    }
+
+    /// Number of entities moved
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    /// Whether no entities were moved
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// Iterate over `(old, new)` handle pairs, in no particular order
+    pub fn iter(&self) -> impl ExactSizeIterator<Item = (Entity, Entity)> + '_ {
+        self.map.iter().map(|(&old, &new)| (old, new))
+    }
+}
+
+/// Entities created by `World::spawn_column_batch`
+pub struct SpawnColumnBatchIter<'a> {
+    meta: &'a [EntityMeta],
+    archetype: &'a Archetype,
+    range: Range<u32>,
+}
+
+impl Iterator for SpawnColumnBatchIter<'_> {
     type Item = Entity;
 
     fn next(&mut self) -> Option<Entity> {
-        let components = self.inner.next()?;
-        let entity = self.entities.alloc();
-        unsafe {
-            let index = self.archetype.allocate(entity.id);
-            components.put(|ptr, ty| {
-                self.archetype
-                    .put_dynamic(ptr, ty.id(), ty.layout().size(), index);
-            });
-            self.entities.meta[entity.id as usize].location = Location {
-                archetype: self.archetype_id,
-                index,
-            };
-        }
-        Some(entity)
+        let id = self.archetype.entity_id(self.range.next()?);
+        Some(Entity {
+            id,
+            generation: self.meta[id as usize].generation,
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.range.size_hint()
+    }
+}
+
+impl ExactSizeIterator for SpawnColumnBatchIter<'_> {
+    fn len(&self) -> usize {
+        self.range.len()
+    }
+}
+
+impl DoubleEndedIterator for SpawnColumnBatchIter<'_> {
+    fn next_back(&mut self) -> Option<Entity> {
+        let id = self.archetype.entity_id(self.range.next_back()?);
+        Some(Entity {
+            id,
+            generation: self.meta[id as usize].generation,
+        })
+    }
+}
+
+impl FusedIterator for SpawnColumnBatchIter<'_> {}
+
+/// Handles of the entities created by `World::spawn_batch`, in the order they were spawned
+///
+/// Owns the handles, so may be kept while the `World` is modified further.
+#[derive(Debug, Clone)]
+pub struct SpawnedEntities {
+    inner: vec::IntoIter<Entity>,
+}
+
+impl SpawnedEntities {
+    /// The handles not yet iterated over
+    pub fn as_slice(&self) -> &[Entity] {
+        self.inner.as_slice()
+    }
+
+    /// Convert into a `Vec` of the handles not yet iterated over, without copying them
+    pub fn into_vec(self) -> Vec<Entity> {
+        self.inner.collect()
+    }
+}
+
+impl Iterator for SpawnedEntities {
+    type Item = Entity;
+
+    #[inline]
+    fn next(&mut self) -> Option<Entity> {
+        self.inner.next()
     }
 
+    #[inline]
     fn size_hint(&self) -> (usize, Option<usize>) {
         self.inner.size_hint()
     }
 }
 
-impl<I, T> ExactSizeIterator for SpawnBatchIter<'_, I>
-where
-    I: ExactSizeIterator<Item = T>,
-    T: Bundle,
-{
+impl DoubleEndedIterator for SpawnedEntities {
+    #[inline]
+    fn next_back(&mut self) -> Option<Entity> {
+        self.inner.next_back()
+    }
+}
+
+impl ExactSizeIterator for SpawnedEntities {
     fn len(&self) -> usize {
         self.inner.len()
     }
 }
 
+impl FusedIterator for SpawnedEntities {}
+
+impl From<SpawnedEntities> for Vec<Entity> {
+    fn from(x: SpawnedEntities) -> Self {
+        x.into_vec()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -0,0 +1,205 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use core::marker::PhantomData;
+
+use crate::{
+    Component, ComponentError, Entity, Query, QueryAccess, QueryItem, QueryMut, QueryOneError,
+    ResourceRef, World,
+};
+
+/// A uniquely borrowed `World` that may be accessed from many places at once, without dynamic
+/// borrow checking
+///
+/// Obtained from [`World::as_unsafe_cell`]. Copies may be handed to other threads, e.g. by a
+/// scheduler that has already established with [`QueryAccess`](crate::QueryAccess) that the
+/// systems it runs concurrently don't conflict. Entities can't be spawned or despawned, nor
+/// components added or removed, through this handle.
+///
+/// # Example
+/// ```
+/// # use hecs::*;
+/// let mut world = World::new();
+/// let a = world.spawn((1, 2.0_f32));
+/// let cell = world.as_unsafe_cell();
+/// std::thread::scope(|s| {
+///     // Safe because the two threads access different component types
+///     s.spawn(move || {
+///         for (_, x) in unsafe { cell.query::<&mut i32>() } {
+///             *x += 1;
+///         }
+///     });
+///     s.spawn(move || {
+///         *unsafe { cell.get_mut::<f32>(a) }.unwrap() *= 2.0;
+///     });
+/// });
+/// assert_eq!(*world.get::<i32>(a).unwrap(), 2);
+/// assert_eq!(*world.get::<f32>(a).unwrap(), 4.0);
+/// ```
+#[derive(Copy, Clone)]
+pub struct UnsafeWorldCell<'w> {
+    world: &'w World,
+    _marker: PhantomData<&'w mut World>,
+}
+
+impl<'w> UnsafeWorldCell<'w> {
+    pub(crate) fn new(world: &'w mut World) -> Self {
+        Self {
+            world,
+            _marker: PhantomData,
+        }
+    }
+
+    /// The world, for access that's borrow checked dynamically regardless
+    #[cfg(feature = "parallel")]
+    pub(crate) fn world(self) -> &'w World {
+        self.world
+    }
+
+    /// Query for `Q` without borrow checks
+    ///
+    /// # Safety
+    ///
+    /// While the returned `QueryMut` or any item it yields is live, no other access made through
+    /// this cell may borrow uniquely a component type `Q` borrows, or borrow at all a component
+    /// type `Q` borrows uniquely.
+    pub unsafe fn query<Q: Query>(self) -> QueryMut<'w, Q> {
+        QueryMut::new_unchecked(self.world.entities_meta(), self.world.archetypes_inner())
+    }
+
+    /// Query a single entity for `Q` without borrow checks
+    ///
+    /// # Safety
+    ///
+    /// As for [`query`](Self::query), restricted to `entity`'s components.
+    pub unsafe fn query_one<Q: Query>(
+        self,
+        entity: Entity,
+    ) -> Result<QueryItem<'w, Q>, QueryOneError> {
+        self.world.query_one_unchecked::<Q>(entity)
+    }
+
+    /// Borrow the `T` component of `entity` without borrow checks
+    ///
+    /// # Safety
+    ///
+    /// No unique borrow of the same component of `entity` may be live simultaneous to the
+    /// returned reference.
+    pub unsafe fn get<T: Component>(self, entity: Entity) -> Result<&'w T, ComponentError> {
+        self.world.get_unchecked(entity)
+    }
+
+    /// Uniquely borrow the `T` component of `entity` without borrow checks
+    ///
+    /// # Safety
+    ///
+    /// No other borrow of the same component of `entity` may be live simultaneous to the
+    /// returned reference.
+    pub unsafe fn get_mut<T: Component>(self, entity: Entity) -> Result<&'w mut T, ComponentError> {
+        self.world.get_unchecked_mut(entity)
+    }
+
+    /// Whether `entity` still exists
+    pub fn contains(self, entity: Entity) -> bool {
+        self.world.contains(entity)
+    }
+}
+
+unsafe impl Send for UnsafeWorldCell<'_> {}
+unsafe impl Sync for UnsafeWorldCell<'_> {}
+
+/// A uniquely borrowed `World` that any number of threads may read from at once
+///
+/// Obtained from [`World::read_view`]. Since nothing can modify the world while a view exists,
+/// queries made through one skip dynamic borrow checks, so readers on different threads never
+/// contend over borrow flags nor fail to borrow. Only queries that don't borrow uniquely are
+/// permitted.
+///
+/// # Example
+/// ```
+/// # use hecs::*;
+/// let mut world = World::new();
+/// let a = world.spawn((1, 2.0_f32));
+/// let view = world.read_view();
+/// std::thread::scope(|s| {
+///     s.spawn(move || assert_eq!(view.query::<&i32>().into_iter().count(), 1));
+///     s.spawn(move || assert_eq!(*view.get::<f32>(a).unwrap(), 2.0));
+/// });
+/// ```
+#[derive(Copy, Clone)]
+pub struct WorldView<'w> {
+    world: &'w World,
+    _marker: PhantomData<&'w mut World>,
+}
+
+impl<'w> WorldView<'w> {
+    pub(crate) fn new(world: &'w mut World) -> Self {
+        world.flush();
+        Self {
+            world,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Query for `Q`
+    ///
+    /// # Panics
+    ///
+    /// Panics if `Q` borrows any component uniquely.
+    pub fn query<Q: Query>(self) -> QueryMut<'w, Q> {
+        Self::assert_shared::<Q>();
+        // Safe because nothing may borrow uniquely while `self` lives
+        unsafe {
+            QueryMut::new_unchecked(self.world.entities_meta(), self.world.archetypes_inner())
+        }
+    }
+
+    /// Query a single entity for `Q`
+    ///
+    /// # Panics
+    ///
+    /// Panics if `Q` borrows any component uniquely.
+    pub fn query_one<Q: Query>(self, entity: Entity) -> Result<QueryItem<'w, Q>, QueryOneError> {
+        Self::assert_shared::<Q>();
+        unsafe { self.world.query_one_unchecked::<Q>(entity) }
+    }
+
+    /// Borrow the `T` component of `entity`
+    pub fn get<T: Component>(self, entity: Entity) -> Result<&'w T, ComponentError> {
+        unsafe { self.world.get_unchecked(entity) }
+    }
+
+    /// Borrow the resource of type `T`, if it exists
+    pub fn resource<T: Component>(self) -> Option<ResourceRef<'w, T>> {
+        self.world.resource()
+    }
+
+    /// Whether `entity` still exists
+    pub fn contains(self, entity: Entity) -> bool {
+        self.world.contains(entity)
+    }
+
+    /// Number of currently live entities
+    pub fn len(self) -> u32 {
+        self.world.len()
+    }
+
+    /// Whether no entities are live
+    pub fn is_empty(self) -> bool {
+        self.world.is_empty()
+    }
+
+    fn assert_shared<Q: Query>() {
+        assert!(
+            QueryAccess::of::<Q>().writes().is_empty(),
+            "queries through a WorldView must not borrow uniquely"
+        );
+    }
+}
+
+unsafe impl Send for WorldView<'_> {}
+unsafe impl Sync for WorldView<'_> {}
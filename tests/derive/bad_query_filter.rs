@@ -0,0 +1,9 @@
+use hecs::Query;
+
+#[derive(Query)]
+#[query(within = "bool")]
+struct Foo<'a> {
+    foo: &'a i32,
+}
+
+fn main() {}
@@ -0,0 +1,8 @@
+#[derive(hecs::StableComponent)]
+struct Position([f32; 2]);
+
+#[derive(hecs::StableComponent)]
+#[stable_id("game::Wrapper")]
+struct Wrapper<T>(T);
+
+fn main() {}
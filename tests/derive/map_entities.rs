@@ -0,0 +1,75 @@
+use hecs::{Entity, MapEntities, World};
+
+#[derive(MapEntities)]
+struct Follow {
+    target: Entity,
+    #[map_entities(skip)]
+    distance: f32,
+}
+
+#[derive(MapEntities)]
+struct Targets(Vec<Entity>, Option<Entity>);
+
+#[derive(MapEntities)]
+enum Goal {
+    Idle,
+    Chase(Entity),
+    Guard {
+        #[map_entities(skip)]
+        radius: f32,
+        post: Entity,
+    },
+}
+
+#[derive(MapEntities)]
+struct Wrapper<T> {
+    inner: T,
+}
+
+fn main() {
+    let mut world = World::new();
+    let mut chunk = World::new();
+    let a = chunk.spawn(());
+    let b = chunk.spawn((
+        Follow {
+            target: a,
+            distance: 2.0,
+        },
+        Targets(vec![a], Some(a)),
+        Goal::Guard {
+            radius: 1.0,
+            post: a,
+        },
+        Wrapper { inner: Goal::Chase(a) },
+    ));
+    let c = chunk.spawn((Goal::Idle,));
+    let map = world.merge(chunk);
+    world.map_entities::<Follow>(&map);
+    world.map_entities::<Targets>(&map);
+    world.map_entities::<Goal>(&map);
+    world.map_entities::<Wrapper<Goal>>(&map);
+
+    let a = map.get(a).unwrap();
+    let b = map.get(b).unwrap();
+    let follow = world.get::<Follow>(b).unwrap();
+    assert_eq!(follow.target, a);
+    assert_eq!(follow.distance, 2.0);
+    let targets = world.get::<Targets>(b).unwrap();
+    assert_eq!(targets.0, [a]);
+    assert_eq!(targets.1, Some(a));
+    match *world.get::<Goal>(b).unwrap() {
+        Goal::Guard { radius, post } => {
+            assert_eq!(radius, 1.0);
+            assert_eq!(post, a);
+        }
+        _ => unreachable!(),
+    }
+    match world.get::<Wrapper<Goal>>(b).unwrap().inner {
+        Goal::Chase(x) => assert_eq!(x, a),
+        _ => unreachable!(),
+    }
+    assert!(matches!(
+        *world.get::<Goal>(map.get(c).unwrap()).unwrap(),
+        Goal::Idle
+    ));
+}
@@ -0,0 +1,43 @@
+use hecs::{Query, World};
+
+struct Marker;
+struct Hidden;
+
+#[derive(Query)]
+#[query(with = "Marker")]
+#[query(without = "Hidden")]
+struct Visible<'a> {
+    value: &'a mut i32,
+    flag: Option<&'a bool>,
+}
+
+#[derive(Query)]
+#[query(without = "Marker", without = "Hidden")]
+struct Plain<'a>(&'a i32);
+
+fn main() {
+    let mut world = World::new();
+    let a = world.spawn((1, Marker));
+    world.spawn((2, Marker, Hidden));
+    let c = world.spawn((3,));
+    world.spawn((4, Hidden));
+
+    let visible = world
+        .query_mut::<Visible>()
+        .into_iter()
+        .map(|(e, q)| {
+            *q.value += 10;
+            assert!(q.flag.is_none());
+            e
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(visible, [a]);
+    assert_eq!(*world.get::<i32>(a).unwrap(), 11);
+
+    let plain = world
+        .query::<Plain>()
+        .iter()
+        .map(|(e, q)| (e, *q.0))
+        .collect::<Vec<_>>();
+    assert_eq!(plain, [(c, 3)]);
+}
@@ -0,0 +1,35 @@
+use hecs::{StableComponent, StableTypeId, TypeInfo, World};
+
+#[derive(StableComponent)]
+#[stable_id("game::Position")]
+struct Position([f32; 2]);
+
+#[derive(StableComponent)]
+#[stable_id("5f0c3a52-8d5e-4b0e-9a71-2f6d1c0e4b8a")]
+enum Mode {
+    Walk,
+    Run(f32),
+}
+
+
+fn main() {
+    assert_eq!(Position::STABLE_NAME, "game::Position");
+    assert_eq!(
+        Position::STABLE_TYPE_ID,
+        StableTypeId::from_name("game::Position")
+    );
+    assert_ne!(Position::STABLE_TYPE_ID, Mode::STABLE_TYPE_ID);
+    assert_eq!(
+        TypeInfo::of_stable::<Mode>().stable_id(),
+        Some(Mode::STABLE_TYPE_ID)
+    );
+
+    let mut world = World::new();
+    world.register_stable::<Mode>();
+    let e = world.spawn((Position([1.0, 2.0]), Mode::Run(2.0)));
+    let ty = world.stable_type(Mode::STABLE_TYPE_ID).unwrap();
+    assert!(world.entity(e).unwrap().get_raw(ty.id()).is_some());
+    assert!(matches!(*world.get::<Mode>(e).unwrap(), Mode::Run(_)));
+    assert!(!matches!(*world.get::<Mode>(e).unwrap(), Mode::Walk));
+    assert_eq!(world.get::<Position>(e).unwrap().0, [1.0, 2.0]);
+}
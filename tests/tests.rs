@@ -324,6 +324,30 @@ fn clear() {
     assert_eq!(world.iter().count(), 0);
 }
 
+#[test]
+fn clear_reuses_ids() {
+    let mut world = World::new();
+    let a = world.spawn((123,));
+    let b = world.spawn((456, true));
+    let capacity = world.archetypes().map(|x| x.capacity()).sum::<u32>();
+    world.clear();
+    assert!(!world.contains(a));
+    assert!(!world.contains(b));
+    assert_eq!(
+        world.archetypes().map(|x| x.capacity()).sum::<u32>(),
+        capacity
+    );
+    let c = world.spawn((789,));
+    assert_eq!(c.id(), a.id());
+    assert_ne!(c, a);
+    assert!(world.get::<i32>(a).is_err());
+
+    world.spawn_batch((0..100).map(|i| (i, false)));
+    assert!(world.clear_with_capacity_policy(10) > 0);
+    assert!(world.archetypes().all(|x| x.capacity() <= 10));
+    assert!(world.is_empty());
+}
+
 #[test]
 #[should_panic(expected = "twice on the same borrow")]
 fn alias() {
@@ -389,6 +413,30 @@ fn query_batched() {
     assert!(entities.contains(&c));
 }
 
+#[test]
+fn query_mut_batched() {
+    let mut world = World::new();
+    world.spawn_batch((0..10).map(|i| (i,)));
+    world.spawn_batch((10..15).map(|i| (i, true)));
+    let batches = world.query_mut::<&mut i32>().into_iter_batched(4);
+    let sizes = batches
+        .map(|batch| {
+            assert!(batch.size_hint().1.unwrap() <= 4);
+            batch.map(|(_, x)| *x *= 2).count()
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(sizes, [4, 4, 2, 4, 1]);
+    let sum = world.query::<&i32>().iter().map(|(_, &x)| x).sum::<i32>();
+    assert_eq!(sum, 14 * 15);
+}
+
+#[test]
+#[should_panic(expected = "batch size must be nonzero")]
+fn query_batched_zero() {
+    let world = World::new();
+    world.query::<()>().iter_batched(0);
+}
+
 #[test]
 fn spawn_batch() {
     let mut world = World::new();
@@ -435,3 +483,4861 @@ fn duplicate_components_panic() {
     let mut world = World::new();
     world.reserve::<(f32, i64, f32)>(1);
 }
+
+#[test]
+fn command_buffer() {
+    let mut world = World::new();
+    let a = world.spawn(("abc", 123));
+    let b = world.spawn(("def", 456, true));
+    let c = world.reserve_entity();
+
+    let mut cmd = CommandBuffer::new();
+    for (id, &x) in &mut world.query::<&i32>() {
+        if x > 200 {
+            cmd.despawn(id);
+        } else {
+            cmd.remove::<(&str,)>(id);
+            cmd.insert(id, (false,));
+        }
+    }
+    let mut builder = EntityBuilder::new();
+    builder.add(789).add("ghi");
+    cmd.insert(c, builder.build());
+    cmd.spawn((42u8,));
+    cmd.run_on(&mut world);
+
+    assert!(!world.contains(b));
+    assert!(world.get::<&str>(a).is_err());
+    assert!(!*world.get::<bool>(a).unwrap());
+    assert_eq!(*world.get::<i32>(c).unwrap(), 789);
+    assert_eq!(*world.get::<&str>(c).unwrap(), "ghi");
+    assert_eq!(world.query::<&u8>().iter().count(), 1);
+
+    // Components inserted into despawned entities are dropped, and the buffer is reusable
+    let counter = std::sync::Arc::new(());
+    cmd.insert(b, (counter.clone(),));
+    cmd.spawn((counter.clone(),));
+    cmd.run_on(&mut world);
+    assert_eq!(std::sync::Arc::strong_count(&counter), 2);
+    cmd.spawn((counter.clone(),));
+    cmd.clear();
+    drop(cmd);
+    assert_eq!(std::sync::Arc::strong_count(&counter), 2);
+}
+
+#[test]
+fn prepared_query() {
+    let mut world = World::new();
+    world.spawn(("abc", 123));
+    world.spawn(("def", 456, true));
+
+    let mut query = PreparedQuery::<&i32>::new();
+    assert_eq!(query.query(&world).iter().count(), 2);
+
+    // Archetypes created after the first execution are picked up
+    let c = world.spawn((789, 'c'));
+    world.spawn(("ghi",));
+    {
+        let mut borrow = query.query(&world);
+        let entities = borrow.iter().map(|(e, &i)| (e, i)).collect::<Vec<_>>();
+        assert_eq!(entities.len(), 3);
+        assert!(entities.contains(&(c, 789)));
+        // Borrows are held until the `PreparedQueryBorrow` is dropped, so iterating again is fine
+        assert_eq!(borrow.iter().count(), 3);
+    }
+
+    for (_, x) in PreparedQuery::<&mut i32>::new().query_mut(&mut world) {
+        *x += 1;
+    }
+    assert_eq!(*world.get::<i32>(c).unwrap(), 790);
+
+    // Executing against another world discards the cache
+    let mut other = World::new();
+    other.spawn((true,));
+    assert_eq!(query.query(&other).iter().count(), 0);
+    assert_eq!(query.query(&world).iter().count(), 3);
+}
+
+#[test]
+#[should_panic(expected = "already borrowed")]
+fn prepared_query_borrow_conflict() {
+    let mut world = World::new();
+    world.spawn(("abc", 123));
+    let mut query = PreparedQuery::<&mut i32>::new();
+    let _a = query.query(&world);
+    let _b = world.get::<i32>(world.iter().next().unwrap().0);
+}
+
+#[test]
+#[cfg(feature = "parallel")]
+fn par_iter() {
+    use rayon::prelude::*;
+
+    let mut world = World::new();
+    world.spawn_batch((0..10_000).map(|i| (i, "abc")));
+    world.spawn_batch((0..10_000).map(|i| (i, true)));
+    world.spawn_batch((0..100).map(|_| (false,)));
+
+    world
+        .query::<&mut i32>()
+        .par_iter()
+        .for_each(|(_, x)| *x += 1);
+    let sum = world
+        .query::<&i32>()
+        .par_iter()
+        .map(|(_, &x)| i64::from(x))
+        .sum::<i64>();
+    assert_eq!(sum, 2 * (1..=10_000).sum::<i64>());
+
+    let count = world
+        .query_mut::<(&i32, &bool)>()
+        .into_par_iter()
+        .filter(|&(_, (&x, _))| x % 2 == 0)
+        .count();
+    assert_eq!(count, 5_000);
+
+    world
+        .query_mut::<(&mut i32, &bool)>()
+        .par_iter_mut()
+        .for_each(|(_, (x, _))| *x = 0);
+    assert_eq!(
+        world
+            .query_mut::<(&i32, &bool)>()
+            .into_iter()
+            .filter(|(_, (&x, _))| x == 0)
+            .count(),
+        10_000
+    );
+}
+
+#[test]
+fn change_detection() {
+    fn sorted<Q: Query>(world: &World) -> Vec<Entity> {
+        let mut xs = world
+            .query::<Q>()
+            .iter()
+            .map(|(e, _)| e)
+            .collect::<Vec<_>>();
+        xs.sort_by_key(|e| e.id());
+        xs
+    }
+
+    let mut world = World::new();
+    let a = world.spawn((123, true));
+    let b = world.spawn((456,));
+    assert_eq!(sorted::<Added<i32, ()>>(&world), [a, b]);
+    assert_eq!(sorted::<Changed<i32, ()>>(&world), [a, b]);
+
+    world.clear_trackers();
+    assert!(sorted::<Added<i32, ()>>(&world).is_empty());
+    assert!(sorted::<Changed<i32, ()>>(&world).is_empty());
+
+    // Unique borrows that are never dereferenced mutably don't count, but queries do
+    let _ = *world.get_mut::<i32>(a).unwrap();
+    assert!(sorted::<Changed<i32, ()>>(&world).is_empty());
+    for (_, x) in world.query_mut::<&mut i32>().with::<bool>() {
+        *x += 1;
+    }
+    assert_eq!(sorted::<Changed<i32, ()>>(&world), [a]);
+    assert!(sorted::<Changed<bool, ()>>(&world).is_empty());
+
+    // Ticks survive moving between archetypes, and inserted components count as added
+    world.insert_one(b, "abc").unwrap();
+    assert!(sorted::<Added<i32, ()>>(&world).is_empty());
+    assert_eq!(sorted::<Added<&str, ()>>(&world), [b]);
+    world.remove_one::<bool>(a).unwrap();
+    assert_eq!(sorted::<Changed<i32, ()>>(&world), [a]);
+
+    // Filters compose with other queries and apply to single-entity queries
+    assert_eq!(
+        world
+            .query::<(&i32, Option<Changed<i32, &i32>>)>()
+            .iter()
+            .filter(|(_, (_, x))| x.is_some())
+            .count(),
+        1
+    );
+    assert!(world
+        .query_one::<&i32>(a)
+        .unwrap()
+        .changed::<i32>()
+        .get()
+        .is_some());
+    assert!(world
+        .query_one::<&i32>(b)
+        .unwrap()
+        .changed::<i32>()
+        .get()
+        .is_none());
+    assert!(world.query_one_mut::<Added<i32, ()>>(b).is_err());
+
+    world.clear_trackers();
+    assert!(sorted::<Changed<i32, ()>>(&world).is_empty());
+    assert!(sorted::<Added<&str, ()>>(&world).is_empty());
+}
+
+#[test]
+fn spawn_column_batch() {
+    fn batch(values: &[i32]) -> ColumnBatch {
+        let mut ty = ColumnBatchType::new();
+        ty.add::<i32>().add::<bool>();
+        let mut builder = ty.into_batch(values.len() as u32);
+        let mut numbers = builder.writer::<i32>().unwrap();
+        for &x in values {
+            numbers.push(x).unwrap();
+        }
+        assert_eq!(numbers.push(0), Err(0));
+        let mut flags = builder.writer::<bool>().unwrap();
+        for _ in values {
+            flags.push(true).unwrap();
+        }
+        assert!(builder.writer::<&str>().is_none());
+        builder.build().unwrap()
+    }
+
+    let mut world = World::new();
+    let a = world.spawn((0, false));
+    let entities = world
+        .spawn_column_batch(batch(&[1, 2, 3]))
+        .collect::<Vec<_>>();
+    assert_eq!(entities.len(), 3);
+    for (i, &e) in entities.iter().enumerate() {
+        assert_eq!(*world.get::<i32>(e).unwrap(), i as i32 + 1);
+        assert!(*world.get::<bool>(e).unwrap());
+    }
+    assert_eq!(world.query::<(&i32, &bool)>().iter().count(), 4);
+
+    // Existing entities with the same IDs are replaced
+    world.despawn(entities[1]).unwrap();
+    world.spawn_column_batch_at(&[a, entities[1]], batch(&[4, 5]));
+    assert_eq!(*world.get::<i32>(a).unwrap(), 4);
+    assert_eq!(*world.get::<i32>(entities[1]).unwrap(), 5);
+    assert_eq!(*world.get::<i32>(entities[2]).unwrap(), 3);
+    assert_eq!(world.len(), 4);
+
+    let reversed = world
+        .spawn_column_batch(batch(&[6, 7]))
+        .rev()
+        .collect::<Vec<_>>();
+    assert_eq!(*world.get::<i32>(reversed[0]).unwrap(), 7);
+    assert_eq!(*world.get::<i32>(reversed[1]).unwrap(), 6);
+}
+
+#[test]
+fn column_batch_type_duplicates() {
+    let mut ty = ColumnBatchType::new();
+    ty.add::<i32>().add::<bool>().add::<i32>();
+    assert!(ty.has::<i32>());
+    ty.remove::<bool>();
+    assert!(!ty.has::<bool>());
+    let mut builder = ty.into_batch(1);
+    builder.writer::<i32>().unwrap().push(7).unwrap();
+    assert!(builder.writer::<bool>().is_none());
+    let mut world = World::new();
+    let e = world
+        .spawn_column_batch(builder.build().unwrap())
+        .next()
+        .unwrap();
+    assert_eq!(*world.get::<i32>(e).unwrap(), 7);
+    assert!(world.get::<bool>(e).is_err());
+}
+
+#[test]
+fn column_batch_dynamic() {
+    use core::any::TypeId;
+
+    let mut ty = ColumnBatchType::new();
+    ty.add_dynamic(TypeInfo::of::<u16>()).add::<bool>();
+    let mut builder = ty.into_batch(3);
+    let (base, layout, capacity) = builder.storage_for_dynamic(TypeId::of::<u16>()).unwrap();
+    assert_eq!(layout, core::alloc::Layout::new::<u16>());
+    assert_eq!(capacity, 3);
+    for i in 0..3 {
+        unsafe {
+            base.add(i * layout.size())
+                .cast::<u16>()
+                .write(i as u16 * 10);
+        }
+    }
+    unsafe {
+        assert!(builder.set_fill_dynamic(TypeId::of::<u16>(), 3));
+        assert!(!builder.set_fill_dynamic(TypeId::of::<i64>(), 3));
+    }
+    assert!(builder.storage_for_dynamic(TypeId::of::<i64>()).is_none());
+    builder.fill([true; 3]);
+    let mut world = World::new();
+    let entities = world
+        .spawn_column_batch(builder.build().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(*world.get::<u16>(entities[2]).unwrap(), 20);
+}
+
+#[test]
+#[cfg(debug_assertions)]
+#[should_panic(
+    expected = "rows [1..2, 3..5] of the u32 column were accounted for without being written"
+)]
+fn column_batch_dynamic_unwritten() {
+    use core::any::TypeId;
+
+    let mut ty = ColumnBatchType::new();
+    ty.add::<u32>();
+    let mut builder = ty.into_batch(6);
+    builder.fill([7_u32]);
+    let (base, _, _) = builder.storage_for_dynamic(TypeId::of::<u32>()).unwrap();
+    unsafe {
+        base.cast::<u32>().add(2).write(0);
+        base.cast::<u32>().add(5).write(0);
+        builder.set_fill_dynamic(TypeId::of::<u32>(), 6);
+    }
+}
+
+#[test]
+fn column_batch_incomplete() {
+    let counter = std::sync::Arc::new(());
+    let mut ty = ColumnBatchType::new();
+    ty.add::<std::sync::Arc<()>>().add::<bool>();
+    let mut builder = ty.into_batch(2);
+    let mut writer = builder.writer::<std::sync::Arc<()>>().unwrap();
+    writer.push(counter.clone()).unwrap();
+    writer.push(counter.clone()).unwrap();
+    assert_eq!(writer.fill(), 2);
+    builder.writer::<bool>().unwrap().push(true).unwrap();
+    assert!(builder.build().is_err());
+    // Written components are dropped along with the builder
+    assert_eq!(std::sync::Arc::strong_count(&counter), 1);
+}
+
+#[test]
+fn column_batch_fill() {
+    let counter = std::sync::Arc::new(());
+    let mut ty = ColumnBatchType::new();
+    ty.add::<std::sync::Arc<()>>().add::<i32>();
+    let mut builder = ty.into_batch(3);
+    let mut source = 0..;
+    assert_eq!(builder.fill(source.by_ref()), Some(3));
+    // Items beyond the batch's capacity are left in the iterator
+    assert_eq!(source.next(), Some(3));
+    assert_eq!(builder.fill(Some(counter.clone())), Some(1));
+    assert_eq!(builder.fill(Some(counter.clone())), Some(2));
+    assert_eq!(builder.fill(Some(true)), None);
+    let batch = builder.build_truncated();
+    assert_eq!(batch.len(), 2);
+    // The unpaired `i32` is discarded; the other components move into the world
+    let mut world = World::new();
+    let entities = world.spawn_column_batch(batch).collect::<Vec<_>>();
+    assert_eq!(*world.get::<i32>(entities[1]).unwrap(), 1);
+    assert_eq!(std::sync::Arc::strong_count(&counter), 3);
+    world.clear();
+    assert_eq!(std::sync::Arc::strong_count(&counter), 1);
+}
+
+#[test]
+fn runtime_typed_components() {
+    use core::alloc::Layout;
+    use core::any::TypeId;
+    use core::sync::atomic::{AtomicUsize, Ordering};
+
+    static DROPS: AtomicUsize = AtomicUsize::new(0);
+    unsafe fn count_drop(_: *mut u8) {
+        DROPS.fetch_add(1, Ordering::Relaxed);
+    }
+    // Stands in for a type defined by a script
+    struct ScriptType;
+    let ty = unsafe {
+        TypeInfo::from_parts(
+            TypeId::of::<ScriptType>(),
+            Layout::new::<[u32; 2]>(),
+            count_drop,
+        )
+    };
+
+    let mut world = World::new();
+    let mut builder = EntityBuilder::new();
+    let mut value = [1u32, 2];
+    unsafe {
+        builder.add(true).add_raw(value.as_mut_ptr().cast(), ty);
+    }
+    let e = world.spawn(builder.build());
+    assert_eq!(world.query::<&bool>().iter().count(), 1);
+    {
+        let entity = world.entity(e).unwrap();
+        assert!(entity.component_types().any(|x| x == ty.id()));
+        let mut raw = entity.get_raw_mut(ty.id()).unwrap();
+        assert_eq!(raw.type_info().layout(), ty.layout());
+        unsafe {
+            *raw.as_mut_ptr().cast::<u32>().add(1) = 3;
+        }
+    }
+    {
+        let entity = world.entity(e).unwrap();
+        let raw = entity.get_raw(ty.id()).unwrap();
+        assert_eq!(unsafe { *raw.as_ptr().cast::<[u32; 2]>() }, [1, 3]);
+        assert!(entity.get_raw(TypeId::of::<i32>()).is_none());
+    }
+    assert_eq!(DROPS.load(Ordering::Relaxed), 0);
+    world.despawn(e).unwrap();
+    assert_eq!(DROPS.load(Ordering::Relaxed), 1);
+}
+
+#[test]
+fn merge() {
+    let mut world = World::new();
+    let existing = world.spawn((1, "one".to_string()));
+    let mut other = World::new();
+    let a = other.spawn((2, "two".to_string()));
+    let b = other.spawn((true,));
+    let c = other.spawn(());
+    other.despawn(b).unwrap();
+    let d = other.spawn((3, 'x'));
+
+    let map = world.merge(other);
+    assert_eq!(map.len(), 3);
+    assert!(map.get(b).is_none());
+    assert_eq!(world.len(), 4);
+    assert_eq!(*world.get::<String>(existing).unwrap(), "one");
+    let a = map.get(a).unwrap();
+    assert_eq!(*world.get::<i32>(a).unwrap(), 2);
+    assert_eq!(*world.get::<String>(a).unwrap(), "two");
+    let c = map.get(c).unwrap();
+    assert!(world.entity(c).unwrap().component_types().next().is_none());
+    let d = map.get(d).unwrap();
+    assert_eq!(*world.get::<char>(d).unwrap(), 'x');
+
+    let mut ints = world
+        .query::<&i32>()
+        .iter()
+        .map(|(_, &x)| x)
+        .collect::<Vec<_>>();
+    ints.sort_unstable();
+    assert_eq!(ints, [1, 2, 3]);
+    world.despawn(a).unwrap();
+    assert_eq!(*world.get::<String>(existing).unwrap(), "one");
+}
+
+#[test]
+fn hierarchy() {
+    let mut world = World::new();
+    let root = world.spawn(("root",));
+    let a = world.spawn(("a",));
+    let b = world.spawn(("b",));
+    let c = world.spawn(("c",));
+    world.attach(a, root).unwrap();
+    world.attach(b, root).unwrap();
+    world.attach(c, a).unwrap();
+    assert_eq!(world.parent(a), Some(root));
+    assert_eq!(world.parent(root), None);
+    assert_eq!(world.get::<Children>(root).unwrap().as_slice(), &[a, b][..]);
+
+    // Reattaching moves to the end of the new parent's children
+    world.attach(a, b).unwrap();
+    assert_eq!(world.get::<Children>(root).unwrap().as_slice(), &[b][..]);
+    assert_eq!(world.parent(a), Some(b));
+
+    assert_eq!(world.detach(b).unwrap(), Some(root));
+    assert!(world.get::<Children>(root).is_err());
+    assert_eq!(world.detach(b).unwrap(), None);
+    world.attach(b, root).unwrap();
+
+    // Plain despawn orphans children
+    world.despawn(a).unwrap();
+    assert_eq!(world.parent(c), None);
+    assert!(world.get::<Children>(b).is_err());
+
+    world.attach(c, b).unwrap();
+    let other = world.spawn(());
+    world.despawn_recursive(root).unwrap();
+    assert!(!world.contains(b));
+    assert!(!world.contains(c));
+    assert!(world.contains(other));
+    assert_eq!(world.len(), 1);
+}
+
+#[test]
+#[should_panic(expected = "own descendant")]
+fn hierarchy_cycle() {
+    let mut world = World::new();
+    let a = world.spawn(());
+    let b = world.spawn(());
+    world.attach(b, a).unwrap();
+    world.attach(a, b).unwrap();
+}
+
+#[test]
+fn merge_hierarchy() {
+    let mut world = World::new();
+    world.spawn(());
+    let mut other = World::new();
+    let parent = other.spawn(());
+    let child = other.spawn(());
+    other.attach(child, parent).unwrap();
+    let map = world.merge(other);
+    let (parent, child) = (map.get(parent).unwrap(), map.get(child).unwrap());
+    assert_eq!(world.parent(child), Some(parent));
+    assert_eq!(
+        world.get::<Children>(parent).unwrap().as_slice(),
+        &[child][..]
+    );
+}
+
+#[test]
+fn reserve_concurrently() {
+    let mut world = World::new();
+    let freed = world.spawn(());
+    world.despawn(freed).unwrap();
+
+    let reserved = std::thread::scope(|s| {
+        let world = &world;
+        let workers = (0..4)
+            .map(|_| s.spawn(move || (0..100).map(|_| world.reserve_entity()).collect::<Vec<_>>()))
+            .collect::<Vec<_>>();
+        workers
+            .into_iter()
+            .flat_map(|x| x.join().unwrap())
+            .collect::<Vec<_>>()
+    });
+    assert_eq!(
+        reserved
+            .iter()
+            .map(|x| x.id())
+            .collect::<std::collections::HashSet<_>>()
+            .len(),
+        400
+    );
+
+    world.flush();
+    assert_eq!(world.len(), 400);
+    for &entity in &reserved {
+        world.insert_one(entity, true).unwrap();
+    }
+    assert_eq!(world.query::<&bool>().iter().count(), 400);
+}
+
+#[test]
+fn get_many_mut() {
+    let mut world = World::new();
+    let a = world.spawn((1, true));
+    let b = world.spawn((2, true));
+    let c = world.spawn((3,));
+    let d = world.spawn(("abc",));
+    {
+        let [x, y, z] = world.get_many_mut::<i32, 3>([a, b, c]).unwrap();
+        *x += 10;
+        *y += 20;
+        *z += 30;
+    }
+    assert_eq!(*world.get::<i32>(a).unwrap(), 11);
+    assert_eq!(*world.get::<i32>(b).unwrap(), 22);
+    assert_eq!(*world.get::<i32>(c).unwrap(), 33);
+    assert!(matches!(
+        world.get_many_mut::<i32, 2>([a, d]),
+        Err(ComponentError::MissingComponent(_))
+    ));
+    world.despawn(c).unwrap();
+    assert!(matches!(
+        world.get_many_mut::<i32, 2>([a, c]),
+        Err(ComponentError::NoSuchEntity)
+    ));
+
+    {
+        let [(x, _), (y, _)] = world
+            .query_many_mut::<(&mut i32, &bool), 2>([a, b])
+            .unwrap();
+        core::mem::swap(x, y);
+    }
+    assert_eq!(*world.get::<i32>(a).unwrap(), 22);
+    assert_eq!(
+        world.query_many_mut::<(&mut i32, &bool), 2>([a, d]).err(),
+        Some(QueryOneError::Unsatisfied)
+    );
+}
+
+#[test]
+#[should_panic(expected = "distinct")]
+fn get_many_mut_duplicate() {
+    let mut world = World::new();
+    let a = world.spawn((1,));
+    let _ = world.get_many_mut::<i32, 2>([a, a]);
+}
+
+#[test]
+fn insert_batch() {
+    let mut world = World::new();
+    let a = world.spawn((1,));
+    let b = world.spawn((2, "b"));
+    let c = world.spawn((3,));
+    let d = world.spawn((true, "d"));
+    world
+        .insert_batch(vec![
+            (a, ("x".to_string(), false)),
+            (b, ("y".to_string(), false)),
+            (c, ("z".to_string(), false)),
+            (d, ("w".to_string(), false)),
+            (a, ("v".to_string(), true)),
+        ])
+        .unwrap();
+    assert_eq!(*world.get::<String>(a).unwrap(), "v");
+    assert!(*world.get::<bool>(a).unwrap());
+    assert_eq!(*world.get::<String>(b).unwrap(), "y");
+    assert_eq!(*world.get::<&str>(b).unwrap(), "b");
+    assert_eq!(*world.get::<i32>(c).unwrap(), 3);
+    assert!(!*world.get::<bool>(d).unwrap());
+    assert_eq!(world.query::<(&String, &bool)>().iter().count(), 4);
+
+    world.despawn(c).unwrap();
+    assert_eq!(
+        world.insert_batch(vec![(a, (0u8,)), (c, (0u8,))]),
+        Err(NoSuchEntity)
+    );
+    assert!(world.get::<u8>(a).is_err());
+}
+
+#[test]
+fn insert_batch_whole_archetype() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static DROPS: AtomicUsize = AtomicUsize::new(0);
+    struct Counted;
+    impl Drop for Counted {
+        fn drop(&mut self) {
+            DROPS.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    let mut world = World::new();
+    let entities = (0..3)
+        .map(|i| world.spawn((i, Counted)))
+        .collect::<Vec<_>>();
+    world.clear_trackers();
+    world
+        .insert_batch(entities.iter().rev().map(|&e| (e, (Counted, true))))
+        .unwrap();
+    assert_eq!(DROPS.load(Ordering::Relaxed), 3);
+    for (i, &e) in entities.iter().enumerate() {
+        assert_eq!(*world.get::<i32>(e).unwrap(), i as i32);
+        assert!(*world.get::<bool>(e).unwrap());
+    }
+    assert_eq!(world.query::<Added<i32, ()>>().iter().count(), 0);
+    assert_eq!(world.query::<Added<bool, ()>>().iter().count(), 3);
+    assert_eq!(world.query::<Added<Counted, ()>>().iter().count(), 3);
+    drop(world);
+    assert_eq!(DROPS.load(Ordering::Relaxed), 6);
+}
+
+#[test]
+fn spawn_at_occupied() {
+    let mut world = World::new();
+    let parent = world.spawn(("parent".to_string(),));
+    let child = world.spawn(());
+    world.attach(child, parent).unwrap();
+    let replacement = Entity::from_bits(parent.to_bits());
+    world.spawn_at(replacement, (42,));
+    assert!(world.contains(replacement));
+    assert!(world.get::<String>(replacement).is_err());
+    assert_eq!(*world.get::<i32>(replacement).unwrap(), 42);
+    assert_eq!(world.parent(child), None);
+    assert_eq!(world.len(), 2);
+}
+
+#[test]
+fn build_entity_clone() {
+    use std::sync::Arc;
+
+    let shared = Arc::new(());
+    let mut world = World::new();
+    let mut builder = EntityBuilderClone::new();
+    builder
+        .add("abc".to_string())
+        .add(Arc::clone(&shared))
+        .add(1u8)
+        .add(2u8);
+    assert_eq!(builder.get::<u8>(), Some(&2));
+    let prefab = builder.build();
+    let a = world.spawn(prefab.clone());
+    let b = world.spawn(prefab.clone());
+    let copy = prefab.clone();
+    let c = world.spawn(copy.clone());
+    assert_eq!(Arc::strong_count(&shared), 6);
+    drop(prefab);
+    let d = world.spawn(copy);
+    assert_eq!(Arc::strong_count(&shared), 5);
+    for e in [a, b, c, d] {
+        assert_eq!(*world.get::<String>(e).unwrap(), "abc");
+        assert_eq!(*world.get::<u8>(e).unwrap(), 2);
+    }
+    world.clear();
+    assert_eq!(Arc::strong_count(&shared), 1);
+}
+
+#[test]
+fn build_entity_clone_panic() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    let budget = Arc::new(AtomicUsize::new(0));
+    let mut world = World::new();
+    let mut builder = EntityBuilderClone::new();
+    builder
+        .add("abc".to_string())
+        .add(Fragile(budget.clone()))
+        .add(1u8);
+    let prefab = builder.build();
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        world.spawn(prefab.clone());
+    }));
+    assert!(result.is_err());
+    assert!(world.is_empty());
+
+    let e = world.spawn((2u8,));
+    budget.store(0, Ordering::Relaxed);
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        world.insert(e, prefab.clone()).unwrap();
+    }));
+    assert!(result.is_err());
+    assert_eq!(*world.get::<u8>(e).unwrap(), 2);
+    assert!(world.get::<String>(e).is_err());
+    assert_eq!(Arc::strong_count(&budget), 2);
+}
+
+#[test]
+fn archetype_introspection() {
+    let mut world = World::new();
+    world.spawn((1, true));
+    world.spawn((2, true));
+    world.spawn(("abc",));
+    let arch = world.archetypes().find(|x| x.has::<i32>()).unwrap();
+    assert_eq!(arch.len(), 2);
+    assert!(!arch.is_empty());
+    assert!(arch.has::<bool>());
+    assert!(!arch.has::<&str>());
+    assert!(arch.has_dynamic(core::any::TypeId::of::<bool>()));
+    let mut ids = arch.types().iter().map(|x| x.id()).collect::<Vec<_>>();
+    ids.sort();
+    let mut expected = vec![
+        core::any::TypeId::of::<i32>(),
+        core::any::TypeId::of::<bool>(),
+    ];
+    expected.sort();
+    assert_eq!(ids, expected);
+    assert_eq!(
+        world.archetypes().map(|x| x.len()).sum::<u32>(),
+        world.len()
+    );
+}
+
+#[test]
+fn query_access() {
+    use core::any::TypeId;
+
+    let access = QueryAccess::of::<(&i32, Option<&mut bool>, Without<u8, &mut i32>)>();
+    assert!(access.reads().is_empty());
+    let mut writes = [TypeId::of::<bool>(), TypeId::of::<i32>()];
+    writes.sort();
+    assert_eq!(access.writes(), &writes[..]);
+    assert!(!access.is_compatible(&QueryAccess::of::<&bool>()));
+    assert!(access.is_compatible(&QueryAccess::of::<(&u8, &mut char)>()));
+    assert!(QueryAccess::of::<&i32>().is_compatible(&QueryAccess::of::<&i32>()));
+    assert!(QueryAccess::of::<Changed<i32, ()>>().writes().is_empty());
+}
+
+#[test]
+#[cfg(feature = "macros")]
+fn derived_query_access() {
+    use core::any::TypeId;
+
+    #[derive(Query)]
+    struct Foo<'a> {
+        _x: &'a i32,
+        _y: &'a mut bool,
+    }
+
+    let access = QueryAccess::of::<Foo>();
+    assert_eq!(access.reads(), &[TypeId::of::<i32>()]);
+    assert_eq!(access.writes(), &[TypeId::of::<bool>()]);
+}
+
+#[test]
+fn resources() {
+    let mut world = World::new();
+    assert!(world.resource::<String>().is_none());
+    assert_eq!(world.insert_resource("abc".to_string()), None);
+    assert!(world.contains_resource::<String>());
+    {
+        let a = world.resource::<String>().unwrap();
+        let b = world.resource::<String>().unwrap();
+        assert_eq!(*a, *b);
+    }
+    world.resource_mut::<String>().unwrap().push_str("def");
+    assert_eq!(
+        world.insert_resource("ghi".to_string()).as_deref(),
+        Some("abcdef")
+    );
+    assert_eq!(world.remove_resource::<String>().as_deref(), Some("ghi"));
+    assert!(!world.contains_resource::<String>());
+    world.insert_resource(1u32);
+    world.clear();
+    assert_eq!(*world.resource::<u32>().unwrap(), 1);
+}
+
+#[test]
+#[should_panic(expected = "already borrowed")]
+fn resource_borrow_conflict() {
+    let mut world = World::new();
+    world.insert_resource(1u32);
+    let _a = world.resource::<u32>().unwrap();
+    let _b = world.resource_mut::<u32>().unwrap();
+}
+
+#[test]
+fn satisfies() {
+    let mut world = World::new();
+    let a = world.spawn((123, true));
+    let b = world.spawn(());
+    assert!(world.satisfies::<(&i32, &mut bool)>(a).unwrap());
+    assert!(world.satisfies::<Without<&str, Option<&char>>>(a).unwrap());
+    assert!(!world.satisfies::<(&i32, &char)>(a).unwrap());
+    assert!(world.satisfies::<()>(b).unwrap());
+    assert!(!world.satisfies::<&i32>(b).unwrap());
+    assert!(world.entity(a).unwrap().satisfies::<With<bool, &i32>>());
+    assert!(world.entity(b).unwrap().satisfies::<Option<&i32>>());
+    assert!(!world.entity(b).unwrap().satisfies::<&i32>());
+
+    world.clear_trackers();
+    assert!(!world.satisfies::<Changed<i32, ()>>(a).unwrap());
+    *world.get_mut::<i32>(a).unwrap() += 1;
+    assert!(world.satisfies::<Changed<i32, ()>>(a).unwrap());
+
+    world.despawn(a).unwrap();
+    assert_eq!(world.satisfies::<()>(a), Err(NoSuchEntity));
+}
+
+#[test]
+fn or_query() {
+    let mut world = World::new();
+    let a = world.spawn((1, true));
+    let b = world.spawn((2,));
+    world.spawn(("abc",));
+    world.clear_trackers();
+    *world.get_mut::<i32>(a).unwrap() = 10;
+
+    let mut results = world
+        .query::<(&i32, Or<Changed<i32, ()>, &bool>)>()
+        .iter()
+        .map(|(e, (&i, x))| (e, i, x.map(|()| (), |&b| b)))
+        .collect::<Vec<_>>();
+    results.sort_by_key(|x| x.0);
+    assert_eq!(results, [(a, 10, Or::Both((), true))]);
+    assert_eq!(world.query::<Or<&i32, &bool>>().iter().count(), 2);
+    for (_, x) in world.query_mut::<Or<&mut i32, &bool>>() {
+        if let Some(i) = x.left() {
+            *i += 1;
+        }
+    }
+    assert_eq!(*world.get::<i32>(b).unwrap(), 3);
+    assert!(world.satisfies::<Or<&char, &bool>>(a).unwrap());
+    assert!(!world.satisfies::<Or<&char, &bool>>(b).unwrap());
+}
+
+#[test]
+fn dynamic_query() {
+    use core::any::TypeId;
+
+    let mut world = World::new();
+    let a = world.spawn((1, true));
+    let b = world.spawn((2, 'x', false));
+    world.spawn((3,));
+    let query = DynamicQuery::new()
+        .write(TypeId::of::<i32>())
+        .read(TypeId::of::<bool>());
+    world.clear_trackers();
+    {
+        let mut borrow = world.query_dynamic(&query);
+        let mut seen = Vec::new();
+        for (entity, mut row) in borrow.iter() {
+            assert_eq!(row.len(), 2);
+            assert_eq!(row.type_info(0).id(), TypeId::of::<i32>());
+            assert_eq!(row.type_info(1).id(), TypeId::of::<bool>());
+            assert!(row.get_mut(1).is_none());
+            unsafe {
+                if *row.get(1).cast::<bool>() {
+                    *row.get_mut(0).unwrap().cast::<i32>() *= 10;
+                }
+            }
+            seen.push(entity);
+        }
+        seen.sort();
+        assert_eq!(seen, [a, b]);
+        // Shared borrows are compatible with the dynamic query
+        assert!(*world.get::<bool>(a).unwrap());
+    }
+    assert_eq!(*world.get::<i32>(a).unwrap(), 10);
+    assert_eq!(*world.get::<i32>(b).unwrap(), 2);
+    let changed = world
+        .query::<Changed<i32, ()>>()
+        .iter()
+        .map(|(e, ())| e)
+        .collect::<Vec<_>>();
+    assert_eq!(changed, [a]);
+    assert_eq!(world.query_dynamic(&DynamicQuery::new()).iter().count(), 3);
+}
+
+#[test]
+#[should_panic(expected = "already borrowed")]
+fn dynamic_query_borrow_conflict() {
+    let mut world = World::new();
+    let a = world.spawn((1,));
+    let _x = world.get::<i32>(a).unwrap();
+    let query = DynamicQuery::new().write(core::any::TypeId::of::<i32>());
+    let _borrow = world.query_dynamic(&query);
+}
+
+#[test]
+fn remove_hooks() {
+    use std::sync::{Arc, Mutex};
+    let log = Arc::new(Mutex::new(Vec::new()));
+    let mut world = World::new();
+    let sink = log.clone();
+    world.on_remove::<i32>(move |entity, x| sink.lock().unwrap().push((entity, *x)));
+    let a = world.spawn((1, true));
+    let b = world.spawn((2, "abc"));
+    let c = world.spawn((3,));
+    let d = world.spawn((4,));
+
+    world.despawn(a).unwrap();
+    assert_eq!(
+        world.remove::<(i32, bool)>(b),
+        Err(ComponentError::MissingComponent(MissingComponent::new::<
+            bool,
+        >()))
+    );
+    assert_eq!(world.remove_one::<i32>(b), Ok(2));
+    world.remove_one::<&str>(b).unwrap();
+    world.insert_one(c, 30).unwrap();
+    world.clear();
+    assert_eq!(
+        *log.lock().unwrap(),
+        [(a, 1), (b, 2), (c, 3), (c, 30), (d, 4)]
+    );
+}
+
+#[test]
+fn world_events() {
+    use core::any::TypeId;
+    use WorldEvent::*;
+    let mut world = World::new();
+    let a = world.spawn((1,));
+    assert!(world.take_events().is_empty());
+
+    world.record_events(true);
+    let b = world.spawn_batch(Some((2, true))).next().unwrap();
+    world.insert(a, (10, "abc")).unwrap();
+    world.remove_one::<bool>(b).unwrap();
+    world.despawn(a).unwrap();
+    assert_eq!(
+        world.take_events(),
+        [
+            ComponentAdded {
+                entity: b,
+                ty: TypeId::of::<i32>()
+            },
+            ComponentAdded {
+                entity: b,
+                ty: TypeId::of::<bool>()
+            },
+            ComponentAdded {
+                entity: a,
+                ty: TypeId::of::<&str>()
+            },
+            ComponentRemoved {
+                entity: b,
+                ty: TypeId::of::<bool>()
+            },
+            Despawned { entity: a },
+        ]
+    );
+    assert!(world.take_events().is_empty());
+
+    world.clear();
+    assert_eq!(world.take_events(), [Despawned { entity: b }]);
+    world.record_events(false);
+    world.spawn((3,));
+    assert!(world.take_events().is_empty());
+}
+
+#[test]
+fn take_between_worlds() {
+    struct Unseen(String);
+    let mut world = World::new();
+    let mut other = World::new();
+    other.spawn((true,));
+    let parent = world.spawn(());
+    let a = world.spawn((1, Unseen("abc".into())));
+    let child = world.spawn((2,));
+    world.attach(a, parent).unwrap();
+    world.attach(child, a).unwrap();
+    let last = world.spawn((3, Unseen("def".into())));
+
+    let b = world.take(&mut other, a).unwrap();
+    assert!(!world.contains(a));
+    assert_eq!(world.take(&mut other, a), Err(NoSuchEntity));
+    assert!(world.get::<Children>(parent).is_err());
+    assert_eq!(world.parent(child), None);
+    assert_eq!(world.get::<Unseen>(last).unwrap().0, "def");
+
+    assert_eq!(*other.get::<i32>(b).unwrap(), 1);
+    assert_eq!(other.get::<Unseen>(b).unwrap().0, "abc");
+    assert!(other.get::<Parent>(b).is_err());
+    assert!(other.get::<Children>(b).is_err());
+    assert_eq!(other.len(), 2);
+}
+
+#[test]
+fn snapshot_restore() {
+    let mut registry = SnapshotRegistry::new();
+    registry.register::<i32>().register::<String>();
+    let mut world = World::new();
+    let a = world.spawn((1, "abc".to_string()));
+    let b = world.spawn((2,));
+    let c = world.spawn(());
+    world.attach(b, a).unwrap();
+    let snapshot = world.snapshot(&registry);
+
+    *world.get_mut::<i32>(a).unwrap() = 10;
+    world.despawn(b).unwrap();
+    world.remove_one::<String>(a).unwrap();
+    world.despawn(c).unwrap();
+    let d = world.spawn((4, "def".to_string()));
+    world.clear_trackers();
+
+    for _ in 0..2 {
+        world.restore(&snapshot);
+        assert_eq!(world.len(), 3);
+        assert!(!world.contains(d));
+        assert!(world.contains(c));
+        assert_eq!(*world.get::<i32>(a).unwrap(), 1);
+        assert_eq!(*world.get::<String>(a).unwrap(), "abc");
+        assert_eq!(world.parent(b), Some(a));
+        assert_eq!(world.query::<Added<i32, ()>>().iter().count(), 2);
+        *world.get_mut::<i32>(b).unwrap() = 20;
+    }
+
+    // Allocation resumes exactly as it would have from the snapshot
+    let e = world.spawn(());
+    world.restore(&snapshot);
+    assert_eq!(world.spawn(()), e);
+}
+
+/// Panics when cloned once its shared budget is exhausted
+struct Fragile(std::sync::Arc<std::sync::atomic::AtomicUsize>);
+
+impl Clone for Fragile {
+    fn clone(&self) -> Self {
+        use std::sync::atomic::Ordering;
+        assert!(self.0.fetch_sub(1, Ordering::Relaxed) > 0, "out of clones");
+        Self(self.0.clone())
+    }
+}
+
+#[test]
+fn snapshot_panic() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    let budget = Arc::new(AtomicUsize::new(1));
+    let mut registry = SnapshotRegistry::new();
+    registry.register::<String>().register::<Fragile>();
+    let mut world = World::new();
+    let a = world.spawn(("abc".to_string(), Fragile(budget.clone())));
+    world.spawn(("def".to_string(), Fragile(budget.clone())));
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        world.snapshot(&registry);
+    }));
+    assert!(result.is_err());
+    assert_eq!(Arc::strong_count(&budget), 3);
+    assert_eq!(*world.get::<String>(a).unwrap(), "abc");
+
+    budget.store(usize::MAX, Ordering::Relaxed);
+    let snapshot = world.snapshot(&registry);
+    assert_eq!(Arc::strong_count(&budget), 5);
+    drop(snapshot);
+    assert_eq!(Arc::strong_count(&budget), 3);
+}
+
+#[test]
+fn snapshot_restore_panic() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    let budget = Arc::new(AtomicUsize::new(usize::MAX));
+    let mut registry = SnapshotRegistry::new();
+    registry.register::<i32>().register::<Fragile>();
+    let mut world = World::new();
+    let a = world.spawn((1, Fragile(budget.clone())));
+    world.spawn((2, Fragile(budget.clone())));
+    let snapshot = world.snapshot(&registry);
+    world.despawn(a).unwrap();
+    let b = world.spawn((3,));
+
+    budget.store(1, Ordering::Relaxed);
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        world.restore(&snapshot);
+    }));
+    assert!(result.is_err());
+    assert_eq!(world.len(), 2);
+    assert!(!world.contains(a));
+    assert_eq!(*world.get::<i32>(b).unwrap(), 3);
+    // Only the world's remaining component and the snapshot's two hold the budget
+    assert_eq!(Arc::strong_count(&budget), 4);
+
+    budget.store(usize::MAX, Ordering::Relaxed);
+    world.restore(&snapshot);
+    assert_eq!(world.len(), 2);
+    assert_eq!(*world.get::<i32>(a).unwrap(), 1);
+    assert_eq!(world.query::<&Fragile>().iter().count(), 2);
+}
+
+#[test]
+#[should_panic(expected = "not registered")]
+fn snapshot_unregistered() {
+    let mut world = World::new();
+    world.spawn((true,));
+    world.snapshot(&SnapshotRegistry::new());
+}
+
+#[test]
+fn world_diff() {
+    #[derive(Clone)]
+    struct Opaque;
+
+    let mut registry = SnapshotRegistry::new();
+    registry
+        .register_eq::<i32>()
+        .register_eq::<bool>()
+        .register::<Opaque>();
+    let mut server = World::new();
+    let mut client = World::new();
+    let a = server.spawn((1, true, Opaque));
+    let b = server.spawn((2, false));
+    let c = server.spawn((3,));
+    let old = server.snapshot(&registry);
+    let empty = client.snapshot(&registry);
+    client.apply_diff(&WorldDiff::new(&empty, &old)).unwrap();
+    assert_eq!(*client.get::<i32>(c).unwrap(), 3);
+
+    *server.get_mut::<i32>(a).unwrap() = 10;
+    server.remove_one::<bool>(b).unwrap();
+    server.despawn(c).unwrap();
+    let d = server.spawn(("abc".to_string(),));
+    registry.register_eq::<String>();
+    let diff = WorldDiff::new(&old, &server.snapshot(&registry));
+    assert_eq!(diff.despawned(), [c]);
+    assert_eq!(diff.removed(), [(b, core::any::TypeId::of::<bool>())]);
+    assert_eq!(diff.spawned().map(|(e, _)| e).collect::<Vec<_>>(), [d]);
+    // `Opaque` can't be compared, so is always included
+    assert_eq!(diff.changed().map(|(e, _)| e).collect::<Vec<_>>(), [a]);
+
+    client.apply_diff(&diff).unwrap();
+    assert_eq!(*client.get::<i32>(a).unwrap(), 10);
+    assert!(*client.get::<bool>(a).unwrap());
+    assert!(client.get::<bool>(b).is_err());
+    assert!(!client.contains(c));
+    assert_eq!(*client.get::<String>(d).unwrap(), "abc");
+    assert_eq!(client.len(), 3);
+
+    let current = server.snapshot(&registry);
+    let mut unchanged = WorldDiff::new(&current, &current);
+    assert_eq!(unchanged.changed().len(), 1);
+    server.remove_one::<Opaque>(a).unwrap();
+    let current = server.snapshot(&registry);
+    unchanged = WorldDiff::new(&current, &current);
+    assert!(unchanged.is_empty());
+}
+
+#[test]
+fn columns() {
+    let mut world = World::new();
+    let a = world.spawn((1, true));
+    let b = world.spawn((2,));
+    let c = world.spawn((3, true));
+    world.spawn(("abc",));
+    world.clear_trackers();
+
+    for mut column in world.columns_mut::<i32>() {
+        for x in column.iter_mut() {
+            *x *= 10;
+        }
+    }
+    let mut seen = world
+        .columns::<i32>()
+        .flat_map(|column| column.entities().zip(column.to_vec()).collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+    seen.sort();
+    assert_eq!(seen, [(a, 10), (b, 20), (c, 30)]);
+    assert_eq!(world.query::<Changed<i32, ()>>().iter().count(), 3);
+    assert_eq!(world.columns::<bool>().count(), 1);
+    assert_eq!(world.columns::<u8>().count(), 0);
+}
+
+#[test]
+#[should_panic(expected = "already borrowed")]
+fn columns_borrow_conflict() {
+    let mut world = World::new();
+    world.spawn((1,));
+    let _shared = world.columns::<i32>().collect::<Vec<_>>();
+    let _unique = world.columns_mut::<i32>().collect::<Vec<_>>();
+}
+
+#[test]
+fn query_sorted() {
+    let mut world = World::new();
+    let a = world.spawn((2, "a"));
+    let b = world.spawn((1,));
+    let c = world.spawn((2,));
+    let d = world.spawn((0, "d"));
+    let order = world
+        .query::<&i32>()
+        .iter_sorted_by_key(|&x| *x)
+        .map(|(e, _)| e)
+        .collect::<Vec<_>>();
+    // Ties are yielded in iteration order
+    let ties = world
+        .query::<&i32>()
+        .iter()
+        .filter(|&(_, &x)| x == 2)
+        .map(|(e, _)| e)
+        .collect::<Vec<_>>();
+    assert_eq!(order[..2], [d, b]);
+    assert_eq!(order[2..], ties[..]);
+    assert!(ties.contains(&a) && ties.contains(&c));
+
+    let mut calls = 0;
+    let mut query = world.query::<(&mut i32, Option<&&str>)>();
+    let mut iter = query.iter_sorted_by_cached_key(|(x, _)| {
+        calls += 1;
+        core::cmp::Reverse(**x)
+    });
+    assert_eq!(iter.len(), 4);
+    let (first, (x, name)) = iter.next().unwrap();
+    *x = 5;
+    assert_eq!(first, ties[0]);
+    assert_eq!(name.is_some(), first == a);
+    assert_eq!(iter.map(|(e, _)| e).last(), Some(d));
+    drop(query);
+    assert_eq!(calls, 4);
+    assert_eq!(*world.get::<i32>(first).unwrap(), 5);
+}
+
+#[test]
+fn extract() {
+    let counter = std::sync::Arc::new(());
+    let mut world = World::new();
+    let a = world.spawn((1, counter.clone()));
+    let b = world.spawn((2, counter.clone()));
+    let c = world.spawn((3,));
+    world.clear_trackers();
+    *world.get_mut::<i32>(a).unwrap() = 10;
+    let d = world.spawn((4,));
+    let parent = world.spawn(());
+    world.attach(c, parent).unwrap();
+
+    // Only some entities of each archetype match
+    let mut batches = world.extract::<Changed<i32, ()>>();
+    batches.sort_by_key(|(x, _)| x.len());
+    assert_eq!(batches.len(), 2);
+    assert_eq!(batches[0].0, [a]);
+    assert_eq!(batches[1].0, [d]);
+    assert!(!world.contains(a) && !world.contains(d));
+    assert_eq!(*world.get::<i32>(b).unwrap(), 2);
+    assert_eq!(*world.get::<i32>(c).unwrap(), 3);
+    assert_eq!(std::sync::Arc::strong_count(&counter), 3);
+    drop(batches);
+    assert_eq!(std::sync::Arc::strong_count(&counter), 2);
+
+    // Whole archetypes are moved
+    let mut other = World::new();
+    for (old, batch) in world.extract::<&Parent>() {
+        assert_eq!(old, [c]);
+        let new = other.spawn_column_batch(batch).collect::<Vec<_>>();
+        assert_eq!(*other.get::<i32>(new[0]).unwrap(), 3);
+    }
+    assert!(!world.contains(c));
+    assert!(world.get::<Children>(parent).is_err());
+    assert_eq!(world.len(), 2);
+}
+
+#[test]
+fn extract_chunked() {
+    #[derive(Clone, PartialEq, Eq, Hash, Debug)]
+    struct Material(u32);
+
+    let mut world = World::new();
+    let a = world.spawn_chunked(Material(1), (1, true));
+    let b = world.spawn_chunked(Material(2), (2,));
+    let c = world.spawn_chunked(Material(2), (3,));
+    world.clear_trackers();
+    *world.get_mut::<i32>(b).unwrap() = 20;
+
+    // A whole archetype, then part of one
+    let mut other = World::new();
+    let whole = world.extract::<&bool>();
+    let part = world.extract::<Changed<i32, ()>>();
+    assert_eq!(whole[0].0, [a]);
+    assert_eq!(part[0].0, [b]);
+    let mut new = Vec::new();
+    for (_, batch) in whole.into_iter().chain(part) {
+        new.extend(other.spawn_column_batch(batch));
+    }
+    assert_eq!(other.chunk::<Material>(new[0]).unwrap(), &Material(1));
+    assert_eq!(other.chunk::<Material>(new[1]).unwrap(), &Material(2));
+    assert_eq!(*other.get::<i32>(new[1]).unwrap(), 20);
+    assert_eq!(world.chunk::<Material>(c).unwrap(), &Material(2));
+}
+
+#[test]
+fn shrink_and_compact() {
+    let mut world = World::new();
+    let entities = world
+        .spawn_batch((0..1000).map(|i| (i,)))
+        .collect::<Vec<_>>();
+    let a = world.spawn((1, "abc"));
+    let b = world.spawn((2, true));
+    let c = world.spawn((3, "def", true));
+    for &e in &entities[1..] {
+        world.despawn(e).unwrap();
+    }
+    assert!(world.shrink_to_fit() >= 999 * 4);
+    assert_eq!(world.shrink_to_fit(), 0);
+
+    let mut prepared = PreparedQuery::<&i32>::new();
+    assert_eq!(prepared.query(&world).iter().count(), 4);
+    world.despawn(a).unwrap();
+    world.despawn(c).unwrap();
+    let generation = world.archetypes_generation();
+    assert!(world.compact() > 0);
+    assert_ne!(world.archetypes_generation(), generation);
+    assert_eq!(world.archetypes().len(), 3);
+    assert_eq!(world.compact(), 0);
+
+    assert_eq!(*world.get::<i32>(entities[0]).unwrap(), 0);
+    assert!(*world.get::<bool>(b).unwrap());
+    assert_eq!(prepared.query(&world).iter().count(), 2);
+    let d = world.spawn((4, "ghi"));
+    assert_eq!(prepared.query(&world).iter().count(), 3);
+    assert_eq!(*world.get::<&str>(d).unwrap(), "ghi");
+    world.despawn(b).unwrap();
+    world.insert_one(entities[0], true).unwrap();
+    assert!(world.get::<bool>(entities[0]).is_ok());
+}
+
+#[test]
+fn despawn_all_and_retain() {
+    struct Dead;
+    let counter = std::sync::Arc::new(());
+    let mut world = World::new();
+    let entities = world
+        .spawn_batch((0..10).map(|i| (i, counter.clone())))
+        .collect::<Vec<_>>();
+    for &e in entities.iter().step_by(3) {
+        world.insert_one(e, Dead).unwrap();
+    }
+    let parent = world.spawn((Dead,));
+    world.attach(entities[1], parent).unwrap();
+
+    assert_eq!(world.despawn_all::<&Dead>(), 5);
+    assert_eq!(std::sync::Arc::strong_count(&counter), 7);
+    assert_eq!(world.parent(entities[1]), None);
+    for (i, &e) in entities.iter().enumerate() {
+        assert_eq!(world.contains(e), i % 3 != 0);
+    }
+    assert_eq!(world.despawn_all::<&Dead>(), 0);
+
+    world.retain(|_, components| *components.get::<i32>().unwrap() % 2 == 0);
+    let mut remaining = world
+        .query::<&i32>()
+        .iter()
+        .map(|(e, &i)| (e, i))
+        .collect::<Vec<_>>();
+    remaining.sort_by_key(|x| x.1);
+    assert_eq!(
+        remaining,
+        [(entities[2], 2), (entities[4], 4), (entities[8], 8)]
+    );
+    world.retain(|_, _| false);
+    assert!(world.is_empty());
+    assert_eq!(std::sync::Arc::strong_count(&counter), 1);
+}
+
+#[test]
+fn dangling_entity() {
+    let mut world = World::new();
+    let a = world.spawn((42,));
+    assert!(!world.contains(Entity::DANGLING));
+    assert!(world.get::<i32>(Entity::DANGLING).is_err());
+    assert!(world.insert_one(Entity::DANGLING, true).is_err());
+    assert!(world.despawn(Entity::DANGLING).is_err());
+    assert_eq!(a.to_bits() >> EntityId::BITS, 0);
+    assert_eq!(a.to_bits() as EntityId, a.id());
+    assert_eq!(Entity::from_bits(a.to_bits()), a);
+    assert_ne!(a, Entity::DANGLING);
+}
+
+#[test]
+fn map_entities() {
+    struct Target(Entity);
+    impl MapEntities for Target {
+        fn map_entities(&mut self, map: &EntityMap) {
+            self.0.map_entities(map);
+        }
+    }
+
+    let mut world = World::new();
+    let mut chunk = World::new();
+    let a = chunk.spawn(());
+    let b = chunk.spawn((Target(a),));
+    let outside = world.spawn((Target(a),));
+    let map = world.merge(chunk);
+    world.map_entities::<Target>(&map);
+    let a = map.get(a).unwrap();
+    assert_eq!(world.get::<Target>(map.get(b).unwrap()).unwrap().0, a);
+    assert_eq!(world.get::<Target>(outside).unwrap().0, a);
+
+    let mut manual = EntityMap::new();
+    assert_eq!(manual.insert(a, outside), None);
+    let mut handles = vec![Some(a), None];
+    handles.map_entities(&manual);
+    assert_eq!(handles, [Some(outside), None]);
+}
+
+#[test]
+fn query_count() {
+    let mut world = World::new();
+    assert!(world.query::<()>().is_empty());
+    world.spawn_batch((0..10).map(|i| (i,)));
+    world.spawn_batch((0..5).map(|i| (i, true)));
+    world.clear_trackers();
+    let e = world.spawn((7, false));
+    let first = world.query::<&i32>().iter().next().unwrap().0;
+    *world.get_mut::<i32>(first).unwrap() += 1;
+
+    assert_eq!(world.query::<&i32>().count(), 16);
+    assert_eq!(world.query::<&bool>().count(), 6);
+    assert_eq!(world.query::<&i32>().without::<bool>().count(), 10);
+    assert_eq!(world.query::<&i32>().added::<i32>().count(), 1);
+    assert_eq!(world.query::<&i32>().changed::<i32>().count(), 2);
+    assert!(!world.query::<&i32>().added::<bool>().is_empty());
+    assert!(world.query::<&u8>().is_empty());
+
+    world.despawn(e).unwrap();
+    assert!(world.query::<&i32>().added::<i32>().is_empty());
+    let mut query = world.query::<&mut i32>();
+    let n = query.iter().count() as u32;
+    assert_eq!(query.count(), n);
+}
+
+#[test]
+fn query_count_per_entity() {
+    struct Stunned;
+    let mut world = World::new();
+    world.register_sparse::<Stunned>();
+    let entities = world.spawn_batch((0..4).map(|i| (i,))).collect::<Vec<_>>();
+    world.spawn((true,));
+    world.insert_one(entities[0], Stunned).unwrap();
+    world.disable(entities[1]).unwrap();
+
+    assert_eq!(world.query::<&i32>().count(), 3);
+    assert_eq!(world.query::<Including<Disabled, &i32>>().count(), 4);
+    assert_eq!(world.query::<(&i32, &Stunned)>().count(), 1);
+    assert_eq!(world.query::<Without<Stunned, &i32>>().count(), 2);
+    assert_eq!(world.query::<With<Stunned, ()>>().count(), 1);
+    assert_eq!(world.query::<Or<&Stunned, &bool>>().count(), 2);
+    assert_eq!(world.query::<Or<&Stunned, &i32>>().count(), 3);
+    assert!(!world.query::<&Stunned>().is_empty());
+    world.remove_one::<Stunned>(entities[0]).unwrap();
+    assert!(world.query::<&Stunned>().is_empty());
+}
+
+#[test]
+fn world_stats() {
+    let mut world = World::new();
+    let empty = world.stats();
+    assert_eq!(empty.entities(), 0);
+    assert_eq!(empty.empty_archetypes(), empty.archetypes().len());
+
+    world.reserve::<(u32, u64)>(10);
+    world.spawn_batch((0..4).map(|i| (i as u32, i as u64)));
+    world.spawn(("abc",));
+    let stats = world.stats();
+    assert_eq!(stats.entities(), 5);
+    assert!(stats.capacity() >= 11);
+    let archetype = stats
+        .archetypes()
+        .iter()
+        .find(|x| {
+            x.types()
+                .iter()
+                .any(|t| t.id() == std::any::TypeId::of::<u64>())
+        })
+        .unwrap();
+    assert_eq!(archetype.len(), 4);
+    assert!(archetype.capacity() >= 10);
+    assert!(archetype.allocated_bytes() >= 10 * (4 + 8));
+    assert_eq!(archetype.types().len(), 2);
+    assert!(stats.entity_bytes() > 0);
+    assert_eq!(
+        stats.allocated_bytes(),
+        stats.entity_bytes()
+            + stats
+                .archetypes()
+                .iter()
+                .map(|x| x.allocated_bytes())
+                .sum::<usize>()
+    );
+}
+
+#[test]
+fn exchange() {
+    let mut world = World::new();
+    let e = world.spawn((123, true, "abc"));
+    let other = world.spawn((456, false, "def"));
+    assert_eq!(
+        world.exchange::<(i32, bool)>(e, ('x', 7u8)),
+        Ok((123, true))
+    );
+    assert!(world.get::<i32>(e).is_err());
+    assert!(world.get::<bool>(e).is_err());
+    assert_eq!(*world.get::<char>(e).unwrap(), 'x');
+    assert_eq!(*world.get::<u8>(e).unwrap(), 7);
+    assert_eq!(*world.get::<&str>(e).unwrap(), "abc");
+    assert_eq!(*world.get::<i32>(other).unwrap(), 456);
+
+    // Missing components leave the entity untouched
+    assert!(world.exchange::<(i32,)>(e, (1.0f32,)).is_err());
+    assert!(world.get::<f32>(e).is_err());
+    assert_eq!(*world.get::<char>(e).unwrap(), 'x');
+
+    // Removing and re-adding the same type stays within the archetype
+    assert_eq!(world.exchange::<(char,)>(e, ('y',)), Ok(('x',)));
+    assert_eq!(*world.get::<char>(e).unwrap(), 'y');
+
+    // Replaced components are dropped exactly once
+    let counter = std::sync::Arc::new(());
+    world.insert_one(e, counter.clone()).unwrap();
+    world.exchange::<(u8,)>(e, (counter.clone(), 1u16)).unwrap();
+    assert_eq!(std::sync::Arc::strong_count(&counter), 2);
+    assert_eq!(*world.get::<u16>(e).unwrap(), 1);
+    assert!(world.get::<u8>(e).is_err());
+    world.exchange::<()>(e, (counter.clone(),)).unwrap();
+    assert_eq!(std::sync::Arc::strong_count(&counter), 2);
+    world.despawn(e).unwrap();
+    assert_eq!(std::sync::Arc::strong_count(&counter), 1);
+}
+
+#[test]
+fn large_tuples() {
+    #[derive(Debug, PartialEq)]
+    struct C<const N: usize>;
+    let mut world = World::new();
+    let e = world.spawn((
+        C::<0>, C::<1>, C::<2>, C::<3>, C::<4>, C::<5>, C::<6>, C::<7>, C::<8>, C::<9>, C::<10>,
+        C::<11>, C::<12>, C::<13>, C::<14>, C::<15>, C::<16>, C::<17>, C::<18>, C::<19>, C::<20>,
+        C::<21>, C::<22>, C::<23>,
+    ));
+    assert_eq!(world.get::<C<23>>(e).as_deref(), Ok(&C::<23>));
+    let mut query = world.query::<(
+        &C<0>,
+        &C<1>,
+        &C<2>,
+        &C<3>,
+        &C<4>,
+        &C<5>,
+        &C<6>,
+        &C<7>,
+        &C<8>,
+        &C<9>,
+        &C<10>,
+        &C<11>,
+        &C<12>,
+        &C<13>,
+        &C<14>,
+        &C<15>,
+        &C<16>,
+        &C<17>,
+        &C<18>,
+        &C<19>,
+        &C<20>,
+        &C<21>,
+        &C<22>,
+        &mut C<23>,
+    )>();
+    assert_eq!(query.iter().count(), 1);
+    drop(query);
+    let nested = world
+        .query::<((&C<0>, &C<1>), (&C<2>, (&mut C<3>, Option<&u8>)))>()
+        .iter()
+        .map(|(x, _)| x)
+        .collect::<Vec<_>>();
+    assert_eq!(nested, [e]);
+}
+
+#[test]
+fn trait_queries() {
+    trait Named {
+        fn name(&self) -> String;
+        fn rename(&mut self, name: &str);
+    }
+    struct Monster(String);
+    impl Named for Monster {
+        fn name(&self) -> String {
+            self.0.clone()
+        }
+        fn rename(&mut self, name: &str) {
+            self.0 = name.into();
+        }
+    }
+    impl Named for u32 {
+        fn name(&self) -> String {
+            self.to_string()
+        }
+        fn rename(&mut self, name: &str) {
+            *self = name.parse().unwrap();
+        }
+    }
+
+    let mut world = World::new();
+    assert_eq!(world.query_trait::<dyn Named>().iter().count(), 0);
+    world.register_trait::<Monster, dyn Named>(|x| x, |x| x);
+    world.register_trait::<u32, dyn Named>(|x| x, |x| x);
+    let a = world.spawn((Monster("orc".into()),));
+    let b = world.spawn((Monster("elf".into()), 7u32));
+    world.spawn((true,));
+
+    let mut names = world
+        .query_trait::<dyn Named>()
+        .iter()
+        .map(|(e, x)| (e, x.name()))
+        .collect::<Vec<_>>();
+    names.sort();
+    let mut expected = vec![(a, "orc".into()), (b, "elf".into()), (b, "7".into())];
+    expected.sort();
+    assert_eq!(names, expected);
+
+    world.clear_trackers();
+    for (e, x) in world.query_trait_mut::<dyn Named>() {
+        if e == a {
+            x.rename("goblin");
+        }
+    }
+    assert_eq!(world.get::<Monster>(a).unwrap().0, "goblin");
+    assert_eq!(world.query::<Changed<Monster, ()>>().iter().count(), 2);
+    assert_eq!(world.query::<Changed<u32, ()>>().iter().count(), 1);
+
+    let _shared = world.query_trait::<dyn Named>();
+    let _also_shared = world.get::<Monster>(a).unwrap();
+}
+
+#[test]
+fn trait_object_queries() {
+    trait Named {
+        fn name(&self) -> String;
+        fn rename(&mut self, name: &str);
+    }
+    struct Monster(String);
+    impl Named for Monster {
+        fn name(&self) -> String {
+            self.0.clone()
+        }
+        fn rename(&mut self, name: &str) {
+            self.0 = name.into();
+        }
+    }
+    impl Named for u32 {
+        fn name(&self) -> String {
+            self.to_string()
+        }
+        fn rename(&mut self, name: &str) {
+            *self = name.parse().unwrap();
+        }
+    }
+    trait_query!(dyn Named: Monster, u32);
+
+    let mut world = World::new();
+    let a = world.spawn((Monster("orc".into()), true));
+    let b = world.spawn((Monster("elf".into()), 7u32));
+    let c = world.spawn((8u32, true));
+    let d = world.spawn((true,));
+
+    // Entities with several implementations yield the first listed
+    let mut names = world
+        .query::<(&dyn Named, Option<&bool>)>()
+        .iter()
+        .map(|(e, (x, flag))| (e, x.name(), flag.is_some()))
+        .collect::<Vec<_>>();
+    names.sort();
+    let mut expected = vec![
+        (a, "orc".into(), true),
+        (b, "elf".into(), false),
+        (c, "8".into(), true),
+    ];
+    expected.sort();
+    assert_eq!(names, expected);
+    assert!(world
+        .query_one_mut::<Option<&dyn Named>>(d)
+        .unwrap()
+        .is_none());
+
+    world.clear_trackers();
+    for (_, x) in world.query_mut::<With<bool, &mut dyn Named>>() {
+        x.rename("9");
+    }
+    assert_eq!(world.get::<Monster>(a).unwrap().0, "9");
+    assert_eq!(*world.get::<u32>(c).unwrap(), 9);
+    assert_eq!(world.query::<Changed<Monster, ()>>().iter().count(), 1);
+    assert_eq!(world.query::<Changed<u32, ()>>().iter().count(), 1);
+
+    let _unique = world.get_mut::<u32>(c).unwrap();
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        world.query::<&dyn Named>().iter().count()
+    }));
+    assert!(result.is_err());
+}
+
+#[test]
+#[should_panic(expected = "already borrowed")]
+fn trait_query_borrow_conflict() {
+    trait Marker {}
+    impl Marker for u32 {}
+    let mut world = World::new();
+    world.register_trait::<u32, dyn Marker>(|x| x, |x| x);
+    let e = world.spawn((7u32,));
+    let _unique = world.get_mut::<u32>(e).unwrap();
+    world.query_trait::<dyn Marker>();
+}
+
+#[test]
+fn chunk_components() {
+    #[derive(Clone, PartialEq, Eq, Hash, Debug)]
+    struct Material(u32);
+    #[derive(Clone, PartialEq, Eq, Hash, Debug)]
+    struct Layer(u8);
+
+    let mut world = World::new();
+    let a = world.spawn_chunked(Material(1), (1,));
+    let b = world.spawn_chunked(Material(2), (2,));
+    let c = world.spawn_chunked(Material(1), (3,));
+    let d = world.spawn((4,));
+    let shared = world
+        .archetypes()
+        .filter(|x| x.chunk::<Material>() == Some(&Material(1)))
+        .map(|x| x.len())
+        .collect::<Vec<_>>();
+    assert_eq!(shared, [2]);
+    assert!(world.chunk::<Material>(d).is_err());
+
+    // Ordinary component changes keep the chunk
+    world.insert_one(a, true).unwrap();
+    assert_eq!(world.chunk::<Material>(a).unwrap(), &Material(1));
+    world.remove_one::<bool>(a).unwrap();
+    assert_eq!(world.chunk::<Material>(a).unwrap(), &Material(1));
+    world.exchange::<(i32,)>(c, (3u8,)).unwrap();
+    assert_eq!(world.chunk::<Material>(c).unwrap(), &Material(1));
+
+    // Several chunk types may be combined
+    world.set_chunk(b, Layer(5)).unwrap();
+    world.set_chunk(b, Material(3)).unwrap();
+    assert_eq!(world.chunk::<Material>(b).unwrap(), &Material(3));
+    assert_eq!(world.chunk::<Layer>(b).unwrap(), &Layer(5));
+    assert_eq!(*world.get::<i32>(b).unwrap(), 2);
+    assert_eq!(world.remove_chunk::<Layer>(b), Ok(Layer(5)));
+    assert!(world.remove_chunk::<Layer>(b).is_err());
+    world.set_chunk(d, Material(3)).unwrap();
+
+    let mut query = world
+        .query::<(Chunk<Material>, &i32)>()
+        .iter()
+        .map(|(e, (m, &x))| (e, m.0, x))
+        .collect::<Vec<_>>();
+    query.sort_by_key(|x| x.2);
+    assert_eq!(query, [(a, 1, 1), (b, 3, 2), (d, 3, 4)]);
+
+    // Chunks survive moving between worlds and snapshots
+    let mut registry = SnapshotRegistry::new();
+    registry.register::<i32>();
+    registry.register::<u8>();
+    let snapshot = world.snapshot(&registry);
+    let mut other = World::new();
+    let b2 = world.take(&mut other, b).unwrap();
+    assert_eq!(other.chunk::<Material>(b2).unwrap(), &Material(3));
+    let map = other.merge(world);
+    assert_eq!(
+        other.chunk::<Material>(map.get(d).unwrap()).unwrap(),
+        &Material(3)
+    );
+    assert_eq!(other.query::<Chunk<Material>>().iter().count(), 4);
+
+    let mut world = World::new();
+    world.restore(&snapshot);
+    assert_eq!(world.chunk::<Material>(b).unwrap(), &Material(3));
+    world.despawn(c).unwrap();
+    world.compact();
+    assert_eq!(world.chunk::<Material>(a).unwrap(), &Material(1));
+    world.spawn_chunked(Material(1), (5,));
+    let shared = world
+        .archetypes()
+        .filter(|x| x.chunk::<Material>() == Some(&Material(1)))
+        .map(|x| x.len())
+        .collect::<Vec<_>>();
+    assert_eq!(shared, [2]);
+}
+
+#[test]
+fn sparse_components() {
+    type Stunned = std::sync::Arc<()>;
+    let counter = std::sync::Arc::new(());
+    let mut world = World::new();
+    world.register_sparse::<Stunned>();
+    let a = world.spawn((1, true));
+    let b = world.spawn((2, true, counter.clone()));
+    let archetypes = world.archetypes().len();
+
+    world.insert_one(a, counter.clone()).unwrap();
+    assert_eq!(world.archetypes().len(), archetypes);
+    assert_eq!(std::sync::Arc::strong_count(&counter), 3);
+    assert!(world.get::<Stunned>(a).is_ok());
+    assert!(world
+        .entity(a)
+        .unwrap()
+        .component_types()
+        .any(|x| x == std::any::TypeId::of::<Stunned>()));
+
+    let removed = world.remove_one::<Stunned>(a).unwrap();
+    assert_eq!(std::sync::Arc::strong_count(&counter), 3);
+    drop(removed);
+    assert!(world.get::<Stunned>(a).is_err());
+    assert_eq!(
+        world
+            .query::<(&i32, &Stunned)>()
+            .iter()
+            .map(|(e, _)| e)
+            .collect::<Vec<_>>(),
+        [b]
+    );
+    assert_eq!(
+        world
+            .query::<Without<Stunned, &i32>>()
+            .iter()
+            .map(|(e, _)| e)
+            .collect::<Vec<_>>(),
+        [a]
+    );
+    assert_eq!(
+        world
+            .query::<Option<&Stunned>>()
+            .iter()
+            .filter(|(_, x)| x.is_some())
+            .count(),
+        1
+    );
+    for (_, (x, _)) in world.query_mut::<(&mut i32, &mut Stunned)>() {
+        *x += 10;
+    }
+    assert_eq!(*world.get::<i32>(b).unwrap(), 12);
+    assert!(world.satisfies::<With<Stunned, ()>>(b).unwrap());
+
+    // Entities with only sparse components
+    let c = world.spawn((counter.clone(),));
+    assert_eq!(world.query::<&Stunned>().iter().count(), 2);
+
+    let mut registry = SnapshotRegistry::new();
+    registry.register::<i32>();
+    registry.register::<bool>();
+    registry.register::<Stunned>();
+    let snapshot = world.snapshot(&registry);
+
+    assert_eq!(std::sync::Arc::strong_count(&counter), 5);
+    world.despawn(b).unwrap();
+    assert_eq!(std::sync::Arc::strong_count(&counter), 4);
+    let mut other = World::new();
+    let c2 = world.take(&mut other, c).unwrap();
+    assert!(other.get::<Stunned>(c2).is_ok());
+    let map = world.merge(other);
+    assert!(world.get::<Stunned>(map.get(c2).unwrap()).is_ok());
+    world.clear();
+    assert_eq!(std::sync::Arc::strong_count(&counter), 3);
+
+    world.restore(&snapshot);
+    assert!(world.get::<Stunned>(b).is_ok());
+    assert!(world.get::<Stunned>(c).is_ok());
+    assert!(world.get::<Stunned>(a).is_err());
+    drop(snapshot);
+    drop(world);
+    assert_eq!(std::sync::Arc::strong_count(&counter), 1);
+}
+
+#[test]
+fn sparse_registration_invalidates_caches() {
+    struct Stunned;
+    struct Haste;
+    let mut world = World::new();
+    let a = world.spawn((1,));
+    let mut stunned = PreparedQuery::<&Stunned>::new();
+    let mut hasted = PreparedQuery::<&Haste>::new();
+    assert_eq!(stunned.query_mut(&mut world).count(), 0);
+    assert_eq!(hasted.query_mut(&mut world).count(), 0);
+    let generation = world.archetypes_generation();
+
+    world.register_sparse::<Stunned>();
+    assert!(world.archetypes_created_since(generation).is_none());
+    world.insert_one(a, Stunned).unwrap();
+    assert_eq!(stunned.query_mut(&mut world).count(), 1);
+
+    world.pin_components::<(Haste,)>(a).unwrap();
+    world.insert_one(a, Haste).unwrap();
+    assert_eq!(hasted.query_mut(&mut world).count(), 1);
+}
+
+#[test]
+fn zero_sized_components() {
+    #[repr(align(64))]
+    struct Marker;
+    static DROPS: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+    struct Noisy;
+    impl Drop for Noisy {
+        fn drop(&mut self) {
+            DROPS.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        }
+    }
+
+    let mut world = World::new();
+    let a = world.spawn((1u8,));
+    let b = world.spawn((2u8, Marker));
+    let plain = world
+        .archetypes()
+        .find(|x| x.has::<u8>() && !x.has::<Marker>())
+        .unwrap();
+    let marked = world.archetypes().find(|x| x.has::<Marker>()).unwrap();
+    // Only the change ticks of `Marker` take up space, regardless of its alignment
+    assert_eq!(plain.capacity(), marked.capacity());
+    assert_eq!(
+        marked.allocated_bytes(),
+        plain.allocated_bytes() + 2 * 4 * marked.capacity() as usize
+    );
+
+    world.insert_one(a, Noisy).unwrap();
+    world.insert_one(b, Noisy).unwrap();
+    world.remove_one::<Marker>(b).unwrap();
+    assert_eq!(DROPS.load(std::sync::atomic::Ordering::Relaxed), 0);
+    assert_eq!(world.query::<(&u8, &Noisy)>().iter().count(), 2);
+    world.despawn(a).unwrap();
+    assert_eq!(DROPS.load(std::sync::atomic::Ordering::Relaxed), 1);
+    world.remove_one::<Noisy>(b).unwrap();
+    assert_eq!(DROPS.load(std::sync::atomic::Ordering::Relaxed), 2);
+}
+
+#[test]
+fn query_pair_mut() {
+    let mut world = World::new();
+    let a = world.spawn((1, 1.0_f32, true));
+    let b = world.spawn((2, false));
+    let (ints, floats) = world.query_pair_mut::<(&mut i32, &bool), &mut f32>();
+    std::thread::scope(|s| {
+        s.spawn(move || {
+            for (_, (i, &flag)) in ints {
+                if flag {
+                    *i *= 10;
+                }
+            }
+        });
+        for (_, f) in floats {
+            *f += 1.0;
+        }
+    });
+    assert_eq!(*world.get::<i32>(a).unwrap(), 10);
+    assert_eq!(*world.get::<i32>(b).unwrap(), 2);
+    assert_eq!(*world.get::<f32>(a).unwrap(), 2.0);
+}
+
+#[test]
+#[should_panic(expected = "conflicting access")]
+fn query_pair_mut_conflict() {
+    let mut world = World::new();
+    world.query_pair_mut::<&mut i32, (&bool, &i32)>();
+}
+
+#[test]
+fn unsafe_world_cell() {
+    let mut world = World::new();
+    let a = world.spawn((1, true));
+    let b = world.spawn((2, false, "b"));
+    let cell = world.as_unsafe_cell();
+    std::thread::scope(|s| {
+        s.spawn(move || {
+            for (_, x) in unsafe { cell.query::<&mut i32>() } {
+                *x *= 10;
+            }
+        });
+        s.spawn(move || {
+            let (flag, name) = unsafe { cell.query_one::<(&mut bool, &&str)>(b) }.unwrap();
+            *flag = name.len() == 1;
+            assert!(unsafe { cell.query_one::<&&str>(a) }.is_err());
+        });
+    });
+    assert!(cell.contains(a));
+    assert_eq!(*world.get::<i32>(a).unwrap(), 10);
+    assert_eq!(*world.get::<i32>(b).unwrap(), 20);
+    assert!(*world.get::<bool>(b).unwrap());
+}
+
+#[test]
+fn disjoint_archetype_borrows() {
+    let mut world = World::new();
+    let a = world.spawn((1, true));
+    let b = world.spawn((2, 'b'));
+    let c = world.spawn((3,));
+
+    // Unique borrows of `i32` coexist so long as they cover disjoint sets of archetypes
+    {
+        let mut with_bool = world.query::<With<bool, &mut i32>>();
+        let mut without_bool = world.query::<Without<bool, &mut i32>>();
+        let with_bool = with_bool.iter().map(|(e, _)| e).collect::<Vec<_>>();
+        let mut without_bool = without_bool.iter().map(|(e, _)| e).collect::<Vec<_>>();
+        without_bool.sort();
+        assert_eq!(with_bool, [a]);
+        assert_eq!(without_bool, [b, c]);
+    }
+
+    let mut chars = world.query::<With<char, &mut i32>>();
+    let _chars = chars.iter().collect::<Vec<_>>();
+    *world.get_mut::<i32>(c).unwrap() += 1;
+}
+
+#[test]
+fn preserve_order() {
+    let mut world = World::new();
+    world.set_preserve_order(true);
+    let entities = (0..6).map(|i| world.spawn((i, 'x'))).collect::<Vec<_>>();
+    world.despawn(entities[1]).unwrap();
+    world.remove_one::<char>(entities[3]).unwrap();
+    world.insert_one(entities[0], true).unwrap();
+    world.despawn(entities[4]).unwrap();
+    let order = world
+        .query_mut::<&i32>()
+        .into_iter()
+        .map(|(_, &x)| x)
+        .collect::<Vec<_>>();
+    assert_eq!(order, [2, 5, 3, 0]);
+    for (i, &e) in entities.iter().enumerate() {
+        if let Ok(x) = world.get::<i32>(e) {
+            assert_eq!(*x, i as i32);
+        }
+    }
+}
+
+#[test]
+#[cfg(feature = "bytemuck")]
+fn column_bytes() {
+    let mut world = World::new();
+    let a = world.spawn((1u32,));
+    let b = world.spawn((2u32,));
+    world.spawn((3u32, true));
+    let mut column = world.columns_mut::<u32>().next().unwrap();
+    assert_eq!(column.entities().collect::<Vec<_>>(), [a, b]);
+    assert_eq!(column.as_bytes(), bytemuck::cast_slice::<u32, u8>(&[1, 2]));
+    column.copy_from_bytes(bytemuck::cast_slice::<u32, u8>(&[10, 20]));
+    drop(column);
+    assert_eq!(*world.get::<u32>(b).unwrap(), 20);
+}
+
+#[test]
+#[cfg(feature = "ffi")]
+fn ffi() {
+    use core::ffi::c_void;
+    use hecs::ffi::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static DROPS: AtomicUsize = AtomicUsize::new(0);
+    unsafe extern "C" fn count_drop(_: *mut c_void) {
+        DROPS.fetch_add(1, Ordering::Relaxed);
+    }
+    unsafe extern "C" fn sum(user: *mut c_void, _: EntityBits, components: *const *mut c_void) {
+        *user.cast::<u32>() += *(*components.add(1)).cast::<u32>();
+    }
+
+    unsafe {
+        let position = hecs_component_register(8, 4, None);
+        let health = hecs_component_register(4, 4, Some(count_drop));
+        assert_ne!(position, health);
+        assert_eq!(hecs_component_register(4, 3, None), HECS_INVALID_COMPONENT);
+
+        let world = hecs_world_new();
+        let a = hecs_spawn(world);
+        let b = hecs_spawn(world);
+        for (entity, hp) in [(a, 10u32), (b, 20)] {
+            let mut pos = [1.0f32, 2.0];
+            let mut hp = hp;
+            assert!(hecs_insert(
+                world,
+                entity,
+                position,
+                pos.as_mut_ptr().cast()
+            ));
+            assert!(hecs_insert(
+                world,
+                entity,
+                health,
+                (&mut hp as *mut u32).cast()
+            ));
+        }
+        assert!(!hecs_insert(
+            world,
+            a,
+            HECS_INVALID_COMPONENT,
+            core::ptr::null_mut()
+        ));
+        assert_eq!(
+            Entity::from_bits(a),
+            world.as_ref().unwrap().iter().next().unwrap().0
+        );
+
+        *hecs_get(world, b, health).cast::<u32>() += 5;
+        assert_eq!(*hecs_get(world, a, position).cast::<[f32; 2]>(), [1.0, 2.0]);
+
+        let mut total = 0u32;
+        let query = [position, health];
+        assert!(hecs_query_each(
+            world,
+            query.as_ptr(),
+            2,
+            sum,
+            (&mut total as *mut u32).cast()
+        ));
+        assert_eq!(total, 35);
+        assert!(!hecs_query_each(
+            world,
+            [health, health].as_ptr(),
+            2,
+            sum,
+            core::ptr::null_mut()
+        ));
+
+        assert!(hecs_remove(world, a, health));
+        assert!(hecs_get(world, a, health).is_null());
+        assert_eq!(DROPS.load(Ordering::Relaxed), 1);
+        assert!(hecs_despawn(world, a));
+        assert!(!hecs_contains(world, a));
+        hecs_world_free(world);
+        assert_eq!(DROPS.load(Ordering::Relaxed), 2);
+    }
+}
+
+#[test]
+fn named_components() {
+    #[derive(Debug, PartialEq)]
+    struct Position([f32; 2]);
+
+    let mut world = World::new();
+    world.register_named::<Position>("game::Position");
+    world.register_named::<Position>("game::Position");
+    world.register_named::<u32>("game::Health");
+    assert_eq!(
+        world.named_type("game::Health").unwrap().id(),
+        core::any::TypeId::of::<u32>()
+    );
+    assert!(world.named_type("game::Mana").is_none());
+
+    let e = world.spawn((Position([1.0, 2.0]),));
+    let mut health = 10u32;
+    unsafe {
+        world
+            .insert_named(e, "game::Health", (&mut health as *mut u32).cast())
+            .unwrap();
+        *world
+            .get_named_mut(e, "game::Health")
+            .unwrap()
+            .as_mut_ptr()
+            .cast::<u32>() += 5;
+    }
+    assert_eq!(*world.get::<u32>(e).unwrap(), 15);
+    assert!(world.get_named(e, "game::Mana").is_none());
+
+    let query = world
+        .named_query(&["game::Position"], &["game::Health"])
+        .unwrap();
+    assert_eq!(world.query_dynamic(&query).iter().count(), 1);
+
+    world.remove_named(e, "game::Position").unwrap();
+    assert!(world.get_named(e, "game::Position").is_none());
+    assert!(world.get::<Position>(e).is_err());
+}
+
+#[test]
+#[should_panic(expected = "already registered")]
+fn named_component_conflict() {
+    let mut world = World::new();
+    world.register_named::<u32>("a");
+    world.register_named::<i32>("a");
+}
+
+#[test]
+fn query_str() {
+    let mut world = World::new();
+    world.register_named::<u32>("Health");
+    world.register_named::<bool>("Dead");
+    world.register_named::<&'static str>("Name");
+    let a = world.spawn((10u32, "a"));
+    world.spawn((0u32, "b", true));
+    world.spawn(("c",));
+
+    let mut names = Vec::new();
+    for (e, mut row) in world
+        .query_str(" &Name ,& mut Health,!Dead")
+        .unwrap()
+        .iter()
+    {
+        assert_eq!(e, a);
+        assert_eq!(row.len(), 2);
+        unsafe {
+            names.push(*row.get(0).cast::<&str>());
+            *row.get_mut(1).unwrap().cast::<u32>() += 1;
+        }
+    }
+    assert_eq!(names, ["a"]);
+    assert_eq!(*world.get::<u32>(a).unwrap(), 11);
+    assert_eq!(world.query_str("").unwrap().iter().count(), 3);
+    assert_eq!(
+        world.parse_query("&mutName").unwrap_err(),
+        QueryParseError::UnknownComponent("mutName".into())
+    );
+
+    for bad in ["Health", "&", "&Health,", "!Dead Name", "&mut"] {
+        assert_eq!(
+            world.parse_query(bad).unwrap_err(),
+            QueryParseError::Syntax(bad.rsplit(',').next().unwrap().trim().into()),
+            "{}",
+            bad
+        );
+    }
+    assert_eq!(
+        world.parse_query("&Mana").unwrap_err(),
+        QueryParseError::UnknownComponent("Mana".into())
+    );
+}
+
+#[test]
+fn query_map() {
+    struct Combatant<'a> {
+        health: &'a i32,
+        effects: &'a mut Vec<&'static str>,
+    }
+
+    impl QueryView for Combatant<'static> {
+        type Query = (&'static i32, &'static mut Vec<&'static str>);
+    }
+
+    impl<'a> QueryViewItem<'a> for Combatant<'static> {
+        type Item = Combatant<'a>;
+        fn view((health, effects): (&'a i32, &'a mut Vec<&'static str>)) -> Combatant<'a> {
+            Combatant { health, effects }
+        }
+    }
+
+    let mut world = World::new();
+    let a = world.spawn((5, Vec::<&'static str>::new()));
+    let b = world.spawn((0, Vec::<&'static str>::new(), true));
+    world.spawn((1,));
+
+    let mut query = world
+        .query::<(&i32, &mut Vec<&'static str>)>()
+        .map::<Combatant>();
+    for (_, c) in query.iter() {
+        if *c.health == 0 {
+            c.effects.push("dead");
+        }
+    }
+    drop(query);
+    assert!(world.get::<Vec<&str>>(a).unwrap().is_empty());
+    assert_eq!(*world.get::<Vec<&str>>(b).unwrap(), ["dead"]);
+
+    let healths = world
+        .query_mut::<(&i32, &mut Vec<&'static str>)>()
+        .map::<Combatant>()
+        .with::<bool>()
+        .into_iter()
+        .map(|(_, c)| *c.health)
+        .collect::<Vec<_>>();
+    assert_eq!(healths, [0]);
+    assert_eq!(world.query::<Map<Combatant>>().iter().count(), 2);
+}
+
+#[test]
+fn spawn_batch_owned() {
+    let mut world = World::new();
+    let a = world.spawn_batch((0..10).map(|i| (i,)));
+    // Size hint of zero, so storage must grow as entities are spawned
+    let b = world.spawn_batch((10..100).filter(|_| true).map(|i| (i,)));
+    assert_eq!(a.len(), 10);
+    assert_eq!(b.len(), 90);
+    world.spawn((true,));
+    for (i, e) in a.chain(b).enumerate() {
+        assert_eq!(*world.get::<i32>(e).unwrap(), i as i32);
+    }
+
+    let mut c = world.spawn_batch((0..3).map(|i| (i, true)));
+    let last = c.next_back().unwrap();
+    assert_eq!(c.as_slice().len(), 2);
+    let rest = Vec::from(c);
+    assert_eq!(rest.len(), 2);
+    assert!(!rest.contains(&last));
+    world.despawn(last).unwrap();
+    assert_eq!(world.query::<&bool>().iter().count(), 3);
+}
+
+#[test]
+fn labels() {
+    let mut world = World::new();
+    let a = world.spawn((1,));
+    let b = world.spawn((2,));
+    assert_eq!(world.insert_label(a, "player").unwrap(), None);
+    assert_eq!(world.insert_label(a, "player").unwrap(), None);
+    assert_eq!(world.find_label("player"), Some(a));
+    assert_eq!(*world.label(a).unwrap(), *"player");
+    assert!(world.label(b).is_none());
+
+    // Labels are unique, and relabeling frees the old label
+    assert_eq!(world.insert_label(b, "player").unwrap(), Some(a));
+    assert!(world.label(a).is_none());
+    world.insert_label(b, "hero").unwrap();
+    assert_eq!(world.find_label("player"), None);
+    assert_eq!(world.find_label("hero"), Some(b));
+    assert_eq!(world.remove_label(b).unwrap().unwrap().as_str(), "hero");
+    assert_eq!(world.find_label("hero"), None);
+
+    world.insert_label(a, "a").unwrap();
+    world.insert_label(b, "b").unwrap();
+    world.despawn(a).unwrap();
+    assert_eq!(world.find_label("a"), None);
+    world.despawn_all::<&i32>();
+    assert_eq!(world.find_label("b"), None);
+
+    let c = world.spawn(());
+    world.insert_label(c, "c").unwrap();
+    let mut other = World::new();
+    let d = other.spawn(());
+    other.insert_label(d, "c").unwrap();
+    let e = other.spawn(());
+    other.insert_label(e, "e").unwrap();
+    let map = world.merge(other);
+    assert_eq!(world.find_label("c"), Some(c));
+    assert!(world.label(map.get(d).unwrap()).is_none());
+    assert_eq!(world.find_label("e"), map.get(e));
+
+    let mut other = World::new();
+    let moved = world.take(&mut other, c).unwrap();
+    assert_eq!(world.find_label("c"), None);
+    assert_eq!(other.find_label("c"), Some(moved));
+
+    world.clear();
+    assert_eq!(world.find_label("e"), None);
+}
+
+#[test]
+fn despawn_and_take() {
+    use std::sync::Arc;
+
+    struct Stunned;
+
+    let mut world = World::new();
+    world.register_sparse::<Stunned>();
+    let parent = world.spawn(());
+    let a = world.spawn((123, Arc::new(()), Stunned));
+    let child = world.spawn(());
+    world.attach(a, parent).unwrap();
+    world.attach(child, a).unwrap();
+    let b = world.spawn((456, Arc::new(())));
+
+    let mut taken = world.despawn_and_take(a).unwrap();
+    assert!(!world.contains(a));
+    assert_eq!(world.parent(child), None);
+    assert!(world.get::<Children>(parent).is_err());
+    assert_eq!(*world.get::<i32>(b).unwrap(), 456);
+    assert_eq!(taken.len(), 3);
+    assert!(taken.has::<Stunned>());
+    assert!(!taken.has::<Parent>() && !taken.has::<Children>());
+    *taken.get_mut::<i32>().unwrap() += 1;
+    let rc = taken.get::<Arc<()>>().unwrap().clone();
+    assert_eq!(Arc::strong_count(&rc), 2);
+
+    let c = world.spawn(taken);
+    assert_eq!(*world.get::<i32>(c).unwrap(), 124);
+    assert!(world.get::<Stunned>(c).is_ok());
+    drop(world.despawn_and_take(c).unwrap());
+    assert_eq!(Arc::strong_count(&rc), 1);
+
+    assert!(world.take_bundle::<(i32, bool)>(b).is_err());
+    assert!(world.contains(b));
+    let (x, _) = world.take_bundle::<(i32, Arc<()>)>(b).unwrap();
+    assert_eq!(x, 456);
+    assert!(world.despawn_and_take(b).is_err());
+}
+
+#[test]
+fn without_any() {
+    struct Hidden;
+    struct Culled;
+
+    let mut world = World::new();
+    world.register_sparse::<Culled>();
+    world.spawn((1, Hidden));
+    let b = world.spawn((2, Culled));
+    let c = world.spawn((3, true));
+    let d = world.spawn((4, true, Culled));
+    let mut entities = world
+        .query::<WithoutAny<(Hidden, Culled), &i32>>()
+        .iter()
+        .map(|(e, &i)| (e, i))
+        .collect::<Vec<_>>();
+    entities.sort_by_key(|&(_, i)| i);
+    assert_eq!(entities, [(c, 3)]);
+    assert_eq!(
+        world
+            .query::<&i32>()
+            .without_any::<(Hidden,)>()
+            .iter()
+            .count(),
+        3
+    );
+    assert_eq!(
+        world
+            .query_mut::<&i32>()
+            .without_any::<()>()
+            .into_iter()
+            .count(),
+        4
+    );
+    assert!(world
+        .query_one::<&i32>(d)
+        .unwrap()
+        .without_any::<(Culled, Hidden)>()
+        .get()
+        .is_none());
+    assert!(world
+        .query_one::<&i32>(b)
+        .unwrap()
+        .without_any::<(Hidden,)>()
+        .get()
+        .is_some());
+}
+
+#[test]
+fn archetype_edges_invalidated() {
+    struct Tag;
+
+    let mut world = World::new();
+    let a = world.spawn((1,));
+    world.insert_one(a, Tag).unwrap();
+    world.remove_one::<Tag>(a).unwrap();
+    world.insert_one(a, Tag).unwrap();
+    assert!(world.get::<Tag>(a).is_ok());
+    world.remove_one::<Tag>(a).unwrap();
+
+    // Tag is now stored sparsely, so inserting it must no longer change archetypes
+    world.register_sparse::<Tag>();
+    let archetypes = world.archetypes().len();
+    world.insert_one(a, Tag).unwrap();
+    assert_eq!(world.archetypes().len(), archetypes);
+    assert!(world.get::<Tag>(a).is_ok());
+    world.remove_one::<Tag>(a).unwrap();
+
+    // Renumbering archetypes must not leave stale edges behind
+    let b = world.spawn((true,));
+    world.insert_one(b, 'x').unwrap();
+    world.despawn(b).unwrap();
+    world.compact();
+    let b = world.spawn((false,));
+    world.insert_one(b, 'y').unwrap();
+    assert_eq!(*world.get::<char>(b).unwrap(), 'y');
+    assert!(!*world.get::<bool>(b).unwrap());
+    world.remove_one::<char>(b).unwrap();
+    assert_eq!(world.query::<(&bool, &char)>().iter().count(), 0);
+    assert_eq!(world.query::<&bool>().iter().count(), 1);
+}
+
+#[test]
+fn register_archetype() {
+    struct Stunned;
+
+    let mut world = World::new();
+    world.register_sparse::<Stunned>();
+    world.register_archetype::<(i32, Stunned)>(64);
+    world.register_archetype::<(i32, Stunned)>(16);
+    let archetype = world
+        .archetypes()
+        .find(|x| x.has::<i32>())
+        .expect("archetype registered");
+    assert!(!archetype.has::<Stunned>());
+    let capacity = archetype.capacity();
+    assert!(capacity >= 64);
+    assert_eq!(world.len(), 0);
+
+    let generation = world.archetypes_generation();
+    for i in 0..64 {
+        world.spawn((i, Stunned));
+    }
+    assert_eq!(world.archetypes_generation(), generation);
+    let archetype = world.archetypes().find(|x| x.has::<i32>()).unwrap();
+    assert_eq!(archetype.capacity(), capacity);
+}
+
+#[test]
+fn query_for_each() {
+    let mut world = World::new();
+    let a = world.spawn((1, true));
+    let b = world.spawn((2,));
+    world.spawn((3, "c"));
+    let c = world.spawn((4, false, 'x'));
+
+    let mut seen = Vec::new();
+    world
+        .query::<&mut i32>()
+        .without::<&str>()
+        .for_each(|(e, x)| {
+            *x *= 10;
+            seen.push(e);
+        });
+    seen.sort();
+    let mut expected = vec![a, b, c];
+    expected.sort();
+    assert_eq!(seen, expected);
+
+    let mut sum = 0;
+    world.query_mut::<&i32>().for_each(|(_, &x)| sum += x);
+    assert_eq!(sum, 73);
+
+    // Resuming a partially consumed iterator visits the rest exactly once
+    let mut query = world.query::<&i32>();
+    let mut iter = query.iter();
+    let first = *iter.next().unwrap().1;
+    assert_eq!(iter.fold(first, |acc, (_, &x)| acc + x), 73);
+}
+
+#[test]
+#[cfg(feature = "rand")]
+fn query_sample() {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    let mut rng = StdRng::seed_from_u64(0);
+    let mut world = World::new();
+    assert!(world.query::<&i32>().get_random(&mut rng).is_none());
+    let evens = (0..100)
+        .map(|i| {
+            if i % 2 == 0 {
+                world.spawn((i, true))
+            } else {
+                world.spawn((i,))
+            }
+        })
+        .step_by(2)
+        .collect::<Vec<_>>();
+    world.spawn(("unmatched",));
+
+    let mut hits = [0; 100];
+    for _ in 0..10_000 {
+        let (e, &x) = world.query::<&i32>().get_random(&mut rng).unwrap();
+        assert_eq!(*world.get::<i32>(e).unwrap(), x);
+        hits[x as usize] += 1;
+    }
+    assert!(hits.iter().all(|&n| n > 50));
+
+    // Filtered queries only ever yield matching entities
+    for _ in 0..100 {
+        let (e, _) = world
+            .query::<&i32>()
+            .with::<bool>()
+            .get_random(&mut rng)
+            .unwrap();
+        assert!(evens.contains(&e));
+    }
+
+    let mut sample = world
+        .query::<&mut i32>()
+        .sample(10, &mut rng)
+        .into_iter()
+        .map(|(e, x)| {
+            *x += 1000;
+            e
+        })
+        .collect::<Vec<_>>();
+    sample.sort();
+    sample.dedup();
+    assert_eq!(sample.len(), 10);
+    assert_eq!(
+        world
+            .query::<&i32>()
+            .iter()
+            .filter(|x| *x.1 >= 1000)
+            .count(),
+        10
+    );
+
+    // Asking for more than match yields every match
+    let mut query = world.query::<&i32>().with::<bool>();
+    assert_eq!(query.sample(80, &mut rng).len(), 50);
+}
+
+#[test]
+fn entity_ref_query() {
+    let mut world = World::new();
+    let a = world.spawn((1, true));
+    let b = world.spawn((2, 'b'));
+    world.clear_trackers();
+    *world.get_mut::<i32>(a).unwrap() += 10;
+
+    let entity = world.entity(a).unwrap();
+    {
+        let mut query = entity.query::<(&mut i32, Option<&char>, Or<&bool, &char>)>();
+        let (x, c, or) = query.get().unwrap();
+        *x += 1;
+        assert!(c.is_none());
+        assert_eq!(or.left(), Some(&true));
+    }
+    assert!(entity.query::<&i32>().changed::<i32>().get().is_some());
+    assert!(entity.query::<&i32>().added::<i32>().get().is_none());
+    assert!(entity.query::<&i32>().without::<bool>().get().is_none());
+    assert!(entity.query::<()>().with::<bool>().get().is_some());
+    assert!(entity.query::<&char>().get().is_none());
+
+    let entity = world.entity(b).unwrap();
+    assert!(entity
+        .query::<&i32>()
+        .without_any::<(bool, u8)>()
+        .changed::<i32>()
+        .get()
+        .is_none());
+    assert_eq!(*entity.query::<&i32>().get().unwrap(), 2);
+
+    let reserved = world.reserve_entity();
+    assert!(world
+        .entity(reserved)
+        .unwrap()
+        .query::<()>()
+        .get()
+        .is_none());
+}
+
+#[test]
+fn journal() {
+    let mut registry = SnapshotRegistry::new();
+    registry.register_eq::<i32>().register::<String>();
+    let mut journal = Journal::new(registry);
+    let mut world = World::new();
+    let a = world.spawn((1, "a".to_string()));
+    let b = world.spawn((2,));
+
+    journal.begin(&mut world);
+    assert!(journal.is_open());
+    *world.get_mut::<i32>(a).unwrap() = 10;
+    world.remove_one::<String>(a).unwrap();
+    world.despawn(b).unwrap();
+    let c = world.spawn((3,));
+    let t = journal.commit(&mut world);
+    assert!(!t.is_empty());
+    assert_eq!(t.redo().despawned(), [b]);
+    assert_eq!(t.undo().despawned(), [c]);
+    assert_eq!(t.undo().spawned().len(), 1);
+
+    journal.begin(&mut world);
+    world.insert_one(c, "c".to_string()).unwrap();
+    journal.commit(&mut world);
+    assert_eq!(journal.applied().len(), 2);
+
+    assert!(journal.undo(&mut world).unwrap());
+    assert!(world.get::<String>(c).is_err());
+    assert!(journal.undo(&mut world).unwrap());
+    assert!(!journal.undo(&mut world).unwrap());
+    assert_eq!(*world.get::<i32>(a).unwrap(), 1);
+    assert_eq!(*world.get::<String>(a).unwrap(), "a");
+    assert_eq!(*world.get::<i32>(b).unwrap(), 2);
+    assert!(!world.contains(c));
+    assert_eq!(journal.undone().len(), 2);
+
+    assert!(journal.redo(&mut world).unwrap());
+    assert_eq!(*world.get::<i32>(a).unwrap(), 10);
+    assert!(!world.contains(b));
+    assert_eq!(*world.get::<i32>(c).unwrap(), 3);
+
+    // Committing after an undo discards the undone transactions
+    journal.begin(&mut world);
+    journal.commit(&mut world);
+    assert_eq!(journal.undone().len(), 0);
+    assert!(!journal.redo(&mut world).unwrap());
+    assert!(journal.applied()[1].is_empty());
+}
+
+#[test]
+#[cfg(feature = "std")]
+fn non_send() {
+    use std::rc::Rc;
+
+    let mut world = World::new();
+    let shared = Rc::new(5);
+    let a = world.spawn((1,));
+    let b = world.spawn((2,));
+    world.insert_non_send(a, shared.clone()).unwrap();
+    world.insert_non_send(b, Rc::new(7)).unwrap();
+    world.insert_non_send(b, core::cell::Cell::new(0)).unwrap();
+    assert_eq!(Rc::strong_count(&shared), 2);
+
+    for (_, (x, rc)) in world.query_mut::<(&mut i32, NonSend<&Rc<i32>>)>() {
+        *x += **rc;
+    }
+    assert_eq!(*world.get::<i32>(a).unwrap(), 6);
+    assert_eq!(*world.get::<i32>(b).unwrap(), 9);
+
+    for (_, cell) in world.query::<NonSend<&mut core::cell::Cell<i32>>>().iter() {
+        cell.set(3);
+    }
+    let total = world
+        .query::<NonSend<&core::cell::Cell<i32>>>()
+        .iter()
+        .map(|(_, x)| x.get())
+        .sum::<i32>();
+    assert_eq!(total, 3);
+    assert_eq!(
+        world
+            .query_one::<NonSend<&Rc<i32>>>(a)
+            .unwrap()
+            .get()
+            .map(|x| **x),
+        Some(5)
+    );
+
+    assert!(Rc::ptr_eq(
+        &world.remove_non_send::<Rc<i32>>(a).unwrap(),
+        &shared
+    ));
+    assert!(world.remove_non_send::<Rc<i32>>(a).is_err());
+    world.despawn(b).unwrap();
+    assert_eq!(Rc::strong_count(&shared), 1);
+
+    // Components are bound to the thread that inserted them
+    world.insert_non_send(a, shared.clone()).unwrap();
+    let result = std::thread::spawn(move || {
+        world.query::<NonSend<&Rc<i32>>>().iter().count();
+    })
+    .join();
+    assert!(result.is_err());
+    // Leaked rather than dropped on the wrong thread
+    assert_eq!(Rc::strong_count(&shared), 2);
+}
+
+#[test]
+fn custom_allocator() {
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    #[derive(Default)]
+    struct Counting {
+        live: AtomicUsize,
+        total: AtomicUsize,
+    }
+
+    unsafe impl GlobalAlloc for Counting {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            self.live.fetch_add(layout.size(), Ordering::Relaxed);
+            self.total.fetch_add(1, Ordering::Relaxed);
+            System.alloc(layout)
+        }
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            self.live.fetch_sub(layout.size(), Ordering::Relaxed);
+            System.dealloc(ptr, layout)
+        }
+    }
+
+    let allocator = Arc::new(Counting::default());
+    let mut world = World::with_allocator(allocator.clone());
+    world.register_sparse::<bool>();
+    let entities = world
+        .spawn_batch((0..1000).map(|i| (i, i as f32)))
+        .into_vec();
+    for &e in &entities[..500] {
+        world.insert_one(e, true).unwrap();
+        world.insert_one(e, "moved").unwrap();
+    }
+    assert!(allocator.live.load(Ordering::Relaxed) >= 1000 * 8);
+
+    for &e in &entities {
+        world.despawn(e).unwrap();
+    }
+    world.shrink_to_fit();
+    let batches = {
+        world.spawn((1, 2.0f32));
+        world.extract::<&i32>()
+    };
+    drop(world);
+    assert!(allocator.live.load(Ordering::Relaxed) > 0);
+    drop(batches);
+    assert_eq!(allocator.live.load(Ordering::Relaxed), 0);
+    assert!(allocator.total.load(Ordering::Relaxed) > 2);
+}
+
+#[test]
+fn entity_mut() {
+    let mut world = World::new();
+    world.register_sparse::<char>();
+    let mut entity = world.spawn_empty();
+    entity.insert((1, "a")).insert_one('x');
+    assert_eq!(entity.get::<i32>(), Some(&1));
+    assert_eq!(entity.get::<char>(), Some(&'x'));
+    *entity.get_mut::<i32>().unwrap() += 1;
+    assert_eq!(entity.remove_one::<&str>(), Ok("a"));
+    assert!(!entity.has::<&str>());
+    assert!(entity.remove_one::<&str>().is_err());
+    assert_eq!(entity.query::<(&i32, &char)>(), Some((&2, &'x')));
+    assert!(entity.query::<(&i32, &bool)>().is_none());
+    let a = entity.id();
+    assert_eq!(entity.world().len(), 1);
+    assert_eq!(*world.get::<i32>(a).unwrap(), 2);
+
+    let b = world.spawn((3,));
+    let mut entity = world.entity_mut(b).unwrap();
+    assert_eq!(entity.remove::<(i32,)>().unwrap(), (3,));
+    assert!(entity.get::<i32>().is_none());
+    entity.despawn();
+    assert!(!world.contains(b));
+    assert!(world.entity_mut(b).is_err());
+
+    let reserved = world.reserve_entity();
+    world.entity_mut(reserved).unwrap().insert_one(5);
+    assert_eq!(*world.get::<i32>(reserved).unwrap(), 5);
+}
+
+#[test]
+fn remove_all() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    struct Marker(Arc<AtomicUsize>);
+    impl Drop for Marker {
+        fn drop(&mut self) {
+            self.0.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    let drops = Arc::new(AtomicUsize::new(0));
+    let mut world = World::new();
+    world.register_sparse::<u8>();
+    let mut entities = Vec::new();
+    for i in 0..10 {
+        let e = world.spawn((i, Marker(drops.clone())));
+        if i % 2 == 0 {
+            world.insert_one(e, true).unwrap();
+        }
+        if i % 3 == 0 {
+            world.insert_one(e, i as u8).unwrap();
+        }
+        entities.push(e);
+    }
+    let plain = world.spawn((100,));
+    world.despawn(entities[9]).unwrap();
+    assert_eq!(drops.load(Ordering::Relaxed), 1);
+    world.clear_trackers();
+    world.query_one_mut::<&mut i32>(entities[1]).unwrap();
+    world.record_events(true);
+
+    // Filtered to entities whose `i32` changed, which splits their archetype
+    assert_eq!(world.remove_all_matching::<Marker, Changed<i32, ()>>(), 1);
+    assert!(world.get::<Marker>(entities[1]).is_err());
+    assert_eq!(world.remove_all_matching::<Marker, With<bool, ()>>(), 5);
+    assert_eq!(drops.load(Ordering::Relaxed), 7);
+
+    let events = world.take_events();
+    assert_eq!(events.len(), 6);
+    assert!(events.iter().all(|x| matches!(
+        *x,
+        WorldEvent::ComponentRemoved { ty, .. } if ty == core::any::TypeId::of::<Marker>()
+    )));
+    assert_eq!(world.remove_all::<Marker>(), 3);
+    assert_eq!(drops.load(Ordering::Relaxed), 10);
+    assert!(world.query::<&Marker>().is_empty());
+    for (i, &e) in entities[..9].iter().enumerate() {
+        assert_eq!(*world.get::<i32>(e).unwrap(), i as i32);
+        assert_eq!(world.get::<bool>(e).is_ok(), i % 2 == 0);
+    }
+    assert_eq!(*world.get::<i32>(plain).unwrap(), 100);
+
+    // Sparse components are removed individually
+    assert_eq!(world.remove_all::<u8>(), 3);
+    assert!(world.query::<&u8>().is_empty());
+    assert_eq!(world.remove_all::<u8>(), 0);
+}
+
+#[test]
+fn transient() {
+    let mut world = World::new();
+    let a = world.spawn((1,));
+    world.insert_transient(a, "marker", 1).unwrap();
+    world.insert_transient(a, 2.0f32, 3).unwrap();
+    let event = world.spawn_transient((true,), 2);
+    let removed = world.spawn_transient((false,), 1);
+    world.despawn(removed).unwrap();
+
+    world.tick();
+    assert!(!world.satisfies::<&&str>(a).unwrap());
+    assert!(world.contains(event));
+
+    // Reinserting extends the expiry
+    world.insert_transient(a, 3.0f32, 3).unwrap();
+    world.tick();
+    assert!(!world.contains(event));
+    world.tick();
+    assert_eq!(*world.get::<f32>(a).unwrap(), 3.0);
+    world.tick();
+    assert!(!world.satisfies::<&f32>(a).unwrap());
+    assert_eq!(*world.get::<i32>(a).unwrap(), 1);
+}
+
+#[test]
+fn query_values() {
+    let mut world = World::new();
+    world.spawn((1, 2.0f32));
+    world.spawn((3, 4.0f32, true));
+    world.spawn((5,));
+    let mut sum = 0;
+    for n in world.query::<&i32>().with::<f32>().iter_values() {
+        sum += *n;
+    }
+    assert_eq!(sum, 4);
+    for (n, x) in world.query_mut::<(&i32, &mut f32)>().into_values() {
+        *x *= *n as f32;
+    }
+    let total = world
+        .query::<&f32>()
+        .iter_values()
+        .fold(0.0, |acc, &x| acc + x);
+    assert_eq!(total, 14.0);
+}
+
+#[test]
+fn query_nested() {
+    let mut world = World::new();
+    let a = world.spawn((0, 1.0f32));
+    let b = world.spawn((0, 2.0f32));
+    let c = world.spawn((0, 3.0f32, true));
+    let (outer, mut inner) = world.query_nested_mut::<&mut i32, &f32>();
+    for (e, n) in outer {
+        for (other, &x) in inner.iter() {
+            if other != e && x > 1.5 {
+                *n += 1;
+            }
+        }
+        assert!(inner.get(e).is_ok());
+    }
+    assert_eq!(*world.get::<i32>(a).unwrap(), 2);
+    assert_eq!(*world.get::<i32>(b).unwrap(), 1);
+    assert_eq!(*world.get::<i32>(c).unwrap(), 1);
+
+    let (_, mut inner) = world.query_nested_mut::<(), With<f32, &bool>>();
+    assert!(inner.get(c).is_ok());
+    assert_eq!(inner.get(a).unwrap_err(), QueryOneError::Unsatisfied);
+}
+
+#[test]
+#[should_panic(expected = "conflicting access")]
+fn query_nested_conflict() {
+    let mut world = World::new();
+    let _ = world.query_nested_mut::<&mut i32, &i32>();
+}
+
+#[test]
+fn validate_handles() {
+    let mut world = World::new();
+    let a = world.spawn((1,));
+    let b = world.spawn((2,));
+    let c = world.spawn((3,));
+    world.despawn(a).unwrap();
+    world.despawn(c).unwrap();
+    let d = world.spawn((4,));
+    assert_eq!(world.occupant(d.id()), Some(d));
+    let free = if d.id() == a.id() { c } else { a };
+    assert_eq!(world.occupant(free.id()), None);
+    let mut handles = vec![a, b, c, d, Entity::DANGLING];
+    assert_eq!(world.validate(&mut handles), 2);
+    assert_eq!(
+        handles,
+        [Entity::DANGLING, b, Entity::DANGLING, d, Entity::DANGLING]
+    );
+    assert_eq!(world.validate(&mut handles), 0);
+}
+
+#[test]
+fn removed_tracking() {
+    let mut world = World::new();
+    world.register_sparse::<u8>();
+    world.track_removed::<i32>();
+    world.track_removed::<u8>();
+    let a = world.spawn((1, 2u8));
+    let b = world.spawn((2, true));
+    let c = world.spawn((3,));
+    world.insert_one(c, 4).unwrap();
+    assert!(world.removed::<i32>().is_empty());
+
+    world.remove_one::<i32>(a).unwrap();
+    world.despawn(b).unwrap();
+    world.despawn(a).unwrap();
+    assert_eq!(world.removed::<i32>(), [a, b]);
+    assert_eq!(world.removed::<u8>(), [a]);
+    assert!(world.removed::<bool>().is_empty());
+
+    world.clear_trackers();
+    assert!(world.removed::<i32>().is_empty());
+    world.clear();
+    assert_eq!(world.removed::<i32>(), [c]);
+}
+
+#[test]
+fn column_batch_reuse() {
+    let mut world = World::new();
+    let mut ty = ColumnBatchType::new();
+    ty.add::<i32>().add::<String>();
+    let mut batch = {
+        let mut builder = ty.into_batch(2);
+        builder.fill([1, 2]);
+        builder.fill(["a".to_owned(), "b".to_owned()]);
+        builder.build().unwrap()
+    };
+    let first = world
+        .spawn_column_batch_from(&mut batch)
+        .collect::<Vec<_>>();
+    assert!(batch.is_empty());
+
+    let mut builder = batch.into_builder(3);
+    builder.fill([3, 4, 5]);
+    builder.fill(["c".to_owned(), "d".to_owned(), "e".to_owned()]);
+    let mut batch = builder.build().unwrap();
+    let second = world
+        .spawn_column_batch_from(&mut batch)
+        .collect::<Vec<_>>();
+    assert_eq!(*world.get::<String>(first[1]).unwrap(), "b");
+    assert_eq!(*world.get::<i32>(second[2]).unwrap(), 5);
+
+    let mut builder = batch.into_builder(1);
+    builder.fill([6]);
+    builder.fill(["f".to_owned()]);
+    let mut batch = builder.build().unwrap();
+    batch.clear();
+    assert!(batch.is_empty());
+    assert_eq!(world.spawn_column_batch_from(&mut batch).len(), 0);
+    assert_eq!(world.len(), 5);
+}
+
+#[test]
+fn query_archetype() {
+    let mut world = World::new();
+    let a = world.spawn((1, true));
+    let b = world.spawn((2, true));
+    let c = world.spawn((3,));
+    let id = world.archetype_id(a).unwrap();
+    assert_eq!(world.archetype_ids().len(), world.archetypes().len());
+    assert!(world.archetype_ids().any(|x| x == id));
+
+    for (_, x) in world.query_archetype_mut::<&mut i32>(id) {
+        *x *= 10;
+    }
+    assert_eq!(*world.get::<i32>(b).unwrap(), 20);
+    assert_eq!(*world.get::<i32>(c).unwrap(), 3);
+    assert_eq!(world.query_archetype::<&bool>(id).iter().count(), 2);
+    let other = world.archetype_id(c).unwrap();
+    assert_eq!(world.query_archetype::<&bool>(other).iter().count(), 0);
+
+    world.despawn(a).unwrap();
+    world.despawn(b).unwrap();
+    world.compact();
+    let id = world.archetype_id(c).unwrap();
+    assert_eq!(
+        world.query_archetype::<&i32>(id).iter().next().unwrap().0,
+        c
+    );
+    assert!(world.archetype_ids().all(|x| world.archetype(x).is_some()));
+}
+
+#[test]
+fn debug_entity() {
+    #[derive(Debug)]
+    struct Health(#[allow(dead_code)] u32);
+    struct Secret;
+
+    let mut world = World::new();
+    world.register_debug::<Health>();
+    world.register_sparse::<bool>();
+    world.register_debug::<bool>();
+    let a = world.spawn((Health(10), Secret));
+    world.insert_one(a, true).unwrap();
+    let description = format!("{:?}", world.debug_entity(a).unwrap());
+    assert!(
+        description.contains("Health: Health(10)"),
+        "{}",
+        description
+    );
+    assert!(description.contains("bool: true"), "{}", description);
+    assert!(description.contains(": .."), "{}", description);
+
+    let empty = world.spawn(());
+    assert_eq!(format!("{:?}", world.debug_entity(empty).unwrap()), "{}");
+    world.despawn(empty).unwrap();
+    assert!(world.debug_entity(empty).is_err());
+}
+
+#[test]
+#[cfg(feature = "std")]
+fn binary_round_trip() {
+    use std::convert::TryInto;
+
+    fn encode_u32(x: &u32, out: &mut Vec<u8>) {
+        out.extend_from_slice(&x.to_le_bytes());
+    }
+    fn decode_u32(bytes: &[u8]) -> Option<u32> {
+        Some(u32::from_le_bytes(bytes.try_into().ok()?))
+    }
+    fn encode_string(x: &String, out: &mut Vec<u8>) {
+        out.extend_from_slice(x.as_bytes());
+    }
+    fn decode_string(bytes: &[u8]) -> Option<String> {
+        String::from_utf8(bytes.to_vec()).ok()
+    }
+
+    let mut registry = BinaryRegistry::new();
+    registry
+        .register::<u32>("u32", 1, encode_u32, decode_u32)
+        .register::<String>("String", 1, encode_string, decode_string);
+
+    let mut world = World::new();
+    let a = world.spawn((1u32, "a".to_owned(), true));
+    let b = world.spawn((2u32,));
+    let c = world.spawn(("c".to_owned(),));
+    world.despawn(b).unwrap();
+    let b = world.spawn((3u32,));
+    world.spawn_at(Entity::from_bits(1 << EntityId::BITS | 100), (4u32,));
+    world.attach(c, a).unwrap();
+
+    let mut save = Vec::new();
+    world.save_to(&registry, &mut save).unwrap();
+
+    let loaded = World::load_from(&registry, &save[..]).unwrap();
+    assert_eq!(loaded.len(), world.len());
+    assert_eq!(*loaded.get::<u32>(a).unwrap(), 1);
+    assert_eq!(*loaded.get::<String>(a).unwrap(), "a");
+    assert!(loaded.get::<bool>(a).is_err());
+    assert_eq!(*loaded.get::<u32>(b).unwrap(), 3);
+    assert_eq!(*loaded.get::<String>(c).unwrap(), "c");
+    let far = Entity::from_bits(1 << EntityId::BITS | 100);
+    assert_eq!(*loaded.get::<u32>(far).unwrap(), 4);
+    assert_eq!(loaded.parent(c), Some(a));
+    assert_eq!(loaded.get::<Children>(a).unwrap().as_slice(), [c]);
+
+    // Components unknown to the loader are skipped
+    let mut older = BinaryRegistry::new();
+    older.register::<u32>("u32", 1, encode_u32, decode_u32);
+    let loaded = World::load_from(&older, &save[..]).unwrap();
+    assert_eq!(*loaded.get::<u32>(a).unwrap(), 1);
+    assert!(loaded.get::<String>(a).is_err());
+    assert!(loaded.contains(c));
+
+    // Known components saved with other versions are rejected
+    let mut newer = BinaryRegistry::new();
+    newer.register::<u32>("u32", 2, encode_u32, decode_u32);
+    assert!(World::load_from(&newer, &save[..]).is_err());
+
+    assert!(World::load_from(&registry, &save[..save.len() - 1]).is_err());
+    assert!(World::load_from(&registry, &b"not a world"[..]).is_err());
+
+    // Worlds saved with the other entity ID width are rejected
+    let mut other = save.clone();
+    other[..8].copy_from_slice(if cfg!(feature = "u64-index") {
+        b"HECSWRLD"
+    } else {
+        b"HECSWR64"
+    });
+    let err = World::load_from(&registry, &other[..]).unwrap_err();
+    assert!(err.to_string().contains("entity ID width"));
+}
+
+#[test]
+#[cfg(feature = "std")]
+fn binary_streaming() {
+    use std::convert::TryInto;
+    use std::io::{self, Read, Write};
+
+    /// A toy stream cipher, recording the largest write it receives
+    struct Xor<T> {
+        inner: T,
+        largest: usize,
+    }
+    impl<T: Write> Write for Xor<T> {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.largest = self.largest.max(buf.len());
+            let data = buf.iter().map(|x| x ^ 0x5a).collect::<Vec<_>>();
+            self.inner.write_all(&data)?;
+            Ok(buf.len())
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            self.inner.flush()
+        }
+    }
+    impl<T: Read> Read for Xor<T> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let n = self.inner.read(buf)?;
+            buf[..n].iter_mut().for_each(|x| *x ^= 0x5a);
+            Ok(n)
+        }
+    }
+
+    let mut registry = BinaryRegistry::new();
+    registry.register::<u32>(
+        "u32",
+        1,
+        |x, out| out.extend_from_slice(&x.to_le_bytes()),
+        |bytes| Some(u32::from_le_bytes(bytes.try_into().ok()?)),
+    );
+    let mut world = World::new();
+    world.spawn_batch((0..100u32).map(|i| (i,)));
+    world.spawn_batch((0..100u32).map(|i| (i, true)));
+    let a = world.spawn((7u32, 'a'));
+
+    let mut writer = Xor {
+        inner: Vec::new(),
+        largest: 0,
+    };
+    world.save_to(&registry, &mut writer).unwrap();
+    let largest = writer.largest;
+    let mut save = writer.inner;
+    // Written a block at a time
+    assert!(largest < save.len() / 2);
+    save.extend_from_slice(&[1, 2, 3].map(|x| x ^ 0x5a));
+
+    let mut reader = Xor {
+        inner: &save[..],
+        largest: 0,
+    };
+    let loaded = World::load_from(&registry, &mut reader).unwrap();
+    assert_eq!(loaded.len(), 201);
+    assert_eq!(*loaded.get::<u32>(a).unwrap(), 7);
+    let mut rest = Vec::new();
+    reader.read_to_end(&mut rest).unwrap();
+    assert_eq!(rest, [1, 2, 3]);
+}
+
+#[test]
+#[cfg(feature = "std")]
+fn binary_migration() {
+    #[derive(Debug, PartialEq)]
+    struct Position(f32, f32);
+
+    let mut old = BinaryRegistry::new();
+    old.register::<u8>(
+        "pos",
+        1,
+        |x, out| out.push(*x),
+        |bytes| bytes.first().copied(),
+    )
+    .register::<u16>(
+        "obsolete",
+        1,
+        |x, out| out.extend_from_slice(&x.to_le_bytes()),
+        |_| None,
+    );
+    let mut world = World::new();
+    let a = world.spawn((3u8, 7u16, true));
+    let b = world.spawn((5u8,));
+    let mut save = Vec::new();
+    world.save_to(&old, &mut save).unwrap();
+
+    let mut registry = BinaryRegistry::new();
+    registry
+        .register::<Position>("pos", 2, |_, _| {}, |_| None)
+        .migrate("pos", 1, |bytes, entity| {
+            entity.add(Position(f32::from(bytes[0]), 0.0));
+        })
+        .migrate("obsolete", 1, |_, _| {});
+    let loaded = World::load_from(&registry, &save[..]).unwrap();
+    assert_eq!(*loaded.get::<Position>(a).unwrap(), Position(3.0, 0.0));
+    assert_eq!(*loaded.get::<Position>(b).unwrap(), Position(5.0, 0.0));
+    assert!(loaded.get::<u8>(a).is_err());
+    assert_eq!(loaded.entity(a).unwrap().component_types().count(), 1);
+}
+
+#[test]
+#[cfg(feature = "parallel")]
+fn schedule_order() {
+    use hecs::schedule::Schedule;
+    use std::sync::Mutex;
+
+    struct Log(Mutex<Vec<&'static str>>);
+
+    let mut world = World::new();
+    world.insert_resource(Log(Mutex::new(Vec::new())));
+    let a = world.spawn((1, 1.0f32));
+    let mut schedule = Schedule::new();
+    schedule
+        .add_system(|q: QueryMut<&mut i32>, log: ResourceRef<Log>| {
+            for (_, x) in q {
+                *x *= 10;
+            }
+            log.0.lock().unwrap().push("write i32");
+        })
+        .add_system(
+            |q: QueryMut<&i32>, log: ResourceRef<Log>, cmd: &mut CommandBuffer| {
+                // Runs after the conflicting system before it
+                for (e, &x) in q {
+                    assert_eq!(x, 10);
+                    cmd.insert(e, ("inserted",));
+                }
+                log.0.lock().unwrap().push("read i32");
+            },
+        )
+        .add_system(|q: QueryMut<&&'static str>| {
+            // Runs before the sync point
+            assert_eq!(q.into_iter().count(), 0);
+        })
+        .add_sync()
+        .add_system(|q: QueryMut<&&'static str>, f: QueryMut<&mut f32>| {
+            assert_eq!(q.into_iter().count(), 1);
+            for (_, x) in f {
+                *x += 1.0;
+            }
+        });
+    schedule.run(&mut world);
+    assert_eq!(*world.get::<i32>(a).unwrap(), 10);
+    assert_eq!(*world.get::<f32>(a).unwrap(), 2.0);
+    assert_eq!(*world.get::<&str>(a).unwrap(), "inserted");
+    assert_eq!(
+        *world.resource::<Log>().unwrap().0.lock().unwrap(),
+        ["write i32", "read i32"]
+    );
+}
+
+#[test]
+#[cfg(feature = "parallel")]
+#[should_panic(expected = "conflicts with an earlier parameter")]
+fn schedule_param_conflict() {
+    hecs::schedule::Schedule::new().add_system(|_: QueryMut<&mut i32>, _: QueryMut<&i32>| {});
+}
+
+#[test]
+fn disabled_entities() {
+    let mut world = World::new();
+    let a = world.spawn((1, true));
+    let b = world.spawn((2, true));
+    let c = world.spawn((3,));
+    world.disable(b).unwrap();
+    world.disable(c).unwrap();
+    assert!(world.is_disabled(b).unwrap());
+    assert!(!world.is_disabled(a).unwrap());
+    assert_eq!(world.archetype_id(a), world.archetype_id(b));
+
+    let visible = world
+        .query::<&i32>()
+        .iter()
+        .map(|(e, _)| e)
+        .collect::<Vec<_>>();
+    assert_eq!(visible, [a]);
+    assert_eq!(world.query::<&i32>().count(), 1);
+    assert_eq!(world.query_mut::<&mut i32>().into_iter().count(), 1);
+    assert_eq!(world.query::<Including<Disabled, &i32>>().iter().count(), 3);
+    assert_eq!(
+        world.query::<(Including<Disabled>, &bool)>().iter().count(),
+        2
+    );
+    assert_eq!(*world.get::<i32>(b).unwrap(), 2);
+
+    world.enable(c).unwrap();
+    world.enable(c).unwrap();
+    assert!(!world.is_disabled(c).unwrap());
+    let mut visible = world
+        .query::<&i32>()
+        .iter()
+        .map(|(e, _)| e)
+        .collect::<Vec<_>>();
+    visible.sort();
+    assert_eq!(visible, [a, c]);
+
+    // An archetype whose entities all store `Disabled` directly is skipped wholesale
+    let d = world.spawn((4, Disabled));
+    assert!(world.is_disabled(d).unwrap());
+    assert_eq!(world.query::<&i32>().iter().count(), 2);
+
+    assert_eq!(world.remove_all::<bool>(), 2);
+    assert!(world.get::<bool>(b).is_err());
+    world.despawn(b).unwrap();
+    assert!(world.enable(b).is_err());
+}
+
+#[test]
+fn sort_archetype() {
+    let mut world = World::new();
+    let entities = [5, 3, 9, 1, 7]
+        .iter()
+        .map(|&z| world.spawn((z, z as f32 * 2.0)))
+        .collect::<Vec<_>>();
+    let other = world.spawn((4, "other"));
+    world.spawn((true,));
+    world.clear_trackers();
+
+    world.sort_archetype_by::<i32, _>(|&z| z);
+    let order = world
+        .query_mut::<With<f32, &i32>>()
+        .into_iter()
+        .map(|(_, &z)| z)
+        .collect::<Vec<_>>();
+    assert_eq!(order, [1, 3, 5, 7, 9]);
+    for &e in &entities {
+        let z = *world.get::<i32>(e).unwrap();
+        assert_eq!(*world.get::<f32>(e).unwrap(), z as f32 * 2.0);
+    }
+    assert_eq!(*world.get::<&str>(other).unwrap(), "other");
+    assert_eq!(world.query_mut::<Changed<i32, ()>>().into_iter().count(), 0);
+
+    // Descending keys, with ties keeping their current order
+    world.sort_archetype_by::<i32, _>(|&z| core::cmp::Reverse(z / 4));
+    let order = world
+        .query_mut::<With<f32, &i32>>()
+        .into_iter()
+        .map(|(_, &z)| z)
+        .collect::<Vec<_>>();
+    assert_eq!(order, [9, 5, 7, 1, 3]);
+    world.despawn(entities[2]).unwrap();
+    assert_eq!(*world.get::<i32>(entities[4]).unwrap(), 7);
+}
+
+#[test]
+fn component_index() {
+    #[derive(Debug, Clone, Copy, Hash, Eq, PartialEq)]
+    struct Cell(i32, i32);
+
+    let mut world = World::new();
+    let a = world.spawn((Cell(0, 0),));
+    world.index_component::<Cell>();
+    let b = world.spawn((Cell(0, 0), true));
+    let c = world.spawn((Cell(1, 0),));
+    let sorted = |x: &[Entity]| {
+        let mut x = x.to_vec();
+        x.sort();
+        x
+    };
+
+    let index = world.component_index::<Cell>().unwrap();
+    assert_eq!(sorted(index.get(&Cell(0, 0))), sorted(&[a, b]));
+    assert_eq!(index.get(&Cell(1, 0)), [c]);
+    assert_eq!(index.len(), 2);
+    world.clear_trackers();
+
+    // Mutation, replacement, removal, and despawning are all noticed
+    for (_, cell) in world.query_mut::<&mut Cell>() {
+        cell.0 += 1;
+    }
+    world.insert_one(a, Cell(5, 5)).unwrap();
+    world.clear_trackers();
+    world.remove_one::<Cell>(b).unwrap();
+    world.disable(c).unwrap();
+    let index = world.component_index::<Cell>().unwrap();
+    assert_eq!(index.get(&Cell(5, 5)), [a]);
+    assert!(index.get(&Cell(1, 0)).is_empty());
+    assert_eq!(index.get(&Cell(2, 0)), [c]);
+    world.clear_trackers();
+    world.despawn(a).unwrap();
+    world.insert_one(b, Cell(2, 0)).unwrap();
+    let index = world.component_index::<Cell>().unwrap();
+    assert!(index.get(&Cell(5, 5)).is_empty());
+    assert_eq!(sorted(index.get(&Cell(2, 0))), sorted(&[b, c]));
+
+    world.clear();
+    assert!(world.component_index::<Cell>().unwrap().is_empty());
+    assert!(world.component_index::<i32>().is_none());
+}
+
+#[test]
+fn spawn_columns() {
+    let mut world = World::new();
+    let names = vec!["a".to_owned(), "b".to_owned()];
+    let entities = world
+        .spawn_columns((vec![1, 2], names, vec![(); 2]))
+        .collect::<Vec<_>>();
+    assert_eq!(entities.len(), 2);
+    assert_eq!(*world.get::<i32>(entities[1]).unwrap(), 2);
+    assert_eq!(*world.get::<String>(entities[0]).unwrap(), "a");
+    assert!(world.satisfies::<&()>(entities[0]).unwrap());
+    assert_eq!(world.spawn_columns((Vec::<u8>::new(),)).count(), 0);
+}
+
+#[test]
+#[should_panic(expected = "columns must have equal lengths")]
+fn spawn_columns_mismatched() {
+    World::new().spawn_columns((vec![1, 2], vec![true]));
+}
+
+#[test]
+#[should_panic(expected = "duplicate component type")]
+fn spawn_columns_duplicate() {
+    World::new().spawn_columns((vec![1, 2], vec![3, 4]));
+}
+
+#[test]
+fn iter_slices() {
+    let mut world = World::new();
+    let a = world.spawn((1, 1.0f32));
+    let b = world.spawn((2, 2.0f32));
+    let c = world.spawn((3, 3.0f32));
+    let d = world.spawn((4, 4.0f32, true));
+    world.spawn((5,));
+    world.disable(b).unwrap();
+    let mut runs = world
+        .query::<(&i32, &mut f32)>()
+        .iter_slices()
+        .map(|(entities, (ns, xs))| {
+            for (&n, x) in ns.iter().zip(&mut *xs) {
+                *x += n as f32;
+            }
+            (entities.collect::<Vec<_>>(), ns.to_vec())
+        })
+        .collect::<Vec<_>>();
+    runs.sort();
+    assert_eq!(
+        runs,
+        [(vec![a], vec![1]), (vec![c], vec![3]), (vec![d], vec![4])]
+    );
+    assert_eq!(*world.get::<f32>(c).unwrap(), 6.0);
+    assert_eq!(*world.get::<f32>(b).unwrap(), 2.0);
+
+    let mut query = world.query::<(&i32, Option<&f32>)>();
+    let runs = query
+        .iter_slices()
+        .map(|(entities, (_, xs))| (entities.len(), xs.is_some()))
+        .collect::<Vec<_>>();
+    assert!(runs.contains(&(1, false)));
+}
+
+#[test]
+fn iter_chunks() {
+    let mut world = World::new();
+    let entities = world
+        .spawn_batch((0..10).map(|i| (i as u8, i as f64)))
+        .collect::<Vec<_>>();
+    world.disable(entities[5]).unwrap();
+    let mut query = world.query::<(&u8, &mut f64)>();
+    let chunks = query
+        .iter_chunks::<4>()
+        .map(|(ids, (ns, xs))| {
+            if ns[0] % 4 == 0 {
+                assert_eq!(ns.as_ptr() as usize % 4, 0);
+                assert_eq!(xs.as_ptr() as usize % 32, 0);
+            }
+            (ids.collect::<Vec<_>>(), ns.to_vec())
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        chunks,
+        [
+            (entities[0..4].to_vec(), vec![0, 1, 2, 3]),
+            (entities[4..5].to_vec(), vec![4]),
+            (entities[6..8].to_vec(), vec![6, 7]),
+            (entities[8..10].to_vec(), vec![8, 9]),
+        ]
+    );
+}
+
+#[test]
+#[should_panic(expected = "can't be borrowed as a slice")]
+fn iter_slices_sparse() {
+    let mut world = World::new();
+    world.register_sparse::<bool>();
+    world.spawn((1, true));
+    for _ in world.query::<(&i32, &bool)>().iter_slices() {}
+}
+
+#[test]
+fn world_delta() {
+    use hecs::testing::{assert_world_eq, WorldDelta};
+
+    let mut a = World::new();
+    a.register_eq::<i32>();
+    let e = a.spawn((1, "x"));
+    let mut b = World::new();
+    b.spawn_at(e, (1, "y"));
+    assert_world_eq(&a, &b);
+
+    let f = a.spawn((2,));
+
+    b.spawn_at(f, (3, true));
+    let g = b.spawn(());
+    let delta = WorldDelta::between(&a, &b);
+    assert_eq!(delta.only_in_a(), []);
+    assert_eq!(delta.only_in_b(), [g]);
+    let differences = delta.differences();
+    assert_eq!(differences.len(), 2);
+    assert!(differences.iter().all(|x| x.entity() == f));
+    let value = differences
+        .iter()
+        .find(|x| x.type_id() == std::any::TypeId::of::<i32>())
+        .unwrap();
+    assert_eq!((value.a(), value.b()), (Some("2"), Some("3")));
+    let added = differences
+        .iter()
+        .find(|x| x.type_id() == std::any::TypeId::of::<bool>())
+        .unwrap();
+    assert_eq!(
+        (added.type_name(), added.a(), added.b()),
+        (None, None, Some(".."))
+    );
+}
+
+#[test]
+#[should_panic(expected = "worlds differ")]
+fn assert_world_eq_panics() {
+    let a = World::new();
+    let mut b = World::new();
+    b.spawn((1,));
+    hecs::testing::assert_world_eq(&a, &b);
+}
+
+#[test]
+fn double_buffered() {
+    let mut world = World::new();
+    world.register_double_buffered::<i32>();
+    let a = world.spawn((1,));
+    let b = world.spawn((10, true));
+    assert!(world.get::<Prev<i32>>(a).is_err());
+    world.tick();
+    for (_, (x, prev)) in world.query_mut::<(&mut i32, &Prev<i32>)>() {
+        *x += prev.0;
+    }
+    assert_eq!(*world.get::<i32>(b).unwrap(), 20);
+    world.remove_one::<i32>(a).unwrap();
+    world.tick();
+    assert!(world.get::<Prev<i32>>(a).is_err());
+    assert_eq!(world.get::<Prev<i32>>(b).unwrap().0, 20);
+}
+
+#[test]
+fn archetypes_created_since() {
+    let mut world = World::new();
+    let generation = world.archetypes_generation();
+    let a = world.spawn((1,));
+    let b = world.spawn((2, true));
+    let created = world
+        .archetypes_created_since(generation)
+        .unwrap()
+        .collect::<Vec<_>>();
+    assert_eq!(
+        created,
+        [
+            world.archetype_id(a).unwrap(),
+            world.archetype_id(b).unwrap()
+        ]
+    );
+
+    let generation = world.archetypes_generation();
+    world.despawn(b).unwrap();
+    assert!(world.compact() > 0);
+    assert!(world.archetypes_created_since(generation).is_none());
+    assert!(World::new()
+        .archetypes_created_since(world.archetypes_generation())
+        .is_none());
+}
+
+#[test]
+fn query_one_unchecked() {
+    let mut world = World::new();
+    let a = world.spawn((1, true));
+    let b = world.spawn(("b",));
+    unsafe {
+        *world.query_one_unchecked::<&mut i32>(a).unwrap() += 1;
+        assert!(matches!(
+            world.query_one_unchecked::<&i32>(b),
+            Err(QueryOneError::Unsatisfied)
+        ));
+    }
+    world.despawn(a).unwrap();
+    assert!(matches!(
+        unsafe { world.query_one_unchecked::<&i32>(a) },
+        Err(QueryOneError::NoSuchEntity)
+    ));
+}
+
+#[test]
+fn get_or_insert_with() {
+    let mut world = World::new();
+    let a = world.spawn((1, true));
+    *world.get_or_insert_with(a, || 10).unwrap() += 1;
+    assert_eq!(*world.get::<i32>(a).unwrap(), 2);
+    assert_eq!(*world.get_or_insert_with(a, || "x").unwrap(), "x");
+    assert!(*world.get::<bool>(a).unwrap());
+    world.despawn(a).unwrap();
+    assert!(world.get_or_insert_with(a, || 0).is_err());
+}
+
+#[test]
+fn or_default() {
+    #[derive(Clone, Default, Debug, PartialEq)]
+    struct Scale(u32);
+
+    let mut world = World::new();
+    let a = world.spawn((Scale(3), true));
+    let b = world.spawn((true,));
+    let mut scales = world
+        .query::<With<bool, OrDefault<&Scale>>>()
+        .iter()
+        .map(|(e, x)| (e, x.into_owned()))
+        .collect::<Vec<_>>();
+    scales.sort_by_key(|(e, _)| *e);
+    assert_eq!(scales, [(a, Scale(3)), (b, Scale(0))]);
+    assert!(QueryAccess::of::<OrDefault<&Scale>>()
+        .reads()
+        .contains(&std::any::TypeId::of::<Scale>()));
+}
+
+#[derive(Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct Pos(i32);
+
+#[derive(Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct Hp(u32);
+
+#[cfg(not(feature = "serde"))]
+ecs_world! {
+    struct ClosedWorld { Pos, Hp }
+}
+
+#[cfg(feature = "serde")]
+ecs_world! {
+    #[serde]
+    struct ClosedWorld { Pos, Hp }
+}
+
+#[test]
+fn closed_world() {
+    let mut world = ClosedWorld::new();
+    let a = world.spawn((Pos(1), Hp(10)));
+    let b = world.spawn((Pos(2),));
+    world.insert_one(b, Hp(20)).unwrap();
+    world.despawn(a).unwrap();
+    let c = world.spawn((Hp(30),));
+    let old = world.snapshot();
+    for (_, hp) in world.query_mut::<&mut Hp>() {
+        hp.0 += 1;
+    }
+    world.remove_one::<Pos>(b).unwrap();
+    let diff = world.diff(&old);
+    assert_eq!(diff.changed().len(), 2);
+    assert_eq!(diff.removed().len(), 1);
+
+    let copy = world.clone();
+    assert_eq!(copy.len(), 2);
+    assert_eq!(copy.get::<Hp>(b).unwrap().0, 21);
+    assert_eq!(copy.get::<Hp>(c).unwrap().0, 31);
+    assert!(!copy.contains(a));
+    assert_eq!(world.into_inner().len(), 2);
+}
+
+#[test]
+// `serde_test` has no token for the `u128` handles of `u64-index`
+#[cfg(all(feature = "serde", not(feature = "u64-index")))]
+fn closed_world_serde() {
+    use serde_test::Token;
+
+    struct Saved(ClosedWorld);
+
+    impl serde::Serialize for Saved {
+        fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            self.0.serialize(serializer)
+        }
+    }
+
+    impl<'de> serde::Deserialize<'de> for Saved {
+        fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            ClosedWorld::deserialize(deserializer).map(Saved)
+        }
+    }
+
+    impl PartialEq for Saved {
+        fn eq(&self, other: &Self) -> bool {
+            self.0.len() == other.0.len()
+                && self.0.iter().all(|(e, x)| {
+                    other.0.entity(e).is_ok_and(|y| {
+                        x.get::<Pos>().as_deref() == y.get::<Pos>().as_deref()
+                            && x.get::<Hp>().as_deref() == y.get::<Hp>().as_deref()
+                    })
+                })
+        }
+    }
+
+    impl core::fmt::Debug for Saved {
+        fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+            f.write_str("Saved")
+        }
+    }
+
+    let mut world = ClosedWorld::new();
+    let a = world.spawn((Pos(1), Hp(2)));
+    serde_test::assert_tokens(
+        &Saved(world),
+        &[
+            Token::Map { len: Some(1) },
+            Token::U64(a.to_bits()),
+            Token::Map { len: None },
+            Token::Str("Pos"),
+            Token::NewtypeStruct { name: "Pos" },
+            Token::I32(1),
+            Token::Str("Hp"),
+            Token::NewtypeStruct { name: "Hp" },
+            Token::U32(2),
+            Token::MapEnd,
+            Token::MapEnd,
+        ],
+    );
+}
+
+#[test]
+fn propagate_transforms() {
+    use hecs::transform::{propagate_transforms, GlobalTransform, LocalTransform};
+
+    let mut world = World::new();
+    let root = world.spawn((LocalTransform(2),));
+    let child = world.spawn((LocalTransform(3),));
+    let grandchild = world.spawn((LocalTransform(5),));
+    let other = world.spawn((LocalTransform(7),));
+    world.attach(child, root).unwrap();
+    world.attach(grandchild, child).unwrap();
+    let global = |world: &World, e| world.get::<GlobalTransform<i32>>(e).unwrap().0;
+
+    propagate_transforms::<i32>(&mut world);
+    assert_eq!(global(&world, root), 2);
+    assert_eq!(global(&world, child), 6);
+    assert_eq!(global(&world, grandchild), 30);
+    assert_eq!(global(&world, other), 7);
+
+    // Clean subtrees aren't revisited
+    world.clear_trackers();
+    world.get_mut::<GlobalTransform<i32>>(other).unwrap().0 = 0;
+    world.clear_trackers();
+    world.get_mut::<LocalTransform<i32>>(child).unwrap().0 = 11;
+    propagate_transforms::<i32>(&mut world);
+    assert_eq!(global(&world, root), 2);
+    assert_eq!(global(&world, grandchild), 110);
+    assert_eq!(global(&world, other), 0);
+
+    // Reparenting
+    world.clear_trackers();
+    world.attach(child, other).unwrap();
+    propagate_transforms::<i32>(&mut world);
+    assert_eq!(global(&world, child), 0);
+    assert_eq!(global(&world, grandchild), 0);
+
+    // Detaching, and losing a local transform
+    world.clear_trackers();
+    world.detach(child).unwrap();
+    world.remove_one::<LocalTransform<i32>>(root).unwrap();
+    propagate_transforms::<i32>(&mut world);
+    assert_eq!(global(&world, child), 11);
+    assert_eq!(global(&world, grandchild), 55);
+    assert!(world.get::<GlobalTransform<i32>>(root).is_err());
+}
+
+#[test]
+fn clone_entity() {
+    let mut world = World::new();
+    world.register_sparse::<bool>();
+    let mut registry = SnapshotRegistry::new();
+    registry
+        .register::<i32>()
+        .register::<String>()
+        .register::<bool>();
+    let parent = world.spawn((1,));
+    let a = world.spawn((2, String::from("abc"), true));
+    world.attach(a, parent).unwrap();
+    let child = world.spawn((3,));
+    world.attach(child, a).unwrap();
+
+    let b = world.clone_entity(&registry, a).unwrap();
+    assert_eq!(*world.get::<i32>(b).unwrap(), 2);
+    assert_eq!(*world.get::<String>(b).unwrap(), "abc");
+    assert!(*world.get::<bool>(b).unwrap());
+    assert_eq!(world.parent(b), Some(parent));
+    assert_eq!(world.get::<Children>(parent).unwrap().as_slice(), [a, b]);
+    assert!(world.get::<Children>(b).is_err());
+    assert_eq!(world.get::<Children>(a).unwrap().as_slice(), [child]);
+
+    let clones = world
+        .clone_entities(&registry, [parent, child, parent])
+        .unwrap();
+    assert_eq!(clones.len(), 3);
+    assert!(world.get::<Children>(clones[0]).is_err());
+    assert_eq!(
+        world.archetype_id(clones[1]).unwrap(),
+        world.archetype_id(child).unwrap()
+    );
+    assert_eq!(*world.get::<i32>(clones[0]).unwrap(), 1);
+    assert_eq!(*world.get::<i32>(clones[1]).unwrap(), 3);
+    assert_eq!(world.parent(clones[1]), Some(a));
+    assert_eq!(world.len(), 7);
+
+    world.despawn(b).unwrap();
+    assert_eq!(world.clone_entities(&registry, [a, b]), Err(NoSuchEntity));
+    assert_eq!(world.len(), 6);
+}
+
+#[test]
+#[should_panic(expected = "not registered for cloning")]
+fn clone_entity_unregistered() {
+    let mut world = World::new();
+    let a = world.spawn((1, true));
+    let mut registry = SnapshotRegistry::new();
+    registry.register::<i32>();
+    let _ = world.clone_entity(&registry, a);
+}
+
+#[test]
+fn clone_entity_panic() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    #[derive(Clone)]
+    struct Sparse(#[allow(dead_code)] Fragile);
+
+    let budget = Arc::new(AtomicUsize::new(0));
+    let mut world = World::new();
+    world.register_sparse::<Sparse>();
+    let mut registry = SnapshotRegistry::new();
+    registry
+        .register::<i32>()
+        .register::<Fragile>()
+        .register::<Sparse>();
+    let a = world.spawn((1, Fragile(budget.clone())));
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        world.clone_entity(&registry, a)
+    }));
+    assert!(result.is_err());
+    assert_eq!(world.len(), 1);
+    assert_eq!(world.query::<&i32>().iter().count(), 1);
+    assert_eq!(Arc::strong_count(&budget), 2);
+
+    // A copy lacking only the sparse component is left behind
+    budget.store(0, Ordering::Relaxed);
+    let b = world.spawn((2, Sparse(Fragile(budget.clone()))));
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        world.clone_entity(&registry, b)
+    }));
+    assert!(result.is_err());
+    assert_eq!(world.len(), 3);
+    assert_eq!(world.query::<&i32>().iter().count(), 3);
+    assert_eq!(world.query::<&Sparse>().iter().count(), 1);
+    assert_eq!(Arc::strong_count(&budget), 3);
+}
+
+#[test]
+fn collect_into() {
+    let mut world = World::new();
+    let a = world.spawn((1,));
+    let b = world.spawn((2, true));
+    let c = world.spawn((3, true));
+    world.disable(c).unwrap();
+    world.spawn(("abc",));
+
+    let mut entities = vec![c];
+    world.query::<&i32>().entities_into(&mut entities);
+    assert_eq!(entities, [c, a, b]);
+    entities.clear();
+    world
+        .query::<&i32>()
+        .without::<bool>()
+        .entities_into(&mut entities);
+    assert_eq!(entities, [a]);
+
+    let mut items = Vec::new();
+    let mut query = world.query::<&mut i32>();
+    query.collect_into(&mut items);
+    assert_eq!(items.len(), 2);
+    for (_, x) in items {
+        *x *= 10;
+    }
+    drop(query);
+    assert_eq!(*world.get::<i32>(b).unwrap(), 20);
+}
+
+#[test]
+fn component_pool() {
+    let mut world = World::new();
+    world.register_sparse::<Vec<u8>>();
+    world.register_pool::<String>(2);
+    world.register_pool::<Vec<u8>>(8);
+    assert!(world.pool_stats::<i32>().is_none());
+
+    let a = world.spawn((String::from("a"), 1));
+    let b = world.spawn((String::from("b"), vec![1u8]));
+    world.insert_one(a, String::from("c")).unwrap();
+    world.despawn(b).unwrap();
+    assert_eq!(world.remove_one::<String>(a).unwrap(), "c");
+    world.spawn((String::from("d"),));
+    world.clear();
+
+    let stats = world.pool_stats::<String>().unwrap();
+    assert_eq!(stats.capacity(), 2);
+    assert_eq!(stats.pooled(), 2);
+    assert_eq!(stats.recycled(), 2);
+    assert_eq!(stats.dropped(), 1);
+    assert_eq!(world.take_pooled::<String>().unwrap(), "b");
+    assert_eq!(world.take_pooled::<String>().unwrap(), "a");
+    assert!(world.take_pooled::<String>().is_none());
+    assert_eq!(world.pool_stats::<String>().unwrap().reused(), 2);
+    assert_eq!(world.take_pooled::<Vec<u8>>().unwrap(), [1]);
+
+    let entities = (0..3)
+        .map(|i| world.spawn((String::from("x"), i)))
+        .collect::<Vec<_>>();
+    world.despawn_all::<&i32>();
+    assert!(entities.iter().all(|&e| !world.contains(e)));
+    assert_eq!(world.pool_stats::<String>().unwrap().pooled(), 2);
+}
+
+#[test]
+fn split_fill() {
+    let mut ty = ColumnBatchType::new();
+    ty.add::<u32>().add::<String>();
+    let mut builder = ty.into_batch(10);
+    let mut split = builder.split_fill(3);
+    let ranges = split.chunks().iter().map(|x| x.range()).collect::<Vec<_>>();
+    assert_eq!(ranges, [0..3, 3..6, 6..10]);
+    std::thread::scope(|scope| {
+        for chunk in split.chunks() {
+            scope.spawn(move || {
+                let range = chunk.range();
+                chunk.fill(range.clone());
+                // Leave the middle chunk's strings incomplete
+                let n = if range.start == 3 { 1 } else { range.len() };
+                chunk.fill(range.take(n).map(|i| i.to_string()));
+            });
+        }
+    });
+    assert_eq!(split.finish(), 4);
+    assert!(builder.build().is_err());
+
+    let mut ty = ColumnBatchType::new();
+    ty.add::<u32>().add::<String>();
+    let mut builder = ty.into_batch(5);
+    let mut split = builder.split_fill(8);
+    for chunk in split.chunks() {
+        let range = chunk.range();
+        chunk.fill(range.clone());
+        chunk.fill(range.map(|i| i.to_string()));
+    }
+    drop(split);
+    let mut world = World::new();
+    let entities = world
+        .spawn_column_batch(builder.build().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(*world.get::<String>(entities[4]).unwrap(), "4");
+}
+
+#[test]
+fn split_fill_swapped_chunks() {
+    let mut ty = ColumnBatchType::new();
+    ty.add::<u32>();
+    let mut small = ty.into_batch(2);
+    let mut ty = ColumnBatchType::new();
+    ty.add::<u32>();
+    let mut large = ty.into_batch(100);
+    let mut small_split = small.split_fill(1);
+    let mut large_split = large.split_fill(1);
+    core::mem::swap(&mut small_split.chunks()[0], &mut large_split.chunks()[0]);
+    // Fills the large builder's storage through the small builder's split
+    let chunk = &mut small_split.chunks()[0];
+    let range = chunk.range();
+    chunk.fill(range);
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| small_split.finish()));
+    assert!(result.is_err());
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| drop(large_split)));
+    assert!(result.is_err());
+    // Components written to swapped chunks are forgotten
+    assert!(small.build().unwrap().is_empty());
+    assert!(large.build().unwrap().is_empty());
+
+    let mut ty = ColumnBatchType::new();
+    ty.add::<u32>();
+    let mut builder = ty.into_batch(10);
+    let mut split = builder.split_fill(2);
+    split.chunks().swap(0, 1);
+    split.chunks()[0].fill(0..);
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| split.finish()));
+    assert!(result.is_err());
+    assert!(builder.build().unwrap().is_empty());
+}
+
+hecs::state_component! {
+    #[derive(Debug, Clone, PartialEq)]
+    enum Door {
+        Open,
+        Locked { key: u32 },
+    }
+    mod door;
+}
+
+#[test]
+fn state_component() {
+    let mut world = World::new();
+    let a = world.spawn((1,));
+    world.set_state(a, Door::Locked { key: 3 }).unwrap();
+    let locked = world.archetype_id(a).unwrap();
+    world.set_state(a, Door::Locked { key: 4 }).unwrap();
+    assert_eq!(world.archetype_id(a).unwrap(), locked);
+    assert_eq!(*world.get::<Door>(a).unwrap(), Door::Locked { key: 4 });
+
+    world.set_state(a, Door::Open).unwrap();
+    assert!(world.get::<door::Open>(a).is_ok());
+    assert!(world.get::<door::Locked>(a).is_err());
+    assert_eq!(world.query::<With<door::Locked, ()>>().iter().count(), 0);
+
+    assert_eq!(world.remove_state::<Door>(a).unwrap(), Door::Open);
+    assert!(world.get::<door::Open>(a).is_err());
+    assert!(matches!(
+        world.remove_state::<Door>(a),
+        Err(ComponentError::MissingComponent(_))
+    ));
+    world.despawn(a).unwrap();
+    assert_eq!(world.set_state(a, Door::Open), Err(NoSuchEntity));
+}
+
+#[test]
+fn query_single() {
+    let mut world = World::new();
+    assert_eq!(
+        world.query_mut::<&i32>().single().err(),
+        Some(QuerySingleError::NoMatches)
+    );
+    let a = world.spawn((1, true));
+    world.spawn((2,));
+    world.spawn((3,));
+    {
+        let (e, x) = world.query_mut::<With<bool, &mut i32>>().single().unwrap();
+        assert_eq!(e, a);
+        *x = 10;
+    }
+    assert_eq!(*world.get::<i32>(a).unwrap(), 10);
+    let err = world.query::<&i32>().single().unwrap_err();
+    assert_eq!(err, QuerySingleError::MultipleMatches(3));
+    assert_eq!(err.to_string(), "3 entities match the query, not one");
+}
+
+#[test]
+#[cfg(feature = "profile")]
+fn profiler() {
+    use core::any::TypeId;
+
+    let mut world = World::new();
+    world.spawn((1, true));
+    world.spawn(("abc",));
+    let mut prepared = PreparedQuery::<&mut bool>::new();
+    prepared.query_mut(&mut world).count();
+    let _ = world.query_pair_mut::<&i32, &mut bool>();
+    let frame = world.profiler().dump();
+    assert_eq!(frame.queries(), 3);
+    assert_eq!(frame.archetypes_matched(), 3);
+    assert_eq!(frame.components()[0].type_id(), TypeId::of::<bool>());
+    assert_eq!(frame.components()[0].writes(), 2);
+    assert_eq!(frame.component(TypeId::of::<i32>()).unwrap().reads(), 1);
+    assert!(frame.component(TypeId::of::<&str>()).is_none());
+}
+
+#[test]
+fn extend_built() {
+    let mut world = World::new();
+    let existing = world.spawn((0u8, "existing"));
+    let mut builders = (0..5)
+        .map(|i| {
+            let mut builder = EntityBuilder::new();
+            if i != 4 {
+                builder.add(i);
+            }
+            if i % 2 == 0 {
+                builder.add(String::from("even"));
+            }
+            builder
+        })
+        .collect::<Vec<_>>();
+    let entities = world.extend_built(builders.iter_mut().map(|x| x.build()));
+    assert_eq!(entities.len(), 5);
+    assert_eq!(world.len(), 6);
+    for (i, &entity) in entities.iter().enumerate().take(4) {
+        assert_eq!(*world.get::<i32>(entity).unwrap(), i as i32);
+        assert_eq!(world.get::<String>(entity).is_ok(), i % 2 == 0);
+    }
+    assert!(world.get::<i32>(entities[4]).is_err());
+    assert_eq!(*world.get::<String>(entities[4]).unwrap(), "even");
+    assert_eq!(*world.get::<&str>(existing).unwrap(), "existing");
+    assert_eq!(world.query::<&i32>().iter().count(), 4);
+
+    world.despawn(entities[0]).unwrap();
+    let empty = world.extend_built(vec![EntityBuilder::new().build()]);
+    assert!(world.contains(empty[0]));
+}
+
+#[test]
+fn stable_types() {
+    struct Position(i32);
+    impl StableComponent for Position {
+        const STABLE_NAME: &'static str = "game::Position";
+    }
+
+    let mut world = World::new();
+    world.register_named::<bool>("flag");
+    world.register_stable::<Position>();
+    let flag = world.stable_type(StableTypeId::from_name("flag")).unwrap();
+    assert_eq!(flag.id(), core::any::TypeId::of::<bool>());
+    assert_eq!(flag.stable_id(), Some(StableTypeId::from_name("flag")));
+    let e = world.spawn((Position(3), true));
+    let ty = world.stable_type(Position::STABLE_TYPE_ID).unwrap();
+    let pos = world.entity(e).unwrap().get_raw(ty.id()).unwrap();
+    assert_eq!(unsafe { (*pos.as_ptr().cast::<Position>()).0 }, 3);
+    assert!(world
+        .stable_type(StableTypeId::from_name("other"))
+        .is_none());
+    assert_eq!(
+        StableTypeId::from_bits(Position::STABLE_TYPE_ID.to_bits()),
+        Position::STABLE_TYPE_ID
+    );
+    assert!(TypeInfo::of::<Position>().stable_id().is_none());
+}
+
+#[test]
+fn component_registry() {
+    #[derive(Clone, PartialEq, Debug)]
+    struct Door(u8);
+    impl StableComponent for Door {
+        const STABLE_NAME: &'static str = "plugin::Door";
+    }
+    #[derive(Clone)]
+    struct Key;
+    impl StableComponent for Key {
+        const STABLE_NAME: &'static str = "plugin::Key";
+    }
+
+    let mut registry = ComponentRegistry::new();
+    registry.register_eq::<Door>().register::<Key>();
+    assert_eq!(registry.len(), 2);
+    let door = registry.get(Door::STABLE_TYPE_ID).unwrap();
+    assert_eq!(door.name(), "plugin::Door");
+    assert!(door.is_cloneable());
+    assert!(!registry.get(Key::STABLE_TYPE_ID).unwrap().is_cloneable());
+
+    let mut world = World::new();
+    world.register_components(&registry);
+    let a = world.spawn((Door(7), Key));
+    let ty = world.stable_type(Key::STABLE_TYPE_ID).unwrap();
+    assert!(world.entity(a).unwrap().get_raw(ty.id()).is_some());
+    world.remove_named(a, "plugin::Key").unwrap();
+
+    let mut snapshots = SnapshotRegistry::new();
+    snapshots.register_from(&registry);
+    let b = world.clone_entity(&snapshots, a).unwrap();
+    assert_eq!(*world.get::<Door>(b).unwrap(), Door(7));
+}
+
+#[test]
+#[cfg(feature = "std")]
+fn component_registry_binary() {
+    #[derive(PartialEq, Debug)]
+    struct Door(u8);
+    impl StableComponent for Door {
+        const STABLE_NAME: &'static str = "plugin::Door";
+    }
+
+    let mut registry = ComponentRegistry::new();
+    registry.register_binary::<Door>(1, |x, out| out.push(x.0), |bytes| Some(Door(bytes[0])));
+    let mut world = World::new();
+    world.register_components(&registry);
+    let a = world.spawn((Door(7), true));
+
+    let mut binary = BinaryRegistry::new();
+    binary.register_from(&registry);
+    let mut save = Vec::new();
+    world.save_to(&binary, &mut save).unwrap();
+    let loaded = World::load_from(&binary, &save[..]).unwrap();
+    assert_eq!(*loaded.get::<Door>(a).unwrap(), Door(7));
+    assert!(loaded.get::<bool>(a).is_err());
+}
+
+#[test]
+fn query_relates() {
+    struct Follow(Entity);
+    impl Relation for Follow {
+        fn target(&self) -> Entity {
+            self.0
+        }
+    }
+
+    let mut world = World::new();
+    let leader = world.spawn((10,));
+    let a = world.spawn((0, Follow(leader)));
+    let b = world.spawn((0, Follow(a), true));
+    let unarmed = world.spawn(("leader",));
+    let c = world.spawn((0, Follow(unarmed)));
+    let dead = world.spawn((5,));
+    let d = world.spawn((0, Follow(dead)));
+    world.despawn(dead).unwrap();
+
+    let mut seen = world
+        .query_relates_mut::<(), Follow, &i32>()
+        .map(|(e, (_, x))| (e, x.copied()))
+        .collect::<Vec<_>>();
+    seen.sort_by_key(|(e, _)| e.id());
+    assert_eq!(seen, [(a, Some(10)), (b, Some(0)), (c, None), (d, None)]);
+
+    for (_, (x, target)) in world.query_relates_mut::<&mut bool, Follow, &i32>() {
+        *x = target == Some(&0);
+    }
+    assert!(*world.get::<bool>(b).unwrap());
+
+    world.attach(a, unarmed).unwrap();
+    let names = world
+        .query_relates_mut::<(), Parent, &&str>()
+        .map(|(e, (_, name))| (e, name.copied()))
+        .collect::<Vec<_>>();
+    assert_eq!(names, [(a, Some("leader"))]);
+}
+
+#[test]
+#[should_panic(expected = "related query must not borrow uniquely")]
+fn query_relates_unique_target() {
+    struct Follow(Entity);
+    impl Relation for Follow {
+        fn target(&self) -> Entity {
+            self.0
+        }
+    }
+
+    let mut world = World::new();
+    world.query_relates_mut::<(), Follow, &mut i32>().count();
+}
+
+#[test]
+fn fixed_capacity() {
+    let mut world = World::with_capacity(3);
+    world.register_archetype::<(i32,)>(2);
+    world.register_archetype::<(i32, bool)>(2);
+    world.register_archetype::<(bool,)>(1);
+    let a = world.try_spawn((1,)).unwrap();
+    let b = world.try_spawn((2,)).unwrap();
+    assert_eq!(world.try_spawn((3,)), Err((3,)));
+    assert_eq!(world.try_spawn(("abc",)), Err(("abc",)));
+    let c = world.try_spawn((true,)).unwrap();
+    assert_eq!(world.try_spawn((false,)), Err((false,)));
+
+    world.try_insert(a, (true,)).unwrap();
+    world.try_insert(a, (false,)).unwrap();
+    assert!(!*world.get::<bool>(a).unwrap());
+    world.try_insert(b, (false,)).unwrap();
+    assert_eq!(world.try_insert(c, (5,)), Err((5,)), "archetype full");
+    assert_eq!(world.try_insert(c, ('x',)), Err(('x',)), "no archetype");
+
+    world.despawn(a).unwrap();
+    assert_eq!(world.try_insert(a, (5,)), Err((5,)));
+    world.try_insert(c, (5,)).unwrap();
+    let d = world.try_spawn((4,)).unwrap();
+    assert_eq!(d.id(), a.id());
+    assert_eq!(world.try_spawn((6,)), Err((6,)), "no handles");
+    assert_eq!(*world.get::<i32>(c).unwrap(), 5);
+}
+
+#[test]
+fn entity_flags() {
+    use std::sync::atomic::Ordering;
+
+    let mut world = World::new();
+    let a = world.spawn((1,));
+    let b = world.spawn(());
+    world.flags(a).unwrap().store(3, Ordering::Relaxed);
+    {
+        let mut query = world.query::<&mut i32>();
+        for (e, _) in query.iter() {
+            world.flags(e).unwrap().fetch_or(4, Ordering::Relaxed);
+        }
+    }
+    assert_eq!(world.flags(a).unwrap().load(Ordering::Relaxed), 7);
+    assert_eq!(world.flags(b).unwrap().load(Ordering::Relaxed), 0);
+
+    let reserved = world.reserve_entity();
+    assert!(world.flags(reserved).is_err());
+    world.flush();
+    assert_eq!(world.flags(reserved).unwrap().load(Ordering::Relaxed), 0);
+
+    world.despawn(a).unwrap();
+    assert!(world.flags(a).is_err());
+    let c = world.spawn((2,));
+    assert_eq!(c.id(), a.id());
+    assert_eq!(world.flags(c).unwrap().load(Ordering::Relaxed), 0);
+    world.flags(c).unwrap().store(1, Ordering::Relaxed);
+    world.spawn_at(c, (3,));
+    assert_eq!(world.flags(c).unwrap().load(Ordering::Relaxed), 0);
+}
+
+#[test]
+#[cfg(feature = "std")]
+fn interned_components() {
+    let names = Interner::<String>::new();
+    let mut world = World::new();
+    let entities = (0..100)
+        .map(|i| world.spawn((i, names.intern_ref(if i % 2 == 0 { "even" } else { "odd" }))))
+        .collect::<Vec<_>>();
+    assert_eq!(names.len(), 2);
+    let evens = world
+        .query::<&Interned<String>>()
+        .iter()
+        .filter(|(_, name)| name.as_str() == "even")
+        .count();
+    assert_eq!(evens, 50);
+
+    let mut other = World::new();
+    let x = other.spawn((names.intern("odd".to_string()),));
+    assert!(Interned::ptr_eq(
+        &other.get::<Interned<String>>(x).unwrap(),
+        &world.get::<Interned<String>>(entities[1]).unwrap()
+    ));
+
+    for e in entities.iter().step_by(2) {
+        world.despawn(*e).unwrap();
+    }
+    assert_eq!(names.purge(), 1);
+    assert_eq!(names.len(), 1);
+    assert_eq!(
+        &**world.get::<Interned<String>>(entities[1]).unwrap(),
+        "odd"
+    );
+}
+
+#[test]
+fn mut_change_detection() {
+    let mut world = World::new();
+    let a = world.spawn((1, 1u8));
+    let b = world.spawn((2,));
+    let c = world.spawn((3, 3u8));
+    world.clear_trackers();
+
+    for (_, (&x, y)) in world.query_mut::<(&i32, Option<Mut<u8>>)>() {
+        if let Some(mut y) = y {
+            if x == 3 {
+                *y += 1;
+            } else {
+                assert_eq!(*y, 1);
+            }
+        }
+    }
+    let changed = world
+        .query::<Changed<u8, ()>>()
+        .iter()
+        .map(|(e, _)| e)
+        .collect::<Vec<_>>();
+    assert_eq!(changed, [c]);
+    assert_eq!(*world.get::<u8>(c).unwrap(), 4);
+
+    world.clear_trackers();
+    let mut query = world.query::<Mut<i32>>();
+    let items = query.iter().collect::<Vec<_>>();
+    for (e, x) in items {
+        if e == b {
+            *x.into_inner() = 5;
+        }
+    }
+    drop(query);
+    let changed = world
+        .query::<Changed<i32, ()>>()
+        .iter()
+        .map(|(e, _)| e)
+        .collect::<Vec<_>>();
+    assert_eq!(changed, [b]);
+    assert_eq!(*world.get::<i32>(a).unwrap(), 1);
+}
+
+#[test]
+fn query_cursor() {
+    let mut world = World::new();
+    let a = world.spawn((1,));
+    let b = world.spawn((2, true));
+    let c = world.spawn((3,));
+    let d = world.spawn((4, true));
+    world.spawn(("no match",));
+    world.disable(c).unwrap();
+
+    let mut cursor = QueryCursor::<&mut i32>::new();
+    assert!(cursor.is_at_start());
+    let visit = |cursor: &mut QueryCursor<&mut i32>, world: &mut World, budget| {
+        cursor
+            .query_mut(world, budget)
+            .map(|(e, x)| {
+                *x += 10;
+                e
+            })
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(visit(&mut cursor, &mut world, 1), [a]);
+    assert!(!cursor.is_at_start());
+    let e = world.spawn((5, 'e'));
+    assert_eq!(visit(&mut cursor, &mut world, 2), [b, d]);
+    assert_eq!(visit(&mut cursor, &mut world, 2), [e]);
+    assert!(cursor.is_at_start());
+    assert_eq!(visit(&mut cursor, &mut world, 0), []);
+    assert_eq!(*world.get::<i32>(a).unwrap(), 11);
+    assert_eq!(*world.get::<i32>(c).unwrap(), 3);
+
+    assert_eq!(visit(&mut cursor, &mut world, 1), [a]);
+    let mut other = World::new();
+    let x = other.spawn((0,));
+    other.spawn((1,));
+    assert_eq!(visit(&mut cursor, &mut other, 1), [x]);
+}
+
+#[test]
+fn generation_exhaustion() {
+    let mut world = World::new();
+    let last = Entity::from_bits(EntityBits::from(u32::MAX - 1) << EntityId::BITS);
+    world.spawn_at(last, (1,));
+    let near = Entity::from_bits(EntityBits::from(u32::MAX - 10) << EntityId::BITS | 1);
+    world.spawn_at(near, ());
+    let audit = world.entity_audit();
+    assert_eq!(audit.near_exhausted(), 2);
+    assert_eq!(audit.max_generation(), u32::MAX - 1);
+    assert_eq!(audit.empty(), 1);
+
+    world.set_generation_policy(GenerationPolicy::Panic);
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        world.despawn(last).unwrap();
+    }));
+    assert!(result.is_err());
+    assert!(world.contains(last));
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| world.clear()));
+    assert!(result.is_err());
+    assert_eq!(*world.get::<i32>(last).unwrap(), 1);
+
+    world.set_generation_policy(GenerationPolicy::Retire);
+    world.despawn(last).unwrap();
+    let audit = world.entity_audit();
+    assert_eq!(audit.retired(), 1);
+    assert_eq!(audit.live(), 1);
+    assert_ne!(world.spawn(()).id(), last.id());
+    // No handle refers to a retired ID, including one naming the generation it retired at
+    let retired = Entity::from_bits(
+        EntityBits::from(u32::MAX) << EntityId::BITS | EntityBits::from(last.id()),
+    );
+    assert!(!world.contains(retired));
+    assert!(world.entity(retired).is_err());
+    assert!(world.despawn(retired).is_err());
+    assert_eq!(world.entity_audit().retired(), 1);
+}
+
+#[test]
+#[cfg(feature = "std")]
+fn transaction_rollback() {
+    let mut world = World::new();
+    let a = world.spawn((1, true));
+    let counter = std::sync::Arc::new(());
+
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        world.transaction(|tx| {
+            tx.spawn((counter.clone(),));
+            tx.remove::<(bool,)>(a);
+            assert!(tx.world().get::<bool>(a).is_ok());
+            panic!("script failed");
+            #[allow(unreachable_code)]
+            Ok::<(), ()>(())
+        })
+    }));
+    assert!(result.is_err());
+    assert_eq!(world.len(), 1);
+    assert!(world.get::<bool>(a).is_ok());
+    assert_eq!(std::sync::Arc::strong_count(&counter), 1);
+
+    let result = world.transaction(|tx| {
+        tx.insert(a, ("abc",));
+        tx.despawn(a);
+        Err(7)
+    });
+    assert_eq!(result, Err::<(), _>(7));
+    assert!(world.get::<&str>(a).is_err());
+    assert!(world.contains(a));
+
+    let b = world
+        .transaction(|tx| {
+            let b = tx.spawn((2,));
+            tx.insert(b, ("abc",));
+            tx.despawn(a);
+            assert!(tx.world().get::<i32>(b).is_err());
+            Ok::<_, ()>(b)
+        })
+        .unwrap();
+    assert!(!world.contains(a));
+    assert_eq!(*world.get::<i32>(b).unwrap(), 2);
+    assert_eq!(*world.get::<&str>(b).unwrap(), "abc");
+}
+
+#[test]
+#[cfg(feature = "async")]
+fn async_world() {
+    use std::future::Future;
+    use std::sync::Arc;
+    use std::task::{Context, Poll, Wake};
+
+    struct Unpark(std::thread::Thread);
+    impl Wake for Unpark {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let mut future = Box::pin(future);
+        let waker = Arc::new(Unpark(std::thread::current())).into();
+        let mut cx = Context::from_waker(&waker);
+        loop {
+            if let Poll::Ready(x) = future.as_mut().poll(&mut cx) {
+                return x;
+            }
+            std::thread::park();
+        }
+    }
+
+    let mut world = AsyncWorld::new(World::new());
+    let handle = world.handle();
+    let task = std::thread::spawn(move || {
+        block_on(async {
+            let e = handle.with(|world| world.spawn((1,))).await.unwrap();
+            handle
+                .with(move |world| {
+                    *world.get_mut::<i32>(e).unwrap() += 1;
+                    e
+                })
+                .await
+                .unwrap()
+        })
+    });
+    while !task.is_finished() {
+        world.run_pending();
+        std::thread::yield_now();
+    }
+    let e = task.join().unwrap();
+    assert_eq!(*world.get::<i32>(e).unwrap(), 2);
+
+    let op = world.handle().with(|world| world.spawn(()));
+    assert_eq!(world.pending(), 1);
+    let world = world.into_inner();
+    assert_eq!(block_on(op), None);
+    assert_eq!(world.len(), 1);
+}
+
+#[test]
+fn pin_components() {
+    struct Haste(u32);
+    struct Slow;
+
+    let mut world = World::new();
+    let a = world.spawn((1, Haste(3)));
+    let b = world.spawn((2,));
+    let c = world.spawn((3, true));
+    world.pin_components::<(Haste, Slow)>(b).unwrap();
+    world.pin_components::<(Haste, Slow)>(a).unwrap();
+    assert_eq!(world.get::<Haste>(a).unwrap().0, 3);
+
+    let home = world.archetype_id(a).unwrap();
+    assert_eq!(world.archetype_id(b).unwrap(), home);
+    let generation = world.archetypes_generation();
+    world.insert(b, (Haste(5), Slow)).unwrap();
+    world.remove_one::<Slow>(b).unwrap();
+    world.insert_one(a, Slow).unwrap();
+    assert_eq!(world.archetypes_generation(), generation);
+    assert_eq!(world.archetype_id(a).unwrap(), home);
+    assert_eq!(world.archetype_id(b).unwrap(), home);
+    world.insert_one(c, Haste(7)).unwrap();
+    assert_ne!(world.archetypes_generation(), generation);
+
+    let mut hastes = world
+        .query::<(&i32, &Haste)>()
+        .iter()
+        .map(|(_, (&i, h))| (i, h.0))
+        .collect::<Vec<_>>();
+    hastes.sort_unstable();
+    assert_eq!(hastes, [(1, 3), (2, 5), (3, 7)]);
+    assert_eq!(world.query::<&Slow>().iter().count(), 1);
+
+    assert_eq!(world.remove_one::<Haste>(a).unwrap().0, 3);
+    world.despawn(b).unwrap();
+    assert_eq!(world.query::<&Haste>().iter().count(), 1);
+    assert!(world.get::<Slow>(a).is_ok());
+    assert!(world.pin_components::<(Haste,)>(b).is_err());
+}
+
+#[test]
+fn pinned_components_follow_entity() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static DROPS: AtomicUsize = AtomicUsize::new(0);
+    struct Haste(u32);
+    impl Drop for Haste {
+        fn drop(&mut self) {
+            DROPS.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    let mut world = World::new();
+    let a = world.spawn((1,));
+    world.pin_components::<(Haste,)>(a).unwrap();
+    world.insert_one(a, Haste(1)).unwrap();
+    // Leaving the pinning archetype takes the pin along, and the sparse `Haste` with it
+    world.insert_one(a, 2.0f32).unwrap();
+    let home = world.archetype_id(a).unwrap();
+    world.insert_one(a, Haste(2)).unwrap();
+    assert_eq!(DROPS.load(Ordering::Relaxed), 1);
+    assert_eq!(world.archetype_id(a).unwrap(), home);
+    assert_eq!(world.get::<Haste>(a).unwrap().0, 2);
+    assert_eq!(world.query::<&Haste>().iter().count(), 1);
+
+    assert_eq!(world.remove_one::<Haste>(a).unwrap().0, 2);
+    assert_eq!(DROPS.load(Ordering::Relaxed), 2);
+    assert!(world.get::<Haste>(a).is_err());
+    world.insert_one(a, Haste(3)).unwrap();
+    assert_eq!(world.archetype_id(a).unwrap(), home);
+    world.remove_one::<f32>(a).unwrap();
+    assert_eq!(world.get::<Haste>(a).unwrap().0, 3);
+
+    // Merged entities keep their sparse components, though not the pin
+    let mut merged = World::new();
+    merged.spawn((Haste(4),));
+    let a = merged.merge(world).get(a).unwrap();
+    merged.insert_one(a, Haste(5)).unwrap();
+    assert_eq!(DROPS.load(Ordering::Relaxed), 3);
+    assert_eq!(merged.get::<Haste>(a).unwrap().0, 5);
+    assert_eq!(merged.query::<&Haste>().iter().count(), 2);
+    drop(merged);
+    assert_eq!(DROPS.load(Ordering::Relaxed), 5);
+}
+
+#[test]
+#[cfg(feature = "std")]
+fn read_view() {
+    let mut world = World::new();
+    let a = world.spawn((1, 2.0_f32));
+    world.spawn((3,));
+    let pending = world.reserve_entity();
+    world.insert_resource(5u8);
+    let view = world.read_view();
+    assert!(view.contains(pending));
+    let held = view.get::<i32>(a).unwrap();
+    let sums = std::thread::scope(|s| {
+        let handles = (0..4)
+            .map(|_| {
+                s.spawn(move || {
+                    let x = view.query_one::<&f32>(a).unwrap();
+                    view.query::<&i32>()
+                        .into_iter()
+                        .map(|(_, &i)| i)
+                        .sum::<i32>()
+                        + *x as i32
+                })
+            })
+            .collect::<Vec<_>>();
+        handles
+            .into_iter()
+            .map(|x| x.join().unwrap())
+            .collect::<Vec<_>>()
+    });
+    assert_eq!(sums, [6; 4]);
+    assert_eq!(*held, 1);
+    assert_eq!(*view.resource::<u8>().unwrap(), 5);
+    assert_eq!(view.len(), 3);
+    assert!(view.get::<bool>(a).is_err());
+}
+
+#[test]
+#[should_panic(expected = "must not borrow uniquely")]
+fn read_view_unique() {
+    let mut world = World::new();
+    world.spawn((1,));
+    world.read_view().query::<&mut i32>();
+}
+
+#[test]
+fn spawn_column_batch_appends() {
+    let mut world = World::new();
+    let existing = world
+        .spawn_columns((vec![1, 2], vec![true, false]))
+        .collect::<Vec<_>>();
+    let archetype = world.archetype_id(existing[0]).unwrap();
+    let generation = world.archetypes_generation();
+
+    let mut ty = ColumnBatchType::new();
+    ty.add::<bool>().add::<i32>();
+    let mut builder = ty.into_batch(3);
+    builder.fill(3..6);
+    builder.fill([true; 3]);
+    let appended = world
+        .spawn_column_batch(builder.build().unwrap())
+        .collect::<Vec<_>>();
+
+    assert_eq!(world.archetypes_generation(), generation);
+    assert_eq!(world.archetype(archetype).unwrap().len(), 5);
+    for (i, &e) in appended.iter().enumerate() {
+        assert_eq!(world.archetype_id(e).unwrap(), archetype);
+        assert_eq!(*world.get::<i32>(e).unwrap(), i as i32 + 3);
+    }
+    assert_eq!(*world.get::<i32>(existing[1]).unwrap(), 2);
+}
+
+#[test]
+fn try_borrow() {
+    let mut world = World::new();
+    let a = world.spawn((1, true));
+    let b = world.spawn((2, true));
+    {
+        let x = world.try_borrow::<i32>(a).unwrap();
+        assert_eq!(*x, 1);
+        assert_eq!(*world.try_borrow::<i32>(b).unwrap(), 2);
+        assert_eq!(
+            world.try_borrow_mut::<i32>(b).err(),
+            Some(BorrowError::Conflict(core::any::type_name::<i32>()))
+        );
+        *world.try_borrow_mut::<bool>(b).unwrap() = false;
+    }
+    let x = world.try_borrow_mut::<i32>(a).unwrap();
+    assert!(matches!(
+        world.try_borrow::<i32>(b),
+        Err(BorrowError::Conflict(_))
+    ));
+    drop(x);
+    assert!(!*world.try_borrow::<bool>(b).unwrap());
+    assert!(matches!(
+        world.try_borrow::<&str>(a),
+        Err(BorrowError::MissingComponent(_))
+    ));
+    world.despawn(a).unwrap();
+    assert_eq!(
+        world.try_borrow::<i32>(a).err(),
+        Some(BorrowError::NoSuchEntity)
+    );
+}
+
+#[test]
+fn filter_archetypes() {
+    let mut world = World::new();
+    let a = world.spawn((1, true));
+    world.spawn((2, "b"));
+    let c = world.spawn((3, true, 'c'));
+    world.spawn((true,));
+    let mut calls = 0;
+    let mut query = world.query::<&i32>();
+    let iter = query.filter_archetypes(|x| {
+        calls += 1;
+        x.has::<bool>()
+    });
+    assert_eq!(iter.size_hint(), (0, Some(3)));
+    let mut entities = iter.map(|(e, &x)| (e, x)).collect::<Vec<_>>();
+    drop(query);
+    entities.sort();
+    assert_eq!(entities, [(a, 1), (c, 3)]);
+    assert_eq!(calls, 3);
+    assert!(world.query_mut::<&mut i32>().into_iter().count() == 3);
+}